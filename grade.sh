@@ -2,22 +2,33 @@
 
 SCRIPT_DIR=$(dirname $(realpath ${BASH_SOURCE}))
 TEST_DIR=$SCRIPT_DIR/tests
-WASM_VM_PATH=${1:-$SCRIPT_DIR/wasm-vm}
+WASM_VM_PATH=$(realpath -m ${1:-$SCRIPT_DIR/wasm-vm})
+cd $SCRIPT_DIR
 
 # Directories
 wasm_dir="$TEST_DIR/wasm"
 expect_dir="$TEST_DIR/expect"
+# Scratch space of this run, @TMPDIR@ in a .flags file
+run_tmp=$(mktemp -d)
+trap 'rm -rf "$run_tmp"' EXIT
 
 
+# Print the exit status of a run after `!exit:` if the test checks it
+function show_status {
+    if [ -f "$status_file" ]; then
+      echo -n "!exit:$1"
+    fi
+}
+
 # Function to run a wasm file with wasmtime and get stdout and stderr
 function run_wasm {
     local wasm_file="$1"
     shift
     local run_args=$@
     if [ -z "$run_args" ]; then
-       local output=$(timeout 5 $WASM_VM_PATH --jit "$wasm_file" 2>&1)
+       local output=$(timeout 5 $WASM_VM_PATH --jit $flags "$wasm_file" 2>&1; show_status $?)
      else
-       local output=$(timeout 5 $WASM_VM_PATH --jit -a $run_args "$wasm_file" 2>&1)
+       local output=$(timeout 5 $WASM_VM_PATH --jit $flags -a $run_args "$wasm_file" 2>&1; show_status $?)
      fi
     if [ $? -ne 0 ]; then
       echo "timeout"
@@ -65,6 +76,12 @@ for wasm_file in "$wasm_dir"/*.wasm; do
     expect_file="$expect_dir/$wasm_filename.expect"
     # Check for corresponding .runs file
     runs_file="$expect_dir/$wasm_filename.runs"
+    # Options of every run, e.g. the directories it preopens, relative to
+    # the root of the repository
+    flags=$(cat "$expect_dir/$wasm_filename.flags" 2>/dev/null)
+    flags=${flags//@TMPDIR@/$run_tmp}
+    # With a .status file, every output ends with the exit status
+    status_file="$expect_dir/$wasm_filename.status"
     
     echo "#### Test $((num_tests+1)) ####"
     if [ -f "$expect_file" ]; then
@@ -3,6 +3,9 @@
 # Directories
 wasm_dir="tests/wasm"
 expect_dir="tests/expect"
+# Scratch space of this run, @TMPDIR@ in a .flags file
+run_tmp=$(mktemp -d)
+trap 'rm -rf "$run_tmp"' EXIT
 
 # Colors
 GREEN="\033[0;32m"
@@ -13,15 +16,22 @@ NC="\033[0m" # No color
 passed_tests=()
 failed_tests=()
 
+# Print the exit status of a run after `!exit:` if the test checks it
+function show_status {
+    if [ -f "$status_file" ]; then
+      echo -n "!exit:$1"
+    fi
+}
+
 # Function to run a wasm file with wasmtime and get stdout and stderr
 function run_wasm {
     local wasm_file="$1"
     shift
     local run_args=$@
     if [ -z "$run_args" ]; then
-      local output=$(timeout 5 ./wasm-vm --jit "$wasm_file" 2>&1)
+      local output=$(timeout 5 ./wasm-vm --jit $flags "$wasm_file" 2>&1; show_status $?)
     else
-      local output=$(timeout 5 ./wasm-vm --jit -a $run_args "$wasm_file" 2>&1)
+      local output=$(timeout 5 ./wasm-vm --jit $flags -a $run_args "$wasm_file" 2>&1; show_status $?)
     fi
     if [ $? -ne 0 ]; then
       echo "timeout"
@@ -72,6 +82,12 @@ for wasm_file in "$wasm_dir"/*.wasm; do
     expect_file="$expect_dir/$wasm_filename.expect"
     # Check for corresponding .runs file
     runs_file="$expect_dir/$wasm_filename.runs"
+    # Options of every run, e.g. the directories it preopens, relative to
+    # the root of the repository
+    flags=$(cat "$expect_dir/$wasm_filename.flags" 2>/dev/null)
+    flags=${flags//@TMPDIR@/$run_tmp}
+    # With a .status file, every output ends with the exit status
+    status_file="$expect_dir/$wasm_filename.status"
     
     echo "#### Test $((num_tests+1)) ####"
     if [ -f "$expect_file" ]; then
@@ -0,0 +1,158 @@
+//! Wasm backtraces of jit code. Every compiled function keeps rbp as its
+//! frame pointer and has its own trap exits, so the trap handler finds the
+//! trapping function from rip and its callers by following the saved frame
+//! pointers. Each return address is looked up in a side table of call sites
+//! that records the caller and the pc of its call instruction. Every
+//! compiler installs its own table, a trap is looked up in the one whose
+//! code it is in, so instances running at the same time keep theirs.
+//!
+//! The handler must not lock or allocate, it only copies the return
+//! addresses into a `RawBacktrace`. They are looked up once the trap has
+//! unwound to `call_guarded`.
+
+use std::{collections::HashMap, ops::Range, sync::Mutex};
+
+use crate::vm::{WasmBacktrace, WasmFrame};
+
+/// The code of every compilation while it is installed, by the address
+/// range of its code.
+static CODE_MAPS: Mutex<Vec<(Range<u64>, JitCodeMap)>> = Mutex::new(Vec::new());
+
+/// at most this many callers are kept of a trap
+const MAX_RAW_FRAMES: usize = 64;
+
+pub(crate) struct JitCodeMap {
+    /// the code address range of each compiled function, with its frame
+    pub(crate) funcs: Vec<(Range<u64>, WasmFrame)>,
+    /// return address of a call -> the frame of the caller at the call
+    pub(crate) call_sites: HashMap<u64, WasmFrame>,
+    /// the trap exit of the host stubs, which return to the wasm caller
+    /// before jumping there
+    pub(crate) host_trap_exit: Range<u64>,
+}
+
+impl JitCodeMap {
+    /// The addresses of the code of the map, from its first function or
+    /// trap exit to its last.
+    fn code(&self) -> Range<u64> {
+        self.funcs
+            .iter()
+            .map(|(range, _)| range)
+            .chain([&self.host_trap_exit])
+            .fold(u64::MAX..0, |code, range| {
+                code.start.min(range.start)..code.end.max(range.end)
+            })
+    }
+
+    fn func_at(&self, addr: u64) -> Option<WasmFrame> {
+        self.funcs
+            .iter()
+            .find(|(range, _)| range.contains(&addr))
+            .map(|(_, frame)| frame.clone())
+    }
+
+    fn caller_frame(&self, return_addr: u64) -> Option<WasmFrame> {
+        self.call_sites.get(&return_addr).cloned()
+    }
+
+    /// Map the addresses of a trap to the wasm frames.
+    fn resolve(&self, raw: &RawBacktrace) -> WasmBacktrace {
+        let mut backtrace = WasmBacktrace::default();
+        if let Some(frame) = self.func_at(raw.rip) {
+            backtrace.push_caller(frame, None);
+        } else if self.host_trap_exit.contains(&raw.rip) {
+            // the stub already returned, its caller resumes at [rsp]
+            match self.caller_frame(raw.stack_top) {
+                Some(frame) => backtrace.push_caller(frame, None),
+                None => return backtrace,
+            }
+        } else {
+            return backtrace;
+        }
+
+        // the entry point jumps to the first function, the walk ends at the
+        // return address into the host
+        for return_addr in &raw.return_addrs[..raw.len] {
+            match self.caller_frame(*return_addr) {
+                Some(frame) => backtrace.push_caller(frame, None),
+                None => break,
+            }
+        }
+        backtrace
+    }
+}
+
+/// The registers and return addresses of a trap, as the handler found them.
+#[derive(Clone, Copy)]
+pub(crate) struct RawBacktrace {
+    rip: u64,
+    /// the word at rsp
+    stack_top: u64,
+    return_addrs: [u64; MAX_RAW_FRAMES],
+    len: usize,
+}
+
+impl RawBacktrace {
+    /// Copy the return addresses of the jit frames below the frame of the
+    /// entry at `entry_rbp`.
+    ///
+    /// # Safety
+    /// `rsp` and `rbp` must be the stack and frame pointers of jit code
+    /// entered with `entry_rbp` as its frame pointer.
+    pub(crate) unsafe fn capture(rip: u64, rsp: u64, mut rbp: u64, entry_rbp: u64) -> Self {
+        let mut raw = Self {
+            rip,
+            stack_top: *(rsp as *const u64),
+            return_addrs: [0; MAX_RAW_FRAMES],
+            len: 0,
+        };
+        // the caller of the function at rbp resumes at [rbp + 8], each
+        // caller frame is above its callee up to the frame of the entry
+        while rsp <= rbp && rbp < entry_rbp && raw.len < MAX_RAW_FRAMES {
+            raw.return_addrs[raw.len] = *((rbp + 8) as *const u64);
+            raw.len += 1;
+            let caller_rbp = *(rbp as *const u64);
+            if caller_rbp <= rbp {
+                break;
+            }
+            rbp = caller_rbp;
+        }
+        raw
+    }
+
+    /// The wasm frames of the trap, `None` if it is not in the code of an
+    /// installed code map.
+    pub(crate) fn resolve(&self) -> Option<WasmBacktrace> {
+        let code_maps = CODE_MAPS.lock().unwrap();
+        let (_, map) = code_maps
+            .iter()
+            .find(|(code, _)| code.contains(&self.rip))?;
+        Some(map.resolve(self))
+    }
+}
+
+/// Keeps the code map of a compiler installed for the backtraces of traps
+/// until it is dropped, along with the code maps of the other compilers.
+pub(crate) struct CodeMapRegistration {
+    code: Range<u64>,
+}
+
+impl CodeMapRegistration {
+    /// Install `map` for the backtraces of the traps in its code. A
+    /// compiler drops its previous registration first, the code it covers
+    /// is part of the new map.
+    pub(crate) fn register(map: JitCodeMap) -> Self {
+        let code = map.code();
+        CODE_MAPS.lock().unwrap().push((code.clone(), map));
+        Self { code }
+    }
+}
+
+impl Drop for CodeMapRegistration {
+    fn drop(&mut self) {
+        CODE_MAPS
+            .lock()
+            .unwrap()
+            .retain(|(code, _)| *code != self.code);
+    }
+}
@@ -1,27 +1,44 @@
+use std::cell::Cell;
 use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::path::PathBuf;
 use std::rc::Rc;
 
+use super::backtrace::{CodeMapRegistration, JitCodeMap};
+use super::fold::fold_constants;
+use super::gdb::GdbJitRegistration;
 use super::insts::{RegReconcileInfo, WasmJitControlFlowFrame, WasmJitControlFlowType};
-use super::regalloc::{Register, X86Register, X86RegisterAllocator, REG_LOCAL_BASE, REG_TEMP};
-use super::{JitLinearMemory, ValueType, WasmJitCompiler};
+use super::perf_map::write_perf_map;
+use super::regalloc::{
+    Register, X86Register, X86RegisterAllocator, REG_LOCAL_BASE, REG_TEMP, REG_TEMP2,
+};
+use super::setup::data::JitDataSegment;
+use super::setup::host::JitHostContext;
+use super::setup::table::{JitElemSegment, JitTable};
+use super::setup::tier::OsrTarget;
+use super::setup::trap::{TrapSite, TrapSiteRegistration};
+use super::{func_symbol_name, JitLinearMemory, JitSettings, ValueType};
 use crate::jit::regalloc::REG_TEMP_FP;
 use crate::jit::utils::emit_mov_reg_to_reg;
 use crate::module::components::FuncDecl;
 use crate::module::insts::Instruction;
-use crate::module::value_type::WasmValue;
 use crate::module::wasm_module::WasmModule;
-use crate::vm::WASM_DEFAULT_PAGE_SIZE_BYTE;
+use crate::vm::{
+    Epoch, HostFuncImport, SharedLimiter, Store, TrapKind, WasmFrame, DEFAULT_MEMORY_MAXIMUM,
+};
 
-use anyhow::Result;
-use debug_cell::RefCell;
-use monoasm::{CodePtr, DestLabel, Disp, Imm, JitMemory, Reg, Rm, Scale};
+use anyhow::{anyhow, Result};
+use monoasm::{DestLabel, Disp, Imm, JitMemory, Reg, Rm, Scale};
 use monoasm_macro::monoasm;
 use wasmparser::ValType;
 
 // Jit compile through abstract interpretation
 pub struct X86JitCompiler<'a> {
     /// module
-    pub(crate) module: Rc<RefCell<WasmModule<'a>>>,
+    pub(crate) module: Rc<WasmModule<'a>>,
+    /// the memories, tables and globals of the instance, the jit code runs
+    /// on them, see `setup::state`
+    pub(crate) store: Rc<Store<'a>>,
 
     /// Register allocator, simply a register stack that controls what we can
     /// use in the current context
@@ -51,116 +68,393 @@ pub struct X86JitCompiler<'a> {
 
     /// In memory assembler
     pub(crate) jit: JitMemory,
+    /// every address the code holds, by the label right after the
+    /// instruction loading it, see `emit_mov_addr`
+    pub(crate) addr_sites: Vec<(DestLabel, u64)>,
+    /// where the code compiled up front is saved and found again, see
+    /// `setup::cache`
+    pub(crate) cache_dir: Option<PathBuf>,
+    /// whether `setup` loaded the code from `cache_dir`, `None` if the code
+    /// is not cached
+    pub(crate) cache_hit: Option<bool>,
 
-    /// Linear memory
-    pub(crate) linear_mem: JitLinearMemory,
+    /// Linear memories of the store, memory 0 always exists and its base
+    /// address is kept in REG_MEMORY_BASE. Never resized once the code is
+    /// emitted since the jit code holds their addresses
+    pub(crate) linear_mems: Vec<JitLinearMemory>,
 
-    /// table stores functions or expressions
-    ///
-    /// we store the table_len separately to get the table size to make sure
-    /// the table index is valid on call_indirect, when it is uninitialized,
-    /// we trap
-    pub(crate) tables: Vec<Vec<u32>>,
-    pub(crate) table_len: Vec<usize>,
+    /// table stores functions or expressions, never resized once the code is
+    /// emitted since the jit code holds their addresses, see `JitTable`
+    pub(crate) tables: Vec<JitTable>,
 
-    /// global variables
-    ///
-    /// we separate the type from the value to get a more
-    /// consistent memory layout so that we can get the global's value in asm
-    /// more easily
+    /// element segments for table.init, never resized once the code is
+    /// emitted since the jit code holds their addresses
+    pub(crate) elem_segments: Vec<JitElemSegment>,
+
+    /// global variables, as raw bits so that the asm can load and store
+    /// them without knowing their types
     pub(crate) globals: Vec<u64>,
-    pub(crate) global_types: Vec<ValueType>, // used statically for type checking
 
-    /// Trap entry label
+    /// data segments for memory.init, never resized once the code is emitted
+    /// since the jit code holds their addresses
+    pub(crate) data_segments: Vec<JitDataSegment>,
+
+    /// return area for multi-value functions, the first result is returned
+    /// in rax and the i-th result (i > 0) is stored in multi_results[i] by
+    /// the callee, the caller reads them right after the call
+    pub(crate) multi_results: Vec<u64>,
+
+    /// the exception in flight, the tag index + 1 of the exception or 0 if
+    /// there is none, followed by the values it carries. Never resized
+    /// since the jit code holds its address, see `insts::exception`
+    pub(crate) exception: Vec<u64>,
+    /// whether the module throws at all, the calls then check for an
+    /// exception once they return
+    pub(crate) throws: bool,
+    /// the exit of the current function taken by the exceptions it does not
+    /// catch, it returns to the caller with the exception in flight
+    pub(crate) unwind_label: DestLabel,
+    /// the frame slot of the save area of the catch clauses of each try
+    /// nesting level of the current function, empty if it does not rethrow
+    pub(crate) exception_saves: Vec<usize>,
+
+    /// Trap entry label, every function has its own trap exits so the trap
+    /// handler can tell which function trapped, see `emit_trap_exits`. This
+    /// one is taken by failed host calls and compilations, whose error is
+    /// kept aside, the wasm traps go through the exit of their kind
     pub(crate) trap_label: DestLabel,
+    /// the trap exit of each kind the current function traps with, see
+    /// `trap_exit`
+    pub(crate) trap_exits: Vec<(TrapKind, DestLabel)>,
+    /// the faulting instruction of every trap site with the reason it traps,
+    /// the trap exits and the memory accesses that fault past the memory,
+    /// registered for the trap handler once relocated
+    pub(crate) trap_sites: Vec<(DestLabel, TrapSite)>,
+    pub(crate) trap_site_registration: TrapSiteRegistration,
+    /// the epilogue shared by the entries into the jit code, where a trap
+    /// resumes, see `setup::trap`
+    pub(crate) entry_exit: DestLabel,
+
+    /// the number of active wasm frames, bumped in the prologue and dropped
+    /// in the epilogue, boxed so its address can be embedded in the jit code
+    pub(crate) call_depth: Box<u64>,
+    pub(crate) max_call_depth: u64,
+
+    /// the last callee of each indirect call that had the signature the
+    /// call expects, `u32::MAX` before the first call, by function index and
+    /// pc. The jit code reads and writes them through their addresses
+    pub(crate) indirect_callees: Vec<Box<[u32]>>,
+
+    /// the fuel left when the execution is metered, function entries and
+    /// loop headers consume one unit each and jump to `out_of_fuel_label`
+    /// once it is gone. It is loaded from and written back to
+    /// `fuel_source` with the rest of the state
+    pub(crate) fuel: Option<Box<u64>>,
+    pub(crate) fuel_source: Option<Rc<Cell<Option<u64>>>>,
+    pub(crate) out_of_fuel_label: DestLabel,
+
+    /// checked at the same places as the fuel, jumps to `interrupt_label`
+    /// once the epoch reaches the deadline of the current call
+    pub(crate) epoch: Option<Epoch>,
+    pub(crate) epoch_deadline: Box<u64>,
+    pub(crate) interrupt_label: DestLabel,
+
+    /// call `trace_block` at the start of every basic block
+    pub(crate) trace: bool,
+    /// stop at `int3` when an entry is entered, for a debugger attached
+    pub(crate) debug_break: bool,
+    /// replace the NaNs produced by float arithmetic by the canonical NaN
+    pub(crate) canonicalize_nans: bool,
+    /// the elements no table can grow past, whatever its maximum
+    pub(crate) max_table_elements: u64,
+    /// approves the growth of the memories and tables, boxed so the jit code
+    /// can hold its address
+    pub(crate) limiter: Option<Box<SharedLimiter>>,
+
+    /// the function index and pc of the instruction being compiled
+    pub(crate) current_site: (u32, usize),
+    /// the label right after every call instruction with the function index
+    /// and pc of the call, the return addresses of a backtrace
+    pub(crate) call_sites: Vec<(DestLabel, u32, usize)>,
+    /// bound around the trap exits shared by the host stubs
+    pub(crate) host_trap_exit: (DestLabel, DestLabel),
+    /// print a wasm backtrace when the jit code traps, see `jit::backtrace`
+    pub(crate) backtrace: bool,
+    pub(crate) code_map_registration: Option<CodeMapRegistration>,
+
+    /// Imported functions are called through the host dispatcher, the
+    /// context is boxed so its address can be embedded in the jit code
+    pub(crate) host_ctx: Box<JitHostContext>,
 
     /// function labels
     pub(crate) func_labels: Vec<DestLabel>,
     pub(crate) func_addrs: Vec<u64>,       // after relocation
     pub(crate) func_sig_indices: Vec<u32>, // for call_indirect dynamic type checking
+    /// bound right after the code of every compiled function, `None` for
+    /// imports
+    pub(crate) func_end_labels: Vec<Option<DestLabel>>,
+
+    /// describe the compiled functions to debuggers, see `jit::gdb`
+    pub(crate) debug_info: bool,
+    /// one registration per batch of compiled functions
+    pub(crate) gdb_registrations: Vec<GdbJitRegistration>,
+    /// list the compiled functions in /tmp/perf-<pid>.map for perf
+    pub(crate) perf_map: bool,
+    /// compile every function on its first call, see `setup::lazy`
+    pub(crate) lazy: bool,
+    /// the loop the function being compiled is entered at, see
+    /// `setup::tier`
+    pub(crate) osr: Option<OsrTarget>,
 }
 
 impl<'a> X86JitCompiler<'a> {
-    pub fn new(module: Rc<RefCell<WasmModule<'a>>>) -> Self {
+    /// A compiler of the code of the instance running on `store` with
+    /// `settings`, fails if the module uses what the jit does not support.
+    pub(crate) fn new(
+        module: Rc<WasmModule<'a>>,
+        store: Rc<Store<'a>>,
+        settings: &JitSettings,
+    ) -> Result<Self> {
         let mut jit = JitMemory::new();
         let trap_label = jit.label();
+        let out_of_fuel_label = jit.label();
+        let interrupt_label = jit.label();
+        let host_trap_exit = (jit.label(), jit.label());
+        let entry_exit = jit.label();
 
         // get some statically known information
-        let module = Rc::clone(&module);
-        let nglobals = module.borrow().get_globals().len();
-        let global_types: Vec<ValueType> = module
-            .borrow()
-            .get_globals()
-            .iter()
-            .map(|g| g.get_ty().content_type)
-            .map(|ty| match ty {
-                ValType::I32 => ValueType::I32,
-                ValType::F64 => ValueType::F64,
-                _ => unreachable!(),
-            })
-            .collect();
-        let ntables = module.borrow().get_tables().len();
-        let nfuncs = module.borrow().get_funcs().len();
+        let nglobals = module.get_globals().len();
+        for global in module.get_globals() {
+            ValueType::from_val_type(&global.get_ty().content_type)?;
+        }
+        let nfuncs = module.get_funcs().len();
         let func_sig_indices: Vec<u32> = module
-            .borrow()
             .get_funcs()
             .iter()
-            .map(|f| module.borrow().get_sig_index(f.get_sig()).unwrap() as u32)
+            .map(|f| module.get_sig_index(f.get_sig()).unwrap() as u32)
             .collect();
         let func_labels = module
-            .borrow()
             .get_funcs()
             .iter()
             .map(|_| jit.label())
             .collect::<Vec<_>>();
-        let mem_limit = match module.borrow().get_memory() {
-            Some(mem) => mem.maximum.unwrap_or(mem.initial),
-            None => 0,
-        };
+        let mut linear_mems = module
+            .get_memories()
+            .iter()
+            .zip(&store.mems)
+            .map(|(ty, mem)| {
+                JitLinearMemory::new(
+                    Some(mem.clone()),
+                    ty.maximum.unwrap_or(DEFAULT_MEMORY_MAXIMUM),
+                    ty.memory64,
+                )
+            })
+            .collect::<Vec<_>>();
+        if linear_mems.is_empty() {
+            linear_mems.push(JitLinearMemory::new(None, 0, false));
+        }
+        // the memories declared without a maximum grow to the default one,
+        // none grows past the limit of the settings
+        for (mem, ty) in linear_mems.iter_mut().zip(module.get_memories()) {
+            if ty.maximum.is_none() {
+                mem.set_mem_limit(settings.default_memory_maximum);
+            }
+        }
+        for mem in &mut linear_mems {
+            mem.set_mem_limit(mem.mem_limit().min(settings.max_memory_pages));
+        }
+        if !linear_mems.iter().all(JitLinearMemory::is_guarded) {
+            return Err(anyhow!(
+                "the jit needs the whole address space reservation of a memory"
+            ));
+        }
+        let max_results = module
+            .get_funcs()
+            .iter()
+            .map(|f| f.get_sig().results().len())
+            .max()
+            .unwrap_or(0);
+        let indirect_callees = module
+            .get_funcs()
+            .iter()
+            .map(|f| {
+                let indirect_call = |inst: &Instruction| {
+                    matches!(
+                        inst,
+                        Instruction::CallIndirect { .. } | Instruction::ReturnCallIndirect { .. }
+                    )
+                };
+                if f.get_insts().iter().any(indirect_call) {
+                    vec![u32::MAX; f.get_insts().len()].into()
+                } else {
+                    Box::default()
+                }
+            })
+            .collect();
+        let max_tag_values = module
+            .get_tags()
+            .iter()
+            .filter_map(|tag| module.get_sig(tag.func_type_idx))
+            .map(|sig| sig.params().len())
+            .max()
+            .unwrap_or(0);
+        let throws = module.get_funcs().iter().any(|f| {
+            f.get_insts().iter().any(|inst| {
+                matches!(
+                    inst,
+                    Instruction::Throw { .. } | Instruction::Rethrow { .. }
+                )
+            })
+        });
+        let unwind_label = jit.label();
+        let imports = HostFuncImport::resolve_all(&module)?;
+        let host_ctx = Box::new(JitHostContext::new(imports));
 
         let mut compiler = Self {
             module,
+            store,
             reg_allocator: X86RegisterAllocator::new(),
             reg_reconcile_info: Vec::new(),
             control_flow_stack: VecDeque::new(),
             jit,
+            addr_sites: Vec::new(),
+            cache_dir: settings.cache_dir.clone(),
+            cache_hit: None,
             brtable_nondefault_target_labels: HashMap::new(),
             brtable_nondefault_target_addrs: HashMap::new(),
-            linear_mem: JitLinearMemory::new(mem_limit),
-            tables: vec![vec![]; ntables],
-            table_len: vec![0; ntables],
+            linear_mems,
+            tables: Vec::new(),
+            elem_segments: Vec::new(),
             globals: vec![0; nglobals],
-            global_types,
+            data_segments: Vec::new(),
+            multi_results: vec![0; std::cmp::max(max_results, 1)],
+            exception: vec![0; 1 + max_tag_values],
+            throws,
+            unwind_label,
+            exception_saves: Vec::new(),
             trap_label,
+            trap_exits: Vec::new(),
+            trap_sites: Vec::new(),
+            trap_site_registration: TrapSiteRegistration::default(),
+            entry_exit,
+            call_depth: Box::new(0),
+            max_call_depth: settings.max_call_depth as u64,
+            indirect_callees,
+            fuel: settings.fuel.get().map(Box::new),
+            fuel_source: Some(Rc::clone(&settings.fuel)),
+            out_of_fuel_label,
+            epoch: settings.epoch.clone(),
+            epoch_deadline: Box::new(u64::MAX),
+            interrupt_label,
+            trace: settings.trace,
+            debug_break: settings.debug_break,
+            canonicalize_nans: settings.canonicalize_nans,
+            max_table_elements: settings.max_table_elements,
+            limiter: settings.limiter.clone().map(Box::new),
+            current_site: (0, 0),
+            call_sites: Vec::new(),
+            host_trap_exit,
+            backtrace: settings.backtrace,
+            code_map_registration: None,
+            host_ctx,
             func_labels,
             func_addrs: vec![0; nfuncs], // setup after compilation
             func_sig_indices,
+            func_end_labels: vec![None; nfuncs],
+            debug_info: settings.debug_info,
+            gdb_registrations: Vec::new(),
+            perf_map: settings.perf_map,
+            lazy: settings.lazy,
+            osr: None,
         };
 
         compiler.set_brtable_nondefault_target_labels();
         compiler.presize_brtable_nondefault_target_addrs();
+        compiler.setup_tables();
+        compiler.setup_data();
 
-        compiler
+        Ok(compiler)
     }
-}
 
-impl WasmJitCompiler for X86JitCompiler<'_> {
-    fn compile(&mut self, main_params: Vec<WasmValue>) -> Result<CodePtr> {
-        let vm_entry_label = self.setup_runtime(main_params);
+    /// Why the jit cannot run `module`, if it cannot: the calls then run in
+    /// the interpreter, on the same store. This is the case of every module
+    /// using simd: the lowering of v128 code to SSE is not implemented yet,
+    /// it needs 16 byte registers and stack slots in `X86RegisterAllocator`.
+    pub fn unsupported(module: &WasmModule) -> Option<String> {
+        module
+            .get_funcs()
+            .iter()
+            .position(|func| !Self::supports(module, func))
+            .map(|func_index| format!("the simd of function {}", func_index))
+    }
 
-        self.compile_functions()?;
+    /// Whether the jit supports `func` of `module`, which must not use simd
+    /// nor throw or catch v128 values.
+    pub(crate) fn supports(module: &WasmModule, func: &FuncDecl) -> bool {
+        let sig = func.get_sig();
+        let no_v128 = |ty: &ValType| *ty != ValType::V128;
+        if !sig.params().iter().all(no_v128)
+            || !sig.results().iter().all(no_v128)
+            || !func.get_pure_locals().iter().all(no_v128)
+        {
+            return false;
+        }
 
-        let codeptr = self.finalize(vm_entry_label);
+        let no_v128_values = |tag_index: &u32| {
+            module
+                .get_tag_sig(*tag_index)
+                .is_none_or(|sig| sig.params().iter().all(no_v128))
+        };
+        func.get_insts().iter().all(|inst| match inst {
+            Instruction::TypedSelect { tys } => tys.iter().all(no_v128),
+            Instruction::Catch { tag_index } | Instruction::Throw { tag_index } => {
+                no_v128_values(tag_index)
+            }
+            Instruction::V128Load { .. }
+            | Instruction::V128Store { .. }
+            | Instruction::V128LoadExtend { .. }
+            | Instruction::V128LoadSplat { .. }
+            | Instruction::V128LoadZero { .. }
+            | Instruction::V128LoadLane { .. }
+            | Instruction::V128StoreLane { .. }
+            | Instruction::V128Const { .. }
+            | Instruction::I8x16Shuffle { .. }
+            | Instruction::V128Splat { .. }
+            | Instruction::V128ExtractLane { .. }
+            | Instruction::V128ReplaceLane { .. }
+            | Instruction::V128Bitselect
+            | Instruction::V128Unop(_)
+            | Instruction::V128Binop(_)
+            | Instruction::V128Shift(_)
+            | Instruction::V128Test(_) => false,
+            _ => true,
+        })
+    }
 
-        log::debug!("\n{}", self.jit.dump_code().unwrap());
-        Ok(unsafe { std::mem::transmute::<u64, CodePtr>(codeptr) })
+    /// The address of the limiter the helpers get, 0 without one.
+    pub(crate) fn limiter_addr(&self) -> u64 {
+        self.limiter
+            .as_deref()
+            .map_or(0, |limiter| limiter as *const SharedLimiter as u64)
     }
 }
 
 impl X86JitCompiler<'_> {
-    fn compile_func(&mut self, fdecl: &FuncDecl) -> Result<()> {
-        let func_index = self.module.borrow().get_func_index(fdecl).unwrap();
+    pub(crate) fn compile_func(&mut self, func_index: usize, fdecl: &FuncDecl) -> Result<()> {
         let func_start = *self.func_labels.get(func_index).unwrap();
+        let code_end = self.emit_func(func_index, fdecl, func_start)?;
+        self.func_end_labels[func_index] = Some(code_end);
+        Ok(())
+    }
+
+    /// Emit the code of a function starting at `func_start`, returns the
+    /// label bound right after it.
+    pub(crate) fn emit_func(
+        &mut self,
+        func_index: usize,
+        fdecl: &FuncDecl,
+        func_start: DestLabel,
+    ) -> Result<DestLabel> {
         let stack_size = self.get_stack_size_in_byte(fdecl);
 
         // reset per function state
@@ -168,18 +462,29 @@ impl X86JitCompiler<'_> {
         self.control_flow_stack.clear();
         self.reg_reconcile_info.clear();
 
-        let end_labels = self.pregen_labals_for_ends(fdecl.get_insts());
-        let else_labels = self.pregen_labels_for_else(fdecl.get_insts());
-        let func_end = *end_labels.get(&(fdecl.get_insts().len() - 1)).unwrap();
+        let insts = fold_constants(fdecl.get_insts());
+        let end_labels = self.pregen_labals_for_ends(&insts);
+        let else_labels = self.pregen_labels_for_else(&insts);
+        let func_end = *end_labels.get(&(insts.len() - 1)).unwrap();
         self.push_initial_control_frame(fdecl, func_start, func_end);
 
+        // the trap exits of this function, bound after its return
+        self.trap_label = self.jit.label();
+        self.out_of_fuel_label = self.jit.label();
+        self.interrupt_label = self.jit.label();
+        self.unwind_label = self.jit.label();
+        self.trap_exits.clear();
+
         // start compilation
         self.prologue(func_start, stack_size);
 
-        let local_types = self.setup_locals(fdecl);
+        let local_types = self.setup_locals(fdecl)?;
+        if self.osr.is_some() {
+            self.emit_osr_prelude(local_types.len());
+        }
         self.emit_asm(
             func_index as u32,
-            fdecl.get_insts(),
+            &insts,
             &local_types,
             stack_size,
             else_labels,
@@ -187,131 +492,257 @@ impl X86JitCompiler<'_> {
         )?;
 
         // emit return, epilogue embedded
-        self.emit_function_return(Some(func_end), stack_size);
+        let num_results = fdecl.get_sig().results().len();
+        self.emit_function_return(Some(func_end), stack_size, num_results);
+        self.emit_unwind_exit(stack_size);
+        self.emit_trap_exits();
 
-        Ok(())
+        let code_end = self.jit.label();
+        self.emit_single_label(code_end);
+        Ok(code_end)
     }
 }
 
 impl X86JitCompiler<'_> {
-    fn setup_runtime(&mut self, main_params: Vec<WasmValue>) -> DestLabel {
-        self.setup_trap_entry();
-        self.setup_tables();
-        self.setup_globals().expect("setup globals failed");
+    /// Emit the trap exits and the host stubs, then compile every function
+    /// or emit its lazy stub. The compiler must not move once it is set up,
+    /// the stubs hold its address. The functions are entered through the
+    /// entries of `setup::tier`.
+    pub(crate) fn setup(&mut self) -> Result<()> {
+        if self.cache_dir.is_some() && !self.lazy {
+            return self.setup_cached();
+        }
+        self.emit_setup_code()?;
+        self.finalize();
+        Ok(())
+    }
 
-        // setup vm entry, the entry point of the whole program
-        let module = Rc::clone(&self.module);
-        let main_label = self
-            .func_labels
-            .get(module.borrow().get_main_index().unwrap() as usize)
-            .unwrap();
-        let initial_mem_size_in_byte = module
-            .borrow()
-            .get_memory()
-            .map(|m| m.initial as usize * WASM_DEFAULT_PAGE_SIZE_BYTE)
-            .unwrap_or(0) as u64;
-        self.setup_vm_entry(*main_label, initial_mem_size_in_byte, main_params)
+    /// Everything `setup` emits before the code is relocated.
+    pub(crate) fn emit_setup_code(&mut self) -> Result<()> {
+        // the host stubs trap through these exits
+        let (host_trap_exit_start, host_trap_exit_end) = self.host_trap_exit;
+        self.emit_single_label(host_trap_exit_start);
+        self.emit_trap_exits();
+        self.emit_single_label(host_trap_exit_end);
+        self.emit_entry_exit();
+        self.setup_host_stubs();
+        self.compile_functions()
     }
 
-    fn compile_functions(&mut self) -> Result<()> {
+    pub(crate) fn compile_functions(&mut self) -> Result<()> {
         let module = Rc::clone(&self.module);
-        for fdecl in module.borrow().get_funcs().iter() {
-            self.compile_func(fdecl)?;
+        let nimports = module.get_num_func_imports();
+
+        // imported functions are bound to host stubs in setup_host_stubs
+        for (func_index, fdecl) in module.get_funcs().iter().enumerate().skip(nimports) {
+            if self.lazy {
+                self.emit_lazy_stub(func_index);
+            } else {
+                self.compile_func(func_index, fdecl)?;
+            }
         }
 
         Ok(())
     }
 
-    fn finalize(&mut self, vm_entry_label: DestLabel) -> u64 {
+    pub(crate) fn finalize(&mut self) {
         self.jit.finalize();
 
         // fill in the relocated addresses after jit relocation
         for (i, label) in self.func_labels.iter().enumerate() {
             self.func_addrs[i] = self.jit.get_label_u64(*label);
         }
-        for (func_index, nondefault_target_labels) in self.brtable_nondefault_target_labels.iter() {
-            let nondefault_target_addrs_ref = self
-                .brtable_nondefault_target_addrs
-                .get_mut(func_index)
-                .unwrap();
-            for (ith_table, labels) in nondefault_target_labels.iter().enumerate() {
-                for (i, label) in labels.iter().enumerate() {
-                    nondefault_target_addrs_ref[ith_table][i] = self.jit.get_label_u64(*label);
+        let compiled = (0..self.func_end_labels.len())
+            .filter(|i| self.func_end_labels[*i].is_some())
+            .collect::<Vec<_>>();
+        for func_index in &compiled {
+            self.fill_brtable_addrs(*func_index);
+        }
+        self.register_compiled_code(&compiled);
+    }
+
+    /// Fill in the relocated addresses of the br_table targets of a compiled
+    /// function.
+    pub(crate) fn fill_brtable_addrs(&mut self, func_index: usize) {
+        let nondefault_target_labels = &self.brtable_nondefault_target_labels[&func_index];
+        let nondefault_target_addrs_ref = self
+            .brtable_nondefault_target_addrs
+            .get_mut(&func_index)
+            .unwrap();
+        for (ith_table, labels) in nondefault_target_labels.iter().enumerate() {
+            for (i, label) in labels.iter().enumerate() {
+                nondefault_target_addrs_ref[ith_table][i] = self.jit.get_label_u64(*label);
+            }
+        }
+    }
+
+    /// Make newly compiled functions known to the trap handler, debuggers
+    /// and perf, only valid after relocation.
+    pub(crate) fn register_compiled_code(&mut self, func_indices: &[usize]) {
+        self.register_trap_sites();
+        if self.backtrace {
+            // the code map covers every compiled function, the old one is
+            // unregistered before the new one is installed
+            self.code_map_registration.take();
+            let code_map = self.code_map();
+            self.code_map_registration = Some(CodeMapRegistration::register(code_map));
+        }
+
+        if self.debug_info || self.perf_map {
+            let symbols = self.func_symbols(func_indices);
+            if self.debug_info {
+                self.gdb_registrations
+                    .push(GdbJitRegistration::register(&symbols));
+            }
+            // profiling is best effort, the code runs all the same
+            if self.perf_map {
+                if let Err(e) = write_perf_map(&symbols) {
+                    log::warn!("failed to write the perf map: {}", e);
                 }
             }
         }
+    }
+
+    /// Make the trap sites emitted since the last call known to the trap
+    /// handler, only valid after relocation.
+    pub(crate) fn register_trap_sites(&mut self) {
+        let sites = std::mem::take(&mut self.trap_sites)
+            .into_iter()
+            .map(|(label, kind)| (self.jit.get_label_u64(label), kind))
+            .collect::<Vec<_>>();
+        self.trap_site_registration.register(sites);
+    }
+
+    /// The symbol name and code address range of the given compiled
+    /// functions, only valid after relocation.
+    pub(crate) fn func_symbols(&self, func_indices: &[usize]) -> Vec<(String, Range<u64>)> {
+        let module = &self.module;
+        func_indices
+            .iter()
+            .filter_map(|&i| {
+                let end = self.jit.get_label_u64(self.func_end_labels[i]?);
+                let name = func_symbol_name(i as u32, module.get_func_name(i as u32));
+                Some((name, self.func_addrs[i]..end))
+            })
+            .collect()
+    }
+
+    /// The functions and call sites for the trap handler, only valid after
+    /// relocation.
+    fn code_map(&self) -> JitCodeMap {
+        let module = &self.module;
+        let frame = |func_index: u32, pc: Option<usize>| WasmFrame {
+            func_index,
+            func_name: module.get_func_name(func_index).map(str::to_string),
+            pc,
+            location: pc.and_then(|pc| module.get_source_location(func_index, pc)),
+        };
+        let funcs = self
+            .func_end_labels
+            .iter()
+            .enumerate()
+            .filter_map(|(i, end_label)| {
+                let end = self.jit.get_label_u64((*end_label)?);
+                Some((self.func_addrs[i]..end, frame(i as u32, None)))
+            })
+            .collect();
+        let call_sites = self
+            .call_sites
+            .iter()
+            .map(|(label, func_index, pc)| {
+                (
+                    self.jit.get_label_u64(*label),
+                    frame(*func_index, Some(*pc)),
+                )
+            })
+            .collect();
+        let (start, end) = self.host_trap_exit;
+        JitCodeMap {
+            funcs,
+            call_sites,
+            host_trap_exit: self.jit.get_label_u64(start)..self.jit.get_label_u64(end),
+        }
+    }
+
+    /// The trap exit of the current function for traps of `kind`.
+    pub(crate) fn trap_exit(&mut self, kind: TrapKind) -> DestLabel {
+        if let Some((_, label)) = self.trap_exits.iter().find(|(k, _)| *k == kind) {
+            return *label;
+        }
+        let label = self.jit.label();
+        self.trap_exits.push((kind, label));
+        label
+    }
 
-        // return vm_entry address for initial execution
-        self.jit.get_label_u64(vm_entry_label)
+    /// Bind the current trap, out of fuel and interrupt labels and the trap
+    /// exit of every kind. Each exit faults at its own store, which is
+    /// recorded as a trap site so the trap handler can tell why it trapped.
+    pub(crate) fn emit_trap_exits(&mut self) {
+        let mut exits = vec![(TrapSite::Host, self.trap_label)];
+        exits.extend(
+            std::mem::take(&mut self.trap_exits)
+                .into_iter()
+                .map(|(kind, label)| (TrapSite::Wasm(kind), label)),
+        );
+        exits.push((
+            TrapSite::Wasm(TrapKind::FuelExhausted),
+            self.out_of_fuel_label,
+        ));
+        exits.push((TrapSite::Wasm(TrapKind::Interrupted), self.interrupt_label));
+        for (site, exit_label) in exits {
+            let fault_label = self.jit.label();
+            monoasm!(
+                &mut self.jit,
+                exit_label:
+                    movq rax, 0;
+                fault_label:
+                    movq [rax], 1;
+            );
+            self.trap_sites.push((fault_label, site));
+        }
     }
 
-    fn setup_trap_entry(&mut self) -> DestLabel {
-        let trap_label = self.trap_label;
+    /// Bind the epilogue of the entries, which restores the registers saved
+    /// by `emit_entry_prologue` and returns to the rust caller.
+    pub(crate) fn emit_entry_exit(&mut self) {
+        let entry_exit = self.entry_exit;
         monoasm!(
             &mut self.jit,
-            trap_label:
-                movq rax, 0;
-                movq [rax], 1;
+        entry_exit:
+            movq rsp, rbp;
+            subq rsp, (5 * 8);
+            popq r15;
+            popq r14;
+            popq r13;
+            popq r12;
+            popq rbx;
+            popq rbp;
+            ret;
         );
-
-        trap_label
     }
 
-    fn setup_vm_entry(
-        &mut self,
-        main_label: DestLabel,
-        initial_mem_size_in_byte: u64,
-        main_params: Vec<WasmValue>,
-    ) -> DestLabel {
-        let vm_entry_label = self.jit.label();
+    /// Save the callee-saved registers of the rust caller and store the
+    /// frame pointer of the entry in the trap frame at `frame`, see
+    /// `setup::trap::call_guarded`. rsp is 8 bytes off the 16 bytes
+    /// alignment afterwards.
+    pub(crate) fn emit_entry_prologue(&mut self, frame: X86Register) {
         monoasm!(
             &mut self.jit,
-            vm_entry_label:
+            pushq rbp;
+            movq rbp, rsp;
+            pushq rbx;
+            pushq r12;
+            pushq r13;
+            pushq r14;
+            pushq r15;
+            movq [R(frame.as_index())], rbp;
         );
+    }
 
-        // setup linear memory info
-        self.linear_mem
-            .init_size(&mut self.jit, initial_mem_size_in_byte);
-
-        self.setup_data().expect("setup data segment failed");
-
-        // setup main params
-        for (i, param) in main_params.iter().enumerate() {
-            if i < 6 {
-                let reg = Register::from_ith_argument(i as u32);
-                match param {
-                    WasmValue::I32(v) => {
-                        self.emit_mov_rawvalue_to_reg(*v as u64, reg);
-                    }
-                    WasmValue::F64(v) => {
-                        self.emit_mov_rawvalue_to_reg(v.to_bits(), reg);
-                    }
-                }
-            } else {
-                // push the constant to stack
-                match param {
-                    WasmValue::I32(v) => {
-                        self.emit_mov_rawvalue_to_reg(*v as u64, Register::Reg(REG_TEMP));
-                        monoasm!(
-                            &mut self.jit,
-                            pushq R(REG_TEMP.as_index());
-                        );
-                    }
-                    WasmValue::F64(v) => {
-                        self.emit_mov_rawvalue_to_reg(v.to_bits(), Register::FpReg(REG_TEMP_FP));
-                        monoasm!(
-                            &mut self.jit,
-                            pushq R(REG_TEMP_FP.as_index());
-                        );
-                    }
-                }
-            }
-        }
-
-        // jump to main
-        self.emit_jmp(main_label);
-
-        vm_entry_label
+    /// The address the entries resume at after a trap, only valid after
+    /// relocation.
+    pub(crate) fn trap_landing(&self) -> u64 {
+        self.jit.get_label_u64(self.entry_exit)
     }
 
     fn push_initial_control_frame(
@@ -325,13 +756,14 @@ impl X86JitCompiler<'_> {
             expected_stack_height: 0,
             entry_regalloc_snapshot: self.reg_allocator.clone(),
             num_results: fdecl.get_sig().results().len(),
+            num_params: 0,
             start_label,
             end_label,
         });
     }
 
     // TODO: refactor this......
-    fn setup_locals(&mut self, fdecl: &FuncDecl) -> Vec<ValueType> {
+    fn setup_locals(&mut self, fdecl: &FuncDecl) -> Result<Vec<ValueType>> {
         let mut local_types = Vec::new();
         let mut local_base_set = false;
         for (i, params) in fdecl.get_sig().params().iter().enumerate() {
@@ -354,15 +786,7 @@ impl X86JitCompiler<'_> {
 
             if i < 6 {
                 emit_mov_reg_to_reg(&mut self.jit, r.reg, Register::from_ith_argument(i as u32));
-                match params {
-                    ValType::I32 => {
-                        local_types.push(ValueType::I32);
-                    }
-                    ValType::F64 => {
-                        local_types.push(ValueType::F64);
-                    }
-                    _ => unreachable!(),
-                }
+                local_types.push(ValueType::from_val_type(params)?);
             } else {
                 // the locals are spilled to the stack
                 match params {
@@ -374,6 +798,30 @@ impl X86JitCompiler<'_> {
                         emit_mov_reg_to_reg(&mut self.jit, r.reg, Register::Reg(REG_TEMP));
                         local_types.push(ValueType::I32);
                     }
+                    ValType::I64 => {
+                        monoasm!(
+                            &mut self.jit,
+                            movq R(REG_TEMP.as_index()), [rbp + ((i as i32 - 6) * 8 + 16)];
+                        );
+                        emit_mov_reg_to_reg(&mut self.jit, r.reg, Register::Reg(REG_TEMP));
+                        local_types.push(ValueType::I64);
+                    }
+                    ValType::F32 => {
+                        monoasm!(
+                            &mut self.jit,
+                            movq R(REG_TEMP.as_index()), [rbp + ((i as i32 - 6) * 8 + 16)];
+                        );
+                        emit_mov_reg_to_reg(&mut self.jit, r.reg, Register::Reg(REG_TEMP));
+                        local_types.push(ValueType::F32);
+                    }
+                    ValType::Ref(_) => {
+                        monoasm!(
+                            &mut self.jit,
+                            movq R(REG_TEMP.as_index()), [rbp + ((i as i32 - 6) * 8 + 16)];
+                        );
+                        emit_mov_reg_to_reg(&mut self.jit, r.reg, Register::Reg(REG_TEMP));
+                        local_types.push(ValueType::Ref);
+                    }
                     ValType::F64 => {
                         monoasm!(
                             &mut self.jit,
@@ -404,17 +852,27 @@ impl X86JitCompiler<'_> {
                 }
             }
 
-            match l {
-                ValType::I32 => local_types.push(ValueType::I32),
-                ValType::F64 => local_types.push(ValueType::F64),
-                _ => unreachable!(),
-            }
+            // zero is also the null reference
+            local_types.push(ValueType::from_val_type(&l)?);
         }
 
-        // clear the register vector
-        self.reg_allocator.clear_vec();
+        // the save areas of the catch clauses follow the locals, see
+        // `insts::exception`
+        self.exception_saves.clear();
+        for _ in 0..Self::exception_save_levels(fdecl) {
+            let slots = (0..self.exception.len())
+                .map(|_| self.reg_allocator.new_spill(ValueType::I64).reg)
+                .collect::<Vec<_>>();
+            let Register::Stack(save) = slots[0] else {
+                unreachable!("the save areas are spilled");
+            };
+            self.exception_saves.push(save);
+        }
 
-        local_types
+        // clear the register vector, the locals keep their slots
+        self.reg_allocator.seal_locals();
+
+        Ok(local_types)
     }
 
     fn prologue(&mut self, func_begin_label: DestLabel, stack_size: u64) {
@@ -432,9 +890,80 @@ impl X86JitCompiler<'_> {
             pushq r14;
             pushq r15;
         );
+
+        // stack probe, trap once the frames nest deeper than the limit
+        let call_depth_ptr = &*self.call_depth as *const u64 as u64;
+        let max_call_depth = self.max_call_depth;
+        let trap_label = self.trap_exit(TrapKind::StackExhausted);
+        self.emit_mov_addr(REG_TEMP2, call_depth_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+            addq R(REG_TEMP.as_index()), (1);
+            movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+            movq R(REG_TEMP2.as_index()), (max_call_depth);
+            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+            jae trap_label;
+        );
+
+        self.emit_fuel_check();
+        self.emit_epoch_check();
+    }
+
+    /// Consume one unit of fuel, jumping to the out of fuel exit if there is
+    /// none left. Nothing is emitted if the execution is not metered.
+    pub(crate) fn emit_fuel_check(&mut self) {
+        let Some(fuel) = &self.fuel else {
+            return;
+        };
+        let fuel_ptr = &**fuel as *const u64 as u64;
+        let out_of_fuel_label = self.out_of_fuel_label;
+        self.emit_mov_addr(REG_TEMP2, fuel_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+            cmpq R(REG_TEMP.as_index()), (0);
+            je out_of_fuel_label;
+            subq R(REG_TEMP.as_index()), (1);
+            movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+        );
     }
 
-    fn epilogue(&mut self, stack_size: u64) {
+    /// Jump to the interrupt exit if the epoch reached the deadline. Nothing
+    /// is emitted without epoch interruption.
+    pub(crate) fn emit_epoch_check(&mut self) {
+        let Some(epoch) = &self.epoch else {
+            return;
+        };
+        let epoch_ptr = epoch.as_ptr() as u64;
+        let deadline_ptr = &*self.epoch_deadline as *const u64 as u64;
+        let interrupt_label = self.interrupt_label;
+        self.emit_mov_addr(REG_TEMP2, epoch_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+        );
+        self.emit_mov_addr(REG_TEMP2, deadline_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP2.as_index()), [R(REG_TEMP2.as_index())];
+            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+            jae interrupt_label;
+        );
+    }
+
+    pub(crate) fn epilogue(&mut self, stack_size: u64) {
+        // rax holds the result or the tail callee, only the temporaries are
+        // free before they are restored
+        let call_depth_ptr = &*self.call_depth as *const u64 as u64;
+        self.emit_mov_addr(REG_TEMP2, call_depth_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+            subq R(REG_TEMP.as_index()), (1);
+            movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+        );
+
         // NOTE: on x86-64 linux, xmms are temporary registers
         // so we don't need to save and restore them
         monoasm!(
@@ -460,12 +989,46 @@ impl X86JitCompiler<'_> {
         }
     }
 
-    pub(crate) fn emit_function_return(&mut self, end_label: Option<DestLabel>, stack_size: u64) {
+    /// Store the results after the first one to the multi-value return area,
+    /// the first result goes to rax last so it cannot be clobbered.
+    fn emit_mov_results_to_return_area(&mut self, num_results: usize) {
+        let regs = self.reg_allocator.get_vec();
+        if regs.len() < num_results {
+            return; /* unreachable code */
+        }
+
+        let results = regs[regs.len() - num_results..].to_vec();
+        for (i, result) in results.iter().enumerate().skip(1) {
+            let addr = self.multi_results.as_ptr() as u64 + (i * 8) as u64;
+            emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), result.reg);
+            self.emit_mov_addr(REG_TEMP2, addr);
+            monoasm!(
+                &mut self.jit,
+                movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+            );
+        }
+        emit_mov_reg_to_reg(
+            &mut self.jit,
+            Register::Reg(X86Register::Rax),
+            results[0].reg,
+        );
+    }
+
+    pub(crate) fn emit_function_return(
+        &mut self,
+        end_label: Option<DestLabel>,
+        stack_size: u64,
+        num_results: usize,
+    ) {
         if let Some(end_label) = end_label {
             self.emit_single_label(end_label);
         }
 
-        self.emit_mov_stack_top_return_reg();
+        if num_results > 1 {
+            self.emit_mov_results_to_return_area(num_results);
+        } else {
+            self.emit_mov_stack_top_return_reg();
+        }
         self.epilogue(stack_size);
         monoasm!(
             &mut self.jit,
@@ -475,7 +1038,7 @@ impl X86JitCompiler<'_> {
 
     fn set_brtable_nondefault_target_labels(&mut self) {
         let mut brtable_nondefault_target_labels = HashMap::new();
-        for (i, fdecl) in self.module.borrow().get_funcs().iter().enumerate() {
+        for (i, fdecl) in self.module.get_funcs().iter().enumerate() {
             let mut nondefault_target_labels = Vec::new();
             for inst in fdecl.get_insts() {
                 if let Instruction::BrTable { table } = inst {
@@ -508,7 +1071,7 @@ impl X86JitCompiler<'_> {
     fn pregen_labals_for_ends(&mut self, insts: &[Instruction]) -> HashMap<usize, DestLabel> {
         let mut end_labals = HashMap::new();
         for (i, inst) in insts.iter().enumerate() {
-            if let Instruction::End = inst {
+            if let Instruction::End | Instruction::Delegate { .. } = inst {
                 end_labals.insert(i, self.jit.label());
             }
         }
@@ -0,0 +1,274 @@
+//! A jit backend generating code with Cranelift, built with the `cranelift`
+//! feature. It translates every function to Cranelift IR, see `translate`,
+//! and lets Cranelift optimize it and generate the code for the host, so it
+//! runs on every architecture Cranelift supports.
+//!
+//! It shares the runtime of the riscv64 backend: the code runs on the store
+//! in place, checks for traps explicitly and calls the same helpers.
+//!
+//! The module is compiled on the first call only, the later calls of the
+//! instance enter the same code, see `WasmInterpreter::run_cranelift_jit`.
+
+mod translate;
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use cranelift_frontend::FunctionBuilderContext;
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use monoasm::CodePtr;
+use wasmparser::ValType;
+
+use self::translate::{func_signature, translate_import, FuncTranslator};
+use super::{
+    func_symbol_name,
+    runtime::{self, JitContext, STATUS_OK},
+    JitSettings, WasmJitCompiler,
+};
+use crate::{
+    module::{value_type::WasmValue, wasm_module::WasmModule},
+    vm::Store,
+};
+
+/// A function of the jit code, see `translate`.
+type CraneliftEntry = extern "C" fn(ctx: *mut JitContext, args: *mut u64) -> u64;
+
+pub struct CraneliftJitCompiler<'a> {
+    module: Rc<WasmModule<'a>>,
+    /// the state of the jit code, boxed as the code holds its address
+    ctx: Box<JitContext<'a>>,
+    settings: JitSettings,
+    /// owns the code, freed on drop
+    jit: Option<JITModule>,
+    func_addrs: Vec<u64>,
+    /// the arguments of the entry function, replaced by its results
+    args: Vec<u64>,
+    /// the types of the results of the entry function
+    results: Vec<ValType>,
+}
+
+impl<'a> CraneliftJitCompiler<'a> {
+    /// A compiler of the code of the instance running on `store` with
+    /// `settings`, of which it applies the call depth, the limits and the
+    /// canonicalization of NaNs.
+    pub(crate) fn new(
+        module: Rc<WasmModule<'a>>,
+        store: Rc<Store<'a>>,
+        settings: &JitSettings,
+    ) -> Result<Self> {
+        let mut ctx = Box::new(JitContext::new(Rc::clone(&module), store)?);
+        ctx.default_memory_maximum = settings.default_memory_maximum;
+        ctx.max_memory_pages = settings.max_memory_pages;
+        ctx.max_table_elements = settings.max_table_elements;
+        ctx.limiter = settings.limiter.clone();
+        Ok(Self {
+            module,
+            ctx,
+            settings: settings.clone(),
+            jit: None,
+            func_addrs: Vec::new(),
+            args: Vec::new(),
+            results: Vec::new(),
+        })
+    }
+
+    /// Whether the code was compiled with `settings`.
+    pub(crate) fn compiled_with(&self, settings: &JitSettings) -> bool {
+        self.settings == *settings
+    }
+
+    /// Why the backend cannot run `module`, if it cannot.
+    pub fn unsupported(module: &WasmModule) -> Option<String> {
+        runtime::unsupported(module)
+    }
+
+    /// Run the entry returned by `compile`, returns the results of the entry
+    /// function as raw values.
+    pub(crate) fn run(&mut self, entry: CodePtr) -> Result<Vec<u64>> {
+        let f: CraneliftEntry = unsafe { std::mem::transmute(entry) };
+        let status = f(&mut *self.ctx, self.args.as_mut_ptr());
+        self.ctx.store_globals();
+        if status != STATUS_OK {
+            return Err(self.ctx.take_error(status));
+        }
+        Ok(self.args[..self.results.len()].to_vec())
+    }
+
+    fn jit_module(&self) -> Result<JITModule> {
+        let flags = [
+            ("opt_level", "speed"),
+            (
+                "enable_nan_canonicalization",
+                if self.settings.canonicalize_nans {
+                    "true"
+                } else {
+                    "false"
+                },
+            ),
+        ];
+        let builder = JITBuilder::with_flags(&flags, default_libcall_names())?;
+        Ok(JITModule::new(builder))
+    }
+
+    /// Translate every function of the module and generate their code.
+    fn compile_module(&mut self) -> Result<()> {
+        let module = Rc::clone(&self.module);
+        let mut jit = self.jit_module()?;
+        let sig = func_signature(&jit);
+        let func_ids = (0..module.get_funcs().len() as u32)
+            .map(|i| {
+                let name = func_symbol_name(i, module.get_func_name(i));
+                Ok(jit.declare_function(&name, Linkage::Local, &sig)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut ctx = jit.make_context();
+        let mut func_ctx = FunctionBuilderContext::new();
+        let num_imports = module.get_num_func_imports();
+        for (func_index, func) in module.get_funcs().iter().enumerate() {
+            ctx.func.signature = sig.clone();
+            if func_index < num_imports {
+                translate_import(&jit, &mut ctx.func, &mut func_ctx, func_index as u32);
+            } else {
+                FuncTranslator::new(
+                    &mut jit,
+                    &mut ctx.func,
+                    &mut func_ctx,
+                    &module,
+                    &func_ids,
+                    func,
+                )
+                .translate(func)?;
+            }
+            jit.define_function(func_ids[func_index], &mut ctx)
+                .map_err(|e| anyhow!("function {}: {:?}", func_index, e))?;
+            jit.clear_context(&mut ctx);
+        }
+        jit.finalize_definitions()?;
+        self.func_addrs = func_ids
+            .iter()
+            .map(|id| jit.get_finalized_function(*id) as u64)
+            .collect();
+        self.jit = Some(jit);
+        Ok(())
+    }
+}
+
+impl WasmJitCompiler for CraneliftJitCompiler<'_> {
+    fn compile(&mut self, entry_index: u32, params: Vec<WasmValue>) -> Result<CodePtr> {
+        if let Some(reason) = Self::unsupported(&self.module) {
+            return Err(anyhow!("the cranelift jit does not support {}", reason));
+        }
+        let module = Rc::clone(&self.module);
+        let entry_func = module
+            .get_func(entry_index)
+            .ok_or_else(|| anyhow!("function {} not found", entry_index))?;
+
+        if self.jit.is_none() {
+            self.compile_module()?;
+        }
+
+        // the entry function takes its arguments where it leaves its results
+        let sig = entry_func.get_sig();
+        self.args = vec![0; sig.params().len().max(sig.results().len())];
+        for (arg, param) in self.args.iter_mut().zip(&params) {
+            *arg = param.to_raw();
+        }
+        self.results = sig.results().to_vec();
+
+        let ctx = &mut self.ctx;
+        ctx.load_state();
+        ctx.func_addrs = self.func_addrs.as_ptr();
+        // a trap leaves the depth of the call it stopped
+        ctx.depth = 0;
+        ctx.max_depth = self.settings.max_call_depth as u64;
+
+        let entry = self.func_addrs[entry_index as usize];
+        Ok(unsafe { std::mem::transmute::<u64, CodePtr>(entry) })
+    }
+}
+
+impl Drop for CraneliftJitCompiler<'_> {
+    fn drop(&mut self) {
+        if let Some(jit) = self.jit.take() {
+            // nothing refers to the code once the compiler is gone
+            unsafe { jit.free_memory() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        module::{value_type::WasmValue, wasm_module::WasmModule},
+        vm::{JitBackend, TrapKind, WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    const WAT: &str = r#"
+        (module
+          (global $count (mut i32) (i32.const 0))
+          (func (export "inc") (result i32)
+            (global.set $count (i32.add (global.get $count) (i32.const 1)))
+            (global.get $count))
+          ;; recurses `n` times, then traps if `trap` is set
+          (func $down (export "down") (param $n i32) (param $trap i32) (result i32)
+            (if (i32.eqz (local.get $n))
+              (then
+                (if (local.get $trap) (then unreachable))
+                (return (i32.const 0))))
+            (i32.add
+              (call $down (i32.sub (local.get $n) (i32.const 1)) (local.get $trap))
+              (i32.const 1))))
+    "#;
+
+    fn cranelift_vm(binary: &mut Vec<u8>) -> WasmInterpreter<'_> {
+        let module = WasmModule::from_wat(WAT, binary).unwrap();
+        let config = WasmInterpreterConfig::new()
+            .jit(true)
+            .jit_backend(JitBackend::Cranelift);
+        WasmInterpreter::with_config(module, &config).unwrap()
+    }
+
+    fn down(vm: &WasmInterpreter, n: i32, trap: bool) -> Result<i32, TrapKind> {
+        let args = vec![WasmValue::I32(n), WasmValue::I32(trap as i32)];
+        match vm.invoke("down", args) {
+            Ok(results) => match results[..] {
+                [WasmValue::I32(v)] => Ok(v),
+                _ => panic!("down returned {:?}", results),
+            },
+            Err(e) => Err(e.trap_kind().unwrap()),
+        }
+    }
+
+    #[test]
+    fn calls_keep_the_state_of_the_instance() {
+        let mut binary = Vec::new();
+        let vm = cranelift_vm(&mut binary);
+        for expected in 1..=3 {
+            let results = vm.invoke("inc", Vec::new()).unwrap();
+            assert!(matches!(results[..], [WasmValue::I32(v)] if v == expected));
+        }
+    }
+
+    #[test]
+    fn a_trap_leaves_the_next_call_its_whole_depth() {
+        let mut binary = Vec::new();
+        let vm = cranelift_vm(&mut binary);
+        assert_eq!(down(&vm, 300, true), Err(TrapKind::Unreachable));
+        // 700 calls deep if the frames of the trapped call were still counted
+        assert_eq!(down(&vm, 400, false), Ok(400));
+        assert_eq!(down(&vm, 1000, false), Err(TrapKind::StackExhausted));
+        assert_eq!(down(&vm, 10, false), Ok(10));
+    }
+
+    #[test]
+    fn metered_calls_run_in_the_interpreter() {
+        let mut binary = Vec::new();
+        let vm = cranelift_vm(&mut binary);
+        assert_eq!(down(&vm, 10, false), Ok(10));
+        vm.set_fuel(1_000_000);
+        assert_eq!(down(&vm, 10, false), Ok(10));
+        assert!(vm.remaining_fuel().unwrap() < 1_000_000);
+    }
+}
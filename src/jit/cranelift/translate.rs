@@ -0,0 +1,1431 @@
+//! The translation of one wasm function to Cranelift IR. Locals are
+//! Cranelift variables and operands are SSA values, a block passes its
+//! results to the block following it as block parameters, a loop passes its
+//! parameters to its header the same way.
+//!
+//! Every function, imports included, has the signature
+//! `fn(ctx, args) -> status`: it takes its arguments as raw values from the
+//! array at `args`, writes its results there and returns a status of
+//! `runtime`. A call passes a scratch stack slot of the caller as `args`,
+//! helpers get their operands in the same slot. A non-zero status is
+//! returned to the caller as is, up to the entry.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use cranelift_codegen::ir::{
+    condcodes::{FloatCC, IntCC},
+    immediates::{Ieee32, Ieee64},
+    types, AbiParam, Block, BlockCall, Endianness, FuncRef, Function, InstBuilder, JumpTableData,
+    MemFlags, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Type, Value,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::JITModule;
+use cranelift_module::{FuncId, Module};
+use wasmparser::{BlockType, ValType};
+
+use crate::{
+    jit::runtime::{trap_status, Helper, JitContext, STATUS_OK},
+    module::{
+        components::FuncDecl,
+        insts::{
+            F32Binop, F32Unop, F64Binop, F64Unop, I32Binop, I32Unop, I64Binop, I64Unop,
+            Instruction, MemArg,
+        },
+        wasm_module::WasmModule,
+    },
+    vm::TrapKind,
+};
+
+/// The signature of every function, see the module documentation.
+pub(super) fn func_signature(jit: &JITModule) -> Signature {
+    let mut sig = jit.make_signature();
+    sig.params.push(AbiParam::new(types::I64));
+    sig.params.push(AbiParam::new(types::I64));
+    sig.returns.push(AbiParam::new(types::I64));
+    sig
+}
+
+/// The signature of the helpers taking the context, see `runtime`.
+fn helper_signature(jit: &JITModule) -> Signature {
+    let mut sig = jit.make_signature();
+    sig.params.extend([AbiParam::new(types::I64); 4]);
+    sig.returns.push(AbiParam::new(types::I64));
+    sig
+}
+
+/// Translate imported function `import`, which calls the host with its
+/// arguments.
+pub(super) fn translate_import(
+    jit: &JITModule,
+    func: &mut Function,
+    func_ctx: &mut FunctionBuilderContext,
+    import: u32,
+) {
+    let mut builder = FunctionBuilder::new(func, func_ctx);
+    let helper_sig = builder.import_signature(helper_signature(jit));
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+    let (ctx, args) = (
+        builder.block_params(entry)[0],
+        builder.block_params(entry)[1],
+    );
+
+    let helper = builder.ins().load(
+        types::I64,
+        MemFlags::trusted(),
+        ctx,
+        JitContext::helper_offset(Helper::HostCall) as i32,
+    );
+    let import = builder.ins().iconst(types::I64, import as i64);
+    let unused = builder.ins().iconst(types::I64, 0);
+    let call = builder
+        .ins()
+        .call_indirect(helper_sig, helper, &[ctx, args, import, unused]);
+    let status = builder.inst_results(call)[0];
+    builder.ins().return_(&[status]);
+    builder.finalize();
+}
+
+/// The Cranelift type of a wasm value, references are `index + 1` like in
+/// the raw values.
+fn val_type(ty: &ValType) -> Type {
+    match ty {
+        ValType::I32 => types::I32,
+        ValType::I64 | ValType::Ref(_) => types::I64,
+        ValType::F32 => types::F32,
+        ValType::F64 => types::F64,
+        ValType::V128 => unreachable!("v128 is rejected by `runtime::unsupported`"),
+    }
+}
+
+/// The flags of an access to the linear memory, which is little endian and
+/// bounds checked before.
+fn heap_flags() -> MemFlags {
+    MemFlags::new()
+        .with_endianness(Endianness::Little)
+        .with_notrap()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Func,
+    Block,
+    Loop,
+    If,
+}
+
+/// A block being translated.
+struct Frame {
+    kind: FrameKind,
+    /// the height of the operand stack below the parameters of the block
+    base: usize,
+    /// where a branch to the block goes, the header of a loop and the end of
+    /// the other blocks
+    target: Block,
+    /// the number of values a branch to the block carries
+    arity: usize,
+    /// the block following the block, taking its results
+    end: Block,
+    num_results: usize,
+    /// the else branch of an `if` and the parameters it starts with, until
+    /// it is reached
+    else_branch: Option<(Block, Vec<Value>)>,
+    /// whether the block is entered at all, a block in unreachable code is
+    /// skipped
+    live: bool,
+    /// whether a branch or the end of the body leads to `end`
+    end_reached: bool,
+}
+
+enum IntBinop {
+    Cmp(IntCC),
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+    Rotl,
+    Rotr,
+}
+
+enum FloatBinop {
+    Cmp(FloatCC),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    Copysign,
+}
+
+/// The float unary operations, the same for both formats.
+enum FloatUnop {
+    Abs,
+    Neg,
+    Ceil,
+    Floor,
+    Trunc,
+    Nearest,
+    Sqrt,
+    /// to an integer of the type, signed or not, trapping or saturating if
+    /// it is out of range
+    Truncate(Type, bool, bool),
+    /// to the other float format
+    Convert,
+    Reinterpret,
+}
+
+/// What a call calls.
+enum Callee {
+    Direct(FuncRef),
+    /// the address of the code of the callee
+    Indirect(Value),
+}
+
+pub(super) struct FuncTranslator<'f, 'a> {
+    builder: FunctionBuilder<'f>,
+    jit: &'f mut JITModule,
+    module: &'f WasmModule<'a>,
+    func_ids: &'f [FuncId],
+    /// the functions called so far
+    func_refs: HashMap<u32, FuncRef>,
+    call_sig: SigRef,
+    helper_sig: SigRef,
+    ctx: Value,
+    args: Value,
+    /// the arguments and results of calls and the operands of helpers
+    scratch: StackSlot,
+    locals: Vec<Type>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    reachable: bool,
+    /// takes the results and returns them
+    ret: Block,
+    /// the block returning each trap the function checks for
+    traps: Vec<(TrapKind, Block)>,
+    /// returns the status of a failed call or helper, its parameter
+    exit: Option<Block>,
+}
+
+impl<'f, 'a> FuncTranslator<'f, 'a> {
+    pub(super) fn new(
+        jit: &'f mut JITModule,
+        func: &'f mut Function,
+        func_ctx: &'f mut FunctionBuilderContext,
+        module: &'f WasmModule<'a>,
+        func_ids: &'f [FuncId],
+        decl: &FuncDecl,
+    ) -> Self {
+        let mut builder = FunctionBuilder::new(func, func_ctx);
+        let call_sig = builder.import_signature(func_signature(jit));
+        let helper_sig = builder.import_signature(helper_signature(jit));
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let (ctx, args) = (
+            builder.block_params(entry)[0],
+            builder.block_params(entry)[1],
+        );
+
+        let scratch_size = scratch_slots(module, decl) * 8;
+        let scratch = builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            scratch_size,
+            3,
+        ));
+        let sig = decl.get_sig();
+        let locals = sig
+            .params()
+            .iter()
+            .chain(&decl.get_pure_locals())
+            .map(val_type)
+            .collect();
+        let ret = builder.create_block();
+        for ty in sig.results() {
+            builder.append_block_param(ret, val_type(ty));
+        }
+        Self {
+            builder,
+            jit,
+            module,
+            func_ids,
+            func_refs: HashMap::new(),
+            call_sig,
+            helper_sig,
+            ctx,
+            args,
+            scratch,
+            locals,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            reachable: true,
+            ret,
+            traps: Vec::new(),
+            exit: None,
+        }
+    }
+
+    pub(super) fn translate(mut self, decl: &FuncDecl) -> Result<()> {
+        self.translate_prologue(decl.get_sig().params().len());
+
+        let num_results = decl.get_sig().results().len();
+        let func_frame = Frame {
+            kind: FrameKind::Func,
+            base: 0,
+            target: self.ret,
+            arity: num_results,
+            end: self.ret,
+            num_results,
+            else_branch: None,
+            live: true,
+            end_reached: false,
+        };
+        self.frames.push(func_frame);
+        for inst in decl.get_insts() {
+            if !self.reachable
+                && !matches!(
+                    inst,
+                    Instruction::Block { .. }
+                        | Instruction::Loop { .. }
+                        | Instruction::If { .. }
+                        | Instruction::Else
+                        | Instruction::End
+                )
+            {
+                continue;
+            }
+            self.translate_inst(inst)?;
+        }
+
+        self.translate_epilogue();
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+        Ok(())
+    }
+
+    fn translate_prologue(&mut self, num_params: usize) {
+        // count the frame
+        let depth = self.load_ctx(JitContext::DEPTH);
+        let depth = self.builder.ins().iadd_imm(depth, 1);
+        let max_depth = self.load_ctx(JitContext::MAX_DEPTH);
+        let exhausted = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThan, depth, max_depth);
+        self.trap_if(exhausted, TrapKind::StackExhausted);
+        self.store_ctx(depth, JitContext::DEPTH);
+
+        for (i, ty) in self.locals.clone().into_iter().enumerate() {
+            let var = Variable::from_u32(i as u32);
+            self.builder.declare_var(var, ty);
+            let value = if i < num_params {
+                let raw = self.builder.ins().load(
+                    types::I64,
+                    MemFlags::trusted(),
+                    self.args,
+                    i as i32 * 8,
+                );
+                self.decode_raw(ty, raw)
+            } else {
+                self.zero(ty)
+            };
+            self.builder.def_var(var, value);
+        }
+    }
+
+    fn translate_epilogue(&mut self) {
+        self.builder.switch_to_block(self.ret);
+        let results = self.builder.block_params(self.ret).to_vec();
+        for (i, result) in results.into_iter().enumerate() {
+            let raw = self.encode_raw(result);
+            self.builder
+                .ins()
+                .store(MemFlags::trusted(), raw, self.args, i as i32 * 8);
+        }
+        let depth = self.load_ctx(JitContext::DEPTH);
+        let depth = self.builder.ins().iadd_imm(depth, -1);
+        self.store_ctx(depth, JitContext::DEPTH);
+        let status = self.builder.ins().iconst(types::I64, STATUS_OK as i64);
+        self.builder.ins().return_(&[status]);
+
+        for (trap, block) in std::mem::take(&mut self.traps) {
+            self.builder.switch_to_block(block);
+            let status = self
+                .builder
+                .ins()
+                .iconst(types::I64, trap_status(trap) as i64);
+            self.builder.ins().return_(&[status]);
+        }
+        if let Some(block) = self.exit {
+            self.builder.switch_to_block(block);
+            let status = self.builder.block_params(block)[0];
+            self.builder.ins().return_(&[status]);
+        }
+    }
+
+    fn load_ctx(&mut self, offset: i64) -> Value {
+        self.builder
+            .ins()
+            .load(types::I64, MemFlags::trusted(), self.ctx, offset as i32)
+    }
+
+    fn store_ctx(&mut self, value: Value, offset: i64) {
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, self.ctx, offset as i32);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    /// The `n` values on top of the operand stack, popped.
+    fn pop_n(&mut self, n: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+
+    /// The `n` values on top of the operand stack, left there.
+    fn peek_n(&self, n: usize) -> Vec<Value> {
+        self.stack[self.stack.len() - n..].to_vec()
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    /// An integer constant, an i32 one is given by its bits.
+    fn int_const(&mut self, ty: Type, value: i64) -> Value {
+        let value = if ty == types::I32 {
+            value as u32 as i64
+        } else {
+            value
+        };
+        self.builder.ins().iconst(ty, value)
+    }
+
+    fn float_const(&mut self, ty: Type, value: f64) -> Value {
+        if ty == types::F32 {
+            self.builder.ins().f32const(Ieee32::from(value as f32))
+        } else {
+            self.builder.ins().f64const(Ieee64::from(value))
+        }
+    }
+
+    fn zero(&mut self, ty: Type) -> Value {
+        if ty.is_float() {
+            self.float_const(ty, 0.0)
+        } else {
+            self.builder.ins().iconst(ty, 0)
+        }
+    }
+
+    /// A condition as an i32, 1 or 0.
+    fn bool_to_i32(&mut self, cond: Value) -> Value {
+        self.builder.ins().uextend(types::I32, cond)
+    }
+
+    /// `value` as a raw value, see `WasmValue::to_raw`.
+    fn encode_raw(&mut self, value: Value) -> Value {
+        let ty = self.builder.func.dfg.value_type(value);
+        let ins = self.builder.ins();
+        match ty {
+            types::I32 => ins.sextend(types::I64, value),
+            types::F32 => {
+                let bits = ins.bitcast(types::I32, MemFlags::new(), value);
+                self.builder.ins().uextend(types::I64, bits)
+            }
+            types::F64 => ins.bitcast(types::I64, MemFlags::new(), value),
+            _ => value,
+        }
+    }
+
+    /// The value of type `ty` of the raw value `raw`.
+    fn decode_raw(&mut self, ty: Type, raw: Value) -> Value {
+        let ins = self.builder.ins();
+        match ty {
+            types::I32 => ins.ireduce(types::I32, raw),
+            types::F32 => {
+                let bits = ins.ireduce(types::I32, raw);
+                self.builder
+                    .ins()
+                    .bitcast(types::F32, MemFlags::new(), bits)
+            }
+            types::F64 => ins.bitcast(types::F64, MemFlags::new(), raw),
+            _ => raw,
+        }
+    }
+
+    /// Continue in a new block, the one the code so far falls through to.
+    fn continue_in(&mut self, block: Block) {
+        self.builder.switch_to_block(block);
+        self.builder.seal_block(block);
+    }
+
+    fn trap_block(&mut self, trap: TrapKind) -> Block {
+        if let Some((_, block)) = self.traps.iter().find(|(t, _)| *t == trap) {
+            return *block;
+        }
+        let block = self.builder.create_block();
+        self.builder.set_cold_block(block);
+        self.traps.push((trap, block));
+        block
+    }
+
+    /// Trap with `trap` if `cond` is not 0.
+    fn trap_if(&mut self, cond: Value, trap: TrapKind) {
+        let trap = self.trap_block(trap);
+        let next = self.builder.create_block();
+        self.builder.ins().brif(cond, trap, &[], next, &[]);
+        self.continue_in(next);
+    }
+
+    /// Return `status` if it is not `STATUS_OK`.
+    fn check_status(&mut self, status: Value) {
+        let exit = match self.exit {
+            Some(block) => block,
+            None => {
+                let block = self.builder.create_block();
+                self.builder.append_block_param(block, types::I64);
+                self.builder.set_cold_block(block);
+                *self.exit.insert(block)
+            }
+        };
+        let next = self.builder.create_block();
+        self.builder.ins().brif(status, exit, &[status], next, &[]);
+        self.continue_in(next);
+    }
+
+    /// Store `values` to the scratch slot as raw values.
+    fn store_scratch(&mut self, values: &[Value]) {
+        for (i, value) in values.iter().enumerate() {
+            let raw = self.encode_raw(*value);
+            self.builder
+                .ins()
+                .stack_store(raw, self.scratch, i as i32 * 8);
+        }
+    }
+
+    /// Push the values of types `tys` from the scratch slot.
+    fn load_scratch(&mut self, tys: &[Type]) {
+        for (i, ty) in tys.iter().enumerate() {
+            let raw = self
+                .builder
+                .ins()
+                .stack_load(types::I64, self.scratch, i as i32 * 8);
+            let value = self.decode_raw(*ty, raw);
+            self.push(value);
+        }
+    }
+
+    /// Call `helper` on the `num_operands` values on top of the operand
+    /// stack, which are replaced by its results of types `results`.
+    fn call_helper(
+        &mut self,
+        helper: Helper,
+        num_operands: usize,
+        a: i64,
+        b: i64,
+        results: &[Type],
+    ) {
+        let operands = self.pop_n(num_operands);
+        self.store_scratch(&operands);
+        let slots = self.builder.ins().stack_addr(types::I64, self.scratch, 0);
+        let addr = self.load_ctx(JitContext::helper_offset(helper));
+        let a = self.builder.ins().iconst(types::I64, a);
+        let b = self.builder.ins().iconst(types::I64, b);
+        let call =
+            self.builder
+                .ins()
+                .call_indirect(self.helper_sig, addr, &[self.ctx, slots, a, b]);
+        let status = self.builder.inst_results(call)[0];
+        self.check_status(status);
+        self.load_scratch(results);
+    }
+
+    fn translate_call(&mut self, callee: Callee, params: &[ValType], results: &[ValType]) {
+        let args = self.pop_n(params.len());
+        self.store_scratch(&args);
+        let slots = self.builder.ins().stack_addr(types::I64, self.scratch, 0);
+        let call = match callee {
+            Callee::Direct(func_ref) => self.builder.ins().call(func_ref, &[self.ctx, slots]),
+            Callee::Indirect(addr) => {
+                self.builder
+                    .ins()
+                    .call_indirect(self.call_sig, addr, &[self.ctx, slots])
+            }
+        };
+        let status = self.builder.inst_results(call)[0];
+        self.check_status(status);
+        self.load_scratch(&results.iter().map(val_type).collect::<Vec<_>>());
+    }
+
+    fn func_ref(&mut self, func_index: u32) -> FuncRef {
+        if let Some(func_ref) = self.func_refs.get(&func_index) {
+            return *func_ref;
+        }
+        let func_ref = self
+            .jit
+            .declare_func_in_func(self.func_ids[func_index as usize], self.builder.func);
+        self.func_refs.insert(func_index, func_ref);
+        func_ref
+    }
+
+    fn translate_inst(&mut self, inst: &Instruction) -> Result<()> {
+        match inst {
+            Instruction::Unreachable => {
+                let trap = self.trap_block(TrapKind::Unreachable);
+                self.builder.ins().jump(trap, &[]);
+                self.reachable = false;
+            }
+            Instruction::Nop => {}
+            Instruction::Block { ty } => self.translate_block(FrameKind::Block, *ty),
+            Instruction::Loop { ty } => self.translate_block(FrameKind::Loop, *ty),
+            Instruction::If { ty } => self.translate_block(FrameKind::If, *ty),
+            Instruction::Else => self.translate_else(),
+            Instruction::End => self.translate_end(),
+            Instruction::Br { rel_depth } => {
+                let (target, args) = self.branch_target(*rel_depth);
+                self.builder.ins().jump(target, &args);
+                self.reachable = false;
+            }
+            Instruction::BrIf { rel_depth } => {
+                let cond = self.pop();
+                let (target, args) = self.branch_target(*rel_depth);
+                let next = self.builder.create_block();
+                self.builder.ins().brif(cond, target, &args, next, &[]);
+                self.continue_in(next);
+            }
+            Instruction::BrTable { table } => {
+                self.translate_br_table(&table.targets, table.default_target);
+                self.reachable = false;
+            }
+            Instruction::Return => {
+                let (target, args) = self.branch_target(self.frames.len() as u32 - 1);
+                self.builder.ins().jump(target, &args);
+                self.reachable = false;
+            }
+            Instruction::Call { func_idx } => {
+                let sig = self
+                    .module
+                    .get_func(*func_idx)
+                    .ok_or_else(|| anyhow!("function {} not found", func_idx))?
+                    .get_sig();
+                let func_ref = self.func_ref(*func_idx);
+                self.translate_call(Callee::Direct(func_ref), sig.params(), sig.results());
+            }
+            Instruction::CallIndirect {
+                type_index,
+                table_index,
+            } => {
+                let sig = self
+                    .module
+                    .get_sig(*type_index)
+                    .ok_or_else(|| anyhow!("call_indirect: unknown type {}", type_index))?;
+                // the index of the callee, then the address of its code
+                self.call_helper(
+                    Helper::IndirectCallee,
+                    1,
+                    *type_index as i64,
+                    *table_index as i64,
+                    &[types::I64],
+                );
+                let callee = self.pop();
+                let func_addrs = self.load_ctx(JitContext::FUNC_ADDRS);
+                let offset = self.builder.ins().ishl_imm(callee, 3);
+                let entry = self.builder.ins().iadd(func_addrs, offset);
+                let addr = self
+                    .builder
+                    .ins()
+                    .load(types::I64, MemFlags::trusted(), entry, 0);
+                self.translate_call(Callee::Indirect(addr), sig.params(), sig.results());
+            }
+            Instruction::Drop => {
+                self.pop();
+            }
+            Instruction::Select | Instruction::TypedSelect { .. } => {
+                let cond = self.pop();
+                let b = self.pop();
+                let a = self.pop();
+                let value = self.builder.ins().select(cond, a, b);
+                self.push(value);
+            }
+            Instruction::LocalGet { local_idx } => {
+                let value = self.builder.use_var(Variable::from_u32(*local_idx));
+                self.push(value);
+            }
+            Instruction::LocalSet { local_idx } => {
+                let value = self.pop();
+                self.builder.def_var(Variable::from_u32(*local_idx), value);
+            }
+            Instruction::LocalTee { local_idx } => {
+                let value = *self.stack.last().expect("operand stack underflow");
+                self.builder.def_var(Variable::from_u32(*local_idx), value);
+            }
+            Instruction::GlobalGet { global_idx } => {
+                let ty = self.global_type(*global_idx)?;
+                let globals = self.load_ctx(JitContext::GLOBALS);
+                let raw = self.builder.ins().load(
+                    types::I64,
+                    MemFlags::trusted(),
+                    globals,
+                    *global_idx as i32 * 8,
+                );
+                let value = self.decode_raw(ty, raw);
+                self.push(value);
+            }
+            Instruction::GlobalSet { global_idx } => {
+                let value = self.pop();
+                let raw = self.encode_raw(value);
+                let globals = self.load_ctx(JitContext::GLOBALS);
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), raw, globals, *global_idx as i32 * 8);
+            }
+            Instruction::TableGet { table } => {
+                self.call_helper(Helper::TableGet, 1, *table as i64, 0, &[types::I64]);
+            }
+            Instruction::TableSet { table } => {
+                self.call_helper(Helper::TableSet, 2, *table as i64, 0, &[]);
+            }
+            Instruction::TableInit { elem_index, table } => {
+                self.call_helper(Helper::TableInit, 3, *elem_index as i64, *table as i64, &[]);
+            }
+            Instruction::ElemDrop { elem_index } => {
+                self.call_helper(Helper::ElemDrop, 0, *elem_index as i64, 0, &[]);
+            }
+            Instruction::TableGrow { table } => {
+                self.call_helper(Helper::TableGrow, 2, *table as i64, 0, &[types::I32]);
+            }
+            Instruction::TableSize { table } => {
+                self.call_helper(Helper::TableSize, 0, *table as i64, 0, &[types::I32]);
+            }
+            Instruction::TableFill { table } => {
+                self.call_helper(Helper::TableFill, 3, *table as i64, 0, &[]);
+            }
+            Instruction::RefNull { .. } => {
+                let value = self.builder.ins().iconst(types::I64, 0);
+                self.push(value);
+            }
+            Instruction::RefIsNull => {
+                let value = self.pop();
+                let cond = self.builder.ins().icmp_imm(IntCC::Equal, value, 0);
+                let value = self.bool_to_i32(cond);
+                self.push(value);
+            }
+            Instruction::RefFunc { func_idx } => {
+                let value = self.builder.ins().iconst(types::I64, *func_idx as i64 + 1);
+                self.push(value);
+            }
+            Instruction::I32Load { memarg } => {
+                self.translate_load(memarg, 4, |ins, p| ins.load(types::I32, heap_flags(), p, 0))
+            }
+            Instruction::I64Load { memarg } => {
+                self.translate_load(memarg, 8, |ins, p| ins.load(types::I64, heap_flags(), p, 0))
+            }
+            Instruction::F32Load { memarg } => {
+                self.translate_load(memarg, 4, |ins, p| ins.load(types::F32, heap_flags(), p, 0))
+            }
+            Instruction::F64Load { memarg } => {
+                self.translate_load(memarg, 8, |ins, p| ins.load(types::F64, heap_flags(), p, 0))
+            }
+            Instruction::I32Load8S { memarg } => self.translate_load(memarg, 1, |ins, p| {
+                ins.sload8(types::I32, heap_flags(), p, 0)
+            }),
+            Instruction::I32Load8U { memarg } => self.translate_load(memarg, 1, |ins, p| {
+                ins.uload8(types::I32, heap_flags(), p, 0)
+            }),
+            Instruction::I32Load16S { memarg } => self.translate_load(memarg, 2, |ins, p| {
+                ins.sload16(types::I32, heap_flags(), p, 0)
+            }),
+            Instruction::I32Load16U { memarg } => self.translate_load(memarg, 2, |ins, p| {
+                ins.uload16(types::I32, heap_flags(), p, 0)
+            }),
+            Instruction::I64Load8S { memarg } => self.translate_load(memarg, 1, |ins, p| {
+                ins.sload8(types::I64, heap_flags(), p, 0)
+            }),
+            Instruction::I64Load8U { memarg } => self.translate_load(memarg, 1, |ins, p| {
+                ins.uload8(types::I64, heap_flags(), p, 0)
+            }),
+            Instruction::I64Load16S { memarg } => self.translate_load(memarg, 2, |ins, p| {
+                ins.sload16(types::I64, heap_flags(), p, 0)
+            }),
+            Instruction::I64Load16U { memarg } => self.translate_load(memarg, 2, |ins, p| {
+                ins.uload16(types::I64, heap_flags(), p, 0)
+            }),
+            Instruction::I64Load32S { memarg } => {
+                self.translate_load(memarg, 4, |ins, p| ins.sload32(heap_flags(), p, 0))
+            }
+            Instruction::I64Load32U { memarg } => {
+                self.translate_load(memarg, 4, |ins, p| ins.uload32(heap_flags(), p, 0))
+            }
+            Instruction::I32Store { memarg }
+            | Instruction::I64Store { memarg }
+            | Instruction::F32Store { memarg }
+            | Instruction::F64Store { memarg } => {
+                let value = *self.stack.last().expect("operand stack underflow");
+                let size = self.builder.func.dfg.value_type(value).bytes() as u64;
+                self.translate_store(memarg, size, |ins, v, p| {
+                    ins.store(heap_flags(), v, p, 0);
+                })
+            }
+            Instruction::I32Store8 { memarg } | Instruction::I64Store8 { memarg } => self
+                .translate_store(memarg, 1, |ins, v, p| {
+                    ins.istore8(heap_flags(), v, p, 0);
+                }),
+            Instruction::I32Store16 { memarg } | Instruction::I64Store16 { memarg } => self
+                .translate_store(memarg, 2, |ins, v, p| {
+                    ins.istore16(heap_flags(), v, p, 0);
+                }),
+            Instruction::I64Store32 { memarg } => self.translate_store(memarg, 4, |ins, v, p| {
+                ins.istore32(heap_flags(), v, p, 0);
+            }),
+            Instruction::MemorySize { .. } => {
+                let len = self.load_ctx(JitContext::MEM_LEN);
+                let pages = self.builder.ins().ushr_imm(len, 16);
+                let pages = self.builder.ins().ireduce(types::I32, pages);
+                self.push(pages);
+            }
+            Instruction::MemoryGrow { .. } => {
+                self.call_helper(Helper::MemoryGrow, 1, 0, 0, &[types::I32]);
+            }
+            Instruction::MemoryCopy { .. } => self.call_helper(Helper::MemoryCopy, 3, 0, 0, &[]),
+            Instruction::MemoryFill { .. } => self.call_helper(Helper::MemoryFill, 3, 0, 0, &[]),
+            Instruction::MemoryInit { data_index, .. } => {
+                self.call_helper(Helper::MemoryInit, 3, *data_index as i64, 0, &[]);
+            }
+            Instruction::DataDrop { data_index } => {
+                self.call_helper(Helper::DataDrop, 0, *data_index as i64, 0, &[]);
+            }
+            Instruction::I32Const { value } => {
+                let value = self.int_const(types::I32, *value as i64);
+                self.push(value);
+            }
+            Instruction::I64Const { value } => {
+                let value = self.int_const(types::I64, *value);
+                self.push(value);
+            }
+            Instruction::F32Const { value } => {
+                let value = self
+                    .builder
+                    .ins()
+                    .f32const(Ieee32::with_bits(value.to_bits()));
+                self.push(value);
+            }
+            Instruction::F64Const { value } => {
+                let value = self
+                    .builder
+                    .ins()
+                    .f64const(Ieee64::with_bits(value.to_bits()));
+                self.push(value);
+            }
+            Instruction::I32Unop(op) => self.translate_i32_unop(op),
+            Instruction::I32Binop(op) => self.translate_int_binop(int_binop_i32(op)),
+            Instruction::I64Unop(op) => self.translate_i64_unop(op),
+            Instruction::I64Binop(op) => self.translate_int_binop(int_binop_i64(op)),
+            Instruction::F32Unop(op) => self.translate_float_unop(f32_unop(op)),
+            Instruction::F32Binop(op) => self.translate_float_binop(float_binop_f32(op)),
+            Instruction::F64Unop(op) => self.translate_float_unop(f64_unop(op)),
+            Instruction::F64Binop(op) => self.translate_float_binop(float_binop_f64(op)),
+            _ => return Err(anyhow!("{:?} is not supported by the cranelift jit", inst)),
+        }
+        Ok(())
+    }
+
+    fn global_type(&self, global_index: u32) -> Result<Type> {
+        let global = self
+            .module
+            .get_globals()
+            .get(global_index as usize)
+            .ok_or_else(|| anyhow!("global {} not found", global_index))?;
+        Ok(val_type(&global.get_ty().content_type))
+    }
+
+    /// The types of the parameters and the results of a block.
+    fn block_types(&self, ty: BlockType) -> (Vec<Type>, Vec<Type>) {
+        match ty {
+            BlockType::Empty => (vec![], vec![]),
+            BlockType::Type(ty) => (vec![], vec![val_type(&ty)]),
+            BlockType::FuncType(type_index) => {
+                let sig = self
+                    .module
+                    .get_sig(type_index)
+                    .expect("block type not found");
+                (
+                    sig.params().iter().map(val_type).collect(),
+                    sig.results().iter().map(val_type).collect(),
+                )
+            }
+        }
+    }
+
+    fn translate_block(&mut self, kind: FrameKind, ty: BlockType) {
+        let (params, results) = self.block_types(ty);
+        let end = self.builder.create_block();
+        let mut frame = Frame {
+            kind,
+            base: 0,
+            target: end,
+            arity: results.len(),
+            end,
+            num_results: results.len(),
+            else_branch: None,
+            live: self.reachable,
+            end_reached: false,
+        };
+        if !self.reachable {
+            self.frames.push(frame);
+            return;
+        }
+        for ty in &results {
+            self.builder.append_block_param(end, *ty);
+        }
+
+        match kind {
+            FrameKind::Loop => {
+                let header = self.builder.create_block();
+                for ty in &params {
+                    self.builder.append_block_param(header, *ty);
+                }
+                let args = self.pop_n(params.len());
+                self.builder.ins().jump(header, &args);
+                // sealed at the end, after the branches back to it
+                self.builder.switch_to_block(header);
+                self.stack
+                    .extend_from_slice(self.builder.block_params(header));
+                frame.target = header;
+                frame.arity = params.len();
+            }
+            FrameKind::If => {
+                let cond = self.pop();
+                let (then_branch, else_branch) =
+                    (self.builder.create_block(), self.builder.create_block());
+                self.builder
+                    .ins()
+                    .brif(cond, then_branch, &[], else_branch, &[]);
+                self.builder.seal_block(else_branch);
+                self.continue_in(then_branch);
+                frame.else_branch = Some((else_branch, self.peek_n(params.len())));
+            }
+            _ => {}
+        }
+        frame.base = self.stack.len() - params.len();
+        self.frames.push(frame);
+    }
+
+    fn translate_else(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        if !frame.live {
+            return;
+        }
+        let (else_branch, params) = frame.else_branch.take().unwrap();
+        let (end, base, num_results) = (frame.end, frame.base, frame.num_results);
+        if self.reachable {
+            frame.end_reached = true;
+            let results = self.peek_n(num_results);
+            self.builder.ins().jump(end, &results);
+        }
+        self.stack.truncate(base);
+        self.stack.extend(params);
+        self.builder.switch_to_block(else_branch);
+        self.reachable = true;
+    }
+
+    fn translate_end(&mut self) {
+        if self.frames.len() == 1 {
+            if self.reachable {
+                let results = self.peek_n(self.frames[0].num_results);
+                self.builder.ins().jump(self.ret, &results);
+            }
+            self.frames.pop();
+            return;
+        }
+        let mut frame = self.frames.pop().unwrap();
+        if !frame.live {
+            return;
+        }
+        if self.reachable {
+            frame.end_reached = true;
+            let results = self.peek_n(frame.num_results);
+            self.builder.ins().jump(frame.end, &results);
+        }
+        // an `if` without `else` passes its parameters on
+        if let Some((else_branch, params)) = frame.else_branch {
+            frame.end_reached = true;
+            self.builder.switch_to_block(else_branch);
+            self.builder.ins().jump(frame.end, &params);
+        }
+        if frame.kind == FrameKind::Loop {
+            self.builder.seal_block(frame.target);
+        }
+
+        self.stack.truncate(frame.base);
+        self.reachable = frame.end_reached;
+        if frame.end_reached {
+            self.continue_in(frame.end);
+            self.stack
+                .extend_from_slice(self.builder.block_params(frame.end));
+        }
+    }
+
+    /// Where a branch to the block `depth` levels out goes and the values it
+    /// carries.
+    fn branch_target(&mut self, depth: u32) -> (Block, Vec<Value>) {
+        let index = self.frames.len() - 1 - depth as usize;
+        let frame = &mut self.frames[index];
+        if frame.kind != FrameKind::Loop {
+            frame.end_reached = true;
+        }
+        let (target, arity) = (frame.target, frame.arity);
+        (target, self.peek_n(arity))
+    }
+
+    fn translate_br_table(&mut self, targets: &[u32], default_target: u32) {
+        let index = self.pop();
+        // one block per target passing the values of the branch
+        let mut blocks = BTreeMap::new();
+        for depth in targets.iter().chain([&default_target]) {
+            blocks
+                .entry(*depth)
+                .or_insert_with(|| self.builder.create_block());
+        }
+
+        let pool = &mut self.builder.func.dfg.value_lists;
+        let default = BlockCall::new(blocks[&default_target], &[], pool);
+        let table = targets
+            .iter()
+            .map(|depth| BlockCall::new(blocks[depth], &[], pool))
+            .collect::<Vec<_>>();
+        let table = self
+            .builder
+            .create_jump_table(JumpTableData::new(default, &table));
+        self.builder.ins().br_table(index, table);
+
+        for (depth, block) in blocks {
+            self.continue_in(block);
+            let (target, args) = self.branch_target(depth);
+            self.builder.ins().jump(target, &args);
+        }
+    }
+
+    /// The address of an access of `size` bytes at the address on top of the
+    /// operand stack, trapping if it is out of bounds.
+    fn heap_addr(&mut self, memarg: &MemArg, size: u64) -> Value {
+        let index = self.pop();
+        let index = self.builder.ins().uextend(types::I64, index);
+        // the offset is below 2^32 and the index too, nothing overflows
+        let end = self
+            .builder
+            .ins()
+            .iadd_imm(index, (memarg.offset + size) as i64);
+        let len = self.load_ctx(JitContext::MEM_LEN);
+        let out_of_bounds = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThan, end, len);
+        self.trap_if(out_of_bounds, TrapKind::OutOfBoundsMemory);
+        let base = self.load_ctx(JitContext::MEM_BASE);
+        let addr = self.builder.ins().iadd(base, index);
+        self.builder.ins().iadd_imm(addr, memarg.offset as i64)
+    }
+
+    fn translate_load(
+        &mut self,
+        memarg: &MemArg,
+        size: u64,
+        load: impl FnOnce(cranelift_frontend::FuncInstBuilder, Value) -> Value,
+    ) {
+        let addr = self.heap_addr(memarg, size);
+        let value = load(self.builder.ins(), addr);
+        self.push(value);
+    }
+
+    fn translate_store(
+        &mut self,
+        memarg: &MemArg,
+        size: u64,
+        store: impl FnOnce(cranelift_frontend::FuncInstBuilder, Value, Value),
+    ) {
+        let value = self.pop();
+        let addr = self.heap_addr(memarg, size);
+        store(self.builder.ins(), value, addr);
+    }
+
+    fn translate_i32_unop(&mut self, op: &I32Unop) {
+        let v = self.pop();
+        let ins = self.builder.ins();
+        let value = match op {
+            I32Unop::Eqz => {
+                let cond = ins.icmp_imm(IntCC::Equal, v, 0);
+                self.bool_to_i32(cond)
+            }
+            I32Unop::Clz => ins.clz(v),
+            I32Unop::Ctz => ins.ctz(v),
+            I32Unop::Popcnt => ins.popcnt(v),
+            I32Unop::Extend8S => {
+                let low = ins.ireduce(types::I8, v);
+                self.builder.ins().sextend(types::I32, low)
+            }
+            I32Unop::Extend16S => {
+                let low = ins.ireduce(types::I16, v);
+                self.builder.ins().sextend(types::I32, low)
+            }
+            I32Unop::F64ConvertI32S => ins.fcvt_from_sint(types::F64, v),
+            I32Unop::F64ConvertI32U => ins.fcvt_from_uint(types::F64, v),
+            I32Unop::F32ConvertI32S => ins.fcvt_from_sint(types::F32, v),
+            I32Unop::F32ConvertI32U => ins.fcvt_from_uint(types::F32, v),
+            I32Unop::I64ExtendI32S => ins.sextend(types::I64, v),
+            I32Unop::I64ExtendI32U => ins.uextend(types::I64, v),
+            I32Unop::F32ReinterpretI32 => ins.bitcast(types::F32, MemFlags::new(), v),
+        };
+        self.push(value);
+    }
+
+    fn translate_i64_unop(&mut self, op: &I64Unop) {
+        let v = self.pop();
+        let ins = self.builder.ins();
+        let value = match op {
+            I64Unop::Eqz => {
+                let cond = ins.icmp_imm(IntCC::Equal, v, 0);
+                self.bool_to_i32(cond)
+            }
+            I64Unop::Clz => ins.clz(v),
+            I64Unop::Ctz => ins.ctz(v),
+            I64Unop::Popcnt => ins.popcnt(v),
+            I64Unop::Extend8S => {
+                let low = ins.ireduce(types::I8, v);
+                self.builder.ins().sextend(types::I64, low)
+            }
+            I64Unop::Extend16S => {
+                let low = ins.ireduce(types::I16, v);
+                self.builder.ins().sextend(types::I64, low)
+            }
+            I64Unop::Extend32S => {
+                let low = ins.ireduce(types::I32, v);
+                self.builder.ins().sextend(types::I64, low)
+            }
+            I64Unop::I32WrapI64 => ins.ireduce(types::I32, v),
+            I64Unop::F32ConvertI64S => ins.fcvt_from_sint(types::F32, v),
+            I64Unop::F32ConvertI64U => ins.fcvt_from_uint(types::F32, v),
+            I64Unop::F64ConvertI64S => ins.fcvt_from_sint(types::F64, v),
+            I64Unop::F64ConvertI64U => ins.fcvt_from_uint(types::F64, v),
+            I64Unop::F64ReinterpretI64 => ins.bitcast(types::F64, MemFlags::new(), v),
+        };
+        self.push(value);
+    }
+
+    fn translate_int_binop(&mut self, op: IntBinop) {
+        let b = self.pop();
+        let a = self.pop();
+        let ty = self.builder.func.dfg.value_type(a);
+        let value = match op {
+            IntBinop::Cmp(cc) => {
+                let cond = self.builder.ins().icmp(cc, a, b);
+                self.bool_to_i32(cond)
+            }
+            IntBinop::Add => self.builder.ins().iadd(a, b),
+            IntBinop::Sub => self.builder.ins().isub(a, b),
+            IntBinop::Mul => self.builder.ins().imul(a, b),
+            IntBinop::DivS => {
+                self.trap_if_zero(b);
+                // the only quotient that does not fit
+                let min = self.int_const(
+                    ty,
+                    if ty == types::I32 {
+                        i32::MIN as i64
+                    } else {
+                        i64::MIN
+                    },
+                );
+                let minus_one = self.int_const(ty, -1);
+                let a_min = self.builder.ins().icmp(IntCC::Equal, a, min);
+                let b_minus_one = self.builder.ins().icmp(IntCC::Equal, b, minus_one);
+                let overflow = self.builder.ins().band(a_min, b_minus_one);
+                self.trap_if(overflow, TrapKind::IntegerOverflow);
+                self.builder.ins().sdiv(a, b)
+            }
+            IntBinop::DivU => {
+                self.trap_if_zero(b);
+                self.builder.ins().udiv(a, b)
+            }
+            IntBinop::RemS => {
+                // Cranelift defines `MIN % -1` as 0 like wasm
+                self.trap_if_zero(b);
+                self.builder.ins().srem(a, b)
+            }
+            IntBinop::RemU => {
+                self.trap_if_zero(b);
+                self.builder.ins().urem(a, b)
+            }
+            IntBinop::And => self.builder.ins().band(a, b),
+            IntBinop::Or => self.builder.ins().bor(a, b),
+            IntBinop::Xor => self.builder.ins().bxor(a, b),
+            // the shift amounts are taken modulo the width like in wasm
+            IntBinop::Shl => self.builder.ins().ishl(a, b),
+            IntBinop::ShrS => self.builder.ins().sshr(a, b),
+            IntBinop::ShrU => self.builder.ins().ushr(a, b),
+            IntBinop::Rotl => self.builder.ins().rotl(a, b),
+            IntBinop::Rotr => self.builder.ins().rotr(a, b),
+        };
+        self.push(value);
+    }
+
+    fn trap_if_zero(&mut self, divisor: Value) {
+        let zero = self.builder.ins().icmp_imm(IntCC::Equal, divisor, 0);
+        self.trap_if(zero, TrapKind::DivisionByZero);
+    }
+
+    fn translate_float_unop(&mut self, op: FloatUnop) {
+        let v = self.pop();
+        let ty = self.builder.func.dfg.value_type(v);
+        let value = match op {
+            FloatUnop::Abs => self.builder.ins().fabs(v),
+            FloatUnop::Neg => self.builder.ins().fneg(v),
+            FloatUnop::Ceil => self.builder.ins().ceil(v),
+            FloatUnop::Floor => self.builder.ins().floor(v),
+            FloatUnop::Trunc => self.builder.ins().trunc(v),
+            FloatUnop::Nearest => self.builder.ins().nearest(v),
+            FloatUnop::Sqrt => self.builder.ins().sqrt(v),
+            FloatUnop::Truncate(int_ty, signed, saturating) => {
+                if !saturating {
+                    self.check_truncate(v, int_ty, signed);
+                }
+                if signed {
+                    self.builder.ins().fcvt_to_sint_sat(int_ty, v)
+                } else {
+                    self.builder.ins().fcvt_to_uint_sat(int_ty, v)
+                }
+            }
+            FloatUnop::Convert if ty == types::F32 => self.builder.ins().fpromote(types::F64, v),
+            FloatUnop::Convert => self.builder.ins().fdemote(types::F32, v),
+            FloatUnop::Reinterpret => {
+                let int_ty = if ty == types::F32 {
+                    types::I32
+                } else {
+                    types::I64
+                };
+                self.builder.ins().bitcast(int_ty, MemFlags::new(), v)
+            }
+        };
+        self.push(value);
+    }
+
+    /// Trap if the float `v` does not truncate to an integer of type
+    /// `int_ty`.
+    fn check_truncate(&mut self, v: Value, int_ty: Type, signed: bool) {
+        let ty = self.builder.func.dfg.value_type(v);
+        let nan = self.builder.ins().fcmp(FloatCC::Unordered, v, v);
+        self.trap_if(nan, TrapKind::InvalidConversion);
+
+        // the bounds are exclusive, the lower one is the float below the
+        // minimum of a signed type
+        let (low, high) = match (int_ty, signed) {
+            (types::I32, true) if ty == types::F32 => (-2147483904.0, 2147483648.0),
+            (types::I32, true) => (-2147483649.0, 2147483648.0),
+            (types::I32, false) => (-1.0, 4294967296.0),
+            (_, true) if ty == types::F32 => (-9223373136366403584.0, 9223372036854775808.0),
+            (_, true) => (-9223372036854777856.0, 9223372036854775808.0),
+            (_, false) => (-1.0, 18446744073709551616.0),
+        };
+        let low = self.float_const(ty, low);
+        let high = self.float_const(ty, high);
+        let below = self.builder.ins().fcmp(FloatCC::LessThanOrEqual, v, low);
+        let above = self
+            .builder
+            .ins()
+            .fcmp(FloatCC::GreaterThanOrEqual, v, high);
+        let overflow = self.builder.ins().bor(below, above);
+        self.trap_if(overflow, TrapKind::IntegerOverflow);
+    }
+
+    fn translate_float_binop(&mut self, op: FloatBinop) {
+        let b = self.pop();
+        let a = self.pop();
+        let ins = self.builder.ins();
+        let value = match op {
+            FloatBinop::Cmp(cc) => {
+                let cond = ins.fcmp(cc, a, b);
+                self.bool_to_i32(cond)
+            }
+            FloatBinop::Add => ins.fadd(a, b),
+            FloatBinop::Sub => ins.fsub(a, b),
+            FloatBinop::Mul => ins.fmul(a, b),
+            FloatBinop::Div => ins.fdiv(a, b),
+            // with the NaN and signed zero rules of wasm
+            FloatBinop::Min => ins.fmin(a, b),
+            FloatBinop::Max => ins.fmax(a, b),
+            FloatBinop::Copysign => ins.fcopysign(a, b),
+        };
+        self.push(value);
+    }
+}
+
+/// The raw values the scratch slot of `decl` holds: the operands of a
+/// helper, or the arguments or results of a call.
+fn scratch_slots(module: &WasmModule, decl: &FuncDecl) -> u32 {
+    let call_slots = |sig: Option<&wasmparser::FuncType>| {
+        sig.map_or(0, |sig| sig.params().len().max(sig.results().len()))
+    };
+    decl.get_insts()
+        .iter()
+        .map(|inst| match inst {
+            Instruction::Call { func_idx } => {
+                call_slots(module.get_func(*func_idx).map(|f| f.get_sig()))
+            }
+            Instruction::CallIndirect { type_index, .. } => call_slots(module.get_sig(*type_index)),
+            _ => 0,
+        })
+        .fold(3, usize::max) as u32
+}
+
+fn int_binop_i32(op: &I32Binop) -> IntBinop {
+    match op {
+        I32Binop::Eq => IntBinop::Cmp(IntCC::Equal),
+        I32Binop::Ne => IntBinop::Cmp(IntCC::NotEqual),
+        I32Binop::LtS => IntBinop::Cmp(IntCC::SignedLessThan),
+        I32Binop::LtU => IntBinop::Cmp(IntCC::UnsignedLessThan),
+        I32Binop::GtS => IntBinop::Cmp(IntCC::SignedGreaterThan),
+        I32Binop::GtU => IntBinop::Cmp(IntCC::UnsignedGreaterThan),
+        I32Binop::LeS => IntBinop::Cmp(IntCC::SignedLessThanOrEqual),
+        I32Binop::LeU => IntBinop::Cmp(IntCC::UnsignedLessThanOrEqual),
+        I32Binop::GeS => IntBinop::Cmp(IntCC::SignedGreaterThanOrEqual),
+        I32Binop::GeU => IntBinop::Cmp(IntCC::UnsignedGreaterThanOrEqual),
+        I32Binop::Add => IntBinop::Add,
+        I32Binop::Sub => IntBinop::Sub,
+        I32Binop::Mul => IntBinop::Mul,
+        I32Binop::DivS => IntBinop::DivS,
+        I32Binop::DivU => IntBinop::DivU,
+        I32Binop::RemS => IntBinop::RemS,
+        I32Binop::RemU => IntBinop::RemU,
+        I32Binop::And => IntBinop::And,
+        I32Binop::Or => IntBinop::Or,
+        I32Binop::Xor => IntBinop::Xor,
+        I32Binop::Shl => IntBinop::Shl,
+        I32Binop::ShrS => IntBinop::ShrS,
+        I32Binop::ShrU => IntBinop::ShrU,
+        I32Binop::Rotl => IntBinop::Rotl,
+        I32Binop::Rotr => IntBinop::Rotr,
+    }
+}
+
+fn int_binop_i64(op: &I64Binop) -> IntBinop {
+    match op {
+        I64Binop::Eq => IntBinop::Cmp(IntCC::Equal),
+        I64Binop::Ne => IntBinop::Cmp(IntCC::NotEqual),
+        I64Binop::LtS => IntBinop::Cmp(IntCC::SignedLessThan),
+        I64Binop::LtU => IntBinop::Cmp(IntCC::UnsignedLessThan),
+        I64Binop::GtS => IntBinop::Cmp(IntCC::SignedGreaterThan),
+        I64Binop::GtU => IntBinop::Cmp(IntCC::UnsignedGreaterThan),
+        I64Binop::LeS => IntBinop::Cmp(IntCC::SignedLessThanOrEqual),
+        I64Binop::LeU => IntBinop::Cmp(IntCC::UnsignedLessThanOrEqual),
+        I64Binop::GeS => IntBinop::Cmp(IntCC::SignedGreaterThanOrEqual),
+        I64Binop::GeU => IntBinop::Cmp(IntCC::UnsignedGreaterThanOrEqual),
+        I64Binop::Add => IntBinop::Add,
+        I64Binop::Sub => IntBinop::Sub,
+        I64Binop::Mul => IntBinop::Mul,
+        I64Binop::DivS => IntBinop::DivS,
+        I64Binop::DivU => IntBinop::DivU,
+        I64Binop::RemS => IntBinop::RemS,
+        I64Binop::RemU => IntBinop::RemU,
+        I64Binop::And => IntBinop::And,
+        I64Binop::Or => IntBinop::Or,
+        I64Binop::Xor => IntBinop::Xor,
+        I64Binop::Shl => IntBinop::Shl,
+        I64Binop::ShrS => IntBinop::ShrS,
+        I64Binop::ShrU => IntBinop::ShrU,
+        I64Binop::Rotl => IntBinop::Rotl,
+        I64Binop::Rotr => IntBinop::Rotr,
+    }
+}
+
+fn f32_unop(op: &F32Unop) -> FloatUnop {
+    match op {
+        F32Unop::Abs => FloatUnop::Abs,
+        F32Unop::Neg => FloatUnop::Neg,
+        F32Unop::Ceil => FloatUnop::Ceil,
+        F32Unop::Floor => FloatUnop::Floor,
+        F32Unop::Trunc => FloatUnop::Trunc,
+        F32Unop::Nearest => FloatUnop::Nearest,
+        F32Unop::Sqrt => FloatUnop::Sqrt,
+        F32Unop::I32TruncF32S => FloatUnop::Truncate(types::I32, true, false),
+        F32Unop::I32TruncF32U => FloatUnop::Truncate(types::I32, false, false),
+        F32Unop::I64TruncF32S => FloatUnop::Truncate(types::I64, true, false),
+        F32Unop::I64TruncF32U => FloatUnop::Truncate(types::I64, false, false),
+        F32Unop::F64PromoteF32 => FloatUnop::Convert,
+        F32Unop::I32ReinterpretF32 => FloatUnop::Reinterpret,
+        F32Unop::I32TruncSatF32S => FloatUnop::Truncate(types::I32, true, true),
+        F32Unop::I32TruncSatF32U => FloatUnop::Truncate(types::I32, false, true),
+        F32Unop::I64TruncSatF32S => FloatUnop::Truncate(types::I64, true, true),
+        F32Unop::I64TruncSatF32U => FloatUnop::Truncate(types::I64, false, true),
+    }
+}
+
+fn f64_unop(op: &F64Unop) -> FloatUnop {
+    match op {
+        F64Unop::Abs => FloatUnop::Abs,
+        F64Unop::Neg => FloatUnop::Neg,
+        F64Unop::Ceil => FloatUnop::Ceil,
+        F64Unop::Floor => FloatUnop::Floor,
+        F64Unop::Trunc => FloatUnop::Trunc,
+        F64Unop::Nearest => FloatUnop::Nearest,
+        F64Unop::Sqrt => FloatUnop::Sqrt,
+        F64Unop::I32TruncF64S => FloatUnop::Truncate(types::I32, true, false),
+        F64Unop::I32TruncF64U => FloatUnop::Truncate(types::I32, false, false),
+        F64Unop::I64TruncF64S => FloatUnop::Truncate(types::I64, true, false),
+        F64Unop::I64TruncF64U => FloatUnop::Truncate(types::I64, false, false),
+        F64Unop::F32DemoteF64 => FloatUnop::Convert,
+        F64Unop::I64ReinterpretF64 => FloatUnop::Reinterpret,
+        F64Unop::I32TruncSatF64S => FloatUnop::Truncate(types::I32, true, true),
+        F64Unop::I32TruncSatF64U => FloatUnop::Truncate(types::I32, false, true),
+        F64Unop::I64TruncSatF64S => FloatUnop::Truncate(types::I64, true, true),
+        F64Unop::I64TruncSatF64U => FloatUnop::Truncate(types::I64, false, true),
+    }
+}
+
+fn float_binop_f32(op: &F32Binop) -> FloatBinop {
+    match op {
+        F32Binop::Eq => FloatBinop::Cmp(FloatCC::Equal),
+        F32Binop::Ne => FloatBinop::Cmp(FloatCC::NotEqual),
+        F32Binop::Lt => FloatBinop::Cmp(FloatCC::LessThan),
+        F32Binop::Gt => FloatBinop::Cmp(FloatCC::GreaterThan),
+        F32Binop::Le => FloatBinop::Cmp(FloatCC::LessThanOrEqual),
+        F32Binop::Ge => FloatBinop::Cmp(FloatCC::GreaterThanOrEqual),
+        F32Binop::Add => FloatBinop::Add,
+        F32Binop::Sub => FloatBinop::Sub,
+        F32Binop::Mul => FloatBinop::Mul,
+        F32Binop::Div => FloatBinop::Div,
+        F32Binop::Min => FloatBinop::Min,
+        F32Binop::Max => FloatBinop::Max,
+        F32Binop::Copysign => FloatBinop::Copysign,
+    }
+}
+
+fn float_binop_f64(op: &F64Binop) -> FloatBinop {
+    match op {
+        F64Binop::Eq => FloatBinop::Cmp(FloatCC::Equal),
+        F64Binop::Ne => FloatBinop::Cmp(FloatCC::NotEqual),
+        F64Binop::Lt => FloatBinop::Cmp(FloatCC::LessThan),
+        F64Binop::Gt => FloatBinop::Cmp(FloatCC::GreaterThan),
+        F64Binop::Le => FloatBinop::Cmp(FloatCC::LessThanOrEqual),
+        F64Binop::Ge => FloatBinop::Cmp(FloatCC::GreaterThanOrEqual),
+        F64Binop::Add => FloatBinop::Add,
+        F64Binop::Sub => FloatBinop::Sub,
+        F64Binop::Mul => FloatBinop::Mul,
+        F64Binop::Div => FloatBinop::Div,
+        F64Binop::Min => FloatBinop::Min,
+        F64Binop::Max => FloatBinop::Max,
+        F64Binop::Copysign => FloatBinop::Copysign,
+    }
+}
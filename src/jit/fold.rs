@@ -0,0 +1,447 @@
+//! Constant folding, run over the instructions of a function before it is
+//! compiled. Integer operations whose operands are all constants are
+//! evaluated at compile time, and operations with a constant right operand
+//! are strength-reduced: identities like `x + 0` disappear, a multiplication
+//! by a power of two becomes a shift, unsigned division and remainder by a
+//! power of two become a shift and a mask.
+//!
+//! Constants are propagated through the locals: a local set to a constant
+//! reads as that constant until it is set again or the next control
+//! instruction, where other paths may join with another value. A constant
+//! address is folded into the offset of the memarg of a load or a store,
+//! leaving a zero address, as long as the sum fits in the offset.
+//!
+//! Folded instructions are replaced with `nop` rather than removed, so the
+//! pc of every other instruction stays the same for the labels, the trap
+//! sites and the loops entered on the stack.
+//!
+//! Only the constants right before an operation are folded, any other
+//! instruction ends the run, including the control instructions. Float
+//! operations are left alone since their NaNs may be canonicalized, so are
+//! operations that trap, like a division by zero, which must trap at run
+//! time. An address computed with `i32.add` is not folded into the offset
+//! even with a constant right operand, the add wraps while the offset does
+//! not.
+
+use std::collections::HashMap;
+
+use crate::module::insts::{I32Binop, I32Unop, I64Binop, I64Unop, Instruction, MemArg};
+
+/// The operation with a constant right operand it is reduced to.
+enum Reduced {
+    /// `x op c` is `x`
+    Identity,
+    /// `x op c` is `x op' c'`
+    Replace(Instruction, Instruction),
+}
+
+/// Fold the constants of `insts`, see the module docs.
+pub(crate) fn fold_constants(insts: &[Instruction]) -> Vec<Instruction> {
+    let mut folded = insts.to_vec();
+    // the pcs of the constants on top of the stack, the last one on top
+    let mut consts: Vec<usize> = Vec::new();
+    // the constant each local was last set to since the last control
+    // instruction
+    let mut locals: HashMap<u32, Instruction> = HashMap::new();
+
+    for (pc, inst) in insts.iter().enumerate() {
+        match inst {
+            Instruction::Nop => continue,
+            Instruction::I32Const { .. } | Instruction::I64Const { .. } => {
+                consts.push(pc);
+                continue;
+            }
+            Instruction::LocalGet { local_idx } => {
+                if let Some(value) = locals.get(local_idx) {
+                    folded[pc] = value.clone();
+                    consts.push(pc);
+                } else {
+                    consts.clear();
+                }
+                continue;
+            }
+            // the constant a tee leaves on the stack is not folded, the tee
+            // still needs its operand
+            Instruction::LocalSet { local_idx } | Instruction::LocalTee { local_idx } => {
+                match consts.last() {
+                    Some(&operand) => locals.insert(*local_idx, folded[operand].clone()),
+                    None => locals.remove(local_idx),
+                };
+                consts.clear();
+                continue;
+            }
+            _ if is_control(inst) => {
+                locals.clear();
+                consts.clear();
+                continue;
+            }
+            _ => {}
+        }
+
+        let operands = consts
+            .iter()
+            .map(|&pc| &folded[pc])
+            .rev()
+            .take(2)
+            .collect::<Vec<_>>();
+        let result = match (inst, operands.as_slice()) {
+            (Instruction::I32Unop(op), [Instruction::I32Const { value: a }, ..]) => {
+                fold_i32_unop(op, *a).map(|v| (1, v))
+            }
+            (Instruction::I64Unop(op), [Instruction::I64Const { value: a }, ..]) => {
+                fold_i64_unop(op, *a).map(|v| (1, v))
+            }
+            (
+                Instruction::I32Binop(op),
+                [Instruction::I32Const { value: b }, Instruction::I32Const { value: a }],
+            ) => fold_i32_binop(op, *a, *b).map(|v| (2, v)),
+            (
+                Instruction::I64Binop(op),
+                [Instruction::I64Const { value: b }, Instruction::I64Const { value: a }],
+            ) => fold_i64_binop(op, *a, *b).map(|v| (2, v)),
+            _ => None,
+        };
+        if let Some((noperands, value)) = result {
+            for _ in 0..noperands {
+                let operand = consts.pop().unwrap();
+                folded[operand] = Instruction::Nop;
+            }
+            folded[pc] = value;
+            consts.push(pc);
+            continue;
+        }
+
+        // the left operand is not a constant, only the right one may be
+        let reduced = match (inst, operands.first()) {
+            (Instruction::I32Binop(op), Some(Instruction::I32Const { value: b })) => {
+                reduce_i32_binop(op, *b)
+            }
+            (Instruction::I64Binop(op), Some(Instruction::I64Const { value: b })) => {
+                reduce_i64_binop(op, *b)
+            }
+            _ => None,
+        };
+        match reduced {
+            Some(Reduced::Identity) => {
+                let operand = consts.pop().unwrap();
+                folded[operand] = Instruction::Nop;
+                folded[pc] = Instruction::Nop;
+            }
+            Some(Reduced::Replace(operand_inst, op_inst)) => {
+                let operand = consts.pop().unwrap();
+                folded[operand] = operand_inst;
+                folded[pc] = op_inst;
+            }
+            None => fold_address(&mut folded, pc, &consts),
+        }
+        consts.clear();
+    }
+
+    folded
+}
+
+/// Whether another path may join or leave at `inst`.
+fn is_control(inst: &Instruction) -> bool {
+    matches!(
+        inst,
+        Instruction::Unreachable
+            | Instruction::Block { .. }
+            | Instruction::Loop { .. }
+            | Instruction::If { .. }
+            | Instruction::Else
+            | Instruction::End
+            | Instruction::Try { .. }
+            | Instruction::Catch { .. }
+            | Instruction::CatchAll
+            | Instruction::Throw { .. }
+            | Instruction::Rethrow { .. }
+            | Instruction::Delegate { .. }
+            | Instruction::Br { .. }
+            | Instruction::BrIf { .. }
+            | Instruction::BrTable { .. }
+            | Instruction::Return
+            | Instruction::ReturnCall { .. }
+            | Instruction::ReturnCallIndirect { .. }
+    )
+}
+
+/// Fold a constant address of the load or store at `pc` into its memarg.
+/// The address of a store is below its value, which must be a constant too.
+fn fold_address(folded: &mut [Instruction], pc: usize, consts: &[usize]) {
+    let mut inst = folded[pc].clone();
+    let Some((memarg, store)) = scalar_memarg_mut(&mut inst) else {
+        return;
+    };
+    let depth = if store { 2 } else { 1 };
+    let Some(&operand) = consts.len().checked_sub(depth).map(|i| &consts[i]) else {
+        return;
+    };
+    // a memory32 offset stays below 4GiB, a memory64 one must not overflow
+    let (offset, zero) = match folded[operand] {
+        Instruction::I32Const { value } => (
+            memarg
+                .offset
+                .checked_add(value as u32 as u64)
+                .filter(|&offset| offset <= u32::MAX as u64),
+            Instruction::I32Const { value: 0 },
+        ),
+        Instruction::I64Const { value } => (
+            memarg.offset.checked_add(value as u64),
+            Instruction::I64Const { value: 0 },
+        ),
+        _ => return,
+    };
+    if let Some(offset) = offset {
+        memarg.offset = offset;
+        folded[operand] = zero;
+        folded[pc] = inst;
+    }
+}
+
+/// The memarg of a scalar load or store, and whether it is a store.
+fn scalar_memarg_mut(inst: &mut Instruction) -> Option<(&mut MemArg, bool)> {
+    match inst {
+        Instruction::I32Load { memarg }
+        | Instruction::I64Load { memarg }
+        | Instruction::F32Load { memarg }
+        | Instruction::F64Load { memarg }
+        | Instruction::I32Load8S { memarg }
+        | Instruction::I32Load8U { memarg }
+        | Instruction::I32Load16S { memarg }
+        | Instruction::I32Load16U { memarg }
+        | Instruction::I64Load8S { memarg }
+        | Instruction::I64Load8U { memarg }
+        | Instruction::I64Load16S { memarg }
+        | Instruction::I64Load16U { memarg }
+        | Instruction::I64Load32S { memarg }
+        | Instruction::I64Load32U { memarg } => Some((memarg, false)),
+        Instruction::I32Store { memarg }
+        | Instruction::I64Store { memarg }
+        | Instruction::F32Store { memarg }
+        | Instruction::F64Store { memarg }
+        | Instruction::I32Store8 { memarg }
+        | Instruction::I32Store16 { memarg }
+        | Instruction::I64Store8 { memarg }
+        | Instruction::I64Store16 { memarg }
+        | Instruction::I64Store32 { memarg } => Some((memarg, true)),
+        _ => None,
+    }
+}
+
+fn fold_i32_unop(op: &I32Unop, a: i32) -> Option<Instruction> {
+    let value = match op {
+        I32Unop::Eqz => (a == 0) as i32,
+        I32Unop::Clz => a.leading_zeros() as i32,
+        I32Unop::Ctz => a.trailing_zeros() as i32,
+        I32Unop::Popcnt => a.count_ones() as i32,
+        I32Unop::Extend8S => a as i8 as i32,
+        I32Unop::Extend16S => a as i16 as i32,
+        I32Unop::I64ExtendI32S => return Some(Instruction::I64Const { value: a as i64 }),
+        I32Unop::I64ExtendI32U => {
+            return Some(Instruction::I64Const {
+                value: a as u32 as i64,
+            })
+        }
+        _ => return None,
+    };
+    Some(Instruction::I32Const { value })
+}
+
+fn fold_i64_unop(op: &I64Unop, a: i64) -> Option<Instruction> {
+    let value = match op {
+        I64Unop::Eqz => {
+            return Some(Instruction::I32Const {
+                value: (a == 0) as i32,
+            })
+        }
+        I64Unop::I32WrapI64 => return Some(Instruction::I32Const { value: a as i32 }),
+        I64Unop::Clz => a.leading_zeros() as i64,
+        I64Unop::Ctz => a.trailing_zeros() as i64,
+        I64Unop::Popcnt => a.count_ones() as i64,
+        I64Unop::Extend8S => a as i8 as i64,
+        I64Unop::Extend16S => a as i16 as i64,
+        I64Unop::Extend32S => a as i32 as i64,
+        _ => return None,
+    };
+    Some(Instruction::I64Const { value })
+}
+
+fn fold_i32_binop(op: &I32Binop, a: i32, b: i32) -> Option<Instruction> {
+    let value = match op {
+        I32Binop::Eq => (a == b) as i32,
+        I32Binop::Ne => (a != b) as i32,
+        I32Binop::LtS => (a < b) as i32,
+        I32Binop::LtU => ((a as u32) < (b as u32)) as i32,
+        I32Binop::GtS => (a > b) as i32,
+        I32Binop::GtU => ((a as u32) > (b as u32)) as i32,
+        I32Binop::LeS => (a <= b) as i32,
+        I32Binop::LeU => ((a as u32) <= (b as u32)) as i32,
+        I32Binop::GeS => (a >= b) as i32,
+        I32Binop::GeU => ((a as u32) >= (b as u32)) as i32,
+        I32Binop::Add => a.wrapping_add(b),
+        I32Binop::Sub => a.wrapping_sub(b),
+        I32Binop::Mul => a.wrapping_mul(b),
+        // division by zero and overflow are left to trap at run time
+        I32Binop::DivS => a.checked_div(b)?,
+        I32Binop::DivU => (a as u32).checked_div(b as u32)? as i32,
+        I32Binop::RemS => {
+            if b == 0 {
+                return None;
+            }
+            a.wrapping_rem(b)
+        }
+        I32Binop::RemU => (a as u32).checked_rem(b as u32)? as i32,
+        I32Binop::And => a & b,
+        I32Binop::Or => a | b,
+        I32Binop::Xor => a ^ b,
+        I32Binop::Shl => a.wrapping_shl(b as u32),
+        I32Binop::ShrS => a.wrapping_shr(b as u32),
+        I32Binop::ShrU => (a as u32).wrapping_shr(b as u32) as i32,
+        I32Binop::Rotl => a.rotate_left((b & 0x1f) as u32),
+        I32Binop::Rotr => a.rotate_right((b & 0x1f) as u32),
+    };
+    Some(Instruction::I32Const { value })
+}
+
+fn fold_i64_binop(op: &I64Binop, a: i64, b: i64) -> Option<Instruction> {
+    let value = match op {
+        I64Binop::Eq => {
+            return Some(Instruction::I32Const {
+                value: (a == b) as i32,
+            })
+        }
+        I64Binop::Ne => {
+            return Some(Instruction::I32Const {
+                value: (a != b) as i32,
+            })
+        }
+        I64Binop::LtS => {
+            return Some(Instruction::I32Const {
+                value: (a < b) as i32,
+            })
+        }
+        I64Binop::LtU => {
+            return Some(Instruction::I32Const {
+                value: ((a as u64) < (b as u64)) as i32,
+            })
+        }
+        I64Binop::GtS => {
+            return Some(Instruction::I32Const {
+                value: (a > b) as i32,
+            })
+        }
+        I64Binop::GtU => {
+            return Some(Instruction::I32Const {
+                value: ((a as u64) > (b as u64)) as i32,
+            })
+        }
+        I64Binop::LeS => {
+            return Some(Instruction::I32Const {
+                value: (a <= b) as i32,
+            })
+        }
+        I64Binop::LeU => {
+            return Some(Instruction::I32Const {
+                value: ((a as u64) <= (b as u64)) as i32,
+            })
+        }
+        I64Binop::GeS => {
+            return Some(Instruction::I32Const {
+                value: (a >= b) as i32,
+            })
+        }
+        I64Binop::GeU => {
+            return Some(Instruction::I32Const {
+                value: ((a as u64) >= (b as u64)) as i32,
+            })
+        }
+        I64Binop::Add => a.wrapping_add(b),
+        I64Binop::Sub => a.wrapping_sub(b),
+        I64Binop::Mul => a.wrapping_mul(b),
+        // division by zero and overflow are left to trap at run time
+        I64Binop::DivS => a.checked_div(b)?,
+        I64Binop::DivU => (a as u64).checked_div(b as u64)? as i64,
+        I64Binop::RemS => {
+            if b == 0 {
+                return None;
+            }
+            a.wrapping_rem(b)
+        }
+        I64Binop::RemU => (a as u64).checked_rem(b as u64)? as i64,
+        I64Binop::And => a & b,
+        I64Binop::Or => a | b,
+        I64Binop::Xor => a ^ b,
+        I64Binop::Shl => a.wrapping_shl(b as u32),
+        I64Binop::ShrS => a.wrapping_shr(b as u32),
+        I64Binop::ShrU => (a as u64).wrapping_shr(b as u32) as i64,
+        I64Binop::Rotl => a.rotate_left((b & 0x3f) as u32),
+        I64Binop::Rotr => a.rotate_right((b & 0x3f) as u32),
+    };
+    Some(Instruction::I64Const { value })
+}
+
+/// An i32 is kept in a 64 bits register whose upper half some operations
+/// clear, only the ones that leave the whole register as is are dropped.
+fn reduce_i32_binop(op: &I32Binop, b: i32) -> Option<Reduced> {
+    match op {
+        I32Binop::Add | I32Binop::Sub | I32Binop::Or | I32Binop::Xor if b == 0 => {
+            Some(Reduced::Identity)
+        }
+        I32Binop::Shl | I32Binop::ShrS if b & 0x1f == 0 => Some(Reduced::Identity),
+        I32Binop::Mul if b == 1 => Some(Reduced::Identity),
+        I32Binop::And if b == -1 => Some(Reduced::Identity),
+        I32Binop::Mul if (b as u32).is_power_of_two() => Some(Reduced::Replace(
+            Instruction::I32Const {
+                value: b.trailing_zeros() as i32,
+            },
+            Instruction::I32Binop(I32Binop::Shl),
+        )),
+        I32Binop::DivU if (b as u32).is_power_of_two() => Some(Reduced::Replace(
+            Instruction::I32Const {
+                value: b.trailing_zeros() as i32,
+            },
+            Instruction::I32Binop(I32Binop::ShrU),
+        )),
+        I32Binop::RemU if (b as u32).is_power_of_two() => Some(Reduced::Replace(
+            Instruction::I32Const {
+                value: (b as u32 - 1) as i32,
+            },
+            Instruction::I32Binop(I32Binop::And),
+        )),
+        _ => None,
+    }
+}
+
+fn reduce_i64_binop(op: &I64Binop, b: i64) -> Option<Reduced> {
+    match op {
+        I64Binop::Add | I64Binop::Sub | I64Binop::Or | I64Binop::Xor if b == 0 => {
+            Some(Reduced::Identity)
+        }
+        I64Binop::Shl | I64Binop::ShrS | I64Binop::ShrU | I64Binop::Rotl | I64Binop::Rotr
+            if b & 0x3f == 0 =>
+        {
+            Some(Reduced::Identity)
+        }
+        I64Binop::Mul | I64Binop::DivS | I64Binop::DivU if b == 1 => Some(Reduced::Identity),
+        I64Binop::And if b == -1 => Some(Reduced::Identity),
+        I64Binop::Mul if (b as u64).is_power_of_two() => Some(Reduced::Replace(
+            Instruction::I64Const {
+                value: b.trailing_zeros() as i64,
+            },
+            Instruction::I64Binop(I64Binop::Shl),
+        )),
+        I64Binop::DivU if (b as u64).is_power_of_two() => Some(Reduced::Replace(
+            Instruction::I64Const {
+                value: b.trailing_zeros() as i64,
+            },
+            Instruction::I64Binop(I64Binop::ShrU),
+        )),
+        I64Binop::RemU if (b as u64).is_power_of_two() => Some(Reduced::Replace(
+            Instruction::I64Const {
+                value: (b as u64 - 1) as i64,
+            },
+            Instruction::I64Binop(I64Binop::And),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,264 @@
+//! The GDB JIT interface. Debuggers put a breakpoint on
+//! `__jit_debug_register_code` and read the in-memory object files linked
+//! from `__jit_debug_descriptor` whenever it is hit. Each compilation is
+//! described by a minimal ELF object holding one symbol per wasm function, so
+//! gdb and lldb show `wasm_function_3` in backtraces instead of `?? ()`.
+
+use std::{ops::Range, ptr, sync::Mutex};
+
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+pub struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// The debugger reads the entry list when this function is called, it must
+/// not be inlined or optimized away.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn __jit_debug_register_code() {
+    std::hint::black_box(());
+}
+
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JIT_NOACTION,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// Serializes updates of the entry list, the debugger only reads it while
+/// the process is stopped in `__jit_debug_register_code`.
+static DESCRIPTOR_LOCK: Mutex<()> = Mutex::new(());
+
+/// An object file registered with the debugger until it is dropped.
+pub(crate) struct GdbJitRegistration {
+    entry: Box<JitCodeEntry>,
+    _image: Vec<u8>,
+}
+
+impl GdbJitRegistration {
+    /// Register symbols for the code of each function, given as its symbol
+    /// name and address range.
+    pub(crate) fn register(funcs: &[(String, Range<u64>)]) -> Self {
+        let image = build_elf(funcs);
+        let mut entry = Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: image.as_ptr(),
+            symfile_size: image.len() as u64,
+        });
+
+        let _guard = DESCRIPTOR_LOCK.lock().unwrap();
+        unsafe {
+            let descriptor = ptr::addr_of_mut!(__jit_debug_descriptor);
+            let entry_ptr: *mut JitCodeEntry = &mut *entry;
+            entry.next_entry = (*descriptor).first_entry;
+            if !entry.next_entry.is_null() {
+                (*entry.next_entry).prev_entry = entry_ptr;
+            }
+            (*descriptor).first_entry = entry_ptr;
+            (*descriptor).relevant_entry = entry_ptr;
+            (*descriptor).action_flag = JIT_REGISTER_FN;
+            __jit_debug_register_code();
+        }
+
+        Self {
+            entry,
+            _image: image,
+        }
+    }
+}
+
+impl Drop for GdbJitRegistration {
+    fn drop(&mut self) {
+        let _guard = DESCRIPTOR_LOCK.lock().unwrap();
+        unsafe {
+            let descriptor = ptr::addr_of_mut!(__jit_debug_descriptor);
+            let entry_ptr: *mut JitCodeEntry = &mut *self.entry;
+            if self.entry.prev_entry.is_null() {
+                (*descriptor).first_entry = self.entry.next_entry;
+            } else {
+                (*self.entry.prev_entry).next_entry = self.entry.next_entry;
+            }
+            if !self.entry.next_entry.is_null() {
+                (*self.entry.next_entry).prev_entry = self.entry.prev_entry;
+            }
+            (*descriptor).relevant_entry = entry_ptr;
+            (*descriptor).action_flag = JIT_UNREGISTER_FN;
+            __jit_debug_register_code();
+        }
+    }
+}
+
+// ELF constants
+const ELF_HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+const ET_REL: u16 = 1;
+const EM_X86_64: u16 = 62;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const STB_GLOBAL: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+// section indices, 0 is the null section
+const TEXT_SECTION: u16 = 1;
+const STRTAB_SECTION: u32 = 3;
+const SHSTRTAB_SECTION: u16 = 4;
+const NUM_SECTIONS: u16 = 5;
+
+/// A relocatable object whose `.text` section is placed at the jit code, it
+/// holds no code itself, only the symbols pointing into it.
+fn build_elf(funcs: &[(String, Range<u64>)]) -> Vec<u8> {
+    let text_start = funcs.iter().map(|(_, r)| r.start).min().unwrap_or(0);
+    let text_end = funcs.iter().map(|(_, r)| r.end).max().unwrap_or(0);
+
+    let shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+    let (text_name, symtab_name, strtab_name, shstrtab_name) = (1, 7, 15, 23);
+
+    let mut strtab = vec![0u8];
+    let mut symtab = vec![0u8; SYMBOL_SIZE];
+    for (symbol, range) in funcs {
+        let name = strtab.len() as u32;
+        strtab.extend_from_slice(symbol.as_bytes());
+        strtab.push(0);
+
+        symtab.extend_from_slice(&name.to_le_bytes());
+        symtab.push((STB_GLOBAL << 4) | STT_FUNC);
+        symtab.push(0);
+        symtab.extend_from_slice(&TEXT_SECTION.to_le_bytes());
+        symtab.extend_from_slice(&(range.start - text_start).to_le_bytes());
+        symtab.extend_from_slice(&(range.end - range.start).to_le_bytes());
+    }
+
+    // header, then the section contents, then the section headers
+    let mut elf = vec![0u8; ELF_HEADER_SIZE];
+    let symtab_offset = append_aligned(&mut elf, &symtab, 8);
+    let strtab_offset = append_aligned(&mut elf, &strtab, 1);
+    let shstrtab_offset = append_aligned(&mut elf, shstrtab, 1);
+    let shoff = append_aligned(&mut elf, &[], 8);
+
+    elf.extend_from_slice(&[0u8; SECTION_HEADER_SIZE]);
+    let text_header = SectionHeader {
+        name: text_name,
+        ty: SHT_NOBITS,
+        flags: SHF_ALLOC | SHF_EXECINSTR,
+        addr: text_start,
+        offset: 0,
+        size: text_end - text_start,
+        link: 0,
+        info: 0,
+        align: 16,
+        entsize: 0,
+    };
+    let symtab_header = SectionHeader {
+        name: symtab_name,
+        ty: SHT_SYMTAB,
+        flags: 0,
+        addr: 0,
+        offset: symtab_offset as u64,
+        size: symtab.len() as u64,
+        link: STRTAB_SECTION,
+        // the index of the first global symbol
+        info: 1,
+        align: 8,
+        entsize: SYMBOL_SIZE as u64,
+    };
+    let strtab_header = SectionHeader {
+        name: strtab_name,
+        ty: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        offset: strtab_offset as u64,
+        size: strtab.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    };
+    let shstrtab_header = SectionHeader {
+        name: shstrtab_name,
+        ty: SHT_STRTAB,
+        flags: 0,
+        addr: 0,
+        offset: shstrtab_offset as u64,
+        size: shstrtab.len() as u64,
+        link: 0,
+        info: 0,
+        align: 1,
+        entsize: 0,
+    };
+    for header in [text_header, symtab_header, strtab_header, shstrtab_header] {
+        header.write(&mut elf);
+    }
+
+    // e_ident: magic, 64-bit, little endian, version 1, System V ABI
+    elf[..16].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    elf[16..18].copy_from_slice(&ET_REL.to_le_bytes());
+    elf[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+    elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+    // no entry point and no program headers
+    elf[40..48].copy_from_slice(&(shoff as u64).to_le_bytes());
+    elf[52..54].copy_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    elf[58..60].copy_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+    elf[60..62].copy_from_slice(&NUM_SECTIONS.to_le_bytes());
+    elf[62..64].copy_from_slice(&SHSTRTAB_SECTION.to_le_bytes());
+
+    elf
+}
+
+/// Pad `elf` to `align` and append `data`, returns the offset of `data`.
+fn append_aligned(elf: &mut Vec<u8>, data: &[u8], align: usize) -> usize {
+    elf.resize(elf.len().next_multiple_of(align), 0);
+    let offset = elf.len();
+    elf.extend_from_slice(data);
+    offset
+}
+
+struct SectionHeader {
+    name: u32,
+    ty: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, elf: &mut Vec<u8>) {
+        elf.extend_from_slice(&self.name.to_le_bytes());
+        elf.extend_from_slice(&self.ty.to_le_bytes());
+        elf.extend_from_slice(&self.flags.to_le_bytes());
+        elf.extend_from_slice(&self.addr.to_le_bytes());
+        elf.extend_from_slice(&self.offset.to_le_bytes());
+        elf.extend_from_slice(&self.size.to_le_bytes());
+        elf.extend_from_slice(&self.link.to_le_bytes());
+        elf.extend_from_slice(&self.info.to_le_bytes());
+        elf.extend_from_slice(&self.align.to_le_bytes());
+        elf.extend_from_slice(&self.entsize.to_le_bytes());
+    }
+}
@@ -3,12 +3,19 @@ use crate::{
         regalloc::{
             RegWithType, Register, X86Register, REG_TEMP, REG_TEMP2, REG_TEMP_FP, REG_TEMP_FP2,
         },
+        setup::trap::TrapSite,
         utils::emit_mov_reg_to_reg,
         ValueType, X86JitCompiler,
     },
-    module::insts::{F64Binop, F64Unop, I32Binop, I32Unop},
+    module::{
+        insts::{F32Binop, F32Unop, F64Binop, F64Unop, I32Binop, I32Unop, I64Binop, I64Unop},
+        value_type::CANONICAL_NAN_F64,
+    },
+    vm::TrapKind,
 };
 
+use super::helpers;
+
 use monoasm::*;
 use monoasm_macro::monoasm;
 
@@ -62,107 +69,159 @@ impl X86JitCompiler<'_> {
                     sqrtsd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP.as_index());
                 );
             }
-            F64Unop::I32TruncF64S => {
-                monoasm!(
-                    &mut self.jit,
-                    roundpd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP.as_index()), (0x03); // trunc
-                );
-
-                // bound check
-                let trap_label = self.trap_label;
-                self.emit_mov_rawvalue_to_reg(
-                    (i32::MIN as f64).to_bits(),
-                    Register::FpReg(REG_TEMP_FP2),
-                );
-                monoasm!(
-                    &mut self.jit,
-                    ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
-                    jb trap_label;
-                );
-                self.emit_mov_rawvalue_to_reg(
-                    (i32::MAX as f64).to_bits(),
-                    Register::FpReg(REG_TEMP_FP2),
-                );
-                monoasm!(
-                    &mut self.jit,
-                    ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
-                    ja trap_label;
-                );
-
-                // convert to i32
-                monoasm!(
-                    &mut self.jit,
-                    cvttsd2siq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
-                );
+            F64Unop::I32TruncF64S
+            | F64Unop::I32TruncF64U
+            | F64Unop::I64TruncF64S
+            | F64Unop::I64TruncF64U => {
+                let (ty, signed) = match unop {
+                    F64Unop::I32TruncF64S => (ValueType::I32, true),
+                    F64Unop::I32TruncF64U => (ValueType::I32, false),
+                    F64Unop::I64TruncF64S => (ValueType::I64, true),
+                    _ => (ValueType::I64, false),
+                };
+                self.emit_trunc_to_int(ty, signed);
                 emit_mov_reg_to_reg(&mut self.jit, a, Register::Reg(REG_TEMP));
-                self.reg_allocator.push(RegWithType::new(a, ValueType::I32));
+                self.reg_allocator.push(RegWithType::new(a, ty));
                 return;
             }
-            F64Unop::I32TruncF64U => {
+            F64Unop::F32DemoteF64 => {
                 monoasm!(
                     &mut self.jit,
-                    roundpd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP.as_index()), (0x03); // trunc
-                );
-
-                // bound check
-                let trap_label = self.trap_label;
-                self.emit_mov_rawvalue_to_reg((0 as f64).to_bits(), Register::FpReg(REG_TEMP_FP2));
-                monoasm!(
-                    &mut self.jit,
-                    ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
-                    jb trap_label;
-                );
-                self.emit_mov_rawvalue_to_reg(
-                    (u32::MAX as f64).to_bits(),
-                    Register::FpReg(REG_TEMP_FP2),
-                );
-                monoasm!(
-                    &mut self.jit,
-                    ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
-                    ja trap_label;
-                );
-
-                // convert to u32, we check if it is larger than i32::MAX first.....
-                // if so, we subtract i32.MAX from it and add 2^31 to the result
-                let beq_i32_max = self.jit.label();
-                let end = self.jit.label();
-                self.emit_mov_rawvalue_to_reg(
-                    (i32::MAX as f64).to_bits(),
-                    Register::FpReg(REG_TEMP_FP2),
-                );
-
-                monoasm!(
-                    &mut self.jit,
-                    ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
-                    jbe beq_i32_max;
-                );
-                self.emit_mov_rawvalue_to_reg(
-                    ((1u64 << 31) as f64).to_bits(),
-                    Register::FpReg(REG_TEMP_FP2),
+                    movq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
                 );
+                self.emit_helper_call(helpers::f32_demote_f64 as *const () as usize as u64);
+                self.emit_canonicalize_f32();
+                emit_mov_reg_to_reg(&mut self.jit, a, Register::Reg(REG_TEMP));
+                self.reg_allocator.push(RegWithType::new(a, ValueType::F32));
+                return;
+            }
+            F64Unop::I32TruncSatF64S
+            | F64Unop::I32TruncSatF64U
+            | F64Unop::I64TruncSatF64S
+            | F64Unop::I64TruncSatF64U => {
+                let (helper, ty) = match unop {
+                    F64Unop::I32TruncSatF64S => (
+                        helpers::i32_trunc_sat_f64_s as *const () as usize,
+                        ValueType::I32,
+                    ),
+                    F64Unop::I32TruncSatF64U => (
+                        helpers::i32_trunc_sat_f64_u as *const () as usize,
+                        ValueType::I32,
+                    ),
+                    F64Unop::I64TruncSatF64S => (
+                        helpers::i64_trunc_sat_f64_s as *const () as usize,
+                        ValueType::I64,
+                    ),
+                    _ => (
+                        helpers::i64_trunc_sat_f64_u as *const () as usize,
+                        ValueType::I64,
+                    ),
+                };
                 monoasm!(
                     &mut self.jit,
-                    subsd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
-                    cvttsd2siq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
-                    movq R(REG_TEMP2.as_index()), (1u64 << 31);
-                    addq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
-                    jmp end;
-
-                beq_i32_max:
-                    cvttsd2siq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
-                end:
+                    movq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
                 );
-
+                self.emit_helper_call(helper as u64);
                 emit_mov_reg_to_reg(&mut self.jit, a, Register::Reg(REG_TEMP));
-                self.reg_allocator.push(RegWithType::new(a, ValueType::I32));
+                self.reg_allocator.push(RegWithType::new(a, ty));
+                return;
+            }
+            // the bits stay where they are
+            F64Unop::I64ReinterpretF64 => {
+                self.reg_allocator.push(RegWithType::new(a, ValueType::I64));
                 return;
             }
         }
 
+        if !matches!(unop, F64Unop::Abs | F64Unop::Neg) {
+            self.emit_canonicalize_f64();
+        }
         emit_mov_reg_to_reg(&mut self.jit, a, Register::FpReg(REG_TEMP_FP));
         self.reg_allocator.push(RegWithType::new(a, ValueType::F64));
     }
 
+    /// Replace a NaN in REG_TEMP_FP by the canonical NaN if NaNs are
+    /// canonicalized. Comparing a NaN with itself is unordered, which sets
+    /// CF, so ordered values skip the replacement.
+    fn emit_canonicalize_f64(&mut self) {
+        if !self.canonicalize_nans {
+            return;
+        }
+        let not_nan = self.jit.label();
+        monoasm!(
+            &mut self.jit,
+            ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP.as_index());
+            jae not_nan;
+            movq R(REG_TEMP.as_index()), (CANONICAL_NAN_F64);
+            movq xmm(REG_TEMP_FP.as_index()), R(REG_TEMP.as_index());
+        not_nan:
+        );
+    }
+
+    /// Replace a NaN f32 in REG_TEMP by the canonical NaN if NaNs are
+    /// canonicalized.
+    fn emit_canonicalize_f32(&mut self) {
+        if self.canonicalize_nans {
+            self.emit_helper_call(helpers::f32_canonicalize_nan as *const () as usize as u64);
+        }
+    }
+
+    /// Truncate the f64 in REG_TEMP_FP to an integer of type `ty` in REG_TEMP,
+    /// trapping if it is NaN or out of range.
+    fn emit_trunc_to_int(&mut self, ty: ValueType, signed: bool) {
+        monoasm!(
+            &mut self.jit,
+            roundpd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP.as_index()), (0x03); // trunc
+        );
+
+        // ucomisd sets CF on NaN, only a NaN compares below itself. The upper
+        // bounds are the largest doubles that still fit
+        let (min, max) = match (ty, signed) {
+            (ValueType::I32, true) => (i32::MIN as f64, i32::MAX as f64),
+            (ValueType::I32, false) => (0.0, u32::MAX as f64),
+            (ValueType::I64, true) => (i64::MIN as f64, 9223372036854774784.0),
+            (ValueType::I64, false) => (0.0, 18446744073709549568.0),
+            _ => unreachable!("truncation to a non-integer type"),
+        };
+
+        let nan_label = self.trap_exit(TrapKind::InvalidConversion);
+        monoasm!(
+            &mut self.jit,
+            ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP.as_index());
+            jb nan_label;
+        );
+
+        // bound check
+        let trap_label = self.trap_exit(TrapKind::IntegerOverflow);
+        self.emit_mov_rawvalue_to_reg(min.to_bits(), Register::FpReg(REG_TEMP_FP2));
+        monoasm!(
+            &mut self.jit,
+            ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
+            jb trap_label;
+        );
+        self.emit_mov_rawvalue_to_reg(max.to_bits(), Register::FpReg(REG_TEMP_FP2));
+        monoasm!(
+            &mut self.jit,
+            ucomisd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
+            ja trap_label;
+        );
+
+        if ty == ValueType::I64 && !signed {
+            // cvttsd2si only handles signed 64-bit integers
+            monoasm!(
+                &mut self.jit,
+                movq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
+            );
+            self.emit_helper_call(helpers::f64_trunc_to_u64 as *const () as usize as u64);
+        } else {
+            // an u32 always fits in the signed 64-bit conversion
+            monoasm!(
+                &mut self.jit,
+                cvttsd2siq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
+            );
+        }
+    }
+
     // jit compile *a = a op b*
     pub(crate) fn emit_f64_binop(&mut self, binop: &F64Binop) {
         let b = self.reg_allocator.pop_noopt().reg;
@@ -286,8 +345,25 @@ impl X86JitCompiler<'_> {
                     maxsd xmm(REG_TEMP_FP.as_index()), xmm(REG_TEMP_FP2.as_index());
                 );
             }
+            F64Binop::Copysign => {
+                // flip the sign bit of a if it differs from the sign bit of b
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
+                    movq R(REG_TEMP2.as_index()), xmm(REG_TEMP_FP2.as_index());
+                    xorq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index()); // a ^ b
+                    movq R(REG_TEMP.as_index()), (0x8000000000000000u64);
+                    andq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index()); // (a ^ b) & sign
+                    movq R(REG_TEMP.as_index()), xmm(REG_TEMP_FP.as_index());
+                    xorq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq xmm(REG_TEMP_FP.as_index()), R(REG_TEMP.as_index());
+                );
+            }
         }
 
+        if !matches!(binop, F64Binop::Copysign) {
+            self.emit_canonicalize_f64();
+        }
         emit_mov_reg_to_reg(&mut self.jit, a, Register::FpReg(REG_TEMP_FP));
         self.reg_allocator.push(RegWithType::new(a, ValueType::F64));
     }
@@ -365,6 +441,41 @@ impl X86JitCompiler<'_> {
                     .push(RegWithType::new(a.reg, ValueType::F64));
                 return;
             }
+            I32Unop::F32ConvertI32S | I32Unop::F32ConvertI32U | I32Unop::F32ReinterpretI32 => {
+                match unop {
+                    I32Unop::F32ConvertI32S => self
+                        .emit_helper_call(helpers::f32_convert_i32_s as *const () as usize as u64),
+                    I32Unop::F32ConvertI32U => self
+                        .emit_helper_call(helpers::f32_convert_i32_u as *const () as usize as u64),
+                    _ => {
+                        monoasm!(
+                            &mut self.jit,
+                            movl R(REG_TEMP.as_index()), R(REG_TEMP.as_index()); // clear upper bits
+                        );
+                    }
+                }
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::F32));
+                return;
+            }
+            I32Unop::I64ExtendI32S | I32Unop::I64ExtendI32U => {
+                if *unop == I32Unop::I64ExtendI32S {
+                    monoasm!(
+                        &mut self.jit,
+                        movsxl R(REG_TEMP.as_index()), R(REG_TEMP.as_index()); // sign extend
+                    );
+                } else {
+                    monoasm!(
+                        &mut self.jit,
+                        movl R(REG_TEMP.as_index()), R(REG_TEMP.as_index()); // zero extend
+                    );
+                }
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::I64));
+                return;
+            }
         }
 
         emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
@@ -478,18 +589,24 @@ impl X86JitCompiler<'_> {
                 );
             }
             I32Binop::DivS | I32Binop::RemS => {
-                let trap_label = self.trap_label;
+                let trap_label = self.trap_exit(TrapKind::DivisionByZero);
                 let no_overflow = self.jit.label();
                 let ret_zero = self.jit.label();
                 let end = self.jit.label();
                 let overflow = match binop {
-                    I32Binop::DivS => trap_label,
+                    I32Binop::DivS => self.trap_exit(TrapKind::IntegerOverflow),
                     I32Binop::RemS => ret_zero,
                     _ => unreachable!(),
                 };
+                let div_site = self.jit.label();
 
                 monoasm!(
                     &mut self.jit,
+                    // the upper halves may be set, the checks compare the
+                    // whole registers
+                    movsxl R(REG_TEMP.as_index()), R(REG_TEMP.as_index());
+                    movsxl R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index());
+
                     // Division by zero check
                     testq R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index()); // Check if divisor is zero
                     jz trap_label;
@@ -515,10 +632,12 @@ impl X86JitCompiler<'_> {
                     xorl rdx, rdx;                    // Clear RDX for 32-bit division
                     movl rax, R(REG_TEMP.as_index()); // Move 32-bit dividend into EAX
                     cdq;                              // Sign-extend EAX into EDX:EAX for division
+                div_site:
                     idivl R(REG_TEMP2.as_index());    // Signed division (EAX = quotient, EDX = remainder)
 
                     // Move the result (quotient for DivS, remainder for RemS) to REG_TEMP
                 );
+                self.trap_sites.push((div_site, TrapSite::Division));
 
                 let src = match binop {
                     I32Binop::DivS => Register::Reg(X86Register::Rax),
@@ -547,10 +666,15 @@ impl X86JitCompiler<'_> {
             }
 
             I32Binop::DivU | I32Binop::RemU => {
-                let trap_label = self.trap_label;
+                let trap_label = self.trap_exit(TrapKind::DivisionByZero);
                 let ok_label = self.jit.label();
+                let div_site = self.jit.label();
                 monoasm!(
                     &mut self.jit,
+                    // the upper half may be set, the check tests the whole
+                    // register
+                    movl R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index());
+
                     // Div by zero check
                     testq R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index()); // Check if divisor is zero
                     jz trap_label;
@@ -567,8 +691,10 @@ impl X86JitCompiler<'_> {
                     movl rax, R(REG_TEMP.as_index());
 
                     // Perform the unsigned 32-bit division
+                div_site:
                     divl R(REG_TEMP2.as_index()); // EAX: quotient, EDX: remainder
                 );
+                self.trap_sites.push((div_site, TrapSite::Division));
 
                 let src = if matches!(binop, I32Binop::DivU) {
                     Register::Reg(X86Register::Rax)
@@ -662,4 +788,464 @@ impl X86JitCompiler<'_> {
         emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
         self.reg_allocator.push(a);
     }
+
+    pub(crate) fn emit_i64_unop(&mut self, unop: &I64Unop) {
+        let a = self.reg_allocator.pop_noopt();
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), a.reg);
+
+        match unop {
+            I64Unop::Eqz => {
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    cmpq R(REG_TEMP2.as_index()), (0);
+                    seteq R(REG_TEMP.as_index());
+                );
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::I32));
+                return;
+            }
+            I64Unop::Clz => self.emit_helper_call(helpers::i64_clz as *const () as usize as u64),
+            I64Unop::Ctz => self.emit_helper_call(helpers::i64_ctz as *const () as usize as u64),
+            I64Unop::Popcnt => {
+                self.emit_helper_call(helpers::i64_popcnt as *const () as usize as u64)
+            }
+            I64Unop::Extend8S => {
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP2.as_index()), (0);
+                    movb R(REG_TEMP2.as_index()), R(REG_TEMP.as_index()); // contains lower 8 now
+                    movsxb R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // sign extend
+                );
+            }
+            I64Unop::Extend16S => {
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP2.as_index()), (0);
+                    movw R(REG_TEMP2.as_index()), R(REG_TEMP.as_index()); // contains lower 16 now
+                    movsxw R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // sign extend
+                );
+            }
+            I64Unop::Extend32S => {
+                monoasm!(
+                    &mut self.jit,
+                    movsxl R(REG_TEMP.as_index()), R(REG_TEMP.as_index()); // sign extend
+                );
+            }
+            I64Unop::I32WrapI64 => {
+                monoasm!(
+                    &mut self.jit,
+                    movl R(REG_TEMP.as_index()), R(REG_TEMP.as_index()); // keep the lower 32 bits
+                );
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::I32));
+                return;
+            }
+            I64Unop::F32ConvertI64S | I64Unop::F32ConvertI64U => {
+                if *unop == I64Unop::F32ConvertI64S {
+                    self.emit_helper_call(helpers::f32_convert_i64_s as *const () as usize as u64);
+                } else {
+                    self.emit_helper_call(helpers::f32_convert_i64_u as *const () as usize as u64);
+                }
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::F32));
+                return;
+            }
+            I64Unop::F64ConvertI64S => {
+                monoasm!(
+                    &mut self.jit,
+                    cvtsi2sdq xmm(REG_TEMP_FP.as_index()), R(REG_TEMP.as_index());
+                );
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::FpReg(REG_TEMP_FP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::F64));
+                return;
+            }
+            I64Unop::F64ConvertI64U => {
+                self.emit_helper_call(helpers::f64_convert_i64_u as *const () as usize as u64);
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::F64));
+                return;
+            }
+            // the bits stay where they are
+            I64Unop::F64ReinterpretI64 => {
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::F64));
+                return;
+            }
+        }
+
+        emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+        self.reg_allocator
+            .push(RegWithType::new(a.reg, ValueType::I64));
+    }
+
+    pub(crate) fn emit_i64_binop(&mut self, binop: &I64Binop) {
+        let b = self.reg_allocator.pop_noopt();
+        let a = self.reg_allocator.pop_noopt();
+
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), a.reg);
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), b.reg);
+
+        let mut result_ty = ValueType::I64;
+        match binop {
+            I64Binop::Eq
+            | I64Binop::Ne
+            | I64Binop::LtS
+            | I64Binop::LtU
+            | I64Binop::GtS
+            | I64Binop::GtU
+            | I64Binop::LeS
+            | I64Binop::LeU
+            | I64Binop::GeS
+            | I64Binop::GeU => {
+                self.emit_i64_compare(binop);
+                result_ty = ValueType::I32;
+            }
+            I64Binop::Add => {
+                monoasm!(
+                    &mut self.jit,
+                    addq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // a = a + b
+                );
+            }
+            I64Binop::Sub => {
+                monoasm!(
+                    &mut self.jit,
+                    subq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // a = a - b
+                );
+            }
+            I64Binop::Mul => {
+                monoasm!(
+                    &mut self.jit,
+                    imul R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // a = a * b
+                );
+            }
+            I64Binop::DivS | I64Binop::DivU | I64Binop::RemS | I64Binop::RemU => {
+                let trap_label = self.trap_exit(TrapKind::DivisionByZero);
+                monoasm!(
+                    &mut self.jit,
+                    testq R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index()); // Check if divisor is zero
+                    jz trap_label;
+                );
+
+                // i64::MIN / -1 overflows, the remainder is simply 0
+                if matches!(binop, I64Binop::DivS) {
+                    let overflow_label = self.trap_exit(TrapKind::IntegerOverflow);
+                    let no_overflow = self.jit.label();
+                    monoasm!(
+                        &mut self.jit,
+                        cmpq R(REG_TEMP2.as_index()), (-1);
+                        jne no_overflow;
+                        movq R(REG_TEMP2.as_index()), (i64::MIN as u64);
+                        cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                        jz overflow_label;
+                        movq R(REG_TEMP2.as_index()), (-1i64 as u64);
+                    no_overflow:
+                    );
+                }
+
+                let helper = match binop {
+                    I64Binop::DivS => helpers::i64_div_s as *const () as usize as u64,
+                    I64Binop::DivU => helpers::i64_div_u as *const () as usize as u64,
+                    I64Binop::RemS => helpers::i64_rem_s as *const () as usize as u64,
+                    I64Binop::RemU => helpers::i64_rem_u as *const () as usize as u64,
+                    _ => unreachable!(),
+                };
+                self.emit_helper_call(helper);
+            }
+            I64Binop::And => {
+                monoasm!(
+                    &mut self.jit,
+                    andq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // a = a & b
+                );
+            }
+            I64Binop::Or => {
+                monoasm!(
+                    &mut self.jit,
+                    orq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // a = a | b
+                );
+            }
+            I64Binop::Xor => {
+                monoasm!(
+                    &mut self.jit,
+                    xorq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // a = a ^ b
+                );
+            }
+            I64Binop::Shl => {
+                monoasm!(
+                    &mut self.jit,
+                    pushq rcx;
+                    movb rcx, R(REG_TEMP2.as_index());
+                    andb cl, (0x3F);
+                    shlq R(REG_TEMP.as_index()), cl; // a = a << b
+                    popq rcx;
+                );
+            }
+            I64Binop::ShrS => {
+                monoasm!(
+                    &mut self.jit,
+                    pushq rcx;
+                    movb rcx, R(REG_TEMP2.as_index());
+                    andb cl, (0x3F);
+                    sarq R(REG_TEMP.as_index()), cl; // a = a >> b
+                    popq rcx;
+                );
+            }
+            I64Binop::ShrU => {
+                monoasm!(
+                    &mut self.jit,
+                    pushq rcx;
+                    movb rcx, R(REG_TEMP2.as_index());
+                    andb cl, (0x3F);
+                    shrq R(REG_TEMP.as_index()), cl; // a = a >> b
+                    popq rcx;
+                );
+            }
+            I64Binop::Rotl => self.emit_helper_call(helpers::i64_rotl as *const () as usize as u64),
+            I64Binop::Rotr => self.emit_helper_call(helpers::i64_rotr as *const () as usize as u64),
+        }
+
+        emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+        self.reg_allocator.push(RegWithType::new(a.reg, result_ty));
+    }
+
+    /// REG_TEMP = REG_TEMP op REG_TEMP2, as a 0/1 i32
+    fn emit_i64_compare(&mut self, binop: &I64Binop) {
+        match binop {
+            I64Binop::Eq => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    seteq R(REG_TEMP.as_index()); // a = a == b
+                );
+            }
+            I64Binop::Ne => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setne R(REG_TEMP.as_index()); // a = a != b
+                );
+            }
+            I64Binop::LtS => {
+                // the sign flag alone is wrong on overflow, compare b > a instead
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setgt R(REG_TEMP.as_index()); // a = b > a
+                );
+            }
+            I64Binop::LtU => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setb R(REG_TEMP.as_index()); // a = a < b
+                );
+            }
+            I64Binop::GtS => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setgt R(REG_TEMP.as_index()); // a = a > b
+                );
+            }
+            I64Binop::GtU => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    seta R(REG_TEMP.as_index()); // a = a > b
+                );
+            }
+            I64Binop::LeS => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setle R(REG_TEMP.as_index()); // a = a <= b
+                );
+            }
+            I64Binop::LeU => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setbe R(REG_TEMP.as_index()); // a = a <= b
+                );
+            }
+            I64Binop::GeS => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setge R(REG_TEMP.as_index()); // a = a >= b
+                );
+            }
+            I64Binop::GeU => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                    movq R(REG_TEMP.as_index()), (0);
+                    setae R(REG_TEMP.as_index()); // a = a >= b
+                );
+            }
+            _ => unreachable!("not a comparison: {:?}", binop),
+        }
+    }
+
+    // f32 values live in general purpose registers as raw bits
+    pub(crate) fn emit_f32_unop(&mut self, unop: &F32Unop) {
+        let a = self.reg_allocator.pop_noopt();
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), a.reg);
+
+        match unop {
+            F32Unop::Abs => {
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP2.as_index()), (0x7fffffffu64);
+                    andq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // clear the sign bit
+                );
+            }
+            F32Unop::Neg => {
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP2.as_index()), (0x80000000u64);
+                    xorq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // flip the sign bit
+                );
+            }
+            F32Unop::Ceil => self.emit_helper_call(helpers::f32_ceil as *const () as usize as u64),
+            F32Unop::Floor => {
+                self.emit_helper_call(helpers::f32_floor as *const () as usize as u64)
+            }
+            F32Unop::Trunc => {
+                self.emit_helper_call(helpers::f32_trunc as *const () as usize as u64)
+            }
+            F32Unop::Nearest => {
+                self.emit_helper_call(helpers::f32_nearest as *const () as usize as u64)
+            }
+            F32Unop::Sqrt => self.emit_helper_call(helpers::f32_sqrt as *const () as usize as u64),
+            F32Unop::I32TruncF32S
+            | F32Unop::I32TruncF32U
+            | F32Unop::I64TruncF32S
+            | F32Unop::I64TruncF32U => {
+                let (ty, signed) = match unop {
+                    F32Unop::I32TruncF32S => (ValueType::I32, true),
+                    F32Unop::I32TruncF32U => (ValueType::I32, false),
+                    F32Unop::I64TruncF32S => (ValueType::I64, true),
+                    _ => (ValueType::I64, false),
+                };
+                // promoting is exact, so truncate the f64 instead
+                self.emit_helper_call(helpers::f64_promote_f32 as *const () as usize as u64);
+                emit_mov_reg_to_reg(
+                    &mut self.jit,
+                    Register::FpReg(REG_TEMP_FP),
+                    Register::Reg(REG_TEMP),
+                );
+                self.emit_trunc_to_int(ty, signed);
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator.push(RegWithType::new(a.reg, ty));
+                return;
+            }
+            F32Unop::F64PromoteF32 => {
+                self.emit_helper_call(helpers::f64_promote_f32 as *const () as usize as u64);
+                if self.canonicalize_nans {
+                    emit_mov_reg_to_reg(
+                        &mut self.jit,
+                        Register::FpReg(REG_TEMP_FP),
+                        Register::Reg(REG_TEMP),
+                    );
+                    self.emit_canonicalize_f64();
+                    emit_mov_reg_to_reg(
+                        &mut self.jit,
+                        Register::Reg(REG_TEMP),
+                        Register::FpReg(REG_TEMP_FP),
+                    );
+                }
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::F64));
+                return;
+            }
+            F32Unop::I32TruncSatF32S
+            | F32Unop::I32TruncSatF32U
+            | F32Unop::I64TruncSatF32S
+            | F32Unop::I64TruncSatF32U => {
+                let (helper, ty) = match unop {
+                    F32Unop::I32TruncSatF32S => (
+                        helpers::i32_trunc_sat_f32_s as *const () as usize,
+                        ValueType::I32,
+                    ),
+                    F32Unop::I32TruncSatF32U => (
+                        helpers::i32_trunc_sat_f32_u as *const () as usize,
+                        ValueType::I32,
+                    ),
+                    F32Unop::I64TruncSatF32S => (
+                        helpers::i64_trunc_sat_f32_s as *const () as usize,
+                        ValueType::I64,
+                    ),
+                    _ => (
+                        helpers::i64_trunc_sat_f32_u as *const () as usize,
+                        ValueType::I64,
+                    ),
+                };
+                self.emit_helper_call(helper as u64);
+                emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+                self.reg_allocator.push(RegWithType::new(a.reg, ty));
+                return;
+            }
+            // the bits stay where they are
+            F32Unop::I32ReinterpretF32 => {
+                self.reg_allocator
+                    .push(RegWithType::new(a.reg, ValueType::I32));
+                return;
+            }
+        }
+
+        if !matches!(unop, F32Unop::Abs | F32Unop::Neg) {
+            self.emit_canonicalize_f32();
+        }
+        emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+        self.reg_allocator
+            .push(RegWithType::new(a.reg, ValueType::F32));
+    }
+
+    pub(crate) fn emit_f32_binop(&mut self, binop: &F32Binop) {
+        let b = self.reg_allocator.pop_noopt();
+        let a = self.reg_allocator.pop_noopt();
+
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), a.reg);
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), b.reg);
+
+        let (helper, result_ty) = match binop {
+            F32Binop::Eq => (helpers::f32_eq as *const () as usize, ValueType::I32),
+            F32Binop::Ne => (helpers::f32_ne as *const () as usize, ValueType::I32),
+            F32Binop::Lt => (helpers::f32_lt as *const () as usize, ValueType::I32),
+            F32Binop::Gt => (helpers::f32_gt as *const () as usize, ValueType::I32),
+            F32Binop::Le => (helpers::f32_le as *const () as usize, ValueType::I32),
+            F32Binop::Ge => (helpers::f32_ge as *const () as usize, ValueType::I32),
+            F32Binop::Add => (helpers::f32_add as *const () as usize, ValueType::F32),
+            F32Binop::Sub => (helpers::f32_sub as *const () as usize, ValueType::F32),
+            F32Binop::Mul => (helpers::f32_mul as *const () as usize, ValueType::F32),
+            F32Binop::Div => (helpers::f32_div as *const () as usize, ValueType::F32),
+            F32Binop::Min => (helpers::f32_min_raw as *const () as usize, ValueType::F32),
+            F32Binop::Max => (helpers::f32_max_raw as *const () as usize, ValueType::F32),
+            F32Binop::Copysign => (helpers::f32_copysign as *const () as usize, ValueType::F32),
+        };
+        self.emit_helper_call(helper as u64);
+        if result_ty == ValueType::F32 && !matches!(binop, F32Binop::Copysign) {
+            self.emit_canonicalize_f32();
+        }
+
+        emit_mov_reg_to_reg(&mut self.jit, a.reg, Register::Reg(REG_TEMP));
+        self.reg_allocator.push(RegWithType::new(a.reg, result_ty));
+    }
 }
@@ -1,17 +1,22 @@
 use std::collections::VecDeque;
 
+use anyhow::Result;
 use monoasm::*;
 use monoasm_macro::monoasm;
-use wasmparser::BlockType;
+use wasmparser::{BlockType, FuncType, ValType};
 
 use crate::{
     jit::{
         regalloc::{RegWithType, Register, X86Register, X86RegisterAllocator, REG_TEMP, REG_TEMP2},
-        utils::emit_mov_reg_to_reg,
-        X86JitCompiler,
+        setup::table::JitTable,
+        utils::{emit_mov_reg_to_reg, emit_parallel_moves},
+        ValueType, X86JitCompiler,
     },
-    module::insts::BrTable,
-    vm::{block_type_num_results, stack_height_delta},
+    module::{
+        insts::{BrTable, I32Binop, I32Unop, I64Binop, I64Unop, Instruction},
+        wasm_module::{block_type_num_params, block_type_num_results},
+    },
+    vm::TrapKind,
 };
 
 #[derive(Debug, Clone)]
@@ -29,6 +34,18 @@ pub(crate) enum WasmJitControlFlowType {
     Block,
     If,
     Loop,
+    /// a try block, the exceptions thrown in its body go to `handler`: the
+    /// landing pad of its catch clauses or where its delegate forwards them,
+    /// see `insts::exception`
+    Try {
+        handler: DestLabel,
+    },
+    /// a try block past its first catch clause, the caught exception is
+    /// saved for rethrow at the save area of `level`, the number of try
+    /// blocks around it
+    Catch {
+        level: usize,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -37,10 +54,71 @@ pub(crate) struct WasmJitControlFlowFrame {
     pub(crate) expected_stack_height: usize,
     pub(crate) entry_regalloc_snapshot: X86RegisterAllocator,
     pub(crate) num_results: usize,
+    pub(crate) num_params: usize,
     pub(crate) start_label: DestLabel,
     pub(crate) end_label: DestLabel,
 }
 
+/// A comparison whose result is only read by the `br_if` right after it, it
+/// branches on the flags instead of materializing the result, see
+/// `emit_compare_br_if`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Comparison {
+    I32Eqz,
+    I64Eqz,
+    Eq,
+    Ne,
+    LtS,
+    LtU,
+    GtS,
+    GtU,
+    LeS,
+    LeU,
+    GeS,
+    GeU,
+}
+
+impl Comparison {
+    pub(crate) fn from_inst(inst: &Instruction) -> Option<Self> {
+        let comparison = match inst {
+            Instruction::I32Unop(I32Unop::Eqz) => Comparison::I32Eqz,
+            Instruction::I64Unop(I64Unop::Eqz) => Comparison::I64Eqz,
+            Instruction::I32Binop(I32Binop::Eq) | Instruction::I64Binop(I64Binop::Eq) => {
+                Comparison::Eq
+            }
+            Instruction::I32Binop(I32Binop::Ne) | Instruction::I64Binop(I64Binop::Ne) => {
+                Comparison::Ne
+            }
+            Instruction::I32Binop(I32Binop::LtS) | Instruction::I64Binop(I64Binop::LtS) => {
+                Comparison::LtS
+            }
+            Instruction::I32Binop(I32Binop::LtU) | Instruction::I64Binop(I64Binop::LtU) => {
+                Comparison::LtU
+            }
+            Instruction::I32Binop(I32Binop::GtS) | Instruction::I64Binop(I64Binop::GtS) => {
+                Comparison::GtS
+            }
+            Instruction::I32Binop(I32Binop::GtU) | Instruction::I64Binop(I64Binop::GtU) => {
+                Comparison::GtU
+            }
+            Instruction::I32Binop(I32Binop::LeS) | Instruction::I64Binop(I64Binop::LeS) => {
+                Comparison::LeS
+            }
+            Instruction::I32Binop(I32Binop::LeU) | Instruction::I64Binop(I64Binop::LeU) => {
+                Comparison::LeU
+            }
+            Instruction::I32Binop(I32Binop::GeS) | Instruction::I64Binop(I64Binop::GeS) => {
+                Comparison::GeS
+            }
+            Instruction::I32Binop(I32Binop::GeU) | Instruction::I64Binop(I64Binop::GeU) => {
+                Comparison::GeU
+            }
+            _ => return None,
+        };
+        Some(comparison)
+    }
+}
+
 impl X86JitCompiler<'_> {
     /// compile the call_indirect instruction
     /// we get the callee label and emit the call instruction sequence
@@ -49,15 +127,32 @@ impl X86JitCompiler<'_> {
         callee_index_in_table: Register,
         type_index: u32,
         table_index: u32,
-    ) {
+    ) -> Result<()> {
+        let sig = self.emit_indirect_callee(callee_index_in_table, type_index, table_index);
+        self.emit_call(REG_TEMP, sig.params().len(), sig.results())
+    }
+
+    pub(crate) fn emit_return_call_indirect(
+        &mut self,
+        callee_index_in_table: Register,
+        type_index: u32,
+        table_index: u32,
+        stack_size: u64,
+    ) -> Result<()> {
+        let sig = self.emit_indirect_callee(callee_index_in_table, type_index, table_index);
+        self.emit_return_call(REG_TEMP, sig.params().len(), sig.results(), stack_size)
+    }
+
+    /// Read the callee of an indirect call from the table and check its
+    /// signature, the callee's function index is left in REG_TEMP.
+    fn emit_indirect_callee(
+        &mut self,
+        callee_index_in_table: Register,
+        type_index: u32,
+        table_index: u32,
+    ) -> FuncType {
         // get the callee label by reading the table
-        let nr_args = self
-            .module
-            .borrow()
-            .get_sig(type_index)
-            .unwrap()
-            .params()
-            .len();
+        let sig = self.module.get_sig(type_index).unwrap().clone();
 
         emit_mov_reg_to_reg(
             &mut self.jit,
@@ -67,37 +162,115 @@ impl X86JitCompiler<'_> {
 
         // compare the table index with the number of elements in the table
         // if it's greater than the number of elements, we should trap
-        let table_size = *self.table_len.get(table_index as usize).unwrap();
-        let trap_label = self.trap_label;
+        let table = self.tables.get(table_index as usize).unwrap() as *const JitTable as u64;
+        let trap_label = self.trap_exit(TrapKind::UndefinedElement);
+        self.emit_mov_addr(REG_TEMP, table);
         monoasm!(
             &mut self.jit,
-            cmpq R(REG_TEMP2.as_index()), (table_size);
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + (JitTable::LEN_OFFSET)];
+            cmpq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
             jge trap_label;
             cmpq R(REG_TEMP2.as_index()), 0;
             js trap_label; // negative index
         );
 
-        // dynamic type checking for signature match
-        let func_sig_indices = self.func_sig_indices.as_ptr();
+        // read the callee's function index from the table, 0 is the null
+        // reference
+        let null_label = self.trap_exit(TrapKind::UninitializedElement);
+        self.emit_mov_addr(REG_TEMP, table);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + (JitTable::DATA_OFFSET)];
+            movl R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + R(REG_TEMP2.as_index()) * 4];
+            cmpq R(REG_TEMP.as_index()), (0);
+            je null_label;
+            subq R(REG_TEMP.as_index()), (1); // reg_temp = func_index
+        );
+
+        // the last callee of this call site that passed the check is not
+        // checked again
+        let (func_index, pc) = self.current_site;
+        let cached_callee_addr =
+            &self.indirect_callees[func_index as usize][pc] as *const u32 as u64;
+        let checked_label = self.jit.label();
+        self.emit_mov_addr(REG_TEMP2, cached_callee_addr);
         monoasm!(
             &mut self.jit,
-            movq R(REG_TEMP.as_index()), (func_sig_indices);
-            movl R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + R(REG_TEMP2.as_index()) * 4]; // reg_temp = func_sig_index
-            cmpq R(REG_TEMP.as_index()), (type_index);
-            jne trap_label;
+            movl R(REG_TEMP2.as_index()), [R(REG_TEMP2.as_index())];
+            cmpq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+            je checked_label;
         );
 
-        let table_data = self.tables.get(table_index as usize).unwrap().as_ptr();
+        // dynamic type checking for signature match, signatures are compared
+        // structurally through their first occurrence in the type section
+        let func_sig_indices = self.func_sig_indices.as_ptr() as u64;
+        let expected_sig_index = self.module.get_sig_index(&sig).unwrap() as u64;
+        let mismatch_label = self.trap_exit(TrapKind::CallIndirectMismatch);
+        self.emit_mov_addr(REG_TEMP2, func_sig_indices);
         monoasm!(
             &mut self.jit,
-            movq R(REG_TEMP.as_index()), (table_data);
-            movl R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + R(REG_TEMP2.as_index()) * 4]; // reg_temp = func_index
+            movl R(REG_TEMP2.as_index()), [R(REG_TEMP2.as_index()) + R(REG_TEMP.as_index()) * 4]; // reg_temp2 = func_sig_index
+            cmpq R(REG_TEMP2.as_index()), (expected_sig_index);
+            jne mismatch_label;
         );
+        self.emit_mov_addr(REG_TEMP2, cached_callee_addr);
+        monoasm!(
+            &mut self.jit,
+            movl [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+        checked_label:
+        );
+
+        sig
+    }
+
+    /// compile a tail call, the arguments are moved to the argument registers
+    /// and we jump to the callee after tearing down our own frame, so the
+    /// callee returns straight to our caller.
+    ///
+    /// Arguments past the sixth live in the caller's frame, which we cannot
+    /// resize, so such calls fall back to a call followed by a return.
+    pub(crate) fn emit_return_call(
+        &mut self,
+        callee_index: X86Register,
+        nr_args: usize,
+        results: &[ValType],
+        stack_size: u64,
+    ) -> Result<()> {
+        if nr_args > 6 {
+            self.emit_call(callee_index, nr_args, results)?;
+            self.emit_function_return(None, stack_size, results.len());
+            return Ok(());
+        }
+
+        emit_mov_reg_to_reg(
+            &mut self.jit,
+            Register::Reg(REG_TEMP),
+            Register::Reg(callee_index),
+        ); // reg_temp = callee_index
+
+        self.setup_function_call_arguments(nr_args);
 
-        self.emit_call(REG_TEMP, nr_args);
+        // the callee address is loaded before the epilogue restores REG_TEMP
+        let func_addrs_ptr = self.func_addrs.as_ptr() as u64;
+        self.emit_mov_addr(REG_TEMP2, func_addrs_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq rax, [R(REG_TEMP2.as_index()) + R(REG_TEMP.as_index()) * 8];
+        );
+        self.epilogue(stack_size);
+        monoasm!(
+            &mut self.jit,
+            jmp rax;
+        );
+        Ok(())
     }
 
-    pub(crate) fn emit_call(&mut self, callee_index: X86Register, nr_args: usize) {
+    pub(crate) fn emit_call(
+        &mut self,
+        callee_index: X86Register,
+        nr_args: usize,
+        results: &[ValType],
+    ) -> Result<()> {
         emit_mov_reg_to_reg(
             &mut self.jit,
             Register::Reg(REG_TEMP),
@@ -130,18 +303,38 @@ impl X86JitCompiler<'_> {
         self.setup_function_call_arguments(nr_args);
 
         // get callee address and call it
-        let func_addrs_ptr = self.func_addrs.as_ptr();
+        let func_addrs_ptr = self.func_addrs.as_ptr() as u64;
+        self.emit_mov_addr(REG_TEMP2, func_addrs_ptr);
         monoasm!(
             &mut self.jit,
-            movq R(REG_TEMP2.as_index()), (func_addrs_ptr);
             movq rax, [R(REG_TEMP2.as_index()) + R(REG_TEMP.as_index()) * 8];
             call rax;
         );
+        let return_label = self.jit.label();
+        self.emit_single_label(return_label);
+        let (func_index, pc) = self.current_site;
+        self.call_sites.push((return_label, func_index, pc));
 
         // note that we don't want the return value to be in caller-saved registers
         // because we will pop them later in the call sequence
-        let ret = self.reg_allocator.next_not_caller_saved();
-        emit_mov_reg_to_reg(&mut self.jit, ret.reg, Register::Reg(X86Register::Rax));
+        for (i, ty) in results.iter().enumerate() {
+            let ret = self
+                .reg_allocator
+                .next_not_caller_saved(ValueType::from_val_type(ty)?)
+                .reg;
+            if i == 0 {
+                emit_mov_reg_to_reg(&mut self.jit, ret, Register::Reg(X86Register::Rax));
+            } else {
+                // the rest of the results are left in the return area
+                let addr = self.multi_results.as_ptr() as u64 + (i * 8) as u64;
+                self.emit_mov_addr(REG_TEMP, addr);
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index())];
+                );
+                emit_mov_reg_to_reg(&mut self.jit, ret, Register::Reg(REG_TEMP));
+            }
+        }
 
         // restore the stack spaced we used.....
         let restore_size = (std::cmp::max(6, nr_args) - 6) * 8;
@@ -169,6 +362,10 @@ impl X86JitCompiler<'_> {
                 Register::Stack(_) => panic!("stack should not be caller saved"),
             }
         }
+
+        // the callee may have left an exception behind
+        self.emit_exception_check();
+        Ok(())
     }
 
     /// compile the select instruction
@@ -205,13 +402,15 @@ impl X86JitCompiler<'_> {
         block_begin: DestLabel,
         block_end: DestLabel,
     ) {
-        let expected_stack_size =
-            self.reg_allocator.size() + stack_height_delta(self.module.clone(), ty);
+        let num_params = block_type_num_params(&self.module, ty);
+        let num_results = block_type_num_results(&self.module, ty);
+        let expected_stack_size = self.reg_allocator.size() - num_params + num_results;
         self.control_flow_stack.push_back(WasmJitControlFlowFrame {
             control_type: WasmJitControlFlowType::Block,
             expected_stack_height: expected_stack_size,
             entry_regalloc_snapshot: self.reg_allocator.clone(),
-            num_results: block_type_num_results(self.module.clone(), ty),
+            num_results,
+            num_params,
             start_label: block_begin,
             end_label: block_end,
         });
@@ -221,18 +420,24 @@ impl X86JitCompiler<'_> {
 
     pub(crate) fn emit_loop(&mut self, ty: BlockType, end_label: DestLabel) {
         let start_label = self.jit.label();
-        let expected_stack_height =
-            self.reg_allocator.size() + stack_height_delta(self.module.clone(), ty);
+        let num_params = block_type_num_params(&self.module, ty);
+        let num_results = block_type_num_results(&self.module, ty);
+        let expected_stack_height = self.reg_allocator.size() - num_params + num_results;
         self.control_flow_stack.push_back(WasmJitControlFlowFrame {
             control_type: WasmJitControlFlowType::Loop,
             expected_stack_height,
             entry_regalloc_snapshot: self.reg_allocator.clone(),
-            num_results: block_type_num_results(self.module.clone(), ty),
+            num_results,
+            num_params,
             start_label,
             end_label,
         });
 
         self.emit_single_label(start_label);
+        // every branch back to the loop header consumes fuel and checks the
+        // epoch
+        self.emit_fuel_check();
+        self.emit_epoch_check();
     }
 
     pub(crate) fn emit_if(
@@ -244,13 +449,15 @@ impl X86JitCompiler<'_> {
     ) {
         let start_label = self.jit.label();
 
-        let expected_stack_height =
-            self.reg_allocator.size() + stack_height_delta(self.module.clone(), ty);
+        let num_params = block_type_num_params(&self.module, ty);
+        let num_results = block_type_num_results(&self.module, ty);
+        let expected_stack_height = self.reg_allocator.size() - num_params + num_results;
         self.control_flow_stack.push_back(WasmJitControlFlowFrame {
             control_type: WasmJitControlFlowType::If,
             expected_stack_height,
             entry_regalloc_snapshot: self.reg_allocator.clone(),
-            num_results: block_type_num_results(self.module.clone(), ty),
+            num_results,
+            num_params,
             start_label,
             end_label,
         });
@@ -264,11 +471,18 @@ impl X86JitCompiler<'_> {
                 jz else_label; /* else block executes until it reaches end */
             );
         } else {
-            // if there is no else block, we jump to the end directly
+            // if there is no else block, the false path carries the params
+            // to the end as the results, reconciled there like a branch
+            let reconcile_start_label = self.jit.label();
+            self.reg_reconcile_info.push(RegReconcileInfo {
+                target_end_label: end_label,
+                reconcile_start_label,
+                regalloc_snapshot: self.reg_allocator.clone(),
+            });
             monoasm!(
                 &mut self.jit,
                 cmpq R(REG_TEMP.as_index()), 0;
-                jmp end_label;
+                jz reconcile_start_label;
             );
         }
     }
@@ -296,11 +510,12 @@ impl X86JitCompiler<'_> {
 
         // now we are jumping to actual target inside the table
         // width = 8 because we are storing u64
-        let target_addrs_ptr =
-            self.brtable_nondefault_target_addrs[&(which_func as usize)][which_table].as_ptr();
+        let target_addrs_ptr = self.brtable_nondefault_target_addrs[&(which_func as usize)]
+            [which_table]
+            .as_ptr() as u64;
+        self.emit_mov_addr(REG_TEMP2, target_addrs_ptr);
         monoasm!(
             &mut self.jit,
-            movq R(REG_TEMP2.as_index()), (target_addrs_ptr);
             jmp [R(REG_TEMP2.as_index()) + R(REG_TEMP.as_index()) * 8];
         );
 
@@ -315,6 +530,16 @@ impl X86JitCompiler<'_> {
         self.emit_br(table.default_target);
     }
 
+    /// Bind the targets of a `br_table` in unreachable code, its jump table
+    /// is filled like the others but never read.
+    pub(crate) fn skip_br_table(&mut self, which_func: u32, which_table: usize) {
+        let target_labels =
+            self.brtable_nondefault_target_labels[&(which_func as usize)][which_table].clone();
+        for target_label in target_labels {
+            self.emit_single_label(target_label);
+        }
+    }
+
     pub(crate) fn emit_br_if(&mut self, cond: Register, rel_depth: u32) {
         let skip_br = self.jit.label();
         emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), cond);
@@ -327,6 +552,113 @@ impl X86JitCompiler<'_> {
         self.emit_single_label(skip_br);
     }
 
+    /// Compile a comparison and the `br_if` reading its result, instead of
+    /// setting the result, testing it and jumping, the branch is skipped on
+    /// the opposite condition.
+    pub(crate) fn emit_compare_br_if(&mut self, comparison: Comparison, rel_depth: u32) {
+        let skip_br = self.jit.label();
+        let b = self.reg_allocator.pop_noopt();
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), b.reg);
+        match comparison {
+            // the i32 in the register may have its upper half set
+            Comparison::I32Eqz => {
+                monoasm!(
+                    &mut self.jit,
+                    movl R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index());
+                    cmpq R(REG_TEMP2.as_index()), (0);
+                    jne skip_br;
+                );
+            }
+            Comparison::I64Eqz => {
+                monoasm!(
+                    &mut self.jit,
+                    cmpq R(REG_TEMP2.as_index()), (0);
+                    jne skip_br;
+                );
+            }
+            _ => {
+                let a = self.reg_allocator.pop_noopt();
+                emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), a.reg);
+                match comparison {
+                    Comparison::Eq => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            jne skip_br;
+                        );
+                    }
+                    Comparison::Ne => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            je skip_br;
+                        );
+                    }
+                    Comparison::LtS => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            jge skip_br;
+                        );
+                    }
+                    Comparison::LtU => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            jae skip_br;
+                        );
+                    }
+                    Comparison::GeS => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            jlt skip_br;
+                        );
+                    }
+                    Comparison::GeU => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            jb skip_br;
+                        );
+                    }
+                    Comparison::LeS => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            jgt skip_br;
+                        );
+                    }
+                    Comparison::LeU => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+                            ja skip_br;
+                        );
+                    }
+                    // a > b is b < a
+                    Comparison::GtS => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+                            jge skip_br;
+                        );
+                    }
+                    Comparison::GtU => {
+                        monoasm!(
+                            &mut self.jit,
+                            cmpq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+                            jae skip_br;
+                        );
+                    }
+                    Comparison::I32Eqz | Comparison::I64Eqz => unreachable!(),
+                }
+            }
+        }
+        self.emit_br(rel_depth);
+        self.emit_single_label(skip_br);
+    }
+
     pub(crate) fn emit_br(&mut self, rel_depth: u32) {
         let target_depth = rel_depth as usize;
         let stack_depth = self.control_flow_stack.len();
@@ -337,8 +669,14 @@ impl X86JitCompiler<'_> {
 
         let target_frame = self.control_flow_stack[stack_depth - target_depth - 1].clone();
 
+        // the values are only unwound on the branch, the code after a br_if
+        // and the other targets of a br_table still see all of them
+        let regalloc_snapshot = self.reg_allocator.clone();
         match target_frame.control_type {
-            WasmJitControlFlowType::Block { .. } | WasmJitControlFlowType::If { .. } => {
+            WasmJitControlFlowType::Block
+            | WasmJitControlFlowType::If
+            | WasmJitControlFlowType::Try { .. }
+            | WasmJitControlFlowType::Catch { .. } => {
                 // we dont need to truncate the stack here, because the jit code
                 // is not actually run during codegen
                 self.unwind_stack(target_frame.expected_stack_height, target_frame.num_results);
@@ -356,29 +694,28 @@ impl X86JitCompiler<'_> {
             // In loop, we need to emit moves in order to reconstruct the
             // register state so a consistent register state is maintained
             WasmJitControlFlowType::Loop => {
-                self.unwind_stack(target_frame.expected_stack_height, target_frame.num_results);
-
-                // make register state consistent
-                let now_regalloc_vec = self.reg_allocator.get_vec().clone();
+                // the loop's parameters are carried back to the loop header
+                let entry_stack_height = target_frame.expected_stack_height
+                    - target_frame.num_results
+                    + target_frame.num_params;
+                self.unwind_stack(entry_stack_height, target_frame.num_params);
+
+                // make register state consistent, the values go back where
+                // the loop header expects them
+                let now_regalloc_vec = self.reg_allocator.get_vec();
                 let target_frame_regalloc_vec = target_frame.entry_regalloc_snapshot.get_vec();
-
-                // now we need to recover the register state by generating moves
-                // keep the last registers
-                let now_regalloc_vec = now_regalloc_vec
+                let moves = now_regalloc_vec
                     .iter()
                     .skip(now_regalloc_vec.len() - target_frame_regalloc_vec.len())
+                    .zip(target_frame_regalloc_vec)
+                    .map(|(now, target)| (target.reg, now.reg))
                     .collect::<Vec<_>>();
-                // for each different register, generate a move
-                for (i, reg) in now_regalloc_vec.iter().enumerate().rev() {
-                    let target_reg = target_frame_regalloc_vec[i].reg;
-                    if reg.reg != target_reg {
-                        emit_mov_reg_to_reg(&mut self.jit, target_reg, reg.reg);
-                    }
-                }
+                emit_parallel_moves(&mut self.jit, &moves);
 
                 self.emit_jmp(target_frame.start_label);
             }
         }
+        self.reg_allocator = regalloc_snapshot;
     }
 
     pub(crate) fn emit_single_label(&mut self, label: DestLabel) {
@@ -1,16 +1,19 @@
 use std::collections::HashMap;
 
+use super::{control::Comparison, helpers::HelperArg};
 use crate::{
     jit::{
         regalloc::{Register, REG_TEMP},
-        utils::emit_mov_reg_to_reg,
+        utils::emit_parallel_moves,
         ValueType, X86JitCompiler,
     },
     module::insts::Instruction,
+    vm::{trace_block, TrapKind},
 };
 
 use anyhow::{anyhow, Result};
 use monoasm::DestLabel;
+use wasmparser::ValType;
 
 impl X86JitCompiler<'_> {
     pub(crate) fn emit_asm(
@@ -23,7 +26,60 @@ impl X86JitCompiler<'_> {
         end_labels: HashMap<usize, DestLabel>,
     ) -> Result<()> {
         let mut nbrtable = 0;
+        // the label of every catch clause, made when its try block is entered
+        let mut catch_labels = HashMap::new();
+        // the br_if already compiled with the comparison before it
+        let mut fused_br_if = None;
+        // after an unreachable, br, br_table or return, the number of blocks
+        // opened in the unreachable code, nothing is emitted until the end
+        // or else of the block the code became unreachable in
+        let mut dead: Option<usize> = None;
         for (i, inst) in insts.iter().enumerate() {
+            if fused_br_if == Some(i) {
+                continue;
+            }
+            if let Some(depth) = dead {
+                match inst {
+                    Instruction::Block { .. }
+                    | Instruction::Loop { .. }
+                    | Instruction::If { .. }
+                    | Instruction::Try { .. } => {
+                        dead = Some(depth + 1);
+                        continue;
+                    }
+                    Instruction::End | Instruction::Delegate { .. } if depth > 0 => {
+                        dead = Some(depth - 1);
+                        continue;
+                    }
+                    Instruction::BrTable { .. } => {
+                        self.skip_br_table(func_index, nbrtable);
+                        nbrtable += 1;
+                        continue;
+                    }
+                    // the else, catch clause or end of the block is compiled
+                    // below
+                    Instruction::Else
+                    | Instruction::End
+                    | Instruction::Catch { .. }
+                    | Instruction::CatchAll
+                    | Instruction::Delegate { .. }
+                        if depth == 0 => {}
+                    _ => continue,
+                }
+            }
+            self.current_site = (func_index, i);
+            if self.trace && Self::starts_basic_block(insts, i) {
+                self.emit_trace_block(func_index, i);
+            }
+
+            if let Some(comparison) = Comparison::from_inst(inst) {
+                if let Some((br_if_pc, rel_depth)) = Self::next_br_if(insts, i) {
+                    self.emit_compare_br_if(comparison, rel_depth);
+                    fused_br_if = Some(br_if_pc);
+                    continue;
+                }
+            }
+
             match inst {
                 Instruction::I32Const { value } => {
                     let reg = self.reg_allocator.next();
@@ -42,6 +98,10 @@ impl X86JitCompiler<'_> {
                     self.emit_block(*ty, block_begin, block_end);
                 }
                 Instruction::Loop { ty } => {
+                    if self.osr.as_ref().is_some_and(|osr| osr.loop_pc == i) {
+                        // the prelude jumps here with the locals set
+                        self.emit_osr_loop_header(local_types.len());
+                    }
                     let end_ind = Self::find_matching_end_index(insts, i);
                     let end_label = *end_labels.get(&end_ind).unwrap();
                     self.emit_loop(*ty, end_label);
@@ -58,20 +118,38 @@ impl X86JitCompiler<'_> {
                 Instruction::Else => {
                     let frame = self.control_flow_stack.back().unwrap();
                     let regalloc_snapshot = frame.entry_regalloc_snapshot.clone();
-                    let end_label = frame.end_label;
 
-                    self.emit_jmp(end_label);
+                    // the then block leaves its results like a branch to the
+                    // end, to be moved where the else block leaves them
+                    if dead.take().is_none() {
+                        self.emit_br(0);
+                    }
                     self.emit_single_label(*else_labels.get(&i).unwrap());
 
                     // reset the register allocator to the snapshot in the else block
                     // to maintain a consistent view of the stack
                     self.reg_allocator = regalloc_snapshot;
                 }
-                Instruction::End => {
+                Instruction::End | Instruction::Delegate { .. } => {
                     self.control_flow_stack.pop_back().unwrap();
                     let end_label = *end_labels.get(&i).unwrap();
 
-                    self.emit_jmp(end_label);
+                    if dead.is_some() {
+                        // the end is only reached by the branches to it, the
+                        // first one leaves the values where the rest of the
+                        // code expects them. The end of a loop is only
+                        // reached by falling through
+                        let branch = self
+                            .reg_reconcile_info
+                            .iter()
+                            .find(|info| info.target_end_label == end_label);
+                        if let Some(branch) = branch {
+                            self.reg_allocator = branch.regalloc_snapshot.clone();
+                            dead = None;
+                        }
+                    } else {
+                        self.emit_jmp(end_label);
+                    }
                     self.emit_reg_reconciliation(end_label);
                     self.emit_single_label(end_label);
                 }
@@ -88,31 +166,51 @@ impl X86JitCompiler<'_> {
                     nbrtable += 1;
                 }
                 Instruction::Return => {
-                    self.emit_function_return(None, stack_size);
-                }
-                Instruction::Call { func_idx } => {
-                    let nargs = self
+                    let num_results = self
                         .module
-                        .borrow()
-                        .get_func(*func_idx)
+                        .get_func(func_index)
                         .unwrap()
                         .get_sig()
-                        .params()
+                        .results()
                         .len();
+                    self.emit_function_return(None, stack_size, num_results);
+                }
+                Instruction::Call { func_idx } => {
+                    let sig = self.module.get_func(*func_idx).unwrap().get_sig().clone();
                     self.emit_mov_rawvalue_to_reg(*func_idx as u64, Register::Reg(REG_TEMP));
-                    self.emit_call(REG_TEMP, nargs);
+                    self.emit_call(REG_TEMP, sig.params().len(), sig.results())?;
                 }
                 Instruction::CallIndirect {
                     type_index,
                     table_index,
                 } => {
                     let callee_index_in_table = self.reg_allocator.pop_noopt();
-                    self.emit_call_indirect(callee_index_in_table.reg, *type_index, *table_index);
+                    self.emit_call_indirect(callee_index_in_table.reg, *type_index, *table_index)?;
+                }
+                Instruction::ReturnCall { func_idx } => {
+                    let sig = self.module.get_func(*func_idx).unwrap().get_sig().clone();
+                    self.emit_mov_rawvalue_to_reg(*func_idx as u64, Register::Reg(REG_TEMP));
+                    self.emit_return_call(REG_TEMP, sig.params().len(), sig.results(), stack_size)?;
+                }
+                Instruction::ReturnCallIndirect {
+                    type_index,
+                    table_index,
+                } => {
+                    let callee_index_in_table = self.reg_allocator.pop_noopt();
+                    self.emit_return_call_indirect(
+                        callee_index_in_table.reg,
+                        *type_index,
+                        *table_index,
+                        stack_size,
+                    )?;
                 }
                 Instruction::Drop => {
                     self.reg_allocator.pop_noopt();
                 }
-                Instruction::Select => {
+                Instruction::TypedSelect { tys } if tys.contains(&ValType::V128) => {
+                    return Err(anyhow!("simd instructions are not supported by the jit"));
+                }
+                Instruction::Select | Instruction::TypedSelect { .. } => {
                     let cond = self.reg_allocator.pop_noopt();
                     let b = self.reg_allocator.pop_noopt();
                     let a = self.reg_allocator.pop_noopt();
@@ -142,85 +240,326 @@ impl X86JitCompiler<'_> {
                     let value = self.reg_allocator.pop_noopt();
                     self.emit_global_set(value.reg, *global_idx);
                 }
+                Instruction::TableGet { table } => {
+                    let index = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_table_get(dst, index.reg, *table);
+                }
+                Instruction::TableSet { table } => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let index = self.reg_allocator.pop_noopt();
+                    self.emit_table_set(index.reg, value.reg, *table);
+                }
+                Instruction::TableInit { elem_index, table } => {
+                    if *elem_index as usize >= self.elem_segments.len() {
+                        return Err(anyhow!("table.init: invalid element index"));
+                    }
+                    if *table as usize >= self.tables.len() {
+                        return Err(anyhow!("table.init: invalid table index"));
+                    }
+
+                    let n = self.reg_allocator.pop_noopt();
+                    let src = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.pop_noopt();
+                    self.emit_table_init(dst.reg, src.reg, n.reg, *elem_index, *table);
+                }
+                Instruction::ElemDrop { elem_index } => {
+                    if *elem_index as usize >= self.elem_segments.len() {
+                        return Err(anyhow!("elem.drop: invalid element index"));
+                    }
+
+                    self.emit_elem_drop(*elem_index);
+                }
+                Instruction::TableGrow { table } => {
+                    if *table as usize >= self.tables.len() {
+                        return Err(anyhow!("table.grow: invalid table index"));
+                    }
+
+                    let n = self.reg_allocator.pop_noopt();
+                    let init = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_table_grow(dst, init.reg, n.reg, *table);
+                }
+                Instruction::TableSize { table } => {
+                    if *table as usize >= self.tables.len() {
+                        return Err(anyhow!("table.size: invalid table index"));
+                    }
+
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_table_size(dst, *table);
+                }
+                Instruction::TableFill { table } => {
+                    if *table as usize >= self.tables.len() {
+                        return Err(anyhow!("table.fill: invalid table index"));
+                    }
+
+                    let n = self.reg_allocator.pop_noopt();
+                    let value = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.pop_noopt();
+                    self.emit_table_fill(dst.reg, value.reg, n.reg, *table);
+                }
+                // references are kept as index + 1 with 0 being null
+                Instruction::RefNull { .. } => {
+                    let reg = self.reg_allocator.next();
+                    self.emit_mov_rawvalue_to_reg(0, reg.reg);
+                }
+                Instruction::Try { ty } => {
+                    let end_ind = Self::find_matching_end_index(insts, i);
+                    let end_label = *end_labels.get(&end_ind).unwrap();
+                    let delegate = match insts[end_ind] {
+                        Instruction::Delegate { rel_depth } => Some(rel_depth),
+                        _ => None,
+                    };
+                    let catches = Self::find_catches(insts, i)
+                        .into_iter()
+                        .map(|(pc, tag_index)| {
+                            let label = self.jit.label();
+                            catch_labels.insert(pc, label);
+                            (tag_index, label)
+                        })
+                        .collect::<Vec<_>>();
+                    self.emit_try(*ty, end_label, &catches, delegate);
+                }
+                Instruction::Catch { .. } | Instruction::CatchAll => {
+                    // the part of the try block before the clause leaves its
+                    // results like a branch to the end
+                    if dead.take().is_none() {
+                        self.emit_br(0);
+                    }
+                    let tag_index = match inst {
+                        Instruction::Catch { tag_index } => Some(*tag_index),
+                        _ => None,
+                    };
+                    self.emit_catch(tag_index, catch_labels[&i])?;
+                }
+                Instruction::Throw { tag_index } => self.emit_throw(*tag_index)?,
+                Instruction::Rethrow { rel_depth } => self.emit_rethrow(*rel_depth)?,
+                // not lowered yet, v128 values do not fit the 8 byte stack
+                // slots and registers of the jit, so modules using simd run
+                // in the interpreter, see `X86JitCompiler::unsupported`
+                Instruction::V128Load { .. }
+                | Instruction::V128Store { .. }
+                | Instruction::V128LoadExtend { .. }
+                | Instruction::V128LoadSplat { .. }
+                | Instruction::V128LoadZero { .. }
+                | Instruction::V128LoadLane { .. }
+                | Instruction::V128StoreLane { .. }
+                | Instruction::V128Const { .. }
+                | Instruction::I8x16Shuffle { .. }
+                | Instruction::V128Splat { .. }
+                | Instruction::V128ExtractLane { .. }
+                | Instruction::V128ReplaceLane { .. }
+                | Instruction::V128Bitselect
+                | Instruction::V128Unop(_)
+                | Instruction::V128Binop(_)
+                | Instruction::V128Shift(_)
+                | Instruction::V128Test(_) => {
+                    return Err(anyhow!("simd instructions are not supported by the jit"));
+                }
+                Instruction::RefIsNull => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_ref_is_null(dst, value.reg);
+                }
+                Instruction::RefFunc { func_idx } => {
+                    let reg = self.reg_allocator.next();
+                    self.emit_mov_rawvalue_to_reg(*func_idx as u64 + 1, reg.reg);
+                }
                 Instruction::I32Load { memarg } => {
                     let base = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let dst = self.reg_allocator.next().reg;
-                    self.emit_load_mem(dst, base.reg, offset, 4, false);
+                    self.emit_load_mem(dst, base.reg, memarg, 4, false)?;
+                }
+                Instruction::F32Load { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 4, false)?;
                 }
                 Instruction::F64Load { memarg } => {
                     let base = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let dst = self.reg_allocator.next().reg;
-                    self.emit_load_mem(dst, base.reg, offset, 8, false);
+                    self.emit_load_mem(dst, base.reg, memarg, 8, false)?;
                 }
                 Instruction::I32Load8S { memarg } => {
                     let base = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let dst = self.reg_allocator.next().reg;
-                    self.emit_load_mem(dst, base.reg, offset, 1, true);
+                    self.emit_load_mem(dst, base.reg, memarg, 1, true)?;
                 }
                 Instruction::I32Load8U { memarg } => {
                     let base = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let dst = self.reg_allocator.next().reg;
-                    self.emit_load_mem(dst, base.reg, offset, 1, false);
+                    self.emit_load_mem(dst, base.reg, memarg, 1, false)?;
                 }
                 Instruction::I32Load16S { memarg } => {
                     let base = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let dst = self.reg_allocator.next().reg;
-                    self.emit_load_mem(dst, base.reg, offset, 2, true);
+                    self.emit_load_mem(dst, base.reg, memarg, 2, true)?;
                 }
                 Instruction::I32Load16U { memarg } => {
                     let base = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let dst = self.reg_allocator.next().reg;
-                    self.emit_load_mem(dst, base.reg, offset, 2, false);
+                    self.emit_load_mem(dst, base.reg, memarg, 2, false)?;
+                }
+                Instruction::I64Load { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 8, false)?;
+                }
+                Instruction::I64Load8S { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 1, true)?;
+                }
+                Instruction::I64Load8U { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 1, false)?;
+                }
+                Instruction::I64Load16S { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 2, true)?;
+                }
+                Instruction::I64Load16U { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 2, false)?;
+                }
+                Instruction::I64Load32S { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 4, true)?;
+                }
+                Instruction::I64Load32U { memarg } => {
+                    let base = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.next().reg;
+                    self.emit_load_mem(dst, base.reg, memarg, 4, false)?;
                 }
                 Instruction::I32Store { memarg } => {
                     let value = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let base = self.reg_allocator.pop_noopt();
-                    self.emit_store_mem(base.reg, offset, value.reg, 4);
+                    self.emit_store_mem(base.reg, memarg, value.reg, 4)?;
+                }
+                Instruction::F32Store { memarg } => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let base = self.reg_allocator.pop_noopt();
+                    self.emit_store_mem(base.reg, memarg, value.reg, 4)?;
                 }
                 Instruction::F64Store { memarg } => {
                     let value = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let base = self.reg_allocator.pop_noopt();
-                    self.emit_store_mem(base.reg, offset, value.reg, 8);
+                    self.emit_store_mem(base.reg, memarg, value.reg, 8)?;
                 }
                 Instruction::I32Store8 { memarg } => {
                     let value = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let base = self.reg_allocator.pop_noopt();
-                    self.emit_store_mem(base.reg, offset, value.reg, 1);
+                    self.emit_store_mem(base.reg, memarg, value.reg, 1)?;
                 }
                 Instruction::I32Store16 { memarg } => {
                     let value = self.reg_allocator.pop_noopt();
-                    let offset = memarg.offset;
                     let base = self.reg_allocator.pop_noopt();
-                    self.emit_store_mem(base.reg, offset, value.reg, 2);
+                    self.emit_store_mem(base.reg, memarg, value.reg, 2)?;
+                }
+                Instruction::I64Store { memarg } => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let base = self.reg_allocator.pop_noopt();
+                    self.emit_store_mem(base.reg, memarg, value.reg, 8)?;
+                }
+                Instruction::I64Store8 { memarg } => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let base = self.reg_allocator.pop_noopt();
+                    self.emit_store_mem(base.reg, memarg, value.reg, 1)?;
+                }
+                Instruction::I64Store16 { memarg } => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let base = self.reg_allocator.pop_noopt();
+                    self.emit_store_mem(base.reg, memarg, value.reg, 2)?;
+                }
+                Instruction::I64Store32 { memarg } => {
+                    let value = self.reg_allocator.pop_noopt();
+                    let base = self.reg_allocator.pop_noopt();
+                    self.emit_store_mem(base.reg, memarg, value.reg, 4)?;
                 }
                 Instruction::MemorySize { mem } => {
-                    if *mem != 0 {
+                    if *mem as usize >= self.linear_mems.len() {
                         return Err(anyhow!("memory.size: invalid memory index"));
                     }
 
                     let dst = self.reg_allocator.next();
-                    self.store_mem_page_size(dst.reg);
+                    self.store_mem_page_size(dst.reg, *mem);
                 }
                 Instruction::MemoryGrow { mem } => {
-                    if *mem != 0 {
-                        return Err(anyhow!("memory.size: invalid memory index"));
+                    if *mem as usize >= self.linear_mems.len() {
+                        return Err(anyhow!("memory.grow: invalid memory index"));
                     }
 
                     let additional_pages = self.reg_allocator.pop_noopt();
 
-                    // use a spill register to avoid aliasing
-                    let dst = self.reg_allocator.new_spill(ValueType::I32);
+                    // use a spill register to avoid aliasing, memory64 sizes
+                    // are i64
+                    let memory64 = self
+                        .module
+                        .get_memories()
+                        .get(*mem as usize)
+                        .is_some_and(|m| m.memory64);
+                    let ty = if memory64 {
+                        ValueType::I64
+                    } else {
+                        ValueType::I32
+                    };
+                    let dst = self.reg_allocator.new_spill(ty);
 
-                    self.emit_memory_grow(dst.reg, additional_pages.reg);
+                    self.emit_memory_grow(dst.reg, additional_pages.reg, *mem);
+                }
+                Instruction::MemoryCopy { dst_mem, src_mem } => {
+                    let nmems = self.linear_mems.len();
+                    if *dst_mem as usize >= nmems || *src_mem as usize >= nmems {
+                        return Err(anyhow!("memory.copy: invalid memory index"));
+                    }
+
+                    let n = self.reg_allocator.pop_noopt();
+                    let src = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.pop_noopt();
+                    self.emit_memory_copy(dst.reg, src.reg, n.reg, *dst_mem, *src_mem);
+                }
+                Instruction::MemoryFill { mem } => {
+                    if *mem as usize >= self.linear_mems.len() {
+                        return Err(anyhow!("memory.fill: invalid memory index"));
+                    }
+
+                    let n = self.reg_allocator.pop_noopt();
+                    let value = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.pop_noopt();
+                    self.emit_memory_fill(dst.reg, value.reg, n.reg, *mem);
+                }
+                Instruction::MemoryInit { data_index, mem } => {
+                    if *mem as usize >= self.linear_mems.len() {
+                        return Err(anyhow!("memory.init: invalid memory index"));
+                    }
+                    if *data_index as usize >= self.data_segments.len() {
+                        return Err(anyhow!("memory.init: invalid data index"));
+                    }
+
+                    let n = self.reg_allocator.pop_noopt();
+                    let src = self.reg_allocator.pop_noopt();
+                    let dst = self.reg_allocator.pop_noopt();
+                    self.emit_memory_init(dst.reg, src.reg, n.reg, *data_index, *mem);
+                }
+                Instruction::DataDrop { data_index } => {
+                    if *data_index as usize >= self.data_segments.len() {
+                        return Err(anyhow!("data.drop: invalid data index"));
+                    }
+
+                    self.emit_data_drop(*data_index);
+                }
+                Instruction::I64Const { value } => {
+                    let reg = self.reg_allocator.next();
+                    self.emit_mov_rawvalue_to_reg(*value as u64, reg.reg);
+                }
+                // f32 values are kept as raw bits in general purpose registers
+                Instruction::F32Const { value } => {
+                    let reg = self.reg_allocator.next();
+                    self.emit_mov_rawvalue_to_reg(value.to_bits() as u64, reg.reg);
                 }
                 Instruction::F64Const { value } => {
                     let reg = self.reg_allocator.next_xmm();
@@ -228,14 +567,75 @@ impl X86JitCompiler<'_> {
                 }
                 Instruction::I32Unop(unop) => self.emit_i32_unop(unop),
                 Instruction::I32Binop(binop) => self.emit_i32_binop(binop),
+                Instruction::I64Unop(unop) => self.emit_i64_unop(unop),
+                Instruction::I64Binop(binop) => self.emit_i64_binop(binop),
+                Instruction::F32Unop(unop) => self.emit_f32_unop(unop),
+                Instruction::F32Binop(binop) => self.emit_f32_binop(binop),
                 Instruction::F64Unop(unop) => self.emit_f64_unop(unop),
                 Instruction::F64Binop(binop) => self.emit_f64_binop(binop),
             }
+
+            if matches!(
+                inst,
+                Instruction::Unreachable
+                    | Instruction::Br { .. }
+                    | Instruction::BrTable { .. }
+                    | Instruction::Return
+                    | Instruction::ReturnCall { .. }
+                    | Instruction::ReturnCallIndirect { .. }
+                    | Instruction::Throw { .. }
+                    | Instruction::Rethrow { .. }
+            ) {
+                dead = Some(0);
+            }
         }
 
         Ok(())
     }
 
+    /// Whether control may reach `insts[i]` other than from `insts[i - 1]`,
+    /// or `insts[i - 1]` transfers control elsewhere.
+    fn starts_basic_block(insts: &[Instruction], i: usize) -> bool {
+        i == 0
+            || matches!(
+                insts[i - 1],
+                Instruction::Block { .. }
+                    | Instruction::Loop { .. }
+                    | Instruction::If { .. }
+                    | Instruction::Try { .. }
+                    | Instruction::Else
+                    | Instruction::Catch { .. }
+                    | Instruction::CatchAll
+                    | Instruction::End
+                    | Instruction::Delegate { .. }
+                    | Instruction::Br { .. }
+                    | Instruction::BrIf { .. }
+                    | Instruction::BrTable { .. }
+                    | Instruction::Throw { .. }
+                    | Instruction::Rethrow { .. }
+            )
+    }
+
+    /// The pc and depth of the `br_if` right after the instruction at `pc`,
+    /// past the nops left by constant folding.
+    fn next_br_if(insts: &[Instruction], pc: usize) -> Option<(usize, u32)> {
+        let (offset, inst) = insts[pc + 1..]
+            .iter()
+            .enumerate()
+            .find(|(_, inst)| !matches!(inst, Instruction::Nop))?;
+        match inst {
+            Instruction::BrIf { rel_depth } => Some((pc + 1 + offset, *rel_depth)),
+            _ => None,
+        }
+    }
+
+    fn emit_trace_block(&mut self, func_index: u32, pc: usize) {
+        self.emit_helper_call_with_args(
+            trace_block as *const () as usize as u64,
+            &[HelperArg::Imm(func_index as u64), HelperArg::Imm(pc as u64)],
+        );
+    }
+
     fn find_closest_else_index(insts: &[Instruction], start: usize) -> Option<usize> {
         let end_index = Self::find_matching_end_index(insts, start);
         for (i, inst) in insts.iter().enumerate() {
@@ -276,7 +676,8 @@ impl X86JitCompiler<'_> {
     }
 
     fn emit_trap(&mut self) {
-        self.emit_jmp(self.trap_label);
+        let trap_label = self.trap_exit(TrapKind::Unreachable);
+        self.emit_jmp(trap_label);
     }
 
     fn emit_reg_reconciliation(&mut self, end_label: DestLabel) {
@@ -290,16 +691,15 @@ impl X86JitCompiler<'_> {
         for info in infos {
             self.emit_single_label(info.reconcile_start_label);
             // reconciliation
-            let branch_point_regvec = info.regalloc_snapshot.get_vec().clone();
-            let now_regvec = self.reg_allocator.get_vec().clone();
-
-            for i in 0..branch_point_regvec.len() {
-                let branch_point_reg = branch_point_regvec[branch_point_regvec.len() - 1 - i];
-                let now_reg = now_regvec[now_regvec.len() - 1 - i];
-                if branch_point_reg != now_reg {
-                    emit_mov_reg_to_reg(&mut self.jit, now_reg.reg, branch_point_reg.reg);
-                }
-            }
+            let branch_point_regvec = info.regalloc_snapshot.get_vec();
+            let now_regvec = self.reg_allocator.get_vec();
+            let moves = branch_point_regvec
+                .iter()
+                .rev()
+                .zip(now_regvec.iter().rev())
+                .map(|(branch_point_reg, now_reg)| (now_reg.reg, branch_point_reg.reg))
+                .collect::<Vec<_>>();
+            emit_parallel_moves(&mut self.jit, &moves);
 
             self.emit_jmp(end_label);
         }
@@ -0,0 +1,421 @@
+//! Exception handling in jit code. The exception in flight is kept in
+//! `X86JitCompiler::exception`: the index + 1 of its tag, 0 if there is
+//! none, followed by the values it carries.
+//!
+//! `throw` stores the exception there and jumps to the handler of the
+//! instruction: the landing pad of the innermost try block whose body it is
+//! in, or the unwind exit of the function, which returns to the caller with
+//! the exception in flight. Every call checks for an exception once it
+//! returns and goes to the handler of the call the same way, so an
+//! exception unwinds the jit frames one by one until a landing pad catches
+//! it. The checks are only emitted when the module throws at all.
+//!
+//! A landing pad dispatches on the tag to the catch clauses of its try
+//! block, or goes on to the handler around the try block if none catches
+//! the exception. The operand stack below the try block is where it was
+//! when the try block was entered: the values in it are never popped in the
+//! try block and the calls restore the registers they save before checking
+//! for an exception. A try block ending with `delegate` has no landing pad,
+//! the exceptions of its body go straight to the handler of its label.
+//!
+//! A catch clause of a function that rethrows saves the exception in the
+//! frame, at the save area of the try nesting level of its try block, where
+//! `rethrow` finds it again.
+//!
+//! An exception leaving the function an entry called is not caught by any
+//! jit code, the entry keeps it as the error of the call and returns
+//! through the trap exit of the host calls, see `setup::trap`, so the
+//! caller gets it like the interpreter's: a try block of an interpreted
+//! caller may still catch it.
+
+use anyhow::{anyhow, Result};
+use monoasm::*;
+use monoasm_macro::monoasm;
+use wasmparser::BlockType;
+
+use super::control::{WasmJitControlFlowFrame, WasmJitControlFlowType};
+use crate::{
+    jit::{
+        regalloc::{Register, X86Register, REG_TEMP, REG_TEMP2},
+        setup::trap::set_pending_error,
+        utils::emit_mov_reg_to_reg,
+        X86JitCompiler,
+    },
+    module::{
+        components::FuncDecl,
+        insts::Instruction,
+        value_type::WasmValue,
+        wasm_module::{block_type_num_params, block_type_num_results},
+    },
+    vm::WasmException,
+};
+
+/// Keep the exception in flight as the error of the call, the entry then
+/// traps through the trap exit of the host calls.
+extern "C" fn jit_uncaught_exception(compiler: *mut X86JitCompiler<'_>) {
+    let compiler = unsafe { &mut *compiler };
+    let tag_index = (compiler.exception[0] - 1) as u32;
+    compiler.exception[0] = 0;
+    let values = compiler
+        .module
+        .get_tag_sig(tag_index)
+        .map(|sig| {
+            sig.params()
+                .iter()
+                .zip(&compiler.exception[1..])
+                .map(|(ty, raw)| WasmValue::from_raw(ty, *raw))
+                .collect()
+        })
+        .unwrap_or_default();
+    set_pending_error(WasmException { tag_index, values }.into());
+}
+
+impl X86JitCompiler<'_> {
+    /// The number of try nesting levels of `fdecl` whose catch clauses save
+    /// the exception they catch, none unless the function rethrows.
+    pub(crate) fn exception_save_levels(fdecl: &FuncDecl) -> usize {
+        let insts = fdecl.get_insts();
+        if !insts
+            .iter()
+            .any(|inst| matches!(inst, Instruction::Rethrow { .. }))
+        {
+            return 0;
+        }
+
+        // whether each open block is a try block
+        let mut open = Vec::new();
+        let mut levels = 0;
+        for inst in insts {
+            match inst {
+                Instruction::Try { .. } => {
+                    open.push(true);
+                    levels = levels.max(open.iter().filter(|is_try| **is_try).count());
+                }
+                Instruction::Block { .. } | Instruction::Loop { .. } | Instruction::If { .. } => {
+                    open.push(false);
+                }
+                Instruction::End | Instruction::Delegate { .. } => {
+                    open.pop();
+                }
+                _ => {}
+            }
+        }
+        levels
+    }
+
+    /// The pc and tag of every catch clause of the try block at `start`,
+    /// `None` for `catch_all`.
+    pub(crate) fn find_catches(insts: &[Instruction], start: usize) -> Vec<(usize, Option<u32>)> {
+        let mut catches = Vec::new();
+        let mut depth = 0;
+        for (pc, inst) in insts.iter().enumerate().skip(start + 1) {
+            match inst {
+                _ if Instruction::is_control_block_start(inst) => depth += 1,
+                _ if Instruction::is_control_block_end(inst) => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                Instruction::Catch { tag_index } if depth == 0 => {
+                    catches.push((pc, Some(*tag_index)));
+                }
+                Instruction::CatchAll if depth == 0 => catches.push((pc, None)),
+                _ => {}
+            }
+        }
+        catches
+    }
+
+    /// Enter a try block whose catch clauses are bound at the labels of
+    /// `catches`, or that ends with `delegate rel_depth`.
+    pub(crate) fn emit_try(
+        &mut self,
+        ty: BlockType,
+        end_label: DestLabel,
+        catches: &[(Option<u32>, DestLabel)],
+        delegate: Option<u32>,
+    ) {
+        let num_params = block_type_num_params(&self.module, ty);
+        let num_results = block_type_num_results(&self.module, ty);
+        let expected_stack_height = self.reg_allocator.size() - num_params + num_results;
+        let handler = match delegate {
+            // the labels of the delegate are counted from outside the try
+            // block
+            Some(rel_depth) => {
+                self.handler_within(self.control_flow_stack.len() - rel_depth as usize)
+            }
+            None => {
+                let landing_pad = self.jit.label();
+                if self.throws {
+                    self.emit_landing_pad(landing_pad, catches);
+                }
+                landing_pad
+            }
+        };
+
+        let start_label = self.jit.label();
+        self.control_flow_stack.push_back(WasmJitControlFlowFrame {
+            control_type: WasmJitControlFlowType::Try { handler },
+            expected_stack_height,
+            entry_regalloc_snapshot: self.reg_allocator.clone(),
+            num_results,
+            num_params,
+            start_label,
+            end_label,
+        });
+        self.emit_single_label(start_label);
+    }
+
+    /// Bind the landing pad of a try block, the code before the try block
+    /// jumps over it. It goes to the first catch clause catching the
+    /// exception, or to the handler around the try block.
+    fn emit_landing_pad(&mut self, landing_pad: DestLabel, catches: &[(Option<u32>, DestLabel)]) {
+        let outer_handler = self.exception_handler();
+        let exception = self.exception.as_ptr() as u64;
+        let body = self.jit.label();
+        self.emit_jmp(body);
+        self.emit_single_label(landing_pad);
+        self.emit_mov_addr(REG_TEMP2, exception);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+        );
+        for &(tag_index, clause) in catches {
+            match tag_index {
+                Some(tag_index) => {
+                    monoasm!(
+                        &mut self.jit,
+                        cmpq R(REG_TEMP.as_index()), (tag_index as u64 + 1);
+                        je clause;
+                    );
+                }
+                None => self.emit_jmp(clause),
+            }
+        }
+        self.emit_jmp(outer_handler);
+        self.emit_single_label(body);
+    }
+
+    /// Start the catch clause bound at `clause` of the innermost try block,
+    /// for the exceptions of `tag_index` or any of them. The operand stack
+    /// is the one the try block started with, followed by the values of the
+    /// exception.
+    pub(crate) fn emit_catch(&mut self, tag_index: Option<u32>, clause: DestLabel) -> Result<()> {
+        let frame_index = self.control_flow_stack.len() - 1;
+        let frame = &self.control_flow_stack[frame_index];
+        let mut reg_allocator = frame.entry_regalloc_snapshot.clone();
+        for _ in 0..frame.num_params {
+            reg_allocator.pop_noopt();
+        }
+        self.reg_allocator = reg_allocator;
+
+        // the try blocks around this one
+        let level = self
+            .control_flow_stack
+            .iter()
+            .take(frame_index)
+            .filter(|frame| {
+                matches!(
+                    frame.control_type,
+                    WasmJitControlFlowType::Try { .. } | WasmJitControlFlowType::Catch { .. }
+                )
+            })
+            .count();
+        self.control_flow_stack[frame_index].control_type = WasmJitControlFlowType::Catch { level };
+
+        let exception = self.exception.as_ptr() as u64;
+        self.emit_single_label(clause);
+        self.emit_mov_addr(REG_TEMP2, exception);
+        if let Some(&save) = self.exception_saves.get(level) {
+            for i in 0..self.exception.len() {
+                let offset = (i * 8) as i32;
+                let slot = save + i * 8;
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index()) + (offset)];
+                    movq [rbp - (slot)], R(REG_TEMP.as_index());
+                );
+            }
+        }
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), (0);
+            movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+        );
+
+        let Some(tag_index) = tag_index else {
+            return Ok(());
+        };
+        let nvalues = self
+            .module
+            .get_tag_sig(tag_index)
+            .ok_or_else(|| anyhow!("catch: invalid tag index"))?
+            .params()
+            .len();
+        for i in 0..nvalues {
+            let dst = self.reg_allocator.next().reg;
+            let offset = ((i + 1) * 8) as i32;
+            monoasm!(
+                &mut self.jit,
+                movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index()) + (offset)];
+            );
+            emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
+        }
+        Ok(())
+    }
+
+    /// Throw an exception of `tag_index` with the values on top of the
+    /// operand stack.
+    pub(crate) fn emit_throw(&mut self, tag_index: u32) -> Result<()> {
+        let nvalues = self
+            .module
+            .get_tag_sig(tag_index)
+            .ok_or_else(|| anyhow!("throw: invalid tag index"))?
+            .params()
+            .len();
+        let mut values = (0..nvalues)
+            .map(|_| self.reg_allocator.pop_noopt())
+            .collect::<Vec<_>>();
+        values.reverse();
+
+        let exception = self.exception.as_ptr() as u64;
+        self.emit_mov_addr(REG_TEMP2, exception);
+        for (i, value) in values.iter().enumerate() {
+            let offset = ((i + 1) * 8) as i32;
+            emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), value.reg);
+            monoasm!(
+                &mut self.jit,
+                movq [R(REG_TEMP2.as_index()) + (offset)], R(REG_TEMP.as_index());
+            );
+        }
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), (tag_index as u64 + 1);
+            movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+        );
+        let handler = self.exception_handler();
+        self.emit_jmp(handler);
+        Ok(())
+    }
+
+    /// Throw the exception caught by the catch clause `rel_depth` blocks up
+    /// again.
+    pub(crate) fn emit_rethrow(&mut self, rel_depth: u32) -> Result<()> {
+        let frame = self
+            .control_flow_stack
+            .len()
+            .checked_sub(rel_depth as usize + 1)
+            .map(|i| &self.control_flow_stack[i]);
+        let Some(WasmJitControlFlowFrame {
+            control_type: WasmJitControlFlowType::Catch { level },
+            ..
+        }) = frame
+        else {
+            return Err(anyhow!("rethrow: target is not a catch clause"));
+        };
+        let save = self.exception_saves[*level];
+
+        let exception = self.exception.as_ptr() as u64;
+        self.emit_mov_addr(REG_TEMP2, exception);
+        for i in 0..self.exception.len() {
+            let offset = (i * 8) as i32;
+            let slot = save + i * 8;
+            monoasm!(
+                &mut self.jit,
+                movq R(REG_TEMP.as_index()), [rbp - (slot)];
+                movq [R(REG_TEMP2.as_index()) + (offset)], R(REG_TEMP.as_index());
+            );
+        }
+        let handler = self.exception_handler();
+        self.emit_jmp(handler);
+        Ok(())
+    }
+
+    /// Go to the handler of the current instruction if an exception is in
+    /// flight, right after a call. Nothing is emitted if the module never
+    /// throws.
+    pub(crate) fn emit_exception_check(&mut self) {
+        if !self.throws {
+            return;
+        }
+        let handler = self.exception_handler();
+        let exception = self.exception.as_ptr() as u64;
+        self.emit_mov_addr(REG_TEMP2, exception);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+            cmpq R(REG_TEMP.as_index()), (0);
+            jne handler;
+        );
+    }
+
+    /// Bind the unwind exit of the current function, which returns to the
+    /// caller with the exception in flight.
+    pub(crate) fn emit_unwind_exit(&mut self, stack_size: u64) {
+        if !self.throws {
+            return;
+        }
+        let unwind_label = self.unwind_label;
+        self.emit_single_label(unwind_label);
+        self.epilogue(stack_size);
+        monoasm!(
+            &mut self.jit,
+            ret;
+        );
+    }
+
+    /// Keep an exception the function called by an entry left in flight as
+    /// the error of the call and trap, right after the call.
+    pub(crate) fn emit_uncaught_exception_check(&mut self) {
+        if !self.throws {
+            return;
+        }
+        let compiler_ptr = self as *mut Self as u64;
+        let uncaught = jit_uncaught_exception as *const () as usize as u64;
+        let (host_trap_exit, _) = self.host_trap_exit;
+        let exception = self.exception.as_ptr() as u64;
+        let caught = self.jit.label();
+        self.emit_mov_addr(REG_TEMP2, exception);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+            cmpq R(REG_TEMP.as_index()), (0);
+            je caught;
+        );
+        // the stack must be 16 bytes aligned when calling into rust, the
+        // trap restores it
+        self.emit_mov_addr(X86Register::Rdi, compiler_ptr);
+        monoasm!(
+            &mut self.jit,
+            andq rsp, (-16);
+        );
+        self.emit_mov_addr(X86Register::Rax, uncaught);
+        monoasm!(
+            &mut self.jit,
+            call rax;
+            jmp host_trap_exit;
+        caught:
+        );
+    }
+
+    /// Where an exception thrown at the current instruction goes.
+    fn exception_handler(&self) -> DestLabel {
+        self.handler_within(self.control_flow_stack.len())
+    }
+
+    /// Where an exception thrown inside the outermost `depth` blocks goes:
+    /// the handler of the innermost try block among them whose body it is
+    /// in, or the unwind exit of the function.
+    fn handler_within(&self, depth: usize) -> DestLabel {
+        self.control_flow_stack
+            .iter()
+            .take(depth)
+            .rev()
+            .find_map(|frame| match frame.control_type {
+                WasmJitControlFlowType::Try { handler } => Some(handler),
+                _ => None,
+            })
+            .unwrap_or(self.unwind_label)
+    }
+}
@@ -0,0 +1,510 @@
+//! Runtime helpers called from jit code for operations that are awkward to
+//! encode inline, e.g. 64-bit division, bit counting, single precision float
+//! arithmetic and bulk memory operations. f32 operands are passed as raw bits.
+//!
+//! Helpers take their operands in REG_TEMP and REG_TEMP2 and leave the result
+//! in REG_TEMP. Memory helpers additionally get the state of the memories
+//! they access, see `emit_mem_helper_call`.
+
+use monoasm::*;
+use monoasm_macro::monoasm;
+
+use crate::{
+    jit::{
+        mem::{JitLinearMemory, JitMemoryState},
+        regalloc::{Register, X86Register, REG_TEMP, REG_TEMP2},
+        setup::{
+            data::JitDataSegment,
+            table::{JitElemSegment, JitTable},
+        },
+        utils::emit_mov_reg_to_reg,
+        X86JitCompiler,
+    },
+    module::value_type::{f32_max, f32_min, CANONICAL_NAN_F32},
+    vm::SharedLimiter,
+};
+
+pub(crate) extern "C" fn i64_div_s(a: i64, b: i64) -> i64 {
+    a.wrapping_div(b)
+}
+
+pub(crate) extern "C" fn i64_div_u(a: u64, b: u64) -> u64 {
+    a / b
+}
+
+pub(crate) extern "C" fn i64_rem_s(a: i64, b: i64) -> i64 {
+    a.wrapping_rem(b)
+}
+
+pub(crate) extern "C" fn i64_rem_u(a: u64, b: u64) -> u64 {
+    a % b
+}
+
+pub(crate) extern "C" fn i64_rotl(a: u64, b: u64) -> u64 {
+    a.rotate_left((b & 0x3f) as u32)
+}
+
+pub(crate) extern "C" fn i64_rotr(a: u64, b: u64) -> u64 {
+    a.rotate_right((b & 0x3f) as u32)
+}
+
+pub(crate) extern "C" fn i64_clz(a: u64) -> u64 {
+    a.leading_zeros() as u64
+}
+
+pub(crate) extern "C" fn i64_ctz(a: u64) -> u64 {
+    a.trailing_zeros() as u64
+}
+
+pub(crate) extern "C" fn i64_popcnt(a: u64) -> u64 {
+    a.count_ones() as u64
+}
+
+fn f32_arg(raw: u64) -> f32 {
+    f32::from_bits(raw as u32)
+}
+
+fn f32_ret(v: f32) -> u64 {
+    v.to_bits() as u64
+}
+
+pub(crate) extern "C" fn f32_ceil(a: u64) -> u64 {
+    f32_ret(f32_arg(a).ceil())
+}
+
+pub(crate) extern "C" fn f32_floor(a: u64) -> u64 {
+    f32_ret(f32_arg(a).floor())
+}
+
+pub(crate) extern "C" fn f32_trunc(a: u64) -> u64 {
+    f32_ret(f32_arg(a).trunc())
+}
+
+pub(crate) extern "C" fn f32_nearest(a: u64) -> u64 {
+    f32_ret(f32_arg(a).round_ties_even())
+}
+
+pub(crate) extern "C" fn f32_sqrt(a: u64) -> u64 {
+    f32_ret(f32_arg(a).sqrt())
+}
+
+pub(crate) extern "C" fn f32_add(a: u64, b: u64) -> u64 {
+    f32_ret(f32_arg(a) + f32_arg(b))
+}
+
+pub(crate) extern "C" fn f32_sub(a: u64, b: u64) -> u64 {
+    f32_ret(f32_arg(a) - f32_arg(b))
+}
+
+pub(crate) extern "C" fn f32_mul(a: u64, b: u64) -> u64 {
+    f32_ret(f32_arg(a) * f32_arg(b))
+}
+
+pub(crate) extern "C" fn f32_div(a: u64, b: u64) -> u64 {
+    f32_ret(f32_arg(a) / f32_arg(b))
+}
+
+pub(crate) extern "C" fn f32_min_raw(a: u64, b: u64) -> u64 {
+    f32_ret(f32_min(f32_arg(a), f32_arg(b)))
+}
+
+pub(crate) extern "C" fn f32_max_raw(a: u64, b: u64) -> u64 {
+    f32_ret(f32_max(f32_arg(a), f32_arg(b)))
+}
+
+pub(crate) extern "C" fn f32_copysign(a: u64, b: u64) -> u64 {
+    f32_ret(f32_arg(a).copysign(f32_arg(b)))
+}
+
+pub(crate) extern "C" fn f32_eq(a: u64, b: u64) -> u64 {
+    (f32_arg(a) == f32_arg(b)) as u64
+}
+
+pub(crate) extern "C" fn f32_ne(a: u64, b: u64) -> u64 {
+    (f32_arg(a) != f32_arg(b)) as u64
+}
+
+pub(crate) extern "C" fn f32_lt(a: u64, b: u64) -> u64 {
+    (f32_arg(a) < f32_arg(b)) as u64
+}
+
+pub(crate) extern "C" fn f32_gt(a: u64, b: u64) -> u64 {
+    (f32_arg(a) > f32_arg(b)) as u64
+}
+
+pub(crate) extern "C" fn f32_le(a: u64, b: u64) -> u64 {
+    (f32_arg(a) <= f32_arg(b)) as u64
+}
+
+pub(crate) extern "C" fn f32_ge(a: u64, b: u64) -> u64 {
+    (f32_arg(a) >= f32_arg(b)) as u64
+}
+
+pub(crate) extern "C" fn f32_convert_i32_s(a: u64) -> u64 {
+    f32_ret(a as i32 as f32)
+}
+
+pub(crate) extern "C" fn f32_convert_i32_u(a: u64) -> u64 {
+    f32_ret(a as u32 as f32)
+}
+
+pub(crate) extern "C" fn f32_convert_i64_s(a: u64) -> u64 {
+    f32_ret(a as i64 as f32)
+}
+
+pub(crate) extern "C" fn f32_convert_i64_u(a: u64) -> u64 {
+    f32_ret(a as f32)
+}
+
+pub(crate) extern "C" fn f32_demote_f64(a: u64) -> u64 {
+    f32_ret(f64::from_bits(a) as f32)
+}
+
+pub(crate) extern "C" fn f32_canonicalize_nan(a: u64) -> u64 {
+    if f32_arg(a).is_nan() {
+        CANONICAL_NAN_F32 as u64
+    } else {
+        a
+    }
+}
+
+pub(crate) extern "C" fn f64_promote_f32(a: u64) -> u64 {
+    f64::from(f32_arg(a)).to_bits()
+}
+
+pub(crate) extern "C" fn f64_convert_i64_u(a: u64) -> u64 {
+    (a as f64).to_bits()
+}
+
+/// The caller has already checked that the value is in range.
+pub(crate) extern "C" fn f64_trunc_to_u64(a: u64) -> u64 {
+    f64::from_bits(a) as u64
+}
+
+// rust float to int casts have the trunc_sat semantics
+pub(crate) extern "C" fn i32_trunc_sat_f32_s(a: u64) -> u64 {
+    f32_arg(a) as i32 as u32 as u64
+}
+
+pub(crate) extern "C" fn i32_trunc_sat_f32_u(a: u64) -> u64 {
+    f32_arg(a) as u32 as u64
+}
+
+pub(crate) extern "C" fn i64_trunc_sat_f32_s(a: u64) -> u64 {
+    f32_arg(a) as i64 as u64
+}
+
+pub(crate) extern "C" fn i64_trunc_sat_f32_u(a: u64) -> u64 {
+    f32_arg(a) as u64
+}
+
+pub(crate) extern "C" fn i32_trunc_sat_f64_s(a: u64) -> u64 {
+    f64::from_bits(a) as i32 as u32 as u64
+}
+
+pub(crate) extern "C" fn i32_trunc_sat_f64_u(a: u64) -> u64 {
+    f64::from_bits(a) as u32 as u64
+}
+
+pub(crate) extern "C" fn i64_trunc_sat_f64_s(a: u64) -> u64 {
+    f64::from_bits(a) as i64 as u64
+}
+
+pub(crate) extern "C" fn i64_trunc_sat_f64_u(a: u64) -> u64 {
+    f64::from_bits(a) as u64
+}
+
+/// Whether `n` bytes at `start` fit in a memory of `size` bytes.
+fn in_bounds(start: u64, n: u64, size: usize) -> bool {
+    start.checked_add(n).is_some_and(|end| end <= size as u64)
+}
+
+/// memory.copy, returns non-zero if either region is out of bounds.
+pub(crate) extern "C" fn memory_copy(
+    dst_mem: *const JitMemoryState,
+    src_mem: *const JitMemoryState,
+    dst: u64,
+    src: u64,
+    n: u64,
+) -> u64 {
+    let (dst_mem, src_mem) = unsafe { (&*dst_mem, &*src_mem) };
+    // the length is an i64 only if both memories are memory64
+    let n = if dst_mem.memory64 != 0 && src_mem.memory64 != 0 {
+        n
+    } else {
+        n as u32 as u64
+    };
+    let (dst, src) = (dst_mem.addr(dst), src_mem.addr(src));
+    if !in_bounds(src, n, src_mem.size()) || !in_bounds(dst, n, dst_mem.size()) {
+        return 1;
+    }
+    // the regions may overlap
+    let (src, dst) = (
+        (src_mem.base + src) as *const u8,
+        (dst_mem.base + dst) as *mut u8,
+    );
+    unsafe { std::ptr::copy(src, dst, n as usize) };
+    0
+}
+
+/// memory.fill, returns non-zero if the region is out of bounds.
+pub(crate) extern "C" fn memory_fill(
+    mem: *const JitMemoryState,
+    dst: u64,
+    value: u64,
+    n: u64,
+) -> u64 {
+    let mem = unsafe { &*mem };
+    let (dst, n) = (mem.addr(dst), mem.addr(n));
+    if !in_bounds(dst, n, mem.size()) {
+        return 1;
+    }
+    let dst = (mem.base + dst) as *mut u8;
+    unsafe { std::ptr::write_bytes(dst, value as u8, n as usize) };
+    0
+}
+
+/// memory.init, returns non-zero if either the segment or the memory region
+/// is out of bounds.
+pub(crate) extern "C" fn memory_init(
+    mem: *const JitMemoryState,
+    dst: u64,
+    src: u64,
+    n: u64,
+    segment: *const JitDataSegment,
+) -> u64 {
+    let (mem, segment) = unsafe { (&*mem, &*segment) };
+    let dst = mem.addr(dst);
+    let (src, n) = (src as u32 as usize, n as u32 as usize);
+    if src + n > segment.len as usize || !in_bounds(dst, n as u64, mem.size()) {
+        return 1;
+    }
+    let dst = (mem.base + dst) as *mut u8;
+    unsafe { std::ptr::copy_nonoverlapping(segment.data.add(src), dst, n) };
+    0
+}
+
+/// table.init, returns non-zero if either the segment or the table region is
+/// out of bounds.
+pub(crate) extern "C" fn table_init(
+    dst: u64,
+    src: u64,
+    n: u64,
+    segment: *const JitElemSegment,
+    table: *mut JitTable,
+) -> u64 {
+    let segment = unsafe { &*segment };
+    let slots = unsafe { &mut *table }.slots_mut();
+    let (dst, src, n) = (dst as u32 as usize, src as u32 as usize, n as u32 as usize);
+    if src + n > segment.len as usize || dst + n > slots.len() {
+        return 1;
+    }
+    slots[dst..dst + n].copy_from_slice(&segment.refs[src..src + n]);
+    0
+}
+
+/// memory.grow, returns the old size in pages or -1 if the memory cannot
+/// grow or the limiter, if not null, denies it.
+pub(crate) extern "C" fn memory_grow(
+    mem: *mut JitLinearMemory,
+    limiter: *const SharedLimiter,
+    npages: u64,
+) -> u64 {
+    let (mem, limiter) = unsafe { (&mut *mem, limiter.as_ref()) };
+    mem.grow(npages, limiter)
+}
+
+/// table.grow, returns the old size or -1 if the table cannot grow or the
+/// limiter, if not null, denies it.
+pub(crate) extern "C" fn table_grow(
+    table: *mut JitTable,
+    limiter: *const SharedLimiter,
+    init: u64,
+    n: u64,
+) -> u64 {
+    let (table, limiter) = unsafe { (&mut *table, limiter.as_ref()) };
+    match table.grow(n as u32, init as u32, limiter) {
+        Some(old_size) => old_size as u64,
+        None => -1i64 as u64,
+    }
+}
+
+/// table.fill, returns non-zero if the region is out of bounds.
+pub(crate) extern "C" fn table_fill(table: *mut JitTable, dst: u64, value: u64, n: u64) -> u64 {
+    let slots = unsafe { &mut *table }.slots_mut();
+    let (dst, n) = (dst as u32 as usize, n as u32 as usize);
+    if dst + n > slots.len() {
+        return 1;
+    }
+    slots[dst..dst + n].fill(value as u32);
+    0
+}
+
+/// An argument of a runtime helper, either held in a register or known at
+/// compile time.
+#[derive(Clone, Copy)]
+pub(crate) enum HelperArg {
+    Reg(Register),
+    Imm(u64),
+    /// the address of data the helper works on, see `emit_mov_addr`
+    Addr(u64),
+}
+
+impl X86JitCompiler<'_> {
+    /// Call `helper(REG_TEMP, REG_TEMP2)` and store the result in REG_TEMP.
+    pub(crate) fn emit_helper_call(&mut self, helper: u64) {
+        // the helper is free to clobber any caller-saved register
+        let caller_saved_regs = self.emit_save_caller_saved_regs();
+
+        // REG_TEMP2 is callee-saved, so it keeps the unaligned stack pointer
+        // across the call
+        monoasm!(
+            &mut self.jit,
+            movq rdi, R(REG_TEMP.as_index());
+            movq rsi, R(REG_TEMP2.as_index());
+            movq R(REG_TEMP2.as_index()), rsp;
+            andq rsp, (-16);
+        );
+        self.emit_mov_addr(X86Register::Rax, helper);
+        monoasm!(
+            &mut self.jit,
+            call rax;
+            movq rsp, R(REG_TEMP2.as_index());
+            movq R(REG_TEMP.as_index()), rax;
+        );
+
+        self.emit_restore_caller_saved_regs(&caller_saved_regs);
+    }
+
+    /// Call `helper(mem_states..., args...)` for helpers that access linear
+    /// memories, each memory is passed as the address of its `JitMemoryState`.
+    /// The result is stored in REG_TEMP.
+    pub(crate) fn emit_mem_helper_call(&mut self, helper: u64, mems: &[u32], args: &[HelperArg]) {
+        let mut all_args = mems
+            .iter()
+            .map(|mem| HelperArg::Addr(self.linear_mems[*mem as usize].get_state_addr()))
+            .collect::<Vec<_>>();
+        all_args.extend_from_slice(args);
+        self.emit_helper_call_with_args(helper, &all_args);
+    }
+
+    /// Call `helper(args...)`, at most six args are supported. The result is
+    /// stored in REG_TEMP.
+    pub(crate) fn emit_helper_call_with_args(&mut self, helper: u64, args: &[HelperArg]) {
+        assert!(args.len() <= 6, "too many helper arguments");
+        let caller_saved_regs = self.emit_save_caller_saved_regs();
+
+        // the args may live in the argument registers, so go through the
+        // stack to avoid clobbering them. The last one would be popped right
+        // after it is pushed, it is moved to its argument register instead
+        let last_reg_arg = args
+            .iter()
+            .rposition(|arg| matches!(arg, HelperArg::Reg(_)));
+        for (i, arg) in args.iter().enumerate() {
+            if let HelperArg::Reg(reg) = arg {
+                if Some(i) == last_reg_arg {
+                    let Register::Reg(r) = Register::from_ith_argument(i as u32) else {
+                        unreachable!("arguments are passed in registers");
+                    };
+                    emit_mov_reg_to_reg(&mut self.jit, Register::Reg(r), *reg);
+                    continue;
+                }
+                emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), *reg);
+                monoasm!(
+                    &mut self.jit,
+                    pushq R(REG_TEMP.as_index());
+                );
+            }
+        }
+        for (i, arg) in args.iter().enumerate().rev() {
+            let Register::Reg(r) = Register::from_ith_argument(i as u32) else {
+                unreachable!("arguments are passed in registers");
+            };
+            if let HelperArg::Reg(_) = arg {
+                if Some(i) == last_reg_arg {
+                    continue;
+                }
+                monoasm!(
+                    &mut self.jit,
+                    popq R(r.as_index());
+                );
+            }
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let Register::Reg(r) = Register::from_ith_argument(i as u32) else {
+                unreachable!("arguments are passed in registers");
+            };
+            match arg {
+                HelperArg::Imm(imm) => {
+                    monoasm!(
+                        &mut self.jit,
+                        movq R(r.as_index()), (*imm);
+                    );
+                }
+                HelperArg::Addr(addr) => self.emit_mov_addr(r, *addr),
+                HelperArg::Reg(_) => {}
+            }
+        }
+
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP2.as_index()), rsp;
+            andq rsp, (-16);
+        );
+        self.emit_mov_addr(X86Register::Rax, helper);
+        monoasm!(
+            &mut self.jit,
+            call rax;
+            movq rsp, R(REG_TEMP2.as_index());
+            movq R(REG_TEMP.as_index()), rax;
+        );
+
+        self.emit_restore_caller_saved_regs(&caller_saved_regs);
+    }
+
+    fn emit_save_caller_saved_regs(&mut self) -> Vec<Register> {
+        let caller_saved_regs = self.reg_allocator.get_used_caller_saved_registers();
+
+        for reg in &caller_saved_regs {
+            match reg {
+                Register::Reg(r) => {
+                    monoasm!(
+                        &mut self.jit,
+                        pushq R(r.as_index());
+                    );
+                }
+                Register::FpReg(r) => {
+                    monoasm!(
+                        &mut self.jit,
+                        movq R(REG_TEMP2.as_index()), xmm(r.as_index());
+                        pushq R(REG_TEMP2.as_index());
+                    );
+                }
+                Register::Stack(_) => panic!("stack should not be caller saved"),
+            }
+        }
+
+        caller_saved_regs
+    }
+
+    fn emit_restore_caller_saved_regs(&mut self, caller_saved_regs: &[Register]) {
+        for reg in caller_saved_regs.iter().rev() {
+            match reg {
+                Register::Reg(r) => {
+                    monoasm!(
+                        &mut self.jit,
+                        popq R(r.as_index());
+                    );
+                }
+                Register::FpReg(r) => {
+                    monoasm!(
+                        &mut self.jit,
+                        popq R(REG_TEMP2.as_index());
+                        movq xmm(r.as_index()), R(REG_TEMP2.as_index());
+                    );
+                }
+                Register::Stack(_) => panic!("stack should not be caller saved"),
+            }
+        }
+    }
+}
@@ -1,10 +1,17 @@
+use anyhow::{anyhow, Result};
+
+use super::helpers::{self, HelperArg};
 use crate::jit::{
+    mem::JitMemoryState,
     regalloc::{
         Register, X86Register, REG_LOCAL_BASE, REG_MEMORY_BASE, REG_TEMP, REG_TEMP2, REG_TEMP_FP,
     },
+    setup::{data::JitDataSegment, trap::TrapSite},
     utils::emit_mov_reg_to_reg,
-    ValueType, X86JitCompiler,
+    JitLinearMemory, ValueType, X86JitCompiler,
 };
+use crate::module::insts::MemArg;
+use crate::vm::{TrapKind, WASM_DEFAULT_PAGE_SIZE_BYTE};
 
 use monoasm::*;
 use monoasm_macro::monoasm;
@@ -18,93 +25,201 @@ impl X86JitCompiler<'_> {
     ) {
         let ty = local_types[local_idx as usize];
         let offset = local_idx * 8;
-        match ty {
-            ValueType::I32 => {
+        // load straight into the destination when it is a register
+        match (ty, dst) {
+            (
+                ValueType::I32 | ValueType::I64 | ValueType::F32 | ValueType::Ref,
+                Register::Reg(r),
+            ) => {
                 monoasm!(
                     &mut self.jit,
-                    movq R(REG_TEMP.as_index()), R(REG_LOCAL_BASE.as_index()); // reg_temp = reg_local_base
-                    movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) - (offset)];
+                    movq R(r.as_index()), [R(REG_LOCAL_BASE.as_index()) - (offset)];
                 );
-                emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
             }
-            ValueType::F64 => {
+            (ValueType::I32 | ValueType::I64 | ValueType::F32 | ValueType::Ref, _) => {
                 monoasm!(
                     &mut self.jit,
-                    movq R(REG_TEMP.as_index()), R(REG_LOCAL_BASE.as_index()); // reg_temp = reg_local_base
-                    movq xmm(REG_TEMP_FP.as_index()), [R(REG_TEMP.as_index()) - (offset)];
+                    movq R(REG_TEMP.as_index()), [R(REG_LOCAL_BASE.as_index()) - (offset)];
                 );
-                emit_mov_reg_to_reg(&mut self.jit, dst, Register::FpReg(REG_TEMP_FP));
+                emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
             }
-        }
-    }
-
-    pub(crate) fn emit_local_set(&mut self, value: Register, local_idx: u32, ty: ValueType) {
-        let offset = local_idx * 8;
-        match ty {
-            ValueType::I32 => {
-                emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), value);
+            (ValueType::F64, Register::FpReg(r)) => {
                 monoasm!(
                     &mut self.jit,
-                    movq [R(REG_LOCAL_BASE.as_index()) - (offset)], R(REG_TEMP2.as_index());
+                    movq xmm(r.as_index()), [R(REG_LOCAL_BASE.as_index()) - (offset)];
                 );
             }
-            ValueType::F64 => {
-                emit_mov_reg_to_reg(&mut self.jit, Register::FpReg(REG_TEMP_FP), value);
+            (ValueType::F64, _) => {
                 monoasm!(
                     &mut self.jit,
-                    movsd [R(REG_LOCAL_BASE.as_index()) - (offset)], xmm(REG_TEMP_FP.as_index());
+                    movq xmm(REG_TEMP_FP.as_index()), [R(REG_LOCAL_BASE.as_index()) - (offset)];
                 );
+                emit_mov_reg_to_reg(&mut self.jit, dst, Register::FpReg(REG_TEMP_FP));
             }
         }
     }
 
+    pub(crate) fn emit_local_set(&mut self, value: Register, local_idx: u32, ty: ValueType) {
+        let offset = local_idx * 8;
+        // store straight from the source when it is a register
+        let src = match (value, ty) {
+            (Register::Reg(_) | Register::FpReg(_), _) => value,
+            (Register::Stack(_), ValueType::F64) => {
+                emit_mov_reg_to_reg(&mut self.jit, Register::FpReg(REG_TEMP_FP), value);
+                Register::FpReg(REG_TEMP_FP)
+            }
+            (Register::Stack(_), _) => {
+                emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), value);
+                Register::Reg(REG_TEMP2)
+            }
+        };
+        self.emit_store_local(offset, src);
+    }
+
     pub(crate) fn emit_local_tee(&mut self, top_of_stack: Register, local_idx: u32, ty: ValueType) {
         let offset = local_idx * 8;
-        match ty {
-            ValueType::I32 => {
+        // store straight from the source when it is a register
+        let src = match (top_of_stack, ty) {
+            (Register::Reg(_) | Register::FpReg(_), _) => top_of_stack,
+            (Register::Stack(_), ValueType::F64) => {
+                emit_mov_reg_to_reg(&mut self.jit, Register::FpReg(REG_TEMP_FP), top_of_stack);
+                Register::FpReg(REG_TEMP_FP)
+            }
+            (Register::Stack(_), _) => {
                 emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), top_of_stack);
+                Register::Reg(REG_TEMP2)
+            }
+        };
+        self.emit_store_local(offset, src);
+    }
+
+    /// Store a register to the local at `offset` below the locals base.
+    fn emit_store_local(&mut self, offset: u32, src: Register) {
+        match src {
+            Register::Reg(r) => {
                 monoasm!(
                     &mut self.jit,
-                    movq [R(REG_LOCAL_BASE.as_index()) - (offset)], R(REG_TEMP2.as_index());
+                    movq [R(REG_LOCAL_BASE.as_index()) - (offset)], R(r.as_index());
                 );
             }
-            ValueType::F64 => {
-                emit_mov_reg_to_reg(&mut self.jit, Register::FpReg(REG_TEMP_FP), top_of_stack);
+            Register::FpReg(r) => {
                 monoasm!(
                     &mut self.jit,
-                    movsd [R(REG_LOCAL_BASE.as_index()) - (offset)], xmm(REG_TEMP_FP.as_index());
+                    movsd [R(REG_LOCAL_BASE.as_index()) - (offset)], xmm(r.as_index());
                 );
             }
+            Register::Stack(_) => unreachable!("locals are stored from registers"),
         }
     }
 
-    pub(crate) fn emit_memory_grow(&mut self, dst: Register, npages: Register) {
-        self.linear_mem.grow(&mut self.jit, Some(dst), npages);
+    pub(crate) fn emit_memory_grow(&mut self, dst: Register, npages: Register, mem: u32) {
+        let linear_mem = &self.linear_mems[mem as usize] as *const JitLinearMemory as u64;
+        let limiter = self.limiter_addr();
+        self.emit_helper_call_with_args(
+            helpers::memory_grow as *const () as usize as u64,
+            &[
+                HelperArg::Addr(linear_mem),
+                HelperArg::Addr(limiter),
+                HelperArg::Reg(npages),
+            ],
+        );
+        // a memory64 memory moves if it grows past its reservation
+        if mem == 0 {
+            self.emit_load_memory_base(0, REG_MEMORY_BASE);
+        }
+        emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
     }
 
-    pub(crate) fn emit_load_mem(
+    pub(crate) fn emit_memory_copy(
         &mut self,
         dst: Register,
-        base: Register,
-        offset: u32,
-        width: u32,
-        sign_extend: bool,
+        src: Register,
+        n: Register,
+        dst_mem: u32,
+        src_mem: u32,
     ) {
-        // if base is negative, we need to trap
-        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), base);
-        let trap_label = self.trap_label;
+        self.emit_mem_helper_call(
+            helpers::memory_copy as *const () as usize as u64,
+            &[dst_mem, src_mem],
+            &[HelperArg::Reg(dst), HelperArg::Reg(src), HelperArg::Reg(n)],
+        );
+        self.emit_trap_if_helper_failed(TrapKind::OutOfBoundsMemory);
+    }
+
+    pub(crate) fn emit_memory_fill(
+        &mut self,
+        dst: Register,
+        value: Register,
+        n: Register,
+        mem: u32,
+    ) {
+        self.emit_mem_helper_call(
+            helpers::memory_fill as *const () as usize as u64,
+            &[mem],
+            &[
+                HelperArg::Reg(dst),
+                HelperArg::Reg(value),
+                HelperArg::Reg(n),
+            ],
+        );
+        self.emit_trap_if_helper_failed(TrapKind::OutOfBoundsMemory);
+    }
+
+    pub(crate) fn emit_memory_init(
+        &mut self,
+        dst: Register,
+        src: Register,
+        n: Register,
+        data_index: u32,
+        mem: u32,
+    ) {
+        let segment = &self.data_segments[data_index as usize] as *const JitDataSegment as u64;
+        self.emit_mem_helper_call(
+            helpers::memory_init as *const () as usize as u64,
+            &[mem],
+            &[
+                HelperArg::Reg(dst),
+                HelperArg::Reg(src),
+                HelperArg::Reg(n),
+                HelperArg::Addr(segment),
+            ],
+        );
+        self.emit_trap_if_helper_failed(TrapKind::OutOfBoundsMemory);
+    }
+
+    /// a dropped segment is emptied, so later memory.init only succeed with
+    /// a zero length
+    pub(crate) fn emit_data_drop(&mut self, data_index: u32) {
+        let len_addr = &self.data_segments[data_index as usize].len as *const u64 as u64;
+        self.emit_mov_addr(REG_TEMP, len_addr);
         monoasm!(
             &mut self.jit,
-            cmpq R(REG_TEMP.as_index()), (0);
-            jlt trap_label;
+            movq R(REG_TEMP2.as_index()), (0);
+            movq [R(REG_TEMP.as_index())], R(REG_TEMP2.as_index());
         );
+    }
 
-        // read the start memory address
-        self.get_effective_address(REG_TEMP, base, offset); // REG_TEMP stores the effective address
+    /// memory and table helpers return non-zero in REG_TEMP on out of bounds
+    /// access, which traps with `kind`
+    pub(crate) fn emit_trap_if_helper_failed(&mut self, kind: TrapKind) {
+        let trap_label = self.trap_exit(kind);
         monoasm!(
             &mut self.jit,
-            addq R(REG_TEMP.as_index()), R(REG_MEMORY_BASE.as_index()); // <-- reg_temp = reg_memory_base + effective_addr
+            cmpq R(REG_TEMP.as_index()), (0);
+            jne trap_label;
         );
+    }
+
+    pub(crate) fn emit_load_mem(
+        &mut self,
+        dst: Register,
+        base: Register,
+        memarg: &MemArg,
+        width: u32,
+        sign_extend: bool,
+    ) -> Result<()> {
+        // read the start memory address
+        self.emit_effective_address(base, memarg, width)?; // <-- reg_temp = memory_base + effective_addr
 
         // clear the temp2 register, it will store the result
         monoasm!(
@@ -113,6 +228,7 @@ impl X86JitCompiler<'_> {
         );
 
         // actual load
+        self.emit_access_site();
         match width {
             8 => {
                 monoasm!(
@@ -160,25 +276,24 @@ impl X86JitCompiler<'_> {
         }
 
         emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP2));
+        Ok(())
     }
 
     pub(crate) fn emit_store_mem(
         &mut self,
         base: Register,
-        offset: u32,
+        memarg: &MemArg,
         value: Register,
         width: u32,
-    ) {
-        self.get_effective_address(REG_TEMP, base, offset); // reg_temp = effective_addr
+    ) -> Result<()> {
+        // 1. reg_temp = memory_base + effective_addr
+        self.emit_effective_address(base, memarg, width)?;
 
         // 2. store the value to dst
-        monoasm!(
-            &mut self.jit,
-            addq R(REG_TEMP.as_index()), R(REG_MEMORY_BASE.as_index()); // <-- reg_temp = reg_memory_base + effective_addr
-        );
 
         emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), value); // <-- reg_temp = value
 
+        self.emit_access_site();
         match width {
             8 => {
                 monoasm!(
@@ -206,13 +321,55 @@ impl X86JitCompiler<'_> {
             }
             _ => unreachable!("invalid width: {}", width),
         }
+        Ok(())
+    }
+
+    /// Record the next instruction as a memory access, which faults in the
+    /// reservation past the memory when it is out of bounds.
+    fn emit_access_site(&mut self) {
+        let access_label = self.jit.label();
+        self.emit_single_label(access_label);
+        self.trap_sites
+            .push((access_label, TrapSite::Wasm(TrapKind::OutOfBoundsMemory)));
+    }
+
+    /// dst += the base address of memory `mem`, the base of memory 0 lives
+    /// in REG_MEMORY_BASE and the others are loaded through REG_TEMP2
+    fn emit_add_memory_base(&mut self, dst: X86Register, mem: u32) -> Result<()> {
+        if mem == 0 {
+            monoasm!(
+                &mut self.jit,
+                addq R(dst.as_index()), R(REG_MEMORY_BASE.as_index());
+            );
+            return Ok(());
+        }
+
+        if mem as usize >= self.linear_mems.len() {
+            return Err(anyhow!("invalid memory index: {}", mem));
+        }
+        self.emit_load_memory_base(mem, REG_TEMP2);
+        monoasm!(
+            &mut self.jit,
+            addq R(dst.as_index()), R(REG_TEMP2.as_index());
+        );
+        Ok(())
+    }
+
+    /// Load the base address of memory `mem` into dst.
+    pub(crate) fn emit_load_memory_base(&mut self, mem: u32, dst: X86Register) {
+        let state_addr = self.linear_mems[mem as usize].get_state_addr();
+        self.emit_mov_addr(dst, state_addr);
+        monoasm!(
+            &mut self.jit,
+            movq R(dst.as_index()), [R(dst.as_index()) + (JitMemoryState::BASE_OFFSET)];
+        );
     }
 
     pub(crate) fn emit_global_get(&mut self, dst: Register, global_idx: u32) {
         let global_addr = self.globals.as_ptr() as u64 + (global_idx * 8) as u64;
+        self.emit_mov_addr(REG_TEMP, global_addr);
         monoasm!(
             &mut self.jit,
-            movq R(REG_TEMP.as_index()), (global_addr);
             movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index())];
         );
         emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
@@ -221,23 +378,96 @@ impl X86JitCompiler<'_> {
     pub(crate) fn emit_global_set(&mut self, value: Register, global_idx: u32) {
         let global_addr = self.globals.as_ptr() as u64 + (global_idx * 8) as u64;
         emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), value);
+        self.emit_mov_addr(REG_TEMP2, global_addr);
         monoasm!(
             &mut self.jit,
-            movq R(REG_TEMP2.as_index()), (global_addr);
             movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
         );
     }
 
-    pub(crate) fn store_mem_page_size(&mut self, dst: Register) {
-        self.linear_mem.read_memory_size_in_page(&mut self.jit, dst);
+    pub(crate) fn store_mem_page_size(&mut self, dst: Register, mem: u32) {
+        let mem_size_addr = self.linear_mems[mem as usize].get_mem_size_addr();
+        self.emit_mov_addr(REG_TEMP, mem_size_addr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index())];
+        );
+        emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
     }
 
-    /// REG_TEMP will store the effective address + width
-    fn get_effective_address(&mut self, dst: X86Register, base: Register, offset: u32) {
-        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(dst), base); // <-- reg_temp2 = base
+    /// REG_TEMP = the host address of an access of `width` bytes at
+    /// `base + memarg.offset`, trapping if it is out of bounds.
+    ///
+    /// A memory32 address is zero extended and its offset is below 4GiB, so
+    /// an access ends below 8GiB, within the PROT_NONE reservation past the
+    /// accessible pages: out of bounds accesses fault there. A memory64
+    /// address is not bounded by the reservation and is checked against the
+    /// size of the memory instead.
+    fn emit_effective_address(
+        &mut self,
+        base: Register,
+        memarg: &MemArg,
+        width: u32,
+    ) -> Result<()> {
+        let memory64 = self
+            .module
+            .get_memories()
+            .get(memarg.memory as usize)
+            .ok_or_else(|| anyhow!("invalid memory index: {}", memarg.memory))?
+            .memory64;
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), base);
+
+        if !memory64 {
+            if memarg.offset > u32::MAX as u64 {
+                return Err(anyhow!("memarg offset out of range: {}", memarg.offset));
+            }
+            // the i32 address may have its upper half set
+            monoasm!(
+                &mut self.jit,
+                movl R(REG_TEMP.as_index()), R(REG_TEMP.as_index());
+            );
+            self.emit_add_offset(memarg.offset);
+            return self.emit_add_memory_base(REG_TEMP, memarg.memory);
+        }
+
+        // trap if base + offset + width overflows or is past the size
+        let trap_label = self.trap_exit(TrapKind::OutOfBoundsMemory);
+        let mem_size_addr = self.linear_mems[memarg.memory as usize].get_mem_size_addr();
+        let page_shift = WASM_DEFAULT_PAGE_SIZE_BYTE.trailing_zeros() as u8;
+        self.emit_add_offset(memarg.offset);
         monoasm!(
             &mut self.jit,
-            addq R(dst.as_index()), (offset);
+            jb trap_label;
+            addq R(REG_TEMP.as_index()), (width as i32);
+            jb trap_label;
         );
+        self.emit_mov_addr(REG_TEMP2, mem_size_addr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP2.as_index()), [R(REG_TEMP2.as_index())];
+            shlq R(REG_TEMP2.as_index()), (page_shift); // the size in bytes
+            cmpq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+            ja trap_label;
+            subq R(REG_TEMP.as_index()), (width as i32);
+        );
+        self.emit_add_memory_base(REG_TEMP, memarg.memory)
+    }
+
+    /// REG_TEMP += offset, the carry flag is set if it overflows.
+    fn emit_add_offset(&mut self, offset: u64) {
+        // the immediate of addq is sign extended, larger offsets go through
+        // REG_TEMP2, which is free at this point
+        if offset <= i32::MAX as u64 {
+            monoasm!(
+                &mut self.jit,
+                addq R(REG_TEMP.as_index()), (offset as i32);
+            );
+        } else {
+            monoasm!(
+                &mut self.jit,
+                movq R(REG_TEMP2.as_index()), (offset);
+                addq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index());
+            );
+        }
     }
 }
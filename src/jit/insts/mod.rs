@@ -1,6 +1,9 @@
 mod arith;
 mod control;
 mod emit;
+mod exception;
+mod helpers;
 mod mem;
+mod table;
 
 pub(crate) use control::{RegReconcileInfo, WasmJitControlFlowFrame, WasmJitControlFlowType};
@@ -0,0 +1,160 @@
+use monoasm::*;
+use monoasm_macro::monoasm;
+
+use super::helpers::{self, HelperArg};
+use crate::jit::{
+    regalloc::{Register, REG_TEMP, REG_TEMP2},
+    setup::table::{JitElemSegment, JitTable},
+    utils::emit_mov_reg_to_reg,
+    X86JitCompiler,
+};
+use crate::vm::TrapKind;
+
+impl X86JitCompiler<'_> {
+    fn table_addr(&self, table_index: u32) -> u64 {
+        self.tables.get(table_index as usize).unwrap() as *const JitTable as u64
+    }
+
+    /// Bounds check the element index and leave the address of the table
+    /// slot in REG_TEMP2.
+    fn emit_table_slot_addr(&mut self, index: Register, table_index: u32) {
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), index);
+
+        let table = self.table_addr(table_index);
+        let trap_label = self.trap_exit(TrapKind::OutOfBoundsTable);
+        self.emit_mov_addr(REG_TEMP, table);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + (JitTable::LEN_OFFSET)];
+            cmpq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+            jge trap_label;
+            cmpq R(REG_TEMP2.as_index()), 0;
+            js trap_label; // negative index
+
+            // slots are 4 bytes wide
+            addq R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index());
+            addq R(REG_TEMP2.as_index()), R(REG_TEMP2.as_index());
+        );
+        self.emit_mov_addr(REG_TEMP, table);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + (JitTable::DATA_OFFSET)];
+            addq R(REG_TEMP2.as_index()), R(REG_TEMP.as_index());
+        );
+    }
+
+    pub(crate) fn emit_table_get(&mut self, dst: Register, index: Register, table_index: u32) {
+        self.emit_table_slot_addr(index, table_index);
+        monoasm!(
+            &mut self.jit,
+            movl R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index())];
+        );
+        emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
+    }
+
+    pub(crate) fn emit_table_set(&mut self, index: Register, value: Register, table_index: u32) {
+        self.emit_table_slot_addr(index, table_index);
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP), value);
+        monoasm!(
+            &mut self.jit,
+            movl [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
+        );
+    }
+
+    pub(crate) fn emit_table_init(
+        &mut self,
+        dst: Register,
+        src: Register,
+        n: Register,
+        elem_index: u32,
+        table_index: u32,
+    ) {
+        let segment = &self.elem_segments[elem_index as usize] as *const JitElemSegment as u64;
+        let table = self.table_addr(table_index);
+        self.emit_helper_call_with_args(
+            helpers::table_init as *const () as usize as u64,
+            &[
+                HelperArg::Reg(dst),
+                HelperArg::Reg(src),
+                HelperArg::Reg(n),
+                HelperArg::Addr(segment),
+                HelperArg::Addr(table),
+            ],
+        );
+        self.emit_trap_if_helper_failed(TrapKind::OutOfBoundsTable);
+    }
+
+    /// a dropped segment is emptied, so later table.init only succeed with a
+    /// zero length
+    pub(crate) fn emit_elem_drop(&mut self, elem_index: u32) {
+        let len_addr = &self.elem_segments[elem_index as usize].len as *const u64 as u64;
+        self.emit_mov_addr(REG_TEMP, len_addr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP2.as_index()), (0);
+            movq [R(REG_TEMP.as_index())], R(REG_TEMP2.as_index());
+        );
+    }
+
+    /// the result is the old size, or -1 if the table cannot grow
+    pub(crate) fn emit_table_grow(
+        &mut self,
+        dst: Register,
+        init: Register,
+        n: Register,
+        table_index: u32,
+    ) {
+        let table = self.table_addr(table_index);
+        self.emit_helper_call_with_args(
+            helpers::table_grow as *const () as usize as u64,
+            &[
+                HelperArg::Addr(table),
+                HelperArg::Addr(self.limiter_addr()),
+                HelperArg::Reg(init),
+                HelperArg::Reg(n),
+            ],
+        );
+        emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
+    }
+
+    pub(crate) fn emit_table_size(&mut self, dst: Register, table_index: u32) {
+        let table = self.table_addr(table_index);
+        self.emit_mov_addr(REG_TEMP, table);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index()) + (JitTable::LEN_OFFSET)];
+        );
+        emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
+    }
+
+    pub(crate) fn emit_table_fill(
+        &mut self,
+        dst: Register,
+        value: Register,
+        n: Register,
+        table_index: u32,
+    ) {
+        let table = self.table_addr(table_index);
+        self.emit_helper_call_with_args(
+            helpers::table_fill as *const () as usize as u64,
+            &[
+                HelperArg::Addr(table),
+                HelperArg::Reg(dst),
+                HelperArg::Reg(value),
+                HelperArg::Reg(n),
+            ],
+        );
+        self.emit_trap_if_helper_failed(TrapKind::OutOfBoundsTable);
+    }
+
+    pub(crate) fn emit_ref_is_null(&mut self, dst: Register, value: Register) {
+        emit_mov_reg_to_reg(&mut self.jit, Register::Reg(REG_TEMP2), value);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP.as_index()), (0);
+            cmpq R(REG_TEMP2.as_index()), (0);
+            seteq R(REG_TEMP.as_index());
+        );
+        emit_mov_reg_to_reg(&mut self.jit, dst, Register::Reg(REG_TEMP));
+    }
+}
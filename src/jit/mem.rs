@@ -1,148 +1,119 @@
-use monoasm::{Disp, Imm, JitMemory, Reg, Rm, Scale};
-use monoasm_macro::monoasm;
+use crate::vm::{Memory, SharedLimiter, WASM_DEFAULT_PAGE_SIZE_BYTE};
+
+/// A linear memory as seen by the jit code and the memory helpers, loaded
+/// from the store, see `JitLinearMemory::load`.
+#[repr(C)]
+pub(crate) struct JitMemoryState {
+    pub(crate) base: u64,
+    pub(crate) size_in_page: u64,
+    pub(crate) memory64: u64,
+}
+
+impl JitMemoryState {
+    pub(crate) const BASE_OFFSET: i32 = 0;
 
-use crate::{
-    jit::{
-        regalloc::{REG_MEMORY_BASE, REG_TEMP, REG_TEMP2},
-        utils::emit_mov_reg_to_reg,
-    },
-    vm::WASM_DEFAULT_PAGE_SIZE_BYTE,
-};
+    /// The state of a memory that is not loaded yet.
+    pub(crate) fn unmapped(memory64: bool) -> Self {
+        Self {
+            base: 0,
+            size_in_page: 0,
+            memory64: memory64 as u64,
+        }
+    }
+
+    /// The size in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.size_in_page as usize * WASM_DEFAULT_PAGE_SIZE_BYTE
+    }
 
-use super::regalloc::Register;
+    /// Interpret a raw address operand according to the index type.
+    pub(crate) fn addr(&self, raw: u64) -> u64 {
+        if self.memory64 != 0 {
+            raw
+        } else {
+            raw as u32 as u64
+        }
+    }
+}
 
+/// A memory of the store, the jit code accesses it in place. Its base and
+/// size are read again whenever it may have grown or moved: on the entry
+/// into the jit code, after a host call and after `memory.grow`.
 pub struct JitLinearMemory {
-    size_mem_in_page: Box<u64>,
+    state: JitMemoryState,
     mem_limit: u64,
+    /// `None` for the memory 0 of a module without memories
+    mem: Option<Memory>,
 }
 
 impl JitLinearMemory {
-    pub fn new(mem_limit: u64) -> Self {
-        Self {
-            size_mem_in_page: Box::new(0),
+    pub(crate) fn new(mem: Option<Memory>, mem_limit: u64, memory64: bool) -> Self {
+        let mut mem = Self {
+            state: JitMemoryState::unmapped(memory64),
             mem_limit,
-        }
+            mem,
+        };
+        mem.load();
+        mem
     }
 
-    pub fn init_size(&mut self, jit: &mut JitMemory, initial_mem_size_in_byte: u64) {
-        // mmap a 32G region and store in the REG_MEMORY_BASE
-        let mem_size_limit: u64 = 32 * 1024 * 1024 * 1024;
-        monoasm!(
-            &mut *jit,
-            xorq rdi, rdi; // addr
-            movq rsi, (mem_size_limit); // size
-            movq rdx, 0; // PROT_NONE
-            movq r10, 0x22; // MAP_PRIVATE | MAP_ANONYMOUS
-            movq r8, 0xFFFFFFFFFFFFFFFF; // -1, no fd
-            xorq r9, r9; // offset
-            movq rax, 9; // mmap
-            syscall; // mmap, rax has the pointer to the memory
-            movq R(REG_MEMORY_BASE.as_index()), rax;
-        );
-
-        let npages = initial_mem_size_in_byte.div_ceil(WASM_DEFAULT_PAGE_SIZE_BYTE as u64);
-
-        monoasm!(
-            &mut *jit,
-            movq R(REG_TEMP.as_index()), (npages);
-        );
-
-        self.grow(jit, None, Register::Reg(REG_TEMP));
+    /// Let `memory.grow` grow the memory up to `mem_limit` pages.
+    pub(crate) fn set_mem_limit(&mut self, mem_limit: u64) {
+        self.mem_limit = mem_limit;
     }
 
-    /// Put the old size in dst and grow the memory
-    pub fn grow(&mut self, jit: &mut JitMemory, dst: Option<Register>, npages: Register) {
-        let invalid_npage = jit.label();
-        let end = jit.label();
+    /// The pages `memory.grow` can grow the memory to.
+    pub(crate) fn mem_limit(&self) -> u64 {
+        self.mem_limit
+    }
 
-        // get the old size
-        emit_mov_reg_to_reg(jit, Register::Reg(REG_TEMP2), npages); // reg_temp2 = npages
-        monoasm!(
-            &mut *jit,
-            cmpq R(REG_TEMP2.as_index()), (0);
-            jlt invalid_npage;
-        );
+    /// Whether the accesses past the memory fault, see
+    /// `X86JitCompiler::emit_effective_address`. Memory64 accesses are
+    /// checked against the size instead.
+    pub(crate) fn is_guarded(&self) -> bool {
+        self.state.memory64 != 0
+            || self
+                .mem
+                .as_ref()
+                .is_none_or(|mem| mem.borrow().data.guards_memory32())
+    }
 
-        self.read_memory_size_in_page(jit, Register::Reg(REG_TEMP)); // reg_temp = old_size
-        if let Some(dst) = dst {
-            emit_mov_reg_to_reg(jit, dst, Register::Reg(REG_TEMP));
+    /// Read where the memory is and its size from the store.
+    pub(crate) fn load(&mut self) {
+        if let Some(mem) = &self.mem {
+            let mut mem = mem.borrow_mut();
+            self.state.base = mem.data.as_mut_ptr() as u64;
+            self.state.size_in_page = (mem.size() / WASM_DEFAULT_PAGE_SIZE_BYTE) as u64;
         }
+    }
 
-        // add the old size and npages
-        monoasm!(
-            &mut *jit,
-            addq R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // reg_temp = new_size_in_pages
-        );
-
-        // if old_size + npages > mem_limit or npages < 0, return -1
-        monoasm!(
-            &mut *jit,
-            cmpq R(REG_TEMP.as_index()), (self.mem_limit);
-            jgt invalid_npage;
-        );
-
-        // store the new size to memory
-        monoasm!(
-            &mut *jit,
-            movq R(REG_TEMP2.as_index()), (self.get_mem_size_addr());
-            movq [R(REG_TEMP2.as_index())], R(REG_TEMP.as_index());
-        );
-
-        // calculate the new size in bytes
-        monoasm!(
-            &mut *jit,
-            movq R(REG_TEMP2.as_index()), (WASM_DEFAULT_PAGE_SIZE_BYTE as u64);
-            imul R(REG_TEMP.as_index()), R(REG_TEMP2.as_index()); // reg_temp = new_size_in_bytes
-        );
-
-        // grow the memory using mprotect
-        monoasm!(
-            &mut *jit,
-            pushq rdi;
-            pushq rsi;
-            pushq rdx;
-            pushq rax;
-
-            movq rdi, R(REG_MEMORY_BASE.as_index()); // rdi = reg_memory_base
-            movq rsi, R(REG_TEMP.as_index()); // rsi = new_size_in_bytes
-            movq rdx, 0x3; // rdx = PROT_READ | PROT_WRITE
-            movq rax, 10; // rax = mprotect
-            syscall; // mprotect
-
-            popq rax;
-            popq rdx;
-            popq rsi;
-            popq rdi;
-
-            jmp end;
-        );
-
-        // invalid input, store -1 to dst
-        monoasm!(
-            &mut *jit,
-        invalid_npage:
-            movq R(REG_TEMP.as_index()), (-1);
-        );
-        if let Some(dst) = dst {
-            emit_mov_reg_to_reg(jit, dst, Register::Reg(REG_TEMP));
-        }
-        monoasm!(
-            &mut *jit,
-            end:
-        );
+    /// memory.grow, returns the old size in pages or -1 if the memory would
+    /// grow past its limit, `limiter` denies it or the pages cannot be
+    /// mapped.
+    pub(crate) fn grow(&mut self, npages: u64, limiter: Option<&SharedLimiter>) -> u64 {
+        let Some(mem) = &self.mem else {
+            return -1i64 as u64;
+        };
+        let npages = self.state.addr(npages);
+        let old_pages = mem.size_in_pages();
+        let max = mem.maximum();
+        let allowed = |new_pages| {
+            new_pages <= self.mem_limit
+                && limiter.is_none_or(|limiter| limiter.memory_growing(old_pages, new_pages, max))
+        };
+        let result = match old_pages.checked_add(npages) {
+            Some(new_pages) if allowed(new_pages) && mem.borrow_mut().grow(npages) => old_pages,
+            _ => -1i64 as u64,
+        };
+        self.load();
+        result
     }
 
-    pub fn read_memory_size_in_page(&self, jit: &mut JitMemory, dst: Register) {
-        let mem_size_addr = self.get_mem_size_addr();
-        monoasm!(
-            &mut *jit,
-            movq R(REG_TEMP.as_index()), (mem_size_addr);
-            movq R(REG_TEMP.as_index()), [R(REG_TEMP.as_index())];
-        );
-        emit_mov_reg_to_reg(jit, dst, Register::Reg(REG_TEMP));
+    pub(crate) fn get_mem_size_addr(&self) -> u64 {
+        &self.state.size_in_page as *const u64 as u64
     }
 
-    fn get_mem_size_addr(&self) -> u64 {
-        Box::<u64>::as_ptr(&self.size_mem_in_page) as u64
+    pub(crate) fn get_state_addr(&self) -> u64 {
+        &self.state as *const JitMemoryState as u64
     }
 }
@@ -1,27 +1,70 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use monoasm::*;
+use wasmparser::ValType;
 
 use crate::module::value_type::WasmValue;
 
 pub use compiler::X86JitCompiler;
+#[cfg(feature = "cranelift")]
+pub use cranelift::CraneliftJitCompiler;
 pub use mem::JitLinearMemory;
+pub use rv64::Rv64JitCompiler;
+pub(crate) use settings::JitSettings;
+pub(crate) use setup::tier::{OsrEntry, TierEntry};
 pub use setup::trap::register_trap_handler;
+pub(crate) use setup::trap::{call_guarded, TrapFrame};
 
-pub type ReturnFunc = extern "C" fn() -> u64;
-
+mod backtrace;
 mod compiler;
+#[cfg(feature = "cranelift")]
+mod cranelift;
+mod fold;
+mod gdb;
 mod insts;
 mod mem;
+mod perf_map;
 mod regalloc;
+mod runtime;
+mod rv64;
+mod settings;
 mod setup;
 mod utils;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum ValueType {
     I32,
+    I64,
+    F32,
     F64,
+    /// funcref and externref, kept as `index + 1` in general purpose
+    /// registers with 0 being the null reference
+    Ref,
+}
+
+impl ValueType {
+    pub(crate) fn from_val_type(ty: &ValType) -> Result<Self> {
+        match ty {
+            ValType::I32 => Ok(ValueType::I32),
+            ValType::I64 => Ok(ValueType::I64),
+            ValType::F32 => Ok(ValueType::F32),
+            ValType::F64 => Ok(ValueType::F64),
+            ValType::Ref(_) => Ok(ValueType::Ref),
+            _ => Err(anyhow!("the jit does not support the value type {:?}", ty)),
+        }
+    }
+}
+
+/// The symbol of the code of a function in debuggers and profilers, with its
+/// name from the name section if it has one.
+pub(crate) fn func_symbol_name(func_index: u32, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("wasm_function_{}::{}", func_index, name),
+        None => format!("wasm_function_{}", func_index),
+    }
 }
 
 pub trait WasmJitCompiler {
-    fn compile(&mut self, main_params: Vec<WasmValue>) -> Result<CodePtr>;
+    /// Compile the module, the returned entry point calls the function
+    /// `entry_index` with `params`.
+    fn compile(&mut self, entry_index: u32, params: Vec<WasmValue>) -> Result<CodePtr>;
 }
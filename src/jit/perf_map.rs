@@ -0,0 +1,31 @@
+//! perf map files. `perf report` names samples in anonymous executable memory
+//! after the entries of /tmp/perf-<pid>.map, one `<start> <size> <name>` line
+//! in hex per symbol.
+
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    ops::Range,
+};
+
+use anyhow::Result;
+
+/// Append an entry for the code of each function, given as its symbol name
+/// and address range. Every compilation appends, perf takes the latest entry
+/// covering an address.
+pub(crate) fn write_perf_map(funcs: &[(String, Range<u64>)]) -> Result<()> {
+    let path = format!("/tmp/perf-{}.map", std::process::id());
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut out = BufWriter::new(file);
+    for (symbol, range) in funcs {
+        writeln!(
+            out,
+            "{:x} {:x} {}",
+            range.start,
+            range.end - range.start,
+            symbol
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -28,6 +28,10 @@ pub struct X86RegisterAllocator {
     // Stack offset for the current function frame, used for spilled variables.
     // Note that we always spills 64-bit value.
     stack_offset: usize,
+    // Stack offset of the last local. The slots below it hold the locals for
+    // the whole function, the ones above are reused once the value spilled
+    // to them is popped, like registers.
+    locals_offset: usize,
 }
 
 impl X86RegisterAllocator {
@@ -36,15 +40,20 @@ impl X86RegisterAllocator {
         Self {
             reg_vec,
             stack_offset: 0,
+            locals_offset: 0,
         }
     }
 
     pub fn reset(&mut self) {
         self.reg_vec.clear();
         self.stack_offset = 0;
+        self.locals_offset = 0;
     }
 
-    pub fn clear_vec(&mut self) {
+    /// Keep the slots spilled so far, the locals, for the whole function and
+    /// clear the register vector.
+    pub fn seal_locals(&mut self) {
+        self.locals_offset = self.stack_offset;
         self.reg_vec.clear();
     }
 
@@ -80,7 +89,7 @@ impl X86RegisterAllocator {
         RegWithType::new(reg, ValueType::I32)
     }
 
-    pub fn next_not_caller_saved(&mut self) -> RegWithType {
+    pub fn next_not_caller_saved(&mut self, ty: ValueType) -> RegWithType {
         let mut pool: Vec<_> = ALLOC_POOL
             .iter()
             .copied()
@@ -94,9 +103,9 @@ impl X86RegisterAllocator {
             Register::Reg(pool.pop().unwrap())
         };
 
-        self.reg_vec.push(RegWithType::new(reg, ValueType::I32));
+        self.reg_vec.push(RegWithType::new(reg, ty));
 
-        RegWithType::new(reg, ValueType::I32)
+        RegWithType::new(reg, ty)
     }
 
     pub fn next_xmm(&mut self) -> RegWithType {
@@ -143,8 +152,18 @@ impl X86RegisterAllocator {
         self.next_spill()
     }
 
+    // Get the lowest spill slot above the locals that holds no value, so the
+    // frame only needs as many slots as values are live at once.
     fn next_spill(&mut self) -> Register {
-        self.stack_offset += 8;
-        Register::Stack(self.stack_offset)
+        let mut offset = self.locals_offset + 8;
+        while self
+            .reg_vec
+            .iter()
+            .any(|rt| rt.reg == Register::Stack(offset))
+        {
+            offset += 8;
+        }
+        self.stack_offset = self.stack_offset.max(offset);
+        Register::Stack(offset)
     }
 }
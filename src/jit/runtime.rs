@@ -0,0 +1,750 @@
+//! The state the jit code of the riscv64 and the Cranelift backends runs
+//! with and the helpers it calls. Unlike the x86 jit, both run on the store
+//! of the instance in place and check for traps themselves.
+//!
+//! The jit code gets the address of its `JitContext` from the entry and
+//! reads the fields before `module` at fixed offsets. Everything else of an
+//! instance stays in its store: memory 0 is accessed in place, the tables
+//! and segments only through the helpers. The globals are copied into
+//! `globals` on entry and written back on exit, and around host calls so
+//! the host sees them.
+//!
+//! A helper taking the context gets the operands of its instruction as an
+//! array of raw values, the operand slots of the frame, and writes its
+//! results at the start of the same array. It returns `STATUS_ERROR` if it
+//! fails, with the error kept in the context, the jit code then exits with
+//! it. The other helpers are pure functions of raw values.
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use wasmparser::ValType;
+
+use crate::{
+    module::{insts::Instruction, value_type::WasmValue, wasm_module::WasmModule},
+    vm::{HostFuncImport, SharedLimiter, Store, TrapKind, DEFAULT_MEMORY_MAXIMUM},
+};
+
+/// The jit code returned normally.
+pub(crate) const STATUS_OK: u64 = 0;
+/// A helper failed, the error is in `JitContext::error`.
+pub(crate) const STATUS_ERROR: u64 = 1;
+
+/// The traps the jit code checks for itself, it exits with the status of
+/// the trap, its index here plus 2.
+const TRAPS: [TrapKind; 6] = [
+    TrapKind::Unreachable,
+    TrapKind::OutOfBoundsMemory,
+    TrapKind::DivisionByZero,
+    TrapKind::IntegerOverflow,
+    TrapKind::InvalidConversion,
+    TrapKind::StackExhausted,
+];
+
+/// The status the jit code exits with on `trap`.
+pub(crate) fn trap_status(trap: TrapKind) -> u64 {
+    let index = TRAPS.iter().position(|t| *t == trap).unwrap();
+    index as u64 + 2
+}
+
+/// The helpers the jit code calls through `JitContext::helpers`, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Helper {
+    HostCall,
+    IndirectCallee,
+    MemoryGrow,
+    MemoryCopy,
+    MemoryFill,
+    MemoryInit,
+    DataDrop,
+    TableGet,
+    TableSet,
+    TableInit,
+    ElemDrop,
+    TableGrow,
+    TableSize,
+    TableFill,
+    I32Clz,
+    I32Ctz,
+    I32Popcnt,
+    I64Clz,
+    I64Ctz,
+    I64Popcnt,
+    F32Ceil,
+    F32Floor,
+    F32Trunc,
+    F32Nearest,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+}
+
+const NUM_HELPERS: usize = Helper::ALL.len();
+
+/// A helper taking the context, the operand slots and two immediates.
+type ContextHelper = extern "C" fn(*mut JitContext, *mut u64, u64, u64) -> u64;
+/// A helper on a raw value.
+type PureHelper = extern "C" fn(u64) -> u64;
+
+impl Helper {
+    const ALL: [Helper; 28] = [
+        Helper::HostCall,
+        Helper::IndirectCallee,
+        Helper::MemoryGrow,
+        Helper::MemoryCopy,
+        Helper::MemoryFill,
+        Helper::MemoryInit,
+        Helper::DataDrop,
+        Helper::TableGet,
+        Helper::TableSet,
+        Helper::TableInit,
+        Helper::ElemDrop,
+        Helper::TableGrow,
+        Helper::TableSize,
+        Helper::TableFill,
+        Helper::I32Clz,
+        Helper::I32Ctz,
+        Helper::I32Popcnt,
+        Helper::I64Clz,
+        Helper::I64Ctz,
+        Helper::I64Popcnt,
+        Helper::F32Ceil,
+        Helper::F32Floor,
+        Helper::F32Trunc,
+        Helper::F32Nearest,
+        Helper::F64Ceil,
+        Helper::F64Floor,
+        Helper::F64Trunc,
+        Helper::F64Nearest,
+    ];
+
+    fn addr(self) -> u64 {
+        let context = |f: ContextHelper| f as usize as u64;
+        let pure = |f: PureHelper| f as usize as u64;
+        match self {
+            Helper::HostCall => context(host_call),
+            Helper::IndirectCallee => context(indirect_callee),
+            Helper::MemoryGrow => context(memory_grow),
+            Helper::MemoryCopy => context(memory_copy),
+            Helper::MemoryFill => context(memory_fill),
+            Helper::MemoryInit => context(memory_init),
+            Helper::DataDrop => context(data_drop),
+            Helper::TableGet => context(table_get),
+            Helper::TableSet => context(table_set),
+            Helper::TableInit => context(table_init),
+            Helper::ElemDrop => context(elem_drop),
+            Helper::TableGrow => context(table_grow),
+            Helper::TableSize => context(table_size),
+            Helper::TableFill => context(table_fill),
+            Helper::I32Clz => pure(i32_clz),
+            Helper::I32Ctz => pure(i32_ctz),
+            Helper::I32Popcnt => pure(i32_popcnt),
+            Helper::I64Clz => pure(i64_clz),
+            Helper::I64Ctz => pure(i64_ctz),
+            Helper::I64Popcnt => pure(i64_popcnt),
+            Helper::F32Ceil => pure(f32_ceil),
+            Helper::F32Floor => pure(f32_floor),
+            Helper::F32Trunc => pure(f32_trunc),
+            Helper::F32Nearest => pure(f32_nearest),
+            Helper::F64Ceil => pure(f64_ceil),
+            Helper::F64Floor => pure(f64_floor),
+            Helper::F64Trunc => pure(f64_trunc),
+            Helper::F64Nearest => pure(f64_nearest),
+        }
+    }
+}
+
+extern "C" fn i32_clz(v: u64) -> u64 {
+    (v as u32).leading_zeros() as u64
+}
+
+extern "C" fn i32_ctz(v: u64) -> u64 {
+    (v as u32).trailing_zeros() as u64
+}
+
+extern "C" fn i32_popcnt(v: u64) -> u64 {
+    (v as u32).count_ones() as u64
+}
+
+extern "C" fn i64_clz(v: u64) -> u64 {
+    v.leading_zeros() as u64
+}
+
+extern "C" fn i64_ctz(v: u64) -> u64 {
+    v.trailing_zeros() as u64
+}
+
+extern "C" fn i64_popcnt(v: u64) -> u64 {
+    v.count_ones() as u64
+}
+
+extern "C" fn f32_ceil(v: u64) -> u64 {
+    f32_op(v, f32::ceil)
+}
+
+extern "C" fn f32_floor(v: u64) -> u64 {
+    f32_op(v, f32::floor)
+}
+
+extern "C" fn f32_trunc(v: u64) -> u64 {
+    f32_op(v, f32::trunc)
+}
+
+extern "C" fn f32_nearest(v: u64) -> u64 {
+    f32_op(v, f32::round_ties_even)
+}
+
+extern "C" fn f64_ceil(v: u64) -> u64 {
+    f64_op(v, f64::ceil)
+}
+
+extern "C" fn f64_floor(v: u64) -> u64 {
+    f64_op(v, f64::floor)
+}
+
+extern "C" fn f64_trunc(v: u64) -> u64 {
+    f64_op(v, f64::trunc)
+}
+
+extern "C" fn f64_nearest(v: u64) -> u64 {
+    f64_op(v, f64::round_ties_even)
+}
+
+fn f32_op(raw: u64, op: fn(f32) -> f32) -> u64 {
+    op(f32::from_bits(raw as u32)).to_bits() as u64
+}
+
+fn f64_op(raw: u64, op: fn(f64) -> f64) -> u64 {
+    op(f64::from_bits(raw)).to_bits()
+}
+
+/// Why the backends using this runtime cannot run `module`, if they
+/// cannot.
+pub(crate) fn unsupported(module: &WasmModule) -> Option<String> {
+    if module.get_memories().len() > 1 {
+        return Some("more than one memory".to_string());
+    }
+    if module.get_memories().iter().any(|mem| mem.memory64) {
+        return Some("a 64-bit memory".to_string());
+    }
+    let v128 = |ty: &ValType| *ty == ValType::V128;
+    let num_imports = module.get_num_func_imports();
+    for (func_index, func) in module.get_funcs().iter().enumerate() {
+        let sig = func.get_sig();
+        if sig.params().iter().chain(sig.results()).any(v128)
+            || (func_index >= num_imports && func.get_pure_locals().iter().any(v128))
+        {
+            return Some(format!("function {} uses v128", func_index));
+        }
+        if func_index < num_imports {
+            continue;
+        }
+        let unsupported = func.get_insts().iter().find(|inst| {
+            matches!(
+                inst,
+                Instruction::Try { .. }
+                    | Instruction::Catch { .. }
+                    | Instruction::CatchAll
+                    | Instruction::Throw { .. }
+                    | Instruction::Rethrow { .. }
+                    | Instruction::Delegate { .. }
+                    | Instruction::ReturnCall { .. }
+                    | Instruction::ReturnCallIndirect { .. }
+                    | Instruction::V128Load { .. }
+                    | Instruction::V128Store { .. }
+                    | Instruction::V128LoadExtend { .. }
+                    | Instruction::V128LoadSplat { .. }
+                    | Instruction::V128LoadZero { .. }
+                    | Instruction::V128LoadLane { .. }
+                    | Instruction::V128StoreLane { .. }
+                    | Instruction::V128Const { .. }
+                    | Instruction::I8x16Shuffle { .. }
+                    | Instruction::V128Splat { .. }
+                    | Instruction::V128ExtractLane { .. }
+                    | Instruction::V128ReplaceLane { .. }
+                    | Instruction::V128Bitselect
+                    | Instruction::V128Unop(_)
+                    | Instruction::V128Binop(_)
+                    | Instruction::V128Shift(_)
+                    | Instruction::V128Test(_)
+            )
+        });
+        if let Some(inst) = unsupported {
+            return Some(format!("{:?} in function {}", inst, func_index));
+        }
+    }
+    if module
+        .get_globals()
+        .iter()
+        .any(|g| v128(&g.get_ty().content_type))
+    {
+        return Some("a v128 global".to_string());
+    }
+    None
+}
+
+#[repr(C)]
+pub(crate) struct JitContext<'a> {
+    /// the base address and the size in bytes of memory 0
+    pub(crate) mem_base: u64,
+    pub(crate) mem_len: u64,
+    /// the raw value of every global
+    pub(crate) globals: *mut u64,
+    /// the address of the code of every function, imports included
+    pub(crate) func_addrs: *const u64,
+    /// the end of the value stack, a frame must end before it
+    pub(crate) stack_end: u64,
+    /// the number of active wasm frames and how many there may be
+    pub(crate) depth: u64,
+    pub(crate) max_depth: u64,
+    /// the stack pointer of the entry, a trap resumes there
+    pub(crate) entry_sp: u64,
+    /// the function the entry calls and the slots of its frame
+    pub(crate) entry_func: u64,
+    pub(crate) entry_frame: u64,
+    /// the address of every `Helper`
+    pub(crate) helpers: [u64; NUM_HELPERS],
+
+    pub(crate) module: Rc<WasmModule<'a>>,
+    pub(crate) store: Rc<Store<'a>>,
+    pub(crate) global_values: Vec<u64>,
+    /// imported functions, indexed by function index
+    pub(crate) imports: Vec<HostFuncImport>,
+    /// the pages a memory declared without a maximum can grow to
+    pub(crate) default_memory_maximum: u64,
+    /// the pages no memory can grow past, whatever its maximum
+    pub(crate) max_memory_pages: u64,
+    /// the elements no table can grow past, whatever its maximum
+    pub(crate) max_table_elements: u64,
+    pub(crate) limiter: Option<SharedLimiter>,
+    /// the error of the helper that failed
+    pub(crate) error: Option<anyhow::Error>,
+}
+
+impl JitContext<'_> {
+    pub(crate) const MEM_BASE: i64 = 0;
+    pub(crate) const MEM_LEN: i64 = 8;
+    pub(crate) const GLOBALS: i64 = 16;
+    pub(crate) const FUNC_ADDRS: i64 = 24;
+    pub(crate) const STACK_END: i64 = 32;
+    pub(crate) const DEPTH: i64 = 40;
+    pub(crate) const MAX_DEPTH: i64 = 48;
+    pub(crate) const ENTRY_SP: i64 = 56;
+    pub(crate) const ENTRY_FUNC: i64 = 64;
+    pub(crate) const ENTRY_FRAME: i64 = 72;
+    pub(crate) const HELPERS: i64 = 80;
+
+    /// The offset of the address of `helper`.
+    pub(crate) fn helper_offset(helper: Helper) -> i64 {
+        Self::HELPERS + helper as i64 * 8
+    }
+}
+
+impl<'a> JitContext<'a> {
+    pub(crate) fn new(module: Rc<WasmModule<'a>>, store: Rc<Store<'a>>) -> Result<Self> {
+        let imports = HostFuncImport::resolve_all(&module)?;
+        let helpers = Helper::ALL.map(Helper::addr);
+        let global_values = vec![0; module.get_globals().len()];
+        Ok(Self {
+            mem_base: 0,
+            mem_len: 0,
+            globals: std::ptr::null_mut(),
+            func_addrs: std::ptr::null(),
+            stack_end: 0,
+            depth: 0,
+            max_depth: 0,
+            entry_sp: 0,
+            entry_func: 0,
+            entry_frame: 0,
+            helpers,
+            module,
+            store,
+            global_values,
+            imports,
+            default_memory_maximum: DEFAULT_MEMORY_MAXIMUM,
+            max_memory_pages: u64::MAX,
+            max_table_elements: u64::MAX,
+            limiter: None,
+            error: None,
+        })
+    }
+
+    /// Read the current state of the store the jit code sees.
+    pub(crate) fn load_state(&mut self) {
+        for (raw, global) in self.global_values.iter_mut().zip(&self.store.globals) {
+            *raw = global.get().to_raw();
+        }
+        self.globals = self.global_values.as_mut_ptr();
+        self.load_memory();
+    }
+
+    /// Write the globals back to the store.
+    pub(crate) fn store_globals(&self) {
+        let decls = self.module.get_globals();
+        for ((global, decl), raw) in self
+            .store
+            .globals
+            .iter()
+            .zip(decls)
+            .zip(&self.global_values)
+        {
+            global.set(WasmValue::from_raw(&decl.get_ty().content_type, *raw));
+        }
+    }
+
+    /// Read where memory 0 is and its size, after it may have grown.
+    fn load_memory(&mut self) {
+        if let Some(mem) = self.store.mems.first() {
+            let mut mem = mem.borrow_mut();
+            self.mem_base = mem.data.as_mut_ptr() as u64;
+            self.mem_len = mem.data.len() as u64;
+        }
+    }
+
+    /// Run a helper, keeping its error.
+    fn status(&mut self, helper: impl FnOnce(&mut Self) -> Result<()>) -> u64 {
+        match helper(self) {
+            Ok(()) => STATUS_OK,
+            Err(e) => {
+                log::debug!("trap: {}", e);
+                self.error = Some(e);
+                STATUS_ERROR
+            }
+        }
+    }
+
+    /// The error the jit code exited with `status`.
+    pub(crate) fn take_error(&mut self, status: u64) -> anyhow::Error {
+        match status {
+            STATUS_ERROR => self
+                .error
+                .take()
+                .unwrap_or_else(|| anyhow!("a helper of the jit failed")),
+            status => anyhow::Error::new(TRAPS[status as usize - 2]),
+        }
+    }
+
+    fn table_elem_type(&self, table_index: u32) -> ValType {
+        ValType::Ref(
+            self.module.get_tables()[table_index as usize]
+                .ty
+                .element_type,
+        )
+    }
+
+    fn mem_size(&self) -> u64 {
+        self.store
+            .mems
+            .first()
+            .map_or(0, |mem| mem.borrow().size() as u64)
+    }
+}
+
+/// The raw operand `i` of a helper.
+///
+/// # Safety
+/// The slots hold the operands of the instruction.
+unsafe fn operand(slots: *mut u64, i: usize) -> u64 {
+    *slots.add(i)
+}
+
+/// An i32 operand as an unsigned length or address.
+unsafe fn operand_u32(slots: *mut u64, i: usize) -> u64 {
+    operand(slots, i) as u32 as u64
+}
+
+/// Whether the `n` items at `start` are within `size` of them.
+fn in_bounds(start: u64, n: u64, size: u64) -> bool {
+    start.checked_add(n).is_some_and(|end| end <= size)
+}
+
+/// Call an imported function with the arguments in `slots`, its results
+/// replace them.
+extern "C" fn host_call(ctx: *mut JitContext, slots: *mut u64, import_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let import = ctx.imports[import_index as usize].clone();
+        let args = import
+            .sig
+            .params()
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| WasmValue::from_raw(ty, unsafe { operand(slots, i) }))
+            .collect::<Vec<_>>();
+
+        // the host may call into an instance of the same store
+        ctx.store_globals();
+        // host functions only see the first memory, a function of another
+        // instance may use the same memory
+        let store = Rc::clone(&ctx.store);
+        let uses_caller = store.host.borrow().uses_caller(&import);
+        let mut mem = store
+            .mems
+            .first()
+            .filter(|_| uses_caller)
+            .map(|m| m.borrow_mut());
+        let data = mem.as_mut().map_or(&mut [][..], |m| &mut m.data[..]);
+        let results = store.host.borrow_mut().call(&import, &args, data);
+        drop(mem);
+        ctx.load_state();
+
+        for (i, result) in results?.iter().enumerate() {
+            unsafe { *slots.add(i) = result.to_raw() };
+        }
+        Ok(())
+    })
+}
+
+/// Write the function the element `slots[0]` of `table_index` refers to
+/// to `slots[0]`, after checking it has the type `type_index`.
+extern "C" fn indirect_callee(
+    ctx: *mut JitContext,
+    slots: *mut u64,
+    type_index: u64,
+    table_index: u64,
+) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let elem_index = unsafe { operand_u32(slots, 0) } as usize;
+        let callee = ctx.store.tables.borrow()[table_index as usize]
+            .elems
+            .get(elem_index)
+            .ok_or(TrapKind::UndefinedElement)?
+            .as_ref()
+            .ok_or(TrapKind::UninitializedElement)?;
+        let expected_sig = ctx
+            .module
+            .get_sig(type_index as u32)
+            .ok_or_else(|| anyhow!("call_indirect: unknown type {}", type_index))?;
+        let actual_sig = ctx
+            .module
+            .get_func(callee)
+            .ok_or_else(|| anyhow!("call_indirect: function {} not found", callee))?
+            .get_sig();
+        if expected_sig != actual_sig {
+            return Err(TrapKind::CallIndirectMismatch.into());
+        }
+        unsafe { *slots = callee as u64 };
+        Ok(())
+    })
+}
+
+extern "C" fn memory_grow(ctx: *mut JitContext, slots: *mut u64, _: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let additional_pages = unsafe { operand_u32(slots, 0) };
+        let mem = &ctx.store.mems[0];
+        let max = mem.borrow().max;
+        let mem_limit = max
+            .unwrap_or(ctx.default_memory_maximum)
+            .min(ctx.max_memory_pages);
+        let old_pages = mem.size_in_pages();
+        let allowed = |new_pages| {
+            new_pages <= mem_limit
+                && ctx
+                    .limiter
+                    .as_ref()
+                    .is_none_or(|limiter| limiter.memory_growing(old_pages, new_pages, max))
+        };
+        // -1 if the memory cannot grow, or the host runs out of memory
+        let result = match old_pages.checked_add(additional_pages) {
+            Some(new_pages) if allowed(new_pages) && mem.borrow_mut().grow(additional_pages) => {
+                old_pages as i32
+            }
+            _ => -1,
+        };
+        ctx.load_memory();
+        unsafe { *slots = result as u64 };
+        Ok(())
+    })
+}
+
+extern "C" fn memory_copy(ctx: *mut JitContext, slots: *mut u64, _: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let (dst, src, n) = unsafe {
+            (
+                operand_u32(slots, 0),
+                operand_u32(slots, 1),
+                operand_u32(slots, 2),
+            )
+        };
+        let size = ctx.mem_size();
+        if !in_bounds(src, n, size) || !in_bounds(dst, n, size) {
+            return Err(TrapKind::OutOfBoundsMemory.into());
+        }
+        let (src, dst, n) = (src as usize, dst as usize, n as usize);
+        // the regions may overlap, copy_within has memmove semantics
+        ctx.store.mems[0]
+            .borrow_mut()
+            .data
+            .copy_within(src..src + n, dst);
+        Ok(())
+    })
+}
+
+extern "C" fn memory_fill(ctx: *mut JitContext, slots: *mut u64, _: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let (dst, value, n) = unsafe {
+            (
+                operand_u32(slots, 0),
+                operand(slots, 1) as u8,
+                operand_u32(slots, 2),
+            )
+        };
+        if !in_bounds(dst, n, ctx.mem_size()) {
+            return Err(TrapKind::OutOfBoundsMemory.into());
+        }
+        let (dst, n) = (dst as usize, n as usize);
+        ctx.store.mems[0].borrow_mut().data[dst..dst + n].fill(value);
+        Ok(())
+    })
+}
+
+extern "C" fn memory_init(ctx: *mut JitContext, slots: *mut u64, data_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let (dst, src, n) = unsafe {
+            (
+                operand_u32(slots, 0),
+                operand_u32(slots, 1) as usize,
+                operand_u32(slots, 2) as usize,
+            )
+        };
+        let dropped = ctx.store.dropped_datas.borrow()[data_index as usize];
+        // a dropped segment behaves like an empty one
+        let data = if dropped {
+            &[]
+        } else {
+            ctx.module.get_datas()[data_index as usize].data
+        };
+        if src + n > data.len() || !in_bounds(dst, n as u64, ctx.mem_size()) {
+            return Err(TrapKind::OutOfBoundsMemory.into());
+        }
+        let dst = dst as usize;
+        ctx.store.mems[0].borrow_mut().data[dst..dst + n].copy_from_slice(&data[src..src + n]);
+        Ok(())
+    })
+}
+
+extern "C" fn data_drop(ctx: *mut JitContext, _: *mut u64, data_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.store.dropped_datas.borrow_mut()[data_index as usize] = true;
+    STATUS_OK
+}
+
+extern "C" fn table_get(ctx: *mut JitContext, slots: *mut u64, table_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let elem_index = unsafe { operand_u32(slots, 0) } as usize;
+        let value = *ctx.store.tables.borrow()[table_index as usize]
+            .elems
+            .get(elem_index)
+            .ok_or(TrapKind::OutOfBoundsTable)?;
+        unsafe { *slots = value.to_raw() };
+        Ok(())
+    })
+}
+
+extern "C" fn table_set(ctx: *mut JitContext, slots: *mut u64, table_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let ty = ctx.table_elem_type(table_index as u32);
+        let (elem_index, value) = unsafe { (operand_u32(slots, 0) as usize, operand(slots, 1)) };
+        let mut tables = ctx.store.tables.borrow_mut();
+        let slot = tables[table_index as usize]
+            .elems
+            .get_mut(elem_index)
+            .ok_or(TrapKind::OutOfBoundsTable)?;
+        *slot = WasmValue::from_raw(&ty, value);
+        Ok(())
+    })
+}
+
+extern "C" fn table_init(
+    ctx: *mut JitContext,
+    slots: *mut u64,
+    elem_index: u64,
+    table_index: u64,
+) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let (dst, src, n) = unsafe {
+            (
+                operand_u32(slots, 0) as usize,
+                operand_u32(slots, 1) as usize,
+                operand_u32(slots, 2) as usize,
+            )
+        };
+        let elem_segments = ctx.store.elem_segments.borrow();
+        let segment = &elem_segments[elem_index as usize];
+        let mut tables = ctx.store.tables.borrow_mut();
+        let table = &mut tables[table_index as usize];
+        if src + n > segment.len() || dst + n > table.size() {
+            return Err(TrapKind::OutOfBoundsTable.into());
+        }
+        table.elems[dst..dst + n].copy_from_slice(&segment[src..src + n]);
+        Ok(())
+    })
+}
+
+extern "C" fn elem_drop(ctx: *mut JitContext, _: *mut u64, elem_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.store.elem_segments.borrow_mut()[elem_index as usize].clear();
+    STATUS_OK
+}
+
+extern "C" fn table_grow(ctx: *mut JitContext, slots: *mut u64, table_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let ty = ctx.table_elem_type(table_index as u32);
+        let (init, n) = unsafe { (operand(slots, 0), operand_u32(slots, 1) as u32) };
+        let mut tables = ctx.store.tables.borrow_mut();
+        let table = &mut tables[table_index as usize];
+        let allowed = table.grown_size(n).is_some_and(|new_size| {
+            new_size as u64 <= ctx.max_table_elements
+                && ctx.limiter.as_ref().is_none_or(|limiter| {
+                    limiter.table_growing(table.size() as u64, new_size as u64, table.max)
+                })
+        });
+        // -1 if the table cannot grow
+        let old_size = if allowed {
+            let init = WasmValue::from_raw(&ty, init);
+            table.grow(n, init).map_or(-1, |size| size as i32)
+        } else {
+            -1
+        };
+        unsafe { *slots = old_size as u64 };
+        Ok(())
+    })
+}
+
+extern "C" fn table_size(ctx: *mut JitContext, slots: *mut u64, table_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    let size = ctx.store.tables.borrow()[table_index as usize].size();
+    unsafe { *slots = size as u64 };
+    STATUS_OK
+}
+
+extern "C" fn table_fill(ctx: *mut JitContext, slots: *mut u64, table_index: u64, _: u64) -> u64 {
+    let ctx = unsafe { &mut *ctx };
+    ctx.status(|ctx| {
+        let ty = ctx.table_elem_type(table_index as u32);
+        let (dst, value, n) = unsafe {
+            (
+                operand_u32(slots, 0) as usize,
+                operand(slots, 1),
+                operand_u32(slots, 2) as usize,
+            )
+        };
+        let mut tables = ctx.store.tables.borrow_mut();
+        let table = &mut tables[table_index as usize];
+        if dst + n > table.size() {
+            return Err(TrapKind::OutOfBoundsTable.into());
+        }
+        table.elems[dst..dst + n].fill(WasmValue::from_raw(&ty, value));
+        Ok(())
+    })
+}
@@ -0,0 +1,895 @@
+//! A small RV64 assembler, only the instructions of the RV64G subset the
+//! code generator emits, in their 32-bit encodings. Code that branches to a
+//! label before it is bound leaves a fixup, patched by `finish` once every
+//! label is known.
+//!
+//! Conditional branches reach 4KiB only, so a branch to a label is emitted
+//! as the inverted branch over a `jal`, which reaches 1MiB. Calls to other
+//! functions and jumps to the code shared by the module go through an
+//! `auipc` pair, which reaches anywhere in the code.
+
+use anyhow::{anyhow, Result};
+
+pub(crate) type Reg = u32;
+pub(crate) type FReg = u32;
+
+pub(crate) const ZERO: Reg = 0;
+pub(crate) const RA: Reg = 1;
+pub(crate) const SP: Reg = 2;
+pub(crate) const T0: Reg = 5;
+pub(crate) const T1: Reg = 6;
+pub(crate) const T2: Reg = 7;
+pub(crate) const S1: Reg = 9;
+pub(crate) const A0: Reg = 10;
+pub(crate) const A1: Reg = 11;
+pub(crate) const A2: Reg = 12;
+pub(crate) const A3: Reg = 13;
+pub(crate) const S2: Reg = 18;
+pub(crate) const S3: Reg = 19;
+pub(crate) const S4: Reg = 20;
+pub(crate) const S5: Reg = 21;
+pub(crate) const T3: Reg = 28;
+/// the scratch register of addresses too far for an immediate offset, and
+/// of far calls and jumps
+pub(crate) const T6: Reg = 31;
+
+pub(crate) const FT0: FReg = 0;
+pub(crate) const FT1: FReg = 1;
+pub(crate) const FT2: FReg = 2;
+
+/// The rounding modes of the float instructions, wasm rounds to nearest
+/// even except for the truncations.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Rounding {
+    NearestEven = 0,
+    TowardZero = 1,
+}
+
+/// The accrued float exception flags, `fflags`, and its invalid operation
+/// flag.
+const CSR_FFLAGS: u32 = 0x001;
+pub(crate) const FFLAGS_INVALID: i32 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Label(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Ltu,
+    Geu,
+}
+
+impl Cond {
+    fn funct3(self) -> u32 {
+        match self {
+            Cond::Eq => 0,
+            Cond::Ne => 1,
+            Cond::Lt => 4,
+            Cond::Ge => 5,
+            Cond::Ltu => 6,
+            Cond::Geu => 7,
+        }
+    }
+
+    fn inverted(self) -> Cond {
+        match self {
+            Cond::Eq => Cond::Ne,
+            Cond::Ne => Cond::Eq,
+            Cond::Lt => Cond::Ge,
+            Cond::Ge => Cond::Lt,
+            Cond::Ltu => Cond::Geu,
+            Cond::Geu => Cond::Ltu,
+        }
+    }
+}
+
+/// The integer loads and stores, by width and extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Width {
+    B,
+    H,
+    W,
+    D,
+    Bu,
+    Hu,
+    Wu,
+}
+
+impl Width {
+    pub(crate) fn bytes(self) -> u64 {
+        match self {
+            Width::B | Width::Bu => 1,
+            Width::H | Width::Hu => 2,
+            Width::W | Width::Wu => 4,
+            Width::D => 8,
+        }
+    }
+
+    fn load_funct3(self) -> u32 {
+        match self {
+            Width::B => 0,
+            Width::H => 1,
+            Width::W => 2,
+            Width::D => 3,
+            Width::Bu => 4,
+            Width::Hu => 5,
+            Width::Wu => 6,
+        }
+    }
+
+    fn store_funct3(self) -> u32 {
+        match self {
+            Width::B | Width::Bu => 0,
+            Width::H | Width::Hu => 1,
+            Width::W | Width::Wu => 2,
+            Width::D => 3,
+        }
+    }
+}
+
+/// The format of a float instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fmt {
+    S = 0,
+    D = 1,
+}
+
+/// The integer register operations, `*w` ones work on the low 32 bits and
+/// sign-extend their result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Alu {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw,
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw,
+}
+
+impl Alu {
+    /// opcode, funct3 and funct7
+    fn encoding(self) -> (u32, u32, u32) {
+        const OP: u32 = 0x33;
+        const OP_32: u32 = 0x3b;
+        match self {
+            Alu::Add => (OP, 0, 0),
+            Alu::Sub => (OP, 0, 0x20),
+            Alu::Sll => (OP, 1, 0),
+            Alu::Slt => (OP, 2, 0),
+            Alu::Sltu => (OP, 3, 0),
+            Alu::Xor => (OP, 4, 0),
+            Alu::Srl => (OP, 5, 0),
+            Alu::Sra => (OP, 5, 0x20),
+            Alu::Or => (OP, 6, 0),
+            Alu::And => (OP, 7, 0),
+            Alu::Mul => (OP, 0, 1),
+            Alu::Div => (OP, 4, 1),
+            Alu::Divu => (OP, 5, 1),
+            Alu::Rem => (OP, 6, 1),
+            Alu::Remu => (OP, 7, 1),
+            Alu::Addw => (OP_32, 0, 0),
+            Alu::Subw => (OP_32, 0, 0x20),
+            Alu::Sllw => (OP_32, 1, 0),
+            Alu::Srlw => (OP_32, 5, 0),
+            Alu::Sraw => (OP_32, 5, 0x20),
+            Alu::Mulw => (OP_32, 0, 1),
+            Alu::Divw => (OP_32, 4, 1),
+            Alu::Divuw => (OP_32, 5, 1),
+            Alu::Remw => (OP_32, 6, 1),
+            Alu::Remuw => (OP_32, 7, 1),
+        }
+    }
+}
+
+/// The float operations on two registers giving a float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fpu {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    SignInject,
+    SignInjectNeg,
+    SignInjectXor,
+}
+
+/// The float comparisons, giving 0 or 1 in an integer register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FCmp {
+    Eq,
+    Lt,
+    Le,
+}
+
+/// The integer types of the conversions from and to floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IntType {
+    W,
+    Wu,
+    L,
+    Lu,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    /// a `jal` at the index
+    Jal,
+    /// an `auipc` at the index followed by a `jalr`
+    Far,
+}
+
+#[derive(Default)]
+pub(crate) struct Assembler {
+    code: Vec<u32>,
+    /// the instruction index each label is bound to
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Label, Fixup)>,
+}
+
+fn fits_signed(value: i64, bits: u32) -> bool {
+    let bound = 1i64 << (bits - 1);
+    (-bound..bound).contains(&value)
+}
+
+impl Assembler {
+    pub(crate) fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub(crate) fn bind(&mut self, label: Label) {
+        debug_assert!(self.labels[label.0].is_none(), "label bound twice");
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    /// The offset in bytes of a bound label.
+    pub(crate) fn label_offset(&self, label: Label) -> Option<usize> {
+        self.labels[label.0].map(|index| index * 4)
+    }
+
+    fn emit(&mut self, inst: u32) {
+        self.code.push(inst);
+    }
+
+    /// Patch the fixups and return the code as bytes, fails if a label is
+    /// out of the reach of a branch to it.
+    pub(crate) fn finish(mut self) -> Result<Vec<u8>> {
+        for (at, label, fixup) in std::mem::take(&mut self.fixups) {
+            let target = self.labels[label.0].expect("branch to an unbound label");
+            let offset = (target as i64 - at as i64) * 4;
+            match fixup {
+                Fixup::Jal => {
+                    if !fits_signed(offset, 21) {
+                        return Err(anyhow!("a function is too large for the riscv64 jit"));
+                    }
+                    self.code[at] |= j_imm(offset);
+                }
+                Fixup::Far => {
+                    let (hi, lo) = split_hi_lo(offset);
+                    self.code[at] |= (hi as u32) << 12;
+                    self.code[at + 1] |= ((lo as u32) & 0xfff) << 20;
+                }
+            }
+        }
+        Ok(self
+            .code
+            .iter()
+            .flat_map(|inst| inst.to_le_bytes())
+            .collect())
+    }
+
+    fn r_type(&mut self, opcode: u32, funct3: u32, funct7: u32, rd: u32, rs1: u32, rs2: u32) {
+        self.emit(funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode);
+    }
+
+    fn i_type(&mut self, opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) {
+        debug_assert!(fits_signed(imm as i64, 12));
+        self.emit(((imm as u32) & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode);
+    }
+
+    fn s_type(&mut self, opcode: u32, funct3: u32, rs1: u32, rs2: u32, imm: i32) {
+        debug_assert!(fits_signed(imm as i64, 12));
+        let imm = imm as u32;
+        self.emit(
+            (imm >> 5 & 0x7f) << 25
+                | rs2 << 20
+                | rs1 << 15
+                | funct3 << 12
+                | (imm & 0x1f) << 7
+                | opcode,
+        );
+    }
+
+    pub(crate) fn alu(&mut self, op: Alu, rd: Reg, rs1: Reg, rs2: Reg) {
+        let (opcode, funct3, funct7) = op.encoding();
+        self.r_type(opcode, funct3, funct7, rd, rs1, rs2);
+    }
+
+    pub(crate) fn addi(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self.i_type(0x13, 0, rd, rs1, imm);
+    }
+
+    /// `addiw rd, rs1, 0` is `sext.w`.
+    pub(crate) fn addiw(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self.i_type(0x1b, 0, rd, rs1, imm);
+    }
+
+    pub(crate) fn xori(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self.i_type(0x13, 4, rd, rs1, imm);
+    }
+
+    pub(crate) fn andi(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self.i_type(0x13, 7, rd, rs1, imm);
+    }
+
+    pub(crate) fn sltiu(&mut self, rd: Reg, rs1: Reg, imm: i32) {
+        self.i_type(0x13, 3, rd, rs1, imm);
+    }
+
+    pub(crate) fn slli(&mut self, rd: Reg, rs1: Reg, shamt: u32) {
+        self.i_type(0x13, 1, rd, rs1, (shamt & 0x3f) as i32);
+    }
+
+    pub(crate) fn srli(&mut self, rd: Reg, rs1: Reg, shamt: u32) {
+        self.i_type(0x13, 5, rd, rs1, (shamt & 0x3f) as i32);
+    }
+
+    pub(crate) fn srai(&mut self, rd: Reg, rs1: Reg, shamt: u32) {
+        self.i_type(0x13, 5, rd, rs1, (0x400 | shamt & 0x3f) as i32);
+    }
+
+    pub(crate) fn mv(&mut self, rd: Reg, rs: Reg) {
+        self.addi(rd, rs, 0);
+    }
+
+    /// `rd = rs == 0`
+    pub(crate) fn seqz(&mut self, rd: Reg, rs: Reg) {
+        self.sltiu(rd, rs, 1);
+    }
+
+    /// `rd = rs != 0`
+    pub(crate) fn snez(&mut self, rd: Reg, rs: Reg) {
+        self.alu(Alu::Sltu, rd, ZERO, rs);
+    }
+
+    fn lui(&mut self, rd: Reg, imm20: i64) {
+        self.emit(((imm20 as u32) & 0xfffff) << 12 | rd << 7 | 0x37);
+    }
+
+    /// Load any 64-bit constant, in at most eight instructions.
+    pub(crate) fn li(&mut self, rd: Reg, value: i64) {
+        if fits_signed(value, 12) {
+            self.addi(rd, ZERO, value as i32);
+        } else if fits_signed(value, 32) {
+            let (hi, lo) = split_hi_lo(value);
+            self.lui(rd, hi);
+            if lo != 0 {
+                self.addiw(rd, rd, lo as i32);
+            }
+        } else {
+            // the upper bits shifted into place, then the low 12 bits
+            let lo = (value << 52) >> 52;
+            let hi = value.wrapping_sub(lo) >> 12;
+            let zeros = hi.trailing_zeros();
+            self.li(rd, hi >> zeros);
+            self.slli(rd, rd, 12 + zeros);
+            if lo != 0 {
+                self.addi(rd, rd, lo as i32);
+            }
+        }
+    }
+
+    /// `rd = rs + imm` for any immediate, `rd` may be `rs` but not T6.
+    pub(crate) fn add_imm(&mut self, rd: Reg, rs: Reg, imm: i64) {
+        if fits_signed(imm, 12) {
+            self.addi(rd, rs, imm as i32);
+        } else {
+            self.li(T6, imm);
+            self.alu(Alu::Add, rd, rs, T6);
+        }
+    }
+
+    /// The base and the offset of `base + offset` in a load or a store, the
+    /// address goes through T6 if the offset does not fit.
+    fn address(&mut self, base: Reg, offset: i64) -> (Reg, i32) {
+        if fits_signed(offset, 12) {
+            (base, offset as i32)
+        } else {
+            self.li(T6, offset);
+            self.alu(Alu::Add, T6, T6, base);
+            (T6, 0)
+        }
+    }
+
+    pub(crate) fn load(&mut self, width: Width, rd: Reg, base: Reg, offset: i64) {
+        let (base, offset) = self.address(base, offset);
+        self.i_type(0x03, width.load_funct3(), rd, base, offset);
+    }
+
+    pub(crate) fn store(&mut self, width: Width, rs: Reg, base: Reg, offset: i64) {
+        let (base, offset) = self.address(base, offset);
+        self.s_type(0x23, width.store_funct3(), base, rs, offset);
+    }
+
+    pub(crate) fn ld(&mut self, rd: Reg, base: Reg, offset: i64) {
+        self.load(Width::D, rd, base, offset);
+    }
+
+    pub(crate) fn sd(&mut self, rs: Reg, base: Reg, offset: i64) {
+        self.store(Width::D, rs, base, offset);
+    }
+
+    pub(crate) fn fload(&mut self, fmt: Fmt, rd: FReg, base: Reg, offset: i64) {
+        let (base, offset) = self.address(base, offset);
+        self.i_type(0x07, 2 + fmt as u32, rd, base, offset);
+    }
+
+    pub(crate) fn fstore(&mut self, fmt: Fmt, rs: FReg, base: Reg, offset: i64) {
+        let (base, offset) = self.address(base, offset);
+        self.s_type(0x27, 2 + fmt as u32, base, rs, offset);
+    }
+
+    pub(crate) fn fpu(&mut self, op: Fpu, fmt: Fmt, rd: FReg, rs1: FReg, rs2: FReg) {
+        let (funct5, funct3) = match op {
+            Fpu::Add => (0x00, Rounding::NearestEven as u32),
+            Fpu::Sub => (0x01, Rounding::NearestEven as u32),
+            Fpu::Mul => (0x02, Rounding::NearestEven as u32),
+            Fpu::Div => (0x03, Rounding::NearestEven as u32),
+            Fpu::SignInject => (0x04, 0),
+            Fpu::SignInjectNeg => (0x04, 1),
+            Fpu::SignInjectXor => (0x04, 2),
+            Fpu::Min => (0x05, 0),
+            Fpu::Max => (0x05, 1),
+        };
+        self.r_type(0x53, funct3, funct5 << 2 | fmt as u32, rd, rs1, rs2);
+    }
+
+    pub(crate) fn fsqrt(&mut self, fmt: Fmt, rd: FReg, rs: FReg) {
+        let funct3 = Rounding::NearestEven as u32;
+        self.r_type(0x53, funct3, 0x0b << 2 | fmt as u32, rd, rs, 0);
+    }
+
+    pub(crate) fn fcmp(&mut self, cmp: FCmp, fmt: Fmt, rd: Reg, rs1: FReg, rs2: FReg) {
+        let funct3 = match cmp {
+            FCmp::Le => 0,
+            FCmp::Lt => 1,
+            FCmp::Eq => 2,
+        };
+        self.r_type(0x53, funct3, 0x14 << 2 | fmt as u32, rd, rs1, rs2);
+    }
+
+    /// Convert the float `rs` to the integer `rd`, out of range values are
+    /// clamped and raise the invalid flag, NaN gives the largest value.
+    pub(crate) fn fcvt_to_int(&mut self, ty: IntType, fmt: Fmt, rd: Reg, rs: FReg, rm: Rounding) {
+        self.r_type(0x53, rm as u32, 0x18 << 2 | fmt as u32, rd, rs, ty as u32);
+    }
+
+    pub(crate) fn fcvt_from_int(&mut self, ty: IntType, fmt: Fmt, rd: FReg, rs: Reg) {
+        let funct3 = Rounding::NearestEven as u32;
+        self.r_type(0x53, funct3, 0x1a << 2 | fmt as u32, rd, rs, ty as u32);
+    }
+
+    /// Convert between the two float formats, `fmt` is the one of `rd`.
+    pub(crate) fn fcvt_float(&mut self, fmt: Fmt, rd: FReg, rs: FReg) {
+        let (funct3, source) = match fmt {
+            Fmt::S => (Rounding::NearestEven as u32, Fmt::D),
+            Fmt::D => (0, Fmt::S),
+        };
+        self.r_type(0x53, funct3, 0x08 << 2 | fmt as u32, rd, rs, source as u32);
+    }
+
+    /// Clear the accrued float exception flags.
+    pub(crate) fn clear_fflags(&mut self) {
+        // csrrw zero, fflags, zero
+        self.i_type(0x73, 1, ZERO, ZERO, CSR_FFLAGS as i32);
+    }
+
+    /// Read the accrued float exception flags.
+    pub(crate) fn read_fflags(&mut self, rd: Reg) {
+        // csrrs rd, fflags, zero
+        self.i_type(0x73, 2, rd, ZERO, CSR_FFLAGS as i32);
+    }
+
+    pub(crate) fn jalr(&mut self, rd: Reg, rs: Reg, offset: i32) {
+        self.i_type(0x67, 0, rd, rs, offset);
+    }
+
+    pub(crate) fn ret(&mut self) {
+        self.jalr(ZERO, RA, 0);
+    }
+
+    /// `jal` to a label in the same function.
+    pub(crate) fn jump(&mut self, label: Label) {
+        self.jal_to(ZERO, label);
+    }
+
+    fn jal_to(&mut self, rd: Reg, label: Label) {
+        self.fixups.push((self.code.len(), label, Fixup::Jal));
+        self.emit(rd << 7 | 0x6f);
+    }
+
+    /// Branch to a label in the same function if `rs1 cond rs2`.
+    pub(crate) fn branch(&mut self, cond: Cond, rs1: Reg, rs2: Reg, label: Label) {
+        // skip the jal if the condition does not hold
+        self.branch_by(cond.inverted(), rs1, rs2, 8);
+        self.jump(label);
+    }
+
+    /// Branch `offset` bytes forward or backward.
+    fn branch_by(&mut self, cond: Cond, rs1: Reg, rs2: Reg, offset: i32) {
+        let imm = offset as u32;
+        self.emit(
+            (imm >> 12 & 1) << 31
+                | (imm >> 5 & 0x3f) << 25
+                | rs2 << 20
+                | rs1 << 15
+                | cond.funct3() << 12
+                | (imm >> 1 & 0xf) << 8
+                | (imm >> 11 & 1) << 7
+                | 0x63,
+        );
+    }
+
+    /// Call a label anywhere in the code.
+    pub(crate) fn call_far(&mut self, label: Label) {
+        self.far(RA, label);
+    }
+
+    /// Jump to a label anywhere in the code.
+    pub(crate) fn jump_far(&mut self, label: Label) {
+        self.far(ZERO, label);
+    }
+
+    fn far(&mut self, rd: Reg, label: Label) {
+        self.fixups.push((self.code.len(), label, Fixup::Far));
+        // auipc t6, 0; jalr rd, 0(t6)
+        self.emit(T6 << 7 | 0x17);
+        self.jalr(rd, T6, 0);
+    }
+
+    /// `rd` = the address of the label, relative to the code.
+    pub(crate) fn load_label_address(&mut self, rd: Reg, label: Label) {
+        self.fixups.push((self.code.len(), label, Fixup::Far));
+        // auipc rd, 0; addi rd, rd, 0
+        self.emit(rd << 7 | 0x17);
+        self.addi(rd, rd, 0);
+    }
+}
+
+/// Split `value` into the upper 20 bits of `lui` or `auipc` and the signed
+/// low 12 bits added to them.
+fn split_hi_lo(value: i64) -> (i64, i64) {
+    let lo = (value << 52) >> 52;
+    ((value - lo) >> 12, lo)
+}
+
+fn j_imm(offset: i64) -> u32 {
+    let imm = offset as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3ff) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xff) << 12
+}
+
+/// The expected words are the encodings `llvm-mc -triple=riscv64
+/// -mattr=+m,+f,+d -show-encoding` gives the instruction in the comment.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(emit: impl FnOnce(&mut Assembler)) -> Vec<u32> {
+        let mut asm = Assembler::default();
+        emit(&mut asm);
+        asm.finish()
+            .unwrap()
+            .chunks(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn integer_register_operations() {
+        let code = words(|asm| {
+            asm.alu(Alu::Add, A0, A0, A1);
+            asm.alu(Alu::Sub, A0, A0, A1);
+            asm.alu(Alu::Sra, T0, T1, T2);
+            asm.alu(Alu::Mulw, A0, A1, A2);
+            asm.alu(Alu::Remuw, A0, A1, A2);
+            asm.alu(Alu::Divu, S2, S3, S4);
+            asm.snez(A0, A1);
+        });
+        let expected: &[u32] = &[
+            0x00b50533, // add a0, a0, a1
+            0x40b50533, // sub a0, a0, a1
+            0x407352b3, // sra t0, t1, t2
+            0x02c5853b, // mulw a0, a1, a2
+            0x02c5f53b, // remuw a0, a1, a2
+            0x0349d933, // divu s2, s3, s4
+            0x00b03533, // snez a0, a1
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn integer_immediate_operations() {
+        let code = words(|asm| {
+            asm.addi(A0, A0, 1);
+            asm.addi(A0, A0, -2048);
+            asm.addiw(A0, A0, 0);
+            asm.xori(A0, A1, -1);
+            asm.andi(A0, A1, 255);
+            asm.slli(A0, A0, 32);
+            asm.srli(A0, A0, 63);
+            asm.srai(A0, A0, 32);
+            asm.seqz(A0, A1);
+            asm.lui(A0, 0x12345);
+            asm.lui(A0, 0x80000);
+        });
+        let expected: &[u32] = &[
+            0x00150513, // addi a0, a0, 1
+            0x80050513, // addi a0, a0, -2048
+            0x0005051b, // sext.w a0, a0
+            0xfff5c513, // not a0, a1
+            0x0ff5f513, // andi a0, a1, 255
+            0x02051513, // slli a0, a0, 32
+            0x03f55513, // srli a0, a0, 63
+            0x42055513, // srai a0, a0, 32
+            0x0015b513, // seqz a0, a1
+            0x12345537, // lui a0, 0x12345
+            0x80000537, // lui a0, 0x80000
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        let code = words(|asm| {
+            asm.ld(A0, SP, 8);
+            asm.sd(RA, SP, -8);
+            asm.load(Width::Bu, A0, A1, 0);
+            asm.load(Width::H, A0, A1, 2);
+            asm.load(Width::Wu, A0, A1, 2047);
+            asm.store(Width::B, A0, A1, -2048);
+            asm.store(Width::H, A0, A1, 2);
+            asm.store(Width::W, A0, A1, 4);
+            asm.fload(Fmt::D, FT0, A0, 16);
+            asm.fstore(Fmt::S, FT1, A0, 4);
+        });
+        let expected: &[u32] = &[
+            0x00813503, // ld a0, 8(sp)
+            0xfe113c23, // sd ra, -8(sp)
+            0x0005c503, // lbu a0, 0(a1)
+            0x00259503, // lh a0, 2(a1)
+            0x7ff5e503, // lwu a0, 2047(a1)
+            0x80a58023, // sb a0, -2048(a1)
+            0x00a59123, // sh a0, 2(a1)
+            0x00a5a223, // sw a0, 4(a1)
+            0x01053007, // fld ft0, 16(a0)
+            0x00152227, // fsw ft1, 4(a0)
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn offsets_out_of_immediate_range_go_through_t6() {
+        let code = words(|asm| asm.ld(A0, SP, 2048));
+        let expected: &[u32] = &[
+            0x00001fb7, // lui t6, 1
+            0x800f8f9b, // addiw t6, t6, -2048
+            0x002f8fb3, // add t6, t6, sp
+            0x000fb503, // ld a0, 0(t6)
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn float_operations() {
+        let code = words(|asm| {
+            asm.fpu(Fpu::Add, Fmt::D, FT0, FT1, FT2);
+            asm.fpu(Fpu::Sub, Fmt::S, FT0, FT1, FT2);
+            asm.fpu(Fpu::Div, Fmt::D, FT0, FT1, FT2);
+            asm.fpu(Fpu::Min, Fmt::S, FT0, FT1, FT2);
+            asm.fpu(Fpu::Max, Fmt::D, FT0, FT1, FT2);
+            asm.fpu(Fpu::SignInject, Fmt::D, FT0, FT1, FT2);
+            asm.fpu(Fpu::SignInjectNeg, Fmt::S, FT0, FT1, FT2);
+            asm.fpu(Fpu::SignInjectXor, Fmt::D, FT0, FT1, FT2);
+            asm.fsqrt(Fmt::S, FT0, FT1);
+            asm.fcmp(FCmp::Eq, Fmt::D, A0, FT0, FT1);
+            asm.fcmp(FCmp::Lt, Fmt::S, A0, FT0, FT1);
+            asm.fcmp(FCmp::Le, Fmt::D, A0, FT0, FT1);
+        });
+        let expected: &[u32] = &[
+            0x02208053, // fadd.d ft0, ft1, ft2, rne
+            0x08208053, // fsub.s ft0, ft1, ft2, rne
+            0x1a208053, // fdiv.d ft0, ft1, ft2, rne
+            0x28208053, // fmin.s ft0, ft1, ft2
+            0x2a209053, // fmax.d ft0, ft1, ft2
+            0x22208053, // fsgnj.d ft0, ft1, ft2
+            0x20209053, // fsgnjn.s ft0, ft1, ft2
+            0x2220a053, // fsgnjx.d ft0, ft1, ft2
+            0x58008053, // fsqrt.s ft0, ft1, rne
+            0xa2102553, // feq.d a0, ft0, ft1
+            0xa0101553, // flt.s a0, ft0, ft1
+            0xa2100553, // fle.d a0, ft0, ft1
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn conversions_and_float_flags() {
+        let code = words(|asm| {
+            asm.fcvt_to_int(IntType::W, Fmt::D, A0, FT0, Rounding::TowardZero);
+            asm.fcvt_to_int(IntType::Lu, Fmt::S, A0, FT0, Rounding::TowardZero);
+            asm.fcvt_from_int(IntType::L, Fmt::D, FT0, A0);
+            asm.fcvt_from_int(IntType::Wu, Fmt::S, FT0, A0);
+            asm.fcvt_float(Fmt::S, FT0, FT1);
+            asm.fcvt_float(Fmt::D, FT0, FT1);
+            asm.clear_fflags();
+            asm.read_fflags(A0);
+        });
+        let expected: &[u32] = &[
+            0xc2001553, // fcvt.w.d a0, ft0, rtz
+            0xc0301553, // fcvt.lu.s a0, ft0, rtz
+            0xd2250053, // fcvt.d.l ft0, a0, rne
+            0xd0150053, // fcvt.s.wu ft0, a0, rne
+            0x40108053, // fcvt.s.d ft0, ft1, rne
+            0x42008053, // fcvt.d.s ft0, ft1
+            0x00101073, // fsflags zero
+            0x00102573, // frflags a0
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn jumps_and_branches_to_labels() {
+        let code = words(|asm| {
+            let back = asm.label();
+            asm.bind(back);
+            asm.ret();
+            asm.jump(back);
+            let forward = asm.label();
+            asm.branch(Cond::Eq, A0, A1, forward);
+            asm.branch(Cond::Ltu, T0, T1, forward);
+            asm.branch(Cond::Ge, A0, ZERO, forward);
+            asm.jalr(RA, T0, 16);
+            asm.bind(forward);
+        });
+        let expected: &[u32] = &[
+            0x00008067, // ret
+            0xffdff06f, // j -4
+            0x00b51463, // bne a0, a1, 8
+            0x0180006f, // j 24
+            0x0062f463, // bgeu t0, t1, 8
+            0x0100006f, // j 16
+            0x00054463, // bltz a0, 8
+            0x0080006f, // j 8
+            0x010280e7, // jalr 16(t0)
+        ];
+        assert_eq!(code, expected);
+    }
+
+    #[test]
+    fn far_calls_jumps_and_label_addresses() {
+        let code = words(|asm| {
+            let near = asm.label();
+            let far = asm.label();
+            asm.load_label_address(A0, near);
+            asm.call_far(near);
+            asm.bind(near);
+            asm.jump_far(far);
+            for _ in 0..510 {
+                asm.mv(ZERO, ZERO);
+            }
+            asm.bind(far);
+        });
+        let expected: &[u32] = &[
+            0x00000517, // auipc a0, 0
+            0x01050513, // addi a0, a0, 16
+            0x00000f97, // auipc t6, 0
+            0x008f80e7, // jalr 8(t6)
+            0x00001f97, // auipc t6, 1
+            0x800f8067, // jr -2048(t6)
+        ];
+        assert_eq!(&code[..6], expected);
+    }
+
+    #[test]
+    fn jump_out_of_reach_fails() {
+        let mut asm = Assembler::default();
+        let label = asm.label();
+        asm.jump(label);
+        for _ in 0..1 << 18 {
+            asm.mv(ZERO, ZERO);
+        }
+        asm.bind(label);
+        assert!(asm.finish().is_err());
+    }
+
+    /// The value `li` leaves in its register, running the instructions it
+    /// emits.
+    fn run_li(value: i64) -> (i64, usize) {
+        let code = words(|asm| asm.li(A0, value));
+        let mut reg = 0i64;
+        for inst in &code {
+            let rd = inst >> 7 & 0x1f;
+            let rs1 = inst >> 15 & 0x1f;
+            let src = if rs1 == ZERO { 0 } else { reg };
+            let imm = (*inst as i32 >> 20) as i64;
+            assert_eq!(rd, A0);
+            reg = match (inst & 0x7f, inst >> 12 & 7) {
+                (0x37, _) => (*inst & 0xfffff000) as i32 as i64,
+                (0x13, 0) => src.wrapping_add(imm),
+                (0x1b, 0) => src.wrapping_add(imm) as i32 as i64,
+                (0x13, 1) => src << (imm & 0x3f),
+                _ => panic!("li emitted {:#010x}", inst),
+            };
+        }
+        (reg, code.len())
+    }
+
+    #[test]
+    fn li_loads_any_constant() {
+        let values = [
+            0,
+            1,
+            -1,
+            2047,
+            -2048,
+            2048,
+            -2049,
+            0x12345678,
+            0x7ffff800,
+            i32::MAX as i64,
+            i32::MIN as i64,
+            0x8000_0000,
+            0xffff_ffff,
+            1 << 40,
+            0x1234_5678_9abc_def0,
+            -0x1234_5678_9abc_def0,
+            0x7fff_ffff_ffff_f800,
+            i64::MAX,
+            i64::MIN,
+        ];
+        for value in values {
+            let (loaded, len) = run_li(value);
+            assert_eq!(loaded, value, "li {:#x}", value);
+            assert!(len <= 8, "li {:#x} takes {} instructions", value, len);
+        }
+    }
+
+    #[test]
+    fn li_uses_the_shortest_forms() {
+        let expected: &[u32] = &[0xffb00513]; // li a0, -5
+        assert_eq!(words(|asm| asm.li(A0, -5)), expected);
+        let expected: &[u32] = &[
+            0x12345537, // lui a0, 0x12345
+            0x6785051b, // addiw a0, a0, 0x678
+        ];
+        assert_eq!(words(|asm| asm.li(A0, 0x12345678)), expected);
+    }
+}
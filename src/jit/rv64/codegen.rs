@@ -0,0 +1,1060 @@
+//! The code of one wasm function. Every local and every operand has a slot
+//! of 8 bytes in the frame of the function on the value stack: the locals
+//! first, parameters included, then the operand stack, whose height before
+//! each instruction the validator already knows. An instruction loads its
+//! operands from their slots into temporaries and stores its result to the
+//! slot of its first operand, so blocks need no reconciliation: a branch
+//! only moves the values it carries down to where its target expects them.
+//!
+//! A call moves the frame base up to the arguments of the callee, which are
+//! the first locals of its frame, and its results are left in their place.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use wasmparser::BlockType;
+
+use super::asm::{
+    Alu, Assembler, Cond, FCmp, Fmt, Fpu, IntType, Label, Rounding, Width, A0, A1, A2, A3, FT0,
+    FT1, FT2, RA, S1, S2, S3, S4, S5, SP, T0, T1, T2, T3, ZERO,
+};
+use crate::{
+    jit::runtime::{trap_status, Helper, JitContext, STATUS_ERROR},
+    module::{
+        components::FuncDecl,
+        insts::{
+            F32Binop, F32Unop, F64Binop, F64Unop, I32Binop, I32Unop, I64Binop, I64Unop,
+            Instruction, MemArg,
+        },
+        wasm_module::{block_type_num_params, block_type_num_results, WasmModule},
+    },
+    vm::TrapKind,
+};
+
+/// The labels shared by the code of every function.
+pub(super) struct ModuleLabels {
+    /// the code of every function, imports included
+    pub(super) funcs: Vec<Label>,
+    /// where the jit code exits to the entry, with its status in a0
+    pub(super) exit: Label,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Func,
+    Block,
+    Loop,
+    If,
+}
+
+/// A block being compiled.
+struct Frame {
+    kind: FrameKind,
+    /// the height of the operand stack below the parameters of the block,
+    /// where its results go
+    base: u32,
+    /// the number of values a branch to the block carries
+    arity: u32,
+    /// where a branch to the block goes, the header of a loop and the end of
+    /// the other blocks
+    label: Label,
+    /// the start of the else branch of an `if` until it is bound
+    else_label: Option<Label>,
+    /// whether the block is entered at all, the code of a block in
+    /// unreachable code is skipped
+    live: bool,
+}
+
+/// The float unary operations, the same for both formats.
+enum FloatUnop {
+    Abs,
+    Neg,
+    Sqrt,
+    Round(Helper),
+    /// to an integer, trapping or saturating if it is out of range
+    Truncate(IntType, bool),
+    /// to the other float format
+    Convert,
+    Reinterpret,
+}
+
+pub(super) struct FuncCodegen<'c, 'a> {
+    asm: &'c mut Assembler,
+    module: &'c WasmModule<'a>,
+    labels: &'c ModuleLabels,
+    nlocals: u32,
+    nresults: u32,
+    heights: &'c [u32],
+    frames: Vec<Frame>,
+    reachable: bool,
+    epilogue: Label,
+    /// the exit of each trap the function checks for
+    traps: Vec<(TrapKind, Label)>,
+    /// the exit taken when a helper fails
+    error_exit: Option<Label>,
+}
+
+impl<'c, 'a> FuncCodegen<'c, 'a> {
+    pub(super) fn new(
+        asm: &'c mut Assembler,
+        module: &'c WasmModule<'a>,
+        labels: &'c ModuleLabels,
+        func: &FuncDecl,
+        heights: &'c [u32],
+    ) -> Self {
+        let sig = func.get_sig();
+        let nlocals = (sig.params().len() + func.get_pure_locals().len()) as u32;
+        let epilogue = asm.label();
+        Self {
+            asm,
+            module,
+            labels,
+            nlocals,
+            nresults: sig.results().len() as u32,
+            heights,
+            frames: Vec::new(),
+            reachable: true,
+            epilogue,
+            traps: Vec::new(),
+            error_exit: None,
+        }
+    }
+
+    /// Emit the function at `label`.
+    pub(super) fn emit(mut self, label: Label, func: &FuncDecl) -> Result<()> {
+        self.asm.bind(label);
+        self.emit_prologue(func);
+
+        let func_frame = Frame {
+            kind: FrameKind::Func,
+            base: 0,
+            arity: self.nresults,
+            label: self.epilogue,
+            else_label: None,
+            live: true,
+        };
+        self.frames.push(func_frame);
+        for (pc, inst) in func.get_insts().iter().enumerate() {
+            let h = self.heights[pc];
+            if !self.reachable
+                && !matches!(
+                    inst,
+                    Instruction::Block { .. }
+                        | Instruction::Loop { .. }
+                        | Instruction::If { .. }
+                        | Instruction::Else
+                        | Instruction::End
+                )
+            {
+                continue;
+            }
+            self.emit_inst(inst, h)?;
+        }
+
+        self.emit_epilogue();
+        Ok(())
+    }
+
+    /// The offset of the slot of local `index` from the frame base.
+    fn local(&self, index: u32) -> i64 {
+        index as i64 * 8
+    }
+
+    /// The offset of the slot of the operand at `height`.
+    fn slot(&self, height: u32) -> i64 {
+        (self.nlocals + height) as i64 * 8
+    }
+
+    fn frame_size(&self) -> i64 {
+        let max_height = self.heights.iter().copied().max().unwrap_or(0);
+        self.slot(max_height.max(self.nresults) + 1)
+    }
+
+    fn emit_prologue(&mut self, func: &FuncDecl) {
+        let stack_exhausted = self.trap_label(TrapKind::StackExhausted);
+        self.asm.addi(SP, SP, -16);
+        self.asm.sd(RA, SP, 8);
+
+        // count the frame, then check it fits on the value stack
+        self.asm.ld(T0, S2, JitContext::DEPTH);
+        self.asm.addi(T0, T0, 1);
+        self.asm.ld(T1, S2, JitContext::MAX_DEPTH);
+        self.asm.branch(Cond::Ltu, T1, T0, stack_exhausted);
+        self.asm.sd(T0, S2, JitContext::DEPTH);
+        self.asm.add_imm(T0, S1, self.frame_size());
+        self.asm.ld(T1, S2, JitContext::STACK_END);
+        self.asm.branch(Cond::Ltu, T1, T0, stack_exhausted);
+
+        let nparams = func.get_sig().params().len() as u32;
+        for local in nparams..self.nlocals {
+            self.asm.sd(ZERO, S1, self.local(local));
+        }
+    }
+
+    fn emit_epilogue(&mut self) {
+        self.asm.bind(self.epilogue);
+        self.asm.ld(T0, S2, JitContext::DEPTH);
+        self.asm.addi(T0, T0, -1);
+        self.asm.sd(T0, S2, JitContext::DEPTH);
+        self.asm.ld(RA, SP, 8);
+        self.asm.addi(SP, SP, 16);
+        self.asm.ret();
+
+        for (trap, label) in std::mem::take(&mut self.traps) {
+            self.asm.bind(label);
+            self.asm.li(A0, trap_status(trap) as i64);
+            self.asm.jump_far(self.labels.exit);
+        }
+        if let Some(label) = self.error_exit {
+            self.asm.bind(label);
+            self.asm.li(A0, STATUS_ERROR as i64);
+            self.asm.jump_far(self.labels.exit);
+        }
+    }
+
+    /// The exit of the function trapping with `trap`.
+    fn trap_label(&mut self, trap: TrapKind) -> Label {
+        if let Some((_, label)) = self.traps.iter().find(|(t, _)| *t == trap) {
+            return *label;
+        }
+        let label = self.asm.label();
+        self.traps.push((trap, label));
+        label
+    }
+
+    fn error_label(&mut self) -> Label {
+        match self.error_exit {
+            Some(label) => label,
+            None => *self.error_exit.insert(self.asm.label()),
+        }
+    }
+
+    fn emit_inst(&mut self, inst: &Instruction, h: u32) -> Result<()> {
+        match inst {
+            Instruction::Unreachable => {
+                let label = self.trap_label(TrapKind::Unreachable);
+                self.asm.jump(label);
+                self.reachable = false;
+            }
+            Instruction::Nop => {}
+            Instruction::Block { ty } | Instruction::Loop { ty } | Instruction::If { ty } => {
+                self.emit_block(inst, *ty, h);
+            }
+            Instruction::Else => self.emit_else(),
+            Instruction::End => self.emit_end(h),
+            Instruction::Br { rel_depth } => {
+                self.emit_branch(*rel_depth, h);
+                self.reachable = false;
+            }
+            Instruction::BrIf { rel_depth } => self.emit_br_if(*rel_depth, h),
+            Instruction::BrTable { table } => {
+                self.emit_br_table(&table.targets, table.default_target, h);
+                self.reachable = false;
+            }
+            Instruction::Return => {
+                self.emit_return(h);
+                self.reachable = false;
+            }
+            Instruction::Call { func_idx } => self.emit_call(*func_idx, h)?,
+            Instruction::CallIndirect {
+                type_index,
+                table_index,
+            } => self.emit_call_indirect(*type_index, *table_index, h)?,
+            Instruction::Drop => {}
+            Instruction::Select | Instruction::TypedSelect { .. } => {
+                // the first operand stays in place unless the condition is 0
+                let keep = self.asm.label();
+                self.asm.load(Width::W, T0, S1, self.slot(h - 1));
+                self.asm.branch(Cond::Ne, T0, ZERO, keep);
+                self.asm.ld(T1, S1, self.slot(h - 2));
+                self.asm.sd(T1, S1, self.slot(h - 3));
+                self.asm.bind(keep);
+            }
+            Instruction::LocalGet { local_idx } => {
+                self.asm.ld(T0, S1, self.local(*local_idx));
+                self.asm.sd(T0, S1, self.slot(h));
+            }
+            Instruction::LocalSet { local_idx } | Instruction::LocalTee { local_idx } => {
+                self.asm.ld(T0, S1, self.slot(h - 1));
+                self.asm.sd(T0, S1, self.local(*local_idx));
+            }
+            Instruction::GlobalGet { global_idx } => {
+                self.asm.ld(T0, S5, *global_idx as i64 * 8);
+                self.asm.sd(T0, S1, self.slot(h));
+            }
+            Instruction::GlobalSet { global_idx } => {
+                self.asm.ld(T0, S1, self.slot(h - 1));
+                self.asm.sd(T0, S5, *global_idx as i64 * 8);
+            }
+            Instruction::TableGet { table } => {
+                self.call_helper(Helper::TableGet, h - 1, *table as i64, 0);
+            }
+            Instruction::TableSet { table } => {
+                self.call_helper(Helper::TableSet, h - 2, *table as i64, 0);
+            }
+            Instruction::TableInit { elem_index, table } => {
+                self.call_helper(Helper::TableInit, h - 3, *elem_index as i64, *table as i64);
+            }
+            Instruction::ElemDrop { elem_index } => {
+                self.call_helper(Helper::ElemDrop, h, *elem_index as i64, 0);
+            }
+            Instruction::TableGrow { table } => {
+                self.call_helper(Helper::TableGrow, h - 2, *table as i64, 0);
+            }
+            Instruction::TableSize { table } => {
+                self.call_helper(Helper::TableSize, h, *table as i64, 0);
+            }
+            Instruction::TableFill { table } => {
+                self.call_helper(Helper::TableFill, h - 3, *table as i64, 0);
+            }
+            Instruction::RefNull { .. } => self.asm.sd(ZERO, S1, self.slot(h)),
+            Instruction::RefIsNull => {
+                self.asm.ld(T0, S1, self.slot(h - 1));
+                self.asm.seqz(T0, T0);
+                self.asm.sd(T0, S1, self.slot(h - 1));
+            }
+            Instruction::RefFunc { func_idx } => {
+                self.asm.li(T0, *func_idx as i64 + 1);
+                self.asm.sd(T0, S1, self.slot(h));
+            }
+            Instruction::I32Load { memarg } | Instruction::F32Load { memarg } => {
+                self.emit_load(memarg, Width::W, h)
+            }
+            Instruction::I64Load { memarg } | Instruction::F64Load { memarg } => {
+                self.emit_load(memarg, Width::D, h)
+            }
+            Instruction::I32Load8S { memarg } | Instruction::I64Load8S { memarg } => {
+                self.emit_load(memarg, Width::B, h)
+            }
+            Instruction::I32Load8U { memarg } | Instruction::I64Load8U { memarg } => {
+                self.emit_load(memarg, Width::Bu, h)
+            }
+            Instruction::I32Load16S { memarg } | Instruction::I64Load16S { memarg } => {
+                self.emit_load(memarg, Width::H, h)
+            }
+            Instruction::I32Load16U { memarg } | Instruction::I64Load16U { memarg } => {
+                self.emit_load(memarg, Width::Hu, h)
+            }
+            Instruction::I64Load32S { memarg } => self.emit_load(memarg, Width::W, h),
+            Instruction::I64Load32U { memarg } => self.emit_load(memarg, Width::Wu, h),
+            Instruction::I32Store { memarg }
+            | Instruction::F32Store { memarg }
+            | Instruction::I64Store32 { memarg } => self.emit_store(memarg, Width::W, h),
+            Instruction::I64Store { memarg } | Instruction::F64Store { memarg } => {
+                self.emit_store(memarg, Width::D, h)
+            }
+            Instruction::I32Store8 { memarg } | Instruction::I64Store8 { memarg } => {
+                self.emit_store(memarg, Width::B, h)
+            }
+            Instruction::I32Store16 { memarg } | Instruction::I64Store16 { memarg } => {
+                self.emit_store(memarg, Width::H, h)
+            }
+            Instruction::MemorySize { .. } => {
+                self.asm.srli(T0, S4, 16);
+                self.asm.sd(T0, S1, self.slot(h));
+            }
+            Instruction::MemoryGrow { .. } => {
+                self.call_helper(Helper::MemoryGrow, h - 1, 0, 0);
+                self.reload_memory();
+            }
+            Instruction::MemoryCopy { .. } => self.call_helper(Helper::MemoryCopy, h - 3, 0, 0),
+            Instruction::MemoryFill { .. } => self.call_helper(Helper::MemoryFill, h - 3, 0, 0),
+            Instruction::MemoryInit { data_index, .. } => {
+                self.call_helper(Helper::MemoryInit, h - 3, *data_index as i64, 0);
+            }
+            Instruction::DataDrop { data_index } => {
+                self.call_helper(Helper::DataDrop, h, *data_index as i64, 0);
+            }
+            Instruction::I32Const { value } => self.emit_const(*value as i64, h),
+            Instruction::I64Const { value } => self.emit_const(*value, h),
+            Instruction::F32Const { value } => self.emit_const(value.to_bits() as i64, h),
+            Instruction::F64Const { value } => self.emit_const(value.to_bits() as i64, h),
+            Instruction::I32Unop(op) => self.emit_i32_unop(op, h),
+            Instruction::I32Binop(op) => self.emit_int_binop(Width::W, int_binop_i32(op), h),
+            Instruction::I64Unop(op) => self.emit_i64_unop(op, h),
+            Instruction::I64Binop(op) => self.emit_int_binop(Width::D, int_binop_i64(op), h),
+            Instruction::F32Unop(op) => self.emit_float_unop(Fmt::S, f32_unop(op), h),
+            Instruction::F32Binop(op) => self.emit_float_binop(Fmt::S, float_binop_f32(op), h),
+            Instruction::F64Unop(op) => self.emit_float_unop(Fmt::D, f64_unop(op), h),
+            Instruction::F64Binop(op) => self.emit_float_binop(Fmt::D, float_binop_f64(op), h),
+            _ => return Err(anyhow!("{:?} is not supported by the riscv64 jit", inst)),
+        }
+        Ok(())
+    }
+
+    fn emit_block(&mut self, inst: &Instruction, ty: BlockType, h: u32) {
+        let nparams = block_type_num_params(self.module, ty) as u32;
+        let nresults = block_type_num_results(self.module, ty) as u32;
+        let label = self.asm.label();
+        let (kind, base) = match inst {
+            Instruction::Loop { .. } => (FrameKind::Loop, h - nparams),
+            Instruction::If { .. } => (FrameKind::If, h - 1 - nparams),
+            _ => (FrameKind::Block, h - nparams),
+        };
+        let mut frame = Frame {
+            kind,
+            base,
+            arity: if kind == FrameKind::Loop {
+                nparams
+            } else {
+                nresults
+            },
+            label,
+            else_label: None,
+            live: self.reachable,
+        };
+        if self.reachable {
+            match kind {
+                FrameKind::Loop => self.asm.bind(label),
+                FrameKind::If => {
+                    let else_label = self.asm.label();
+                    self.asm.load(Width::W, T0, S1, self.slot(h - 1));
+                    self.asm.branch(Cond::Eq, T0, ZERO, else_label);
+                    frame.else_label = Some(else_label);
+                }
+                _ => {}
+            }
+        }
+        self.frames.push(frame);
+    }
+
+    fn emit_else(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        if !frame.live {
+            return;
+        }
+        // the results of the then branch are already where the block ends
+        let (end, else_label) = (frame.label, frame.else_label.take().unwrap());
+        if self.reachable {
+            self.asm.jump(end);
+        }
+        self.asm.bind(else_label);
+        self.reachable = true;
+    }
+
+    fn emit_end(&mut self, h: u32) {
+        if self.frames.len() == 1 {
+            if self.reachable {
+                self.emit_return(h);
+            }
+            self.frames.pop();
+            return;
+        }
+        let frame = self.frames.pop().unwrap();
+        if !frame.live {
+            return;
+        }
+        if let Some(else_label) = frame.else_label {
+            self.asm.bind(else_label);
+        }
+        if frame.kind != FrameKind::Loop {
+            self.asm.bind(frame.label);
+        }
+        self.reachable = true;
+    }
+
+    /// Move the `n` values on top of the operand stack of height `h` to the
+    /// slots from `base` on.
+    fn move_values(&mut self, n: u32, h: u32, base: u32) {
+        if h - n == base {
+            return;
+        }
+        for i in 0..n {
+            self.asm.ld(T0, S1, self.slot(h - n + i));
+            self.asm.sd(T0, S1, self.slot(base + i));
+        }
+    }
+
+    /// Branch to the block `depth` levels out, the operand stack has height
+    /// `h`.
+    fn emit_branch(&mut self, depth: u32, h: u32) {
+        let frame = &self.frames[self.frames.len() - 1 - depth as usize];
+        if frame.kind == FrameKind::Func {
+            self.emit_return(h);
+            return;
+        }
+        let (arity, base, label) = (frame.arity, frame.base, frame.label);
+        self.move_values(arity, h, base);
+        self.asm.jump(label);
+    }
+
+    fn emit_br_if(&mut self, depth: u32, h: u32) {
+        let h = h - 1;
+        self.asm.load(Width::W, T0, S1, self.slot(h));
+        let frame = &self.frames[self.frames.len() - 1 - depth as usize];
+        if frame.kind != FrameKind::Func && (frame.arity == 0 || h - frame.arity == frame.base) {
+            let label = frame.label;
+            self.asm.branch(Cond::Ne, T0, ZERO, label);
+            return;
+        }
+        let skip = self.asm.label();
+        self.asm.branch(Cond::Eq, T0, ZERO, skip);
+        self.emit_branch(depth, h);
+        self.asm.bind(skip);
+    }
+
+    fn emit_br_table(&mut self, targets: &[u32], default_target: u32, h: u32) {
+        let h = h - 1;
+        // one stub per target moving the values of the branch
+        let mut stubs = BTreeMap::new();
+        for depth in targets.iter().chain([&default_target]) {
+            stubs.entry(*depth).or_insert_with(|| self.asm.label());
+        }
+
+        self.asm.load(Width::Wu, T0, S1, self.slot(h));
+        self.asm.li(T1, targets.len() as i64);
+        self.asm.branch(Cond::Geu, T0, T1, stubs[&default_target]);
+        // jump into a table of jumps to the stubs
+        let table = self.asm.label();
+        self.asm.load_label_address(T1, table);
+        self.asm.slli(T0, T0, 2);
+        self.asm.alu(Alu::Add, T1, T1, T0);
+        self.asm.jalr(ZERO, T1, 0);
+        self.asm.bind(table);
+        for depth in targets {
+            self.asm.jump(stubs[depth]);
+        }
+
+        for (depth, stub) in stubs {
+            self.asm.bind(stub);
+            self.emit_branch(depth, h);
+        }
+    }
+
+    /// Move the results to the start of the frame and return.
+    fn emit_return(&mut self, h: u32) {
+        let n = self.nresults;
+        if self.slot(h - n) != 0 {
+            for i in 0..n {
+                self.asm.ld(T0, S1, self.slot(h - n + i));
+                self.asm.sd(T0, S1, self.local(i));
+            }
+        }
+        self.asm.jump(self.epilogue);
+    }
+
+    fn emit_call(&mut self, func_idx: u32, h: u32) -> Result<()> {
+        let func = self
+            .module
+            .get_func(func_idx)
+            .ok_or_else(|| anyhow!("function {} not found", func_idx))?;
+        let frame = self.slot(h - func.get_sig().params().len() as u32);
+        self.asm.add_imm(S1, S1, frame);
+        self.asm.call_far(self.labels.funcs[func_idx as usize]);
+        self.asm.add_imm(S1, S1, -frame);
+        Ok(())
+    }
+
+    fn emit_call_indirect(&mut self, type_index: u32, table_index: u32, h: u32) -> Result<()> {
+        let sig = self
+            .module
+            .get_sig(type_index)
+            .ok_or_else(|| anyhow!("call_indirect: unknown type {}", type_index))?;
+        let frame = self.slot(h - 1 - sig.params().len() as u32);
+
+        // the helper replaces the element index with the function index
+        self.call_helper(
+            Helper::IndirectCallee,
+            h - 1,
+            type_index as i64,
+            table_index as i64,
+        );
+        self.asm.ld(T0, S1, self.slot(h - 1));
+        self.asm.ld(T1, S2, JitContext::FUNC_ADDRS);
+        self.asm.slli(T0, T0, 3);
+        self.asm.alu(Alu::Add, T1, T1, T0);
+        self.asm.ld(T1, T1, 0);
+        self.asm.add_imm(S1, S1, frame);
+        self.asm.jalr(RA, T1, 0);
+        self.asm.add_imm(S1, S1, -frame);
+        Ok(())
+    }
+
+    /// Call `helper` with the operand slots from `height` on and the
+    /// immediates `a` and `b`, the jit code exits if it fails.
+    fn call_helper(&mut self, helper: Helper, height: u32, a: i64, b: i64) {
+        self.asm.mv(A0, S2);
+        self.asm.add_imm(A1, S1, self.slot(height));
+        self.asm.li(A2, a);
+        self.asm.li(A3, b);
+        self.asm.ld(T0, S2, JitContext::helper_offset(helper));
+        self.asm.jalr(RA, T0, 0);
+        let error = self.error_label();
+        self.asm.branch(Cond::Ne, A0, ZERO, error);
+    }
+
+    /// Replace the operand at `height` with `helper` of it.
+    fn call_pure_helper(&mut self, helper: Helper, width: Width, height: u32) {
+        self.asm.load(width, A0, S1, self.slot(height));
+        self.asm.ld(T0, S2, JitContext::helper_offset(helper));
+        self.asm.jalr(RA, T0, 0);
+        self.asm.sd(A0, S1, self.slot(height));
+    }
+
+    /// Memory 0 may have grown, read its base and size again.
+    fn reload_memory(&mut self) {
+        self.asm.ld(S3, S2, JitContext::MEM_BASE);
+        self.asm.ld(S4, S2, JitContext::MEM_LEN);
+    }
+
+    /// Check that the `width` bytes at the address in t0 plus `offset` are
+    /// within memory 0, leaves their end in t1.
+    fn emit_bounds_check(&mut self, memarg: &MemArg, width: u64) {
+        let out_of_bounds = self.trap_label(TrapKind::OutOfBoundsMemory);
+        self.asm.add_imm(T1, T0, (memarg.offset + width) as i64);
+        self.asm.branch(Cond::Ltu, S4, T1, out_of_bounds);
+        self.asm.alu(Alu::Add, T1, T1, S3);
+    }
+
+    fn emit_load(&mut self, memarg: &MemArg, width: Width, h: u32) {
+        self.asm.load(Width::Wu, T0, S1, self.slot(h - 1));
+        self.emit_bounds_check(memarg, width.bytes());
+        self.asm.load(width, T2, T1, -(width.bytes() as i64));
+        self.asm.sd(T2, S1, self.slot(h - 1));
+    }
+
+    fn emit_store(&mut self, memarg: &MemArg, width: Width, h: u32) {
+        self.asm.load(Width::Wu, T0, S1, self.slot(h - 2));
+        self.emit_bounds_check(memarg, width.bytes());
+        self.asm.ld(T2, S1, self.slot(h - 1));
+        self.asm.store(width, T2, T1, -(width.bytes() as i64));
+    }
+
+    fn emit_const(&mut self, raw: i64, h: u32) {
+        self.asm.li(T0, raw);
+        self.asm.sd(T0, S1, self.slot(h));
+    }
+
+    fn emit_i32_unop(&mut self, op: &I32Unop, h: u32) {
+        let slot = self.slot(h - 1);
+        match op {
+            I32Unop::Eqz => {
+                self.asm.load(Width::W, T0, S1, slot);
+                self.asm.seqz(T0, T0);
+                self.asm.sd(T0, S1, slot);
+            }
+            I32Unop::Clz => self.call_pure_helper(Helper::I32Clz, Width::W, h - 1),
+            I32Unop::Ctz => self.call_pure_helper(Helper::I32Ctz, Width::W, h - 1),
+            I32Unop::Popcnt => self.call_pure_helper(Helper::I32Popcnt, Width::W, h - 1),
+            I32Unop::Extend8S => self.emit_sign_extend(56, h),
+            I32Unop::Extend16S => self.emit_sign_extend(48, h),
+            I32Unop::F64ConvertI32S => self.emit_int_to_float(Width::W, IntType::W, Fmt::D, h),
+            I32Unop::F64ConvertI32U => self.emit_int_to_float(Width::W, IntType::Wu, Fmt::D, h),
+            I32Unop::F32ConvertI32S => self.emit_int_to_float(Width::W, IntType::W, Fmt::S, h),
+            I32Unop::F32ConvertI32U => self.emit_int_to_float(Width::W, IntType::Wu, Fmt::S, h),
+            I32Unop::I64ExtendI32S => self.emit_sign_extend(32, h),
+            I32Unop::I64ExtendI32U => {
+                self.asm.load(Width::Wu, T0, S1, slot);
+                self.asm.sd(T0, S1, slot);
+            }
+            // the low 32 bits are the same
+            I32Unop::F32ReinterpretI32 => {}
+        }
+    }
+
+    fn emit_i64_unop(&mut self, op: &I64Unop, h: u32) {
+        let slot = self.slot(h - 1);
+        match op {
+            I64Unop::Eqz => {
+                self.asm.ld(T0, S1, slot);
+                self.asm.seqz(T0, T0);
+                self.asm.sd(T0, S1, slot);
+            }
+            I64Unop::Clz => self.call_pure_helper(Helper::I64Clz, Width::D, h - 1),
+            I64Unop::Ctz => self.call_pure_helper(Helper::I64Ctz, Width::D, h - 1),
+            I64Unop::Popcnt => self.call_pure_helper(Helper::I64Popcnt, Width::D, h - 1),
+            I64Unop::Extend8S => self.emit_sign_extend(56, h),
+            I64Unop::Extend16S => self.emit_sign_extend(48, h),
+            I64Unop::Extend32S => self.emit_sign_extend(32, h),
+            I64Unop::F32ConvertI64S => self.emit_int_to_float(Width::D, IntType::L, Fmt::S, h),
+            I64Unop::F32ConvertI64U => self.emit_int_to_float(Width::D, IntType::Lu, Fmt::S, h),
+            I64Unop::F64ConvertI64S => self.emit_int_to_float(Width::D, IntType::L, Fmt::D, h),
+            I64Unop::F64ConvertI64U => self.emit_int_to_float(Width::D, IntType::Lu, Fmt::D, h),
+            // an i32 is read from the low 32 bits of its slot
+            I64Unop::I32WrapI64 | I64Unop::F64ReinterpretI64 => {}
+        }
+    }
+
+    /// Sign-extend the low `64 - shift` bits of the operand on top.
+    fn emit_sign_extend(&mut self, shift: u32, h: u32) {
+        let slot = self.slot(h - 1);
+        self.asm.ld(T0, S1, slot);
+        self.asm.slli(T0, T0, shift);
+        self.asm.srai(T0, T0, shift);
+        self.asm.sd(T0, S1, slot);
+    }
+
+    fn emit_int_to_float(&mut self, width: Width, ty: IntType, fmt: Fmt, h: u32) {
+        let slot = self.slot(h - 1);
+        self.asm.load(width, T0, S1, slot);
+        self.asm.fcvt_from_int(ty, fmt, FT0, T0);
+        self.asm.fstore(fmt, FT0, S1, slot);
+    }
+
+    fn emit_int_binop(&mut self, width: Width, op: IntBinop, h: u32) {
+        let (a, b) = (self.slot(h - 2), self.slot(h - 1));
+        self.asm.load(width, T0, S1, a);
+        self.asm.load(width, T1, S1, b);
+        let word = width == Width::W;
+        match op {
+            IntBinop::Alu(op) => self.asm.alu(op, T0, T0, T1),
+            IntBinop::Cmp(cond) => self.emit_int_compare(cond),
+            IntBinop::Div { signed, rem } => self.emit_division(word, signed, rem),
+            IntBinop::Rotl | IntBinop::Rotr => {
+                let (sll, srl) = if word {
+                    (Alu::Sllw, Alu::Srlw)
+                } else {
+                    (Alu::Sll, Alu::Srl)
+                };
+                let (first, second) = if op == IntBinop::Rotl {
+                    (sll, srl)
+                } else {
+                    (srl, sll)
+                };
+                // the shifts only take the low bits of the negated count
+                self.asm.alu(first, T2, T0, T1);
+                self.asm.alu(Alu::Sub, T3, ZERO, T1);
+                self.asm.alu(second, T3, T0, T3);
+                self.asm.alu(Alu::Or, T0, T2, T3);
+            }
+        }
+        self.asm.sd(T0, S1, a);
+    }
+
+    /// t0 = t0 `cond` t1, the comparisons the branches do not have are the
+    /// negation or the reverse of one they have.
+    fn emit_int_compare(&mut self, cond: IntCompare) {
+        match cond {
+            IntCompare::Eq | IntCompare::Ne => {
+                self.asm.alu(Alu::Sub, T0, T0, T1);
+                if cond == IntCompare::Eq {
+                    self.asm.seqz(T0, T0);
+                } else {
+                    self.asm.snez(T0, T0);
+                }
+            }
+            IntCompare::Lt(unsigned) => self.asm.alu(slt(unsigned), T0, T0, T1),
+            IntCompare::Gt(unsigned) => self.asm.alu(slt(unsigned), T0, T1, T0),
+            IntCompare::Le(unsigned) => {
+                self.asm.alu(slt(unsigned), T0, T1, T0);
+                self.asm.xori(T0, T0, 1);
+            }
+            IntCompare::Ge(unsigned) => {
+                self.asm.alu(slt(unsigned), T0, T0, T1);
+                self.asm.xori(T0, T0, 1);
+            }
+        }
+    }
+
+    /// t0 = t0 / t1 or t0 % t1, trapping like wasm does: the division
+    /// instructions give a result for every operand.
+    fn emit_division(&mut self, word: bool, signed: bool, rem: bool) {
+        let division_by_zero = self.trap_label(TrapKind::DivisionByZero);
+        self.asm.branch(Cond::Eq, T1, ZERO, division_by_zero);
+        if signed && !rem {
+            // the smallest value divided by -1 overflows
+            let ok = self.asm.label();
+            let overflow = self.trap_label(TrapKind::IntegerOverflow);
+            let min = if word { i32::MIN as i64 } else { i64::MIN };
+            self.asm.li(T2, -1);
+            self.asm.branch(Cond::Ne, T1, T2, ok);
+            self.asm.li(T2, min);
+            self.asm.branch(Cond::Eq, T0, T2, overflow);
+            self.asm.bind(ok);
+        }
+        let op = match (word, signed, rem) {
+            (true, true, false) => Alu::Divw,
+            (true, false, false) => Alu::Divuw,
+            (true, true, true) => Alu::Remw,
+            (true, false, true) => Alu::Remuw,
+            (false, true, false) => Alu::Div,
+            (false, false, false) => Alu::Divu,
+            (false, true, true) => Alu::Rem,
+            (false, false, true) => Alu::Remu,
+        };
+        self.asm.alu(op, T0, T0, T1);
+    }
+
+    fn emit_float_unop(&mut self, fmt: Fmt, op: FloatUnop, h: u32) {
+        let slot = self.slot(h - 1);
+        let width = if fmt == Fmt::S { Width::W } else { Width::D };
+        match op {
+            FloatUnop::Abs | FloatUnop::Neg | FloatUnop::Sqrt => {
+                self.asm.fload(fmt, FT0, S1, slot);
+                match op {
+                    FloatUnop::Abs => self.asm.fpu(Fpu::SignInjectXor, fmt, FT0, FT0, FT0),
+                    FloatUnop::Neg => self.asm.fpu(Fpu::SignInjectNeg, fmt, FT0, FT0, FT0),
+                    _ => self.asm.fsqrt(fmt, FT0, FT0),
+                }
+                self.asm.fstore(fmt, FT0, S1, slot);
+            }
+            FloatUnop::Round(helper) => self.call_pure_helper(helper, width, h - 1),
+            FloatUnop::Truncate(ty, saturating) => {
+                self.asm.fload(fmt, FT0, S1, slot);
+                if saturating {
+                    // the conversion clamps, only NaN has to become 0
+                    self.asm.fcvt_to_int(ty, fmt, T0, FT0, Rounding::TowardZero);
+                    self.asm.fcmp(FCmp::Eq, fmt, T1, FT0, FT0);
+                    self.asm.alu(Alu::Sub, T1, ZERO, T1);
+                    self.asm.alu(Alu::And, T0, T0, T1);
+                } else {
+                    let invalid = self.trap_label(TrapKind::InvalidConversion);
+                    let overflow = self.trap_label(TrapKind::IntegerOverflow);
+                    self.asm.fcmp(FCmp::Eq, fmt, T0, FT0, FT0);
+                    self.asm.branch(Cond::Eq, T0, ZERO, invalid);
+                    // a value out of range raises the invalid flag
+                    self.asm.clear_fflags();
+                    self.asm.fcvt_to_int(ty, fmt, T0, FT0, Rounding::TowardZero);
+                    self.asm.read_fflags(T1);
+                    self.asm.andi(T1, T1, super::asm::FFLAGS_INVALID);
+                    self.asm.branch(Cond::Ne, T1, ZERO, overflow);
+                }
+                self.asm.sd(T0, S1, slot);
+            }
+            FloatUnop::Convert => {
+                let to = if fmt == Fmt::S { Fmt::D } else { Fmt::S };
+                self.asm.fload(fmt, FT0, S1, slot);
+                self.asm.fcvt_float(to, FT0, FT0);
+                self.asm.fstore(to, FT0, S1, slot);
+            }
+            FloatUnop::Reinterpret => {}
+        }
+    }
+
+    fn emit_float_binop(&mut self, fmt: Fmt, op: FloatBinop, h: u32) {
+        let (a, b) = (self.slot(h - 2), self.slot(h - 1));
+        self.asm.fload(fmt, FT0, S1, a);
+        self.asm.fload(fmt, FT1, S1, b);
+        match op {
+            FloatBinop::Arith(op) => {
+                self.asm.fpu(op, fmt, FT0, FT0, FT1);
+                self.asm.fstore(fmt, FT0, S1, a);
+            }
+            FloatBinop::MinMax(op) => {
+                // fmin and fmax return the other operand if one is NaN,
+                // wasm returns NaN, which the addition gives
+                let done = self.asm.label();
+                self.asm.fcmp(FCmp::Eq, fmt, T0, FT0, FT0);
+                self.asm.fcmp(FCmp::Eq, fmt, T1, FT1, FT1);
+                self.asm.alu(Alu::And, T0, T0, T1);
+                self.asm.fpu(op, fmt, FT2, FT0, FT1);
+                self.asm.branch(Cond::Ne, T0, ZERO, done);
+                self.asm.fpu(Fpu::Add, fmt, FT2, FT0, FT1);
+                self.asm.bind(done);
+                self.asm.fstore(fmt, FT2, S1, a);
+            }
+            FloatBinop::Cmp(cmp, swap, negate) => {
+                if swap {
+                    self.asm.fcmp(cmp, fmt, T0, FT1, FT0);
+                } else {
+                    self.asm.fcmp(cmp, fmt, T0, FT0, FT1);
+                }
+                if negate {
+                    self.asm.xori(T0, T0, 1);
+                }
+                self.asm.sd(T0, S1, a);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntCompare {
+    Eq,
+    Ne,
+    /// the flag is whether the comparison is unsigned
+    Lt(bool),
+    Gt(bool),
+    Le(bool),
+    Ge(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntBinop {
+    Alu(Alu),
+    Cmp(IntCompare),
+    Div { signed: bool, rem: bool },
+    Rotl,
+    Rotr,
+}
+
+fn slt(unsigned: bool) -> Alu {
+    if unsigned {
+        Alu::Sltu
+    } else {
+        Alu::Slt
+    }
+}
+
+fn int_binop_i32(op: &I32Binop) -> IntBinop {
+    match op {
+        I32Binop::Eq => IntBinop::Cmp(IntCompare::Eq),
+        I32Binop::Ne => IntBinop::Cmp(IntCompare::Ne),
+        I32Binop::LtS => IntBinop::Cmp(IntCompare::Lt(false)),
+        I32Binop::LtU => IntBinop::Cmp(IntCompare::Lt(true)),
+        I32Binop::GtS => IntBinop::Cmp(IntCompare::Gt(false)),
+        I32Binop::GtU => IntBinop::Cmp(IntCompare::Gt(true)),
+        I32Binop::LeS => IntBinop::Cmp(IntCompare::Le(false)),
+        I32Binop::LeU => IntBinop::Cmp(IntCompare::Le(true)),
+        I32Binop::GeS => IntBinop::Cmp(IntCompare::Ge(false)),
+        I32Binop::GeU => IntBinop::Cmp(IntCompare::Ge(true)),
+        I32Binop::Add => IntBinop::Alu(Alu::Addw),
+        I32Binop::Sub => IntBinop::Alu(Alu::Subw),
+        I32Binop::Mul => IntBinop::Alu(Alu::Mulw),
+        I32Binop::DivS => IntBinop::Div {
+            signed: true,
+            rem: false,
+        },
+        I32Binop::DivU => IntBinop::Div {
+            signed: false,
+            rem: false,
+        },
+        I32Binop::RemS => IntBinop::Div {
+            signed: true,
+            rem: true,
+        },
+        I32Binop::RemU => IntBinop::Div {
+            signed: false,
+            rem: true,
+        },
+        I32Binop::And => IntBinop::Alu(Alu::And),
+        I32Binop::Or => IntBinop::Alu(Alu::Or),
+        I32Binop::Xor => IntBinop::Alu(Alu::Xor),
+        I32Binop::Shl => IntBinop::Alu(Alu::Sllw),
+        I32Binop::ShrS => IntBinop::Alu(Alu::Sraw),
+        I32Binop::ShrU => IntBinop::Alu(Alu::Srlw),
+        I32Binop::Rotl => IntBinop::Rotl,
+        I32Binop::Rotr => IntBinop::Rotr,
+    }
+}
+
+fn int_binop_i64(op: &I64Binop) -> IntBinop {
+    match op {
+        I64Binop::Eq => IntBinop::Cmp(IntCompare::Eq),
+        I64Binop::Ne => IntBinop::Cmp(IntCompare::Ne),
+        I64Binop::LtS => IntBinop::Cmp(IntCompare::Lt(false)),
+        I64Binop::LtU => IntBinop::Cmp(IntCompare::Lt(true)),
+        I64Binop::GtS => IntBinop::Cmp(IntCompare::Gt(false)),
+        I64Binop::GtU => IntBinop::Cmp(IntCompare::Gt(true)),
+        I64Binop::LeS => IntBinop::Cmp(IntCompare::Le(false)),
+        I64Binop::LeU => IntBinop::Cmp(IntCompare::Le(true)),
+        I64Binop::GeS => IntBinop::Cmp(IntCompare::Ge(false)),
+        I64Binop::GeU => IntBinop::Cmp(IntCompare::Ge(true)),
+        I64Binop::Add => IntBinop::Alu(Alu::Add),
+        I64Binop::Sub => IntBinop::Alu(Alu::Sub),
+        I64Binop::Mul => IntBinop::Alu(Alu::Mul),
+        I64Binop::DivS => IntBinop::Div {
+            signed: true,
+            rem: false,
+        },
+        I64Binop::DivU => IntBinop::Div {
+            signed: false,
+            rem: false,
+        },
+        I64Binop::RemS => IntBinop::Div {
+            signed: true,
+            rem: true,
+        },
+        I64Binop::RemU => IntBinop::Div {
+            signed: false,
+            rem: true,
+        },
+        I64Binop::And => IntBinop::Alu(Alu::And),
+        I64Binop::Or => IntBinop::Alu(Alu::Or),
+        I64Binop::Xor => IntBinop::Alu(Alu::Xor),
+        I64Binop::Shl => IntBinop::Alu(Alu::Sll),
+        I64Binop::ShrS => IntBinop::Alu(Alu::Sra),
+        I64Binop::ShrU => IntBinop::Alu(Alu::Srl),
+        I64Binop::Rotl => IntBinop::Rotl,
+        I64Binop::Rotr => IntBinop::Rotr,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FloatBinop {
+    Arith(Fpu),
+    MinMax(Fpu),
+    /// the comparison, whether its operands are swapped and whether its
+    /// result is negated
+    Cmp(FCmp, bool, bool),
+}
+
+fn float_binop_f32(op: &F32Binop) -> FloatBinop {
+    match op {
+        F32Binop::Eq => FloatBinop::Cmp(FCmp::Eq, false, false),
+        F32Binop::Ne => FloatBinop::Cmp(FCmp::Eq, false, true),
+        F32Binop::Lt => FloatBinop::Cmp(FCmp::Lt, false, false),
+        F32Binop::Gt => FloatBinop::Cmp(FCmp::Lt, true, false),
+        F32Binop::Le => FloatBinop::Cmp(FCmp::Le, false, false),
+        F32Binop::Ge => FloatBinop::Cmp(FCmp::Le, true, false),
+        F32Binop::Add => FloatBinop::Arith(Fpu::Add),
+        F32Binop::Sub => FloatBinop::Arith(Fpu::Sub),
+        F32Binop::Mul => FloatBinop::Arith(Fpu::Mul),
+        F32Binop::Div => FloatBinop::Arith(Fpu::Div),
+        F32Binop::Min => FloatBinop::MinMax(Fpu::Min),
+        F32Binop::Max => FloatBinop::MinMax(Fpu::Max),
+        F32Binop::Copysign => FloatBinop::Arith(Fpu::SignInject),
+    }
+}
+
+fn float_binop_f64(op: &F64Binop) -> FloatBinop {
+    match op {
+        F64Binop::Eq => FloatBinop::Cmp(FCmp::Eq, false, false),
+        F64Binop::Ne => FloatBinop::Cmp(FCmp::Eq, false, true),
+        F64Binop::Lt => FloatBinop::Cmp(FCmp::Lt, false, false),
+        F64Binop::Gt => FloatBinop::Cmp(FCmp::Lt, true, false),
+        F64Binop::Le => FloatBinop::Cmp(FCmp::Le, false, false),
+        F64Binop::Ge => FloatBinop::Cmp(FCmp::Le, true, false),
+        F64Binop::Add => FloatBinop::Arith(Fpu::Add),
+        F64Binop::Sub => FloatBinop::Arith(Fpu::Sub),
+        F64Binop::Mul => FloatBinop::Arith(Fpu::Mul),
+        F64Binop::Div => FloatBinop::Arith(Fpu::Div),
+        F64Binop::Min => FloatBinop::MinMax(Fpu::Min),
+        F64Binop::Max => FloatBinop::MinMax(Fpu::Max),
+        F64Binop::Copysign => FloatBinop::Arith(Fpu::SignInject),
+    }
+}
+
+fn f32_unop(op: &F32Unop) -> FloatUnop {
+    match op {
+        F32Unop::Abs => FloatUnop::Abs,
+        F32Unop::Neg => FloatUnop::Neg,
+        F32Unop::Ceil => FloatUnop::Round(Helper::F32Ceil),
+        F32Unop::Floor => FloatUnop::Round(Helper::F32Floor),
+        F32Unop::Trunc => FloatUnop::Round(Helper::F32Trunc),
+        F32Unop::Nearest => FloatUnop::Round(Helper::F32Nearest),
+        F32Unop::Sqrt => FloatUnop::Sqrt,
+        F32Unop::I32TruncF32S => FloatUnop::Truncate(IntType::W, false),
+        F32Unop::I32TruncF32U => FloatUnop::Truncate(IntType::Wu, false),
+        F32Unop::I64TruncF32S => FloatUnop::Truncate(IntType::L, false),
+        F32Unop::I64TruncF32U => FloatUnop::Truncate(IntType::Lu, false),
+        F32Unop::F64PromoteF32 => FloatUnop::Convert,
+        F32Unop::I32ReinterpretF32 => FloatUnop::Reinterpret,
+        F32Unop::I32TruncSatF32S => FloatUnop::Truncate(IntType::W, true),
+        F32Unop::I32TruncSatF32U => FloatUnop::Truncate(IntType::Wu, true),
+        F32Unop::I64TruncSatF32S => FloatUnop::Truncate(IntType::L, true),
+        F32Unop::I64TruncSatF32U => FloatUnop::Truncate(IntType::Lu, true),
+    }
+}
+
+fn f64_unop(op: &F64Unop) -> FloatUnop {
+    match op {
+        F64Unop::Abs => FloatUnop::Abs,
+        F64Unop::Neg => FloatUnop::Neg,
+        F64Unop::Ceil => FloatUnop::Round(Helper::F64Ceil),
+        F64Unop::Floor => FloatUnop::Round(Helper::F64Floor),
+        F64Unop::Trunc => FloatUnop::Round(Helper::F64Trunc),
+        F64Unop::Nearest => FloatUnop::Round(Helper::F64Nearest),
+        F64Unop::Sqrt => FloatUnop::Sqrt,
+        F64Unop::I32TruncF64S => FloatUnop::Truncate(IntType::W, false),
+        F64Unop::I32TruncF64U => FloatUnop::Truncate(IntType::Wu, false),
+        F64Unop::I64TruncF64S => FloatUnop::Truncate(IntType::L, false),
+        F64Unop::I64TruncF64U => FloatUnop::Truncate(IntType::Lu, false),
+        F64Unop::F32DemoteF64 => FloatUnop::Convert,
+        F64Unop::I64ReinterpretF64 => FloatUnop::Reinterpret,
+        F64Unop::I32TruncSatF64S => FloatUnop::Truncate(IntType::W, true),
+        F64Unop::I32TruncSatF64U => FloatUnop::Truncate(IntType::Wu, true),
+        F64Unop::I64TruncSatF64S => FloatUnop::Truncate(IntType::L, true),
+        F64Unop::I64TruncSatF64U => FloatUnop::Truncate(IntType::Lu, true),
+    }
+}
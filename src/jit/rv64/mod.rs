@@ -0,0 +1,272 @@
+//! A jit backend for riscv64 (RV64GC), the x86 backend only runs on
+//! x86-64. It is a plain translation of every instruction to a short RV64IMFD
+//! sequence over slots of the value stack, see `codegen`, without a register
+//! allocator. Anything but the instructions themselves, memory growth, host
+//! calls, tables, bulk memory and the operations without an RV64 instruction,
+//! is done by Rust helpers, see `runtime`.
+//!
+//! Traps are checked for explicitly rather than with signal handlers: the jit
+//! code exits to the entry with a status naming the trap. Memory 0 is
+//! accessed in place with a bounds check, the code does not rely on guard
+//! pages either.
+
+mod asm;
+mod codegen;
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use monoasm::CodePtr;
+use wasmparser::ValType;
+
+use self::{
+    asm::{Assembler, Cond, Label, A0, A1, A2, RA, S1, S2, S3, S4, S5, SP, T0, ZERO},
+    codegen::{FuncCodegen, ModuleLabels},
+};
+use super::{
+    runtime::{self, Helper, JitContext, STATUS_OK},
+    JitSettings, WasmJitCompiler,
+};
+use crate::{
+    module::{value_type::WasmValue, wasm_module::WasmModule},
+    vm::Store,
+};
+
+/// The slots of the value stack, the frames of all active functions.
+const VALUE_STACK_SLOTS: usize = 1 << 20;
+
+/// The entry of the jit code, returns a status, see `runtime`.
+type Rv64Entry = extern "C" fn(ctx: *mut JitContext) -> u64;
+
+pub struct Rv64JitCompiler<'a> {
+    module: Rc<WasmModule<'a>>,
+    /// the state of the jit code, boxed as the code holds its address
+    ctx: Box<JitContext<'a>>,
+    max_call_depth: usize,
+    code: Option<ExecutableCode>,
+    func_addrs: Vec<u64>,
+    /// the frames of the wasm functions, the one of the entry function
+    /// first
+    value_stack: Vec<u64>,
+    /// the types of the results of the entry function
+    results: Vec<ValType>,
+}
+
+impl<'a> Rv64JitCompiler<'a> {
+    /// A compiler of the code of the instance running on `store` with
+    /// `settings`, of which it applies the call depth and the limits.
+    pub(crate) fn new(
+        module: Rc<WasmModule<'a>>,
+        store: Rc<Store<'a>>,
+        settings: &JitSettings,
+    ) -> Result<Self> {
+        let mut ctx = Box::new(JitContext::new(Rc::clone(&module), store)?);
+        ctx.default_memory_maximum = settings.default_memory_maximum;
+        ctx.max_memory_pages = settings.max_memory_pages;
+        ctx.max_table_elements = settings.max_table_elements;
+        ctx.limiter = settings.limiter.clone();
+        Ok(Self {
+            module,
+            ctx,
+            max_call_depth: settings.max_call_depth,
+            code: None,
+            func_addrs: Vec::new(),
+            value_stack: Vec::new(),
+            results: Vec::new(),
+        })
+    }
+
+    /// Why the backend cannot run `module`, if it cannot.
+    pub fn unsupported(module: &WasmModule) -> Option<String> {
+        runtime::unsupported(module)
+    }
+
+    /// Run the entry returned by `compile`, returns the results of the entry
+    /// function as raw values.
+    pub(crate) fn run(&mut self, entry: CodePtr) -> Result<Vec<u64>> {
+        let f: Rv64Entry = unsafe { std::mem::transmute(entry) };
+        let status = f(&mut *self.ctx);
+        self.ctx.store_globals();
+        if status != STATUS_OK {
+            return Err(self.ctx.take_error(status));
+        }
+        Ok(self.value_stack[..self.results.len()].to_vec())
+    }
+
+    /// Emit the entry of the jit code and the exit the code takes when it
+    /// traps, the entry keeps the registers the jit code uses.
+    fn emit_entry(asm: &mut Assembler, labels: &ModuleLabels) -> Label {
+        let saved = [RA, S1, S2, S3, S4, S5];
+        let entry = asm.label();
+        let restore = asm.label();
+        asm.bind(entry);
+        asm.addi(SP, SP, -64);
+        for (i, reg) in saved.iter().enumerate() {
+            asm.sd(*reg, SP, i as i64 * 8);
+        }
+        asm.mv(S2, A0);
+        asm.sd(SP, S2, JitContext::ENTRY_SP);
+        asm.ld(S1, S2, JitContext::ENTRY_FRAME);
+        asm.ld(S3, S2, JitContext::MEM_BASE);
+        asm.ld(S4, S2, JitContext::MEM_LEN);
+        asm.ld(S5, S2, JitContext::GLOBALS);
+        asm.ld(T0, S2, JitContext::ENTRY_FUNC);
+        asm.jalr(RA, T0, 0);
+        asm.li(A0, STATUS_OK as i64);
+
+        asm.bind(restore);
+        for (i, reg) in saved.iter().enumerate() {
+            asm.ld(*reg, SP, i as i64 * 8);
+        }
+        asm.addi(SP, SP, 64);
+        asm.ret();
+
+        // the status is in a0 already
+        asm.bind(labels.exit);
+        asm.ld(SP, S2, JitContext::ENTRY_SP);
+        asm.jump(restore);
+        entry
+    }
+
+    /// Emit the code of imported function `import`, which calls the host
+    /// with the arguments in its frame.
+    fn emit_import(asm: &mut Assembler, labels: &ModuleLabels, import: u32) {
+        let ok = asm.label();
+        asm.bind(labels.funcs[import as usize]);
+        asm.addi(SP, SP, -16);
+        asm.sd(RA, SP, 8);
+        asm.mv(A0, S2);
+        asm.mv(A1, S1);
+        asm.li(A2, import as i64);
+        asm.ld(T0, S2, JitContext::helper_offset(Helper::HostCall));
+        asm.jalr(RA, T0, 0);
+        asm.ld(RA, SP, 8);
+        asm.addi(SP, SP, 16);
+        asm.branch(Cond::Eq, A0, ZERO, ok);
+        asm.jump_far(labels.exit);
+        asm.bind(ok);
+        // the host may have grown the memory
+        asm.ld(S3, S2, JitContext::MEM_BASE);
+        asm.ld(S4, S2, JitContext::MEM_LEN);
+        asm.ret();
+    }
+}
+
+impl WasmJitCompiler for Rv64JitCompiler<'_> {
+    fn compile(&mut self, entry_index: u32, params: Vec<WasmValue>) -> Result<CodePtr> {
+        if let Some(reason) = Self::unsupported(&self.module) {
+            return Err(anyhow!("the riscv64 jit does not support {}", reason));
+        }
+        let module = Rc::clone(&self.module);
+        let entry_func = module
+            .get_func(entry_index)
+            .ok_or_else(|| anyhow!("function {} not found", entry_index))?;
+        let heights = module.validate_stack_heights()?;
+
+        let mut asm = Assembler::default();
+        let labels = ModuleLabels {
+            funcs: module.get_funcs().iter().map(|_| asm.label()).collect(),
+            exit: asm.label(),
+        };
+        let entry = Self::emit_entry(&mut asm, &labels);
+        let num_imports = module.get_num_func_imports();
+        for import in 0..num_imports as u32 {
+            Self::emit_import(&mut asm, &labels, import);
+        }
+        for (func_index, func) in module.get_funcs().iter().enumerate().skip(num_imports) {
+            let heights = &heights[func_index - num_imports];
+            FuncCodegen::new(&mut asm, &module, &labels, func, heights)
+                .emit(labels.funcs[func_index], func)?;
+        }
+
+        let entry_offset = asm.label_offset(entry).unwrap();
+        let func_offsets = labels
+            .funcs
+            .iter()
+            .map(|label| asm.label_offset(*label).unwrap())
+            .collect::<Vec<_>>();
+        let code = ExecutableCode::new(&asm.finish()?)?;
+        self.func_addrs = func_offsets.iter().map(|off| code.addr(*off)).collect();
+
+        // the entry function's frame starts with its parameters
+        self.value_stack = vec![0; VALUE_STACK_SLOTS];
+        for (slot, param) in self.value_stack.iter_mut().zip(&params) {
+            *slot = param.to_raw();
+        }
+        self.results = entry_func.get_sig().results().to_vec();
+
+        let ctx = &mut self.ctx;
+        ctx.load_state();
+        ctx.func_addrs = self.func_addrs.as_ptr();
+        ctx.stack_end = self.value_stack.as_ptr_range().end as u64;
+        ctx.max_depth = self.max_call_depth as u64;
+        ctx.entry_func = self.func_addrs[entry_index as usize];
+        ctx.entry_frame = self.value_stack.as_mut_ptr() as u64;
+
+        let entry = code.addr(entry_offset);
+        self.code = Some(code);
+        Ok(unsafe { std::mem::transmute::<u64, CodePtr>(entry) })
+    }
+}
+
+/// Code mapped executable, unmapped on drop.
+struct ExecutableCode {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl ExecutableCode {
+    fn new(code: &[u8]) -> Result<Self> {
+        let len = code.len().max(1);
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(anyhow!("mmap failed: {}", std::io::Error::last_os_error()));
+        }
+        let mapped = Self { ptr, len };
+        unsafe {
+            std::ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, code.len());
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return Err(anyhow!(
+                    "mprotect failed: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+        mapped.flush_icache();
+        Ok(mapped)
+    }
+
+    /// The instruction cache is not coherent with stores on riscv.
+    #[cfg(target_arch = "riscv64")]
+    fn flush_icache(&self) {
+        const SYS_RISCV_FLUSH_ICACHE: libc::c_long = 259;
+        let start = self.ptr as usize;
+        unsafe {
+            libc::syscall(SYS_RISCV_FLUSH_ICACHE, start, start + self.len, 0);
+        }
+    }
+
+    #[cfg(not(target_arch = "riscv64"))]
+    fn flush_icache(&self) {}
+
+    fn addr(&self, offset: usize) -> u64 {
+        self.ptr as u64 + offset as u64
+    }
+}
+
+impl Drop for ExecutableCode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! The settings the jit code of an instance is compiled with, one value for
+//! every backend: `X86JitCompiler`, with the tiering of the interpreter, and
+//! the riscv64 and Cranelift jits. A backend applies the settings it
+//! supports, the calls needing the others run in the interpreter, see
+//! `WasmInterpreter::unsupported_in_place`.
+
+use std::{cell::Cell, path::PathBuf, rc::Rc};
+
+use crate::vm::{Epoch, SharedLimiter};
+
+#[derive(Clone)]
+pub(crate) struct JitSettings {
+    pub(crate) max_call_depth: usize,
+    /// the fuel left in the instance, the code is metered if there is some
+    /// when it is compiled
+    pub(crate) fuel: Rc<Cell<Option<u64>>>,
+    /// the epoch the code checks against the deadline of each call
+    pub(crate) epoch: Option<Epoch>,
+    /// asked before growing a memory or a table
+    pub(crate) limiter: Option<SharedLimiter>,
+    pub(crate) trace: bool,
+    pub(crate) debug_info: bool,
+    pub(crate) debug_break: bool,
+    pub(crate) perf_map: bool,
+    pub(crate) backtrace: bool,
+    pub(crate) canonicalize_nans: bool,
+    /// the maximum of the memories declared without one
+    pub(crate) default_memory_maximum: u64,
+    pub(crate) max_memory_pages: u64,
+    pub(crate) max_table_elements: u64,
+    pub(crate) lazy: bool,
+    pub(crate) cache_dir: Option<PathBuf>,
+}
+
+impl JitSettings {
+    /// Whether the code compiled now is metered.
+    pub(crate) fn metered(&self) -> bool {
+        self.fuel.get().is_some()
+    }
+}
+
+/// The same code is compiled with both: the same knobs, the same fuel and
+/// epoch, and a limiter in both or neither. Whether the code is metered is
+/// decided when it is compiled, see `metered`.
+impl PartialEq for JitSettings {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_call_depth == other.max_call_depth
+            && Rc::ptr_eq(&self.fuel, &other.fuel)
+            && self.epoch.as_ref().map(Epoch::as_ptr) == other.epoch.as_ref().map(Epoch::as_ptr)
+            && self.limiter.is_some() == other.limiter.is_some()
+            && self.trace == other.trace
+            && self.debug_info == other.debug_info
+            && self.debug_break == other.debug_break
+            && self.perf_map == other.perf_map
+            && self.backtrace == other.backtrace
+            && self.canonicalize_nans == other.canonicalize_nans
+            && self.default_memory_maximum == other.default_memory_maximum
+            && self.max_memory_pages == other.max_memory_pages
+            && self.max_table_elements == other.max_table_elements
+            && self.lazy == other.lazy
+            && self.cache_dir == other.cache_dir
+    }
+}
@@ -0,0 +1,761 @@
+//! The jit code of a module cached on disk, see
+//! `WasmInterpreter::jit_cache_dir`. The code `setup` compiles up front only
+//! depends on the module and on the few settings that change what is
+//! emitted, so it is saved with where its labels are bound and loaded again
+//! by the next run instead of being compiled from scratch.
+//!
+//! The code only jumps and calls within itself relative to the instruction,
+//! the one thing tied to the process is the addresses it loads, which are
+//! all emitted by `emit_mov_addr`. Each one is saved as an offset in a region
+//! of the data of the compiler, see `data_regions`, or as an offset in the
+//! binary of the interpreter for the helpers. The addresses of this run are
+//! patched in before the code is emitted again, then it is relocated and
+//! registered like freshly compiled code.
+//!
+//! The file of a module is named after a SHA-256 digest of the module, of
+//! the settings that change the code and of the binary of the interpreter,
+//! and its header holds the version of the interpreter that wrote it. A file
+//! that cannot be used is compiled again and replaced. The lazy stubs, the
+//! entries and the code compiled once the instance runs are not cached.
+
+use std::{
+    ffi::c_void,
+    fs,
+    mem::{size_of, size_of_val, MaybeUninit},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{anyhow, Result};
+use monoasm::DestLabel;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    jit::{setup::trap::TrapSite, X86JitCompiler},
+    vm::TrapKind,
+};
+
+const MAGIC: &[u8; 4] = b"wjit";
+const VERSION: u32 = 2;
+/// the version of the interpreter, in the header of every file
+const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// every trap kind, by its code in the file
+const TRAP_KINDS: [TrapKind; 14] = [
+    TrapKind::Unreachable,
+    TrapKind::OutOfBoundsMemory,
+    TrapKind::OutOfBoundsTable,
+    TrapKind::DivisionByZero,
+    TrapKind::IntegerOverflow,
+    TrapKind::InvalidConversion,
+    TrapKind::UndefinedElement,
+    TrapKind::UninitializedElement,
+    TrapKind::CallIndirectMismatch,
+    TrapKind::StackExhausted,
+    TrapKind::FuelExhausted,
+    TrapKind::Interrupted,
+    TrapKind::Timeout,
+    TrapKind::UncaughtException,
+];
+
+/// The code `setup` emitted, the labels are kept as offsets from its start.
+struct CachedCode {
+    code: Vec<u8>,
+    /// the number of regions of `data_regions`
+    nregions: usize,
+    /// the offset of every address the code loads, with what it points to
+    relocs: Vec<(usize, Target)>,
+    func_starts: Vec<usize>,
+    func_ends: Vec<Option<usize>>,
+    /// the targets of the br_tables of each function, by function index
+    brtable_targets: Vec<(usize, Vec<Vec<usize>>)>,
+    trap_sites: Vec<(usize, TrapSite)>,
+    call_sites: Vec<(usize, u32, usize)>,
+    entry_exit: usize,
+    host_trap_exit: (usize, usize),
+}
+
+/// What an address loaded by the code points to.
+#[derive(Clone, Copy)]
+enum Target {
+    /// an offset in a region of `data_regions`, by index
+    Data(usize, u64),
+    /// an offset in the binary of the interpreter
+    Image(u64),
+}
+
+impl X86JitCompiler<'_> {
+    /// `setup` with the code saved in `cache_dir` by an earlier run, or
+    /// saving it there for the next one.
+    pub(crate) fn setup_cached(&mut self) -> Result<()> {
+        let Some(path) = self.cache_path() else {
+            // a module built in memory has no binary to key the code by
+            self.emit_setup_code()?;
+            self.finalize();
+            return Ok(());
+        };
+
+        match self.load_cached(&path) {
+            Ok((cached, code)) => {
+                log::debug!("loaded the jit code from {}", path.display());
+                self.emit_cached(&cached, &code);
+                self.finalize();
+                self.cache_hit = Some(true);
+                Ok(())
+            }
+            Err(e) => {
+                log::debug!("no usable jit code in {}: {}", path.display(), e);
+                self.cache_hit = Some(false);
+                self.compile_and_save(&path)
+            }
+        }
+    }
+
+    /// The file the code of the module is cached in, `None` if it cannot be
+    /// cached.
+    fn cache_path(&self) -> Option<PathBuf> {
+        let dir = self.cache_dir.as_ref()?;
+        let module = self.module.get_bytes();
+        if module.is_empty() {
+            return None;
+        }
+        // the helpers move with the binary, and a rebuild may change them
+        let binary = fs::metadata(std::env::current_exe().ok()?).ok()?;
+        let modified = binary.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        // every field has a fixed width or is prefixed by its length, so no
+        // two keys are digested from the same bytes
+        let mut key = Sha256::new();
+        key.update(VERSION.to_le_bytes());
+        key.update((COMPILER_VERSION.len() as u64).to_le_bytes());
+        key.update(COMPILER_VERSION);
+        key.update(binary.len().to_le_bytes());
+        key.update(modified.as_secs().to_le_bytes());
+        key.update(modified.subsec_nanos().to_le_bytes());
+        key.update((module.len() as u64).to_le_bytes());
+        key.update(module);
+        // the settings that change the code, the others only change how it
+        // is registered or entered
+        key.update(self.max_call_depth.to_le_bytes());
+        key.update([
+            self.fuel.is_some() as u8,
+            self.epoch.is_some() as u8,
+            self.limiter.is_some() as u8,
+            self.trace as u8,
+            self.canonicalize_nans as u8,
+        ]);
+        Some(dir.join(format!("{:x}.jit", key.finalize())))
+    }
+
+    /// Compile the code like `setup` and save it to `path`. The code runs
+    /// all the same if it cannot be saved.
+    fn compile_and_save(&mut self, path: &Path) -> Result<()> {
+        let start = self.jit.label();
+        self.emit_single_label(start);
+        self.emit_setup_code()?;
+        let end = self.jit.label();
+        self.emit_single_label(end);
+        // finalize hands the trap sites over to the trap handler
+        let trap_sites = self.trap_sites.clone();
+        self.finalize();
+
+        let saved = self
+            .cached_code(start, end, &trap_sites)
+            .and_then(|cached| save(path, &cached.to_bytes()));
+        match saved {
+            Ok(()) => log::debug!("saved the jit code to {}", path.display()),
+            Err(e) => log::warn!("failed to save the jit code to {}: {}", path.display(), e),
+        }
+        Ok(())
+    }
+
+    /// The relocated code between `start` and `end` with its labels and the
+    /// targets of the addresses it loads.
+    fn cached_code(
+        &self,
+        start: DestLabel,
+        end: DestLabel,
+        trap_sites: &[(DestLabel, TrapSite)],
+    ) -> Result<CachedCode> {
+        let start = self.jit.get_label_u64(start);
+        let end = self.jit.get_label_u64(end);
+        // SAFETY: the code is mapped readable and no longer written to once
+        // it is relocated
+        let code =
+            unsafe { std::slice::from_raw_parts(start as *const u8, (end - start) as usize) };
+        let offset = |label: DestLabel| (self.jit.get_label_u64(label) - start) as usize;
+
+        let regions = self.data_regions();
+        let image = own_image_base().ok_or_else(|| anyhow!("the binary is not mapped"))?;
+        let relocs = self
+            .addr_sites
+            .iter()
+            // only the missing limiter is null, the key covers it
+            .filter(|(_, addr)| *addr != 0)
+            .map(|(site, addr)| {
+                let region = regions
+                    .iter()
+                    .position(|(base, len)| (*base..*base + *len as u64).contains(addr));
+                let target = match region {
+                    Some(i) => Target::Data(i, addr - regions[i].0),
+                    None if image_base(*addr) == Some(image) => Target::Image(addr - image),
+                    // `data_regions` misses a region the code loads from,
+                    // which release builds survive by not caching the code
+                    None if cfg!(debug_assertions) => {
+                        panic!("jit cache: the code loads the unknown address {:#x}", addr)
+                    }
+                    None => return Err(anyhow!("the code loads the unknown address {:#x}", addr)),
+                };
+                // the address is the last 8 bytes of the movabs
+                Ok((offset(*site) - 8, target))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut brtable_targets = self
+            .brtable_nondefault_target_labels
+            .iter()
+            .map(|(func_index, tables)| {
+                let targets: Vec<Vec<usize>> = tables
+                    .iter()
+                    .map(|labels| labels.iter().map(|label| offset(*label)).collect())
+                    .collect();
+                (*func_index, targets)
+            })
+            .collect::<Vec<_>>();
+        brtable_targets.sort_by_key(|(func_index, _)| *func_index);
+
+        Ok(CachedCode {
+            code: code.to_vec(),
+            nregions: regions.len(),
+            relocs,
+            func_starts: self
+                .func_labels
+                .iter()
+                .map(|label| offset(*label))
+                .collect(),
+            func_ends: self
+                .func_end_labels
+                .iter()
+                .map(|label| label.map(offset))
+                .collect(),
+            brtable_targets,
+            trap_sites: trap_sites
+                .iter()
+                .map(|(label, site)| (offset(*label), *site))
+                .collect(),
+            call_sites: self
+                .call_sites
+                .iter()
+                .map(|(label, func_index, pc)| (offset(*label), *func_index, *pc))
+                .collect(),
+            entry_exit: offset(self.entry_exit),
+            host_trap_exit: (offset(self.host_trap_exit.0), offset(self.host_trap_exit.1)),
+        })
+    }
+
+    /// Read the code cached in `path` and patch in the addresses of this
+    /// run. Nothing is emitted before all of it is checked, the code is
+    /// compiled again if it does not fit the module.
+    fn load_cached(&self, path: &Path) -> Result<(CachedCode, Vec<u8>)> {
+        let cached = CachedCode::from_bytes(&fs::read(path)?)?;
+        let len = cached.code.len();
+        let in_code = |offset: &usize| *offset <= len;
+
+        let brtable_labels = &self.brtable_nondefault_target_labels;
+        let brtables_match = cached.brtable_targets.len() == brtable_labels.len()
+            && cached.brtable_targets.iter().all(|(func_index, targets)| {
+                brtable_labels.get(func_index).is_some_and(|labels| {
+                    labels.len() == targets.len()
+                        && labels.iter().zip(targets).all(|(l, t)| l.len() == t.len())
+                })
+            });
+        if cached.func_starts.len() != self.func_labels.len()
+            || cached.func_ends.len() != self.func_end_labels.len()
+            || !brtables_match
+        {
+            return Err(anyhow!("jit cache: the code is of another module"));
+        }
+        let labels_in_code = cached.func_starts.iter().all(in_code)
+            && cached.func_ends.iter().flatten().all(in_code)
+            && cached
+                .brtable_targets
+                .iter()
+                .all(|(_, targets)| targets.iter().flatten().all(in_code))
+            && cached.trap_sites.iter().all(|(offset, _)| in_code(offset))
+            && cached
+                .call_sites
+                .iter()
+                .all(|(offset, _, _)| in_code(offset))
+            && in_code(&cached.entry_exit)
+            && in_code(&cached.host_trap_exit.0)
+            && in_code(&cached.host_trap_exit.1);
+        if !labels_in_code {
+            return Err(anyhow!("jit cache: label past the end of the code"));
+        }
+
+        let regions = self.data_regions();
+        if cached.nregions != regions.len() {
+            return Err(anyhow!("jit cache: the data of the code does not match"));
+        }
+        let image = own_image_base().ok_or_else(|| anyhow!("the binary is not mapped"))?;
+        let mut code = cached.code.clone();
+        for (offset, target) in &cached.relocs {
+            let addr = match *target {
+                Target::Data(i, offset) => match regions.get(i) {
+                    Some((base, len)) if offset < *len as u64 => base + offset,
+                    _ => return Err(anyhow!("jit cache: address past its data")),
+                },
+                Target::Image(offset) => image + offset,
+            };
+            let imm = offset
+                .checked_add(8)
+                .and_then(|end| code.get_mut(*offset..end))
+                .ok_or_else(|| anyhow!("jit cache: address past the end of the code"))?;
+            imm.copy_from_slice(&addr.to_le_bytes());
+        }
+        Ok((cached, code))
+    }
+
+    /// Emit `code` with the labels of the compiler bound where `cached`
+    /// says, as if it had just been compiled.
+    fn emit_cached(&mut self, cached: &CachedCode, code: &[u8]) {
+        let mut labels = cached
+            .func_starts
+            .iter()
+            .copied()
+            .zip(self.func_labels.iter().copied())
+            .collect::<Vec<_>>();
+        for (func_index, offset) in cached.func_ends.iter().enumerate() {
+            if let Some(offset) = offset {
+                let label = self.jit.label();
+                self.func_end_labels[func_index] = Some(label);
+                labels.push((*offset, label));
+            }
+        }
+        for (func_index, targets) in &cached.brtable_targets {
+            let tables = &self.brtable_nondefault_target_labels[func_index];
+            for (offsets, table) in targets.iter().zip(tables) {
+                labels.extend(offsets.iter().copied().zip(table.iter().copied()));
+            }
+        }
+        for (offset, site) in &cached.trap_sites {
+            let label = self.jit.label();
+            self.trap_sites.push((label, *site));
+            labels.push((*offset, label));
+        }
+        for (offset, func_index, pc) in &cached.call_sites {
+            let label = self.jit.label();
+            self.call_sites.push((label, *func_index, *pc));
+            labels.push((*offset, label));
+        }
+        labels.push((cached.entry_exit, self.entry_exit));
+        labels.push((cached.host_trap_exit.0, self.host_trap_exit.0));
+        labels.push((cached.host_trap_exit.1, self.host_trap_exit.1));
+        labels.sort_by_key(|(offset, _)| *offset);
+
+        let mut labels = labels.into_iter().peekable();
+        for (i, byte) in code.iter().enumerate() {
+            while let Some((_, label)) = labels.next_if(|(offset, _)| *offset == i) {
+                self.emit_single_label(label);
+            }
+            self.jit.emitb(*byte);
+        }
+        for (_, label) in labels {
+            self.emit_single_label(label);
+        }
+    }
+
+    /// The data the code loads the address of, as its address and size. The
+    /// same module with the same settings has the same regions in the same
+    /// order in every run.
+    fn data_regions(&self) -> Vec<(u64, usize)> {
+        fn region<T>(data: &T) -> (u64, usize) {
+            (data as *const T as u64, size_of::<T>())
+        }
+        fn slice<T>(data: &[T]) -> (u64, usize) {
+            (data.as_ptr() as u64, size_of_val(data))
+        }
+
+        let mut regions = vec![
+            region(self),
+            region(&*self.call_depth),
+            region(&*self.epoch_deadline),
+            region(&*self.host_ctx),
+        ];
+        regions.extend(self.fuel.as_deref().map(region));
+        regions.extend(
+            self.epoch
+                .as_ref()
+                .map(|epoch| (epoch.as_ptr() as u64, size_of::<u64>())),
+        );
+        regions.extend(self.limiter.as_deref().map(region));
+        regions.extend([
+            slice(&self.multi_results),
+            slice(&self.exception),
+            slice(&self.globals),
+            slice(&self.func_addrs),
+            slice(&self.func_sig_indices),
+            slice(&self.linear_mems),
+            slice(&self.tables),
+            slice(&self.elem_segments),
+            slice(&self.data_segments),
+        ]);
+        regions.extend(self.indirect_callees.iter().map(|callees| slice(callees)));
+        let mut brtables = self
+            .brtable_nondefault_target_addrs
+            .iter()
+            .collect::<Vec<_>>();
+        brtables.sort_by_key(|(func_index, _)| **func_index);
+        for (_, tables) in brtables {
+            regions.extend(tables.iter().map(|addrs| slice(addrs)));
+        }
+        regions
+    }
+}
+
+/// The base address of the binary `addr` is mapped from.
+fn image_base(addr: u64) -> Option<u64> {
+    let mut info = MaybeUninit::<libc::Dl_info>::uninit();
+    // SAFETY: dladdr only writes to `info`, which it fills in if it
+    // succeeds
+    unsafe {
+        if libc::dladdr(addr as *const c_void, info.as_mut_ptr()) == 0 {
+            return None;
+        }
+        Some(info.assume_init().dli_fbase as u64)
+    }
+}
+
+/// The base address of the binary of the interpreter, which holds the
+/// helpers the code calls.
+fn own_image_base() -> Option<u64> {
+    image_base(own_image_base as *const () as usize as u64)
+}
+
+/// Write `bytes` to `path` through a temporary file, so a concurrent run
+/// never reads half of it.
+fn save(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+impl CachedCode {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u32(VERSION);
+        w.len(COMPILER_VERSION.len());
+        w.bytes(COMPILER_VERSION.as_bytes());
+
+        w.len(self.code.len());
+        w.bytes(&self.code);
+        w.len(self.nregions);
+        w.len(self.relocs.len());
+        for (offset, target) in &self.relocs {
+            w.len(*offset);
+            match *target {
+                Target::Data(i, offset) => {
+                    w.u8(0);
+                    w.len(i);
+                    w.u64(offset);
+                }
+                Target::Image(offset) => {
+                    w.u8(1);
+                    w.u64(offset);
+                }
+            }
+        }
+        w.len(self.func_starts.len());
+        for offset in &self.func_starts {
+            w.len(*offset);
+        }
+        w.len(self.func_ends.len());
+        for offset in &self.func_ends {
+            w.option(*offset, Writer::len);
+        }
+        w.len(self.brtable_targets.len());
+        for (func_index, targets) in &self.brtable_targets {
+            w.len(*func_index);
+            w.len(targets.len());
+            for offsets in targets {
+                w.len(offsets.len());
+                for offset in offsets {
+                    w.len(*offset);
+                }
+            }
+        }
+        w.len(self.trap_sites.len());
+        for (offset, site) in &self.trap_sites {
+            w.len(*offset);
+            match site {
+                TrapSite::Wasm(kind) => {
+                    w.u8(0);
+                    let code = TRAP_KINDS.iter().position(|k| k == kind).unwrap();
+                    w.u8(code as u8);
+                }
+                TrapSite::Host => w.u8(1),
+                TrapSite::Division => w.u8(2),
+            }
+        }
+        w.len(self.call_sites.len());
+        for (offset, func_index, pc) in &self.call_sites {
+            w.len(*offset);
+            w.u32(*func_index);
+            w.len(*pc);
+        }
+        w.len(self.entry_exit);
+        w.len(self.host_trap_exit.0);
+        w.len(self.host_trap_exit.1);
+        w.out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("jit cache: bad magic"));
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(anyhow!("jit cache: unsupported version {}", version));
+        }
+        let len = r.len()?;
+        let compiler_version = r.bytes(len)?;
+        if compiler_version != COMPILER_VERSION.as_bytes() {
+            return Err(anyhow!(
+                "jit cache: written by version {} of the interpreter",
+                String::from_utf8_lossy(compiler_version)
+            ));
+        }
+
+        let len = r.len()?;
+        let code = r.bytes(len)?.to_vec();
+        let nregions = r.len()?;
+        let relocs = r.vec(|r| {
+            let offset = r.len()?;
+            let target = match r.u8()? {
+                0 => Target::Data(r.len()?, r.u64()?),
+                1 => Target::Image(r.u64()?),
+                t => return Err(anyhow!("jit cache: invalid address target {}", t)),
+            };
+            Ok((offset, target))
+        })?;
+        let func_starts = r.vec(Reader::len)?;
+        let func_ends = r.vec(|r| r.option(Reader::len))?;
+        let brtable_targets = r.vec(|r| Ok((r.len()?, r.vec(|r| r.vec(Reader::len))?)))?;
+        let trap_sites = r.vec(|r| {
+            let offset = r.len()?;
+            let site = match r.u8()? {
+                0 => {
+                    let code = r.u8()?;
+                    let kind = TRAP_KINDS
+                        .get(code as usize)
+                        .ok_or_else(|| anyhow!("jit cache: invalid trap kind {}", code))?;
+                    TrapSite::Wasm(*kind)
+                }
+                1 => TrapSite::Host,
+                2 => TrapSite::Division,
+                s => return Err(anyhow!("jit cache: invalid trap site {}", s)),
+            };
+            Ok((offset, site))
+        })?;
+        let call_sites = r.vec(|r| Ok((r.len()?, r.u32()?, r.len()?)))?;
+        let entry_exit = r.len()?;
+        let host_trap_exit = (r.len()?, r.len()?);
+        if r.pos != bytes.len() {
+            return Err(anyhow!("jit cache: trailing bytes"));
+        }
+
+        Ok(Self {
+            code,
+            nregions,
+            relocs,
+            func_starts,
+            func_ends,
+            brtable_targets,
+            trap_sites,
+            call_sites,
+            entry_exit,
+            host_trap_exit,
+        })
+    }
+}
+
+/// Little endian fixed width encoding, offsets and lengths are u64.
+#[derive(Default)]
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.out.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn len(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    fn option<T: Copy>(&mut self, v: Option<T>, write: fn(&mut Self, T)) {
+        self.u8(v.is_some() as u8);
+        if let Some(v) = v {
+            write(self, v);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| anyhow!("jit cache: unexpected end"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| anyhow!("jit cache: length too large"))
+    }
+
+    fn option<T>(&mut self, read: fn(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(read(self)?)),
+            b => Err(anyhow!("jit cache: invalid bool {}", b)),
+        }
+    }
+
+    fn vec<T>(&mut self, mut read: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let len = self.len()?;
+        // do not trust the length with the allocation, every item is at
+        // least a byte
+        let mut items = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+        for _ in 0..len {
+            items.push(read(self)?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        jit::{regalloc::X86Register, JitSettings},
+        module::wasm_module::WasmModule,
+        vm::{Linker, Store, DEFAULT_MAX_CALL_DEPTH, DEFAULT_MEMORY_MAXIMUM},
+    };
+
+    fn settings() -> JitSettings {
+        JitSettings {
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            fuel: Rc::new(Cell::new(None)),
+            epoch: None,
+            limiter: None,
+            trace: false,
+            debug_info: false,
+            debug_break: false,
+            perf_map: false,
+            backtrace: false,
+            canonicalize_nans: false,
+            default_memory_maximum: DEFAULT_MEMORY_MAXIMUM,
+            max_memory_pages: u64::MAX,
+            max_table_elements: u64::MAX,
+            lazy: false,
+            cache_dir: None,
+        }
+    }
+
+    fn empty_code() -> CachedCode {
+        CachedCode {
+            code: vec![0xc3],
+            nregions: 0,
+            relocs: Vec::new(),
+            func_starts: Vec::new(),
+            func_ends: Vec::new(),
+            brtable_targets: Vec::new(),
+            trap_sites: Vec::new(),
+            call_sites: Vec::new(),
+            entry_exit: 0,
+            host_trap_exit: (0, 0),
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "unknown address")]
+    fn unknown_address_panics_in_debug_builds() {
+        let mut binary = Vec::new();
+        let module =
+            WasmModule::from_wat("(module (func (export \"main\")))", &mut binary).unwrap();
+        let store = Store::new(&module, &Linker::default()).unwrap();
+        let mut compiler =
+            X86JitCompiler::new(Rc::new(module), Rc::new(store), &settings()).unwrap();
+
+        // on the heap, in none of the regions of the compiler
+        let unknown = Box::new(0u64);
+        let start = compiler.jit.label();
+        compiler.emit_single_label(start);
+        compiler.emit_setup_code().unwrap();
+        compiler.emit_mov_addr(X86Register::Rax, &*unknown as *const u64 as u64);
+        let end = compiler.jit.label();
+        compiler.emit_single_label(end);
+        compiler.finalize();
+        let _ = compiler.cached_code(start, end, &[]);
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let bytes = empty_code().to_bytes();
+        let cached = CachedCode::from_bytes(&bytes).unwrap();
+        assert_eq!(cached.code, vec![0xc3]);
+    }
+
+    #[test]
+    fn other_compiler_version_is_rejected() {
+        let mut bytes = empty_code().to_bytes();
+        // the version string follows the magic, the format version and its
+        // length
+        let at = MAGIC.len() + 4 + 8;
+        bytes[at] ^= 0xff;
+        let err = CachedCode::from_bytes(&bytes).err().unwrap();
+        assert!(err.to_string().contains("written by version"));
+    }
+}
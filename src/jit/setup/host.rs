@@ -0,0 +1,196 @@
+//! Host function calls from jit code. Every imported function gets a small
+//! stub bound to its function label, so `call` and `call_indirect` do not need
+//! to know whether the callee is a wasm function or a host function.
+//!
+//! The stub loads the index of the import into rax and jumps to the
+//! trampoline of its signature. The arguments of a call are all passed in
+//! general purpose registers, so the trampolines only differ by the number
+//! of parameters and imports of the same arity share one. The trampoline
+//! spills the arguments into an array on the stack and calls into
+//! `jit_host_call` with the compiler, which decodes them using the import's
+//! signature and forwards them to the shared host dispatcher along with the
+//! caller's memory. The state of the instance is written back to the store
+//! before and loaded again after, see `setup::state`, and the trampoline
+//! reloads the base of memory 0 as the memory may have moved. Like a wasm
+//! function, the first result is returned in rax and the rest are left in
+//! the multi-value return area.
+//!
+//! If the host function fails, its error is kept for the trap handler and
+//! the trampoline jumps to the trap entry, `proc_exit` fails with its status
+//! so it takes the same way out.
+
+use std::{collections::BTreeMap, rc::Rc};
+
+use monoasm::*;
+use monoasm_macro::monoasm;
+
+use crate::{
+    jit::{
+        regalloc::{Register, X86Register, REG_MEMORY_BASE, REG_TEMP},
+        setup::trap::set_pending_error,
+        utils::emit_mov_reg_to_reg,
+        X86JitCompiler,
+    },
+    module::value_type::WasmValue,
+    vm::HostFuncImport,
+};
+
+pub(crate) struct JitHostContext {
+    /// imported functions, indexed by function index
+    imports: Vec<HostFuncImport>,
+    /// set to non-zero when a host call fails
+    trapped: u64,
+}
+
+impl JitHostContext {
+    pub(crate) fn new(imports: Vec<HostFuncImport>) -> Self {
+        Self {
+            imports,
+            trapped: 0,
+        }
+    }
+}
+
+extern "C" fn jit_host_call(
+    compiler: *mut X86JitCompiler<'_>,
+    import_index: u64,
+    args: *const u64,
+) -> u64 {
+    // the host may call back into the jit code of the instance, so the
+    // compiler is only borrowed around the calls into it
+    let (store, import) = {
+        let compiler = unsafe { &*compiler };
+        (
+            Rc::clone(&compiler.store),
+            compiler.host_ctx.imports[import_index as usize].clone(),
+        )
+    };
+
+    let nargs = import.sig.params().len();
+    let raw_args = unsafe { std::slice::from_raw_parts(args, nargs) };
+    let args = import
+        .sig
+        .params()
+        .iter()
+        .zip(raw_args)
+        .map(|(ty, raw)| WasmValue::from_raw(ty, *raw))
+        .collect::<Vec<_>>();
+
+    unsafe { (*compiler).store_state() };
+    // host functions only see the first memory
+    let uses_caller = store.host.borrow().uses_caller(&import);
+    let mut mem = store
+        .mems
+        .first()
+        .filter(|_| uses_caller)
+        .map(|m| m.borrow_mut());
+    let data = mem.as_mut().map_or(&mut [][..], |m| &mut m.data[..]);
+    let results = store.host.borrow_mut().call(&import, &args, data);
+    drop(mem);
+
+    let compiler = unsafe { &mut *compiler };
+    compiler.load_state();
+    match results {
+        Ok(results) => {
+            // the flag of an earlier call that trapped is left set
+            compiler.host_ctx.trapped = 0;
+            for (i, result) in results.iter().enumerate().skip(1) {
+                compiler.multi_results[i] = result.to_raw();
+            }
+            results.first().map_or(0, WasmValue::to_raw)
+        }
+        Err(e) => {
+            log::debug!("host call {}.{} failed: {}", import.module, import.name, e);
+            set_pending_error(e);
+            compiler.host_ctx.trapped = 1;
+            0
+        }
+    }
+}
+
+impl X86JitCompiler<'_> {
+    /// Emit a stub for each imported function at the function's label, and
+    /// the trampolines they jump to. The compiler must not move once they
+    /// are emitted, the trampolines hold its address.
+    pub(crate) fn setup_host_stubs(&mut self) {
+        // number of parameters -> trampoline
+        let mut trampolines = BTreeMap::new();
+        for import_index in 0..self.host_ctx.imports.len() {
+            let nargs = self.host_ctx.imports[import_index].sig.params().len();
+            let trampoline = *trampolines.entry(nargs).or_insert_with(|| self.jit.label());
+            let stub_label = self.func_labels[import_index];
+            monoasm!(
+                &mut self.jit,
+            stub_label:
+                movq rax, (import_index as u64);
+                jmp trampoline;
+            );
+        }
+
+        for (nargs, trampoline) in trampolines {
+            self.emit_host_trampoline(trampoline, nargs);
+        }
+    }
+
+    /// Emit the trampoline of the imports taking `nargs` arguments, it is
+    /// jumped to with the index of the import in rax.
+    fn emit_host_trampoline(&mut self, trampoline: DestLabel, nargs: usize) {
+        let compiler_ptr = self as *mut Self as u64;
+        let trapped_addr = &self.host_ctx.trapped as *const u64 as u64;
+        let host_call = jit_host_call as *const () as usize as u64;
+        let trap_label = self.trap_label;
+        // the import index is kept at [rbp - 8], below it the arguments
+        let frame_size = (((nargs + 1) * 8 + 15) & !15) as u64;
+
+        monoasm!(
+            &mut self.jit,
+        trampoline:
+            pushq rbp;
+            movq rbp, rsp;
+            subq rsp, (frame_size);
+            movq [rbp - (8)], rax;
+        );
+
+        // args[i] lives at [rbp - frame_size + i * 8]
+        for i in 0..nargs {
+            let slot = Register::Stack(frame_size as usize - i * 8);
+            if i < 6 {
+                emit_mov_reg_to_reg(&mut self.jit, slot, Register::from_ith_argument(i as u32));
+            } else {
+                monoasm!(
+                    &mut self.jit,
+                    movq R(REG_TEMP.as_index()), [rbp + ((i as i32 - 6) * 8 + 16)];
+                );
+                emit_mov_reg_to_reg(&mut self.jit, slot, Register::Reg(REG_TEMP));
+            }
+        }
+
+        // jit_host_call(compiler, import_index, args), the stack must be 16
+        // bytes aligned when calling into rust
+        self.emit_mov_addr(X86Register::Rdi, compiler_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq rsi, [rbp - (8)];
+            movq rdx, rbp;
+            subq rdx, (frame_size);
+            andq rsp, (-16);
+        );
+        self.emit_mov_addr(X86Register::Rax, host_call);
+        monoasm!(
+            &mut self.jit,
+            call rax;
+            movq rsp, rbp;
+            popq rbp;
+        );
+        self.emit_load_memory_base(0, REG_MEMORY_BASE);
+        // rax holds the result, check whether the host call trapped
+        self.emit_mov_addr(X86Register::Rdx, trapped_addr);
+        monoasm!(
+            &mut self.jit,
+            movq rdx, [rdx];
+            cmpq rdx, (0);
+            jne trap_label;
+            ret;
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Lazy compilation. Instead of compiling every function up front, each
+//! function first gets a small stub bound to its function label, the first
+//! call through the stub compiles the function and patches its entry in
+//! `func_addrs`, later calls jump to the compiled code directly.
+//!
+//! The stub saves the argument registers and calls into `jit_compile_lazy`,
+//! which returns the address of the compiled function, then restores them
+//! and jumps there, so the function runs as if it had been called directly.
+//! Arguments passed on the stack and the return address are left untouched.
+//! A function that fails to compile traps through the host trap exits, with
+//! the compilation error.
+
+use monoasm::*;
+use monoasm_macro::monoasm;
+
+use crate::jit::{regalloc::X86Register, setup::trap::set_pending_error, X86JitCompiler};
+
+/// the registers holding the first six arguments, saved by the stub
+const ARG_REGS_SIZE: i32 = 6 * 8;
+
+extern "C" fn jit_compile_lazy(compiler: *mut X86JitCompiler<'_>, func_index: u64) -> u64 {
+    let compiler = unsafe { &mut *compiler };
+    match compiler.compile_lazily(func_index as usize) {
+        Ok(addr) => addr,
+        Err(e) => {
+            log::debug!("lazy compilation of function {} failed: {}", func_index, e);
+            set_pending_error(e);
+            compiler.jit.get_label_u64(compiler.host_trap_exit.0)
+        }
+    }
+}
+
+impl X86JitCompiler<'_> {
+    /// Emit the compilation stub of a function at the function's label. The
+    /// compiler must not move once its stubs are emitted, they hold its
+    /// address.
+    pub(crate) fn emit_lazy_stub(&mut self, func_index: usize) {
+        let stub_label = self.func_labels[func_index];
+        let compiler_ptr = self as *mut Self as u64;
+        let trampoline = jit_compile_lazy as *const () as usize as u64;
+
+        // jit_compile_lazy(compiler, func_index), the stack must be 16 bytes
+        // aligned when calling into rust
+        monoasm!(
+            &mut self.jit,
+        stub_label:
+            pushq rbp;
+            movq rbp, rsp;
+            pushq rdi;
+            pushq rsi;
+            pushq rdx;
+            pushq rcx;
+            pushq r8;
+            pushq r9;
+            andq rsp, (-16);
+        );
+        self.emit_mov_addr(X86Register::Rdi, compiler_ptr);
+        monoasm!(
+            &mut self.jit,
+            movq rsi, (func_index as u64);
+        );
+        self.emit_mov_addr(X86Register::Rax, trampoline);
+        monoasm!(
+            &mut self.jit,
+            call rax;
+            movq rsp, rbp;
+            subq rsp, (ARG_REGS_SIZE);
+            popq r9;
+            popq r8;
+            popq rcx;
+            popq rdx;
+            popq rsi;
+            popq rdi;
+            popq rbp;
+            jmp rax;
+        );
+    }
+
+    /// Compile a function whose stub was called, or that the interpreter
+    /// tiers up, and make the calls through `func_addrs` reach it, returns
+    /// its address.
+    pub(crate) fn compile_lazily(&mut self, func_index: usize) -> anyhow::Result<u64> {
+        if self.func_end_labels[func_index].is_some() {
+            return Ok(self.func_addrs[func_index]);
+        }
+
+        let module = std::rc::Rc::clone(&self.module);
+        let fdecl = &module.get_funcs()[func_index];
+        // the stub keeps its label, the compiled code gets a new one
+        self.func_labels[func_index] = self.jit.label();
+        self.compile_func(func_index, fdecl)?;
+        self.jit.finalize();
+
+        self.func_addrs[func_index] = self.jit.get_label_u64(self.func_labels[func_index]);
+        self.fill_brtable_addrs(func_index);
+        self.register_compiled_code(&[func_index]);
+        Ok(self.func_addrs[func_index])
+    }
+}
@@ -1,4 +1,8 @@
+pub(crate) mod cache;
 pub(crate) mod data;
-pub(crate) mod global;
+pub(crate) mod host;
+pub(crate) mod lazy;
+pub(crate) mod state;
 pub(crate) mod table;
+pub(crate) mod tier;
 pub(crate) mod trap;
@@ -0,0 +1,132 @@
+//! The state of the instance the jit code runs on. The code of an instance
+//! is compiled once and runs on its store: the memories are accessed in
+//! place, while the globals, tables and segments are copied into the
+//! compiler, where the jit code reads and writes them at fixed addresses.
+//!
+//! The copies are loaded from the store on every entry into the jit code
+//! and written back once it returns or traps, and around every host call so
+//! that the host, and the instances it calls into, see them. The fuel of a
+//! metered instance is kept the same way.
+
+use std::rc::Rc;
+
+use wasmparser::ValType;
+
+use crate::{jit::X86JitCompiler, module::value_type::WasmValue, vm::EpochDeadline};
+
+/// What an entry into the jit code changes for its call alone, restored by
+/// `X86JitCompiler::leave` as entries nest when the host calls back into
+/// the instance.
+pub(crate) struct EntryState {
+    call_depth: u64,
+    epoch_deadline: u64,
+}
+
+impl X86JitCompiler<'_> {
+    /// Load the state before entering the jit code for a call interrupted at
+    /// `epoch_deadline`, returns what `leave` restores.
+    pub(crate) fn enter(&mut self, epoch_deadline: Option<&EpochDeadline>) -> EntryState {
+        let saved = EntryState {
+            call_depth: *self.call_depth,
+            epoch_deadline: *self.epoch_deadline,
+        };
+        if let Some(epoch_deadline) = epoch_deadline {
+            *self.epoch_deadline = epoch_deadline.deadline;
+        }
+        self.load_state();
+        saved
+    }
+
+    /// Write the state back once the jit code returned or trapped, a trap
+    /// skips the epilogues so the call depth is restored as well.
+    pub(crate) fn leave(&mut self, saved: EntryState) {
+        self.store_state();
+        *self.call_depth = saved.call_depth;
+        *self.epoch_deadline = saved.epoch_deadline;
+    }
+
+    /// Read the current state of the store.
+    pub(crate) fn load_state(&mut self) {
+        let store = Rc::clone(&self.store);
+        let module = Rc::clone(&self.module);
+        for (raw, global) in self.globals.iter_mut().zip(&store.globals) {
+            *raw = global.get().to_raw();
+        }
+        for mem in &mut self.linear_mems {
+            mem.load();
+        }
+        for (jit_table, table) in self.tables.iter_mut().zip(store.tables.borrow().iter()) {
+            jit_table.load(table);
+        }
+        for (segment, refs) in self
+            .elem_segments
+            .iter_mut()
+            .zip(store.elem_segments.borrow().iter())
+        {
+            segment.refs.clear();
+            segment
+                .refs
+                .extend(refs.iter().map(|elem| elem.to_raw() as u32));
+            segment.len = segment.refs.len() as u64;
+        }
+        let dropped_datas = store.dropped_datas.borrow();
+        for ((segment, data), dropped) in self
+            .data_segments
+            .iter_mut()
+            .zip(module.get_datas())
+            .zip(dropped_datas.iter())
+        {
+            segment.len = if *dropped { 0 } else { data.data.len() as u64 };
+        }
+        if let (Some(fuel), Some(source)) = (&mut self.fuel, &self.fuel_source) {
+            **fuel = source.get().unwrap_or(0);
+        }
+    }
+
+    /// Write what the jit code changed back to the store.
+    pub(crate) fn store_state(&mut self) {
+        let store = Rc::clone(&self.store);
+        let module = Rc::clone(&self.module);
+        for ((global, decl), raw) in store
+            .globals
+            .iter()
+            .zip(module.get_globals())
+            .zip(&self.globals)
+        {
+            global.set(WasmValue::from_raw(&decl.get_ty().content_type, *raw));
+        }
+        let mut tables = store.tables.borrow_mut();
+        for ((jit_table, table), decl) in self
+            .tables
+            .iter()
+            .zip(tables.iter_mut())
+            .zip(module.get_tables())
+        {
+            jit_table.store(table, &ValType::Ref(decl.ty.element_type));
+        }
+        drop(tables);
+        // the jit code only ever drops segments
+        for (segment, refs) in self
+            .elem_segments
+            .iter()
+            .zip(store.elem_segments.borrow_mut().iter_mut())
+        {
+            if segment.len == 0 {
+                refs.clear();
+            }
+        }
+        for ((segment, data), dropped) in self
+            .data_segments
+            .iter()
+            .zip(module.get_datas())
+            .zip(store.dropped_datas.borrow_mut().iter_mut())
+        {
+            if (segment.len as usize) < data.data.len() {
+                *dropped = true;
+            }
+        }
+        if let (Some(fuel), Some(source)) = (&self.fuel, &self.fuel_source) {
+            source.set(Some(**fuel));
+        }
+    }
+}
@@ -1,48 +1,121 @@
-use crate::{jit::X86JitCompiler, module::wasmops::WASM_OP_I32_CONST};
+use std::rc::Rc;
 
-impl X86JitCompiler<'_> {
-    // table are setup using the element section
-    pub(crate) fn setup_tables(&mut self) {
-        let module_ref = self.module.borrow();
-        let elems = module_ref.get_elems();
-        for elem in elems {
-            let ind = match &elem.kind {
-                wasmparser::ElementKind::Active {
-                    table_index,
-                    offset_expr,
-                } => {
-                    if let Some(table_index) = table_index {
-                        *table_index
-                    } else {
-                        let mut reader = offset_expr.get_binary_reader();
-                        let op = reader.read_u8().expect(
-                            "invalid offset expression when parsing opcode, should be i32.const",
-                        );
-                        if op as u32 != WASM_OP_I32_CONST {
-                            panic!("invalid offset expression when parsing opcode, should be i32.const, op: {}", op);
-                        }
-                        reader
-                            .read_var_i32()
-                            .expect("invalid offset expression when parsing value of i32.const")
-                            as u32
-                    }
-                }
-                _ => panic!("we dont support passive and declared element segment"),
-            };
-
-            // setup the elements in the table
-            let items = elem.items.clone();
-            match items {
-                wasmparser::ElementItems::Functions(r) => {
-                    for func_idx in r {
-                        self.tables[ind as usize].push(func_idx.unwrap());
-                    }
-                }
-                _ => panic!("we dont support expressions element segment"),
-            }
+use wasmparser::ValType;
+
+use crate::{
+    jit::X86JitCompiler,
+    module::value_type::WasmValue,
+    vm::{SharedLimiter, Table, DEFAULT_TABLE_MAXIMUM},
+};
+
+/// A table as seen by the jit code. The slots move when the table grows, so
+/// the jit code reads `data` and `len` through the table's address.
+#[repr(C)]
+pub(crate) struct JitTable {
+    pub(crate) data: *mut u32,
+    pub(crate) len: u64,
+    slots: Vec<u32>,
+    max: Option<u64>,
+    /// the size the table can grow to, its maximum or
+    /// `DEFAULT_TABLE_MAXIMUM` unless the instance limits it further
+    limit: u64,
+}
+
+impl JitTable {
+    pub(crate) const DATA_OFFSET: i32 = 0;
+    pub(crate) const LEN_OFFSET: i32 = 8;
+
+    fn new(max: Option<u64>, max_elements: u64) -> Self {
+        let mut table = Self {
+            data: std::ptr::null_mut(),
+            len: 0,
+            slots: Vec::new(),
+            max,
+            limit: max.unwrap_or(DEFAULT_TABLE_MAXIMUM).min(max_elements),
+        };
+        table.sync();
+        table
+    }
+
+    /// Read the elements of `table`, a slot holds the function index + 1
+    /// and 0 for null, see `WasmValue::from_raw`.
+    pub(crate) fn load(&mut self, table: &Table) {
+        self.slots.clear();
+        self.slots
+            .extend(table.elems.iter().map(|elem| elem.to_raw() as u32));
+        self.sync();
+    }
+
+    /// Write the elements back to `table`, whose elements are of type `ty`.
+    pub(crate) fn store(&self, table: &mut Table, ty: &ValType) {
+        table.elems.clear();
+        table.elems.extend(
+            self.slots
+                .iter()
+                .map(|slot| WasmValue::from_raw(ty, *slot as u64)),
+        );
+    }
+
+    fn sync(&mut self) {
+        self.data = self.slots.as_mut_ptr();
+        self.len = self.slots.len() as u64;
+    }
+
+    pub(crate) fn slots_mut(&mut self) -> &mut [u32] {
+        &mut self.slots
+    }
+
+    /// Grow the table by `n` slots set to `init`, returns the old size or
+    /// `None` if the table would exceed its limit, `limiter` denies the
+    /// growth or the slots cannot be allocated.
+    pub(crate) fn grow(
+        &mut self,
+        n: u32,
+        init: u32,
+        limiter: Option<&SharedLimiter>,
+    ) -> Option<u32> {
+        let old_size = self.slots.len() as u32;
+        let new_size = old_size.checked_add(n)?;
+        if new_size as u64 > self.limit {
+            return None;
         }
-        for (i, table) in self.tables.iter().enumerate() {
-            self.table_len[i] = table.len();
+        if limiter.is_some_and(|l| !l.table_growing(old_size as u64, new_size as u64, self.max)) {
+            return None;
         }
+        self.slots.try_reserve_exact(n as usize).ok()?;
+        self.slots.resize(new_size as usize, init);
+        self.sync();
+        Some(old_size)
+    }
+}
+
+/// An element segment as seen by `table.init`, the jit code drops a segment
+/// by setting its length to 0.
+pub(crate) struct JitElemSegment {
+    pub(crate) refs: Vec<u32>,
+    pub(crate) len: u64,
+}
+
+impl X86JitCompiler<'_> {
+    /// One table per table of the store and one segment per element
+    /// segment, their elements are loaded with the rest of the state, see
+    /// `load_state`.
+    pub(crate) fn setup_tables(&mut self) {
+        let store = Rc::clone(&self.store);
+        self.tables = store
+            .tables
+            .borrow()
+            .iter()
+            .map(|table| JitTable::new(table.max, self.max_table_elements))
+            .collect();
+        self.elem_segments = store
+            .elem_segments
+            .borrow()
+            .iter()
+            .map(|_| JitElemSegment {
+                refs: Vec::new(),
+                len: 0,
+            })
+            .collect();
     }
 }
@@ -0,0 +1,277 @@
+//! Entering jit code from rust. The compiler is set up once per instance,
+//! see `X86JitCompiler::setup`, and every call enters the code of its
+//! function through an entry that takes the raw arguments from an array:
+//! the calls of the x86 jit, see `vm::jit_instance`, and the hot functions
+//! of the interpreter, see `vm::tiering`, whose compiler has a lazy stub for
+//! every function.
+//!
+//! An entry returns to its rust caller: it saves the callee-saved
+//! registers, loads the base of memory 0, passes the arguments like a wasm
+//! call does and restores the stack once the function returned, or trapped.
+//! An exception the function leaves in flight traps, see
+//! `insts::exception`.
+//!
+//! A hot loop is entered on the stack instead, in the middle of its
+//! function: the function is compiled again with a prelude that overwrites
+//! the locals with the ones of the interpreted frame and jumps to the loop
+//! header, where the operand stack of the frame is loaded into the
+//! registers and slots the code of the loop expects it in. Its entry takes
+//! no arguments, the interpreter writes the locals and the operand stack to
+//! `OsrEntry::values` before calling it. The copy has jump tables of its
+//! own for its `br_table`s, the ones of the function stay as they are.
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use monoasm::*;
+use monoasm_macro::monoasm;
+
+use crate::{
+    jit::{
+        regalloc::{Register, X86Register, REG_LOCAL_BASE, REG_MEMORY_BASE, REG_TEMP, REG_TEMP2},
+        setup::trap::TrapFrame,
+        utils::emit_mov_reg_to_reg,
+        X86JitCompiler,
+    },
+    module::value_type::WasmValue,
+};
+
+/// An entry into the jit code of a function, see `emit_tier_entry`. The
+/// first result is returned, the rest are left in the multi-value return
+/// area of the compiler. It is called through `setup::trap::call_guarded`.
+pub(crate) type TierEntry = extern "C" fn(args: *const u64, frame: *mut TrapFrame) -> u64;
+
+/// The entry of a function at one of its loops, see `emit_osr_entry`.
+pub(crate) struct OsrEntry {
+    pub(crate) entry: TierEntry,
+    /// the raw locals the copy of the function starts with, followed by its
+    /// operand stack at the loop
+    pub(crate) values: Box<[u64]>,
+    /// the address of `values`, read by the copy
+    _values_addr: Box<u64>,
+    /// the address of the copy of the function, called by the entry
+    _addr: Box<u64>,
+    /// the jump tables of the `br_table`s of the copy
+    _jump_tables: Vec<Vec<u64>>,
+}
+
+/// The loop the function being compiled is entered at, see
+/// `emit_osr_entry`.
+pub(crate) struct OsrTarget {
+    pub(crate) loop_pc: usize,
+    loop_header: DestLabel,
+    /// where the address of the values the copy starts with is stored
+    values_addr: u64,
+    /// the height of the operand stack at the loop, once it is compiled
+    stack_height: Option<usize>,
+}
+
+impl X86JitCompiler<'_> {
+    /// Set the compiler up with a lazy stub for every function, see
+    /// `setup`.
+    pub(crate) fn setup_tiering(&mut self) -> Result<()> {
+        self.lazy = true;
+        self.setup()
+    }
+
+    /// Compile `func_index` unless it already is, then emit an entry calling
+    /// it with its `nargs` arguments read from an array.
+    pub(crate) fn emit_tier_entry(&mut self, func_index: u32, nargs: usize) -> Result<TierEntry> {
+        self.compile_lazily(func_index as usize)?;
+        let func_addr = self.func_addrs.as_ptr() as u64 + func_index as u64 * 8;
+        Ok(self.emit_entry(func_addr, nargs))
+    }
+
+    /// The results of a call of `func_index` through an entry, which
+    /// returned `first`.
+    pub(crate) fn entry_results(&self, func_index: u32, first: u64) -> Vec<WasmValue> {
+        self.module.get_funcs()[func_index as usize]
+            .get_sig()
+            .results()
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                let raw = if i == 0 { first } else { self.multi_results[i] };
+                WasmValue::from_raw(ty, raw)
+            })
+            .collect()
+    }
+
+    /// Compile a copy of `func_index` that starts at the loop at `loop_pc`
+    /// and emit its entry.
+    pub(crate) fn emit_osr_entry(&mut self, func_index: u32, loop_pc: usize) -> Result<OsrEntry> {
+        let module = Rc::clone(&self.module);
+        let fdecl = &module.get_funcs()[func_index as usize];
+        let index = func_index as usize;
+
+        // the copy gets its own jump tables, swapped back once it is emitted
+        let labels = self.brtable_nondefault_target_labels[&index]
+            .iter()
+            .map(|targets| targets.iter().map(|_| self.jit.label()).collect())
+            .collect::<Vec<Vec<_>>>();
+        let jump_tables = labels
+            .iter()
+            .map(|targets| vec![0; targets.len()])
+            .collect();
+        let func_labels = self
+            .brtable_nondefault_target_labels
+            .insert(index, labels)
+            .unwrap();
+        let func_jump_tables = self
+            .brtable_nondefault_target_addrs
+            .insert(index, jump_tables)
+            .unwrap();
+
+        let mut values_addr = Box::new(0);
+        let func_start = self.jit.label();
+        self.osr = Some(OsrTarget {
+            loop_pc,
+            loop_header: self.jit.label(),
+            values_addr: &*values_addr as *const u64 as u64,
+            stack_height: None,
+        });
+        let emitted = self.emit_func(index, fdecl, func_start);
+        let osr = self.osr.take().unwrap();
+        let emitted = emitted.and_then(|_| {
+            osr.stack_height
+                .ok_or_else(|| anyhow!("osr: the loop is unreachable"))
+        });
+        let mut addr = Box::new(0);
+        let entry = emitted.map(|_| self.emit_entry(&*addr as *const u64 as u64, 0));
+        if entry.is_ok() {
+            self.fill_brtable_addrs(index);
+        }
+        self.brtable_nondefault_target_labels
+            .insert(index, func_labels);
+        let jump_tables = self
+            .brtable_nondefault_target_addrs
+            .insert(index, func_jump_tables)
+            .unwrap();
+        let entry = entry?;
+
+        let nlocals = fdecl.get_sig().params().len() + fdecl.get_pure_locals().len();
+        let values = vec![0; nlocals + osr.stack_height.unwrap()].into_boxed_slice();
+        *values_addr = values.as_ptr() as u64;
+        *addr = self.jit.get_label_u64(func_start);
+        self.register_trap_sites();
+        Ok(OsrEntry {
+            entry,
+            values,
+            _values_addr: values_addr,
+            _addr: addr,
+            _jump_tables: jump_tables,
+        })
+    }
+
+    /// Overwrite the `nlocals` locals of the function with the ones of the
+    /// interpreted frame and jump to the loop header, right after the locals
+    /// are set up.
+    pub(crate) fn emit_osr_prelude(&mut self, nlocals: usize) {
+        let osr = self.osr.as_ref().unwrap();
+        let loop_header = osr.loop_header;
+        self.emit_load_osr_values(osr.values_addr);
+        // local i lives at [REG_LOCAL_BASE - i * 8]
+        for i in 0..nlocals {
+            let offset = i as i32 * 8;
+            monoasm!(
+                &mut self.jit,
+                movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index()) + (offset)];
+                movq [R(REG_LOCAL_BASE.as_index()) - (offset)], R(REG_TEMP.as_index());
+            );
+        }
+        self.emit_jmp(loop_header);
+    }
+
+    /// Bind the loop header the prelude jumps to, at the loop about to be
+    /// compiled, and load the operand stack of the interpreted frame, stored
+    /// after its `nlocals` locals, where the code of the loop expects it.
+    /// The code before the loop jumps over it.
+    pub(crate) fn emit_osr_loop_header(&mut self, nlocals: usize) {
+        let stack = self.reg_allocator.get_vec().clone();
+        let osr = self.osr.as_mut().unwrap();
+        osr.stack_height = Some(stack.len());
+        let (loop_header, values_addr) = (osr.loop_header, osr.values_addr);
+
+        let loop_start = self.jit.label();
+        self.emit_jmp(loop_start);
+        self.emit_single_label(loop_header);
+        self.emit_load_osr_values(values_addr);
+        for (i, value) in stack.iter().enumerate() {
+            let offset = ((nlocals + i) * 8) as i32;
+            monoasm!(
+                &mut self.jit,
+                movq R(REG_TEMP.as_index()), [R(REG_TEMP2.as_index()) + (offset)];
+            );
+            emit_mov_reg_to_reg(&mut self.jit, value.reg, Register::Reg(REG_TEMP));
+        }
+        self.emit_single_label(loop_start);
+    }
+
+    /// Load the address of the values the copy starts with into REG_TEMP2.
+    fn emit_load_osr_values(&mut self, values_addr: u64) {
+        self.emit_mov_addr(REG_TEMP2, values_addr);
+        monoasm!(
+            &mut self.jit,
+            movq R(REG_TEMP2.as_index()), [R(REG_TEMP2.as_index())];
+        );
+    }
+
+    /// Emit an entry calling the function whose address is stored at
+    /// `callee_slot` with `nargs` arguments read from an array.
+    fn emit_entry(&mut self, callee_slot: u64, nargs: usize) -> TierEntry {
+        let entry_label = self.jit.label();
+
+        // an odd number of arguments on the stack realigns rsp
+        let nstack_args = nargs.saturating_sub(6);
+        let padding = ((1 - nstack_args % 2) * 8) as i32;
+        self.emit_single_label(entry_label);
+        if self.debug_break {
+            monoasm!(
+                &mut self.jit,
+                int3;
+            );
+        }
+        self.emit_entry_prologue(X86Register::Rsi);
+        self.emit_load_memory_base(0, REG_MEMORY_BASE);
+        monoasm!(
+            &mut self.jit,
+            movq r11, rdi;
+            subq rsp, (padding);
+        );
+
+        // the 7th argument ends up right above the return address
+        for i in (6..nargs).rev() {
+            monoasm!(
+                &mut self.jit,
+                movq rax, [r11 + (i as i32 * 8)];
+                pushq rax;
+            );
+        }
+        for i in 0..nargs.min(6) {
+            let Register::Reg(reg) = Register::from_ith_argument(i as u32) else {
+                unreachable!("arguments are passed in general purpose registers");
+            };
+            monoasm!(
+                &mut self.jit,
+                movq R(reg.as_index()), [r11 + (i as i32 * 8)];
+            );
+        }
+
+        let entry_exit = self.entry_exit;
+        self.emit_mov_addr(X86Register::Rax, callee_slot);
+        monoasm!(
+            &mut self.jit,
+            movq rax, [rax];
+            call rax;
+        );
+        self.emit_uncaught_exception_check();
+        monoasm!(
+            &mut self.jit,
+            jmp entry_exit;
+        );
+
+        self.jit.finalize();
+        let entry = self.jit.get_label_u64(entry_label);
+        unsafe { std::mem::transmute::<u64, TierEntry>(entry) }
+    }
+}
@@ -2,23 +2,274 @@
 //! invalid instructions. For example, when we divide by zero, or when we
 //! access invalid memory address, or we reach unreachable instruction.
 //!
-//! The way we do this is to trigger sigsegv whenever trap happens, and here
-//! we print "!trap" and exit.
+//! The way we do this is to trigger sigsegv whenever trap happens. Every
+//! trap site faults at its own instruction: each kind of trap has its own
+//! exit, and an out of bounds memory access faults at the access. The
+//! faulting pc of each site is recorded with the kind in a side table, the
+//! handler looks rip up there to tell why the code trapped. The handler
+//! neither locks nor allocates: the table is read through an atomic
+//! pointer, and a change swaps in a new table and frees the old one once no
+//! handler reads it. A division the
+//! checks before it let through raises sigfpe instead, its site tells the
+//! kind from the divisor.
+//!
+//! Jit code is only entered through `call_guarded`, which records the frame
+//! of the entry. On a fault at a trap site, the handler unwinds the jit
+//! frames like `siglongjmp` would: it resumes at the epilogue of the entry
+//! with the frame pointer of the entry, and the trap is returned to the
+//! caller as an error. Any other fault is not ours, it goes to the handler
+//! that was installed before.
+
+#[cfg(target_arch = "x86_64")]
+use std::sync::{Once, OnceLock};
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicPtr, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::anyhow;
+#[cfg(target_arch = "x86_64")]
+use libc::{sigaction, siginfo_t, ucontext_t, SIGFPE, SIGSEGV};
 
-use libc::{sigaction, siginfo_t, SIGSEGV};
+use crate::{jit::backtrace::RawBacktrace, vm::TrapKind};
 
-extern "C" fn trap_handler(signum: i32, _info: *mut siginfo_t, _ctx: *mut libc::c_void) {
-    if signum == SIGSEGV {
-        print!("!trap");
-        std::process::exit(0);
+/// faulting pc -> the trap site, for the code of every compiler, sorted by
+/// the pc. Null until the first sites are registered
+static TRAP_SITES: AtomicPtr<Vec<(u64, TrapSite)>> = AtomicPtr::new(std::ptr::null_mut());
+/// the handlers reading `TRAP_SITES`
+static TRAP_SITE_READERS: AtomicUsize = AtomicUsize::new(0);
+/// held while the trap sites are changed
+static TRAP_SITES_UPDATE: Mutex<()> = Mutex::new(());
+/// the SIGSEGV and SIGFPE actions before ours, faults outside the jit code
+/// go there
+#[cfg(target_arch = "x86_64")]
+static PREVIOUS_SEGV_ACTION: OnceLock<sigaction> = OnceLock::new();
+#[cfg(target_arch = "x86_64")]
+static PREVIOUS_FPE_ACTION: OnceLock<sigaction> = OnceLock::new();
+
+thread_local! {
+    /// the innermost entry into jit code on this thread, null outside
+    static TRAP_FRAME: Cell<*const TrapFrame> = const { Cell::new(std::ptr::null()) };
+    /// set by the handler when the jit code trapped
+    static TRAPPED: Cell<Option<(TrapSite, RawBacktrace)>> = const { Cell::new(None) };
+    /// the error of the failed host call or compilation that trapped
+    static PENDING_ERROR: RefCell<Option<anyhow::Error>> = const { RefCell::new(None) };
+}
+
+/// Why the jit code traps at a site.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum TrapSite {
+    /// the wasm code trapped
+    Wasm(TrapKind),
+    /// a host call or a lazy compilation failed, see `set_pending_error`
+    Host,
+    /// a 32 bits division by REG_TEMP2, it traps with `DivisionByZero` if
+    /// the divisor is zero and `IntegerOverflow` otherwise
+    Division,
+}
+
+/// Keeps the trap sites of a compiler known to the trap handler until it is
+/// dropped along with its code.
+#[derive(Default)]
+pub(crate) struct TrapSiteRegistration {
+    addrs: Vec<u64>,
+}
+
+impl TrapSiteRegistration {
+    /// Add the faulting pcs of newly relocated code.
+    pub(crate) fn register(&mut self, sites: Vec<(u64, TrapSite)>) {
+        self.addrs.extend(sites.iter().map(|(addr, _)| *addr));
+        update_trap_sites(|trap_sites| {
+            trap_sites.extend(sites);
+            trap_sites.sort_unstable_by_key(|(addr, _)| *addr);
+        });
+    }
+}
+
+impl Drop for TrapSiteRegistration {
+    fn drop(&mut self) {
+        if self.addrs.is_empty() {
+            return;
+        }
+        self.addrs.sort_unstable();
+        update_trap_sites(|trap_sites| {
+            trap_sites.retain(|(addr, _)| self.addrs.binary_search(addr).is_err());
+        });
+    }
+}
+
+/// Replace the trap sites with a changed copy.
+fn update_trap_sites(change: impl FnOnce(&mut Vec<(u64, TrapSite)>)) {
+    let _update = TRAP_SITES_UPDATE.lock().unwrap();
+    let old = TRAP_SITES.load(Ordering::SeqCst);
+    let mut trap_sites = unsafe { old.as_ref() }.cloned().unwrap_or_default();
+    change(&mut trap_sites);
+    TRAP_SITES.store(Box::into_raw(Box::new(trap_sites)), Ordering::SeqCst);
+    // a handler that loaded the old table before the store is counted as a
+    // reader until it is done with it
+    while TRAP_SITE_READERS.load(Ordering::SeqCst) != 0 {
+        std::hint::spin_loop();
+    }
+    if !old.is_null() {
+        drop(unsafe { Box::from_raw(old) });
     }
 }
 
+/// The trap site faulting at `rip`, `None` if it is not jit code.
+fn trap_site_at(rip: u64) -> Option<TrapSite> {
+    TRAP_SITE_READERS.fetch_add(1, Ordering::SeqCst);
+    let trap_sites = unsafe { TRAP_SITES.load(Ordering::SeqCst).as_ref() };
+    let site = trap_sites.and_then(|trap_sites| {
+        let index = trap_sites
+            .binary_search_by_key(&rip, |(addr, _)| *addr)
+            .ok()?;
+        Some(trap_sites[index].1)
+    });
+    TRAP_SITE_READERS.fetch_sub(1, Ordering::SeqCst);
+    site
+}
+
+/// Where a trap resumes, the entry stores its frame pointer in `rbp` and
+/// the trap resumes at `landing`, the epilogue of the entry.
+#[repr(C)]
+pub(crate) struct TrapFrame {
+    rbp: u64,
+    landing: u64,
+}
+
+/// Keep the error a host call or a compilation failed with, the jit code
+/// then traps through a `TrapSite::Host` site.
+pub(crate) fn set_pending_error(err: anyhow::Error) {
+    PENDING_ERROR.with(|pending| *pending.borrow_mut() = Some(err));
+}
+
+/// Call into jit code through an entry taking the trap frame, the epilogue
+/// of the entry is at `landing`. A trap of the jit code is returned as an
+/// error like the interpreter's, with the wasm backtrace as context if it
+/// is enabled.
+pub(crate) fn call_guarded(
+    landing: u64,
+    call: impl FnOnce(*mut TrapFrame) -> u64,
+) -> anyhow::Result<u64> {
+    let mut frame = TrapFrame { rbp: 0, landing };
+    // the entry writes to the frame behind our back, only go through the
+    // one pointer
+    let frame_ptr: *mut TrapFrame = &mut frame;
+    let outer = TRAP_FRAME.replace(frame_ptr);
+    let result = call(frame_ptr);
+    TRAP_FRAME.set(outer);
+
+    let Some((site, raw_backtrace)) = TRAPPED.take() else {
+        return Ok(result);
+    };
+    let err = match site {
+        TrapSite::Wasm(kind) => {
+            log::debug!("trap: {}", kind);
+            anyhow::Error::new(kind)
+        }
+        TrapSite::Host => PENDING_ERROR
+            .with(|pending| pending.borrow_mut().take())
+            .unwrap_or_else(|| anyhow!("a host call failed")),
+        TrapSite::Division => unreachable!("the handler tells the kind of a division"),
+    };
+    Err(
+        match raw_backtrace
+            .resolve()
+            .filter(|backtrace| !backtrace.is_empty())
+        {
+            Some(backtrace) => err.context(backtrace),
+            None => err,
+        },
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+extern "C" fn trap_handler(signum: i32, info: *mut siginfo_t, ctx: *mut libc::c_void) {
+    let gregs = unsafe { &mut (*(ctx as *mut ucontext_t)).uc_mcontext.gregs };
+    let (rip, rsp, rbp) = (
+        gregs[libc::REG_RIP as usize] as u64,
+        gregs[libc::REG_RSP as usize] as u64,
+        gregs[libc::REG_RBP as usize] as u64,
+    );
+    let frame = TRAP_FRAME.get();
+    let site = match trap_site_at(rip) {
+        Some(site) if !frame.is_null() => site,
+        _ => return unsafe { chain(signum, info, ctx) },
+    };
+    let site = match site {
+        // REG_TEMP2 is r14
+        TrapSite::Division if gregs[libc::REG_R14 as usize] as u32 == 0 => {
+            TrapSite::Wasm(TrapKind::DivisionByZero)
+        }
+        TrapSite::Division => TrapSite::Wasm(TrapKind::IntegerOverflow),
+        site => site,
+    };
+
+    // the entry restores its callee-saved registers from its frame pointer
+    let (entry_rbp, landing) = unsafe { ((*frame).rbp, (*frame).landing) };
+    let raw_backtrace = unsafe { RawBacktrace::capture(rip, rsp, rbp, entry_rbp) };
+    TRAPPED.set(Some((site, raw_backtrace)));
+    gregs[libc::REG_RBP as usize] = entry_rbp as i64;
+    gregs[libc::REG_RSP as usize] = entry_rbp as i64;
+    gregs[libc::REG_RIP as usize] = landing as i64;
+    gregs[libc::REG_RAX as usize] = 0;
+}
+
+/// Hand a fault outside the jit code to the previous handler.
+///
+/// # Safety
+/// Only called from the trap handler with its arguments.
+#[cfg(target_arch = "x86_64")]
+unsafe fn chain(signum: i32, info: *mut siginfo_t, ctx: *mut libc::c_void) {
+    let previous = match signum {
+        SIGFPE => &PREVIOUS_FPE_ACTION,
+        _ => &PREVIOUS_SEGV_ACTION,
+    };
+    let previous = previous.get().copied().unwrap_or_else(|| {
+        let mut default: sigaction = std::mem::zeroed();
+        default.sa_sigaction = libc::SIG_DFL;
+        default
+    });
+    match previous.sa_sigaction {
+        // the faulting instruction runs again once the handler returns, and
+        // faults into the default action or is ignored
+        libc::SIG_DFL | libc::SIG_IGN => {
+            sigaction(signum, &previous, std::ptr::null_mut());
+        }
+        handler if previous.sa_flags & libc::SA_SIGINFO != 0 => {
+            let handler: extern "C" fn(i32, *mut siginfo_t, *mut libc::c_void) =
+                std::mem::transmute(handler);
+            handler(signum, info, ctx);
+        }
+        handler => {
+            let handler: extern "C" fn(i32) = std::mem::transmute(handler);
+            handler(signum);
+        }
+    }
+}
+
+/// Install the trap handler for SIGSEGV and SIGFPE once, keeping the
+/// handlers it replaces for the faults outside the jit code. Only the x86
+/// jit code traps through signals.
 pub fn register_trap_handler() {
-    unsafe {
+    #[cfg(target_arch = "x86_64")]
+    static REGISTER: Once = Once::new();
+    #[cfg(target_arch = "x86_64")]
+    REGISTER.call_once(|| unsafe {
         let mut sa: sigaction = std::mem::zeroed();
-        sa.sa_sigaction = trap_handler as usize;
-        sa.sa_flags = libc::SA_SIGINFO;
-        sigaction(SIGSEGV, &sa, std::ptr::null_mut());
-    }
+        sa.sa_sigaction = trap_handler as *const () as usize;
+        // the handler may run on a stack overflow, on the alternate stack
+        sa.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        for (signum, previous_action) in [
+            (SIGSEGV, &PREVIOUS_SEGV_ACTION),
+            (SIGFPE, &PREVIOUS_FPE_ACTION),
+        ] {
+            let mut previous: sigaction = std::mem::zeroed();
+            sigaction(signum, &sa, &mut previous);
+            let _ = previous_action.set(previous);
+        }
+    });
 }
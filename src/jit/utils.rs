@@ -1,7 +1,7 @@
 use crate::module::{components::FuncDecl, insts::Instruction};
 
 use super::{
-    regalloc::{Register, REG_TEMP},
+    regalloc::{Register, X86Register, REG_TEMP, REG_TEMP2},
     X86JitCompiler,
 };
 
@@ -73,7 +73,60 @@ pub(crate) fn emit_mov_reg_to_reg(jit: &mut JitMemory, dst: Register, src: Regis
     }
 }
 
+/// Move every `(dst, src)` pair at once, so a move never overwrites the
+/// source of another one. The destinations must be distinct. A cycle of
+/// moves is broken by saving one destination in REG_TEMP2, REG_TEMP is used
+/// as by `emit_mov_reg_to_reg`.
+pub(crate) fn emit_parallel_moves(jit: &mut JitMemory, moves: &[(Register, Register)]) {
+    let mut pending = moves
+        .iter()
+        .filter(|(dst, src)| dst != src)
+        .copied()
+        .collect::<Vec<_>>();
+
+    while !pending.is_empty() {
+        // a move whose destination no other move still reads
+        let ready = pending
+            .iter()
+            .position(|(dst, _)| !pending.iter().any(|(_, src)| src == dst));
+        match ready {
+            Some(i) => {
+                let (dst, src) = pending.remove(i);
+                emit_mov_reg_to_reg(jit, dst, src);
+            }
+            None => {
+                // only cycles are left, free the destination of the first
+                // move by saving it for the moves reading it
+                let (dst, _) = pending[0];
+                emit_mov_reg_to_reg(jit, Register::Reg(REG_TEMP2), dst);
+                for (_, src) in pending.iter_mut() {
+                    if *src == dst {
+                        *src = Register::Reg(REG_TEMP2);
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl X86JitCompiler<'_> {
+    /// Load `addr`, the address of data or of a helper the code uses, into
+    /// `dst`. It is always encoded as a `movabs` with the label bound right
+    /// after it recorded in `addr_sites`, so the cache can patch in the
+    /// address of the same data in another process, see `setup::cache`.
+    pub(crate) fn emit_mov_addr(&mut self, dst: X86Register, addr: u64) {
+        let reg = dst.as_index() as u8;
+        // REX.W, with REX.B for r8 to r15, and B8+r
+        self.jit.emitb(0x48 | (reg >> 3));
+        self.jit.emitb(0xb8 | (reg & 7));
+        for byte in addr.to_le_bytes() {
+            self.jit.emitb(byte);
+        }
+        let site = self.jit.label();
+        self.emit_single_label(site);
+        self.addr_sites.push((site, addr));
+    }
+
     pub(crate) fn emit_mov_rawvalue_to_reg(&mut self, value: u64, reg: Register) {
         match reg {
             Register::Reg(r) => {
@@ -102,6 +155,8 @@ impl X86JitCompiler<'_> {
     // We get only an upper bound approximate, since we don't want too much overhead
     pub(crate) fn get_stack_size_in_byte(&self, fdecl: &FuncDecl) -> u64 {
         let nlocals = (fdecl.get_pure_locals().len() + fdecl.get_sig().params().len()) as u64;
+        // the save areas of the catch clauses live with the locals
+        let nsaved = (Self::exception_save_levels(fdecl) * self.exception.len()) as u64;
 
         let mut max_stack_depth: u64 = 0;
         let mut current_stack_depth: u64 = 0;
@@ -113,7 +168,10 @@ impl X86JitCompiler<'_> {
             let inst = &insts[pc];
             match inst {
                 // Constants push a value onto the stack
-                Instruction::I32Const { .. } | Instruction::F64Const { .. } => {
+                Instruction::I32Const { .. }
+                | Instruction::I64Const { .. }
+                | Instruction::F32Const { .. }
+                | Instruction::F64Const { .. } => {
                     current_stack_depth += 1;
                 }
 
@@ -131,27 +189,47 @@ impl X86JitCompiler<'_> {
                 }
 
                 // Binary operations pop two values and push one; net effect is -1
-                Instruction::I32Binop(_) | Instruction::F64Binop(_) => {
+                Instruction::I32Binop(_)
+                | Instruction::I64Binop(_)
+                | Instruction::F32Binop(_)
+                | Instruction::F64Binop(_) => {
                     current_stack_depth = current_stack_depth.saturating_sub(1);
                 }
 
                 // Unary operations consume one value and produce one; net effect is 0
-                Instruction::I32Unop(_) | Instruction::F64Unop(_) => {}
-
-                // Block, Loop, If: push current stack depth onto block stack
-                Instruction::Block { .. } | Instruction::Loop { .. } | Instruction::If { .. } => {
+                Instruction::I32Unop(_)
+                | Instruction::I64Unop(_)
+                | Instruction::F32Unop(_)
+                | Instruction::F64Unop(_) => {}
+
+                // Block, Loop, If, Try: push current stack depth onto block stack
+                Instruction::Block { .. }
+                | Instruction::Loop { .. }
+                | Instruction::If { .. }
+                | Instruction::Try { .. } => {
                     block_stack.push(current_stack_depth);
                 }
 
-                // Else: reset stack depth to the depth at the start of the block
-                Instruction::Else => {
+                // Else, CatchAll: reset stack depth to the depth at the start of the block
+                Instruction::Else | Instruction::CatchAll => {
                     if let Some(depth_at_if) = block_stack.last().cloned() {
                         current_stack_depth = depth_at_if;
                     }
                 }
 
+                // a catch clause starts with the values of the exception
+                Instruction::Catch { tag_index } => {
+                    if let Some(depth_at_try) = block_stack.last().cloned() {
+                        let nvalues = self
+                            .module
+                            .get_tag_sig(*tag_index)
+                            .map_or(0, |sig| sig.params().len());
+                        current_stack_depth = depth_at_try + nvalues as u64;
+                    }
+                }
+
                 // End: pop from block stack and take the maximum of current and block start depth
-                Instruction::End => {
+                Instruction::End | Instruction::Delegate { .. } => {
                     if let Some(depth_at_block_start) = block_stack.pop() {
                         current_stack_depth =
                             std::cmp::max(current_stack_depth, depth_at_block_start);
@@ -180,13 +258,17 @@ impl X86JitCompiler<'_> {
                     // If you have type info, adjust current_stack_depth accordingly
                 }
 
-                // Return resets the current stack depth
-                Instruction::Return => {
+                // Return, tail calls and throws reset the current stack depth
+                Instruction::Return
+                | Instruction::ReturnCall { .. }
+                | Instruction::ReturnCallIndirect { .. }
+                | Instruction::Throw { .. }
+                | Instruction::Rethrow { .. } => {
                     current_stack_depth = 0;
                 }
 
                 // Select pops three values and pushes one; net effect is -2
-                Instruction::Select => {
+                Instruction::Select | Instruction::TypedSelect { .. } => {
                     current_stack_depth = current_stack_depth.saturating_sub(2);
                 }
 
@@ -213,13 +295,74 @@ impl X86JitCompiler<'_> {
                     current_stack_depth = current_stack_depth.saturating_sub(1);
                 }
 
+                // RefNull and RefFunc push a reference onto the stack
+                Instruction::RefNull { .. } | Instruction::RefFunc { .. } => {
+                    current_stack_depth += 1;
+                }
+
+                // TableGet and RefIsNull pop one and push one; net effect is 0
+                Instruction::TableGet { .. } | Instruction::RefIsNull => {}
+
+                // TableSet pops the index and the value
+                Instruction::TableSet { .. } => {
+                    current_stack_depth = current_stack_depth.saturating_sub(2);
+                }
+
+                // TableInit and TableFill pop three values, ElemDrop does not
+                // touch the stack
+                Instruction::TableInit { .. } | Instruction::TableFill { .. } => {
+                    current_stack_depth = current_stack_depth.saturating_sub(3);
+                }
+                Instruction::ElemDrop { .. } => {}
+
+                // simd code is rejected by the jit, approximate the depth anyway
+                Instruction::V128Const { .. } => {
+                    current_stack_depth += 1;
+                }
+                Instruction::V128Load { .. }
+                | Instruction::V128LoadExtend { .. }
+                | Instruction::V128LoadSplat { .. }
+                | Instruction::V128LoadZero { .. }
+                | Instruction::V128Splat { .. }
+                | Instruction::V128ExtractLane { .. }
+                | Instruction::V128Unop(_)
+                | Instruction::V128Test(_) => {}
+                Instruction::V128ReplaceLane { .. }
+                | Instruction::V128LoadLane { .. }
+                | Instruction::I8x16Shuffle { .. }
+                | Instruction::V128Binop(_)
+                | Instruction::V128Shift(_) => {
+                    current_stack_depth = current_stack_depth.saturating_sub(1);
+                }
+                Instruction::V128Store { .. }
+                | Instruction::V128StoreLane { .. }
+                | Instruction::V128Bitselect => {
+                    current_stack_depth = current_stack_depth.saturating_sub(2);
+                }
+
+                // TableGrow pops two and pushes one, TableSize pushes one
+                Instruction::TableGrow { .. } => {
+                    current_stack_depth = current_stack_depth.saturating_sub(1);
+                }
+                Instruction::TableSize { .. } => {
+                    current_stack_depth += 1;
+                }
+
                 // Memory load instructions pop one address and push one value; net effect is 0
                 Instruction::I32Load { .. }
                 | Instruction::F64Load { .. }
                 | Instruction::I32Load8S { .. }
                 | Instruction::I32Load8U { .. }
                 | Instruction::I32Load16S { .. }
-                | Instruction::I32Load16U { .. } => {
+                | Instruction::I32Load16U { .. }
+                | Instruction::I64Load { .. }
+                | Instruction::I64Load8S { .. }
+                | Instruction::I64Load8U { .. }
+                | Instruction::I64Load16S { .. }
+                | Instruction::I64Load16U { .. }
+                | Instruction::I64Load32S { .. }
+                | Instruction::I64Load32U { .. }
+                | Instruction::F32Load { .. } => {
                     // Pops one, pushes one; stack depth remains the same
                 }
 
@@ -227,7 +370,12 @@ impl X86JitCompiler<'_> {
                 Instruction::I32Store { .. }
                 | Instruction::F64Store { .. }
                 | Instruction::I32Store8 { .. }
-                | Instruction::I32Store16 { .. } => {
+                | Instruction::I32Store16 { .. }
+                | Instruction::I64Store { .. }
+                | Instruction::I64Store8 { .. }
+                | Instruction::I64Store16 { .. }
+                | Instruction::I64Store32 { .. }
+                | Instruction::F32Store { .. } => {
                     if current_stack_depth >= 2 {
                         current_stack_depth -= 2;
                     } else {
@@ -242,6 +390,16 @@ impl X86JitCompiler<'_> {
 
                 // MemoryGrow pops one and pushes one; net effect is 0
                 Instruction::MemoryGrow { .. } => {}
+
+                // MemoryCopy, MemoryFill and MemoryInit pop three values
+                Instruction::MemoryCopy { .. }
+                | Instruction::MemoryFill { .. }
+                | Instruction::MemoryInit { .. } => {
+                    current_stack_depth = current_stack_depth.saturating_sub(3);
+                }
+
+                // DataDrop does not touch the stack
+                Instruction::DataDrop { .. } => {}
             }
 
             // Update max_stack_depth if current_stack_depth exceeds it
@@ -256,7 +414,7 @@ impl X86JitCompiler<'_> {
         // Each stack slot is 8 bytes (for alignment)
         //
         // +1 for storing the current memory size
-        let total_stack_size = (nlocals + max_stack_depth + 1) * 8;
+        let total_stack_size = (nlocals + nsaved + max_stack_depth + 1) * 8;
 
         // Align stack size to 16 bytes (common requirement for x86-64)
         (total_stack_size + 15) & !15
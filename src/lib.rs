@@ -0,0 +1,32 @@
+#![feature(explicit_tail_calls)]
+#![allow(incomplete_features)]
+
+//! A WebAssembly interpreter with jit compilers: a single pass x86-64 jit, a
+//! riscv64 (RV64GC) jit, and an optional Cranelift backend built with the
+//! `cranelift` feature.
+//!
+//! ```ignore
+//! use wasm_interpreter_rs::{WasmInterpreter, WasmInterpreterConfig, WasmModule, WasmValue, WasmVm};
+//!
+//! let bytes = std::fs::read("fib.wasm")?;
+//! let module = WasmModule::from_bytecode(&bytes)?;
+//! let config = WasmInterpreterConfig::new().jit(true);
+//! let vm = WasmInterpreter::with_config(module, &config)?;
+//! println!("{}", vm.run(vec![WasmValue::I32(10)])?);
+//! ```
+
+mod jit;
+pub mod module;
+pub mod vm;
+pub mod wast_runner;
+
+pub use module::{
+    features::{Feature, Features},
+    value_type::{FloatFormat, WasmValue},
+    wasm_module::WasmModule,
+};
+pub use vm::{
+    Caller, Checkpoint, Engine, Epoch, FrameState, ImportTraceSink, Instance, JitBackend, Linker,
+    Memory, RuntimeError, Snapshot, StepOutcome, TrapKind, WasiCtx, WasmBacktrace, WasmFrame,
+    WasmFunctionExecutor, WasmInterpreter, WasmInterpreterConfig, WasmVm,
+};
@@ -1,89 +1,668 @@
-#![feature(box_as_ptr)]
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
 
-use std::env;
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    error::ErrorKind,
+    CommandFactory, Parser,
+};
+use wasm_interpreter_rs::{
+    module::dump::dump_module,
+    vm::{TRAP_EXIT_STATUS, WASI_MODULE},
+    wast_runner, Checkpoint, Feature, Features, FloatFormat, ImportTraceSink, JitBackend, Linker,
+    RuntimeError, WasiCtx, WasmInterpreter, WasmInterpreterConfig, WasmModule, WasmValue, WasmVm,
+};
+use wasmparser::ValType;
 
-use module::{value_type::WasmValue, wasm_module::WasmModule};
+/// Run a function exported by a WebAssembly module, `main` by default.
+#[derive(Parser)]
+#[command(version)]
+struct CliArgs {
+    /// The module, in the binary or the text format, the script with --wast
+    /// or the directory of the scripts with --conformance
+    infile: String,
+    /// The arguments of the entry function, parsed as the types of its
+    /// parameters. A `d` suffix (`1.5d`) is accepted on floats. Everything
+    /// after INFILE is an argument. WASI commands also get them, after
+    /// INFILE, from `args_get`, and an entry without parameters only there.
+    /// `-a ARGS... INFILE`, with the arguments before the module, is the
+    /// same
+    #[arg(
+        allow_hyphen_values = true,
+        trailing_var_arg = true,
+        conflicts_with = "typed_args"
+    )]
+    args: Vec<String>,
+    /// An argument of the entry function with its type, e.g. `--arg i32:5
+    /// --arg f64:3.14`
+    #[arg(long = "arg", value_name = "TYPE:VALUE", value_parser = parse_typed_arg)]
+    typed_args: Vec<WasmValue>,
+    /// A variable of the environment WASI commands get from `environ_get`,
+    /// e.g. `--env HOME=/`
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env)]
+    envs: Vec<(String, String)>,
+    /// Make the WASI clocks all read this time, for runs that replay the
+    /// same
+    #[arg(long, value_name = "NANOS")]
+    fixed_clock: Option<u64>,
+    /// Make WASI random_get return the same bytes for every run with this
+    /// seed, instead of secure random bytes
+    #[arg(long, value_name = "SEED")]
+    random_seed: Option<u64>,
+    /// Read WASI stdin from FILE instead of the stdin of the process
+    #[arg(long, value_name = "FILE")]
+    stdin: Option<PathBuf>,
+    /// Let WASI commands open the files under the host directory HOST,
+    /// which they see as GUEST, e.g. `--dir ./data::/data`. A lone DIR is
+    /// seen under its own name
+    #[arg(long = "dir", value_name = "HOST::GUEST", value_parser = parse_dir)]
+    dirs: Vec<(String, String)>,
+    /// The value of an imported global, e.g. `--global env.base=i32:1024`
+    #[arg(long = "global", value_name = "MODULE.NAME=TYPE:VALUE", value_parser = parse_global)]
+    globals: Vec<(String, String, WasmValue)>,
+    /// Instantiate a module first and let the module import its exports from
+    /// NAME, e.g. `--preload libc=libc.wasm`. Preloaded modules run on the
+    /// jit too with `--jit`
+    #[arg(long = "preload", value_name = "NAME=FILE", value_parser = parse_preload)]
+    preloads: Vec<(String, String)>,
+    /// Let the module import the `spectest` module of the spec testsuite,
+    /// for modules taken from its scripts
+    #[arg(long)]
+    spectest: bool,
+    /// The export to run, instead of `main`, `_start` or the first exported
+    /// function
+    #[arg(long, value_name = "NAME")]
+    entry: Option<String>,
+    /// Compile the module with the jit instead of interpreting it
+    #[arg(long = "jit")]
+    jit_mode: bool,
+    /// Interpret the module even with --jit
+    #[arg(long)]
+    no_jit: bool,
+    /// The code generator of the jit, cranelift needs a build with the
+    /// `cranelift` feature
+    #[arg(
+        long,
+        default_value = "builtin",
+        value_parser = PossibleValuesParser::new(["builtin", "cranelift"]).map(|s| {
+            match s.as_str() {
+                "cranelift" => JitBackend::Cranelift,
+                _ => JitBackend::Builtin,
+            }
+        })
+    )]
+    backend: JitBackend,
+    /// Log every call to an imported function to stderr
+    #[arg(long)]
+    trace_imports: bool,
+    /// Log every call to an imported function to a JSON lines file
+    #[arg(long, value_name = "PATH")]
+    trace_imports_json: Option<PathBuf>,
+    /// Log every executed instruction to stderr
+    #[arg(long)]
+    trace: bool,
+    /// Run under the interactive debugger
+    #[arg(long)]
+    debugger: bool,
+    /// Register the jit code with gdb and lldb
+    #[arg(long)]
+    jit_debug_info: bool,
+    /// Stop at a breakpoint instruction when the jit code is entered, to
+    /// step through it in a debugger
+    #[arg(long)]
+    debug_break: bool,
+    /// Write the jit symbols to /tmp/perf-<pid>.map
+    #[arg(long)]
+    perf_map: bool,
+    /// Compile the functions on their first call instead of up front
+    #[arg(long)]
+    lazy_jit: bool,
+    /// Save the jit code to DIR and reuse it on later runs of the module
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<String>,
+    /// Print how many times the jit code was loaded from --cache-dir and
+    /// compiled there, as !cache:LOADED/COMPILED after the results
+    #[arg(long)]
+    cache_stats: bool,
+    /// Print the wasm frames to stderr on a trap
+    #[arg(long)]
+    backtrace: bool,
+    /// Write a wasm core dump to PATH on a trap
+    #[arg(long, value_name = "PATH")]
+    coredump: Option<String>,
+    /// Save the state of an interrupted run to PATH
+    #[arg(long, value_name = "PATH")]
+    checkpoint: Option<String>,
+    /// Continue the run saved with --checkpoint in PATH
+    #[arg(long, value_name = "PATH")]
+    resume: Option<String>,
+    /// Replace the NaNs produced by float arithmetic by the canonical NaN
+    #[arg(long)]
+    canonicalize_nans: bool,
+    /// How float results are printed
+    #[arg(
+        long,
+        default_value = "fixed",
+        value_parser = PossibleValuesParser::new(["fixed", "shortest", "hex"]).map(|s| {
+            match s.as_str() {
+                "shortest" => FloatFormat::Shortest,
+                "hex" => FloatFormat::Hex,
+                _ => FloatFormat::Fixed,
+            }
+        })
+    )]
+    float_format: FloatFormat,
+    /// Exit with status 134 on a trap instead of 0, errors always exit with 1.
+    /// A module that calls `proc_exit` always exits with its status
+    #[arg(long)]
+    exit_code: bool,
+    /// Print the parsed module instead of running it
+    #[arg(long)]
+    dump: bool,
+    /// Run a spec testsuite script on the interpreter and the jit
+    #[arg(long)]
+    wast: bool,
+    /// Run every spec testsuite script in a directory, and in its
+    /// subdirectories named after the proposals they test, and print the
+    /// directives passed on each backend per script and proposal
+    #[arg(long)]
+    conformance: bool,
+    /// How --conformance prints the matrix
+    #[arg(
+        long,
+        default_value = "markdown",
+        value_parser = PossibleValuesParser::new(["markdown", "json"])
+    )]
+    conformance_format: String,
+    /// Write the matrix of --conformance to PATH instead of stdout, which
+    /// the scripts print to as well
+    #[arg(long, value_name = "PATH")]
+    conformance_output: Option<PathBuf>,
+    /// Trap once the run has executed this many instructions
+    #[arg(long)]
+    fuel: Option<u64>,
+    /// Let the module use a WebAssembly proposal disabled by default
+    #[arg(
+        long = "enable",
+        value_name = "FEATURE",
+        value_parser = PossibleValuesParser::new(Feature::ALL.map(|f| f.name()))
+            .map(|s| Feature::from_name(&s).unwrap())
+    )]
+    enabled: Vec<Feature>,
+    /// Reject modules using a WebAssembly proposal, e.g. `--disable simd`
+    #[arg(
+        long = "disable",
+        value_name = "FEATURE",
+        value_parser = PossibleValuesParser::new(Feature::ALL.map(|f| f.name()))
+            .map(|s| Feature::from_name(&s).unwrap())
+    )]
+    disabled: Vec<Feature>,
+    /// Never let a memory grow past this many pages, a module whose memories
+    /// start larger fails to instantiate
+    #[arg(long, value_name = "PAGES")]
+    max_memory_pages: Option<u64>,
+    /// Never let a table grow past this many elements, a module whose tables
+    /// start larger fails to instantiate
+    #[arg(long, value_name = "N")]
+    max_table_elements: Option<u64>,
+    /// Trap once the run has taken this many milliseconds
+    #[arg(long, value_name = "MS")]
+    timeout: Option<u64>,
+    /// Interpret every function first and jit compile it once it was called
+    /// this many times
+    #[arg(long, value_name = "CALLS")]
+    tier_up: Option<u64>,
+}
 
-use vm::{WasmInterpreter, WasmVm};
+/// Parse `<type>:<value>`, e.g. `i32:5` or `f64:3.14`.
+fn parse_typed_arg(arg: &str) -> Result<WasmValue, String> {
+    let (ty, value) = arg
+        .split_once(':')
+        .ok_or("expected <type>:<value>, e.g. i32:5")?;
+    let ty = match ty {
+        "i32" => ValType::I32,
+        "i64" => ValType::I64,
+        "f32" => ValType::F32,
+        "f64" => ValType::F64,
+        _ => {
+            return Err(format!(
+                "unsupported type {}, expected i32, i64, f32 or f64",
+                ty
+            ))
+        }
+    };
+    parse_value(value, ty)
+}
 
-mod jit;
-mod module;
-mod vm;
+/// Parse `<module>.<name>=<type>:<value>`, e.g. `env.base=i32:1024`.
+fn parse_global(arg: &str) -> Result<(String, String, WasmValue), String> {
+    let (import, value) = arg
+        .split_once('=')
+        .ok_or("expected <module>.<name>=<type>:<value>, e.g. env.base=i32:1024")?;
+    let (module, name) = import
+        .split_once('.')
+        .ok_or_else(|| format!("expected <module>.<name>, got {}", import))?;
+    Ok((
+        module.to_string(),
+        name.to_string(),
+        parse_typed_arg(value)?,
+    ))
+}
 
-struct WasmInterpreterConfig {
-    wasm_args: Vec<WasmValue>,
-    infile: String,
-    jit_mode: bool,
+/// Parse `<key>=<value>`, e.g. `HOME=/`.
+fn parse_env(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or("expected <key>=<value>, e.g. HOME=/")?;
+    Ok((key.to_string(), value.to_string()))
 }
 
-fn parse_args() -> WasmInterpreterConfig {
-    let args: Vec<String> = env::args().collect();
+/// Parse `<host>::<guest>` or `<dir>`, e.g. `./data::/data`.
+fn parse_dir(arg: &str) -> Result<(String, String), String> {
+    let (host, guest) = arg.split_once("::").unwrap_or((arg, arg));
+    Ok((host.to_string(), guest.to_string()))
+}
 
-    let mut wasm_args_str = vec![];
-    let mut infile = String::new();
-    let mut jit_mode = false;
-    let mut i = 1;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--jit" => {
-                jit_mode = true;
-                i += 1;
-            }
-            "-a" => {
-                i += 1;
-                while i < args.len() - 1 {
-                    wasm_args_str.push(args[i].clone());
-                    i += 1;
-                }
-            }
-            _ => {
-                infile = args[i].clone();
-                i += 1;
+/// Parse `<name>=<file>`, e.g. `libc=libc.wasm`.
+fn parse_preload(arg: &str) -> Result<(String, String), String> {
+    let (name, file) = arg
+        .split_once('=')
+        .ok_or("expected <name>=<file>, e.g. libc=libc.wasm")?;
+    Ok((name.to_string(), file.to_string()))
+}
+
+/// The proposals enabled with --enable and --disable.
+fn features(args: &CliArgs) -> Features {
+    let mut features = Features::default();
+    for feature in &args.enabled {
+        features.set(*feature, true);
+    }
+    for feature in &args.disabled {
+        features.set(*feature, false);
+    }
+    features
+}
+
+/// The engine, the proposals and the limits of the module and the preloaded
+/// ones.
+fn base_config(args: &CliArgs) -> WasmInterpreterConfig<'static> {
+    let mut config = WasmInterpreterConfig::new()
+        .jit(args.jit_mode && !args.no_jit)
+        .jit_backend(args.backend)
+        .features(features(args));
+    if let Some(pages) = args.max_memory_pages {
+        config = config.max_memory_pages(pages);
+    }
+    if let Some(elements) = args.max_table_elements {
+        config = config.max_table_elements(elements);
+    }
+    if let Some(dir) = &args.cache_dir {
+        config = config.jit_cache_dir(dir);
+    }
+    config
+}
+
+/// Instantiate `bytes` with the definitions of `linker` so far, then define
+/// its exports under `name`. The instance borrows the binary, which must
+/// outlive the linker as the exports are called from other instances.
+fn preload<'a>(
+    linker: &mut Linker<'a>,
+    name: &str,
+    bytes: &'a [u8],
+    args: &CliArgs,
+) -> anyhow::Result<()> {
+    let module = WasmModule::from_bytecode_with_features(bytes, &features(args))?;
+    let config = base_config(args).linker(linker.clone());
+    let instance = Rc::new(WasmInterpreter::with_config(module, &config)?);
+    linker.instance(name, &instance)?;
+    Ok(())
+}
+
+/// Integers may also be given unsigned, `i32:4294967295` is -1.
+fn parse_value(value: &str, ty: ValType) -> Result<WasmValue, String> {
+    let parsed = match ty {
+        ValType::I32 => value
+            .parse::<i32>()
+            .ok()
+            .or_else(|| value.parse::<u32>().ok().map(|v| v as i32))
+            .map(WasmValue::I32),
+        ValType::I64 => value
+            .parse::<i64>()
+            .ok()
+            .or_else(|| value.parse::<u64>().ok().map(|v| v as i64))
+            .map(WasmValue::I64),
+        ValType::F32 => value.parse().ok().map(WasmValue::F32),
+        ValType::F64 => value.parse().ok().map(WasmValue::F64),
+        _ => return Err(format!("{} parameters cannot be passed", ty)),
+    };
+    parsed.ok_or_else(|| format!("invalid {} value {}", ty, value))
+}
+
+/// The arguments of the entry function, checked against its parameters.
+fn main_args(args: &CliArgs, module: &WasmModule) -> Result<Vec<WasmValue>, String> {
+    // a resumed run continues with the arguments it was started with, and
+    // a missing entry function is reported by the run
+    if args.resume.is_some() {
+        return Ok(vec![]);
+    }
+    let Some(sig) = module
+        .get_entry_index(args.entry.as_deref())
+        .and_then(|index| module.get_func(index))
+        .map(|func| func.get_sig())
+    else {
+        return Ok(vec![]);
+    };
+    let params = sig.params();
+    // the arguments of a command are for args_get then
+    if params.is_empty() && args.typed_args.is_empty() && is_wasi_command(module) {
+        return Ok(vec![]);
+    }
+    let nargs = args.args.len().max(args.typed_args.len());
+    if nargs != params.len() {
+        return Err(format!(
+            "the entry function takes {} arguments ({}), {} given",
+            params.len(),
+            params
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            nargs
+        ));
+    }
+    if !args.typed_args.is_empty() {
+        for (i, (arg, ty)) in args.typed_args.iter().zip(params).enumerate() {
+            if !arg.has_type(ty) {
+                return Err(format!("argument {} of the entry function is {}", i, ty));
             }
         }
+        return Ok(args.typed_args.clone());
     }
-
-    let wasm_args = wasm_args_str
+    args.args
         .iter()
-        .map(|arg| {
-            if arg.ends_with("d") {
-                let arg = &arg[..arg.len() - 1];
-                WasmValue::F64(arg.parse().unwrap())
-            } else {
-                WasmValue::I32(arg.parse().unwrap())
-            }
+        .zip(params)
+        .map(|(arg, ty)| {
+            let value = match ty {
+                ValType::F32 | ValType::F64 => arg.strip_suffix('d').unwrap_or(arg),
+                _ => arg,
+            };
+            parse_value(value, *ty)
         })
-        .collect();
+        .collect()
+}
+
+/// Whether `module` imports WASI functions.
+fn is_wasi_command(module: &WasmModule) -> bool {
+    (0..module.get_num_func_imports() as u32).any(|i| {
+        module
+            .get_imports()
+            .get_func_import(i)
+            .is_some_and(|import| import.module == WASI_MODULE)
+    })
+}
+
+/// Run a spec testsuite script on both the interpreter and the jit, exits
+/// with 1 if the script cannot be read or parsed or if any directive failed.
+fn run_wast(infile: &str) {
+    let report = match wast_runner::run_wast_file(infile) {
+        Ok(report) => report,
+        Err(e) => fail(format!("{}: {}", infile, e)),
+    };
+    for directive in &report.directives {
+        println!("{}:{}", infile, directive);
+    }
+    println!("interp: {}", report.interp_summary());
+    println!("jit: {}", report.jit_summary());
+    if report.has_failures() {
+        std::process::exit(1);
+    }
+}
+
+/// Print the conformance matrix of the scripts in `dir`, or write it to
+/// `output`, exits with 1 if the directory cannot be read or the matrix
+/// cannot be written.
+fn run_conformance(dir: &str, format: &str, output: Option<&Path>) {
+    let report = match wast_runner::run_conformance(dir) {
+        Ok(report) => report,
+        Err(e) => fail(format!("{}: {}", dir, e)),
+    };
+    let matrix = match format {
+        "json" => format!("{}\n", report.json()),
+        _ => report.markdown(),
+    };
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, matrix) {
+                fail(format!("{}: {}", path.display(), e));
+            }
+        }
+        None => print!("{}", matrix),
+    }
+}
 
-    WasmInterpreterConfig {
-        wasm_args,
-        infile,
-        jit_mode,
+/// Parse the command line, the arguments of the entry function may also
+/// come before the module, `-a ARGS... INFILE`.
+fn parse_cli() -> CliArgs {
+    let argv = std::env::args().collect::<Vec<_>>();
+    match CliArgs::try_parse_from(&argv) {
+        Ok(args) => args,
+        Err(e) => match dash_a_to_trailing(&argv) {
+            Some(argv) if e.kind() == ErrorKind::UnknownArgument => CliArgs::parse_from(argv),
+            _ => e.exit(),
+        },
     }
 }
 
+/// `... -a ARGS... INFILE` as `... INFILE ARGS...`, `None` without `-a`
+/// followed by the module.
+fn dash_a_to_trailing(argv: &[String]) -> Option<Vec<String>> {
+    let dash_a = argv.iter().position(|arg| arg == "-a")?;
+    let (infile, wasm_args) = argv[dash_a + 1..].split_last()?;
+    let mut rewritten = argv[..dash_a].to_vec();
+    rewritten.push(infile.clone());
+    rewritten.extend_from_slice(wasm_args);
+    Some(rewritten)
+}
+
 fn main() {
     env_logger::init();
 
-    let args = parse_args();
+    let args = parse_cli();
+    if args.wast {
+        run_wast(&args.infile);
+        return;
+    }
+    if args.conformance {
+        run_conformance(
+            &args.infile,
+            &args.conformance_format,
+            args.conformance_output.as_deref(),
+        );
+        return;
+    }
 
-    let wasm_bytes: Vec<u8> = std::fs::read(&args.infile).unwrap();
-    let module = WasmModule::from_bytecode(&wasm_bytes);
-    let module = match module {
+    // .wat/.wast text is translated to binary, binary modules pass through
+    let wasm_bytes = match wat::parse_file(&args.infile) {
+        Ok(wasm_bytes) => wasm_bytes,
+        Err(e) => fail(format!("{}: {}", args.infile, e)),
+    };
+    let module = match WasmModule::from_bytecode_with_features(&wasm_bytes, &features(&args)) {
         Ok(module) => module,
-        Err(e) => {
-            panic!("{:?}", e);
-        }
+        Err(e) => fail(format!("{}: {:#}", args.infile, e)),
+    };
+    if args.dump {
+        print!("{}", dump_module(&module));
+        return;
+    }
+    let wasm_args = match main_args(&args, &module) {
+        Ok(wasm_args) => wasm_args,
+        Err(msg) => CliArgs::command()
+            .error(ErrorKind::ValueValidation, msg)
+            .exit(),
     };
 
-    let vm = WasmInterpreter::from_module(module, args.jit_mode);
-    match vm.run(args.wasm_args) {
+    let preloads = args
+        .preloads
+        .iter()
+        .map(|(name, file)| (name, file, wat::parse_file(file)))
+        .collect::<Vec<_>>();
+    let mut linker = Linker::new();
+    let mut wasi = WasiCtx::new().args(std::iter::once(&args.infile).chain(&args.args));
+    for (key, value) in &args.envs {
+        wasi = wasi.env(key, value);
+    }
+    if let Some(nanos) = args.fixed_clock {
+        wasi = wasi.fixed_clock(nanos);
+    }
+    if let Some(seed) = args.random_seed {
+        wasi = wasi.random_seed(seed);
+    }
+    if let Some(path) = &args.stdin {
+        wasi = wasi.stdin(path);
+    }
+    for (host, guest) in &args.dirs {
+        wasi = wasi.preopen_dir(host, guest);
+    }
+    if let Err(e) = linker.wasi(wasi) {
+        CliArgs::command()
+            .error(ErrorKind::ValueValidation, e)
+            .exit()
+    }
+    if args.spectest {
+        if let Err(e) = linker.spectest() {
+            fail(e);
+        }
+    }
+    for (module_name, name, value) in &args.globals {
+        if let Err(e) = linker.global(module_name, name, *value) {
+            CliArgs::command()
+                .error(ErrorKind::ValueValidation, e)
+                .exit()
+        }
+    }
+    for (name, file, bytes) in &preloads {
+        let preloaded = match bytes {
+            Ok(bytes) => preload(&mut linker, name, bytes, &args),
+            Err(e) => Err(anyhow::anyhow!("{}", e)),
+        };
+        if let Err(e) = preloaded {
+            CliArgs::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!("failed to preload {}: {}", file, e),
+                )
+                .exit()
+        }
+    }
+    let config = base_config(&args).linker(linker);
+    // a segment that does not fit in its memory or table traps while the
+    // module is instantiated, as does its start function. A missing import
+    // or a memory or a table past the limits keeps it from running
+    let mut vm = match WasmInterpreter::with_config(module, &config).map_err(RuntimeError::from) {
+        Ok(vm) => vm,
+        Err(RuntimeError::Exit(status)) => std::process::exit(status),
+        Err(e @ RuntimeError::Trap(..)) => {
+            log::debug!("{}", e);
+            print!("!trap");
+            exit_with(&args, TRAP_EXIT_STATUS);
+            return;
+        }
+        Err(e) => fail(format!("{}: {}", args.infile, e)),
+    };
+    let trace_imports = match &args.trace_imports_json {
+        Some(path) => Some(ImportTraceSink::JsonFile(path.clone())),
+        None => args.trace_imports.then_some(ImportTraceSink::Stderr),
+    };
+    if let Some(sink) = &trace_imports {
+        if let Err(e) = vm.trace_imports(sink) {
+            fail(format!("failed to open the import trace: {}", e));
+        }
+    }
+    vm.trace_instructions(args.trace);
+    if args.debugger {
+        vm.enable_debugger();
+    }
+    vm.jit_debug_info(args.jit_debug_info);
+    vm.debug_break(args.debug_break);
+    vm.perf_map(args.perf_map);
+    vm.lazy_jit(args.lazy_jit);
+    vm.jit_backtrace(args.backtrace);
+    if let Some(path) = &args.coredump {
+        vm.coredump_on_trap(path.into());
+    }
+    vm.checkpoint_on_interrupt(args.checkpoint.is_some());
+    vm.canonicalize_nans(args.canonicalize_nans);
+    if let Some(name) = &args.entry {
+        vm.set_entry(name.clone());
+    }
+    vm.float_format(args.float_format);
+    if let Some(calls) = args.tier_up {
+        vm.tier_up(calls);
+    }
+    if let Some(fuel) = args.fuel {
+        vm.set_fuel(fuel);
+    }
+    if let Some(ms) = args.timeout {
+        vm.set_timeout(Duration::from_millis(ms));
+    }
+    let result = match &args.resume {
+        Some(path) => {
+            let checkpoint = match Checkpoint::load(path) {
+                Ok(checkpoint) => checkpoint,
+                Err(e) => fail(format!("failed to read the checkpoint {}: {}", path, e)),
+            };
+            vm.resume(&checkpoint)
+                .map(|results| vm.format_results(&results))
+        }
+        None => vm.run(wasm_args),
+    };
+    match result {
         Ok(r) => {
-            print!("{}", r)
+            print!("{}", r);
+            print_cache_stats(&args, &vm);
         }
+        Err(RuntimeError::Exit(status)) => std::process::exit(status),
+        // e.g. code the jit refuses to compile, which is not a trap of the
+        // module
+        Err(RuntimeError::Other(e)) => fail(format!("{}: {}", args.infile, e)),
         Err(e) => {
             log::debug!("{}", e);
+            if let Some(backtrace) = e.backtrace().filter(|_| args.backtrace) {
+                eprintln!("{}", backtrace);
+            }
+            if let (Some(path), Some(checkpoint)) = (&args.checkpoint, vm.take_checkpoint()) {
+                if let Err(e) = checkpoint.save(path) {
+                    fail(format!("failed to write the checkpoint {}: {}", path, e));
+                }
+            }
             print!("!trap");
+            print_cache_stats(&args, &vm);
+            exit_with(&args, TRAP_EXIT_STATUS);
         }
     }
 }
+
+/// The `--cache-stats` line, after the results of the run.
+fn print_cache_stats(args: &CliArgs, vm: &WasmInterpreter) {
+    if args.cache_stats {
+        let (loaded, compiled) = vm.jit_cache_stats();
+        print!("!cache:{}/{}", loaded, compiled);
+    }
+}
+
+/// Report an error that keeps the module from running and exit with 1,
+/// whether exit codes are enabled or not.
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", msg);
+    std::process::exit(1);
+}
+
+/// Exit with `status` if exit codes are enabled.
+fn exit_with(args: &CliArgs, status: i32) {
+    if args.exit_code {
+        std::process::exit(status);
+    }
+}
@@ -0,0 +1,826 @@
+//! The function bodies lowered for the interpreter. Every instruction maps
+//! to one fixed width `Op` at the same pc, so the pcs of the trace, the
+//! debugger, the backtraces and the checkpoints keep pointing into the
+//! decoded instructions.
+//!
+//! The control flow is resolved while lowering: blocks and ends do nothing,
+//! the end of the function returns, `if`, `else` and the branches jump to
+//! their target pc, and a branch knows the operand stack height and the
+//! number of values it carries from the validator. Only the function body
+//! and the try blocks leave a control frame at runtime, for the exceptions
+//! to find their handler.
+
+use wasmparser::{BlockType, RefType};
+
+use super::{
+    components::FuncDecl,
+    insts::{
+        F32Binop, F32Unop, F64Binop, F64Unop, I32Binop, I32Unop, I64Binop, I64Unop, Instruction,
+        MemArg, V128Binop, V128Shape, V128Shift, V128Test, V128Unop,
+    },
+    wasm_module::{block_type_num_params, block_type_num_results, WasmModule},
+};
+
+/// A lowered instruction. The operands that do not fit in 16 bytes are kept
+/// in the tables of the `Code`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Op {
+    Unreachable,
+    /// also `block` and the `end` of every block but a try block
+    Nop,
+    /// the header of a loop, a branch to the loop runs it again
+    Loop,
+    /// pop the condition, go to `target` if it is zero: past the `else` or
+    /// to the `end`
+    If {
+        target: u32,
+    },
+    /// `else` and the catch clauses, which end the part of the block before
+    /// them and go to the `end`
+    Jump {
+        target: u32,
+    },
+    /// enter the try block of `Code::handlers[handler]`
+    Try {
+        handler: u32,
+    },
+    /// the `end` or `delegate` of a try block, it leaves the try block or
+    /// the catch clause
+    EndTry,
+    Throw {
+        tag_index: u32,
+    },
+    /// rethrow the exception of the catch clause `depth` try blocks up
+    Rethrow {
+        depth: u32,
+    },
+    /// branch to `Code::branches[branch]`
+    Br {
+        branch: u32,
+    },
+    BrIf {
+        branch: u32,
+    },
+    /// branch to `Code::branches[first + index]`, `Code::branches[first +
+    /// len]` for an index out of range
+    BrTable {
+        first: u32,
+        len: u32,
+    },
+    /// also the `end` of the function body
+    Return,
+    Call {
+        func_idx: u32,
+    },
+    CallIndirect {
+        type_index: u32,
+        table_index: u32,
+    },
+    ReturnCall {
+        func_idx: u32,
+    },
+    ReturnCallIndirect {
+        type_index: u32,
+        table_index: u32,
+    },
+    /// `drop` and the typed `select` too
+    Drop,
+    Select,
+    LocalGet {
+        local_idx: u32,
+    },
+    LocalSet {
+        local_idx: u32,
+    },
+    LocalTee {
+        local_idx: u32,
+    },
+    GlobalGet {
+        global_idx: u32,
+    },
+    GlobalSet {
+        global_idx: u32,
+    },
+    TableGet {
+        table: u32,
+    },
+    TableSet {
+        table: u32,
+    },
+    TableInit {
+        elem_index: u32,
+        table: u32,
+    },
+    ElemDrop {
+        elem_index: u32,
+    },
+    TableGrow {
+        table: u32,
+    },
+    TableSize {
+        table: u32,
+    },
+    TableFill {
+        table: u32,
+    },
+    RefNull {
+        ty: RefType,
+    },
+    RefIsNull,
+    RefFunc {
+        func_idx: u32,
+    },
+    // memory accesses, the alignment hint is dropped
+    I32Load {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load {
+        mem: u32,
+        offset: u64,
+    },
+    F32Load {
+        mem: u32,
+        offset: u64,
+    },
+    F64Load {
+        mem: u32,
+        offset: u64,
+    },
+    I32Load8S {
+        mem: u32,
+        offset: u64,
+    },
+    I32Load8U {
+        mem: u32,
+        offset: u64,
+    },
+    I32Load16S {
+        mem: u32,
+        offset: u64,
+    },
+    I32Load16U {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load8S {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load8U {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load16S {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load16U {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load32S {
+        mem: u32,
+        offset: u64,
+    },
+    I64Load32U {
+        mem: u32,
+        offset: u64,
+    },
+    I32Store {
+        mem: u32,
+        offset: u64,
+    },
+    I64Store {
+        mem: u32,
+        offset: u64,
+    },
+    F32Store {
+        mem: u32,
+        offset: u64,
+    },
+    F64Store {
+        mem: u32,
+        offset: u64,
+    },
+    I32Store8 {
+        mem: u32,
+        offset: u64,
+    },
+    I32Store16 {
+        mem: u32,
+        offset: u64,
+    },
+    I64Store8 {
+        mem: u32,
+        offset: u64,
+    },
+    I64Store16 {
+        mem: u32,
+        offset: u64,
+    },
+    I64Store32 {
+        mem: u32,
+        offset: u64,
+    },
+    MemorySize {
+        mem: u32,
+    },
+    MemoryGrow {
+        mem: u32,
+    },
+    MemoryCopy {
+        dst_mem: u32,
+        src_mem: u32,
+    },
+    MemoryFill {
+        mem: u32,
+    },
+    MemoryInit {
+        data_index: u32,
+        mem: u32,
+    },
+    DataDrop {
+        data_index: u32,
+    },
+    I32Const {
+        value: i32,
+    },
+    I64Const {
+        value: i64,
+    },
+    F32Const {
+        value: f32,
+    },
+    F64Const {
+        value: f64,
+    },
+    I32Unop(I32Unop),
+    I32Binop(I32Binop),
+    I64Unop(I64Unop),
+    I64Binop(I64Binop),
+    F32Unop(F32Unop),
+    F32Binop(F32Binop),
+    F64Unop(F64Unop),
+    F64Binop(F64Binop),
+    V128Load {
+        mem: u32,
+        offset: u64,
+    },
+    V128Store {
+        mem: u32,
+        offset: u64,
+    },
+    // the lane operands come first so that the ops stay 16 bytes
+    V128LoadExtend {
+        shape: V128Shape,
+        signed: bool,
+        mem: u32,
+        offset: u64,
+    },
+    V128LoadSplat {
+        shape: V128Shape,
+        mem: u32,
+        offset: u64,
+    },
+    V128LoadZero {
+        shape: V128Shape,
+        mem: u32,
+        offset: u64,
+    },
+    V128LoadLane {
+        shape: V128Shape,
+        lane: u8,
+        mem: u32,
+        offset: u64,
+    },
+    V128StoreLane {
+        shape: V128Shape,
+        lane: u8,
+        mem: u32,
+        offset: u64,
+    },
+    /// push `Code::v128s[index]`
+    V128Const {
+        index: u32,
+    },
+    /// the lanes are the bytes of `Code::v128s[index]`, little endian
+    I8x16Shuffle {
+        index: u32,
+    },
+    V128Splat {
+        shape: V128Shape,
+    },
+    V128ExtractLane {
+        shape: V128Shape,
+        lane: u8,
+        signed: bool,
+    },
+    V128ReplaceLane {
+        shape: V128Shape,
+        lane: u8,
+    },
+    V128Bitselect,
+    V128Unop(V128Unop),
+    V128Binop(V128Binop),
+    V128Shift(V128Shift),
+    V128Test(V128Test),
+    // fused ops, see `fuse`
+    /// `local.get lhs`, `local.get rhs`, `i32.add`
+    I32AddLocals {
+        lhs: u32,
+        rhs: u32,
+    },
+    /// `i32.const value`, `i32.add`
+    I32AddConst {
+        value: i32,
+    },
+    /// an i32 comparison and a `br_if` on its result
+    I32CmpBrIf {
+        cmp: I32Binop,
+        branch: u32,
+    },
+}
+
+const _: () = assert!(std::mem::size_of::<Op>() == 16);
+
+impl Op {
+    /// The discriminant of the op, which indexes the handlers of the
+    /// interpreter.
+    pub const fn opcode(&self) -> usize {
+        // SAFETY: `Op` is `repr(u8)`, so every variant starts with its
+        // discriminant as a u8
+        unsafe { *(self as *const Op).cast::<u8>() as usize }
+    }
+
+    /// The op of the first instruction of a fused op, for running the
+    /// instructions one by one. Other ops are returned unchanged.
+    pub fn unfused(self) -> Op {
+        match self {
+            Op::I32AddLocals { lhs, .. } => Op::LocalGet { local_idx: lhs },
+            Op::I32AddConst { value } => Op::I32Const { value },
+            Op::I32CmpBrIf { cmp, .. } => Op::I32Binop(cmp),
+            op => op,
+        }
+    }
+}
+
+/// Where a branch goes and what it leaves on the operand stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Branch {
+    /// the `end` of the target block, or the `loop` itself
+    pub pc: u32,
+    /// the height of the operand stack below the block
+    pub height: u32,
+    /// the values carried to the target, the results of a block or the
+    /// parameters of a loop
+    pub arity: u32,
+    /// the try blocks left on the way, the target's own is left by its `end`
+    pub try_blocks: u32,
+}
+
+/// The handlers of a try block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handler {
+    /// the tag (None for catch_all) and the pc of each catch clause
+    pub catches: Vec<(Option<u32>, u32)>,
+    /// for a `delegate`, the try blocks between the try and the target
+    /// label, which the exception skips
+    pub delegate: Option<u32>,
+    /// the height of the operand stack below the block, a caught exception
+    /// leaves its values there
+    pub height: u32,
+}
+
+/// A lowered function body, see the module documentation.
+#[derive(Debug, Default, PartialEq)]
+pub struct Code {
+    pub ops: Box<[Op]>,
+    pub branches: Box<[Branch]>,
+    pub handlers: Box<[Handler]>,
+    /// the constants of `v128.const` and the lanes of `i8x16.shuffle`
+    pub v128s: Box<[u128]>,
+}
+
+/// A block enclosing the instruction being lowered.
+struct Label {
+    is_loop: bool,
+    /// the index of its handler for a try block
+    handler: Option<u32>,
+    start_pc: usize,
+    end_pc: usize,
+    height: u32,
+    arity: u32,
+}
+
+struct Lowering<'m, 'a> {
+    module: &'m WasmModule<'a>,
+    func: &'m FuncDecl,
+    labels: Vec<Label>,
+    branches: Vec<Branch>,
+    handlers: Vec<Handler>,
+    v128s: Vec<u128>,
+}
+
+/// Lower the body of `func`, which must be valid. `heights` is the height of
+/// the operand stack before each instruction, as found by the validator.
+pub(crate) fn lower(module: &WasmModule, func: &FuncDecl, heights: &[u32]) -> Code {
+    let insts = func.get_insts();
+    let mut lowering = Lowering {
+        module,
+        func,
+        labels: vec![Label {
+            is_loop: false,
+            handler: None,
+            start_pc: 0,
+            end_pc: insts.len().saturating_sub(1),
+            height: 0,
+            arity: func.get_sig().results().len() as u32,
+        }],
+        branches: vec![],
+        handlers: vec![],
+        v128s: vec![],
+    };
+    let mut ops: Box<[Op]> = insts
+        .iter()
+        .enumerate()
+        .map(|(pc, inst)| lowering.lower_inst(pc, inst, heights[pc]))
+        .collect();
+    fuse(&mut ops);
+
+    Code {
+        ops,
+        branches: lowering.branches.into(),
+        handlers: lowering.handlers.into(),
+        v128s: lowering.v128s.into(),
+    }
+}
+
+impl Lowering<'_, '_> {
+    /// Enter the block starting at `pc`, the operand stack is `height` high
+    /// with its parameters.
+    fn push_label(&mut self, pc: usize, ty: BlockType, height: u32, is_loop: bool) -> &Label {
+        let num_params = block_type_num_params(self.module, ty) as u32;
+        let num_results = block_type_num_results(self.module, ty) as u32;
+        // the body is valid, so every block is closed
+        let end_pc = self
+            .func
+            .get_block_targets(pc)
+            .map_or(pc, |targets| targets.end_pc);
+        self.labels.push(Label {
+            is_loop,
+            handler: None,
+            start_pc: pc,
+            end_pc,
+            // blocks in unreachable code see a stack that is too low
+            height: height.saturating_sub(num_params),
+            arity: if is_loop { num_params } else { num_results },
+        });
+        self.labels.last().unwrap()
+    }
+
+    /// The label `rel_depth` blocks up.
+    fn label(&self, rel_depth: u32) -> &Label {
+        &self.labels[self.labels.len() - 1 - rel_depth as usize]
+    }
+
+    /// The try blocks inside the label `rel_depth` blocks up.
+    fn try_blocks_within(&self, rel_depth: u32) -> u32 {
+        let inner = &self.labels[self.labels.len() - rel_depth as usize..];
+        inner.iter().filter(|label| label.handler.is_some()).count() as u32
+    }
+
+    fn branch(&mut self, rel_depth: u32) -> Branch {
+        let label = self.label(rel_depth);
+        Branch {
+            pc: if label.is_loop {
+                label.start_pc
+            } else {
+                label.end_pc
+            } as u32,
+            height: label.height,
+            arity: label.arity,
+            try_blocks: self.try_blocks_within(rel_depth),
+        }
+    }
+
+    fn add_branch(&mut self, branch: Branch) -> u32 {
+        self.branches.push(branch);
+        self.branches.len() as u32 - 1
+    }
+
+    fn add_v128(&mut self, value: u128) -> u32 {
+        self.v128s.push(value);
+        self.v128s.len() as u32 - 1
+    }
+
+    /// The handler of the innermost block, which is a try block.
+    fn handler(&mut self) -> &mut Handler {
+        let handler = self.labels.last().unwrap().handler.unwrap();
+        &mut self.handlers[handler as usize]
+    }
+
+    fn lower_inst(&mut self, pc: usize, inst: &Instruction, height: u32) -> Op {
+        match *inst {
+            Instruction::Unreachable => Op::Unreachable,
+            Instruction::Nop => Op::Nop,
+            Instruction::Block { ty } => {
+                self.push_label(pc, ty, height, false);
+                Op::Nop
+            }
+            Instruction::Loop { ty } => {
+                self.push_label(pc, ty, height, true);
+                Op::Loop
+            }
+            Instruction::If { ty } => {
+                // the condition is popped before the block is entered
+                self.push_label(pc, ty, height.saturating_sub(1), false);
+                let targets = self.func.get_block_targets(pc);
+                let target = match targets {
+                    Some(targets) => targets.else_pc.map_or(targets.end_pc, |pc| pc + 1),
+                    None => pc,
+                };
+                Op::If {
+                    target: target as u32,
+                }
+            }
+            Instruction::Else => Op::Jump {
+                target: self.label(0).end_pc as u32,
+            },
+            Instruction::End => match self.labels.pop() {
+                Some(Label {
+                    handler: Some(_), ..
+                }) => Op::EndTry,
+                _ if self.labels.is_empty() => Op::Return,
+                _ => Op::Nop,
+            },
+            Instruction::Try { ty } => {
+                let height = self.push_label(pc, ty, height, false).height;
+                self.handlers.push(Handler {
+                    catches: vec![],
+                    delegate: None,
+                    height,
+                });
+                let handler = self.handlers.len() as u32 - 1;
+                self.labels.last_mut().unwrap().handler = Some(handler);
+                Op::Try { handler }
+            }
+            Instruction::Catch { tag_index } => {
+                self.handler().catches.push((Some(tag_index), pc as u32));
+                Op::Jump {
+                    target: self.label(0).end_pc as u32,
+                }
+            }
+            Instruction::CatchAll => {
+                self.handler().catches.push((None, pc as u32));
+                Op::Jump {
+                    target: self.label(0).end_pc as u32,
+                }
+            }
+            Instruction::Delegate { rel_depth } => {
+                // label 0 of the delegate is the block around the try
+                let try_label = self.labels.pop().unwrap();
+                let skipped = self.try_blocks_within(rel_depth);
+                if let Some(handler) = try_label.handler {
+                    self.handlers[handler as usize].delegate = Some(skipped);
+                }
+                Op::EndTry
+            }
+            Instruction::Throw { tag_index } => Op::Throw { tag_index },
+            Instruction::Rethrow { rel_depth } => Op::Rethrow {
+                depth: self.try_blocks_within(rel_depth),
+            },
+            Instruction::Br { rel_depth } => {
+                let branch = self.branch(rel_depth);
+                Op::Br {
+                    branch: self.add_branch(branch),
+                }
+            }
+            Instruction::BrIf { rel_depth } => {
+                let branch = self.branch(rel_depth);
+                Op::BrIf {
+                    branch: self.add_branch(branch),
+                }
+            }
+            Instruction::BrTable { ref table } => {
+                let first = self.branches.len() as u32;
+                for &rel_depth in table.targets.iter().chain([&table.default_target]) {
+                    let branch = self.branch(rel_depth);
+                    self.add_branch(branch);
+                }
+                Op::BrTable {
+                    first,
+                    len: table.targets.len() as u32,
+                }
+            }
+            Instruction::Return => Op::Return,
+            Instruction::Call { func_idx } => Op::Call { func_idx },
+            Instruction::CallIndirect {
+                type_index,
+                table_index,
+            } => Op::CallIndirect {
+                type_index,
+                table_index,
+            },
+            Instruction::ReturnCall { func_idx } => Op::ReturnCall { func_idx },
+            Instruction::ReturnCallIndirect {
+                type_index,
+                table_index,
+            } => Op::ReturnCallIndirect {
+                type_index,
+                table_index,
+            },
+            Instruction::Drop => Op::Drop,
+            Instruction::Select | Instruction::TypedSelect { .. } => Op::Select,
+            Instruction::LocalGet { local_idx } => Op::LocalGet { local_idx },
+            Instruction::LocalSet { local_idx } => Op::LocalSet { local_idx },
+            Instruction::LocalTee { local_idx } => Op::LocalTee { local_idx },
+            Instruction::GlobalGet { global_idx } => Op::GlobalGet { global_idx },
+            Instruction::GlobalSet { global_idx } => Op::GlobalSet { global_idx },
+            Instruction::TableGet { table } => Op::TableGet { table },
+            Instruction::TableSet { table } => Op::TableSet { table },
+            Instruction::TableInit { elem_index, table } => Op::TableInit { elem_index, table },
+            Instruction::ElemDrop { elem_index } => Op::ElemDrop { elem_index },
+            Instruction::TableGrow { table } => Op::TableGrow { table },
+            Instruction::TableSize { table } => Op::TableSize { table },
+            Instruction::TableFill { table } => Op::TableFill { table },
+            Instruction::RefNull { ty } => Op::RefNull { ty },
+            Instruction::RefIsNull => Op::RefIsNull,
+            Instruction::RefFunc { func_idx } => Op::RefFunc { func_idx },
+            Instruction::I32Load { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Load { mem, offset })
+            }
+            Instruction::I64Load { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load { mem, offset })
+            }
+            Instruction::F32Load { memarg } => {
+                mem_op(memarg, |mem, offset| Op::F32Load { mem, offset })
+            }
+            Instruction::F64Load { memarg } => {
+                mem_op(memarg, |mem, offset| Op::F64Load { mem, offset })
+            }
+            Instruction::I32Load8S { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Load8S { mem, offset })
+            }
+            Instruction::I32Load8U { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Load8U { mem, offset })
+            }
+            Instruction::I32Load16S { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Load16S { mem, offset })
+            }
+            Instruction::I32Load16U { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Load16U { mem, offset })
+            }
+            Instruction::I64Load8S { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load8S { mem, offset })
+            }
+            Instruction::I64Load8U { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load8U { mem, offset })
+            }
+            Instruction::I64Load16S { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load16S { mem, offset })
+            }
+            Instruction::I64Load16U { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load16U { mem, offset })
+            }
+            Instruction::I64Load32S { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load32S { mem, offset })
+            }
+            Instruction::I64Load32U { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Load32U { mem, offset })
+            }
+            Instruction::I32Store { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Store { mem, offset })
+            }
+            Instruction::I64Store { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Store { mem, offset })
+            }
+            Instruction::F32Store { memarg } => {
+                mem_op(memarg, |mem, offset| Op::F32Store { mem, offset })
+            }
+            Instruction::F64Store { memarg } => {
+                mem_op(memarg, |mem, offset| Op::F64Store { mem, offset })
+            }
+            Instruction::I32Store8 { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Store8 { mem, offset })
+            }
+            Instruction::I32Store16 { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I32Store16 { mem, offset })
+            }
+            Instruction::I64Store8 { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Store8 { mem, offset })
+            }
+            Instruction::I64Store16 { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Store16 { mem, offset })
+            }
+            Instruction::I64Store32 { memarg } => {
+                mem_op(memarg, |mem, offset| Op::I64Store32 { mem, offset })
+            }
+            Instruction::MemorySize { mem } => Op::MemorySize { mem },
+            Instruction::MemoryGrow { mem } => Op::MemoryGrow { mem },
+            Instruction::MemoryCopy { dst_mem, src_mem } => Op::MemoryCopy { dst_mem, src_mem },
+            Instruction::MemoryFill { mem } => Op::MemoryFill { mem },
+            Instruction::MemoryInit { data_index, mem } => Op::MemoryInit { data_index, mem },
+            Instruction::DataDrop { data_index } => Op::DataDrop { data_index },
+            Instruction::I32Const { value } => Op::I32Const { value },
+            Instruction::I64Const { value } => Op::I64Const { value },
+            Instruction::F32Const { value } => Op::F32Const { value },
+            Instruction::F64Const { value } => Op::F64Const { value },
+            Instruction::I32Unop(op) => Op::I32Unop(op),
+            Instruction::I32Binop(op) => Op::I32Binop(op),
+            Instruction::I64Unop(op) => Op::I64Unop(op),
+            Instruction::I64Binop(op) => Op::I64Binop(op),
+            Instruction::F32Unop(op) => Op::F32Unop(op),
+            Instruction::F32Binop(op) => Op::F32Binop(op),
+            Instruction::F64Unop(op) => Op::F64Unop(op),
+            Instruction::F64Binop(op) => Op::F64Binop(op),
+            Instruction::V128Load { memarg } => {
+                mem_op(memarg, |mem, offset| Op::V128Load { mem, offset })
+            }
+            Instruction::V128Store { memarg } => {
+                mem_op(memarg, |mem, offset| Op::V128Store { mem, offset })
+            }
+            Instruction::V128LoadExtend {
+                memarg,
+                shape,
+                signed,
+            } => mem_op(memarg, |mem, offset| Op::V128LoadExtend {
+                shape,
+                signed,
+                mem,
+                offset,
+            }),
+            Instruction::V128LoadSplat { memarg, shape } => mem_op(memarg, |mem, offset| {
+                Op::V128LoadSplat { shape, mem, offset }
+            }),
+            Instruction::V128LoadZero { memarg, shape } => mem_op(memarg, |mem, offset| {
+                Op::V128LoadZero { shape, mem, offset }
+            }),
+            Instruction::V128LoadLane {
+                memarg,
+                shape,
+                lane,
+            } => mem_op(memarg, |mem, offset| Op::V128LoadLane {
+                shape,
+                lane,
+                mem,
+                offset,
+            }),
+            Instruction::V128StoreLane {
+                memarg,
+                shape,
+                lane,
+            } => mem_op(memarg, |mem, offset| Op::V128StoreLane {
+                shape,
+                lane,
+                mem,
+                offset,
+            }),
+            Instruction::V128Const { value } => Op::V128Const {
+                index: self.add_v128(value),
+            },
+            Instruction::I8x16Shuffle { lanes } => Op::I8x16Shuffle {
+                index: self.add_v128(u128::from_le_bytes(lanes)),
+            },
+            Instruction::V128Splat { shape } => Op::V128Splat { shape },
+            Instruction::V128ExtractLane {
+                shape,
+                lane,
+                signed,
+            } => Op::V128ExtractLane {
+                shape,
+                lane,
+                signed,
+            },
+            Instruction::V128ReplaceLane { shape, lane } => Op::V128ReplaceLane { shape, lane },
+            Instruction::V128Bitselect => Op::V128Bitselect,
+            Instruction::V128Unop(op) => Op::V128Unop(op),
+            Instruction::V128Binop(op) => Op::V128Binop(op),
+            Instruction::V128Shift(op) => Op::V128Shift(op),
+            Instruction::V128Test(op) => Op::V128Test(op),
+        }
+    }
+}
+
+/// Replace the first op of the common sequences by one op running all of
+/// them. The ops after it are left, a branch or a resumed checkpoint landing
+/// there runs the rest of the sequence from them.
+fn fuse(ops: &mut [Op]) {
+    for pc in 0..ops.len() {
+        ops[pc] = match ops[pc..] {
+            [Op::LocalGet { local_idx: lhs }, Op::LocalGet { local_idx: rhs }, Op::I32Binop(I32Binop::Add), ..] => {
+                Op::I32AddLocals { lhs, rhs }
+            }
+            [Op::I32Const { value }, Op::I32Binop(I32Binop::Add), ..] => Op::I32AddConst { value },
+            [Op::I32Binop(cmp), Op::BrIf { branch }, ..] if cmp.is_compare() => {
+                Op::I32CmpBrIf { cmp, branch }
+            }
+            _ => continue,
+        };
+    }
+}
+
+fn mem_op(memarg: MemArg, op: impl FnOnce(u32, u64) -> Op) -> Op {
+    op(memarg.memory, memarg.offset)
+}
@@ -1,5 +1,11 @@
-use super::{insts::Instruction, parse::FuncBody};
-use wasmparser::{FuncType, GlobalType, Import, ValType};
+use std::rc::Rc;
+
+use super::{
+    bytecode::Code,
+    insts::{BlockTargets, Instruction},
+    parse::FuncBody,
+};
+use wasmparser::{FuncType, GlobalType, Import, TypeRef, ValType};
 
 #[derive(Default, Debug)]
 pub struct ImportSet<'a> {
@@ -14,13 +20,26 @@ impl ImportSet<'_> {
     pub fn get_num_imports(&self) -> usize {
         self.imports.len()
     }
+
+    /// Get the import for the given function index, imported functions come
+    /// first in the function index space.
+    pub fn get_func_import(&self, func_idx: u32) -> Option<&Import<'_>> {
+        self.imports
+            .iter()
+            .filter(|i| matches!(i.ty, TypeRef::Func(_)))
+            .nth(func_idx as usize)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FuncDecl {
     sig: FuncType,
     pure_locals: Vec<(u32, ValType)>, // count, type
-    insts: Vec<Instruction>,
+    insts: Box<[Instruction]>,
+    code: Rc<Code>,                           // the insts lowered for the interpreter
+    inst_offsets: Vec<u32>,                   // from the start of the code section
+    body_offset: u32,                         // from the start of the code section
+    block_targets: Vec<Option<BlockTargets>>, // by pc
 }
 
 impl FuncDecl {
@@ -28,7 +47,11 @@ impl FuncDecl {
         Self {
             sig,
             pure_locals: vec![],
-            insts: vec![],
+            insts: Box::new([]),
+            code: Rc::default(),
+            inst_offsets: vec![],
+            body_offset: 0,
+            block_targets: vec![],
         }
     }
 
@@ -46,17 +69,47 @@ impl FuncDecl {
         pure_locals
     }
 
-    pub fn get_insts(&self) -> &Vec<Instruction> {
+    pub fn get_insts(&self) -> &[Instruction] {
         &self.insts
     }
 
+    /// The lowered body shared with the function, for the interpreter to
+    /// hold on to while it runs it without borrowing the function.
+    pub fn get_code(&self) -> Rc<Code> {
+        Rc::clone(&self.code)
+    }
+
+    pub fn set_code(&mut self, code: Code) {
+        self.code = Rc::new(code);
+    }
+
     pub fn get_inst(&self, idx: usize) -> &Instruction {
         &self.insts[idx]
     }
 
-    pub fn add_func_body(&mut self, func_body: FuncBody) {
+    /// The offset of the instruction at `pc` from the start of the code
+    /// section, as used by the DWARF debug info.
+    pub fn get_inst_offset(&self, pc: usize) -> Option<u32> {
+        self.inst_offsets.get(pc).copied()
+    }
+
+    /// The targets of the block starting at `pc`, `None` if no block starts
+    /// there or it is never closed.
+    pub fn get_block_targets(&self, pc: usize) -> Option<BlockTargets> {
+        self.block_targets.get(pc).copied().flatten()
+    }
+
+    /// The offset of the function body from the start of the code section.
+    pub fn get_body_offset(&self) -> u32 {
+        self.body_offset
+    }
+
+    pub(crate) fn add_func_body(&mut self, func_body: FuncBody) {
         self.pure_locals = func_body.locals;
-        self.insts = func_body.insts;
+        self.insts = func_body.insts.into();
+        self.inst_offsets = func_body.inst_offsets;
+        self.body_offset = func_body.body_offset;
+        self.block_targets = func_body.block_targets;
     }
 }
 
@@ -78,8 +131,4 @@ impl GlobalDecl {
     pub fn get_init_expr(&self) -> &Vec<u8> {
         &self.init_expr
     }
-
-    pub fn set_init_expr(&mut self, init_expr: Vec<u8>) {
-        self.init_expr = init_expr;
-    }
 }
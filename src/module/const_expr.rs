@@ -0,0 +1,108 @@
+//! Evaluation of the constant expressions used to initialize globals, tables
+//! and memories, i.e. global initializers, segment offsets and reference
+//! initializers.
+
+use anyhow::{anyhow, Result};
+use wasmparser::{AbstractHeapType, BinaryReader, ConstExpr, ElementItems, HeapType, RefType};
+
+use super::{
+    value_type::WasmValue,
+    wasmops::{
+        WASM_EXT1_SIMD, WASM_OP_F32_CONST, WASM_OP_F64_CONST, WASM_OP_GLOBAL_GET,
+        WASM_OP_I32_CONST, WASM_OP_I64_CONST, WASM_OP_REF_FUNC, WASM_OP_REF_NULL,
+        WASM_OP_V128_CONST,
+    },
+};
+
+/// Evaluate an `i32.const` or `global.get` offset expression of an active
+/// segment.
+pub(crate) fn eval_offset_expr(expr: &ConstExpr, globals: &[WasmValue]) -> Result<u32> {
+    match eval_const_expr(expr.get_binary_reader(), globals)? {
+        WasmValue::I32(offset) => Ok(offset as u32),
+        value => Err(anyhow!(
+            "invalid offset expression, should be i32.const, got {:?}",
+            value
+        )),
+    }
+}
+
+/// Evaluate the offset expression of an active data segment, memory64
+/// memories use `i64.const`.
+pub(crate) fn eval_data_offset_expr(expr: &ConstExpr, globals: &[WasmValue]) -> Result<u64> {
+    match eval_const_expr(expr.get_binary_reader(), globals)? {
+        WasmValue::I32(offset) => Ok(offset as u32 as u64),
+        WasmValue::I64(offset) => Ok(offset as u64),
+        value => Err(anyhow!(
+            "invalid offset expression, should be i32.const or i64.const, got {:?}",
+            value
+        )),
+    }
+}
+
+/// Evaluate a constant expression made of a single constant, `ref.null`,
+/// `ref.func` or `global.get` of one of `globals`, the values of the globals
+/// before it.
+pub(crate) fn eval_const_expr(
+    mut reader: BinaryReader,
+    globals: &[WasmValue],
+) -> Result<WasmValue> {
+    let op = reader.read_u8()? as u32;
+    let value = match op {
+        WASM_OP_I32_CONST => WasmValue::I32(reader.read_var_i32()?),
+        WASM_OP_I64_CONST => WasmValue::I64(reader.read_var_i64()?),
+        WASM_OP_F32_CONST => WasmValue::F32(f32::from(reader.read_f32()?)),
+        WASM_OP_F64_CONST => WasmValue::F64(f64::from(reader.read_f64()?)),
+        WASM_EXT1_SIMD if WASM_EXT1_SIMD << 8 | reader.read_var_u32()? == WASM_OP_V128_CONST => {
+            let bytes: [u8; 16] = reader.read_bytes(16)?.try_into()?;
+            WasmValue::V128(u128::from_le_bytes(bytes))
+        }
+        WASM_OP_REF_NULL => match reader.read::<HeapType>()? {
+            HeapType::Abstract {
+                ty: AbstractHeapType::Extern,
+                ..
+            } => WasmValue::ExternRef(None),
+            _ => WasmValue::FuncRef(None),
+        },
+        WASM_OP_REF_FUNC => WasmValue::FuncRef(Some(reader.read_var_u32()?)),
+        WASM_OP_GLOBAL_GET => {
+            let index = reader.read_var_u32()?;
+            *globals
+                .get(index as usize)
+                .ok_or_else(|| anyhow!("invalid constant expression, unknown global {}", index))?
+        }
+        _ => return Err(anyhow!("unsupported constant expression, op: 0x{:x}", op)),
+    };
+    Ok(value)
+}
+
+/// Evaluate a `ref.null` or `ref.func` expression of the given type.
+pub(crate) fn eval_ref_expr(expr: &ConstExpr, ty: &RefType) -> Result<WasmValue> {
+    let mut reader = expr.get_binary_reader();
+    let op = reader.read_u8()? as u32;
+    match op {
+        WASM_OP_REF_NULL => Ok(WasmValue::null_ref(ty)),
+        WASM_OP_REF_FUNC => Ok(WasmValue::FuncRef(Some(reader.read_var_u32()?))),
+        _ => Err(anyhow!(
+            "invalid reference expression, should be ref.null or ref.func, op: 0x{:x}",
+            op
+        )),
+    }
+}
+
+/// The references held by an element segment.
+pub(crate) fn elem_segment_refs(items: &ElementItems) -> Result<Vec<WasmValue>> {
+    let mut refs = vec![];
+    match items {
+        ElementItems::Functions(r) => {
+            for func_idx in r.clone() {
+                refs.push(WasmValue::FuncRef(Some(func_idx?)));
+            }
+        }
+        ElementItems::Expressions(ty, r) => {
+            for expr in r.clone() {
+                refs.push(eval_ref_expr(&expr?, ty)?);
+            }
+        }
+    }
+    Ok(refs)
+}
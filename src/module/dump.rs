@@ -0,0 +1,173 @@
+//! A human readable listing of a parsed module, printed by `--dump`: the
+//! types, imports, exports, memories, tables and globals, followed by the
+//! decoded instructions of every function with their pc.
+
+use std::fmt::Write;
+
+use wasmparser::{BinaryReader, ConstExpr, FuncType, Operator, TypeRef, ValType, WasmFeatures};
+
+use super::{insts::Instruction, wasm_module::WasmModule};
+
+/// Describe `module`, function bodies are listed one instruction per line and
+/// indented by block depth.
+pub fn dump_module(module: &WasmModule) -> String {
+    let mut out = String::new();
+    // writing to a String cannot fail
+    let _ = write_module(&mut out, module);
+    out
+}
+
+fn write_module(out: &mut String, module: &WasmModule) -> std::fmt::Result {
+    writeln!(out, "types:")?;
+    let mut index = 0;
+    while let Some(sig) = module.get_sig(index) {
+        writeln!(out, "  type {}: {}", index, format_sig(sig))?;
+        index += 1;
+    }
+
+    writeln!(out, "imports:")?;
+    for import in &module.get_imports().imports {
+        let desc = match import.ty {
+            TypeRef::Func(ty) => format!("func (type {})", ty),
+            TypeRef::Table(ty) => format!("table {}", ty.element_type),
+            TypeRef::Memory(_) => "memory".to_string(),
+            TypeRef::Global(ty) => {
+                format!("global {}", format_global_type(ty.content_type, ty.mutable))
+            }
+            TypeRef::Tag(_) => "tag".to_string(),
+        };
+        writeln!(out, "  {}.{}: {}", import.module, import.name, desc)?;
+    }
+
+    writeln!(out, "exports:")?;
+    for export in module.get_exports() {
+        writeln!(
+            out,
+            "  \"{}\": {:?} {}",
+            export.name, export.kind, export.index
+        )?;
+    }
+
+    if let Some(start) = module.get_start_func() {
+        writeln!(out, "start: func {}", start)?;
+    }
+
+    writeln!(out, "memories:")?;
+    let nmem_imports = module.get_num_mem_imports();
+    for (i, mem) in module.get_memories().iter().enumerate().skip(nmem_imports) {
+        write!(out, "  memory {}: {} pages", i, mem.initial)?;
+        if let Some(maximum) = mem.maximum {
+            write!(out, ", max {}", maximum)?;
+        }
+        if mem.memory64 {
+            write!(out, ", i64 addresses")?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "tables:")?;
+    let ntable_imports = module.get_num_table_imports();
+    for (i, table) in module.get_tables().iter().enumerate().skip(ntable_imports) {
+        write!(
+            out,
+            "  table {}: {} x {}",
+            i, table.ty.element_type, table.ty.initial
+        )?;
+        if let Some(maximum) = table.ty.maximum {
+            write!(out, ", max {}", maximum)?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "globals:")?;
+    let nglobal_imports = module.get_num_global_imports();
+    for (i, global) in module
+        .get_globals()
+        .iter()
+        .enumerate()
+        .skip(nglobal_imports)
+    {
+        let ty = global.get_ty();
+        writeln!(
+            out,
+            "  global {}: {} = {}",
+            i,
+            format_global_type(ty.content_type, ty.mutable),
+            format_const_expr(global.get_init_expr())
+        )?;
+    }
+
+    let nimports = module.get_num_func_imports();
+    for (i, func) in module.get_funcs().iter().enumerate().skip(nimports) {
+        writeln!(out)?;
+        write!(out, "func {}", i)?;
+        if let Some(name) = module.get_func_name(i as u32) {
+            write!(out, " ${}", name)?;
+        }
+        write!(out, ": {}", format_sig(func.get_sig()))?;
+        let locals = func.get_pure_locals();
+        if !locals.is_empty() {
+            write!(out, ", locals {}", format_types(&locals))?;
+        }
+        writeln!(out)?;
+        write_body(out, func.get_insts())?;
+    }
+
+    Ok(())
+}
+
+fn write_body(out: &mut String, insts: &[Instruction]) -> std::fmt::Result {
+    let mut depth = 1;
+    for (pc, inst) in insts.iter().enumerate() {
+        let indent = match inst {
+            // the body of the function is closed by the last end
+            _ if Instruction::is_control_block_end(inst) => {
+                depth -= 1;
+                depth.max(1)
+            }
+            Instruction::Else | Instruction::Catch { .. } | Instruction::CatchAll => depth - 1,
+            _ => depth,
+        };
+        writeln!(out, "{:>6}  {}{:?}", pc, "  ".repeat(indent), inst)?;
+        if Instruction::is_control_block_start(inst) {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn format_sig(sig: &FuncType) -> String {
+    format!(
+        "{} -> {}",
+        format_types(sig.params()),
+        format_types(sig.results())
+    )
+}
+
+fn format_types(types: &[ValType]) -> String {
+    let types = types.iter().map(|ty| ty.to_string()).collect::<Vec<_>>();
+    format!("[{}]", types.join(", "))
+}
+
+fn format_global_type(ty: ValType, mutable: bool) -> String {
+    if mutable {
+        format!("mut {}", ty)
+    } else {
+        ty.to_string()
+    }
+}
+
+/// The operators of a constant expression, without the final `end`.
+fn format_const_expr(expr: &[u8]) -> String {
+    let expr = ConstExpr::new(BinaryReader::new(expr, 0, WasmFeatures::all()));
+    let mut reader = expr.get_operators_reader();
+    let mut ops = vec![];
+    while !reader.eof() {
+        match reader.read() {
+            Ok(Operator::End) => break,
+            Ok(op) => ops.push(format!("{:?}", op)),
+            Err(_) => return "<invalid>".to_string(),
+        }
+    }
+    ops.join(", ")
+}
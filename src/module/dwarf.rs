@@ -0,0 +1,476 @@
+//! Source locations from DWARF debug info. Modules compiled with `-g` carry
+//! their DWARF sections as custom sections, and code addresses in them are
+//! offsets from the start of the contents of the code section. Only the line
+//! number programs of `.debug_line` are read, they map every address to a
+//! file, line and column.
+
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+
+/// A position in the source the module was compiled from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u64,
+    /// 0 if the column is not known.
+    pub column: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if self.column != 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// The custom sections the line table is read from.
+#[derive(Debug, Default)]
+pub(crate) struct DwarfSections<'a> {
+    pub(crate) debug_line: Option<&'a [u8]>,
+    pub(crate) debug_str: Option<&'a [u8]>,
+    pub(crate) debug_line_str: Option<&'a [u8]>,
+}
+
+/// The rows of all line number programs of a module.
+#[derive(Debug, Default)]
+pub(crate) struct LineTable {
+    /// paths of the source files, rows refer to them by index
+    files: Vec<String>,
+    sequences: Vec<Sequence>,
+}
+
+/// Rows covering a contiguous range of code, sorted by address.
+#[derive(Debug)]
+struct Sequence {
+    rows: Vec<LineRow>,
+    /// the first address past the sequence
+    end: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineRow {
+    address: u64,
+    file: usize,
+    line: u64,
+    column: u64,
+}
+
+// standard opcodes
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_SET_COLUMN: u8 = 5;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_SET_BASIC_BLOCK: u8 = 7;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+// extended opcodes
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+
+// content types and forms of the directory and file entries of DWARF 5
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+
+impl LineTable {
+    /// Run the line number programs of every unit in `.debug_line`.
+    pub(crate) fn parse(sections: &DwarfSections) -> Result<Self> {
+        let debug_line = sections
+            .debug_line
+            .ok_or_else(|| anyhow!("no .debug_line section"))?;
+        let mut table = LineTable {
+            files: vec!["<unknown>".to_string()],
+            sequences: vec![],
+        };
+        let mut reader = DwarfReader::new(debug_line);
+        while !reader.eof() {
+            table.parse_unit(&mut reader, sections)?;
+        }
+        Ok(table)
+    }
+
+    /// The source of the code at `address`, `None` if no row covers it or
+    /// it has no line.
+    pub(crate) fn lookup(&self, address: u64) -> Option<SourceLocation> {
+        let sequence = self.sequences.iter().find(|sequence| {
+            // code at offset 0 would overlap the function count, sequences
+            // there describe functions the linker discarded
+            let start = sequence.rows[0].address;
+            start != 0 && start <= address && address < sequence.end
+        })?;
+        let index = sequence.rows.partition_point(|row| row.address <= address) - 1;
+        let row = sequence.rows[index];
+        (row.line != 0).then(|| SourceLocation {
+            file: self.files[row.file].clone(),
+            line: row.line,
+            column: row.column,
+        })
+    }
+
+    fn parse_unit(&mut self, reader: &mut DwarfReader, sections: &DwarfSections) -> Result<()> {
+        let (unit_length, offset_size) = reader.initial_length()?;
+        let unit_end = reader.pos.saturating_add(unit_length);
+        let version = reader.u16()?;
+        if !(2..=5).contains(&version) {
+            bail!("unsupported .debug_line version {}", version);
+        }
+        if version >= 5 {
+            // address and segment selector size
+            reader.bytes(2)?;
+        }
+        let header_length = reader.offset(offset_size)?;
+        let program_start = reader.pos.saturating_add(header_length);
+        let min_inst_length = reader.u8()? as u64;
+        if version >= 4 {
+            // maximum operations per instruction, only used by VLIW targets
+            reader.u8()?;
+        }
+        // default_is_stmt
+        reader.u8()?;
+        let line_base = reader.u8()? as i8 as i64;
+        let line_range = reader.u8()?;
+        if line_range == 0 {
+            bail!("invalid line range 0");
+        }
+        let opcode_base = reader.u8()?;
+        let standard_opcode_lengths = reader.bytes(opcode_base.saturating_sub(1) as usize)?;
+
+        // the index of each file of the unit in `self.files`
+        let unit_files = if version >= 5 {
+            self.parse_v5_files(reader, sections, offset_size)?
+        } else {
+            self.parse_v4_files(reader)?
+        };
+
+        reader.pos = program_start;
+        let mut state = LineRow::initial();
+        let mut rows = vec![];
+        let emit_row = |state: &LineRow, rows: &mut Vec<LineRow>| {
+            let file = unit_files.get(state.file).copied().unwrap_or(0);
+            rows.push(LineRow { file, ..*state });
+        };
+        while reader.pos < unit_end {
+            let opcode = reader.u8()?;
+            if opcode >= opcode_base {
+                let adjusted = (opcode - opcode_base) as u64;
+                state.advance_address(adjusted / line_range as u64 * min_inst_length);
+                state.advance_line(line_base + (adjusted % line_range as u64) as i64);
+                emit_row(&state, &mut rows);
+                continue;
+            }
+            match opcode {
+                0 => {
+                    let len = reader.uleb()? as usize;
+                    let end = reader.pos.saturating_add(len);
+                    match reader.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            if !rows.is_empty() {
+                                self.sequences.push(Sequence {
+                                    rows: std::mem::take(&mut rows),
+                                    end: state.address,
+                                });
+                            }
+                            state = LineRow::initial();
+                        }
+                        DW_LNE_SET_ADDRESS => state.address = reader.uint(len.saturating_sub(1))?,
+                        _ => {}
+                    }
+                    reader.pos = end;
+                }
+                DW_LNS_COPY => emit_row(&state, &mut rows),
+                DW_LNS_ADVANCE_PC => {
+                    state.advance_address(reader.uleb()?.wrapping_mul(min_inst_length));
+                }
+                DW_LNS_ADVANCE_LINE => state.advance_line(reader.sleb()?),
+                DW_LNS_SET_FILE => state.file = reader.uleb()? as usize,
+                DW_LNS_SET_COLUMN => state.column = reader.uleb()?,
+                DW_LNS_NEGATE_STMT | DW_LNS_SET_BASIC_BLOCK => {}
+                DW_LNS_CONST_ADD_PC => {
+                    state.advance_address(
+                        (255 - opcode_base) as u64 / line_range as u64 * min_inst_length,
+                    );
+                }
+                DW_LNS_FIXED_ADVANCE_PC => state.advance_address(reader.u16()? as u64),
+                // prologue_end, epilogue_begin, set_isa and opcodes of later
+                // versions, skip their arguments
+                _ => {
+                    for _ in 0..standard_opcode_lengths[opcode as usize - 1] {
+                        reader.uleb()?;
+                    }
+                }
+            }
+        }
+
+        reader.pos = unit_end;
+        Ok(())
+    }
+
+    /// The include directories and file names of DWARF 2 to 4, files are
+    /// numbered from 1 and directory 0 is the compilation directory.
+    fn parse_v4_files(&mut self, reader: &mut DwarfReader) -> Result<Vec<usize>> {
+        let mut dirs = vec![String::new()];
+        loop {
+            let dir = reader.cstr()?;
+            if dir.is_empty() {
+                break;
+            }
+            dirs.push(dir);
+        }
+
+        let mut unit_files = vec![0];
+        loop {
+            let name = reader.cstr()?;
+            if name.is_empty() {
+                break;
+            }
+            let dir = reader.uleb()? as usize;
+            // modification time and length
+            reader.uleb()?;
+            reader.uleb()?;
+            let dir = dirs.get(dir).map(String::as_str).unwrap_or("");
+            unit_files.push(self.add_file(dir, &name));
+        }
+        Ok(unit_files)
+    }
+
+    /// The directory and file entries of DWARF 5, both numbered from 0 and
+    /// described by a list of content types and forms.
+    fn parse_v5_files(
+        &mut self,
+        reader: &mut DwarfReader,
+        sections: &DwarfSections,
+        offset_size: usize,
+    ) -> Result<Vec<usize>> {
+        let dirs = reader
+            .v5_entries(sections, offset_size)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        let files = reader.v5_entries(sections, offset_size)?;
+        Ok(files
+            .into_iter()
+            .map(|(name, dir)| {
+                let dir = dirs.get(dir as usize).map(String::as_str).unwrap_or("");
+                self.add_file(dir, &name)
+            })
+            .collect())
+    }
+
+    fn add_file(&mut self, dir: &str, name: &str) -> usize {
+        let path = if dir.is_empty() || name.starts_with('/') {
+            name.to_string()
+        } else {
+            format!("{}/{}", dir.trim_end_matches('/'), name)
+        };
+        self.files.push(path);
+        self.files.len() - 1
+    }
+}
+
+impl LineRow {
+    fn initial() -> Self {
+        Self {
+            address: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+        }
+    }
+
+    // the registers wrap around like those of the DWARF state machine, a
+    // malformed program yields wrong rows instead of an overflow
+    fn advance_address(&mut self, delta: u64) {
+        self.address = self.address.wrapping_add(delta);
+    }
+
+    fn advance_line(&mut self, delta: i64) {
+        self.line = self.line.wrapping_add_signed(delta);
+    }
+}
+
+/// A little endian reader over a DWARF section.
+struct DwarfReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DwarfReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+            .ok_or_else(|| anyhow!("unexpected end of DWARF section"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    /// An unsigned integer of `len` bytes, at most 8.
+    fn uint(&mut self, len: usize) -> Result<u64> {
+        if len > 8 {
+            bail!("integer of {} bytes", len);
+        }
+        let bytes = self.bytes(len)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | *byte as u64))
+    }
+
+    fn uleb(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    /// A null terminated string.
+    fn cstr(&mut self) -> Result<String> {
+        let len = self.data[self.pos.min(self.data.len())..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| anyhow!("unterminated string in DWARF section"))?;
+        let s = String::from_utf8_lossy(self.bytes(len)?).into_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+
+    /// The length of a unit, and whether it is in the 32 or 64-bit format
+    /// as the size of its section offsets.
+    fn initial_length(&mut self) -> Result<(usize, usize)> {
+        match self.uint(4)? {
+            0xffff_ffff => Ok((self.uint(8)? as usize, 8)),
+            len => Ok((len as usize, 4)),
+        }
+    }
+
+    fn offset(&mut self, offset_size: usize) -> Result<usize> {
+        Ok(self.uint(offset_size)? as usize)
+    }
+
+    /// A list of directory or file entries of DWARF 5 as their path and
+    /// directory index.
+    fn v5_entries(
+        &mut self,
+        sections: &DwarfSections,
+        offset_size: usize,
+    ) -> Result<Vec<(String, u64)>> {
+        let nformats = self.u8()?;
+        let formats = (0..nformats)
+            .map(|_| Ok((self.uleb()?, self.uleb()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let count = self.uleb()?;
+        let mut entries = vec![];
+        for _ in 0..count {
+            let mut path = String::new();
+            let mut dir = 0;
+            for (content, form) in &formats {
+                match (*content, *form) {
+                    (DW_LNCT_PATH, form) => path = self.string_form(form, sections, offset_size)?,
+                    (DW_LNCT_DIRECTORY_INDEX, form) => dir = self.skip_form(form)?,
+                    (_, form) => {
+                        self.skip_form(form)?;
+                    }
+                }
+            }
+            entries.push((path, dir));
+        }
+        Ok(entries)
+    }
+
+    fn string_form(
+        &mut self,
+        form: u64,
+        sections: &DwarfSections,
+        offset_size: usize,
+    ) -> Result<String> {
+        let section = match form {
+            DW_FORM_STRING => return self.cstr(),
+            DW_FORM_LINE_STRP => sections.debug_line_str,
+            DW_FORM_STRP => sections.debug_str,
+            _ => bail!("unsupported string form 0x{:x}", form),
+        };
+        let offset = self.offset(offset_size)?;
+        let section = section.ok_or_else(|| anyhow!("missing DWARF string section"))?;
+        let mut reader = DwarfReader::new(section);
+        reader.pos = offset;
+        reader.cstr()
+    }
+
+    /// Skip a value of a constant or block form, returns the value of a
+    /// constant.
+    fn skip_form(&mut self, form: u64) -> Result<u64> {
+        match form {
+            DW_FORM_DATA1 => self.uint(1),
+            DW_FORM_DATA2 => self.uint(2),
+            DW_FORM_DATA4 => self.uint(4),
+            DW_FORM_DATA8 => self.uint(8),
+            DW_FORM_UDATA => self.uleb(),
+            DW_FORM_DATA16 => self.bytes(16).map(|_| 0),
+            DW_FORM_BLOCK => {
+                let len = self.uleb()? as usize;
+                self.bytes(len).map(|_| 0)
+            }
+            _ => bail!("unsupported form 0x{:x}", form),
+        }
+    }
+}
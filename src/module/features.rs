@@ -0,0 +1,269 @@
+//! The WebAssembly proposals a module may use. A module using a disabled
+//! proposal is rejected before it runs, with the proposal named in the
+//! error, instead of running the parts the engines happen to support.
+
+use anyhow::{anyhow, Result};
+use wasmparser::{BlockType, DataKind, ElementKind, ValType};
+
+use super::{
+    insts::Instruction,
+    validate::{ValidationError, ValidationErrorKind},
+    wasm_module::WasmModule,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// `memory.copy`, `memory.fill`, `memory.init`, `data.drop`,
+    /// `table.init`, `elem.drop` and passive segments
+    BulkMemory,
+    /// the `v128` type and its instructions
+    Simd,
+    /// shared memories, atomic instructions are not supported either way
+    Threads,
+    /// `return_call` and `return_call_indirect`
+    TailCall,
+    /// functions returning several values and blocks with parameters or
+    /// several results
+    MultiValue,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] = [
+        Feature::BulkMemory,
+        Feature::Simd,
+        Feature::Threads,
+        Feature::TailCall,
+        Feature::MultiValue,
+    ];
+
+    /// The name of the proposal, as taken by `--enable` and `--disable`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::BulkMemory => "bulk-memory",
+            Feature::Simd => "simd",
+            Feature::Threads => "threads",
+            Feature::TailCall => "tail-call",
+            Feature::MultiValue => "multi-value",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Self::ALL.into_iter().find(|feature| feature.name() == name)
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the {} proposal is disabled", self.name())
+    }
+}
+
+/// The enabled proposals, all but threads by default as the engines run a
+/// single thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    pub bulk_memory: bool,
+    pub simd: bool,
+    pub threads: bool,
+    pub tail_call: bool,
+    pub multi_value: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            bulk_memory: true,
+            simd: true,
+            threads: false,
+            tail_call: true,
+            multi_value: true,
+        }
+    }
+}
+
+impl Features {
+    /// Enable or disable `feature`.
+    pub fn set(&mut self, feature: Feature, enabled: bool) {
+        *self.flag(feature) = enabled;
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::BulkMemory => self.bulk_memory,
+            Feature::Simd => self.simd,
+            Feature::Threads => self.threads,
+            Feature::TailCall => self.tail_call,
+            Feature::MultiValue => self.multi_value,
+        }
+    }
+
+    fn flag(&mut self, feature: Feature) -> &mut bool {
+        match feature {
+            Feature::BulkMemory => &mut self.bulk_memory,
+            Feature::Simd => &mut self.simd,
+            Feature::Threads => &mut self.threads,
+            Feature::TailCall => &mut self.tail_call,
+            Feature::MultiValue => &mut self.multi_value,
+        }
+    }
+
+    /// Fail with `what` if something `used` the disabled `feature`.
+    fn require(&self, used: bool, feature: Feature, what: impl FnOnce() -> String) -> Result<()> {
+        if !used || self.is_enabled(feature) {
+            return Ok(());
+        }
+        Err(anyhow!("{}: {}", what(), feature))
+    }
+}
+
+/// The proposal `inst` belongs to, if it is not in the MVP.
+fn inst_feature(module: &WasmModule, inst: &Instruction) -> Option<Feature> {
+    match inst {
+        Instruction::MemoryCopy { .. }
+        | Instruction::MemoryFill { .. }
+        | Instruction::MemoryInit { .. }
+        | Instruction::DataDrop { .. }
+        | Instruction::TableInit { .. }
+        | Instruction::ElemDrop { .. } => Some(Feature::BulkMemory),
+        Instruction::V128Load { .. }
+        | Instruction::V128Store { .. }
+        | Instruction::V128LoadExtend { .. }
+        | Instruction::V128LoadSplat { .. }
+        | Instruction::V128LoadZero { .. }
+        | Instruction::V128LoadLane { .. }
+        | Instruction::V128StoreLane { .. }
+        | Instruction::V128Const { .. }
+        | Instruction::I8x16Shuffle { .. }
+        | Instruction::V128Splat { .. }
+        | Instruction::V128ExtractLane { .. }
+        | Instruction::V128ReplaceLane { .. }
+        | Instruction::V128Bitselect
+        | Instruction::V128Unop(_)
+        | Instruction::V128Binop(_)
+        | Instruction::V128Shift(_)
+        | Instruction::V128Test(_) => Some(Feature::Simd),
+        Instruction::ReturnCall { .. } | Instruction::ReturnCallIndirect { .. } => {
+            Some(Feature::TailCall)
+        }
+        Instruction::Block { ty }
+        | Instruction::Loop { ty }
+        | Instruction::If { ty }
+        | Instruction::Try { ty } => match ty {
+            BlockType::FuncType(idx) => module
+                .get_sig(*idx)
+                .filter(|sig| !sig.params().is_empty() || sig.results().len() > 1)
+                .map(|_| Feature::MultiValue),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl WasmModule<'_> {
+    /// Fail if the module uses a proposal `features` disables, function
+    /// bodies fail with a `ValidationError` at the first instruction of a
+    /// disabled proposal.
+    pub fn check_features(&self, features: &Features) -> Result<()> {
+        let mut index = 0;
+        while let Some(sig) = self.get_sig(index) {
+            let nresults = sig.results().len();
+            features.require(nresults > 1, Feature::MultiValue, || {
+                format!("type {} returns {} values", index, nresults)
+            })?;
+            let v128 = sig
+                .params()
+                .iter()
+                .chain(sig.results())
+                .any(|ty| *ty == ValType::V128);
+            features.require(v128, Feature::Simd, || format!("type {} uses v128", index))?;
+            index += 1;
+        }
+        for (i, global) in self.get_globals().iter().enumerate() {
+            let v128 = global.get_ty().content_type == ValType::V128;
+            features.require(v128, Feature::Simd, || format!("global {} is a v128", i))?;
+        }
+        for (i, mem) in self.get_memories().iter().enumerate() {
+            features.require(mem.shared, Feature::Threads, || {
+                format!("memory {} is shared", i)
+            })?;
+        }
+        for (i, data) in self.get_datas().iter().enumerate() {
+            let passive = matches!(data.kind, DataKind::Passive);
+            features.require(passive, Feature::BulkMemory, || {
+                format!("data segment {} is passive", i)
+            })?;
+        }
+        for (i, elem) in self.get_elems().iter().enumerate() {
+            let passive = matches!(elem.kind, ElementKind::Passive);
+            features.require(passive, Feature::BulkMemory, || {
+                format!("elem segment {} is passive", i)
+            })?;
+        }
+
+        let num_imports = self.get_num_func_imports();
+        for (func_index, func) in self.get_funcs().iter().enumerate().skip(num_imports) {
+            let v128 = func.get_pure_locals().contains(&ValType::V128);
+            features.require(v128, Feature::Simd, || {
+                format!("function {} has v128 locals", func_index)
+            })?;
+            let disabled = func.get_insts().iter().enumerate().find_map(|(i, inst)| {
+                inst_feature(self, inst)
+                    .filter(|feature| !features.is_enabled(*feature))
+                    .map(|feature| (i, feature))
+            });
+            if let Some((inst_index, feature)) = disabled {
+                return Err(ValidationError {
+                    func_index: func_index as u32,
+                    inst_index,
+                    kind: ValidationErrorKind::DisabledFeature(feature),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Feature, Features};
+    use crate::module::wasm_module::WasmModule;
+
+    const WAT: &str = r#"
+        (module
+          (memory 1)
+          (func (export "nop"))
+          (func (export "clear")
+            (memory.fill (i32.const 0) (i32.const 0) (i32.const 16))))
+    "#;
+
+    fn parse(wat: &str, features: &Features) -> Result<(), String> {
+        let binary = wat::parse_str(wat).unwrap();
+        WasmModule::from_bytecode_with_features(&binary, features)
+            .map(|_| ())
+            .map_err(|e| format!("{:#}", e))
+    }
+
+    #[test]
+    fn a_disabled_proposal_is_rejected_with_its_name() {
+        let mut features = Features::default();
+        assert_eq!(parse(WAT, &features), Ok(()));
+
+        features.set(Feature::BulkMemory, false);
+        assert_eq!(
+            parse(WAT, &features),
+            Err("invalid function 1 at instruction 3: the bulk-memory proposal is disabled".into())
+        );
+    }
+
+    #[test]
+    fn multiple_results_need_multi_value() {
+        let wat = r#"(module (func (result i32 i32) (i32.const 1) (i32.const 2)))"#;
+        let mut features = Features::default();
+        features.set(Feature::MultiValue, false);
+        assert_eq!(
+            parse(wat, &features),
+            Err("type 0 returns 2 values: the multi-value proposal is disabled".into())
+        );
+    }
+}
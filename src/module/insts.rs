@@ -1,5 +1,5 @@
 use anyhow::Result;
-use wasmparser::{BinaryReader, BlockType, WasmFeatures};
+use wasmparser::{BinaryReader, BlockType, RefType, ValType, WasmFeatures};
 
 use super::wasmops::*;
 
@@ -9,13 +9,23 @@ pub struct BrTable {
     pub default_target: u32,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Where the instructions of a block lead, see `Instruction::block_targets`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockTargets {
+    /// the pc of the `end` or `delegate` closing the block
+    pub end_pc: usize,
+    /// the pc of the `else` of an `if`
+    pub else_pc: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MemArg {
-    pub offset: u32,
+    pub offset: u64,
     pub align: u32,
+    pub memory: u32,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum I32Unop {
     Eqz,
     Clz,
@@ -25,9 +35,14 @@ pub enum I32Unop {
     Extend16S,
     F64ConvertI32S,
     F64ConvertI32U,
+    F32ConvertI32S,
+    F32ConvertI32U,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    F32ReinterpretI32,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum I32Binop {
     Eq,
     Ne,
@@ -56,7 +71,102 @@ pub enum I32Binop {
     Rotr,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl I32Binop {
+    /// Whether the op compares its operands, with 0 or 1 as the result.
+    pub fn is_compare(&self) -> bool {
+        use I32Binop::*;
+        matches!(
+            self,
+            Eq | Ne | LtS | LtU | GtS | GtU | LeS | LeU | GeS | GeU
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I64Unop {
+    Eqz,
+    Clz,
+    Ctz,
+    Popcnt,
+    Extend8S,
+    Extend16S,
+    Extend32S,
+    I32WrapI64,
+    F32ConvertI64S,
+    F32ConvertI64U,
+    F64ConvertI64S,
+    F64ConvertI64U,
+    F64ReinterpretI64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I64Binop {
+    Eq,
+    Ne,
+    LtS,
+    LtU,
+    GtS,
+    GtU,
+    LeS,
+    LeU,
+    GeS,
+    GeU,
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+    Rotl,
+    Rotr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum F32Unop {
+    Abs,
+    Neg,
+    Ceil,
+    Floor,
+    Trunc,
+    Nearest,
+    Sqrt,
+    I32TruncF32S,
+    I32TruncF32U,
+    I64TruncF32S,
+    I64TruncF32U,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum F32Binop {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    Copysign,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum F64Unop {
     Abs,
     Neg,
@@ -67,9 +177,17 @@ pub enum F64Unop {
     Sqrt,
     I32TruncF64S,
     I32TruncF64U,
+    I64TruncF64S,
+    I64TruncF64U,
+    F32DemoteF64,
+    I64ReinterpretF64,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum F64Binop {
     Eq,
     Ne,
@@ -83,6 +201,136 @@ pub enum F64Binop {
     Div,
     Min,
     Max,
+    Copysign,
+}
+
+/// The lane interpretation of a v128 value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum V128Shape {
+    I8x16,
+    I16x8,
+    I32x4,
+    I64x2,
+    F32x4,
+    F64x2,
+}
+
+impl V128Shape {
+    pub fn lane_bits(&self) -> u32 {
+        match self {
+            V128Shape::I8x16 => 8,
+            V128Shape::I16x8 => 16,
+            V128Shape::I32x4 | V128Shape::F32x4 => 32,
+            V128Shape::I64x2 | V128Shape::F64x2 => 64,
+        }
+    }
+
+    pub fn num_lanes(&self) -> u32 {
+        128 / self.lane_bits()
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, V128Shape::F32x4 | V128Shape::F64x2)
+    }
+}
+
+/// v128 -> v128
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum V128Unop {
+    Not,
+    Abs(V128Shape),
+    Neg(V128Shape),
+    Sqrt(V128Shape),
+    Ceil(V128Shape),
+    Floor(V128Shape),
+    Trunc(V128Shape),
+    Nearest(V128Shape),
+    I8x16Popcnt,
+    I32x4TruncSatF32x4S,
+    I32x4TruncSatF32x4U,
+    F32x4ConvertI32x4S,
+    F32x4ConvertI32x4U,
+    I32x4TruncSatF64x2SZero,
+    I32x4TruncSatF64x2UZero,
+    F64x2ConvertLowI32x4S,
+    F64x2ConvertLowI32x4U,
+    F32x4DemoteF64x2Zero,
+    F64x2PromoteLowF32x4,
+    /// the lanes of the low half widened to `shape`
+    ExtendLowS(V128Shape),
+    ExtendLowU(V128Shape),
+    ExtendHighS(V128Shape),
+    ExtendHighU(V128Shape),
+    /// adjacent lanes added and widened to `shape`
+    ExtaddPairwiseS(V128Shape),
+    ExtaddPairwiseU(V128Shape),
+}
+
+/// v128 v128 -> v128, comparisons set all bits of the lanes that hold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum V128Binop {
+    And,
+    AndNot,
+    Or,
+    Xor,
+    I8x16Swizzle,
+    Add(V128Shape),
+    Sub(V128Shape),
+    Mul(V128Shape),
+    Div(V128Shape),
+    Min(V128Shape),
+    Max(V128Shape),
+    MinS(V128Shape),
+    MinU(V128Shape),
+    MaxS(V128Shape),
+    MaxU(V128Shape),
+    AddSatS(V128Shape),
+    AddSatU(V128Shape),
+    SubSatS(V128Shape),
+    SubSatU(V128Shape),
+    AvgrU(V128Shape),
+    Eq(V128Shape),
+    Ne(V128Shape),
+    LtS(V128Shape),
+    LtU(V128Shape),
+    GtS(V128Shape),
+    GtU(V128Shape),
+    LeS(V128Shape),
+    LeU(V128Shape),
+    GeS(V128Shape),
+    GeU(V128Shape),
+    Lt(V128Shape),
+    Gt(V128Shape),
+    Le(V128Shape),
+    Ge(V128Shape),
+    Pmin(V128Shape),
+    Pmax(V128Shape),
+    /// the lanes of both operands saturated to the narrower `shape`
+    NarrowS(V128Shape),
+    NarrowU(V128Shape),
+    /// the lanes of the low halves multiplied and widened to `shape`
+    ExtmulLowS(V128Shape),
+    ExtmulLowU(V128Shape),
+    ExtmulHighS(V128Shape),
+    ExtmulHighU(V128Shape),
+    I16x8Q15MulrSatS,
+    I32x4DotI16x8S,
+}
+
+/// v128 i32 -> v128
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum V128Shift {
+    Shl(V128Shape),
+    ShrS(V128Shape),
+    ShrU(V128Shape),
+}
+
+/// v128 -> i32
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum V128Test {
+    AnyTrue,
+    AllTrue(V128Shape),
+    Bitmask(V128Shape),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -90,53 +338,296 @@ pub enum Instruction {
     Unreachable,
     Nop,
     // control flow
-    Block { ty: BlockType },
-    Loop { ty: BlockType },
-    If { ty: BlockType },
+    Block {
+        ty: BlockType,
+    },
+    Loop {
+        ty: BlockType,
+    },
+    If {
+        ty: BlockType,
+    },
     Else,
     End,
-    Br { rel_depth: u32 },
-    BrIf { rel_depth: u32 },
-    BrTable { table: BrTable },
+    // exception handling
+    Try {
+        ty: BlockType,
+    },
+    Catch {
+        tag_index: u32,
+    },
+    CatchAll,
+    Throw {
+        tag_index: u32,
+    },
+    Rethrow {
+        rel_depth: u32,
+    },
+    /// ends a try block, exceptions thrown in it are forwarded to the
+    /// enclosing label `rel_depth`
+    Delegate {
+        rel_depth: u32,
+    },
+    Br {
+        rel_depth: u32,
+    },
+    BrIf {
+        rel_depth: u32,
+    },
+    BrTable {
+        table: BrTable,
+    },
     Return,
-    Call { func_idx: u32 },
-    CallIndirect { type_index: u32, table_index: u32 },
+    Call {
+        func_idx: u32,
+    },
+    CallIndirect {
+        type_index: u32,
+        table_index: u32,
+    },
+    ReturnCall {
+        func_idx: u32,
+    },
+    ReturnCallIndirect {
+        type_index: u32,
+        table_index: u32,
+    },
     // variable
     Drop,
     Select,
-    LocalGet { local_idx: u32 },
-    LocalSet { local_idx: u32 },
-    LocalTee { local_idx: u32 },
-    GlobalGet { global_idx: u32 },
-    GlobalSet { global_idx: u32 },
+    /// select with explicit result types, required for reference and v128
+    /// operands
+    TypedSelect {
+        tys: Vec<ValType>,
+    },
+    LocalGet {
+        local_idx: u32,
+    },
+    LocalSet {
+        local_idx: u32,
+    },
+    LocalTee {
+        local_idx: u32,
+    },
+    GlobalGet {
+        global_idx: u32,
+    },
+    GlobalSet {
+        global_idx: u32,
+    },
+    // table
+    TableGet {
+        table: u32,
+    },
+    TableSet {
+        table: u32,
+    },
+    TableInit {
+        elem_index: u32,
+        table: u32,
+    },
+    ElemDrop {
+        elem_index: u32,
+    },
+    TableGrow {
+        table: u32,
+    },
+    TableSize {
+        table: u32,
+    },
+    TableFill {
+        table: u32,
+    },
+    // reference
+    RefNull {
+        ty: RefType,
+    },
+    RefIsNull,
+    RefFunc {
+        func_idx: u32,
+    },
     // memory
-    I32Load { memarg: MemArg },
-    F64Load { memarg: MemArg },
-    I32Load8S { memarg: MemArg },
-    I32Load8U { memarg: MemArg },
-    I32Load16S { memarg: MemArg },
-    I32Load16U { memarg: MemArg },
-    I32Store { memarg: MemArg },
-    F64Store { memarg: MemArg },
-    I32Store8 { memarg: MemArg },
-    I32Store16 { memarg: MemArg },
-    MemorySize { mem: u32 },
-    MemoryGrow { mem: u32 },
-    I32Const { value: i32 },
-    F64Const { value: f64 },
+    I32Load {
+        memarg: MemArg,
+    },
+    I64Load {
+        memarg: MemArg,
+    },
+    F32Load {
+        memarg: MemArg,
+    },
+    F64Load {
+        memarg: MemArg,
+    },
+    I32Load8S {
+        memarg: MemArg,
+    },
+    I32Load8U {
+        memarg: MemArg,
+    },
+    I32Load16S {
+        memarg: MemArg,
+    },
+    I32Load16U {
+        memarg: MemArg,
+    },
+    I64Load8S {
+        memarg: MemArg,
+    },
+    I64Load8U {
+        memarg: MemArg,
+    },
+    I64Load16S {
+        memarg: MemArg,
+    },
+    I64Load16U {
+        memarg: MemArg,
+    },
+    I64Load32S {
+        memarg: MemArg,
+    },
+    I64Load32U {
+        memarg: MemArg,
+    },
+    I32Store {
+        memarg: MemArg,
+    },
+    I64Store {
+        memarg: MemArg,
+    },
+    F32Store {
+        memarg: MemArg,
+    },
+    F64Store {
+        memarg: MemArg,
+    },
+    I32Store8 {
+        memarg: MemArg,
+    },
+    I32Store16 {
+        memarg: MemArg,
+    },
+    I64Store8 {
+        memarg: MemArg,
+    },
+    I64Store16 {
+        memarg: MemArg,
+    },
+    I64Store32 {
+        memarg: MemArg,
+    },
+    MemorySize {
+        mem: u32,
+    },
+    MemoryGrow {
+        mem: u32,
+    },
+    MemoryCopy {
+        dst_mem: u32,
+        src_mem: u32,
+    },
+    MemoryFill {
+        mem: u32,
+    },
+    MemoryInit {
+        data_index: u32,
+        mem: u32,
+    },
+    DataDrop {
+        data_index: u32,
+    },
+    I32Const {
+        value: i32,
+    },
+    I64Const {
+        value: i64,
+    },
+    F32Const {
+        value: f32,
+    },
+    F64Const {
+        value: f64,
+    },
     // arithmetic
     I32Unop(I32Unop),
     I32Binop(I32Binop),
+    I64Unop(I64Unop),
+    I64Binop(I64Binop),
+    F32Unop(F32Unop),
+    F32Binop(F32Binop),
     F64Unop(F64Unop),
     F64Binop(F64Binop),
+    // simd
+    V128Load {
+        memarg: MemArg,
+    },
+    V128Store {
+        memarg: MemArg,
+    },
+    /// 8 bytes, each of their lanes widened to `shape`
+    V128LoadExtend {
+        memarg: MemArg,
+        shape: V128Shape,
+        signed: bool,
+    },
+    V128LoadSplat {
+        memarg: MemArg,
+        shape: V128Shape,
+    },
+    /// one lane of `shape`, the others are 0
+    V128LoadZero {
+        memarg: MemArg,
+        shape: V128Shape,
+    },
+    V128LoadLane {
+        memarg: MemArg,
+        shape: V128Shape,
+        lane: u8,
+    },
+    V128StoreLane {
+        memarg: MemArg,
+        shape: V128Shape,
+        lane: u8,
+    },
+    V128Const {
+        value: u128,
+    },
+    I8x16Shuffle {
+        lanes: [u8; 16],
+    },
+    V128Splat {
+        shape: V128Shape,
+    },
+    V128ExtractLane {
+        shape: V128Shape,
+        lane: u8,
+        signed: bool,
+    },
+    V128ReplaceLane {
+        shape: V128Shape,
+        lane: u8,
+    },
+    V128Bitselect,
+    V128Unop(V128Unop),
+    V128Binop(V128Binop),
+    V128Shift(V128Shift),
+    V128Test(V128Test),
 }
 
 impl Instruction {
     pub fn from_code_bytes(code_bytes: Vec<u8>) -> Result<Vec<Instruction>> {
+        Ok(Self::decode_code_bytes(&code_bytes)?.0)
+    }
+
+    /// Decode the operators of a function body, along with the offset of
+    /// every instruction in `code_bytes`.
+    pub(crate) fn decode_code_bytes(code_bytes: &[u8]) -> Result<(Vec<Instruction>, Vec<u32>)> {
         let mut insts = vec![];
-        let mut binary_reader = BinaryReader::new(&code_bytes, 0, WasmFeatures::all());
+        let mut offsets = vec![];
+        let mut binary_reader = BinaryReader::new(code_bytes, 0, WasmFeatures::all());
 
         while !binary_reader.eof() {
+            offsets.push(binary_reader.current_position() as u32);
             // legal opcodes are u8 operators, so we can just read u8
             let opcode = binary_reader.read_u8()? as u32;
             match opcode {
@@ -153,6 +644,22 @@ impl Instruction {
                 }),
                 WASM_OP_ELSE => insts.push(Instruction::Else),
                 WASM_OP_END => insts.push(Instruction::End),
+                WASM_OP_TRY => insts.push(Instruction::Try {
+                    ty: Self::read_block_type(&mut binary_reader)?,
+                }),
+                WASM_OP_CATCH => insts.push(Instruction::Catch {
+                    tag_index: binary_reader.read_var_u32()?,
+                }),
+                WASM_OP_CATCH_ALL => insts.push(Instruction::CatchAll),
+                WASM_OP_THROW => insts.push(Instruction::Throw {
+                    tag_index: binary_reader.read_var_u32()?,
+                }),
+                WASM_OP_RETHROW => insts.push(Instruction::Rethrow {
+                    rel_depth: binary_reader.read_var_u32()?,
+                }),
+                WASM_OP_DELEGATE => insts.push(Instruction::Delegate {
+                    rel_depth: binary_reader.read_var_u32()?,
+                }),
                 WASM_OP_BR => insts.push(Instruction::Br {
                     rel_depth: binary_reader.read_var_u32()?,
                 }),
@@ -170,8 +677,18 @@ impl Instruction {
                     type_index: binary_reader.read_var_u32()?,
                     table_index: binary_reader.read_var_u32()?,
                 }),
+                WASM_OP_RETURN_CALL => insts.push(Instruction::ReturnCall {
+                    func_idx: binary_reader.read_var_u32()?,
+                }),
+                WASM_OP_RETURN_CALL_INDIRECT => insts.push(Instruction::ReturnCallIndirect {
+                    type_index: binary_reader.read_var_u32()?,
+                    table_index: binary_reader.read_var_u32()?,
+                }),
                 WASM_OP_DROP => insts.push(Instruction::Drop),
                 WASM_OP_SELECT => insts.push(Instruction::Select),
+                WASM_OP_SELECT_T => insts.push(Instruction::TypedSelect {
+                    tys: Self::read_val_types(&mut binary_reader)?,
+                }),
                 WASM_OP_LOCAL_GET => insts.push(Instruction::LocalGet {
                     local_idx: binary_reader.read_var_u32()?,
                 }),
@@ -187,9 +704,25 @@ impl Instruction {
                 WASM_OP_GLOBAL_SET => insts.push(Instruction::GlobalSet {
                     global_idx: binary_reader.read_var_u32()?,
                 }),
+                WASM_OP_TABLE_GET => insts.push(Instruction::TableGet {
+                    table: binary_reader.read_var_u32()?,
+                }),
+                WASM_OP_TABLE_SET => insts.push(Instruction::TableSet {
+                    table: binary_reader.read_var_u32()?,
+                }),
+                WASM_OP_REF_NULL => insts.push(Instruction::RefNull {
+                    ty: Self::read_ref_null_type(&mut binary_reader)?,
+                }),
+                WASM_OP_REF_IS_NULL => insts.push(Instruction::RefIsNull),
+                WASM_OP_REF_FUNC => insts.push(Instruction::RefFunc {
+                    func_idx: binary_reader.read_var_u32()?,
+                }),
                 WASM_OP_I32_LOAD => insts.push(Instruction::I32Load {
                     memarg: Self::read_memarg(&mut binary_reader)?,
                 }),
+                WASM_OP_F32_LOAD => insts.push(Instruction::F32Load {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
                 WASM_OP_F64_LOAD => insts.push(Instruction::F64Load {
                     memarg: Self::read_memarg(&mut binary_reader)?,
                 }),
@@ -205,9 +738,33 @@ impl Instruction {
                 WASM_OP_I32_LOAD16_U => insts.push(Instruction::I32Load16U {
                     memarg: Self::read_memarg(&mut binary_reader)?,
                 }),
+                WASM_OP_I64_LOAD => insts.push(Instruction::I64Load {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_LOAD8_S => insts.push(Instruction::I64Load8S {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_LOAD8_U => insts.push(Instruction::I64Load8U {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_LOAD16_S => insts.push(Instruction::I64Load16S {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_LOAD16_U => insts.push(Instruction::I64Load16U {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_LOAD32_S => insts.push(Instruction::I64Load32S {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_LOAD32_U => insts.push(Instruction::I64Load32U {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
                 WASM_OP_I32_STORE => insts.push(Instruction::I32Store {
                     memarg: Self::read_memarg(&mut binary_reader)?,
                 }),
+                WASM_OP_F32_STORE => insts.push(Instruction::F32Store {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
                 WASM_OP_F64_STORE => insts.push(Instruction::F64Store {
                     memarg: Self::read_memarg(&mut binary_reader)?,
                 }),
@@ -217,15 +774,33 @@ impl Instruction {
                 WASM_OP_I32_STORE16 => insts.push(Instruction::I32Store16 {
                     memarg: Self::read_memarg(&mut binary_reader)?,
                 }),
+                WASM_OP_I64_STORE => insts.push(Instruction::I64Store {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_STORE8 => insts.push(Instruction::I64Store8 {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_STORE16 => insts.push(Instruction::I64Store16 {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
+                WASM_OP_I64_STORE32 => insts.push(Instruction::I64Store32 {
+                    memarg: Self::read_memarg(&mut binary_reader)?,
+                }),
                 WASM_OP_MEMORY_SIZE => insts.push(Instruction::MemorySize {
-                    mem: binary_reader.read_var_u32()?, // always 0
+                    mem: binary_reader.read_var_u32()?,
                 }),
                 WASM_OP_MEMORY_GROW => insts.push(Instruction::MemoryGrow {
-                    mem: binary_reader.read_var_u32()?, // always 0
+                    mem: binary_reader.read_var_u32()?,
                 }),
                 WASM_OP_I32_CONST => insts.push(Instruction::I32Const {
                     value: binary_reader.read_var_i32()?,
                 }),
+                WASM_OP_I64_CONST => insts.push(Instruction::I64Const {
+                    value: binary_reader.read_var_i64()?,
+                }),
+                WASM_OP_F32_CONST => insts.push(Instruction::F32Const {
+                    value: f32::from(binary_reader.read_f32()?),
+                }),
                 WASM_OP_F64_CONST => insts.push(Instruction::F64Const {
                     value: f64::from(binary_reader.read_f64()?),
                 }),
@@ -240,6 +815,26 @@ impl Instruction {
                 WASM_OP_I32_LE_U => insts.push(Instruction::I32Binop(I32Binop::LeU)),
                 WASM_OP_I32_GE_S => insts.push(Instruction::I32Binop(I32Binop::GeS)),
                 WASM_OP_I32_GE_U => insts.push(Instruction::I32Binop(I32Binop::GeU)),
+                WASM_OP_F32_EQ => insts.push(Instruction::F32Binop(F32Binop::Eq)),
+                WASM_OP_F32_NE => insts.push(Instruction::F32Binop(F32Binop::Ne)),
+                WASM_OP_F32_LT => insts.push(Instruction::F32Binop(F32Binop::Lt)),
+                WASM_OP_F32_GT => insts.push(Instruction::F32Binop(F32Binop::Gt)),
+                WASM_OP_F32_LE => insts.push(Instruction::F32Binop(F32Binop::Le)),
+                WASM_OP_F32_GE => insts.push(Instruction::F32Binop(F32Binop::Ge)),
+                WASM_OP_F32_ABS => insts.push(Instruction::F32Unop(F32Unop::Abs)),
+                WASM_OP_F32_NEG => insts.push(Instruction::F32Unop(F32Unop::Neg)),
+                WASM_OP_F32_CEIL => insts.push(Instruction::F32Unop(F32Unop::Ceil)),
+                WASM_OP_F32_FLOOR => insts.push(Instruction::F32Unop(F32Unop::Floor)),
+                WASM_OP_F32_TRUNC => insts.push(Instruction::F32Unop(F32Unop::Trunc)),
+                WASM_OP_F32_NEAREST => insts.push(Instruction::F32Unop(F32Unop::Nearest)),
+                WASM_OP_F32_SQRT => insts.push(Instruction::F32Unop(F32Unop::Sqrt)),
+                WASM_OP_F32_ADD => insts.push(Instruction::F32Binop(F32Binop::Add)),
+                WASM_OP_F32_SUB => insts.push(Instruction::F32Binop(F32Binop::Sub)),
+                WASM_OP_F32_MUL => insts.push(Instruction::F32Binop(F32Binop::Mul)),
+                WASM_OP_F32_DIV => insts.push(Instruction::F32Binop(F32Binop::Div)),
+                WASM_OP_F32_MIN => insts.push(Instruction::F32Binop(F32Binop::Min)),
+                WASM_OP_F32_MAX => insts.push(Instruction::F32Binop(F32Binop::Max)),
+                WASM_OP_F32_COPYSIGN => insts.push(Instruction::F32Binop(F32Binop::Copysign)),
                 WASM_OP_F64_EQ => insts.push(Instruction::F64Binop(F64Binop::Eq)),
                 WASM_OP_F64_NE => insts.push(Instruction::F64Binop(F64Binop::Ne)),
                 WASM_OP_F64_LT => insts.push(Instruction::F64Binop(F64Binop::Lt)),
@@ -277,6 +872,7 @@ impl Instruction {
                 WASM_OP_F64_DIV => insts.push(Instruction::F64Binop(F64Binop::Div)),
                 WASM_OP_F64_MIN => insts.push(Instruction::F64Binop(F64Binop::Min)),
                 WASM_OP_F64_MAX => insts.push(Instruction::F64Binop(F64Binop::Max)),
+                WASM_OP_F64_COPYSIGN => insts.push(Instruction::F64Binop(F64Binop::Copysign)),
                 WASM_OP_I32_TRUNC_F64_S => insts.push(Instruction::F64Unop(F64Unop::I32TruncF64S)),
                 WASM_OP_I32_TRUNC_F64_U => insts.push(Instruction::F64Unop(F64Unop::I32TruncF64U)),
                 WASM_OP_F64_CONVERT_I32_S => {
@@ -287,22 +883,179 @@ impl Instruction {
                 }
                 WASM_OP_I32_EXTEND8_S => insts.push(Instruction::I32Unop(I32Unop::Extend8S)),
                 WASM_OP_I32_EXTEND16_S => insts.push(Instruction::I32Unop(I32Unop::Extend16S)),
+                WASM_OP_I64_EQZ => insts.push(Instruction::I64Unop(I64Unop::Eqz)),
+                WASM_OP_I64_EQ => insts.push(Instruction::I64Binop(I64Binop::Eq)),
+                WASM_OP_I64_NE => insts.push(Instruction::I64Binop(I64Binop::Ne)),
+                WASM_OP_I64_LT_S => insts.push(Instruction::I64Binop(I64Binop::LtS)),
+                WASM_OP_I64_LT_U => insts.push(Instruction::I64Binop(I64Binop::LtU)),
+                WASM_OP_I64_GT_S => insts.push(Instruction::I64Binop(I64Binop::GtS)),
+                WASM_OP_I64_GT_U => insts.push(Instruction::I64Binop(I64Binop::GtU)),
+                WASM_OP_I64_LE_S => insts.push(Instruction::I64Binop(I64Binop::LeS)),
+                WASM_OP_I64_LE_U => insts.push(Instruction::I64Binop(I64Binop::LeU)),
+                WASM_OP_I64_GE_S => insts.push(Instruction::I64Binop(I64Binop::GeS)),
+                WASM_OP_I64_GE_U => insts.push(Instruction::I64Binop(I64Binop::GeU)),
+                WASM_OP_I64_CLZ => insts.push(Instruction::I64Unop(I64Unop::Clz)),
+                WASM_OP_I64_CTZ => insts.push(Instruction::I64Unop(I64Unop::Ctz)),
+                WASM_OP_I64_POPCNT => insts.push(Instruction::I64Unop(I64Unop::Popcnt)),
+                WASM_OP_I64_ADD => insts.push(Instruction::I64Binop(I64Binop::Add)),
+                WASM_OP_I64_SUB => insts.push(Instruction::I64Binop(I64Binop::Sub)),
+                WASM_OP_I64_MUL => insts.push(Instruction::I64Binop(I64Binop::Mul)),
+                WASM_OP_I64_DIV_S => insts.push(Instruction::I64Binop(I64Binop::DivS)),
+                WASM_OP_I64_DIV_U => insts.push(Instruction::I64Binop(I64Binop::DivU)),
+                WASM_OP_I64_REM_S => insts.push(Instruction::I64Binop(I64Binop::RemS)),
+                WASM_OP_I64_REM_U => insts.push(Instruction::I64Binop(I64Binop::RemU)),
+                WASM_OP_I64_AND => insts.push(Instruction::I64Binop(I64Binop::And)),
+                WASM_OP_I64_OR => insts.push(Instruction::I64Binop(I64Binop::Or)),
+                WASM_OP_I64_XOR => insts.push(Instruction::I64Binop(I64Binop::Xor)),
+                WASM_OP_I64_SHL => insts.push(Instruction::I64Binop(I64Binop::Shl)),
+                WASM_OP_I64_SHR_S => insts.push(Instruction::I64Binop(I64Binop::ShrS)),
+                WASM_OP_I64_SHR_U => insts.push(Instruction::I64Binop(I64Binop::ShrU)),
+                WASM_OP_I64_ROTL => insts.push(Instruction::I64Binop(I64Binop::Rotl)),
+                WASM_OP_I64_ROTR => insts.push(Instruction::I64Binop(I64Binop::Rotr)),
+                WASM_OP_I64_EXTEND8_S => insts.push(Instruction::I64Unop(I64Unop::Extend8S)),
+                WASM_OP_I64_EXTEND16_S => insts.push(Instruction::I64Unop(I64Unop::Extend16S)),
+                WASM_OP_I64_EXTEND32_S => insts.push(Instruction::I64Unop(I64Unop::Extend32S)),
+                WASM_OP_I32_WRAP_I64 => insts.push(Instruction::I64Unop(I64Unop::I32WrapI64)),
+                WASM_OP_I32_TRUNC_F32_S => insts.push(Instruction::F32Unop(F32Unop::I32TruncF32S)),
+                WASM_OP_I32_TRUNC_F32_U => insts.push(Instruction::F32Unop(F32Unop::I32TruncF32U)),
+                WASM_OP_I64_EXTEND_I32_S => {
+                    insts.push(Instruction::I32Unop(I32Unop::I64ExtendI32S))
+                }
+                WASM_OP_I64_EXTEND_I32_U => {
+                    insts.push(Instruction::I32Unop(I32Unop::I64ExtendI32U))
+                }
+                WASM_OP_I64_TRUNC_F32_S => insts.push(Instruction::F32Unop(F32Unop::I64TruncF32S)),
+                WASM_OP_I64_TRUNC_F32_U => insts.push(Instruction::F32Unop(F32Unop::I64TruncF32U)),
+                WASM_OP_I64_TRUNC_F64_S => insts.push(Instruction::F64Unop(F64Unop::I64TruncF64S)),
+                WASM_OP_I64_TRUNC_F64_U => insts.push(Instruction::F64Unop(F64Unop::I64TruncF64U)),
+                WASM_OP_F32_CONVERT_I32_S => {
+                    insts.push(Instruction::I32Unop(I32Unop::F32ConvertI32S))
+                }
+                WASM_OP_F32_CONVERT_I32_U => {
+                    insts.push(Instruction::I32Unop(I32Unop::F32ConvertI32U))
+                }
+                WASM_OP_F32_CONVERT_I64_S => {
+                    insts.push(Instruction::I64Unop(I64Unop::F32ConvertI64S))
+                }
+                WASM_OP_F32_CONVERT_I64_U => {
+                    insts.push(Instruction::I64Unop(I64Unop::F32ConvertI64U))
+                }
+                WASM_OP_F32_DEMOTE_F64 => insts.push(Instruction::F64Unop(F64Unop::F32DemoteF64)),
+                WASM_OP_F64_CONVERT_I64_S => {
+                    insts.push(Instruction::I64Unop(I64Unop::F64ConvertI64S))
+                }
+                WASM_OP_F64_CONVERT_I64_U => {
+                    insts.push(Instruction::I64Unop(I64Unop::F64ConvertI64U))
+                }
+                WASM_OP_F64_PROMOTE_F32 => insts.push(Instruction::F32Unop(F32Unop::F64PromoteF32)),
+                WASM_OP_I32_REINTERPRET_F32 => {
+                    insts.push(Instruction::F32Unop(F32Unop::I32ReinterpretF32))
+                }
+                WASM_OP_I64_REINTERPRET_F64 => {
+                    insts.push(Instruction::F64Unop(F64Unop::I64ReinterpretF64))
+                }
+                WASM_OP_F32_REINTERPRET_I32 => {
+                    insts.push(Instruction::I32Unop(I32Unop::F32ReinterpretI32))
+                }
+                WASM_OP_F64_REINTERPRET_I64 => {
+                    insts.push(Instruction::I64Unop(I64Unop::F64ReinterpretI64))
+                }
+                WASM_EXT1_FC => insts.push(Self::read_fc_instruction(&mut binary_reader)?),
+                WASM_EXT1_SIMD => insts.push(Self::read_simd_instruction(&mut binary_reader)?),
                 _ => anyhow::bail!("unsupported opcode: 0x{:x}", opcode),
             }
         }
 
-        Ok(insts)
+        Ok((insts, offsets))
+    }
+
+    /// Read an instruction with the 0xFC prefix, the prefix is already
+    /// consumed.
+    fn read_fc_instruction(binary_reader: &mut BinaryReader) -> Result<Instruction> {
+        let opcode = (WASM_EXT1_FC << 8) | binary_reader.read_var_u32()?;
+        let inst = match opcode {
+            WASM_OP_I32_TRUNC_SAT_F32_S => Instruction::F32Unop(F32Unop::I32TruncSatF32S),
+            WASM_OP_I32_TRUNC_SAT_F32_U => Instruction::F32Unop(F32Unop::I32TruncSatF32U),
+            WASM_OP_I32_TRUNC_SAT_F64_S => Instruction::F64Unop(F64Unop::I32TruncSatF64S),
+            WASM_OP_I32_TRUNC_SAT_F64_U => Instruction::F64Unop(F64Unop::I32TruncSatF64U),
+            WASM_OP_I64_TRUNC_SAT_F32_S => Instruction::F32Unop(F32Unop::I64TruncSatF32S),
+            WASM_OP_I64_TRUNC_SAT_F32_U => Instruction::F32Unop(F32Unop::I64TruncSatF32U),
+            WASM_OP_I64_TRUNC_SAT_F64_S => Instruction::F64Unop(F64Unop::I64TruncSatF64S),
+            WASM_OP_I64_TRUNC_SAT_F64_U => Instruction::F64Unop(F64Unop::I64TruncSatF64U),
+            WASM_OP_MEMORY_COPY => Instruction::MemoryCopy {
+                dst_mem: binary_reader.read_var_u32()?,
+                src_mem: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_MEMORY_FILL => Instruction::MemoryFill {
+                mem: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_MEMORY_INIT => Instruction::MemoryInit {
+                data_index: binary_reader.read_var_u32()?,
+                mem: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_DATA_DROP => Instruction::DataDrop {
+                data_index: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_TABLE_INIT => Instruction::TableInit {
+                elem_index: binary_reader.read_var_u32()?,
+                table: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_ELEM_DROP => Instruction::ElemDrop {
+                elem_index: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_TABLE_GROW => Instruction::TableGrow {
+                table: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_TABLE_SIZE => Instruction::TableSize {
+                table: binary_reader.read_var_u32()?,
+            },
+            WASM_OP_TABLE_FILL => Instruction::TableFill {
+                table: binary_reader.read_var_u32()?,
+            },
+            _ => anyhow::bail!("unsupported opcode: 0x{:x}", opcode),
+        };
+
+        Ok(inst)
     }
 
     pub fn is_control_block_start(inst: &Instruction) -> bool {
         matches!(
             inst,
-            Instruction::Block { .. } | Instruction::Loop { .. } | Instruction::If { .. }
+            Instruction::Block { .. }
+                | Instruction::Loop { .. }
+                | Instruction::If { .. }
+                | Instruction::Try { .. }
         )
     }
 
     pub fn is_control_block_end(inst: &Instruction) -> bool {
-        matches!(inst, Instruction::End)
+        matches!(inst, Instruction::End | Instruction::Delegate { .. })
+    }
+
+    /// The targets of every block of a function body, by the pc of the
+    /// instruction starting it, in one pass over the body. A block that is
+    /// never closed has none, the body is validated later.
+    pub fn block_targets(insts: &[Instruction]) -> Vec<Option<BlockTargets>> {
+        let mut targets = vec![None; insts.len()];
+        let mut open = vec![];
+        for (pc, inst) in insts.iter().enumerate() {
+            if Instruction::is_control_block_start(inst) {
+                open.push((pc, None));
+            } else if inst == &Instruction::Else {
+                if let Some((_, else_pc)) = open.last_mut() {
+                    *else_pc = Some(pc);
+                }
+            } else if Instruction::is_control_block_end(inst) {
+                // the last end closes the function body
+                if let Some((start_pc, else_pc)) = open.pop() {
+                    targets[start_pc] = Some(BlockTargets {
+                        end_pc: pc,
+                        else_pc,
+                    });
+                }
+            }
+        }
+        targets
     }
 
     fn read_block_type(binary_reader: &mut BinaryReader) -> Result<BlockType> {
@@ -325,6 +1078,23 @@ impl Instruction {
         }
     }
 
+    fn read_ref_null_type(binary_reader: &mut BinaryReader) -> Result<RefType> {
+        match binary_reader.read_u8()? {
+            0x70 => Ok(RefType::FUNCREF),
+            0x6f => Ok(RefType::EXTERNREF),
+            ty => anyhow::bail!("unsupported reference type: 0x{:x}", ty),
+        }
+    }
+
+    fn read_val_types(binary_reader: &mut BinaryReader) -> Result<Vec<ValType>> {
+        let count = binary_reader.read_var_u32()?;
+        let mut tys = vec![];
+        for _ in 0..count {
+            tys.push(binary_reader.read()?);
+        }
+        Ok(tys)
+    }
+
     fn read_br_table(binary_reader: &mut BinaryReader) -> Result<BrTable> {
         let count = binary_reader.read_var_u32()?;
         let mut targets = vec![];
@@ -338,9 +1108,430 @@ impl Instruction {
         })
     }
 
+    /// Read an instruction with the 0xFD prefix, the prefix is already
+    /// consumed.
+    #[allow(non_upper_case_globals)]
+    fn read_simd_instruction(binary_reader: &mut BinaryReader) -> Result<Instruction> {
+        use V128Shape::*;
+
+        // opcodes past 0x7f are spelled with their LEB128 bytes in wasmops,
+        // e.g. 0xFD8001 for 128
+        let sub = binary_reader.read_var_u32()?;
+        let opcode = if sub < 0x80 {
+            (WASM_EXT1_SIMD << 8) | sub
+        } else {
+            (WASM_EXT1_SIMD << 16) | (((sub & 0x7f) | 0x80) << 8) | (sub >> 7)
+        };
+
+        let inst = match opcode {
+            WASM_OP_V128_LOAD => Instruction::V128Load {
+                memarg: Self::read_memarg(binary_reader)?,
+            },
+            WASM_OP_V128_STORE => Instruction::V128Store {
+                memarg: Self::read_memarg(binary_reader)?,
+            },
+            WASM_OP_V128_LOAD8x8_S => Self::read_load_extend(binary_reader, I16x8, true)?,
+            WASM_OP_V128_LOAD8x8_U => Self::read_load_extend(binary_reader, I16x8, false)?,
+            WASM_OP_V128_LOAD16x4_S => Self::read_load_extend(binary_reader, I32x4, true)?,
+            WASM_OP_V128_LOAD16x4_U => Self::read_load_extend(binary_reader, I32x4, false)?,
+            WASM_OP_V128_LOAD32x2_S => Self::read_load_extend(binary_reader, I64x2, true)?,
+            WASM_OP_V128_LOAD32x2_U => Self::read_load_extend(binary_reader, I64x2, false)?,
+            WASM_OP_V128_LOAD8_SPLAT => Instruction::V128LoadSplat {
+                memarg: Self::read_memarg(binary_reader)?,
+                shape: I8x16,
+            },
+            WASM_OP_V128_LOAD16_SPLAT => Instruction::V128LoadSplat {
+                memarg: Self::read_memarg(binary_reader)?,
+                shape: I16x8,
+            },
+            WASM_OP_V128_LOAD32_SPLAT => Instruction::V128LoadSplat {
+                memarg: Self::read_memarg(binary_reader)?,
+                shape: I32x4,
+            },
+            WASM_OP_V128_LOAD64_SPLAT => Instruction::V128LoadSplat {
+                memarg: Self::read_memarg(binary_reader)?,
+                shape: I64x2,
+            },
+            WASM_OP_V128_LOAD32_ZERO => Instruction::V128LoadZero {
+                memarg: Self::read_memarg(binary_reader)?,
+                shape: I32x4,
+            },
+            WASM_OP_V128_LOAD64_ZERO => Instruction::V128LoadZero {
+                memarg: Self::read_memarg(binary_reader)?,
+                shape: I64x2,
+            },
+            WASM_OP_V128_LOAD8_LANE => Self::read_load_lane(binary_reader, I8x16)?,
+            WASM_OP_V128_LOAD16_LANE => Self::read_load_lane(binary_reader, I16x8)?,
+            WASM_OP_V128_LOAD32_LANE => Self::read_load_lane(binary_reader, I32x4)?,
+            WASM_OP_V128_LOAD64_LANE => Self::read_load_lane(binary_reader, I64x2)?,
+            WASM_OP_V128_STORE8_LANE => Self::read_store_lane(binary_reader, I8x16)?,
+            WASM_OP_V128_STORE16_LANE => Self::read_store_lane(binary_reader, I16x8)?,
+            WASM_OP_V128_STORE32_LANE => Self::read_store_lane(binary_reader, I32x4)?,
+            WASM_OP_V128_STORE64_LANE => Self::read_store_lane(binary_reader, I64x2)?,
+            WASM_OP_V128_CONST => {
+                let bytes: [u8; 16] = binary_reader.read_bytes(16)?.try_into()?;
+                Instruction::V128Const {
+                    value: u128::from_le_bytes(bytes),
+                }
+            }
+            WASM_OP_I8x16_SHUFFLE => {
+                let lanes: [u8; 16] = binary_reader.read_bytes(16)?.try_into()?;
+                if lanes.iter().any(|&lane| lane >= 32) {
+                    anyhow::bail!("i8x16.shuffle: invalid lane index");
+                }
+                Instruction::I8x16Shuffle { lanes }
+            }
+            WASM_OP_I8x16_SPLAT => Instruction::V128Splat { shape: I8x16 },
+            WASM_OP_I16x8_SPLAT => Instruction::V128Splat { shape: I16x8 },
+            WASM_OP_I32x4_SPLAT => Instruction::V128Splat { shape: I32x4 },
+            WASM_OP_I64x2_SPLAT => Instruction::V128Splat { shape: I64x2 },
+            WASM_OP_F32x4_SPLAT => Instruction::V128Splat { shape: F32x4 },
+            WASM_OP_F64x2_SPLAT => Instruction::V128Splat { shape: F64x2 },
+            WASM_OP_I8x16_EXTRACT_LANE_S => Self::read_extract_lane(binary_reader, I8x16, true)?,
+            WASM_OP_I8x16_EXTRACT_LANE_U => Self::read_extract_lane(binary_reader, I8x16, false)?,
+            WASM_OP_I16x8_EXTRACT_LANE_S => Self::read_extract_lane(binary_reader, I16x8, true)?,
+            WASM_OP_I16x8_EXTRACT_LANE_U => Self::read_extract_lane(binary_reader, I16x8, false)?,
+            WASM_OP_I32x4_EXTRACT_LANE => Self::read_extract_lane(binary_reader, I32x4, false)?,
+            WASM_OP_I64x2_EXTRACT_LANE => Self::read_extract_lane(binary_reader, I64x2, false)?,
+            WASM_OP_F32x4_EXTRACT_LANE => Self::read_extract_lane(binary_reader, F32x4, false)?,
+            WASM_OP_F64x2_EXTRACT_LANE => Self::read_extract_lane(binary_reader, F64x2, false)?,
+            WASM_OP_I8x16_REPLACE_LANE => Self::read_replace_lane(binary_reader, I8x16)?,
+            WASM_OP_I16x8_REPLACE_LANE => Self::read_replace_lane(binary_reader, I16x8)?,
+            WASM_OP_I32x4_REPLACE_LANE => Self::read_replace_lane(binary_reader, I32x4)?,
+            WASM_OP_I64x2_REPLACE_LANE => Self::read_replace_lane(binary_reader, I64x2)?,
+            WASM_OP_F32x4_REPLACE_LANE => Self::read_replace_lane(binary_reader, F32x4)?,
+            WASM_OP_F64x2_REPLACE_LANE => Self::read_replace_lane(binary_reader, F64x2)?,
+            WASM_OP_V128_BITSELECT => Instruction::V128Bitselect,
+            // v128 -> v128
+            WASM_OP_V128_NOT => Instruction::V128Unop(V128Unop::Not),
+            WASM_OP_I8x16_ABS => Instruction::V128Unop(V128Unop::Abs(I8x16)),
+            WASM_OP_I16x8_ABS => Instruction::V128Unop(V128Unop::Abs(I16x8)),
+            WASM_OP_I32x4_ABS => Instruction::V128Unop(V128Unop::Abs(I32x4)),
+            WASM_OP_I64x2_ABS => Instruction::V128Unop(V128Unop::Abs(I64x2)),
+            WASM_OP_F32x4_ABS => Instruction::V128Unop(V128Unop::Abs(F32x4)),
+            WASM_OP_F64x2_ABS => Instruction::V128Unop(V128Unop::Abs(F64x2)),
+            WASM_OP_I8x16_NEG => Instruction::V128Unop(V128Unop::Neg(I8x16)),
+            WASM_OP_I16x8_NEG => Instruction::V128Unop(V128Unop::Neg(I16x8)),
+            WASM_OP_I32x4_NEG => Instruction::V128Unop(V128Unop::Neg(I32x4)),
+            WASM_OP_I64x2_NEG => Instruction::V128Unop(V128Unop::Neg(I64x2)),
+            WASM_OP_F32x4_NEG => Instruction::V128Unop(V128Unop::Neg(F32x4)),
+            WASM_OP_F64x2_NEG => Instruction::V128Unop(V128Unop::Neg(F64x2)),
+            WASM_OP_F32x4_SQRT => Instruction::V128Unop(V128Unop::Sqrt(F32x4)),
+            WASM_OP_F64x2_SQRT => Instruction::V128Unop(V128Unop::Sqrt(F64x2)),
+            WASM_OP_F32x4_CEIL => Instruction::V128Unop(V128Unop::Ceil(F32x4)),
+            WASM_OP_F64x2_CEIL => Instruction::V128Unop(V128Unop::Ceil(F64x2)),
+            WASM_OP_F32x4_FLOOR => Instruction::V128Unop(V128Unop::Floor(F32x4)),
+            WASM_OP_F64x2_FLOOR => Instruction::V128Unop(V128Unop::Floor(F64x2)),
+            WASM_OP_F32x4_TRUNC => Instruction::V128Unop(V128Unop::Trunc(F32x4)),
+            WASM_OP_F64x2_TRUNC => Instruction::V128Unop(V128Unop::Trunc(F64x2)),
+            WASM_OP_F32x4_NEAREST => Instruction::V128Unop(V128Unop::Nearest(F32x4)),
+            WASM_OP_F64x2_NEAREST => Instruction::V128Unop(V128Unop::Nearest(F64x2)),
+            WASM_OP_I8x16_POPCNT => Instruction::V128Unop(V128Unop::I8x16Popcnt),
+            WASM_OP_I32x4_TRUNC_SAT_F32x4_S => Instruction::V128Unop(V128Unop::I32x4TruncSatF32x4S),
+            WASM_OP_I32x4_TRUNC_SAT_F32x4_U => Instruction::V128Unop(V128Unop::I32x4TruncSatF32x4U),
+            WASM_OP_F32x4_TRUNC_SAT_I32x4_S => Instruction::V128Unop(V128Unop::F32x4ConvertI32x4S),
+            WASM_OP_F32x4_TRUNC_SAT_I32x4_U => Instruction::V128Unop(V128Unop::F32x4ConvertI32x4U),
+            WASM_OP_I32x4_TRUNC_SAT_F64x2_S_ZERO => {
+                Instruction::V128Unop(V128Unop::I32x4TruncSatF64x2SZero)
+            }
+            WASM_OP_I32x4_TRUNC_SAT_F64x2_U_ZERO => {
+                Instruction::V128Unop(V128Unop::I32x4TruncSatF64x2UZero)
+            }
+            WASM_OP_F64x2_CONVERT_LOW_I32x4_S => {
+                Instruction::V128Unop(V128Unop::F64x2ConvertLowI32x4S)
+            }
+            WASM_OP_F64x2_CONVERT_LOW_I32x4_U => {
+                Instruction::V128Unop(V128Unop::F64x2ConvertLowI32x4U)
+            }
+            WASM_OP_F32x4_DEMOTE_F64x2_ZERO => {
+                Instruction::V128Unop(V128Unop::F32x4DemoteF64x2Zero)
+            }
+            WASM_OP_F64x2_PROMOTE_F32x4 => Instruction::V128Unop(V128Unop::F64x2PromoteLowF32x4),
+            WASM_OP_I16x8_EXTEND_LOW_I8x16_S => Instruction::V128Unop(V128Unop::ExtendLowS(I16x8)),
+            WASM_OP_I16x8_EXTEND_LOW_I8x16_U => Instruction::V128Unop(V128Unop::ExtendLowU(I16x8)),
+            WASM_OP_I16x8_EXTEND_HIGH_I8x16_S => {
+                Instruction::V128Unop(V128Unop::ExtendHighS(I16x8))
+            }
+            WASM_OP_I16x8_EXTEND_HIGH_I8x16_U => {
+                Instruction::V128Unop(V128Unop::ExtendHighU(I16x8))
+            }
+            WASM_OP_I32x4_EXTEND_LOW_I16x8_S => Instruction::V128Unop(V128Unop::ExtendLowS(I32x4)),
+            WASM_OP_I32x4_EXTEND_LOW_I16x8_U => Instruction::V128Unop(V128Unop::ExtendLowU(I32x4)),
+            WASM_OP_I32x4_EXTEND_HIGH_I16x8_S => {
+                Instruction::V128Unop(V128Unop::ExtendHighS(I32x4))
+            }
+            WASM_OP_I32x4_EXTEND_HIGH_I16x8_U => {
+                Instruction::V128Unop(V128Unop::ExtendHighU(I32x4))
+            }
+            WASM_OP_I64x2_EXTEND_LOW_I32x4_S => Instruction::V128Unop(V128Unop::ExtendLowS(I64x2)),
+            WASM_OP_I64x2_EXTEND_LOW_I32x4_U => Instruction::V128Unop(V128Unop::ExtendLowU(I64x2)),
+            WASM_OP_I64x2_EXTEND_HIGH_I32x4_S => {
+                Instruction::V128Unop(V128Unop::ExtendHighS(I64x2))
+            }
+            WASM_OP_I64x2_EXTEND_HIGH_I32x4_U => {
+                Instruction::V128Unop(V128Unop::ExtendHighU(I64x2))
+            }
+            WASM_OP_I16x8_EXTADD_PAIRWISE_I8x16_S => {
+                Instruction::V128Unop(V128Unop::ExtaddPairwiseS(I16x8))
+            }
+            WASM_OP_I16x8_EXTADD_PAIRWISE_I8x16_U => {
+                Instruction::V128Unop(V128Unop::ExtaddPairwiseU(I16x8))
+            }
+            WASM_OP_I32x4_EXTADD_PAIRWISE_I16x8_S => {
+                Instruction::V128Unop(V128Unop::ExtaddPairwiseS(I32x4))
+            }
+            WASM_OP_I32x4_EXTADD_PAIRWISE_I16x8_U => {
+                Instruction::V128Unop(V128Unop::ExtaddPairwiseU(I32x4))
+            }
+            // v128 v128 -> v128
+            WASM_OP_V128_AND => Instruction::V128Binop(V128Binop::And),
+            WASM_OP_V128_ANDNOT => Instruction::V128Binop(V128Binop::AndNot),
+            WASM_OP_V128_OR => Instruction::V128Binop(V128Binop::Or),
+            WASM_OP_V128_XOR => Instruction::V128Binop(V128Binop::Xor),
+            WASM_OP_I8x16_SWIZZLE => Instruction::V128Binop(V128Binop::I8x16Swizzle),
+            WASM_OP_I8x16_ADD => Instruction::V128Binop(V128Binop::Add(I8x16)),
+            WASM_OP_I16x8_ADD => Instruction::V128Binop(V128Binop::Add(I16x8)),
+            WASM_OP_I32x4_ADD => Instruction::V128Binop(V128Binop::Add(I32x4)),
+            WASM_OP_I64x2_ADD => Instruction::V128Binop(V128Binop::Add(I64x2)),
+            WASM_OP_F32x4_ADD => Instruction::V128Binop(V128Binop::Add(F32x4)),
+            WASM_OP_F64x2_ADD => Instruction::V128Binop(V128Binop::Add(F64x2)),
+            WASM_OP_I8x16_SUB => Instruction::V128Binop(V128Binop::Sub(I8x16)),
+            WASM_OP_I16x8_SUB => Instruction::V128Binop(V128Binop::Sub(I16x8)),
+            WASM_OP_I32x4_SUB => Instruction::V128Binop(V128Binop::Sub(I32x4)),
+            WASM_OP_I64x2_SUB => Instruction::V128Binop(V128Binop::Sub(I64x2)),
+            WASM_OP_F32x4_SUB => Instruction::V128Binop(V128Binop::Sub(F32x4)),
+            WASM_OP_F64x2_SUB => Instruction::V128Binop(V128Binop::Sub(F64x2)),
+            WASM_OP_I16x8_MUL => Instruction::V128Binop(V128Binop::Mul(I16x8)),
+            WASM_OP_I32x4_MUL => Instruction::V128Binop(V128Binop::Mul(I32x4)),
+            WASM_OP_I64x2_MUL => Instruction::V128Binop(V128Binop::Mul(I64x2)),
+            WASM_OP_F32x4_MUL => Instruction::V128Binop(V128Binop::Mul(F32x4)),
+            WASM_OP_F64x2_MUL => Instruction::V128Binop(V128Binop::Mul(F64x2)),
+            WASM_OP_F32x4_DIV => Instruction::V128Binop(V128Binop::Div(F32x4)),
+            WASM_OP_F64x2_DIV => Instruction::V128Binop(V128Binop::Div(F64x2)),
+            WASM_OP_F32x4_MIN => Instruction::V128Binop(V128Binop::Min(F32x4)),
+            WASM_OP_F64x2_MIN => Instruction::V128Binop(V128Binop::Min(F64x2)),
+            WASM_OP_F32x4_MAX => Instruction::V128Binop(V128Binop::Max(F32x4)),
+            WASM_OP_F64x2_MAX => Instruction::V128Binop(V128Binop::Max(F64x2)),
+            WASM_OP_I8x16_MIN_S => Instruction::V128Binop(V128Binop::MinS(I8x16)),
+            WASM_OP_I16x8_MIN_S => Instruction::V128Binop(V128Binop::MinS(I16x8)),
+            WASM_OP_I32x4_MIN_S => Instruction::V128Binop(V128Binop::MinS(I32x4)),
+            WASM_OP_I8x16_MIN_U => Instruction::V128Binop(V128Binop::MinU(I8x16)),
+            WASM_OP_I16x8_MIN_U => Instruction::V128Binop(V128Binop::MinU(I16x8)),
+            WASM_OP_I32x4_MIN_U => Instruction::V128Binop(V128Binop::MinU(I32x4)),
+            WASM_OP_I8x16_MAX_S => Instruction::V128Binop(V128Binop::MaxS(I8x16)),
+            WASM_OP_I16x8_MAX_S => Instruction::V128Binop(V128Binop::MaxS(I16x8)),
+            WASM_OP_I32x4_MAX_S => Instruction::V128Binop(V128Binop::MaxS(I32x4)),
+            WASM_OP_I8x16_MAX_U => Instruction::V128Binop(V128Binop::MaxU(I8x16)),
+            WASM_OP_I16x8_MAX_U => Instruction::V128Binop(V128Binop::MaxU(I16x8)),
+            WASM_OP_I32x4_MAX_U => Instruction::V128Binop(V128Binop::MaxU(I32x4)),
+            WASM_OP_I8x16_ADD_SAT_S => Instruction::V128Binop(V128Binop::AddSatS(I8x16)),
+            WASM_OP_I16x8_ADD_SAT_S => Instruction::V128Binop(V128Binop::AddSatS(I16x8)),
+            WASM_OP_I8x16_ADD_SAT_U => Instruction::V128Binop(V128Binop::AddSatU(I8x16)),
+            WASM_OP_I16x8_ADD_SAT_U => Instruction::V128Binop(V128Binop::AddSatU(I16x8)),
+            WASM_OP_I8x16_SUB_SAT_S => Instruction::V128Binop(V128Binop::SubSatS(I8x16)),
+            WASM_OP_I16x8_SUB_SAT_S => Instruction::V128Binop(V128Binop::SubSatS(I16x8)),
+            WASM_OP_I8x16_SUB_SAT_U => Instruction::V128Binop(V128Binop::SubSatU(I8x16)),
+            WASM_OP_I16x8_SUB_SAT_U => Instruction::V128Binop(V128Binop::SubSatU(I16x8)),
+            WASM_OP_I8x16_AVGR_U => Instruction::V128Binop(V128Binop::AvgrU(I8x16)),
+            WASM_OP_I16x8_AVGR_U => Instruction::V128Binop(V128Binop::AvgrU(I16x8)),
+            WASM_OP_I8x16_EQ => Instruction::V128Binop(V128Binop::Eq(I8x16)),
+            WASM_OP_I16x8_EQ => Instruction::V128Binop(V128Binop::Eq(I16x8)),
+            WASM_OP_I32x4_EQ => Instruction::V128Binop(V128Binop::Eq(I32x4)),
+            WASM_OP_I64x2_EQ => Instruction::V128Binop(V128Binop::Eq(I64x2)),
+            WASM_OP_F32x4_EQ => Instruction::V128Binop(V128Binop::Eq(F32x4)),
+            WASM_OP_F64x2_EQ => Instruction::V128Binop(V128Binop::Eq(F64x2)),
+            WASM_OP_I8x16_NE => Instruction::V128Binop(V128Binop::Ne(I8x16)),
+            WASM_OP_I16x8_NE => Instruction::V128Binop(V128Binop::Ne(I16x8)),
+            WASM_OP_I32x4_NE => Instruction::V128Binop(V128Binop::Ne(I32x4)),
+            WASM_OP_I64x2_NE => Instruction::V128Binop(V128Binop::Ne(I64x2)),
+            WASM_OP_F32x4_NE => Instruction::V128Binop(V128Binop::Ne(F32x4)),
+            WASM_OP_F64x2_NE => Instruction::V128Binop(V128Binop::Ne(F64x2)),
+            WASM_OP_I8x16_LT_S => Instruction::V128Binop(V128Binop::LtS(I8x16)),
+            WASM_OP_I16x8_LT_S => Instruction::V128Binop(V128Binop::LtS(I16x8)),
+            WASM_OP_I32x4_LT_S => Instruction::V128Binop(V128Binop::LtS(I32x4)),
+            WASM_OP_I64x2_LT_S => Instruction::V128Binop(V128Binop::LtS(I64x2)),
+            WASM_OP_I8x16_LT_U => Instruction::V128Binop(V128Binop::LtU(I8x16)),
+            WASM_OP_I16x8_LT_U => Instruction::V128Binop(V128Binop::LtU(I16x8)),
+            WASM_OP_I32x4_LT_U => Instruction::V128Binop(V128Binop::LtU(I32x4)),
+            WASM_OP_I8x16_GT_S => Instruction::V128Binop(V128Binop::GtS(I8x16)),
+            WASM_OP_I16x8_GT_S => Instruction::V128Binop(V128Binop::GtS(I16x8)),
+            WASM_OP_I32x4_GT_S => Instruction::V128Binop(V128Binop::GtS(I32x4)),
+            WASM_OP_I64x2_GT_S => Instruction::V128Binop(V128Binop::GtS(I64x2)),
+            WASM_OP_I8x16_GT_U => Instruction::V128Binop(V128Binop::GtU(I8x16)),
+            WASM_OP_I16x8_GT_U => Instruction::V128Binop(V128Binop::GtU(I16x8)),
+            WASM_OP_I32x4_GT_U => Instruction::V128Binop(V128Binop::GtU(I32x4)),
+            WASM_OP_I8x16_LE_S => Instruction::V128Binop(V128Binop::LeS(I8x16)),
+            WASM_OP_I16x8_LE_S => Instruction::V128Binop(V128Binop::LeS(I16x8)),
+            WASM_OP_I32x4_LE_S => Instruction::V128Binop(V128Binop::LeS(I32x4)),
+            WASM_OP_I64x2_LE_S => Instruction::V128Binop(V128Binop::LeS(I64x2)),
+            WASM_OP_I8x16_LE_U => Instruction::V128Binop(V128Binop::LeU(I8x16)),
+            WASM_OP_I16x8_LE_U => Instruction::V128Binop(V128Binop::LeU(I16x8)),
+            WASM_OP_I32x4_LE_U => Instruction::V128Binop(V128Binop::LeU(I32x4)),
+            WASM_OP_I8x16_GE_S => Instruction::V128Binop(V128Binop::GeS(I8x16)),
+            WASM_OP_I16x8_GE_S => Instruction::V128Binop(V128Binop::GeS(I16x8)),
+            WASM_OP_I32x4_GE_S => Instruction::V128Binop(V128Binop::GeS(I32x4)),
+            WASM_OP_I64x2_GE_S => Instruction::V128Binop(V128Binop::GeS(I64x2)),
+            WASM_OP_I8x16_GE_U => Instruction::V128Binop(V128Binop::GeU(I8x16)),
+            WASM_OP_I16x8_GE_U => Instruction::V128Binop(V128Binop::GeU(I16x8)),
+            WASM_OP_I32x4_GE_U => Instruction::V128Binop(V128Binop::GeU(I32x4)),
+            WASM_OP_F32x4_LT => Instruction::V128Binop(V128Binop::Lt(F32x4)),
+            WASM_OP_F64x2_LT => Instruction::V128Binop(V128Binop::Lt(F64x2)),
+            WASM_OP_F32x4_GT => Instruction::V128Binop(V128Binop::Gt(F32x4)),
+            WASM_OP_F64x2_GT => Instruction::V128Binop(V128Binop::Gt(F64x2)),
+            WASM_OP_F32x4_LE => Instruction::V128Binop(V128Binop::Le(F32x4)),
+            WASM_OP_F64x2_LE => Instruction::V128Binop(V128Binop::Le(F64x2)),
+            WASM_OP_F32x4_GE => Instruction::V128Binop(V128Binop::Ge(F32x4)),
+            WASM_OP_F64x2_GE => Instruction::V128Binop(V128Binop::Ge(F64x2)),
+            WASM_OP_F32x4_PMIN => Instruction::V128Binop(V128Binop::Pmin(F32x4)),
+            WASM_OP_F64x2_PMIN => Instruction::V128Binop(V128Binop::Pmin(F64x2)),
+            WASM_OP_F32x4_PMAX => Instruction::V128Binop(V128Binop::Pmax(F32x4)),
+            WASM_OP_F64x2_PMAX => Instruction::V128Binop(V128Binop::Pmax(F64x2)),
+            WASM_OP_I8x16_NARROW_I16x8_S => Instruction::V128Binop(V128Binop::NarrowS(I8x16)),
+            WASM_OP_I8x16_NARROW_I16x8_U => Instruction::V128Binop(V128Binop::NarrowU(I8x16)),
+            WASM_OP_I16x8_NARROW_I32x4_S => Instruction::V128Binop(V128Binop::NarrowS(I16x8)),
+            WASM_OP_I16x8_NARROW_I32x4_U => Instruction::V128Binop(V128Binop::NarrowU(I16x8)),
+            WASM_OP_I16x8_EXTMUL_LOW_I8x16_S => {
+                Instruction::V128Binop(V128Binop::ExtmulLowS(I16x8))
+            }
+            WASM_OP_I16x8_EXTMUL_LOW_I8x16_U => {
+                Instruction::V128Binop(V128Binop::ExtmulLowU(I16x8))
+            }
+            WASM_OP_I16x8_EXTMUL_HIGH_I8x16_S => {
+                Instruction::V128Binop(V128Binop::ExtmulHighS(I16x8))
+            }
+            WASM_OP_I16x8_EXTMUL_HIGH_I8x16_U => {
+                Instruction::V128Binop(V128Binop::ExtmulHighU(I16x8))
+            }
+            WASM_OP_I32x4_EXTMUL_LOW_I16x8_S => {
+                Instruction::V128Binop(V128Binop::ExtmulLowS(I32x4))
+            }
+            WASM_OP_I32x4_EXTMUL_LOW_I16x8_U => {
+                Instruction::V128Binop(V128Binop::ExtmulLowU(I32x4))
+            }
+            WASM_OP_I32x4_EXTMUL_HIGH_I16x8_S => {
+                Instruction::V128Binop(V128Binop::ExtmulHighS(I32x4))
+            }
+            WASM_OP_I32x4_EXTMUL_HIGH_I16x8_U => {
+                Instruction::V128Binop(V128Binop::ExtmulHighU(I32x4))
+            }
+            WASM_OP_I64x2_EXTMUL_LOW_I32x4_S => {
+                Instruction::V128Binop(V128Binop::ExtmulLowS(I64x2))
+            }
+            WASM_OP_I64x2_EXTMUL_LOW_I32x4_U => {
+                Instruction::V128Binop(V128Binop::ExtmulLowU(I64x2))
+            }
+            WASM_OP_I64x2_EXTMUL_HIGH_I32x4_S => {
+                Instruction::V128Binop(V128Binop::ExtmulHighS(I64x2))
+            }
+            WASM_OP_I64x2_EXTMUL_HIGH_I32x4_U => {
+                Instruction::V128Binop(V128Binop::ExtmulHighU(I64x2))
+            }
+            WASM_OP_I16x8_Q15MULR_SAT_S => Instruction::V128Binop(V128Binop::I16x8Q15MulrSatS),
+            WASM_OP_I32x4_DOT_I16x8_S => Instruction::V128Binop(V128Binop::I32x4DotI16x8S),
+            // v128 i32 -> v128
+            WASM_OP_I8x16_SHL => Instruction::V128Shift(V128Shift::Shl(I8x16)),
+            WASM_OP_I16x8_SHL => Instruction::V128Shift(V128Shift::Shl(I16x8)),
+            WASM_OP_I32x4_SHL => Instruction::V128Shift(V128Shift::Shl(I32x4)),
+            WASM_OP_I64x2_SHL => Instruction::V128Shift(V128Shift::Shl(I64x2)),
+            WASM_OP_I8x16_SHR_S => Instruction::V128Shift(V128Shift::ShrS(I8x16)),
+            WASM_OP_I16x8_SHR_S => Instruction::V128Shift(V128Shift::ShrS(I16x8)),
+            WASM_OP_I32x4_SHR_S => Instruction::V128Shift(V128Shift::ShrS(I32x4)),
+            WASM_OP_I64x2_SHR_S => Instruction::V128Shift(V128Shift::ShrS(I64x2)),
+            WASM_OP_I8x16_SHR_U => Instruction::V128Shift(V128Shift::ShrU(I8x16)),
+            WASM_OP_I16x8_SHR_U => Instruction::V128Shift(V128Shift::ShrU(I16x8)),
+            WASM_OP_I32x4_SHR_U => Instruction::V128Shift(V128Shift::ShrU(I32x4)),
+            WASM_OP_I64x2_SHR_U => Instruction::V128Shift(V128Shift::ShrU(I64x2)),
+            // v128 -> i32
+            WASM_OP_V128_ANY_TRUE => Instruction::V128Test(V128Test::AnyTrue),
+            WASM_OP_I8x16_ALL_TRUE => Instruction::V128Test(V128Test::AllTrue(I8x16)),
+            WASM_OP_I16x8_ALL_TRUE => Instruction::V128Test(V128Test::AllTrue(I16x8)),
+            WASM_OP_I32x4_ALL_TRUE => Instruction::V128Test(V128Test::AllTrue(I32x4)),
+            WASM_OP_I64x2_ALL_TRUE => Instruction::V128Test(V128Test::AllTrue(I64x2)),
+            WASM_OP_I8x16_BITMASK => Instruction::V128Test(V128Test::Bitmask(I8x16)),
+            WASM_OP_I16x8_BITMASK => Instruction::V128Test(V128Test::Bitmask(I16x8)),
+            WASM_OP_I32x4_BITMASK => Instruction::V128Test(V128Test::Bitmask(I32x4)),
+            WASM_OP_I64x2_BITMASK => Instruction::V128Test(V128Test::Bitmask(I64x2)),
+            _ => anyhow::bail!("unsupported opcode: 0x{:x}", opcode),
+        };
+
+        Ok(inst)
+    }
+
+    fn read_lane_index(binary_reader: &mut BinaryReader, shape: V128Shape) -> Result<u8> {
+        let lane = binary_reader.read_u8()?;
+        if lane as u32 >= shape.num_lanes() {
+            anyhow::bail!("invalid lane index {} for {:?}", lane, shape);
+        }
+        Ok(lane)
+    }
+
+    fn read_extract_lane(
+        binary_reader: &mut BinaryReader,
+        shape: V128Shape,
+        signed: bool,
+    ) -> Result<Instruction> {
+        Ok(Instruction::V128ExtractLane {
+            shape,
+            lane: Self::read_lane_index(binary_reader, shape)?,
+            signed,
+        })
+    }
+
+    fn read_replace_lane(
+        binary_reader: &mut BinaryReader,
+        shape: V128Shape,
+    ) -> Result<Instruction> {
+        Ok(Instruction::V128ReplaceLane {
+            shape,
+            lane: Self::read_lane_index(binary_reader, shape)?,
+        })
+    }
+
+    fn read_load_extend(
+        binary_reader: &mut BinaryReader,
+        shape: V128Shape,
+        signed: bool,
+    ) -> Result<Instruction> {
+        Ok(Instruction::V128LoadExtend {
+            memarg: Self::read_memarg(binary_reader)?,
+            shape,
+            signed,
+        })
+    }
+
+    fn read_load_lane(binary_reader: &mut BinaryReader, shape: V128Shape) -> Result<Instruction> {
+        let memarg = Self::read_memarg(binary_reader)?;
+        Ok(Instruction::V128LoadLane {
+            memarg,
+            shape,
+            lane: Self::read_lane_index(binary_reader, shape)?,
+        })
+    }
+
+    fn read_store_lane(binary_reader: &mut BinaryReader, shape: V128Shape) -> Result<Instruction> {
+        let memarg = Self::read_memarg(binary_reader)?;
+        Ok(Instruction::V128StoreLane {
+            memarg,
+            shape,
+            lane: Self::read_lane_index(binary_reader, shape)?,
+        })
+    }
+
     fn read_memarg(binary_reader: &mut BinaryReader) -> Result<MemArg> {
-        let align = binary_reader.read_var_u32()?;
-        let offset = binary_reader.read_var_u32()?;
-        Ok(MemArg { offset, align })
+        let mut align = binary_reader.read_var_u32()?;
+        // with multiple memories, bit 6 of the alignment tells that a memory
+        // index follows
+        let mut memory = 0;
+        if align & (1 << 6) != 0 {
+            align &= !(1 << 6);
+            memory = binary_reader.read_var_u32()?;
+        }
+        // memory64 offsets can be larger than u32
+        let offset = binary_reader.read_var_u64()?;
+        Ok(MemArg {
+            offset,
+            align,
+            memory,
+        })
     }
 }
@@ -1,6 +1,12 @@
+pub mod bytecode;
 pub mod components;
+pub(crate) mod const_expr;
+pub mod dump;
+pub mod dwarf;
+pub mod features;
 pub mod insts;
 pub mod parse;
+pub mod validate;
 pub mod value_type;
 pub mod wasm_module;
 pub mod wasmdefs;
@@ -1,15 +1,34 @@
-use anyhow::Result;
-use wasmparser::{Data, Element, Export, FuncType, MemoryType, Table, ValType};
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use wasmparser::{Data, Element, Export, FuncType, MemoryType, Name, Table, TagType, ValType};
 
 use super::{
     components::{FuncDecl, GlobalDecl, ImportSet},
-    insts::Instruction,
+    insts::{BlockTargets, Instruction},
     wasm_module::WasmModule,
 };
 
 pub(crate) struct FuncBody {
     pub(crate) locals: Vec<(u32, ValType)>,
     pub(crate) insts: Vec<Instruction>,
+    /// the offset of every instruction from the start of the code section,
+    /// the addresses of the DWARF debug info
+    pub(crate) inst_offsets: Vec<u32>,
+    /// the targets of the blocks, by the pc of their start
+    pub(crate) block_targets: Vec<Option<BlockTargets>>,
+    /// the offset of the body, its locals first, from the start of the code
+    /// section
+    pub(crate) body_offset: u32,
+}
+
+/// The module, function and local names of the custom name section.
+#[derive(Debug, Default)]
+pub(crate) struct NameSection<'a> {
+    pub(crate) module_name: Option<&'a str>,
+    pub(crate) func_names: HashMap<u32, &'a str>,
+    /// function index -> local index -> name
+    pub(crate) local_names: HashMap<u32, HashMap<u32, &'a str>>,
 }
 
 impl<'a> WasmModule<'a> {
@@ -21,17 +40,19 @@ impl<'a> WasmModule<'a> {
         for recgroup in tsread {
             let recgroup = recgroup?;
             if recgroup.is_explicit_rec_group() {
-                todo!("explicit rec groups not supported");
-            } else {
-                let ty = recgroup.into_types().next().unwrap();
-                match ty.composite_type.inner {
-                    wasmparser::CompositeInnerType::Func(func_type) => {
-                        sigs.push(func_type);
-                    }
-                    wasmparser::CompositeInnerType::Array(_)
-                    | wasmparser::CompositeInnerType::Struct(_) => {
-                        todo!("Array and struct are not yet implemented")
-                    }
+                bail!("explicit rec groups are not supported");
+            }
+            let ty = recgroup
+                .into_types()
+                .next()
+                .ok_or_else(|| anyhow!("empty rec group"))?;
+            match ty.composite_type.inner {
+                wasmparser::CompositeInnerType::Func(func_type) => {
+                    sigs.push(func_type);
+                }
+                wasmparser::CompositeInnerType::Array(_)
+                | wasmparser::CompositeInnerType::Struct(_) => {
+                    bail!("array and struct types are not supported")
                 }
             }
         }
@@ -57,7 +78,11 @@ impl<'a> WasmModule<'a> {
                 wasmparser::TypeRef::Table(_) => import_set.num_tables += 1,
                 wasmparser::TypeRef::Memory(_) => import_set.num_mems += 1,
                 wasmparser::TypeRef::Global(_) => import_set.num_globals += 1,
-                _ => todo!("import tag not yet implemented"),
+                _ => bail!(
+                    "import {}.{}: importing tags is not supported",
+                    import.module,
+                    import.name
+                ),
             }
             import_set.imports.push(import);
         }
@@ -73,8 +98,10 @@ impl<'a> WasmModule<'a> {
 
         for ind in fread {
             let ind = ind?;
-            let ty = sigs[ind as usize].clone();
-            func_decls.push(FuncDecl::new(ty));
+            let ty = sigs
+                .get(ind as usize)
+                .ok_or_else(|| anyhow!("function section: unknown type {}", ind))?;
+            func_decls.push(FuncDecl::new(ty.clone()));
         }
 
         Ok(func_decls)
@@ -97,11 +124,6 @@ impl<'a> WasmModule<'a> {
         memread: wasmparser::MemorySectionReader,
     ) -> Result<Vec<MemoryType>> {
         let mut mems = vec![];
-
-        if memread.count() != 1 {
-            anyhow::bail!("multiple memories not yet supported");
-        }
-
         for mem in memread {
             mems.push(mem?);
         }
@@ -109,6 +131,15 @@ impl<'a> WasmModule<'a> {
         Ok(mems)
     }
 
+    pub(crate) fn parse_tag_section(tagread: wasmparser::TagSectionReader) -> Result<Vec<TagType>> {
+        let mut tags = vec![];
+        for tag in tagread {
+            tags.push(tag?);
+        }
+
+        Ok(tags)
+    }
+
     pub(crate) fn parse_global_section(
         gread: wasmparser::GlobalSectionReader<'a>,
     ) -> Result<Vec<GlobalDecl>> {
@@ -164,7 +195,43 @@ impl<'a> WasmModule<'a> {
         Ok(datas)
     }
 
-    pub(crate) fn parse_code_section(func_body: wasmparser::FunctionBody<'a>) -> Result<FuncBody> {
+    pub(crate) fn parse_name_section(
+        nread: wasmparser::NameSectionReader<'a>,
+    ) -> Result<NameSection<'a>> {
+        let mut names = NameSection::default();
+        for name in nread {
+            match name? {
+                Name::Module { name, .. } => names.module_name = Some(name),
+                Name::Function(map) => {
+                    for naming in map {
+                        let naming = naming?;
+                        names.func_names.insert(naming.index, naming.name);
+                    }
+                }
+                Name::Local(map) => {
+                    for func in map {
+                        let func = func?;
+                        let mut locals = HashMap::new();
+                        for naming in func.names {
+                            let naming = naming?;
+                            locals.insert(naming.index, naming.name);
+                        }
+                        names.local_names.insert(func.index, locals);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(names)
+    }
+
+    /// Parse a function body, `code_section_start` is the offset of the
+    /// contents of the code section in the module.
+    pub(crate) fn parse_code_section(
+        func_body: wasmparser::FunctionBody<'a>,
+        code_section_start: usize,
+    ) -> Result<FuncBody> {
+        let body_offset = (func_body.range().start - code_section_start) as u32;
         let mut locals = vec![];
         let local_reader = func_body.get_locals_reader()?;
         for local in local_reader {
@@ -179,12 +246,22 @@ impl<'a> WasmModule<'a> {
             binary_reader.read::<ValType>()?;
         }
         // the remaining bytes are the operators
-        let code_bytes = binary_reader
-            .read_bytes(binary_reader.bytes_remaining())?
-            .to_vec();
+        let code_offset = (binary_reader.original_position() - code_section_start) as u32;
+        let code_bytes = binary_reader.read_bytes(binary_reader.bytes_remaining())?;
 
-        let insts = Instruction::from_code_bytes(code_bytes)?;
+        let (insts, offsets) = Instruction::decode_code_bytes(code_bytes)?;
+        let inst_offsets = offsets
+            .into_iter()
+            .map(|offset| code_offset + offset)
+            .collect();
+        let block_targets = Instruction::block_targets(&insts);
 
-        Ok(FuncBody { locals, insts })
+        Ok(FuncBody {
+            locals,
+            insts,
+            inst_offsets,
+            block_targets,
+            body_offset,
+        })
     }
 }
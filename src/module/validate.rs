@@ -0,0 +1,955 @@
+//! Validation of function bodies before anything is executed. Every body is
+//! type checked with an abstract operand stack, following the algorithm of
+//! the validation appendix of the spec, so that the interpreter and the jit
+//! can rely on well typed code.
+
+use wasmparser::{BlockType, FuncType, GlobalType, MemoryType, RefType, TableType, ValType};
+
+use super::{
+    components::FuncDecl,
+    features::Feature,
+    insts::{
+        F32Binop, F32Unop, F64Binop, F64Unop, I32Unop, I64Binop, I64Unop, Instruction, MemArg,
+        V128Shape,
+    },
+    wasm_module::WasmModule,
+};
+
+/// An invalid instruction, `inst_index` is its position in the body of
+/// function `func_index`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub func_index: u32,
+    pub inst_index: usize,
+    pub kind: ValidationErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationErrorKind {
+    /// an operand of the wrong type, `found` is `None` if the stack was
+    /// empty
+    TypeMismatch {
+        expected: Option<ValType>,
+        found: Option<ValType>,
+    },
+    /// values left on the stack at the end of a block
+    ExtraValues {
+        expected: usize,
+        found: usize,
+    },
+    /// the targets of a `br_table` take different numbers of values
+    BranchArityMismatch,
+    /// an `if` without `else` whose params and results differ
+    MissingElse,
+    /// `select` without types on reference or vector operands
+    InvalidSelect,
+    ImmutableGlobal(u32),
+    UnknownLocal(u32),
+    UnknownGlobal(u32),
+    UnknownFunction(u32),
+    UnknownType(u32),
+    UnknownTable(u32),
+    UnknownMemory(u32),
+    UnknownLabel(u32),
+    UnknownTag(u32),
+    UnknownDataSegment(u32),
+    UnknownElemSegment(u32),
+    /// `memory.init` and `data.drop` need a data count section
+    MissingDataCount,
+    InvalidAlignment,
+    InvalidResultArity,
+    /// `else`, `catch`, `delegate` or `end` in the wrong block, or
+    /// instructions past the end of the function
+    UnbalancedBlock,
+    /// an instruction of a proposal the module may not use, see
+    /// `WasmModule::check_features`
+    DisabledFeature(Feature),
+}
+
+impl std::fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationErrorKind::TypeMismatch { expected, found } => write!(
+                f,
+                "type mismatch: expected {}, found {}",
+                expected.as_ref().map_or("a value".to_string(), type_name),
+                found.as_ref().map_or("nothing".to_string(), type_name)
+            ),
+            ValidationErrorKind::ExtraValues { expected, found } => write!(
+                f,
+                "type mismatch: expected {} values at the end of the block, found {}",
+                expected, found
+            ),
+            ValidationErrorKind::BranchArityMismatch => {
+                write!(f, "type mismatch: br_table targets have different arities")
+            }
+            ValidationErrorKind::MissingElse => {
+                write!(
+                    f,
+                    "type mismatch: if without else must not change the stack"
+                )
+            }
+            ValidationErrorKind::InvalidSelect => write!(f, "type mismatch: invalid select"),
+            ValidationErrorKind::ImmutableGlobal(idx) => write!(f, "global is immutable: {}", idx),
+            ValidationErrorKind::UnknownLocal(idx) => write!(f, "unknown local {}", idx),
+            ValidationErrorKind::UnknownGlobal(idx) => write!(f, "unknown global {}", idx),
+            ValidationErrorKind::UnknownFunction(idx) => write!(f, "unknown function {}", idx),
+            ValidationErrorKind::UnknownType(idx) => write!(f, "unknown type {}", idx),
+            ValidationErrorKind::UnknownTable(idx) => write!(f, "unknown table {}", idx),
+            ValidationErrorKind::UnknownMemory(idx) => write!(f, "unknown memory {}", idx),
+            ValidationErrorKind::UnknownLabel(idx) => write!(f, "unknown label {}", idx),
+            ValidationErrorKind::UnknownTag(idx) => write!(f, "unknown tag {}", idx),
+            ValidationErrorKind::UnknownDataSegment(idx) => {
+                write!(f, "unknown data segment {}", idx)
+            }
+            ValidationErrorKind::UnknownElemSegment(idx) => {
+                write!(f, "unknown elem segment {}", idx)
+            }
+            ValidationErrorKind::MissingDataCount => write!(f, "data count section required"),
+            ValidationErrorKind::InvalidAlignment => {
+                write!(f, "alignment must not be larger than natural")
+            }
+            ValidationErrorKind::InvalidResultArity => write!(f, "invalid result arity"),
+            ValidationErrorKind::UnbalancedBlock => write!(f, "unbalanced block structure"),
+            ValidationErrorKind::DisabledFeature(feature) => write!(f, "{}", feature),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid function {} at instruction {}: {}",
+            self.func_index, self.inst_index, self.kind
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+fn type_name(ty: &ValType) -> String {
+    match ty {
+        ValType::Ref(r) if r.is_func_ref() => "funcref".to_string(),
+        ValType::Ref(r) if r.is_extern_ref() => "externref".to_string(),
+        ty => ty.to_string(),
+    }
+}
+
+type ValidationResult<T> = Result<T, ValidationErrorKind>;
+
+/// The index spaces a function body can refer to, imports come first.
+struct ModuleContext<'m> {
+    sigs: Vec<&'m FuncType>,
+    funcs: Vec<&'m FuncType>,
+    tables: Vec<TableType>,
+    mems: Vec<MemoryType>,
+    globals: Vec<GlobalType>,
+    tags: Vec<&'m FuncType>,
+    elem_types: Vec<RefType>,
+    data_count: Option<u32>,
+}
+
+impl<'m> ModuleContext<'m> {
+    fn new(module: &'m WasmModule) -> Self {
+        let mut sigs = vec![];
+        let mut index = 0;
+        while let Some(sig) = module.get_sig(index) {
+            sigs.push(sig);
+            index += 1;
+        }
+
+        // the module's tables, memories and globals start with the imported
+        // ones
+        let tables = module.get_tables().iter().map(|t| t.ty).collect();
+        let mems = module.get_memories().clone();
+        let globals = module.get_globals().iter().map(|g| *g.get_ty()).collect();
+
+        let mut tags = vec![];
+        let mut index = 0;
+        while let Some(sig) = module.get_tag_sig(index) {
+            tags.push(sig);
+            index += 1;
+        }
+
+        let elem_types = module
+            .get_elems()
+            .iter()
+            .map(|elem| match &elem.items {
+                wasmparser::ElementItems::Functions(_) => RefType::FUNCREF,
+                wasmparser::ElementItems::Expressions(ty, _) => *ty,
+            })
+            .collect();
+
+        Self {
+            sigs,
+            funcs: module.get_funcs().iter().map(|f| f.get_sig()).collect(),
+            tables,
+            mems,
+            globals,
+            tags,
+            elem_types,
+            data_count: module.get_data_count(),
+        }
+    }
+
+    fn block_type(&self, ty: &BlockType) -> ValidationResult<(Vec<ValType>, Vec<ValType>)> {
+        match ty {
+            BlockType::Empty => Ok((vec![], vec![])),
+            BlockType::Type(ty) => Ok((vec![], vec![*ty])),
+            BlockType::FuncType(idx) => {
+                let sig = self.sig(*idx)?;
+                Ok((sig.params().to_vec(), sig.results().to_vec()))
+            }
+        }
+    }
+
+    fn sig(&self, idx: u32) -> ValidationResult<&'m FuncType> {
+        self.sigs
+            .get(idx as usize)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownType(idx))
+    }
+
+    fn func(&self, idx: u32) -> ValidationResult<&'m FuncType> {
+        self.funcs
+            .get(idx as usize)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownFunction(idx))
+    }
+
+    fn table(&self, idx: u32) -> ValidationResult<&TableType> {
+        self.tables
+            .get(idx as usize)
+            .ok_or(ValidationErrorKind::UnknownTable(idx))
+    }
+
+    fn global(&self, idx: u32) -> ValidationResult<&GlobalType> {
+        self.globals
+            .get(idx as usize)
+            .ok_or(ValidationErrorKind::UnknownGlobal(idx))
+    }
+
+    fn tag(&self, idx: u32) -> ValidationResult<&'m FuncType> {
+        self.tags
+            .get(idx as usize)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownTag(idx))
+    }
+
+    fn elem_type(&self, idx: u32) -> ValidationResult<RefType> {
+        self.elem_types
+            .get(idx as usize)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownElemSegment(idx))
+    }
+
+    fn check_data(&self, idx: u32) -> ValidationResult<()> {
+        match self.data_count {
+            None => Err(ValidationErrorKind::MissingDataCount),
+            Some(count) if idx >= count => Err(ValidationErrorKind::UnknownDataSegment(idx)),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// the type of addresses into memory `idx`
+    fn addr_type(&self, idx: u32) -> ValidationResult<ValType> {
+        let mem = self
+            .mems
+            .get(idx as usize)
+            .ok_or(ValidationErrorKind::UnknownMemory(idx))?;
+        Ok(if mem.memory64 {
+            ValType::I64
+        } else {
+            ValType::I32
+        })
+    }
+
+    /// check the alignment of an access of `natural` bytes and return the
+    /// address type of its memory
+    fn memarg(&self, memarg: &MemArg, natural: u32) -> ValidationResult<ValType> {
+        let addr_type = self.addr_type(memarg.memory)?;
+        if memarg.align >= 32 || 1u32 << memarg.align > natural {
+            return Err(ValidationErrorKind::InvalidAlignment);
+        }
+        Ok(addr_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameKind {
+    Block,
+    Loop,
+    If,
+    Else,
+    Try,
+    Catch,
+    CatchAll,
+}
+
+struct ControlFrame {
+    kind: FrameKind,
+    start_types: Vec<ValType>,
+    end_types: Vec<ValType>,
+    /// the operand stack height when the block was entered
+    height: usize,
+    unreachable: bool,
+}
+
+impl ControlFrame {
+    /// the values a branch to this frame takes
+    fn label_types(&self) -> &[ValType] {
+        if self.kind == FrameKind::Loop {
+            &self.start_types
+        } else {
+            &self.end_types
+        }
+    }
+}
+
+/// Type checks one function body, `None` on the operand stack is a value of
+/// unknown type, which only appears in unreachable code.
+struct FuncValidator<'c, 'm> {
+    ctx: &'c ModuleContext<'m>,
+    locals: Vec<ValType>,
+    results: Vec<ValType>,
+    vals: Vec<Option<ValType>>,
+    ctrls: Vec<ControlFrame>,
+}
+
+impl<'c, 'm> FuncValidator<'c, 'm> {
+    fn new(ctx: &'c ModuleContext<'m>, func: &FuncDecl) -> Self {
+        let sig = func.get_sig();
+        let mut locals = sig.params().to_vec();
+        locals.extend(func.get_pure_locals());
+
+        let mut validator = Self {
+            ctx,
+            locals,
+            results: sig.results().to_vec(),
+            vals: vec![],
+            ctrls: vec![],
+        };
+        validator.push_ctrl(FrameKind::Block, vec![], sig.results().to_vec());
+        validator
+    }
+
+    fn push_val(&mut self, ty: ValType) {
+        self.vals.push(Some(ty));
+    }
+
+    fn push_vals(&mut self, tys: &[ValType]) {
+        for ty in tys {
+            self.push_val(*ty);
+        }
+    }
+
+    fn pop_val(&mut self) -> ValidationResult<Option<ValType>> {
+        let frame = self.ctrls.last().unwrap();
+        if self.vals.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err(ValidationErrorKind::TypeMismatch {
+                expected: None,
+                found: None,
+            });
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: ValType) -> ValidationResult<Option<ValType>> {
+        let frame = self.ctrls.last().unwrap();
+        if self.vals.len() == frame.height && !frame.unreachable {
+            return Err(ValidationErrorKind::TypeMismatch {
+                expected: Some(expected),
+                found: None,
+            });
+        }
+        match self.pop_val()? {
+            Some(actual) if actual != expected => Err(ValidationErrorKind::TypeMismatch {
+                expected: Some(expected),
+                found: Some(actual),
+            }),
+            _ => Ok(Some(expected)),
+        }
+    }
+
+    fn pop_vals(&mut self, tys: &[ValType]) -> ValidationResult<()> {
+        for ty in tys.iter().rev() {
+            self.pop_expect(*ty)?;
+        }
+        Ok(())
+    }
+
+    fn pop_ref(&mut self) -> ValidationResult<()> {
+        match self.pop_val()? {
+            Some(
+                actual
+                @ (ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64 | ValType::V128),
+            ) => Err(ValidationErrorKind::TypeMismatch {
+                expected: Some(ValType::FUNCREF),
+                found: Some(actual),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn push_ctrl(&mut self, kind: FrameKind, start_types: Vec<ValType>, end_types: Vec<ValType>) {
+        let height = self.vals.len();
+        self.push_vals(&start_types);
+        self.ctrls.push(ControlFrame {
+            kind,
+            start_types,
+            end_types,
+            height,
+            unreachable: false,
+        });
+    }
+
+    fn pop_ctrl(&mut self) -> ValidationResult<ControlFrame> {
+        let end_types = self
+            .ctrls
+            .last()
+            .ok_or(ValidationErrorKind::UnbalancedBlock)?
+            .end_types
+            .clone();
+        self.pop_vals(&end_types)?;
+        let frame = self.ctrls.pop().unwrap();
+        if self.vals.len() != frame.height {
+            return Err(ValidationErrorKind::ExtraValues {
+                expected: end_types.len(),
+                found: end_types.len() + self.vals.len() - frame.height,
+            });
+        }
+        Ok(frame)
+    }
+
+    fn label(&self, rel_depth: u32) -> ValidationResult<&ControlFrame> {
+        let depth = rel_depth as usize;
+        if depth >= self.ctrls.len() {
+            return Err(ValidationErrorKind::UnknownLabel(rel_depth));
+        }
+        Ok(&self.ctrls[self.ctrls.len() - 1 - depth])
+    }
+
+    fn label_types(&self, rel_depth: u32) -> ValidationResult<Vec<ValType>> {
+        Ok(self.label(rel_depth)?.label_types().to_vec())
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn local(&self, idx: u32) -> ValidationResult<ValType> {
+        self.locals
+            .get(idx as usize)
+            .copied()
+            .ok_or(ValidationErrorKind::UnknownLocal(idx))
+    }
+
+    /// pop `params` and push `results`
+    fn op(&mut self, params: &[ValType], results: &[ValType]) -> ValidationResult<()> {
+        self.pop_vals(params)?;
+        self.push_vals(results);
+        Ok(())
+    }
+
+    /// Returns the height of the operand stack before each instruction.
+    fn validate(mut self, insts: &[Instruction]) -> Result<Vec<u32>, (usize, ValidationErrorKind)> {
+        let mut heights = Vec::with_capacity(insts.len());
+        for (i, inst) in insts.iter().enumerate() {
+            if self.ctrls.is_empty() {
+                return Err((i, ValidationErrorKind::UnbalancedBlock));
+            }
+            heights.push(self.vals.len() as u32);
+            self.validate_inst(inst).map_err(|kind| (i, kind))?;
+        }
+        if !self.ctrls.is_empty() {
+            return Err((insts.len(), ValidationErrorKind::UnbalancedBlock));
+        }
+        Ok(heights)
+    }
+
+    fn validate_inst(&mut self, inst: &Instruction) -> ValidationResult<()> {
+        use ValType::*;
+
+        match inst {
+            Instruction::Unreachable => self.set_unreachable(),
+            Instruction::Nop => {}
+            Instruction::Block { ty } | Instruction::Loop { ty } | Instruction::Try { ty } => {
+                let (params, results) = self.ctx.block_type(ty)?;
+                self.pop_vals(&params)?;
+                let kind = match inst {
+                    Instruction::Block { .. } => FrameKind::Block,
+                    Instruction::Loop { .. } => FrameKind::Loop,
+                    _ => FrameKind::Try,
+                };
+                self.push_ctrl(kind, params, results);
+            }
+            Instruction::If { ty } => {
+                let (params, results) = self.ctx.block_type(ty)?;
+                self.pop_expect(I32)?;
+                self.pop_vals(&params)?;
+                self.push_ctrl(FrameKind::If, params, results);
+            }
+            Instruction::Else => {
+                if self.ctrls.last().map(|f| f.kind) != Some(FrameKind::If) {
+                    return Err(ValidationErrorKind::UnbalancedBlock);
+                }
+                let frame = self.pop_ctrl()?;
+                self.push_ctrl(FrameKind::Else, frame.start_types, frame.end_types);
+            }
+            Instruction::Catch { tag_index } => {
+                let tag = self.ctx.tag(*tag_index)?;
+                let frame = self.pop_try_or_catch()?;
+                self.push_ctrl(FrameKind::Catch, tag.params().to_vec(), frame.end_types);
+            }
+            Instruction::CatchAll => {
+                let frame = self.pop_try_or_catch()?;
+                self.push_ctrl(FrameKind::CatchAll, vec![], frame.end_types);
+            }
+            Instruction::End => {
+                let frame = self.pop_ctrl()?;
+                if frame.kind == FrameKind::If && frame.start_types != frame.end_types {
+                    return Err(ValidationErrorKind::MissingElse);
+                }
+                self.push_vals(&frame.end_types);
+            }
+            Instruction::Delegate { rel_depth } => {
+                if self.ctrls.last().map(|f| f.kind) != Some(FrameKind::Try) {
+                    return Err(ValidationErrorKind::UnbalancedBlock);
+                }
+                let frame = self.pop_ctrl()?;
+                self.label(*rel_depth)?;
+                self.push_vals(&frame.end_types);
+            }
+            Instruction::Throw { tag_index } => {
+                let tag = self.ctx.tag(*tag_index)?;
+                self.pop_vals(tag.params())?;
+                self.set_unreachable();
+            }
+            Instruction::Rethrow { rel_depth } => {
+                let kind = self.label(*rel_depth)?.kind;
+                if !matches!(kind, FrameKind::Catch | FrameKind::CatchAll) {
+                    return Err(ValidationErrorKind::UnknownLabel(*rel_depth));
+                }
+                self.set_unreachable();
+            }
+            Instruction::Br { rel_depth } => {
+                let tys = self.label_types(*rel_depth)?;
+                self.pop_vals(&tys)?;
+                self.set_unreachable();
+            }
+            Instruction::BrIf { rel_depth } => {
+                self.pop_expect(I32)?;
+                let tys = self.label_types(*rel_depth)?;
+                self.op(&tys, &tys)?;
+            }
+            Instruction::BrTable { table } => {
+                self.pop_expect(I32)?;
+                let default_tys = self.label_types(table.default_target)?;
+                for target in &table.targets {
+                    let tys = self.label_types(*target)?;
+                    if tys.len() != default_tys.len() {
+                        return Err(ValidationErrorKind::BranchArityMismatch);
+                    }
+                    // every target checks the operands without consuming them
+                    let saved = self.vals.clone();
+                    self.pop_vals(&tys)?;
+                    self.vals = saved;
+                }
+                self.pop_vals(&default_tys)?;
+                self.set_unreachable();
+            }
+            Instruction::Return => {
+                let results = self.results.clone();
+                self.pop_vals(&results)?;
+                self.set_unreachable();
+            }
+            Instruction::Call { func_idx } => {
+                let sig = self.ctx.func(*func_idx)?;
+                self.op(sig.params(), sig.results())?;
+            }
+            Instruction::CallIndirect {
+                type_index,
+                table_index,
+            } => {
+                let sig = self.call_indirect_sig(*type_index, *table_index)?;
+                self.pop_expect(I32)?;
+                self.op(sig.params(), sig.results())?;
+            }
+            Instruction::ReturnCall { func_idx } => {
+                let sig = self.ctx.func(*func_idx)?;
+                self.return_call(sig)?;
+            }
+            Instruction::ReturnCallIndirect {
+                type_index,
+                table_index,
+            } => {
+                let sig = self.call_indirect_sig(*type_index, *table_index)?;
+                self.pop_expect(I32)?;
+                self.return_call(sig)?;
+            }
+            Instruction::Drop => {
+                self.pop_val()?;
+            }
+            Instruction::Select => {
+                self.pop_expect(I32)?;
+                let t1 = self.pop_val()?;
+                let t2 = self.pop_val()?;
+                let is_num = |t: Option<ValType>| matches!(t, None | Some(I32 | I64 | F32 | F64));
+                if !is_num(t1) || !is_num(t2) {
+                    return Err(ValidationErrorKind::InvalidSelect);
+                }
+                if let (Some(t1), Some(t2)) = (t1, t2) {
+                    if t1 != t2 {
+                        return Err(ValidationErrorKind::TypeMismatch {
+                            expected: Some(t1),
+                            found: Some(t2),
+                        });
+                    }
+                }
+                self.vals.push(t1.or(t2));
+            }
+            Instruction::TypedSelect { tys } => {
+                if tys.len() != 1 {
+                    return Err(ValidationErrorKind::InvalidResultArity);
+                }
+                self.pop_expect(I32)?;
+                self.op(&[tys[0], tys[0]], &[tys[0]])?;
+            }
+            Instruction::LocalGet { local_idx } => {
+                let ty = self.local(*local_idx)?;
+                self.push_val(ty);
+            }
+            Instruction::LocalSet { local_idx } => {
+                let ty = self.local(*local_idx)?;
+                self.pop_expect(ty)?;
+            }
+            Instruction::LocalTee { local_idx } => {
+                let ty = self.local(*local_idx)?;
+                self.op(&[ty], &[ty])?;
+            }
+            Instruction::GlobalGet { global_idx } => {
+                let ty = self.ctx.global(*global_idx)?.content_type;
+                self.push_val(ty);
+            }
+            Instruction::GlobalSet { global_idx } => {
+                let global = self.ctx.global(*global_idx)?;
+                if !global.mutable {
+                    return Err(ValidationErrorKind::ImmutableGlobal(*global_idx));
+                }
+                let ty = global.content_type;
+                self.pop_expect(ty)?;
+            }
+            Instruction::TableGet { table } => {
+                let ty = ValType::Ref(self.ctx.table(*table)?.element_type);
+                self.op(&[I32], &[ty])?;
+            }
+            Instruction::TableSet { table } => {
+                let ty = ValType::Ref(self.ctx.table(*table)?.element_type);
+                self.op(&[I32, ty], &[])?;
+            }
+            Instruction::TableInit { elem_index, table } => {
+                let table_ty = self.ctx.table(*table)?.element_type;
+                let elem_ty = self.ctx.elem_type(*elem_index)?;
+                if table_ty != elem_ty {
+                    return Err(ValidationErrorKind::TypeMismatch {
+                        expected: Some(ValType::Ref(table_ty)),
+                        found: Some(ValType::Ref(elem_ty)),
+                    });
+                }
+                self.op(&[I32, I32, I32], &[])?;
+            }
+            Instruction::ElemDrop { elem_index } => {
+                self.ctx.elem_type(*elem_index)?;
+            }
+            Instruction::TableGrow { table } => {
+                let ty = ValType::Ref(self.ctx.table(*table)?.element_type);
+                self.op(&[ty, I32], &[I32])?;
+            }
+            Instruction::TableSize { table } => {
+                self.ctx.table(*table)?;
+                self.push_val(I32);
+            }
+            Instruction::TableFill { table } => {
+                let ty = ValType::Ref(self.ctx.table(*table)?.element_type);
+                self.op(&[I32, ty, I32], &[])?;
+            }
+            Instruction::RefNull { ty } => self.push_val(ValType::Ref(*ty)),
+            Instruction::RefIsNull => {
+                self.pop_ref()?;
+                self.push_val(I32);
+            }
+            Instruction::RefFunc { func_idx } => {
+                self.ctx.func(*func_idx)?;
+                self.push_val(ValType::FUNCREF);
+            }
+            Instruction::I32Load { memarg } => self.load(memarg, 4, I32)?,
+            Instruction::I64Load { memarg } => self.load(memarg, 8, I64)?,
+            Instruction::F32Load { memarg } => self.load(memarg, 4, F32)?,
+            Instruction::F64Load { memarg } => self.load(memarg, 8, F64)?,
+            Instruction::I32Load8S { memarg } | Instruction::I32Load8U { memarg } => {
+                self.load(memarg, 1, I32)?
+            }
+            Instruction::I32Load16S { memarg } | Instruction::I32Load16U { memarg } => {
+                self.load(memarg, 2, I32)?
+            }
+            Instruction::I64Load8S { memarg } | Instruction::I64Load8U { memarg } => {
+                self.load(memarg, 1, I64)?
+            }
+            Instruction::I64Load16S { memarg } | Instruction::I64Load16U { memarg } => {
+                self.load(memarg, 2, I64)?
+            }
+            Instruction::I64Load32S { memarg } | Instruction::I64Load32U { memarg } => {
+                self.load(memarg, 4, I64)?
+            }
+            Instruction::I32Store { memarg } => self.store(memarg, 4, I32)?,
+            Instruction::I64Store { memarg } => self.store(memarg, 8, I64)?,
+            Instruction::F32Store { memarg } => self.store(memarg, 4, F32)?,
+            Instruction::F64Store { memarg } => self.store(memarg, 8, F64)?,
+            Instruction::I32Store8 { memarg } => self.store(memarg, 1, I32)?,
+            Instruction::I32Store16 { memarg } => self.store(memarg, 2, I32)?,
+            Instruction::I64Store8 { memarg } => self.store(memarg, 1, I64)?,
+            Instruction::I64Store16 { memarg } => self.store(memarg, 2, I64)?,
+            Instruction::I64Store32 { memarg } => self.store(memarg, 4, I64)?,
+            Instruction::MemorySize { mem } => {
+                let addr = self.ctx.addr_type(*mem)?;
+                self.push_val(addr);
+            }
+            Instruction::MemoryGrow { mem } => {
+                let addr = self.ctx.addr_type(*mem)?;
+                self.op(&[addr], &[addr])?;
+            }
+            Instruction::MemoryCopy { dst_mem, src_mem } => {
+                let dst = self.ctx.addr_type(*dst_mem)?;
+                let src = self.ctx.addr_type(*src_mem)?;
+                // the length is only 64-bit if both memories are
+                let len = if dst == I64 && src == I64 { I64 } else { I32 };
+                self.op(&[dst, src, len], &[])?;
+            }
+            Instruction::MemoryFill { mem } => {
+                let addr = self.ctx.addr_type(*mem)?;
+                self.op(&[addr, I32, addr], &[])?;
+            }
+            Instruction::MemoryInit { data_index, mem } => {
+                let addr = self.ctx.addr_type(*mem)?;
+                self.ctx.check_data(*data_index)?;
+                self.op(&[addr, I32, I32], &[])?;
+            }
+            Instruction::DataDrop { data_index } => self.ctx.check_data(*data_index)?,
+            Instruction::I32Const { .. } => self.push_val(I32),
+            Instruction::I64Const { .. } => self.push_val(I64),
+            Instruction::F32Const { .. } => self.push_val(F32),
+            Instruction::F64Const { .. } => self.push_val(F64),
+            Instruction::I32Unop(op) => self.op(&[I32], &[i32_unop_result(op)])?,
+            Instruction::I32Binop(_) => self.op(&[I32, I32], &[I32])?,
+            Instruction::I64Unop(op) => self.op(&[I64], &[i64_unop_result(op)])?,
+            Instruction::I64Binop(op) => self.op(&[I64, I64], &[i64_binop_result(op)])?,
+            Instruction::F32Unop(op) => self.op(&[F32], &[f32_unop_result(op)])?,
+            Instruction::F32Binop(op) => self.op(&[F32, F32], &[f32_binop_result(op)])?,
+            Instruction::F64Unop(op) => self.op(&[F64], &[f64_unop_result(op)])?,
+            Instruction::F64Binop(op) => self.op(&[F64, F64], &[f64_binop_result(op)])?,
+            Instruction::V128Load { memarg } => self.load(memarg, 16, V128)?,
+            Instruction::V128Store { memarg } => self.store(memarg, 16, V128)?,
+            Instruction::V128LoadExtend { memarg, .. } => self.load(memarg, 8, V128)?,
+            Instruction::V128LoadSplat { memarg, shape }
+            | Instruction::V128LoadZero { memarg, shape } => {
+                self.load(memarg, shape.lane_bits() / 8, V128)?
+            }
+            Instruction::V128LoadLane { memarg, shape, .. } => {
+                let addr = self.ctx.memarg(memarg, shape.lane_bits() / 8)?;
+                self.op(&[addr, V128], &[V128])?
+            }
+            Instruction::V128StoreLane { memarg, shape, .. } => {
+                self.store(memarg, shape.lane_bits() / 8, V128)?
+            }
+            Instruction::V128Const { .. } => self.push_val(V128),
+            Instruction::I8x16Shuffle { .. } => self.op(&[V128, V128], &[V128])?,
+            Instruction::V128Splat { shape } => self.op(&[lane_type(shape)], &[V128])?,
+            Instruction::V128ExtractLane { shape, .. } => self.op(&[V128], &[lane_type(shape)])?,
+            Instruction::V128ReplaceLane { shape, .. } => {
+                self.op(&[V128, lane_type(shape)], &[V128])?
+            }
+            Instruction::V128Bitselect => self.op(&[V128, V128, V128], &[V128])?,
+            Instruction::V128Unop(_) => self.op(&[V128], &[V128])?,
+            Instruction::V128Binop(_) => self.op(&[V128, V128], &[V128])?,
+            Instruction::V128Shift(_) => self.op(&[V128, I32], &[V128])?,
+            Instruction::V128Test(_) => self.op(&[V128], &[I32])?,
+        }
+        Ok(())
+    }
+
+    /// `catch` and `catch_all` end the previous clause of the same try
+    fn pop_try_or_catch(&mut self) -> ValidationResult<ControlFrame> {
+        match self.ctrls.last().map(|f| f.kind) {
+            Some(FrameKind::Try | FrameKind::Catch) => self.pop_ctrl(),
+            _ => Err(ValidationErrorKind::UnbalancedBlock),
+        }
+    }
+
+    fn call_indirect_sig(
+        &self,
+        type_index: u32,
+        table_index: u32,
+    ) -> ValidationResult<&'m FuncType> {
+        let table = self.ctx.table(table_index)?;
+        if !table.element_type.is_func_ref() {
+            return Err(ValidationErrorKind::TypeMismatch {
+                expected: Some(ValType::FUNCREF),
+                found: Some(ValType::Ref(table.element_type)),
+            });
+        }
+        self.ctx.sig(type_index)
+    }
+
+    /// a tail call returns the results of the callee as the caller's own
+    fn return_call(&mut self, sig: &FuncType) -> ValidationResult<()> {
+        if sig.results() != self.results.as_slice() {
+            return Err(ValidationErrorKind::TypeMismatch {
+                expected: self.results.first().copied(),
+                found: sig.results().first().copied(),
+            });
+        }
+        self.pop_vals(sig.params())?;
+        self.set_unreachable();
+        Ok(())
+    }
+
+    fn load(&mut self, memarg: &MemArg, natural: u32, ty: ValType) -> ValidationResult<()> {
+        let addr = self.ctx.memarg(memarg, natural)?;
+        self.op(&[addr], &[ty])
+    }
+
+    fn store(&mut self, memarg: &MemArg, natural: u32, ty: ValType) -> ValidationResult<()> {
+        let addr = self.ctx.memarg(memarg, natural)?;
+        self.op(&[addr, ty], &[])
+    }
+}
+
+fn lane_type(shape: &V128Shape) -> ValType {
+    match shape {
+        V128Shape::I8x16 | V128Shape::I16x8 | V128Shape::I32x4 => ValType::I32,
+        V128Shape::I64x2 => ValType::I64,
+        V128Shape::F32x4 => ValType::F32,
+        V128Shape::F64x2 => ValType::F64,
+    }
+}
+
+fn i32_unop_result(op: &I32Unop) -> ValType {
+    match op {
+        I32Unop::F64ConvertI32S | I32Unop::F64ConvertI32U => ValType::F64,
+        I32Unop::F32ConvertI32S | I32Unop::F32ConvertI32U | I32Unop::F32ReinterpretI32 => {
+            ValType::F32
+        }
+        I32Unop::I64ExtendI32S | I32Unop::I64ExtendI32U => ValType::I64,
+        _ => ValType::I32,
+    }
+}
+
+fn i64_unop_result(op: &I64Unop) -> ValType {
+    match op {
+        I64Unop::Eqz | I64Unop::I32WrapI64 => ValType::I32,
+        I64Unop::F32ConvertI64S | I64Unop::F32ConvertI64U => ValType::F32,
+        I64Unop::F64ConvertI64S | I64Unop::F64ConvertI64U | I64Unop::F64ReinterpretI64 => {
+            ValType::F64
+        }
+        _ => ValType::I64,
+    }
+}
+
+fn i64_binop_result(op: &I64Binop) -> ValType {
+    match op {
+        I64Binop::Eq
+        | I64Binop::Ne
+        | I64Binop::LtS
+        | I64Binop::LtU
+        | I64Binop::GtS
+        | I64Binop::GtU
+        | I64Binop::LeS
+        | I64Binop::LeU
+        | I64Binop::GeS
+        | I64Binop::GeU => ValType::I32,
+        _ => ValType::I64,
+    }
+}
+
+fn f32_unop_result(op: &F32Unop) -> ValType {
+    match op {
+        F32Unop::I32TruncF32S
+        | F32Unop::I32TruncF32U
+        | F32Unop::I32ReinterpretF32
+        | F32Unop::I32TruncSatF32S
+        | F32Unop::I32TruncSatF32U => ValType::I32,
+        F32Unop::I64TruncF32S
+        | F32Unop::I64TruncF32U
+        | F32Unop::I64TruncSatF32S
+        | F32Unop::I64TruncSatF32U => ValType::I64,
+        F32Unop::F64PromoteF32 => ValType::F64,
+        _ => ValType::F32,
+    }
+}
+
+fn f32_binop_result(op: &F32Binop) -> ValType {
+    match op {
+        F32Binop::Eq | F32Binop::Ne | F32Binop::Lt | F32Binop::Gt | F32Binop::Le | F32Binop::Ge => {
+            ValType::I32
+        }
+        _ => ValType::F32,
+    }
+}
+
+fn f64_unop_result(op: &F64Unop) -> ValType {
+    match op {
+        F64Unop::I32TruncF64S
+        | F64Unop::I32TruncF64U
+        | F64Unop::I32TruncSatF64S
+        | F64Unop::I32TruncSatF64U => ValType::I32,
+        F64Unop::I64TruncF64S
+        | F64Unop::I64TruncF64U
+        | F64Unop::I64ReinterpretF64
+        | F64Unop::I64TruncSatF64S
+        | F64Unop::I64TruncSatF64U => ValType::I64,
+        F64Unop::F32DemoteF64 => ValType::F32,
+        _ => ValType::F64,
+    }
+}
+
+fn f64_binop_result(op: &F64Binop) -> ValType {
+    match op {
+        F64Binop::Eq | F64Binop::Ne | F64Binop::Lt | F64Binop::Gt | F64Binop::Le | F64Binop::Ge => {
+            ValType::I32
+        }
+        _ => ValType::F64,
+    }
+}
+
+impl WasmModule<'_> {
+    /// Type check every function body of the module, imported functions
+    /// have no body and are skipped.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_stack_heights().map(|_| ())
+    }
+
+    /// Validate the module, returns the height of the operand stack before
+    /// each instruction of every function body.
+    pub(crate) fn validate_stack_heights(&self) -> Result<Vec<Vec<u32>>, ValidationError> {
+        let ctx = ModuleContext::new(self);
+        let num_imports = self.get_num_func_imports();
+        let funcs = self.get_funcs().iter().enumerate().skip(num_imports);
+        funcs
+            .map(|(func_index, func)| {
+                FuncValidator::new(&ctx, func)
+                    .validate(func.get_insts())
+                    .map_err(|(inst_index, kind)| ValidationError {
+                        func_index: func_index as u32,
+                        inst_index,
+                        kind,
+                    })
+            })
+            .collect()
+    }
+}
@@ -1,21 +1,73 @@
-use wasmparser::ValType;
+use wasmparser::{RefType, ValType};
+
+/// The canonical NaNs of the spec: positive, with only the most significant
+/// bit of the payload set.
+pub const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+pub const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// How floats are printed by `WasmValue::format_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// six decimal places, as printed by `Display`
+    #[default]
+    Fixed,
+    /// the shortest decimal that parses back to the same value, with the
+    /// `inf` and `nan:0x...` notation of the text format
+    Shortest,
+    /// the hexadecimal float literals of the text format, e.g. `0x1.8p+1`
+    Hex,
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum WasmValue {
     I32(i32),
+    I64(i64),
+    F32(f32),
     F64(f64),
+    /// A function reference, `None` is the null reference
+    FuncRef(Option<u32>),
+    /// An opaque host reference, `None` is the null reference
+    ExternRef(Option<u32>),
+    /// A 128-bit vector, lanes are stored in little endian order
+    V128(u128),
 }
 
 impl std::fmt::Display for WasmValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             WasmValue::I32(val) => write!(f, "{}", val),
+            WasmValue::I64(val) => write!(f, "{}", val),
+            WasmValue::F32(val) => write!(f, "{:.6}", val),
             WasmValue::F64(val) => write!(f, "{:.6}", val),
+            WasmValue::FuncRef(Some(idx)) | WasmValue::ExternRef(Some(idx)) => {
+                write!(f, "{}", idx)
+            }
+            WasmValue::FuncRef(None) | WasmValue::ExternRef(None) => write!(f, "null"),
+            WasmValue::V128(val) => write!(f, "0x{:032x}", val),
         }
     }
 }
 
 impl WasmValue {
+    /// Print the value like `Display`, with floats in the given format.
+    pub fn format_with(&self, float_format: FloatFormat) -> String {
+        match (self, float_format) {
+            (_, FloatFormat::Fixed) => self.to_string(),
+            (WasmValue::F32(val), FloatFormat::Shortest) => {
+                format_special_float(val.to_bits() as u64, 23, 8)
+                    .unwrap_or_else(|| format!("{:?}", val))
+            }
+            (WasmValue::F64(val), FloatFormat::Shortest) => {
+                format_special_float(val.to_bits(), 52, 11).unwrap_or_else(|| format!("{:?}", val))
+            }
+            (WasmValue::F32(val), FloatFormat::Hex) => {
+                format_hex_float(val.to_bits() as u64, 23, 8)
+            }
+            (WasmValue::F64(val), FloatFormat::Hex) => format_hex_float(val.to_bits(), 52, 11),
+            _ => self.to_string(),
+        }
+    }
+
     pub fn as_i32(&self) -> i32 {
         match self {
             WasmValue::I32(val) => *val,
@@ -23,6 +75,20 @@ impl WasmValue {
         }
     }
 
+    pub fn as_i64(&self) -> i64 {
+        match self {
+            WasmValue::I64(val) => *val,
+            _ => panic!("WasmValue is not I64"),
+        }
+    }
+
+    pub fn as_f32(&self) -> f32 {
+        match self {
+            WasmValue::F32(val) => *val,
+            _ => panic!("WasmValue is not F32"),
+        }
+    }
+
     pub fn as_f64(&self) -> f64 {
         match self {
             WasmValue::F64(val) => *val,
@@ -30,11 +96,286 @@ impl WasmValue {
         }
     }
 
+    pub fn as_v128(&self) -> u128 {
+        match self {
+            WasmValue::V128(val) => *val,
+            _ => panic!("WasmValue is not V128"),
+        }
+    }
+
+    pub fn as_ref(&self) -> Option<u32> {
+        match self {
+            WasmValue::FuncRef(val) | WasmValue::ExternRef(val) => *val,
+            _ => panic!("WasmValue is not a reference"),
+        }
+    }
+
+    pub fn is_ref(&self) -> bool {
+        matches!(self, WasmValue::FuncRef(_) | WasmValue::ExternRef(_))
+    }
+
+    /// Whether the value can be stored in a slot of the given type.
+    pub fn has_type(&self, value_type: &ValType) -> bool {
+        match (self, value_type) {
+            (WasmValue::I32(_), ValType::I32)
+            | (WasmValue::I64(_), ValType::I64)
+            | (WasmValue::F32(_), ValType::F32)
+            | (WasmValue::F64(_), ValType::F64)
+            | (WasmValue::V128(_), ValType::V128) => true,
+            (WasmValue::FuncRef(_), ValType::Ref(ref_type)) => !ref_type.is_extern_ref(),
+            (WasmValue::ExternRef(_), ValType::Ref(ref_type)) => ref_type.is_extern_ref(),
+            _ => false,
+        }
+    }
+
+    /// The null reference of the given reference type.
+    pub fn null_ref(ref_type: &RefType) -> WasmValue {
+        if ref_type.is_extern_ref() {
+            WasmValue::ExternRef(None)
+        } else {
+            WasmValue::FuncRef(None)
+        }
+    }
+
     pub fn default_value(value_type: &ValType) -> WasmValue {
         match value_type {
             ValType::I32 => WasmValue::I32(0),
+            ValType::I64 => WasmValue::I64(0),
+            ValType::F32 => WasmValue::F32(0.0),
             ValType::F64 => WasmValue::F64(0.0),
+            ValType::V128 => WasmValue::V128(0),
+            ValType::Ref(ref_type) => WasmValue::null_ref(ref_type),
+        }
+    }
+
+    /// Decode a raw 64-bit slot (as used by the JIT) into a typed value.
+    ///
+    /// References are stored as `index + 1`, so that 0 is the null reference
+    /// and zero initialized slots hold null.
+    pub fn from_raw(value_type: &ValType, raw: u64) -> WasmValue {
+        match value_type {
+            ValType::I32 => WasmValue::I32(raw as i32),
+            ValType::I64 => WasmValue::I64(raw as i64),
+            ValType::F32 => WasmValue::F32(f32::from_bits(raw as u32)),
+            ValType::F64 => WasmValue::F64(f64::from_bits(raw)),
+            ValType::Ref(ref_type) => {
+                let idx = (raw as u32).checked_sub(1);
+                if ref_type.is_extern_ref() {
+                    WasmValue::ExternRef(idx)
+                } else {
+                    WasmValue::FuncRef(idx)
+                }
+            }
             _ => panic!("Unsupported value type"),
         }
     }
+
+    /// Replace a NaN float by the canonical NaN of its type, other values are
+    /// returned as they are.
+    pub fn canonicalize_nan(self) -> WasmValue {
+        match self {
+            WasmValue::F32(v) if v.is_nan() => WasmValue::F32(f32::from_bits(CANONICAL_NAN_F32)),
+            WasmValue::F64(v) if v.is_nan() => WasmValue::F64(f64::from_bits(CANONICAL_NAN_F64)),
+            v => v,
+        }
+    }
+
+    /// Encode the value into a raw 64-bit slot (as used by the JIT).
+    pub fn to_raw(&self) -> u64 {
+        match self {
+            WasmValue::I32(val) => *val as u64,
+            WasmValue::I64(val) => *val as u64,
+            WasmValue::F32(val) => val.to_bits() as u64,
+            WasmValue::F64(val) => val.to_bits(),
+            WasmValue::FuncRef(val) | WasmValue::ExternRef(val) => ref_to_raw(*val) as u64,
+            WasmValue::V128(_) => panic!("v128 does not fit in a raw slot"),
+        }
+    }
+}
+
+/// Encode a reference into a table slot, see `WasmValue::from_raw`.
+pub(crate) fn ref_to_raw(idx: Option<u32>) -> u32 {
+    idx.map_or(0, |idx| idx + 1)
+}
+
+/// f32.min, NaN if either operand is NaN and -0 is smaller than +0.
+pub(crate) fn f32_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        f32::NAN
+    } else if a == b {
+        f32::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+/// f32.max, NaN if either operand is NaN and +0 is larger than -0.
+pub(crate) fn f32_max(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {
+        f32::NAN
+    } else if a == b {
+        f32::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+/// f64.min, NaN if either operand is NaN and -0 is smaller than +0.
+pub(crate) fn f64_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+/// f64.max, NaN if either operand is NaN and +0 is larger than -0.
+pub(crate) fn f64_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+/// `inf` and `nan` in the notation of the text format, `nan` alone is the
+/// canonical NaN. `None` for finite floats.
+fn format_special_float(bits: u64, mant_width: u32, exp_width: u32) -> Option<String> {
+    let sign = if bits >> (mant_width + exp_width) & 1 == 1 {
+        "-"
+    } else {
+        ""
+    };
+    let exp_max = (1 << exp_width) - 1;
+    let exp = (bits >> mant_width) & exp_max;
+    let mant = bits & ((1 << mant_width) - 1);
+    if exp != exp_max {
+        None
+    } else if mant == 0 {
+        Some(format!("{}inf", sign))
+    } else if mant == 1 << (mant_width - 1) {
+        Some(format!("{}nan", sign))
+    } else {
+        Some(format!("{}nan:0x{:x}", sign, mant))
+    }
+}
+
+/// A float with `mant_width` mantissa and `exp_width` exponent bits as a
+/// hexadecimal float literal, subnormals are normalized.
+fn format_hex_float(bits: u64, mant_width: u32, exp_width: u32) -> String {
+    if let Some(special) = format_special_float(bits, mant_width, exp_width) {
+        return special;
+    }
+    let sign = if bits >> (mant_width + exp_width) & 1 == 1 {
+        "-"
+    } else {
+        ""
+    };
+    let bias = (1i64 << (exp_width - 1)) - 1;
+    let mut exp = ((bits >> mant_width) & ((1 << exp_width) - 1)) as i64;
+    let mut mant = bits & ((1 << mant_width) - 1);
+    if exp == 0 {
+        if mant == 0 {
+            return format!("{}0x0p+0", sign);
+        }
+        // shift the leading one of the subnormal to the implicit bit
+        exp = 1;
+        while mant & (1 << mant_width) == 0 {
+            mant <<= 1;
+            exp -= 1;
+        }
+        mant &= (1 << mant_width) - 1;
+    }
+    let exp = exp - bias;
+
+    // pad the fraction to whole hex digits
+    let pad = (4 - mant_width % 4) % 4;
+    let digits = ((mant_width + pad) / 4) as usize;
+    let fraction = format!("{:0digits$x}", mant << pad, digits = digits);
+    let fraction = fraction.trim_end_matches('0');
+    let exp_sign = if exp < 0 { "-" } else { "+" };
+    if fraction.is_empty() {
+        format!("{}0x1p{}{}", sign, exp_sign, exp.abs())
+    } else {
+        format!("{}0x1.{}p{}{}", sign, fraction, exp_sign, exp.abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FloatFormat, WasmValue};
+
+    fn f32_bits(bits: u32) -> WasmValue {
+        WasmValue::F32(f32::from_bits(bits))
+    }
+
+    fn f64_bits(bits: u64) -> WasmValue {
+        WasmValue::F64(f64::from_bits(bits))
+    }
+
+    #[test]
+    fn hex_normal_floats() {
+        let hex = |value: WasmValue| value.format_with(FloatFormat::Hex);
+        assert_eq!(hex(WasmValue::F32(1.5)), "0x1.8p+0");
+        assert_eq!(hex(WasmValue::F64(-1.5)), "-0x1.8p+0");
+        assert_eq!(hex(WasmValue::F32(0.1)), "0x1.99999ap-4");
+        assert_eq!(hex(WasmValue::F64(0.1)), "0x1.999999999999ap-4");
+        assert_eq!(hex(WasmValue::F32(f32::MAX)), "0x1.fffffep+127");
+        assert_eq!(hex(WasmValue::F64(f64::MAX)), "0x1.fffffffffffffp+1023");
+    }
+
+    #[test]
+    fn hex_subnormals_are_normalized() {
+        let hex = |value: WasmValue| value.format_with(FloatFormat::Hex);
+        assert_eq!(hex(f32_bits(1)), "0x1p-149");
+        assert_eq!(hex(f32_bits(0x0040_0000)), "0x1p-127");
+        assert_eq!(hex(f32_bits(0x007f_ffff)), "0x1.fffffcp-127");
+        assert_eq!(hex(f64_bits(1)), "0x1p-1074");
+        assert_eq!(hex(f64_bits(0x8008_0000_0000_0000)), "-0x1p-1023");
+    }
+
+    #[test]
+    fn hex_zeros_infinities_and_nans() {
+        let hex = |value: WasmValue| value.format_with(FloatFormat::Hex);
+        assert_eq!(hex(WasmValue::F32(0.0)), "0x0p+0");
+        assert_eq!(hex(WasmValue::F64(-0.0)), "-0x0p+0");
+        assert_eq!(hex(WasmValue::F32(f32::INFINITY)), "inf");
+        assert_eq!(hex(WasmValue::F64(f64::NEG_INFINITY)), "-inf");
+        assert_eq!(hex(f32_bits(0x7fc0_0000)), "nan");
+        assert_eq!(hex(f32_bits(0xffc0_0000)), "-nan");
+        assert_eq!(hex(f32_bits(0x7fa0_0001)), "nan:0x200001");
+        assert_eq!(hex(f64_bits(0x7ff8_0000_0000_0000)), "nan");
+        assert_eq!(hex(f64_bits(0x7ff0_0000_0000_0001)), "nan:0x1");
+    }
+
+    #[test]
+    fn shortest_floats() {
+        let shortest = |value: WasmValue| value.format_with(FloatFormat::Shortest);
+        assert_eq!(shortest(WasmValue::F32(0.1)), "0.1");
+        assert_eq!(
+            shortest(WasmValue::F64(0.1f32 as f64)),
+            "0.10000000149011612"
+        );
+        assert_eq!(shortest(WasmValue::F64(-0.0)), "-0.0");
+        assert_eq!(shortest(f32_bits(1)), "1e-45");
+        assert_eq!(shortest(f64_bits(1)), "5e-324");
+        assert_eq!(shortest(WasmValue::F32(f32::NEG_INFINITY)), "-inf");
+        assert_eq!(
+            shortest(f64_bits(0xfff8_0000_0000_0001)),
+            "-nan:0x8000000000001"
+        );
+    }
+
+    #[test]
+    fn fixed_floats_and_other_values() {
+        let fixed = |value: WasmValue| value.format_with(FloatFormat::Fixed);
+        assert_eq!(fixed(WasmValue::F32(0.1)), "0.100000");
+        assert_eq!(fixed(WasmValue::F64(-0.0)), "-0.000000");
+        assert_eq!(fixed(WasmValue::F64(f64::INFINITY)), "inf");
+        assert_eq!(WasmValue::I32(-7).format_with(FloatFormat::Hex), "-7");
+    }
 }
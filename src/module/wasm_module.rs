@@ -1,6 +1,13 @@
+use super::bytecode;
 use super::components::{FuncDecl, GlobalDecl, ImportSet};
-use anyhow::Result;
-use wasmparser::{Data, Element, Export, FuncType, MemoryType, Parser, Payload::*, Table};
+use super::dwarf::{DwarfSections, LineTable, SourceLocation};
+use super::features::Features;
+use super::parse::NameSection;
+use anyhow::{anyhow, Result};
+use wasmparser::{
+    BlockType, Data, Element, Export, ExternalKind, FuncType, KnownCustom, MemoryType, Parser,
+    Payload::*, Table, TagType,
+};
 
 #[derive(Default)]
 pub struct WasmModule<'a> {
@@ -10,12 +17,19 @@ pub struct WasmModule<'a> {
     tables: Vec<Table<'a>>,
     mems: Vec<MemoryType>,
     globals: Vec<GlobalDecl>,
+    tags: Vec<TagType>,
     exports: Vec<Export<'a>>,
     elems: Vec<Element<'a>>,
     datas: Vec<Data<'a>>,
 
     start_func_id: Option<u32>,
     data_count: Option<u32>,
+
+    names: NameSection<'a>,
+    /// source lines of the DWARF debug info, if the module has any
+    line_table: Option<LineTable>,
+    /// the binary the module was parsed from
+    bytes: &'a [u8],
 }
 
 impl<'a> WasmModule<'a> {
@@ -25,14 +39,32 @@ impl<'a> WasmModule<'a> {
         }
     }
 
+    /// Parse a module in the text format. The module borrows its bytecode,
+    /// the translated binary is kept in `binary`:
+    /// `let mut binary = vec![]; WasmModule::from_wat("(module)", &mut binary)`.
+    pub fn from_wat(wat: &str, binary: &'a mut Vec<u8>) -> Result<Self> {
+        *binary = wat::parse_str(wat)?;
+        Self::from_bytecode(binary)
+    }
+
+    /// Parse a binary module with the proposals enabled by default.
     pub fn from_bytecode(bytes: &'a [u8]) -> Result<Self> {
+        Self::from_bytecode_with_features(bytes, &Features::default())
+    }
+
+    /// Parse a binary module, fails if it uses a proposal `features`
+    /// disables.
+    pub fn from_bytecode_with_features(bytes: &'a [u8], features: &Features) -> Result<Self> {
         let parser = Parser::new(0);
         let payloads = parser.parse_all(bytes);
 
         let mut module = WasmModule::new();
+        module.bytes = bytes;
 
         let mut tot_func: u32 = 0;
         let mut n_func: u32 = 0;
+        let mut code_section_start = 0;
+        let mut dwarf = DwarfSections::default();
 
         for payload in payloads {
             match payload? {
@@ -45,28 +77,57 @@ impl<'a> WasmModule<'a> {
                     module.imports = Self::parse_import_section(iread)?;
                     for import in &module.imports.imports {
                         match import.ty {
-                            wasmparser::TypeRef::Func(ind) => module
-                                .funcs
-                                .push(FuncDecl::new(module.sigs[ind as usize].clone())),
-                            _ => todo!("import tag not yet implemented"),
+                            wasmparser::TypeRef::Func(ind) => {
+                                let sig = module.sigs.get(ind as usize).ok_or_else(|| {
+                                    anyhow!(
+                                        "import {}.{}: unknown type",
+                                        import.module,
+                                        import.name
+                                    )
+                                })?;
+                                module.funcs.push(FuncDecl::new(sig.clone()))
+                            }
+                            // the value is provided when the module is
+                            // instantiated
+                            wasmparser::TypeRef::Global(ty) => {
+                                module.globals.push(GlobalDecl::new(ty, vec![]))
+                            }
+                            wasmparser::TypeRef::Memory(ty) => module.mems.push(ty),
+                            // the elements are provided when the module is
+                            // instantiated
+                            wasmparser::TypeRef::Table(ty) => module.tables.push(Table {
+                                ty,
+                                init: wasmparser::TableInit::RefNull,
+                            }),
+                            _ => anyhow::bail!(
+                                "import {}.{}: importing tags is not supported",
+                                import.module,
+                                import.name
+                            ),
                         }
                     }
                 }
                 FunctionSection(fread) => {
-                    if module.funcs.len() != module.get_num_imports() {
+                    if module.funcs.len() != module.get_num_func_imports() {
                         anyhow::bail!("malformed func imports");
                     }
                     let funcs = Self::parse_function_section(fread, module.sigs.clone())?;
                     module.funcs.extend(funcs);
                 }
                 TableSection(tread) => {
-                    module.tables = Self::parse_table_section(tread)?;
+                    let tables = Self::parse_table_section(tread)?;
+                    module.tables.extend(tables);
                 }
                 MemorySection(memread) => {
-                    module.mems = Self::parse_memory_section(memread)?;
+                    let mems = Self::parse_memory_section(memread)?;
+                    module.mems.extend(mems);
+                }
+                TagSection(tagread) => {
+                    module.tags = Self::parse_tag_section(tagread)?;
                 }
                 GlobalSection(gread) => {
-                    module.globals = Self::parse_global_section(gread)?;
+                    let globals = Self::parse_global_section(gread)?;
+                    module.globals.extend(globals);
                 }
                 ExportSection(eread) => {
                     module.exports = Self::parse_export_section(eread)?;
@@ -81,27 +142,44 @@ impl<'a> WasmModule<'a> {
                 DataSection(dread) => {
                     module.datas = module.parse_data_section(dread)?;
                 }
-                CodeSectionStart { count, .. } => {
+                CodeSectionStart { count, range, .. } => {
                     tot_func = count;
+                    code_section_start = range.start;
                 }
                 CodeSectionEntry(body) => {
-                    let func_ind = n_func + module.get_num_imports() as u32;
-                    let func_ref = module.funcs.get_mut(func_ind as usize).unwrap();
-                    func_ref.add_func_body(Self::parse_code_section(body)?);
+                    let func_ind = n_func + module.get_num_func_imports() as u32;
+                    let func_ref = module
+                        .funcs
+                        .get_mut(func_ind as usize)
+                        .ok_or_else(|| anyhow!("code entry without a function declaration"))?;
+                    func_ref.add_func_body(Self::parse_code_section(body, code_section_start)?);
 
                     n_func += 1;
                 }
 
-                // === The following are not yet implemented ===
-                CustomSection(_) => { /* ... */ }
+                CustomSection(reader) => {
+                    // names are debug info, a malformed name section is
+                    // ignored like any other custom section
+                    match reader.as_known() {
+                        KnownCustom::Name(names) => match Self::parse_name_section(names) {
+                            Ok(names) => module.names = names,
+                            Err(e) => log::debug!("ignoring malformed name section: {}", e),
+                        },
+                        _ => match reader.name() {
+                            ".debug_line" => dwarf.debug_line = Some(reader.data()),
+                            ".debug_str" => dwarf.debug_str = Some(reader.data()),
+                            ".debug_line_str" => dwarf.debug_line_str = Some(reader.data()),
+                            _ => {}
+                        },
+                    }
+                }
 
-                // most likely you'd return an error here
-                UnknownSection { .. } => {
-                    panic!("Section id unknown");
+                // === The following are not yet implemented ===
+                UnknownSection { id, .. } => {
+                    anyhow::bail!("unknown section id {}", id);
                 }
 
                 // Sections for WebAssembly components
-                TagSection(_) => { /* ... */ }
                 ModuleSection { .. } => { /* ... */ }
                 InstanceSection(_) => { /* ... */ }
                 CoreTypeSection(_) => { /* ... */ }
@@ -125,6 +203,17 @@ impl<'a> WasmModule<'a> {
             anyhow::bail!("Function section size mismatch");
         }
 
+        if dwarf.debug_line.is_some() {
+            match LineTable::parse(&dwarf) {
+                Ok(line_table) => module.line_table = Some(line_table),
+                Err(e) => log::debug!("ignoring malformed DWARF line info: {}", e),
+            }
+        }
+
+        module.check_features(features)?;
+        let stack_heights = module.validate_stack_heights()?;
+        module.lower_funcs(&stack_heights);
+
         Ok(module)
     }
 
@@ -144,6 +233,25 @@ impl<'a> WasmModule<'a> {
         self.imports.get_num_imports()
     }
 
+    pub fn get_num_func_imports(&self) -> usize {
+        self.imports.num_funcs as usize
+    }
+
+    /// Imported tables come first in the table index space.
+    pub fn get_num_table_imports(&self) -> usize {
+        self.imports.num_tables as usize
+    }
+
+    /// Imported memories come first in the memory index space.
+    pub fn get_num_mem_imports(&self) -> usize {
+        self.imports.num_mems as usize
+    }
+
+    /// Imported globals come first in the global index space.
+    pub fn get_num_global_imports(&self) -> usize {
+        self.imports.num_globals as usize
+    }
+
     pub fn get_func(&self, index: u32) -> Option<&FuncDecl> {
         self.funcs.get(index as usize)
     }
@@ -172,6 +280,10 @@ impl<'a> WasmModule<'a> {
         self.mems.first()
     }
 
+    pub fn get_memories(&self) -> &Vec<MemoryType> {
+        &self.mems
+    }
+
     pub fn get_globals(&self) -> &Vec<GlobalDecl> {
         &self.globals
     }
@@ -180,14 +292,136 @@ impl<'a> WasmModule<'a> {
         &self.elems
     }
 
-    pub fn get_globals_mut(&mut self) -> &mut Vec<GlobalDecl> {
-        &mut self.globals
+    pub fn get_tags(&self) -> &Vec<TagType> {
+        &self.tags
+    }
+
+    pub fn get_tag(&self, index: u32) -> Option<&TagType> {
+        self.tags.get(index as usize)
+    }
+
+    /// The values carried by an exception with the given tag.
+    pub fn get_tag_sig(&self, index: u32) -> Option<&FuncType> {
+        self.get_tag(index)
+            .and_then(|tag| self.get_sig(tag.func_type_idx))
+    }
+
+    /// The name of the module in the name section.
+    pub fn get_module_name(&self) -> Option<&'a str> {
+        self.names.module_name
+    }
+
+    /// The name of the function at `index` in the name section.
+    pub fn get_func_name(&self, index: u32) -> Option<&'a str> {
+        self.names.func_names.get(&index).copied()
+    }
+
+    /// The source line of the instruction at `pc` of function `func`, from
+    /// the DWARF debug info of modules compiled with `-g`.
+    pub fn get_source_location(&self, func: u32, pc: usize) -> Option<SourceLocation> {
+        let offset = self.funcs.get(func as usize)?.get_inst_offset(pc)?;
+        self.line_table.as_ref()?.lookup(offset as u64)
+    }
+
+    /// The name of local `local` of function `func` in the name section.
+    pub fn get_local_name(&self, func: u32, local: u32) -> Option<&'a str> {
+        self.names.local_names.get(&func)?.get(&local).copied()
+    }
+
+    /// The index of the function named `name` in the name section.
+    pub fn get_func_index_by_name(&self, name: &str) -> Option<u32> {
+        self.names
+            .func_names
+            .iter()
+            .find(|(_, func_name)| **func_name == name)
+            .map(|(index, _)| *index)
+    }
+
+    pub fn get_exports(&self) -> &Vec<Export<'a>> {
+        &self.exports
+    }
+
+    /// The function of the start section, run when the module is
+    /// instantiated.
+    pub fn get_start_func(&self) -> Option<u32> {
+        self.start_func_id
+    }
+
+    /// The binary the module was parsed from, empty for a module built in
+    /// memory.
+    pub(crate) fn get_bytes(&self) -> &'a [u8] {
+        self.bytes
     }
 
     pub fn get_main_index(&self) -> Option<u32> {
+        self.get_export_func_index("main")
+    }
+
+    /// The function a run starts with, the export `entry` if it is given.
+    /// Otherwise `main`, then `_start` as exported by WASI commands, then
+    /// the first exported function.
+    pub fn get_entry_index(&self, entry: Option<&str>) -> Option<u32> {
+        match entry {
+            Some(name) => self.get_export_func_index(name),
+            None => self
+                .get_main_index()
+                .or_else(|| self.get_export_func_index("_start"))
+                .or_else(|| {
+                    self.exports
+                        .iter()
+                        .find(|export| export.kind == ExternalKind::Func)
+                        .map(|export| export.index)
+                }),
+        }
+    }
+
+    /// The index of the function exported as `name`.
+    pub fn get_export_func_index(&self, name: &str) -> Option<u32> {
+        self.get_export_index(name, ExternalKind::Func)
+    }
+
+    /// The index of the `kind` export `name` in the index space of its kind.
+    pub fn get_export_index(&self, name: &str, kind: ExternalKind) -> Option<u32> {
         self.exports
             .iter()
-            .find(|export| export.name == "main")
+            .find(|export| export.name == name && export.kind == kind)
             .map(|export| export.index)
     }
+
+    /// Lower the bodies of the defined functions for the interpreter, with
+    /// the operand stack heights the validator found before each instruction.
+    fn lower_funcs(&mut self, stack_heights: &[Vec<u32>]) {
+        let num_imports = self.get_num_func_imports();
+        let codes: Vec<_> = self.funcs[num_imports..]
+            .iter()
+            .zip(stack_heights)
+            .map(|(func, heights)| bytecode::lower(self, func, heights))
+            .collect();
+        for (func, code) in self.funcs[num_imports..].iter_mut().zip(codes) {
+            func.set_code(code);
+        }
+    }
+}
+
+pub(crate) fn block_type_num_params(module: &WasmModule, block_type: BlockType) -> usize {
+    match block_type {
+        BlockType::Empty | BlockType::Type(_) => 0,
+        BlockType::FuncType(type_idx) => module
+            .get_sig(type_idx)
+            .expect("block type not found")
+            .params()
+            .len(),
+    }
+}
+
+pub(crate) fn block_type_num_results(module: &WasmModule, block_type: BlockType) -> usize {
+    match block_type {
+        BlockType::Empty => 0,
+        BlockType::Type(_) => 1,
+        BlockType::FuncType(type_idx) => module
+            .get_sig(type_idx)
+            .expect("block type not found")
+            .results()
+            .len(),
+    }
 }
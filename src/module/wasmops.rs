@@ -211,10 +211,23 @@ pub const WASM_EXT1_SIMD: u32 = 0xFD;
 pub const WASM_EXT1_THREADS: u32 = 0xFE;
 
 /** 0xFC extensions: Partially implemented **/
+pub const WASM_OP_I32_TRUNC_SAT_F32_S: u32 = 0xFC00; /* "i32.trunc_sat_f32_s", ImmSigs.NONE */
+pub const WASM_OP_I32_TRUNC_SAT_F32_U: u32 = 0xFC01; /* "i32.trunc_sat_f32_u", ImmSigs.NONE */
+pub const WASM_OP_I32_TRUNC_SAT_F64_S: u32 = 0xFC02; /* "i32.trunc_sat_f64_s", ImmSigs.NONE */
+pub const WASM_OP_I32_TRUNC_SAT_F64_U: u32 = 0xFC03; /* "i32.trunc_sat_f64_u", ImmSigs.NONE */
+pub const WASM_OP_I64_TRUNC_SAT_F32_S: u32 = 0xFC04; /* "i64.trunc_sat_f32_s", ImmSigs.NONE */
+pub const WASM_OP_I64_TRUNC_SAT_F32_U: u32 = 0xFC05; /* "i64.trunc_sat_f32_u", ImmSigs.NONE */
+pub const WASM_OP_I64_TRUNC_SAT_F64_S: u32 = 0xFC06; /* "i64.trunc_sat_f64_s", ImmSigs.NONE */
+pub const WASM_OP_I64_TRUNC_SAT_F64_U: u32 = 0xFC07; /* "i64.trunc_sat_f64_u", ImmSigs.NONE */
 pub const WASM_OP_MEMORY_INIT: u32 = 0xFC08; /* "memory.init", ImmSigs.DATA_MEMORY */
 pub const WASM_OP_DATA_DROP: u32 = 0xFC09; /* "data.drop", ImmSigs.DATA */
 pub const WASM_OP_MEMORY_COPY: u32 = 0xFC0A; /* "memory.copy", ImmSigs.MEMORYCP */
 pub const WASM_OP_MEMORY_FILL: u32 = 0xFC0B; /* "memory.fill", ImmSigs.MEMORY */
+pub const WASM_OP_TABLE_INIT: u32 = 0xFC0C; /* "table.init", ImmSigs.ELEM_TABLE */
+pub const WASM_OP_ELEM_DROP: u32 = 0xFC0D; /* "elem.drop", ImmSigs.ELEM */
+pub const WASM_OP_TABLE_GROW: u32 = 0xFC0F; /* "table.grow", ImmSigs.TABLE */
+pub const WASM_OP_TABLE_SIZE: u32 = 0xFC10; /* "table.size", ImmSigs.TABLE */
+pub const WASM_OP_TABLE_FILL: u32 = 0xFC11; /* "table.fill", ImmSigs.TABLE */
 
 /** SIMD: 0xFD extensions **/
 pub const WASM_OP_V128_LOAD: u32 = 0xFD00; /* "v128.load", ImmSigs.MEMARG */
@@ -466,6 +479,7 @@ pub const WASM_OP_F32x4_PMAX: u32 = 0xFDEB01; /* "f32x4.pmax", ImmSigs.NONE */
 pub const WASM_OP_F64x2_ABS: u32 = 0xFDEC01; /* "f64x2.abs", ImmSigs.NONE */
 pub const WASM_OP_F64x2_NEG: u32 = 0xFDED01; /* "f64x2.neg", ImmSigs.NONE */
 pub const WASM_OP_F64x2_SQRT: u32 = 0xFDEF01; /* "f64x2.sqrt", ImmSigs.NONE */
+pub const WASM_OP_F64x2_ADD: u32 = 0xFDF001; /* "f64x2.add", ImmSigs.NONE */
 pub const WASM_OP_F64x2_SUB: u32 = 0xFDF101; /* "f64x2.sub", ImmSigs.NONE */
 pub const WASM_OP_F64x2_MUL: u32 = 0xFDF201; /* "f64x2.mul", ImmSigs.NONE */
 pub const WASM_OP_F64x2_DIV: u32 = 0xFDF301; /* "f64x2.div", ImmSigs.NONE */
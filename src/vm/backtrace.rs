@@ -0,0 +1,79 @@
+//! Wasm level backtraces of traps. Every interpreter frame a trap unwinds
+//! through records itself in the backtrace attached to the error, the jit
+//! walks the native frames of its code instead, see `jit::backtrace`.
+
+use std::fmt;
+
+use super::checkpoint::SavedFrame;
+use crate::module::dwarf::SourceLocation;
+
+/// A wasm function on the call stack.
+#[derive(Debug, Clone)]
+pub struct WasmFrame {
+    pub func_index: u32,
+    /// The name of the function in the name section.
+    pub func_name: Option<String>,
+    /// The instruction the function was executing, a call for every frame but
+    /// the innermost one. `None` if it is not known, as for the innermost
+    /// frame of jit code.
+    pub pc: Option<usize>,
+    /// The source line of the instruction in the DWARF debug info.
+    pub location: Option<SourceLocation>,
+}
+
+impl fmt::Display for WasmFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "func {}", self.func_index)?;
+        if let Some(name) = &self.func_name {
+            write!(f, " ${}", name)?;
+        }
+        if let Some(pc) = self.pc {
+            write!(f, " pc {}", pc)?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+/// The wasm frames that were active when a trap occurred, the innermost
+/// frame first.
+#[derive(Debug, Clone, Default)]
+pub struct WasmBacktrace {
+    frames: Vec<WasmFrame>,
+    /// the states of the frames, if the interpreter was asked to record them
+    /// for a core dump or a checkpoint
+    states: Vec<SavedFrame>,
+}
+
+impl WasmBacktrace {
+    pub fn frames(&self) -> &[WasmFrame] {
+        &self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Add a frame that called the ones recorded so far.
+    pub(crate) fn push_caller(&mut self, frame: WasmFrame, state: Option<SavedFrame>) {
+        self.frames.push(frame);
+        self.states.extend(state);
+    }
+
+    /// The recorded states of the frames, the innermost frame first.
+    pub(crate) fn frame_states(&self) -> &[SavedFrame] {
+        &self.states
+    }
+}
+
+impl fmt::Display for WasmBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "wasm backtrace:")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n  {:>3}: {}", i, frame)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+//! Checkpoints of calls that stopped, see `WasmInterpreter::take_checkpoint`.
+//! A call only stops on fuel exhaustion and epoch interruption before an
+//! instruction runs, so every frame resumes at its pc: the innermost one
+//! runs the instruction, the callers take the results of their call.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    exception::WasmException,
+    func_exec::BlockControlFlowFrame,
+    store::{LinearMemory, Table},
+    Snapshot,
+};
+use crate::module::value_type::WasmValue;
+
+const MAGIC: &[u8; 4] = b"wick";
+const VERSION: u32 = 4;
+
+/// The state of a wasm frame, with the control frames that a frame state
+/// leaves out.
+#[derive(Debug, Clone)]
+pub(crate) struct SavedFrame {
+    pub(crate) func_index: u32,
+    pub(crate) pc: usize,
+    pub(crate) locals: Vec<WasmValue>,
+    /// the topmost value last
+    pub(crate) operand_stack: Vec<WasmValue>,
+    /// empty if the function was interrupted before it set up its frame
+    pub(super) control_flow_frames: Vec<BlockControlFlowFrame>,
+}
+
+/// The execution state of a call that ran out of fuel or was interrupted:
+/// the wasm frames and the instance they ran on. `WasmInterpreter::resume`
+/// continues the call, also on another interpreter of the same module after
+/// a round trip through `save` and `load`.
+#[derive(Clone)]
+pub struct Checkpoint {
+    /// the innermost frame first
+    pub(crate) frames: Vec<SavedFrame>,
+    pub(crate) instance: Snapshot,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u32(VERSION);
+
+        let instance = &self.instance;
+        w.len(instance.mems.len());
+        for mem in &instance.mems {
+            w.bool(mem.memory64);
+            w.option(mem.max, Writer::u64);
+            w.len(mem.data.len());
+            w.bytes(&mem.data);
+        }
+        w.values(&instance.globals);
+        w.len(instance.tables.len());
+        for table in &instance.tables {
+            w.option(table.max, Writer::u64);
+            w.values(&table.elems);
+        }
+        w.len(instance.elem_segments.len());
+        for segment in &instance.elem_segments {
+            w.values(segment);
+        }
+        w.len(instance.dropped_datas.len());
+        for dropped in &instance.dropped_datas {
+            w.bool(*dropped);
+        }
+
+        w.len(self.frames.len());
+        for frame in &self.frames {
+            w.u32(frame.func_index);
+            w.len(frame.pc);
+            w.values(&frame.locals);
+            w.values(&frame.operand_stack);
+            w.len(frame.control_flow_frames.len());
+            for control in &frame.control_flow_frames {
+                w.control_flow_frame(control);
+            }
+        }
+        w.out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader { bytes, pos: 0 };
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("checkpoint: bad magic"));
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(anyhow!("checkpoint: unsupported version {}", version));
+        }
+
+        let mems = r.vec(|r| {
+            let memory64 = r.bool()?;
+            let max = r.option(Reader::u64)?;
+            let len = r.len()?;
+            Ok(LinearMemory::from_bytes(r.bytes(len)?, 0, memory64, max))
+        })?;
+        let globals = r.values()?;
+        let tables = r.vec(|r| {
+            let max = r.option(Reader::u64)?;
+            Ok(Table {
+                elems: r.values()?,
+                max,
+            })
+        })?;
+        let elem_segments = r.vec(Reader::values)?;
+        let dropped_datas = r.vec(Reader::bool)?;
+
+        let frames = r.vec(|r| {
+            Ok(SavedFrame {
+                func_index: r.u32()?,
+                pc: r.len()?,
+                locals: r.values()?,
+                operand_stack: r.values()?,
+                control_flow_frames: r.vec(Reader::control_flow_frame)?,
+            })
+        })?;
+        if r.pos != bytes.len() {
+            return Err(anyhow!("checkpoint: trailing bytes"));
+        }
+
+        Ok(Self {
+            frames,
+            instance: Snapshot {
+                mems,
+                globals,
+                tables,
+                elem_segments,
+                dropped_datas,
+            },
+        })
+    }
+}
+
+/// Little endian fixed width encoding, lengths and pcs are u64.
+#[derive(Default)]
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+    }
+
+    fn bool(&mut self, v: bool) {
+        self.out.push(v as u8);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn len(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    fn option<T: Copy>(&mut self, v: Option<T>, write: fn(&mut Self, T)) {
+        self.bool(v.is_some());
+        if let Some(v) = v {
+            write(self, v);
+        }
+    }
+
+    fn values(&mut self, values: &[WasmValue]) {
+        self.len(values.len());
+        for value in values {
+            match value {
+                WasmValue::I32(v) => {
+                    self.out.push(0);
+                    self.u32(*v as u32);
+                }
+                WasmValue::I64(v) => {
+                    self.out.push(1);
+                    self.u64(*v as u64);
+                }
+                WasmValue::F32(v) => {
+                    self.out.push(2);
+                    self.u32(v.to_bits());
+                }
+                WasmValue::F64(v) => {
+                    self.out.push(3);
+                    self.u64(v.to_bits());
+                }
+                WasmValue::FuncRef(v) => {
+                    self.out.push(4);
+                    self.option(*v, Self::u32);
+                }
+                WasmValue::ExternRef(v) => {
+                    self.out.push(5);
+                    self.option(*v, Self::u32);
+                }
+                WasmValue::V128(v) => {
+                    self.out.push(6);
+                    self.bytes(&v.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    fn control_flow_frame(&mut self, frame: &BlockControlFlowFrame) {
+        match frame {
+            BlockControlFlowFrame::Function => self.out.push(0),
+            BlockControlFlowFrame::Try { handler } => {
+                self.out.push(1);
+                self.u32(*handler);
+            }
+            BlockControlFlowFrame::Catch { exception } => {
+                self.out.push(2);
+                self.u32(exception.tag_index);
+                self.values(&exception.values);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| anyhow!("checkpoint: unexpected end"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(anyhow!("checkpoint: invalid bool {}", b)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| anyhow!("checkpoint: length too large"))
+    }
+
+    fn option<T>(&mut self, read: fn(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        if self.bool()? {
+            Ok(Some(read(self)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn vec<T>(&mut self, mut read: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let len = self.len()?;
+        // do not trust the length with the allocation, every item is at
+        // least a byte
+        let mut items = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+        for _ in 0..len {
+            items.push(read(self)?);
+        }
+        Ok(items)
+    }
+
+    fn values(&mut self) -> Result<Vec<WasmValue>> {
+        self.vec(|r| {
+            Ok(match r.u8()? {
+                0 => WasmValue::I32(r.u32()? as i32),
+                1 => WasmValue::I64(r.u64()? as i64),
+                2 => WasmValue::F32(f32::from_bits(r.u32()?)),
+                3 => WasmValue::F64(f64::from_bits(r.u64()?)),
+                4 => WasmValue::FuncRef(r.option(Self::u32)?),
+                5 => WasmValue::ExternRef(r.option(Self::u32)?),
+                6 => WasmValue::V128(u128::from_le_bytes(r.bytes(16)?.try_into().unwrap())),
+                tag => return Err(anyhow!("checkpoint: invalid value tag {}", tag)),
+            })
+        })
+    }
+
+    fn control_flow_frame(&mut self) -> Result<BlockControlFlowFrame> {
+        Ok(match self.u8()? {
+            0 => BlockControlFlowFrame::Function,
+            1 => BlockControlFlowFrame::Try {
+                handler: self.u32()?,
+            },
+            2 => BlockControlFlowFrame::Catch {
+                exception: WasmException {
+                    tag_index: self.u32()?,
+                    values: self.values()?,
+                },
+            },
+            tag => return Err(anyhow!("checkpoint: invalid control frame tag {}", tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checkpoint;
+    use crate::{
+        module::{value_type::WasmValue, wasm_module::WasmModule},
+        vm::{TrapKind, WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    const WAT: &str = r#"
+        (module
+          (memory 1)
+          (global $iterations (mut i64) (i64.const 0))
+          (func $count (param $n i32) (result i32) (local $sum i32)
+            (block $done
+              (loop $next
+                (br_if $done (i32.eqz (local.get $n)))
+                (local.set $sum (i32.add (local.get $sum) (local.get $n)))
+                (i32.store (i32.const 0) (local.get $sum))
+                (global.set $iterations (i64.add (global.get $iterations) (i64.const 1)))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br $next)))
+            (local.get $sum))
+          (func (export "run") (param $n i32) (result i32)
+            (i32.add (call $count (local.get $n)) (i32.const 1)))
+          (func (export "stored") (result i32) (i32.load (i32.const 0)))
+          (func (export "iterations") (result i64) (global.get $iterations)))
+    "#;
+
+    /// The checkpoint of `run(100)` running out of fuel inside `$count`.
+    fn interrupted_run() -> Checkpoint {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let config = WasmInterpreterConfig::new()
+            .fuel(500)
+            .checkpoint_on_interrupt(true);
+        let vm = WasmInterpreter::with_config(module, &config).unwrap();
+        let e = vm.invoke("run", vec![WasmValue::I32(100)]).unwrap_err();
+        assert_eq!(e.trap_kind(), Some(TrapKind::FuelExhausted));
+        let checkpoint = vm.take_checkpoint().unwrap();
+        // stopped midway through the loop
+        assert!(matches!(
+            checkpoint.instance.globals[..],
+            [WasmValue::I64(iterations)] if iterations > 0 && iterations < 100
+        ));
+        checkpoint
+    }
+
+    #[test]
+    fn a_saved_checkpoint_resumes_on_another_instance() {
+        let bytes = interrupted_run().to_bytes();
+        let checkpoint = Checkpoint::from_bytes(&bytes).unwrap();
+        assert_eq!(checkpoint.frames.len(), 2);
+
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let vm = WasmInterpreter::with_config(module, &WasmInterpreterConfig::new()).unwrap();
+        let results = vm.resume(&checkpoint).unwrap();
+        assert!(matches!(results[..], [WasmValue::I32(5051)]));
+        let stored = vm.invoke("stored", Vec::new()).unwrap();
+        assert!(matches!(stored[..], [WasmValue::I32(5050)]));
+        let iterations = vm.invoke("iterations", Vec::new()).unwrap();
+        assert!(matches!(iterations[..], [WasmValue::I64(100)]));
+    }
+
+    #[test]
+    fn a_truncated_or_corrupt_checkpoint_is_an_error() {
+        let bytes = interrupted_run().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(Checkpoint::from_bytes(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Checkpoint::from_bytes(&trailing).is_err());
+        let mut magic = bytes.clone();
+        magic[0] ^= 0xff;
+        assert!(Checkpoint::from_bytes(&magic).is_err());
+        let mut version = bytes.clone();
+        version[4] ^= 0xff;
+        assert!(Checkpoint::from_bytes(&version).is_err());
+    }
+
+    #[test]
+    fn a_checkpoint_of_another_module_does_not_resume() {
+        let checkpoint = interrupted_run();
+        let mut binary = Vec::new();
+        let other = WasmModule::from_wat("(module (func (export \"f\")))", &mut binary).unwrap();
+        let vm = WasmInterpreter::with_config(other, &WasmInterpreterConfig::new()).unwrap();
+        assert!(vm.resume(&checkpoint).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use std::{path::PathBuf, time::Duration};
+
+use super::{Epoch, ImportTraceSink, Linker, ResourceLimiter, SharedLimiter};
+use crate::module::{features::Features, value_type::FloatFormat};
+
+/// The code generator of the jit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JitBackend {
+    /// the single pass x86-64 compiler, the riscv64 one on riscv64
+    #[default]
+    Builtin,
+    /// Cranelift, which optimizes and supports more architectures, only
+    /// with the `cranelift` feature. Metered, interruptible and traced
+    /// calls run in the interpreter
+    Cranelift,
+}
+
+/// Configuration for a [`WasmInterpreter`](super::WasmInterpreter).
+///
+/// ```ignore
+/// let config = WasmInterpreterConfig::new()
+///     .jit(true)
+///     .trace_imports(ImportTraceSink::Stderr);
+/// let vm = WasmInterpreter::with_config(module, &config)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WasmInterpreterConfig<'a> {
+    pub(crate) jit_mode: bool,
+    pub(crate) jit_backend: JitBackend,
+    pub(crate) trace_imports: Option<ImportTraceSink>,
+    pub(crate) trace_instructions: bool,
+    pub(crate) debugger: bool,
+    pub(crate) jit_debug_info: bool,
+    pub(crate) debug_break: bool,
+    pub(crate) perf_map: bool,
+    pub(crate) lazy_jit: bool,
+    pub(crate) jit_cache_dir: Option<PathBuf>,
+    pub(crate) jit_backtrace: bool,
+    pub(crate) coredump: Option<PathBuf>,
+    pub(crate) checkpoint_on_interrupt: bool,
+    pub(crate) canonicalize_nans: bool,
+    pub(crate) entry: Option<String>,
+    pub(crate) float_format: FloatFormat,
+    pub(crate) tier_up: Option<u64>,
+    pub(crate) linker: Linker<'a>,
+    pub(crate) features: Features,
+    pub(crate) max_call_depth: Option<usize>,
+    pub(crate) default_memory_maximum: Option<u64>,
+    pub(crate) max_memory_pages: Option<u64>,
+    pub(crate) max_table_elements: Option<u64>,
+    pub(crate) limiter: Option<SharedLimiter>,
+    pub(crate) fuel: Option<u64>,
+    pub(crate) epoch: Option<Epoch>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl<'a> WasmInterpreterConfig<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile the module to native code instead of interpreting it.
+    pub fn jit(mut self, enabled: bool) -> Self {
+        self.jit_mode = enabled;
+        self
+    }
+
+    /// Generate the code of the jit with `backend`.
+    pub fn jit_backend(mut self, backend: JitBackend) -> Self {
+        self.jit_backend = backend;
+        self
+    }
+
+    /// Log every call to an imported host function to the given sink.
+    pub fn trace_imports(mut self, sink: ImportTraceSink) -> Self {
+        self.trace_imports = Some(sink);
+        self
+    }
+
+    /// Log every executed instruction to stderr, see
+    /// `WasmInterpreter::trace_instructions`.
+    pub fn trace_instructions(mut self, enabled: bool) -> Self {
+        self.trace_instructions = enabled;
+        self
+    }
+
+    /// Run the interactive debugger on stdin, see
+    /// `WasmInterpreter::enable_debugger`.
+    pub fn debugger(mut self, enabled: bool) -> Self {
+        self.debugger = enabled;
+        self
+    }
+
+    /// Register jit code with debuggers, see
+    /// `WasmInterpreter::jit_debug_info`.
+    pub fn jit_debug_info(mut self, enabled: bool) -> Self {
+        self.jit_debug_info = enabled;
+        self
+    }
+
+    /// Break into the debugger when jit code is entered, see
+    /// `WasmInterpreter::debug_break`.
+    pub fn debug_break(mut self, enabled: bool) -> Self {
+        self.debug_break = enabled;
+        self
+    }
+
+    /// List jit code in the perf map of the process, see
+    /// `WasmInterpreter::perf_map`.
+    pub fn perf_map(mut self, enabled: bool) -> Self {
+        self.perf_map = enabled;
+        self
+    }
+
+    /// Compile functions on their first call, see
+    /// `WasmInterpreter::lazy_jit`.
+    pub fn lazy_jit(mut self, enabled: bool) -> Self {
+        self.lazy_jit = enabled;
+        self
+    }
+
+    /// Cache the jit code in `dir` across runs, see
+    /// `WasmInterpreter::jit_cache_dir`.
+    pub fn jit_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.jit_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Record the wasm frames when jit code traps, see
+    /// `WasmInterpreter::jit_backtrace`.
+    pub fn jit_backtrace(mut self, enabled: bool) -> Self {
+        self.jit_backtrace = enabled;
+        self
+    }
+
+    /// Write a wasm core dump to `path` when a call traps, see
+    /// `WasmInterpreter::coredump_on_trap`.
+    pub fn coredump_on_trap(mut self, path: impl Into<PathBuf>) -> Self {
+        self.coredump = Some(path.into());
+        self
+    }
+
+    /// Keep the state of interrupted calls, see
+    /// `WasmInterpreter::checkpoint_on_interrupt`.
+    pub fn checkpoint_on_interrupt(mut self, enabled: bool) -> Self {
+        self.checkpoint_on_interrupt = enabled;
+        self
+    }
+
+    /// Canonicalize the NaNs of float arithmetic in both engines, see
+    /// `WasmInterpreter::canonicalize_nans`.
+    pub fn canonicalize_nans(mut self, enabled: bool) -> Self {
+        self.canonicalize_nans = enabled;
+        self
+    }
+
+    /// Call the function exported as `name` from `WasmVm::run`, see
+    /// `WasmInterpreter::set_entry`.
+    pub fn entry(mut self, name: impl Into<String>) -> Self {
+        self.entry = Some(name.into());
+        self
+    }
+
+    /// Print the float results of `WasmVm::run` in `format`.
+    pub fn float_format(mut self, format: FloatFormat) -> Self {
+        self.float_format = format;
+        self
+    }
+
+    /// Jit compile the functions called `calls` times by the interpreter,
+    /// see `WasmInterpreter::tier_up`.
+    pub fn tier_up(mut self, calls: u64) -> Self {
+        self.tier_up = Some(calls);
+        self
+    }
+
+    /// Resolve imported functions to the host functions defined in `linker`
+    /// before the built-in ones.
+    pub fn linker(mut self, linker: Linker<'a>) -> Self {
+        self.linker = linker;
+        self
+    }
+
+    /// Only let the instantiated modules use the proposals enabled in
+    /// `features`, see `WasmModule::check_features`.
+    pub fn features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Trap with `StackExhausted` once wasm calls nest `depth` deep, the
+    /// default is `DEFAULT_MAX_CALL_DEPTH`.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    /// Let the memories declared without a maximum grow to `pages` in both
+    /// engines, the default is `DEFAULT_MEMORY_MAXIMUM`.
+    pub fn default_memory_maximum(mut self, pages: u64) -> Self {
+        self.default_memory_maximum = Some(pages);
+        self
+    }
+
+    /// Never let a memory be larger than `pages`, whatever its maximum, in
+    /// both engines. A module whose memories start larger fails to
+    /// instantiate and `memory.grow` past it returns -1.
+    pub fn max_memory_pages(mut self, pages: u64) -> Self {
+        self.max_memory_pages = Some(pages);
+        self
+    }
+
+    /// Never let a table hold more than `elements`, like
+    /// `max_memory_pages` does for memories.
+    pub fn max_table_elements(mut self, elements: u64) -> Self {
+        self.max_table_elements = Some(elements);
+        self
+    }
+
+    /// Ask `limiter` before growing a memory or a table, in both engines.
+    pub fn limiter(mut self, limiter: impl ResourceLimiter + 'static) -> Self {
+        self.limiter = Some(SharedLimiter::new(limiter));
+        self
+    }
+
+    /// Meter the execution, every instruction consumes one unit of `fuel`
+    /// and running out of it traps with `FuelExhausted`.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Interrupt the execution once `epoch` is incremented, see
+    /// `WasmInterpreter::set_epoch_deadline` for later deadlines.
+    pub fn epoch_interruption(mut self, epoch: Epoch) -> Self {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    /// Stop every call that runs longer than `timeout` with
+    /// `TrapKind::Timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
@@ -0,0 +1,252 @@
+//! Wasm core dumps, as described in the coredump document of the
+//! WebAssembly tool conventions. A core dump is itself a wasm module: its
+//! memory, global and data sections hold the state of the instance when it
+//! trapped, and the `core`, `coremodules`, `coreinstances` and `corestack`
+//! custom sections describe the process and the frames of the call stack.
+
+use std::path::Path;
+
+use anyhow::Result;
+use wasmparser::{RefType, ValType};
+
+use super::{
+    checkpoint::SavedFrame,
+    func_exec::{encode_i32leb, encode_i64leb},
+    store::LinearMemory,
+    WASM_DEFAULT_PAGE_SIZE_BYTE,
+};
+use crate::module::{value_type::WasmValue, wasm_module::WasmModule};
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_DATA: u8 = 11;
+
+/// Write the core dump of the trap with the interpreter frames `frames`,
+/// innermost first, to `path`.
+pub(crate) fn write_coredump(
+    path: &Path,
+    module: &WasmModule,
+    mems: &[LinearMemory],
+    globals: &[WasmValue],
+    frames: &[SavedFrame],
+) -> Result<()> {
+    let module_name = module.get_module_name().unwrap_or("<module>");
+
+    let mut out = b"\0asm".to_vec();
+    out.extend_from_slice(&1u32.to_le_bytes());
+
+    let mut core = vec![0];
+    write_name(&mut core, module_name);
+    write_section(&mut out, SECTION_CUSTOM, &custom_section("core", &core));
+
+    let mut coremodules = vec![];
+    write_u64leb(&mut coremodules, 1);
+    coremodules.push(0);
+    write_name(&mut coremodules, module_name);
+    write_section(
+        &mut out,
+        SECTION_CUSTOM,
+        &custom_section("coremodules", &coremodules),
+    );
+
+    // a single instance of module 0 owning every memory and global
+    let nglobals = module.get_globals().len() as u64;
+    let mut coreinstances = vec![];
+    write_u64leb(&mut coreinstances, 1);
+    coreinstances.push(0);
+    write_u64leb(&mut coreinstances, 0);
+    write_u64leb(&mut coreinstances, mems.len() as u64);
+    for i in 0..mems.len() {
+        write_u64leb(&mut coreinstances, i as u64);
+    }
+    write_u64leb(&mut coreinstances, nglobals);
+    for i in 0..nglobals {
+        write_u64leb(&mut coreinstances, i);
+    }
+    write_section(
+        &mut out,
+        SECTION_CUSTOM,
+        &custom_section("coreinstances", &coreinstances),
+    );
+
+    write_section(
+        &mut out,
+        SECTION_CUSTOM,
+        &custom_section("corestack", &corestack(module, frames)),
+    );
+
+    // memories at their current size
+    let mut memory_section = vec![];
+    write_u64leb(&mut memory_section, mems.len() as u64);
+    for mem in mems {
+        memory_section.push(if mem.memory64 { 0x04 } else { 0x00 });
+        write_u64leb(
+            &mut memory_section,
+            (mem.size() / WASM_DEFAULT_PAGE_SIZE_BYTE) as u64,
+        );
+    }
+    write_section(&mut out, SECTION_MEMORY, &memory_section);
+
+    // every global is initialized to its current value
+    let mut global_section = vec![];
+    write_u64leb(&mut global_section, nglobals);
+    for (global, value) in module.get_globals().iter().zip(globals) {
+        let ty = global.get_ty();
+        global_section.push(val_type_byte(ty.content_type));
+        global_section.push(ty.mutable as u8);
+        write_const_expr(&mut global_section, value);
+    }
+    write_section(&mut out, SECTION_GLOBAL, &global_section);
+
+    // one active segment per memory, without the trailing zeros
+    let mut data_section = vec![];
+    write_u64leb(&mut data_section, mems.len() as u64);
+    for (i, mem) in mems.iter().enumerate() {
+        let len = mem.data.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        if i == 0 {
+            data_section.push(0x00);
+        } else {
+            data_section.push(0x02);
+            write_u64leb(&mut data_section, i as u64);
+        }
+        // offset 0, then end
+        data_section.extend_from_slice(if mem.memory64 {
+            &[0x42, 0x00, 0x0b]
+        } else {
+            &[0x41, 0x00, 0x0b]
+        });
+        write_u64leb(&mut data_section, len as u64);
+        data_section.extend_from_slice(&mem.data[..len]);
+    }
+    write_section(&mut out, SECTION_DATA, &data_section);
+
+    std::fs::write(path, out)?;
+    Ok(())
+}
+
+/// The thread name followed by the frames, innermost first. The code offset
+/// of a frame is relative to the start of its function body.
+fn corestack(module: &WasmModule, frames: &[SavedFrame]) -> Vec<u8> {
+    let mut corestack = vec![0];
+    write_name(&mut corestack, "main");
+    write_u64leb(&mut corestack, frames.len() as u64);
+    for frame in frames {
+        let func = module.get_func(frame.func_index);
+        let code_offset = func
+            .and_then(|func| Some(func.get_inst_offset(frame.pc)? - func.get_body_offset()))
+            .unwrap_or(0);
+        corestack.push(0);
+        // instance index
+        write_u64leb(&mut corestack, 0);
+        write_u64leb(&mut corestack, frame.func_index as u64);
+        write_u64leb(&mut corestack, code_offset as u64);
+        write_u64leb(&mut corestack, frame.locals.len() as u64);
+        for value in &frame.locals {
+            write_value(&mut corestack, value);
+        }
+        write_u64leb(&mut corestack, frame.operand_stack.len() as u64);
+        for value in &frame.operand_stack {
+            write_value(&mut corestack, value);
+        }
+    }
+    corestack
+}
+
+/// Values that have no encoding in core dumps are missing.
+fn write_value(out: &mut Vec<u8>, value: &WasmValue) {
+    match value {
+        WasmValue::I32(v) => {
+            out.push(0x7f);
+            out.extend(encode_i32leb(*v));
+        }
+        WasmValue::I64(v) => {
+            out.push(0x7e);
+            out.extend(encode_i64leb(*v));
+        }
+        WasmValue::F32(v) => {
+            out.push(0x7d);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        WasmValue::F64(v) => {
+            out.push(0x7c);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        WasmValue::FuncRef(_) | WasmValue::ExternRef(_) | WasmValue::V128(_) => out.push(0x01),
+    }
+}
+
+/// A host reference has no encoding, it is written as a null reference.
+fn write_const_expr(out: &mut Vec<u8>, value: &WasmValue) {
+    match value {
+        WasmValue::I32(v) => {
+            out.push(0x41);
+            out.extend(encode_i32leb(*v));
+        }
+        WasmValue::I64(v) => {
+            out.push(0x42);
+            out.extend(encode_i64leb(*v));
+        }
+        WasmValue::F32(v) => {
+            out.push(0x43);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        WasmValue::F64(v) => {
+            out.push(0x44);
+            out.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        WasmValue::V128(v) => {
+            out.extend_from_slice(&[0xfd, 0x0c]);
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        WasmValue::FuncRef(Some(index)) => {
+            out.push(0xd2);
+            write_u64leb(out, *index as u64);
+        }
+        WasmValue::FuncRef(None) => out.extend_from_slice(&[0xd0, 0x70]),
+        WasmValue::ExternRef(_) => out.extend_from_slice(&[0xd0, 0x6f]),
+    }
+    out.push(0x0b);
+}
+
+fn val_type_byte(ty: ValType) -> u8 {
+    match ty {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+        ValType::V128 => 0x7b,
+        ValType::Ref(RefType::EXTERNREF) => 0x6f,
+        ValType::Ref(_) => 0x70,
+    }
+}
+
+fn custom_section(name: &str, contents: &[u8]) -> Vec<u8> {
+    let mut section = vec![];
+    write_name(&mut section, name);
+    section.extend_from_slice(contents);
+    section
+}
+
+fn write_section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    write_u64leb(out, contents.len() as u64);
+    out.extend_from_slice(contents);
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_u64leb(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn write_u64leb(out: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,190 @@
+//! An interactive debugger for the interpreter. The executor hands every
+//! instruction to `Debugger::on_instruction` before running it, which stops
+//! at breakpoints and after single steps and then reads commands from stdin.
+
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::func_exec::WasmFunctionExecutorImpl;
+use crate::module::insts::Instruction;
+
+const HELP: &str = "\
+break <func> [pc]    stop before the instruction at pc (default 0) of a
+                     function, given by its index, export name or name
+delete <func> [pc]   remove a breakpoint
+info                 list the breakpoints
+step                 run a single instruction, stepping into calls
+continue             run until the next breakpoint
+where                print the current function, pc and instruction
+backtrace            print the wasm call stack, innermost frame first
+locals               print the locals of the current frame
+stack                print the operand stack, topmost value first
+mem <addr> [len]     dump len (default 16) bytes of memory 0 from addr
+quit                 abort the execution";
+
+/// The number of bytes `mem` dumps when no length is given.
+const DEFAULT_DUMP_LEN: usize = 16;
+
+pub(crate) struct Debugger {
+    /// (function index, pc)
+    breakpoints: BTreeSet<(u32, usize)>,
+    /// stop before the next instruction, whatever frame it is in
+    stepping: bool,
+    /// set once stdin is closed, the execution then runs to the end
+    detached: bool,
+}
+
+impl Debugger {
+    /// The debugger stops before the first instruction so breakpoints can be
+    /// set up.
+    pub(crate) fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            stepping: true,
+            detached: false,
+        }
+    }
+
+    /// Called before `frame` executes `inst`, returns an error if the user
+    /// aborted the execution.
+    pub(crate) fn on_instruction(
+        &mut self,
+        frame: &WasmFunctionExecutorImpl,
+        inst: &Instruction,
+    ) -> Result<()> {
+        let here = (frame.func_index(), frame.pc());
+        if self.detached || !(self.stepping || self.breakpoints.contains(&here)) {
+            return Ok(());
+        }
+
+        self.stepping = false;
+        Self::print_position(frame, inst);
+        let stdin = io::stdin();
+        loop {
+            print!("(wdb) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                self.detached = true;
+                return Ok(());
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                [] => {}
+                ["s" | "step"] => {
+                    self.stepping = true;
+                    return Ok(());
+                }
+                ["c" | "continue"] => return Ok(()),
+                ["q" | "quit"] => return Err(anyhow!("debugger: execution aborted")),
+                ["b" | "break", location @ ..] => match Self::parse_location(frame, location) {
+                    Ok(loc) => {
+                        self.breakpoints.insert(loc);
+                        println!("breakpoint at func {} pc {}", loc.0, loc.1);
+                    }
+                    Err(e) => println!("{}", e),
+                },
+                ["d" | "delete", location @ ..] => match Self::parse_location(frame, location) {
+                    Ok(loc) if self.breakpoints.remove(&loc) => {}
+                    Ok(loc) => println!("no breakpoint at func {} pc {}", loc.0, loc.1),
+                    Err(e) => println!("{}", e),
+                },
+                ["i" | "info"] => {
+                    for (func, pc) in &self.breakpoints {
+                        println!("func {} pc {}", func, pc);
+                    }
+                }
+                ["w" | "where"] => Self::print_position(frame, inst),
+                ["bt" | "backtrace"] => {
+                    for (i, caller) in frame.call_stack().iter().enumerate() {
+                        println!("  {:>3}: {}", i, caller);
+                    }
+                }
+                ["l" | "locals"] => {
+                    for (i, v) in frame.locals().iter().enumerate() {
+                        match frame.local_name(i as u32) {
+                            Some(name) => println!("  local {} ${}: {}", i, name, v),
+                            None => println!("  local {}: {}", i, v),
+                        }
+                    }
+                }
+                ["st" | "stack"] => {
+                    for (depth, v) in frame.operand_stack().iter().rev().enumerate() {
+                        println!("  {}: {}", depth, v);
+                    }
+                }
+                ["m" | "mem", addr, len @ ..] => match Self::parse_range(addr, len) {
+                    Ok((addr, len)) => match frame.read_memory(0, addr, len) {
+                        Some(bytes) => Self::dump_memory(addr, &bytes),
+                        None => println!("out of bounds of memory 0"),
+                    },
+                    Err(e) => println!("{}", e),
+                },
+                ["h" | "help"] => println!("{}", HELP),
+                _ => println!("unknown command, try help"),
+            }
+        }
+    }
+
+    fn print_position(frame: &WasmFunctionExecutorImpl, inst: &Instruction) {
+        match frame.func_name() {
+            Some(name) => print!("func {} ${}", frame.func_index(), name),
+            None => print!("func {}", frame.func_index()),
+        }
+        print!(" pc {}", frame.pc());
+        if let Some(location) = frame.source_location(frame.pc()) {
+            print!(" ({})", location);
+        }
+        println!(": {:?}", inst);
+    }
+
+    fn parse_location(frame: &WasmFunctionExecutorImpl, words: &[&str]) -> Result<(u32, usize)> {
+        let (func, pc) = match words {
+            [func] => (*func, "0"),
+            [func, pc] => (*func, *pc),
+            _ => return Err(anyhow!("expected <func> [pc]")),
+        };
+        let func = match func.parse() {
+            Ok(index) => index,
+            Err(_) => frame
+                .func_index_by_name(func.trim_start_matches('$'))
+                .ok_or_else(|| anyhow!("no function named {}", func))?,
+        };
+        let pc = pc.parse().map_err(|_| anyhow!("invalid pc {}", pc))?;
+        Ok((func, pc))
+    }
+
+    fn parse_range(addr: &str, len: &[&str]) -> Result<(usize, usize)> {
+        let addr = parse_number(addr).ok_or_else(|| anyhow!("invalid address {}", addr))?;
+        let len = match len {
+            [] => DEFAULT_DUMP_LEN,
+            [len] => parse_number(len).ok_or_else(|| anyhow!("invalid length {}", len))?,
+            _ => return Err(anyhow!("expected <addr> [len]")),
+        };
+        Ok((addr, len))
+    }
+
+    fn dump_memory(addr: usize, bytes: &[u8]) {
+        for (i, row) in bytes.chunks(16).enumerate() {
+            let hex = row
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!("  0x{:08x}: {}", addr + i * 16, hex);
+        }
+    }
+}
+
+/// a decimal or 0x prefixed hexadecimal number
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
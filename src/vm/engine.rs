@@ -0,0 +1,42 @@
+//! The entry point of the embedding API: an `Engine` holds the configuration,
+//! including whether calls run on the jit, and instantiates modules into
+//! `Instance`s, each owning the store of its memories, tables and globals.
+
+use anyhow::Result;
+
+use crate::module::wasm_module::WasmModule;
+
+use super::{WasmInterpreter, WasmInterpreterConfig};
+
+/// An instantiated module, its store and the configuration it runs with.
+pub type Instance<'a> = WasmInterpreter<'a>;
+
+/// Instantiates modules with the same configuration.
+///
+/// ```ignore
+/// let engine = Engine::new(WasmInterpreterConfig::new().jit(true));
+/// let instance = engine.instantiate(WasmModule::from_bytecode(&bytes)?)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Engine<'a> {
+    config: WasmInterpreterConfig<'a>,
+}
+
+impl<'a> Engine<'a> {
+    pub fn new(config: WasmInterpreterConfig<'a>) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &WasmInterpreterConfig<'a> {
+        &self.config
+    }
+
+    /// Instantiate `module`, its imports are resolved in the linker of the
+    /// configuration, see `WasmInterpreter::with_config`.
+    pub fn instantiate<'m>(&self, module: WasmModule<'m>) -> Result<Instance<'m>>
+    where
+        'a: 'm,
+    {
+        WasmInterpreter::with_config(module, &self.config)
+    }
+}
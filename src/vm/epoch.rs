@@ -0,0 +1,128 @@
+//! Epoch based interruption. The host owns an `Epoch` counter and bumps it,
+//! e.g. from a timer thread, while wasm code checks it at function entries
+//! and loop headers and stops with `TrapKind::Interrupted` once it has
+//! reached the deadline of the instance.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// A counter shared between the host and running instances, clones refer to
+/// the same counter so it can be moved to another thread.
+#[derive(Debug, Clone, Default)]
+pub struct Epoch(Arc<AtomicU64>);
+
+impl Epoch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advance the epoch, instances whose deadline is reached are interrupted
+    /// at their next check.
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn current(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Address of the counter, the jit code reads it directly.
+    pub(crate) fn as_ptr(&self) -> *const u64 {
+        self.0.as_ptr()
+    }
+}
+
+/// The epoch an execution is interrupted at.
+#[derive(Debug, Clone)]
+pub(crate) struct EpochDeadline {
+    pub(crate) epoch: Epoch,
+    pub(crate) deadline: u64,
+}
+
+impl EpochDeadline {
+    pub(crate) fn reached(&self) -> bool {
+        self.epoch.current() >= self.deadline
+    }
+}
+
+/// Increments an epoch from another thread once a timeout has elapsed,
+/// unless it is dropped before.
+pub(crate) struct Watchdog {
+    _cancel: mpsc::Sender<()>,
+    fired: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub(crate) fn arm(epoch: Epoch, timeout: Duration) -> Self {
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let fired = Arc::new(AtomicBool::new(false));
+        let watchdog_fired = Arc::clone(&fired);
+        thread::spawn(move || {
+            // dropping the sender wakes the thread up early
+            if let Err(mpsc::RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+                watchdog_fired.store(true, Ordering::Relaxed);
+                epoch.increment();
+            }
+        });
+        Self {
+            _cancel: cancel,
+            fired,
+        }
+    }
+
+    pub(crate) fn fired(&self) -> bool {
+        self.fired.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::Epoch;
+    use crate::{
+        module::wasm_module::WasmModule,
+        vm::{TrapKind, WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    const WAT: &str = r#"
+        (module
+          (func $spin (loop (br 0)))
+          (func (export "spin") (call $spin)))
+    "#;
+
+    /// Run an endless loop until another thread increments the epoch.
+    fn interrupt(jit: bool) -> Option<TrapKind> {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let epoch = Epoch::new();
+        let config = WasmInterpreterConfig::new()
+            .jit(jit)
+            .epoch_interruption(epoch.clone());
+        let vm = WasmInterpreter::with_config(module, &config).unwrap();
+        let host = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            epoch.increment();
+        });
+        let trap = vm.invoke("spin", Vec::new()).unwrap_err().trap_kind();
+        host.join().unwrap();
+        trap
+    }
+
+    #[test]
+    fn another_thread_interrupts_the_interpreter() {
+        assert_eq!(interrupt(false), Some(TrapKind::Interrupted));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn another_thread_interrupts_the_jit_code() {
+        assert_eq!(interrupt(true), Some(TrapKind::Interrupted));
+    }
+}
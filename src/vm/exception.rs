@@ -0,0 +1,20 @@
+use std::fmt;
+
+use crate::module::value_type::WasmValue;
+
+/// A Wasm exception raised by `throw`, it unwinds the wasm frames as an
+/// error until a `try` block with a matching `catch` is found.
+#[derive(Debug, Clone)]
+pub(crate) struct WasmException {
+    pub(crate) tag_index: u32,
+    /// The values carried by the exception, the first one comes first.
+    pub(crate) values: Vec<WasmValue>,
+}
+
+impl fmt::Display for WasmException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "uncaught exception with tag {}", self.tag_index)
+    }
+}
+
+impl std::error::Error for WasmException {}
@@ -1,287 +1,1103 @@
 use anyhow::{anyhow, Result};
 use debug_cell::RefCell;
-use wasmparser::{BinaryReader, BlockType, TypeRef, ValType, WasmFeatures};
 
-use std::{collections::VecDeque, rc::Rc};
-
-use super::{interpreter::LinearMemory, WasmFunctionExecutor, WASM_DEFAULT_PAGE_SIZE_BYTE};
+use std::{cell::Cell, rc::Rc};
+
+use super::{
+    backtrace::{WasmBacktrace, WasmFrame},
+    checkpoint::SavedFrame,
+    debugger::Debugger,
+    exception::WasmException,
+    host::HostFuncImport,
+    simd::{
+        i8x16_shuffle, v128_binop, v128_bitselect, v128_extract_lane, v128_lane, v128_load_extend,
+        v128_replace_lane, v128_shift, v128_splat, v128_splat_lane, v128_test, v128_unop,
+        v128_with_lane,
+    },
+    store::Store,
+    tiering::Tiering,
+    trace::trace_instruction,
+    trap::TrapKind,
+    EpochDeadline, FrameState, SharedLimiter, StepOutcome, WasmFunctionExecutor,
+    DEFAULT_MAX_CALL_DEPTH, DEFAULT_MEMORY_MAXIMUM, WASM_DEFAULT_PAGE_SIZE_BYTE,
+};
 use crate::module::{
+    bytecode::{Code, Op},
     components::FuncDecl,
-    insts::{BrTable, F64Binop, F64Unop, I32Binop, I32Unop, Instruction, MemArg},
-    value_type::WasmValue,
+    dwarf::SourceLocation,
+    insts::{
+        F32Binop, F32Unop, F64Binop, F64Unop, I32Binop, I32Unop, I64Binop, I64Unop, V128Shape,
+    },
+    value_type::{f32_max, f32_min, WasmValue},
     wasm_module::WasmModule,
-    wasmops::{WASM_OP_END, WASM_OP_F64_CONST, WASM_OP_I32_CONST},
 };
 
 type Pc = usize;
 
-#[derive(Debug, Clone, PartialEq)]
-pub(super) enum BlockControlFlowType {
-    Block,
-    If {
-        else_pc: Option<Pc>,
-        condition_met: bool,
-    },
-    Loop,
-}
-
-/// Control flow frame for a code block, start with Block, If, Loop, etc.
-#[derive(Debug, Clone, PartialEq)]
-pub(super) struct BlockControlFlowFrame {
-    /// Could be If, Else, Block, Loop, etc.
-    pub(super) control_type: BlockControlFlowType,
-    /// the height of the stack that expected when the block ends, for unwinding
-    pub(super) expected_stack_height: usize,
-    /// The number of results in the block, for unwinding
-    pub(super) num_results: usize,
-    /// Program counter where the block starts
-    pub(super) start_pc: Pc,
-    /// Program counter of the `end` instruction for the block
-    pub(super) end_pc: Pc,
+/// The values the operand stack of a frame has room for before it grows.
+const OPERAND_STACK_CAPACITY: usize = 16;
+
+// bounds of the float to integer truncations, the upper bounds are exclusive
+const I32_MIN: f64 = -2147483648.0;
+const I32_MAX_EXCL: f64 = 2147483648.0;
+const U32_MAX_EXCL: f64 = 4294967296.0;
+const I64_MIN: f64 = -9223372036854775808.0;
+const I64_MAX_EXCL: f64 = 9223372036854775808.0;
+const U64_MAX_EXCL: f64 = 18446744073709551616.0;
+
+/// The control frames of a function at runtime. Branches are resolved when
+/// the body is lowered, only the try blocks leave a frame, for the exceptions
+/// to find their handler.
+#[derive(Debug, Clone)]
+pub(super) enum BlockControlFlowFrame {
+    /// The body of the function, at the bottom of its frames.
+    Function,
+    /// The body of a try block, with its handler in the code.
+    Try { handler: u32 },
+    /// A catch clause handling `exception`, kept around for rethrow.
+    Catch { exception: WasmException },
 }
 
+/// Runs a wasm call and the calls it makes. The fields of the innermost
+/// frame are kept inline, the frames of its callers wait in `callers`, so
+/// wasm calls never recurse on the host stack.
 pub(crate) struct WasmFunctionExecutorImpl<'a> {
-    /// The function to execute.
-    func: FuncDecl,
+    /// The index of the function of the innermost frame in the module.
+    func_index: u32,
+    /// The lowered body of that function, shared with the module.
+    code: Rc<Code>,
     /// The program counter. Point into function's instructions.
     pc: Pc,
-    /// The operand stack.
-    operand_stack: VecDeque<WasmValue>,
+    /// The operand stack, the topmost value last.
+    operand_stack: Vec<WasmValue>,
     /// local variables
     locals: Vec<WasmValue>,
-    /// The control flow frame for code blocks
-    control_flow_frames: VecDeque<BlockControlFlowFrame>,
-    /// The reference to the linear memory for the Wasm VM instance.
-    mem: Rc<RefCell<LinearMemory>>,
+    /// The control frames of the innermost frame, the function's first
+    control_flow_frames: Vec<BlockControlFlowFrame>,
+    /// The frames waiting for their callee to return, the outermost first.
+    /// Each one is stopped at its call.
+    callers: Vec<SavedFrame>,
     /// The reference to the Wasm module for the Wasm VM instance.
-    module: Rc<RefCell<WasmModule<'a>>>,
+    module: Rc<WasmModule<'a>>,
+    /// The memories, tables, globals and host functions of the instance.
+    store: Rc<Store<'a>>,
+    /// Calls trap with `StackExhausted` once the frames nest this deep.
+    max_call_depth: usize,
+    /// The pages a memory declared without a maximum can grow to.
+    default_memory_maximum: u64,
+    /// The pages no memory can grow past, whatever its maximum.
+    max_memory_pages: u64,
+    /// The elements no table can grow past, whatever its maximum.
+    max_table_elements: u64,
+    /// Approves the growth of the memories and tables, shared by all frames.
+    limiter: Option<SharedLimiter>,
+    /// The fuel left to the instance, shared by all its frames. Every executed
+    /// instruction consumes one unit, `None` runs unmetered.
+    fuel: Rc<Cell<Option<u64>>>,
+    /// Checked on function entry and at every loop iteration.
+    epoch_deadline: Option<EpochDeadline>,
+    /// Log every executed instruction to stderr.
+    trace: bool,
+    /// Stops the execution at breakpoints, shared by all frames.
+    debugger: Option<Rc<RefCell<Debugger>>>,
+    /// Record the state of the frames a trap unwinds through, for the core
+    /// dump or the checkpoint.
+    record_frame_states: bool,
+    /// Replace the NaNs produced by float arithmetic by the canonical NaN.
+    canonicalize_nans: bool,
+    /// Runs the hot functions as jit code, shared by all frames.
+    tiering: Option<Rc<Tiering<'a>>>,
+    /// Whether the first step of the innermost frame has set up its
+    /// function frame.
+    started: bool,
+    /// Whether every call of `run` runs a single instruction, for `step`,
+    /// fuel, the trace and the debugger. Otherwise the ops run threaded.
+    single_step: bool,
+    /// Whether the outermost function has returned its results.
+    returned: bool,
 }
 
 impl WasmFunctionExecutor for WasmFunctionExecutorImpl<'_> {
-    fn execute(&mut self) -> Result<Option<WasmValue>> {
-        // function frame
-        self.control_flow_frames.push_back(BlockControlFlowFrame {
-            control_type: BlockControlFlowType::Block,
-            expected_stack_height: 0,
-            num_results: self.func.get_sig().results().len(),
-            start_pc: 0,
-            end_pc: self.func.get_insts().len() - 1,
-        });
+    fn execute(&mut self) -> Result<Vec<WasmValue>> {
+        // fuel, the trace and the debugger see every instruction on its own
+        self.single_step = self.trace || self.debugger.is_some() || self.fuel.get().is_some();
+        loop {
+            if let StepOutcome::Returned(results) = self.step_frame()? {
+                return Ok(results);
+            }
+        }
+    }
 
-        let mut done_exec = false;
-        while !done_exec && self.pc < self.func.get_insts().len() {
-            let inst = self.func.get_inst(self.pc).clone();
+    fn step(&mut self) -> Result<StepOutcome> {
+        self.single_step = true;
+        // the calls made by the instruction run to completion
+        let depth = self.callers.len();
+        loop {
+            let outcome = self.step_frame()?;
+            if self.callers.len() <= depth {
+                return Ok(outcome);
+            }
+        }
+    }
 
-            if self.should_skip(self.pc) {
-                self.inc_pc();
-                continue;
+    fn state(&self) -> FrameState {
+        FrameState {
+            func_index: self.func_index,
+            pc: self.pc,
+            next_instruction: (!self.returned).then(|| self.func().get_inst(self.pc).clone()),
+            locals: self.locals.clone(),
+            operand_stack: self.operand_stack.clone(),
+        }
+    }
+}
+
+impl WasmFunctionExecutorImpl<'_> {
+    /// Run the innermost frame from pc, a call enters the frame of the callee
+    /// and a trap unwinds to the caller that catches it.
+    fn step_frame(&mut self) -> Result<StepOutcome> {
+        match self.run() {
+            Ok(outcome) => Ok(outcome),
+            Err(err) => {
+                let err = self.record_trap_frame(err, self.pc);
+                self.unwind(err)
             }
+        }
+    }
 
-            match inst {
-                Instruction::Return => {
-                    done_exec = true;
-                }
-                Instruction::Unreachable => {
-                    Err(anyhow!("unreachable instruction"))?;
-                }
-                Instruction::Nop => {
-                    self.inc_pc();
-                }
-                Instruction::Block { ty } => {
-                    let insts = self.func.get_insts().clone();
-                    self.run_block(&insts, ty)?;
-                    self.inc_pc();
-                }
-                Instruction::Loop { ty } => {
-                    let insts = self.func.get_insts().clone();
-                    self.run_loop(&insts, ty)?;
-                    self.inc_pc();
-                }
-                Instruction::If { ty } => {
-                    let insts = self.func.get_insts().clone();
-                    self.run_if(&insts, ty)?;
-                    self.inc_pc();
-                }
-                // we use control flow frames to handle else blocks, instructions
-                // check the top of the stack and conditionally execute, so we
-                // don't need to handle them here.
-                Instruction::Else => {
-                    self.inc_pc();
-                }
-                Instruction::End => {
-                    self.control_flow_frames.pop_back();
-                    self.inc_pc();
-                }
-                Instruction::Br { rel_depth } => {
-                    self.run_br(rel_depth)?;
-                }
-                Instruction::BrIf { rel_depth } => {
-                    let cond_met = self.run_br_if(rel_depth)?;
-                    if !cond_met {
-                        self.inc_pc();
-                    }
-                }
-                Instruction::BrTable { table } => {
-                    self.run_br_table(&table)?;
-                }
-                Instruction::Call { func_idx } => {
-                    self.run_call(func_idx)?;
-                    self.inc_pc();
-                }
-                Instruction::CallIndirect {
-                    type_index,
-                    table_index,
-                } => {
-                    self.run_call_indirect(type_index, table_index)?;
-                    self.inc_pc();
-                }
-                Instruction::Drop => {
-                    self.pop_operand_stack();
-                    self.inc_pc();
-                }
-                Instruction::Select => {
-                    let cond = self.pop_operand_stack().as_i32();
-                    let b = self.pop_operand_stack();
-                    let a = self.pop_operand_stack();
-                    self.push_operand_stack(if cond != 0 { a } else { b });
-                    self.inc_pc();
-                }
-                Instruction::LocalGet { local_idx } => {
-                    let local = self.locals[local_idx as usize];
-                    self.push_operand_stack(local);
-                    self.inc_pc();
-                }
-                Instruction::LocalSet { local_idx } => {
-                    let value = self.pop_operand_stack();
-                    self.locals[local_idx as usize] = value;
-                    self.inc_pc();
-                }
-                Instruction::LocalTee { local_idx } => {
-                    let value = self.pop_operand_stack();
-                    self.locals[local_idx as usize] = value;
-                    self.push_operand_stack(value);
-                    self.inc_pc();
-                }
-                Instruction::GlobalGet { global_idx } => {
-                    self.run_global_get(global_idx)?;
-                    self.inc_pc();
-                }
-                Instruction::GlobalSet { global_idx } => {
-                    self.run_global_set(global_idx)?;
-                    self.inc_pc();
-                }
-                Instruction::I32Load { memarg } => {
-                    let v = self.run_i32_load(&memarg, 4)?;
-                    self.push_operand_stack(v);
-                    self.inc_pc();
-                }
-                Instruction::F64Load { memarg } => {
-                    let v = self.run_f64_load(&memarg)?;
-                    self.push_operand_stack(v);
-                    self.inc_pc();
-                }
-                Instruction::I32Load8S { memarg } => {
-                    let v = self.run_i32_load(&memarg, 1)?.as_i32();
-                    let v = ((v & 0xFF) as i8) as i32;
-                    self.push_operand_stack(WasmValue::I32(v));
-                    self.inc_pc();
-                }
-                Instruction::I32Load8U { memarg } => {
-                    let v = self.run_i32_load(&memarg, 1)?.as_i32();
-                    let v = v & 0xFF;
-                    self.push_operand_stack(WasmValue::I32(v));
-                    self.inc_pc();
-                }
-                Instruction::I32Load16S { memarg } => {
-                    let v = self.run_i32_load(&memarg, 2)?.as_i32();
-                    let v = ((v & 0xFFFF) as i16) as i32;
-                    self.push_operand_stack(WasmValue::I32(v));
-                    self.inc_pc();
-                }
-                Instruction::I32Load16U { memarg } => {
-                    let v = self.run_i32_load(&memarg, 2)?.as_i32();
-                    let v = v & 0xFFFF;
-                    self.push_operand_stack(WasmValue::I32(v));
-                    self.inc_pc();
-                }
-                Instruction::I32Store { memarg } => {
-                    self.run_i32_store(&memarg, 4)?;
-                    self.inc_pc();
-                }
-                Instruction::F64Store { memarg } => {
-                    self.run_f64_store(&memarg)?;
-                    self.inc_pc();
-                }
-                Instruction::I32Store8 { memarg } => {
-                    self.run_i32_store(&memarg, 1)?;
-                    self.inc_pc();
-                }
-                Instruction::I32Store16 { memarg } => {
-                    self.run_i32_store(&memarg, 2)?;
-                    self.inc_pc();
-                }
-                Instruction::MemorySize { mem } => {
-                    self.run_memory_size(mem)?;
-                    self.inc_pc();
-                }
-                Instruction::MemoryGrow { mem } => {
-                    self.run_memory_grow(mem)?;
-                    self.inc_pc();
-                }
-                Instruction::I32Const { value } => {
-                    self.push_operand_stack(WasmValue::I32(value));
-                    self.inc_pc();
-                }
-                Instruction::F64Const { value } => {
-                    self.push_operand_stack(WasmValue::F64(value));
-                    self.inc_pc();
-                }
-                Instruction::I32Unop(i32_unop) => {
-                    self.run_i32_unop(&i32_unop)?;
-                    self.inc_pc();
-                }
-                Instruction::I32Binop(i32_binop) => {
-                    self.run_i32_binop(&i32_binop)?;
-                    self.inc_pc();
-                }
-                Instruction::F64Unop(f64_unop) => {
-                    self.run_f64_unop(&f64_unop)?;
-                    self.inc_pc();
-                }
-                Instruction::F64Binop(f64_binop) => {
-                    self.run_f64_binop(&f64_binop)?;
-                    self.inc_pc();
-                }
+    /// Leave the innermost frame with `err` and hand it to the call of each
+    /// caller in turn, until one catches it. It is returned if none does.
+    fn unwind(&mut self, mut err: anyhow::Error) -> Result<StepOutcome> {
+        while let Some(caller) = self.callers.pop() {
+            self.restore_frame(caller);
+            let pc = self.pc;
+            match self.catch_exception(err) {
+                Ok(()) => return Ok(StepOutcome::Running),
+                Err(e) => err = self.record_trap_frame(e, pc),
+            }
+        }
+        Err(err)
+    }
+
+    /// Run the op at pc of the innermost frame. Unless the call is single
+    /// stepped, its handler goes on with the following ops until the
+    /// outermost function returns or an op traps.
+    fn run(&mut self) -> Result<StepOutcome> {
+        if self.returned {
+            return Err(anyhow!("step: the function has already returned"));
+        }
+        self.start_frame()?;
+
+        let mut op = self.code.ops[self.pc];
+        if self.single_step {
+            // the instructions of a fused op run one by one
+            op = op.unfused();
+            self.consume_fuel()?;
+            self.observe_instruction()?;
+        }
+        Self::HANDLERS[op.opcode()](self, op)
+    }
+
+    /// Set up the function frame on the first op of the innermost frame.
+    fn start_frame(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            self.check_epoch()?;
+            self.push_function_frame();
+        }
+        Ok(())
+    }
+}
+
+/// Runs an op and returns the outcome of the ops run after it.
+type Handler<'a> = fn(&mut WasmFunctionExecutorImpl<'a>, Op) -> Result<StepOutcome>;
+
+/// Go on with the op at pc: return to `run` when single stepping, otherwise
+/// jump to its handler with a tail call, so the handlers run threaded
+/// without growing the host stack.
+macro_rules! dispatch {
+    ($exec:ident) => {{
+        if $exec.single_step {
+            return Ok(StepOutcome::Running);
+        }
+        let op = $exec.code.ops[$exec.pc];
+        become Self::HANDLERS[op.opcode()]($exec, op)
+    }};
+}
+
+/// The handlers of the ops
+impl<'a> WasmFunctionExecutorImpl<'a> {
+    /// The handler of each op by its opcode. The common ops have their own,
+    /// the others share `op_other`.
+    const HANDLERS: [Handler<'a>; 256] = {
+        let mut handlers = [Self::op_other as Handler<'a>; 256];
+        handlers[Op::Nop.opcode()] = Self::op_nop;
+        handlers[Op::Loop.opcode()] = Self::op_loop;
+        handlers[Op::If { target: 0 }.opcode()] = Self::op_if;
+        handlers[Op::Jump { target: 0 }.opcode()] = Self::op_jump;
+        handlers[Op::Br { branch: 0 }.opcode()] = Self::op_br;
+        handlers[Op::BrIf { branch: 0 }.opcode()] = Self::op_br_if;
+        handlers[Op::BrTable { first: 0, len: 0 }.opcode()] = Self::op_br_table;
+        handlers[Op::Return.opcode()] = Self::op_return;
+        handlers[Op::Call { func_idx: 0 }.opcode()] = Self::op_call;
+        handlers[Op::CallIndirect {
+            type_index: 0,
+            table_index: 0,
+        }
+        .opcode()] = Self::op_call_indirect;
+        handlers[Op::Drop.opcode()] = Self::op_drop;
+        handlers[Op::Select.opcode()] = Self::op_select;
+        handlers[Op::LocalGet { local_idx: 0 }.opcode()] = Self::op_local_get;
+        handlers[Op::LocalSet { local_idx: 0 }.opcode()] = Self::op_local_set;
+        handlers[Op::LocalTee { local_idx: 0 }.opcode()] = Self::op_local_tee;
+        handlers[Op::GlobalGet { global_idx: 0 }.opcode()] = Self::op_global_get;
+        handlers[Op::GlobalSet { global_idx: 0 }.opcode()] = Self::op_global_set;
+        handlers[Op::I32Load { mem: 0, offset: 0 }.opcode()] = Self::op_i32_load;
+        handlers[Op::I64Load { mem: 0, offset: 0 }.opcode()] = Self::op_i64_load;
+        handlers[Op::F32Load { mem: 0, offset: 0 }.opcode()] = Self::op_f32_load;
+        handlers[Op::F64Load { mem: 0, offset: 0 }.opcode()] = Self::op_f64_load;
+        handlers[Op::I32Store { mem: 0, offset: 0 }.opcode()] = Self::op_i32_store;
+        handlers[Op::I64Store { mem: 0, offset: 0 }.opcode()] = Self::op_i64_store;
+        handlers[Op::F32Store { mem: 0, offset: 0 }.opcode()] = Self::op_f32_store;
+        handlers[Op::F64Store { mem: 0, offset: 0 }.opcode()] = Self::op_f64_store;
+        handlers[Op::I32Const { value: 0 }.opcode()] = Self::op_i32_const;
+        handlers[Op::I64Const { value: 0 }.opcode()] = Self::op_i64_const;
+        handlers[Op::F32Const { value: 0.0 }.opcode()] = Self::op_f32_const;
+        handlers[Op::F64Const { value: 0.0 }.opcode()] = Self::op_f64_const;
+        handlers[Op::I32Unop(I32Unop::Eqz).opcode()] = Self::op_i32_unop;
+        handlers[Op::I32Binop(I32Binop::Add).opcode()] = Self::op_i32_binop;
+        handlers[Op::I64Unop(I64Unop::Eqz).opcode()] = Self::op_i64_unop;
+        handlers[Op::I64Binop(I64Binop::Add).opcode()] = Self::op_i64_binop;
+        handlers[Op::F32Unop(F32Unop::Neg).opcode()] = Self::op_f32_unop;
+        handlers[Op::F32Binop(F32Binop::Add).opcode()] = Self::op_f32_binop;
+        handlers[Op::F64Unop(F64Unop::Neg).opcode()] = Self::op_f64_unop;
+        handlers[Op::F64Binop(F64Binop::Add).opcode()] = Self::op_f64_binop;
+        handlers[Op::I32AddLocals { lhs: 0, rhs: 0 }.opcode()] = Self::op_i32_add_locals;
+        handlers[Op::I32AddConst { value: 0 }.opcode()] = Self::op_i32_add_const;
+        handlers[Op::I32CmpBrIf {
+            cmp: I32Binop::Eq,
+            branch: 0,
+        }
+        .opcode()] = Self::op_i32_cmp_br_if;
+        handlers
+    };
+
+    fn op_nop(&mut self, _: Op) -> Result<StepOutcome> {
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_loop(&mut self, op: Op) -> Result<StepOutcome> {
+        // a branch back to the loop runs this again
+        self.check_epoch()?;
+        if let Some(results) = self.enter_loop_tiered() {
+            // the jit code ran the rest of the function
+            self.operand_stack.extend(results?);
+            become Self::op_return(self, op);
+        }
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_if(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::If { target } = op else {
+            unreachable!()
+        };
+        if self.pop_operand_stack().as_i32() == 0 {
+            self.set_pc(target as Pc);
+        } else {
+            self.inc_pc();
+        }
+        dispatch!(self)
+    }
+
+    /// The part of the block before an else or a catch clause is done.
+    fn op_jump(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::Jump { target } = op else {
+            unreachable!()
+        };
+        self.set_pc(target as Pc);
+        dispatch!(self)
+    }
+
+    fn op_br(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::Br { branch } = op else {
+            unreachable!()
+        };
+        self.run_br(branch);
+        dispatch!(self)
+    }
+
+    fn op_br_if(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::BrIf { branch } = op else {
+            unreachable!()
+        };
+        if self.pop_operand_stack().as_i32() != 0 {
+            self.run_br(branch);
+        } else {
+            self.inc_pc();
+        }
+        dispatch!(self)
+    }
+
+    fn op_br_table(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::BrTable { first, len } = op else {
+            unreachable!()
+        };
+        // the default target follows the others
+        let index = self.pop_operand_stack().as_i32() as u32;
+        self.run_br(first + index.min(len));
+        dispatch!(self)
+    }
+
+    /// Leave the innermost frame with its results, the caller continues after
+    /// its call.
+    fn op_return(&mut self, _: Op) -> Result<StepOutcome> {
+        // the last result is on the top of the stack
+        let nresults = self.func().get_sig().results().len();
+        let results = self.pop_operand_values(nresults);
+        let Some(caller) = self.callers.pop() else {
+            self.returned = true;
+            return Ok(StepOutcome::Returned(results));
+        };
+        self.restore_frame(caller);
+        self.operand_stack.extend(results);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_call(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::Call { func_idx } = op else {
+            unreachable!()
+        };
+        let entered = self.run_call(func_idx);
+        self.finish_call(entered)?;
+        dispatch!(self)
+    }
+
+    fn op_call_indirect(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::CallIndirect {
+            type_index,
+            table_index,
+        } = op
+        else {
+            unreachable!()
+        };
+        let entered = self.run_call_indirect(type_index, table_index);
+        self.finish_call(entered)?;
+        dispatch!(self)
+    }
+
+    /// The callee of an entered frame runs from its first op, its caller
+    /// continues once it returns.
+    fn finish_call(&mut self, entered: Result<bool>) -> Result<()> {
+        match entered {
+            Ok(true) => self.start_frame(),
+            Ok(false) => {
+                self.inc_pc();
+                Ok(())
             }
+            Err(e) => self.catch_exception(e),
         }
+    }
 
-        if self.func.get_sig().results().is_empty() {
-            Ok(None)
+    fn op_drop(&mut self, _: Op) -> Result<StepOutcome> {
+        self.pop_operand_stack();
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_select(&mut self, _: Op) -> Result<StepOutcome> {
+        let cond = self.pop_operand_stack().as_i32();
+        let b = self.pop_operand_stack();
+        let a = self.pop_operand_stack();
+        self.push_operand_stack(if cond != 0 { a } else { b });
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_local_get(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::LocalGet { local_idx } = op else {
+            unreachable!()
+        };
+        let local = self.locals[local_idx as usize];
+        self.push_operand_stack(local);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_local_set(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::LocalSet { local_idx } = op else {
+            unreachable!()
+        };
+        let value = self.pop_operand_stack();
+        self.locals[local_idx as usize] = value;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_local_tee(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::LocalTee { local_idx } = op else {
+            unreachable!()
+        };
+        let value = self.pop_operand_stack();
+        self.locals[local_idx as usize] = value;
+        self.push_operand_stack(value);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_global_get(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::GlobalGet { global_idx } = op else {
+            unreachable!()
+        };
+        self.run_global_get(global_idx)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_global_set(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::GlobalSet { global_idx } = op else {
+            unreachable!()
+        };
+        self.run_global_set(global_idx)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i32_load(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32Load { mem, offset } = op else {
+            unreachable!()
+        };
+        let v = self.run_i32_load(mem, offset, 4)?;
+        self.push_operand_stack(v);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i64_load(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I64Load { mem, offset } = op else {
+            unreachable!()
+        };
+        let v = self.run_i64_load(mem, offset, 8)?;
+        self.push_operand_stack(v);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f32_load(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F32Load { mem, offset } = op else {
+            unreachable!()
+        };
+        let v = self.run_f32_load(mem, offset)?;
+        self.push_operand_stack(v);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f64_load(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F64Load { mem, offset } = op else {
+            unreachable!()
+        };
+        let v = self.run_f64_load(mem, offset)?;
+        self.push_operand_stack(v);
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i32_store(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32Store { mem, offset } = op else {
+            unreachable!()
+        };
+        self.run_i32_store(mem, offset, 4)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i64_store(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I64Store { mem, offset } = op else {
+            unreachable!()
+        };
+        self.run_i64_store(mem, offset, 8)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f32_store(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F32Store { mem, offset } = op else {
+            unreachable!()
+        };
+        self.run_f32_store(mem, offset)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f64_store(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F64Store { mem, offset } = op else {
+            unreachable!()
+        };
+        self.run_f64_store(mem, offset)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i32_const(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32Const { value } = op else {
+            unreachable!()
+        };
+        self.push_operand_stack(WasmValue::I32(value));
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i64_const(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I64Const { value } = op else {
+            unreachable!()
+        };
+        self.push_operand_stack(WasmValue::I64(value));
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f32_const(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F32Const { value } = op else {
+            unreachable!()
+        };
+        self.push_operand_stack(WasmValue::F32(value));
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f64_const(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F64Const { value } = op else {
+            unreachable!()
+        };
+        self.push_operand_stack(WasmValue::F64(value));
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i32_unop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32Unop(i32_unop) = op else {
+            unreachable!()
+        };
+        self.run_i32_unop(&i32_unop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i32_binop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32Binop(i32_binop) = op else {
+            unreachable!()
+        };
+        self.run_i32_binop(&i32_binop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i64_unop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I64Unop(i64_unop) = op else {
+            unreachable!()
+        };
+        self.run_i64_unop(&i64_unop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i64_binop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I64Binop(i64_binop) = op else {
+            unreachable!()
+        };
+        self.run_i64_binop(&i64_binop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f32_unop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F32Unop(f32_unop) = op else {
+            unreachable!()
+        };
+        self.run_f32_unop(&f32_unop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f32_binop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F32Binop(f32_binop) = op else {
+            unreachable!()
+        };
+        self.run_f32_binop(&f32_binop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f64_unop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F64Unop(f64_unop) = op else {
+            unreachable!()
+        };
+        self.run_f64_unop(&f64_unop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_f64_binop(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::F64Binop(f64_binop) = op else {
+            unreachable!()
+        };
+        self.run_f64_binop(&f64_binop)?;
+        self.inc_pc();
+        dispatch!(self)
+    }
+
+    fn op_i32_add_locals(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32AddLocals { lhs, rhs } = op else {
+            unreachable!()
+        };
+        let a = self.locals[lhs as usize].as_i32();
+        let b = self.locals[rhs as usize].as_i32();
+        self.push_operand_stack(WasmValue::I32(a.wrapping_add(b)));
+        self.set_pc(self.pc + 3);
+        dispatch!(self)
+    }
+
+    fn op_i32_add_const(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32AddConst { value } = op else {
+            unreachable!()
+        };
+        let a = self.pop_operand_stack().as_i32();
+        self.push_operand_stack(WasmValue::I32(a.wrapping_add(value)));
+        self.set_pc(self.pc + 2);
+        dispatch!(self)
+    }
+
+    fn op_i32_cmp_br_if(&mut self, op: Op) -> Result<StepOutcome> {
+        let Op::I32CmpBrIf { cmp, branch } = op else {
+            unreachable!()
+        };
+        let b = self.pop_operand_stack().as_i32();
+        let a = self.pop_operand_stack().as_i32();
+        if i32_compare(&cmp, a, b) {
+            self.run_br(branch);
         } else {
-            Ok(Some(self.pop_operand_stack()))
+            self.set_pc(self.pc + 2);
         }
+        dispatch!(self)
+    }
+
+    /// The handler of the ops without one of their own.
+    fn op_other(&mut self, op: Op) -> Result<StepOutcome> {
+        match op {
+            Op::Unreachable => {
+                Err(TrapKind::Unreachable)?;
+            }
+            Op::Try { handler } => {
+                self.control_flow_frames
+                    .push(BlockControlFlowFrame::Try { handler });
+                self.inc_pc();
+            }
+            Op::EndTry => {
+                self.control_flow_frames.pop();
+                self.inc_pc();
+            }
+            Op::Throw { tag_index } => {
+                self.run_throw(tag_index)?;
+            }
+            Op::Rethrow { depth } => {
+                self.run_rethrow(depth)?;
+            }
+            Op::ReturnCall { func_idx } => {
+                if self.run_return_call(func_idx)? {
+                    become Self::op_return(self, op);
+                }
+            }
+            Op::ReturnCallIndirect {
+                type_index,
+                table_index,
+            } => {
+                let callee_index = self.indirect_callee(type_index, table_index)?;
+                if self.run_return_call(callee_index)? {
+                    become Self::op_return(self, op);
+                }
+            }
+            Op::TableGet { table } => {
+                self.run_table_get(table)?;
+                self.inc_pc();
+            }
+            Op::TableSet { table } => {
+                self.run_table_set(table)?;
+                self.inc_pc();
+            }
+            Op::TableInit { elem_index, table } => {
+                self.run_table_init(elem_index, table)?;
+                self.inc_pc();
+            }
+            Op::ElemDrop { elem_index } => {
+                self.run_elem_drop(elem_index)?;
+                self.inc_pc();
+            }
+            Op::TableGrow { table } => {
+                self.run_table_grow(table)?;
+                self.inc_pc();
+            }
+            Op::TableSize { table } => {
+                self.run_table_size(table)?;
+                self.inc_pc();
+            }
+            Op::TableFill { table } => {
+                self.run_table_fill(table)?;
+                self.inc_pc();
+            }
+            Op::RefNull { ty } => {
+                self.push_operand_stack(WasmValue::null_ref(&ty));
+                self.inc_pc();
+            }
+            Op::RefIsNull => {
+                let v = self.pop_operand_stack();
+                self.push_operand_stack(WasmValue::I32(v.as_ref().is_none() as i32));
+                self.inc_pc();
+            }
+            Op::RefFunc { func_idx } => {
+                self.push_operand_stack(WasmValue::FuncRef(Some(func_idx)));
+                self.inc_pc();
+            }
+            Op::I32Load8S { mem, offset } => {
+                let v = self.run_i32_load(mem, offset, 1)?.as_i32();
+                let v = ((v & 0xFF) as i8) as i32;
+                self.push_operand_stack(WasmValue::I32(v));
+                self.inc_pc();
+            }
+            Op::I32Load8U { mem, offset } => {
+                let v = self.run_i32_load(mem, offset, 1)?.as_i32();
+                let v = v & 0xFF;
+                self.push_operand_stack(WasmValue::I32(v));
+                self.inc_pc();
+            }
+            Op::I32Load16S { mem, offset } => {
+                let v = self.run_i32_load(mem, offset, 2)?.as_i32();
+                let v = ((v & 0xFFFF) as i16) as i32;
+                self.push_operand_stack(WasmValue::I32(v));
+                self.inc_pc();
+            }
+            Op::I32Load16U { mem, offset } => {
+                let v = self.run_i32_load(mem, offset, 2)?.as_i32();
+                let v = v & 0xFFFF;
+                self.push_operand_stack(WasmValue::I32(v));
+                self.inc_pc();
+            }
+            Op::I64Load8S { mem, offset } => {
+                let v = self.run_i64_load(mem, offset, 1)?.as_i64();
+                let v = ((v & 0xFF) as i8) as i64;
+                self.push_operand_stack(WasmValue::I64(v));
+                self.inc_pc();
+            }
+            Op::I64Load8U { mem, offset } => {
+                let v = self.run_i64_load(mem, offset, 1)?;
+                self.push_operand_stack(v);
+                self.inc_pc();
+            }
+            Op::I64Load16S { mem, offset } => {
+                let v = self.run_i64_load(mem, offset, 2)?.as_i64();
+                let v = ((v & 0xFFFF) as i16) as i64;
+                self.push_operand_stack(WasmValue::I64(v));
+                self.inc_pc();
+            }
+            Op::I64Load16U { mem, offset } => {
+                let v = self.run_i64_load(mem, offset, 2)?;
+                self.push_operand_stack(v);
+                self.inc_pc();
+            }
+            Op::I64Load32S { mem, offset } => {
+                let v = self.run_i64_load(mem, offset, 4)?.as_i64();
+                let v = ((v & 0xFFFFFFFF) as i32) as i64;
+                self.push_operand_stack(WasmValue::I64(v));
+                self.inc_pc();
+            }
+            Op::I64Load32U { mem, offset } => {
+                let v = self.run_i64_load(mem, offset, 4)?;
+                self.push_operand_stack(v);
+                self.inc_pc();
+            }
+            Op::I32Store8 { mem, offset } => {
+                self.run_i32_store(mem, offset, 1)?;
+                self.inc_pc();
+            }
+            Op::I32Store16 { mem, offset } => {
+                self.run_i32_store(mem, offset, 2)?;
+                self.inc_pc();
+            }
+            Op::I64Store8 { mem, offset } => {
+                self.run_i64_store(mem, offset, 1)?;
+                self.inc_pc();
+            }
+            Op::I64Store16 { mem, offset } => {
+                self.run_i64_store(mem, offset, 2)?;
+                self.inc_pc();
+            }
+            Op::I64Store32 { mem, offset } => {
+                self.run_i64_store(mem, offset, 4)?;
+                self.inc_pc();
+            }
+            Op::MemorySize { mem } => {
+                self.run_memory_size(mem)?;
+                self.inc_pc();
+            }
+            Op::MemoryGrow { mem } => {
+                self.run_memory_grow(mem)?;
+                self.inc_pc();
+            }
+            Op::MemoryCopy { dst_mem, src_mem } => {
+                self.run_memory_copy(dst_mem, src_mem)?;
+                self.inc_pc();
+            }
+            Op::MemoryFill { mem } => {
+                self.run_memory_fill(mem)?;
+                self.inc_pc();
+            }
+            Op::MemoryInit { data_index, mem } => {
+                self.run_memory_init(data_index, mem)?;
+                self.inc_pc();
+            }
+            Op::DataDrop { data_index } => {
+                self.run_data_drop(data_index)?;
+                self.inc_pc();
+            }
+            Op::V128Load { mem, offset } => {
+                let v = self.run_v128_load(mem, offset)?;
+                self.push_operand_stack(v);
+                self.inc_pc();
+            }
+            Op::V128Store { mem, offset } => {
+                self.run_v128_store(mem, offset)?;
+                self.inc_pc();
+            }
+            Op::V128LoadExtend {
+                shape,
+                signed,
+                mem,
+                offset,
+            } => {
+                let bytes = u64::from_le_bytes(self.load(mem, offset)?);
+                let v = v128_load_extend(shape, bytes, signed);
+                self.push_operand_stack(WasmValue::V128(v));
+                self.inc_pc();
+            }
+            Op::V128LoadSplat { shape, mem, offset } => {
+                let x = self.load_v128_lane(mem, offset, shape)?;
+                self.push_operand_stack(WasmValue::V128(v128_splat_lane(shape, x)));
+                self.inc_pc();
+            }
+            Op::V128LoadZero { shape, mem, offset } => {
+                let x = self.load_v128_lane(mem, offset, shape)?;
+                self.push_operand_stack(WasmValue::V128(v128_with_lane(shape, 0, 0, x)));
+                self.inc_pc();
+            }
+            Op::V128LoadLane {
+                shape,
+                lane,
+                mem,
+                offset,
+            } => {
+                let v = self.pop_operand_stack().as_v128();
+                let x = self.load_v128_lane(mem, offset, shape)?;
+                self.push_operand_stack(WasmValue::V128(v128_with_lane(shape, v, lane, x)));
+                self.inc_pc();
+            }
+            Op::V128StoreLane {
+                shape,
+                lane,
+                mem,
+                offset,
+            } => {
+                let v = self.pop_operand_stack().as_v128();
+                self.store_v128_lane(mem, offset, shape, v128_lane(shape, v, lane))?;
+                self.inc_pc();
+            }
+            Op::V128Const { index } => {
+                let value = self.code.v128s[index as usize];
+                self.push_operand_stack(WasmValue::V128(value));
+                self.inc_pc();
+            }
+            Op::I8x16Shuffle { index } => {
+                let lanes = self.code.v128s[index as usize].to_le_bytes();
+                let b = self.pop_operand_stack().as_v128();
+                let a = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(WasmValue::V128(i8x16_shuffle(a, b, &lanes)));
+                self.inc_pc();
+            }
+            Op::V128Splat { shape } => {
+                self.run_v128_splat(shape)?;
+                self.inc_pc();
+            }
+            Op::V128ExtractLane {
+                shape,
+                lane,
+                signed,
+            } => {
+                let v = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(v128_extract_lane(shape, v, lane, signed));
+                self.inc_pc();
+            }
+            Op::V128ReplaceLane { shape, lane } => {
+                self.run_v128_replace_lane(shape, lane)?;
+                self.inc_pc();
+            }
+            Op::V128Bitselect => {
+                let mask = self.pop_operand_stack().as_v128();
+                let b = self.pop_operand_stack().as_v128();
+                let a = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(WasmValue::V128(v128_bitselect(a, b, mask)));
+                self.inc_pc();
+            }
+            Op::V128Unop(v128_unop_kind) => {
+                let a = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(WasmValue::V128(v128_unop(&v128_unop_kind, a)));
+                self.inc_pc();
+            }
+            Op::V128Binop(v128_binop_kind) => {
+                let b = self.pop_operand_stack().as_v128();
+                let a = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(WasmValue::V128(v128_binop(&v128_binop_kind, a, b)));
+                self.inc_pc();
+            }
+            Op::V128Shift(v128_shift_kind) => {
+                let count = self.pop_operand_stack().as_i32() as u32;
+                let a = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(WasmValue::V128(v128_shift(&v128_shift_kind, a, count)));
+                self.inc_pc();
+            }
+            Op::V128Test(v128_test_kind) => {
+                let a = self.pop_operand_stack().as_v128();
+                self.push_operand_stack(WasmValue::I32(v128_test(&v128_test_kind, a)));
+                self.inc_pc();
+            }
+            _ => unreachable!("{op:?} has a handler of its own"),
+        }
+        dispatch!(self)
     }
 }
 
 impl<'a> WasmFunctionExecutorImpl<'a> {
+    /// An executor of the function at `func_index`, which must be defined in
+    /// `module`.
     pub fn new(
-        func: FuncDecl,
-        module: Rc<RefCell<WasmModule<'a>>>,
-        mem: Rc<RefCell<LinearMemory>>,
+        func_index: u32,
+        module: Rc<WasmModule<'a>>,
+        store: Rc<Store<'a>>,
         init_locals: Option<Vec<WasmValue>>,
     ) -> Self {
-        let locals = Self::setup_locals(init_locals, &func);
+        let func = &module.get_funcs()[func_index as usize];
+        let locals = Self::setup_locals(init_locals, func);
         Self {
-            func,
+            func_index,
+            code: func.get_code(),
             pc: 0,
-            mem,
             module,
+            store,
             locals,
-            control_flow_frames: VecDeque::new(),
-            operand_stack: VecDeque::new(),
+            control_flow_frames: Vec::new(),
+            operand_stack: Vec::with_capacity(OPERAND_STACK_CAPACITY),
+            callers: Vec::new(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            default_memory_maximum: DEFAULT_MEMORY_MAXIMUM,
+            max_memory_pages: u64::MAX,
+            max_table_elements: u64::MAX,
+            limiter: None,
+            fuel: Rc::new(Cell::new(None)),
+            epoch_deadline: None,
+            trace: false,
+            debugger: None,
+            record_frame_states: false,
+            canonicalize_nans: false,
+            tiering: None,
+            started: false,
+            single_step: false,
+            returned: false,
         }
     }
 
+    /// Set the depth at which calls trap with `StackExhausted`.
+    pub(crate) fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Let the memories declared without a maximum grow to `pages`.
+    pub(crate) fn with_default_memory_maximum(mut self, pages: u64) -> Self {
+        self.default_memory_maximum = pages;
+        self
+    }
+
+    /// Never let a memory grow past `pages`.
+    pub(crate) fn with_max_memory_pages(mut self, pages: u64) -> Self {
+        self.max_memory_pages = pages;
+        self
+    }
+
+    /// Never let a table grow past `elements`.
+    pub(crate) fn with_max_table_elements(mut self, elements: u64) -> Self {
+        self.max_table_elements = elements;
+        self
+    }
+
+    /// Ask `limiter` before growing a memory or a table.
+    pub(crate) fn with_limiter(mut self, limiter: Option<SharedLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Meter the execution with the instance's fuel.
+    pub(crate) fn with_fuel(mut self, fuel: Rc<Cell<Option<u64>>>) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Interrupt the execution once the epoch reaches the deadline.
+    pub(crate) fn with_epoch_deadline(mut self, epoch_deadline: Option<EpochDeadline>) -> Self {
+        self.epoch_deadline = epoch_deadline;
+        self
+    }
+
+    /// Log every executed instruction to stderr.
+    pub(crate) fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Hand every instruction to `debugger` before it is executed.
+    pub(crate) fn with_debugger(mut self, debugger: Option<Rc<RefCell<Debugger>>>) -> Self {
+        self.debugger = debugger;
+        self
+    }
+
+    /// Record the state of every frame a trap unwinds through in its
+    /// backtrace.
+    pub(crate) fn with_frame_states(mut self, record_frame_states: bool) -> Self {
+        self.record_frame_states = record_frame_states;
+        self
+    }
+
+    /// Canonicalize the NaNs produced by float arithmetic, so the results do
+    /// not depend on the NaN propagation of the host.
+    pub(crate) fn with_canonicalize_nans(mut self, canonicalize_nans: bool) -> Self {
+        self.canonicalize_nans = canonicalize_nans;
+        self
+    }
+
+    /// Count the calls of every function and run the hot ones as jit code.
+    pub(crate) fn with_tiering(mut self, tiering: Option<Rc<Tiering<'a>>>) -> Self {
+        self.tiering = tiering;
+        self
+    }
+
+    /// Continue from the frames of a checkpoint, the innermost one first,
+    /// instead of the start of the function. The callers must be at a call.
+    pub(crate) fn with_saved_frames(mut self, frames: &[SavedFrame]) -> Self {
+        if let Some((innermost, callers)) = frames.split_first() {
+            self.callers = callers.iter().rev().cloned().collect();
+            self.restore_frame(innermost.clone());
+        }
+        self
+    }
+
     // constructor helpers
     fn setup_locals(main_locals: Option<Vec<WasmValue>>, func: &FuncDecl) -> Vec<WasmValue> {
         let mut locals = main_locals.unwrap_or_default();
@@ -295,7 +1111,192 @@ impl<'a> WasmFunctionExecutorImpl<'a> {
     }
 }
 
-impl WasmFunctionExecutorImpl<'_> {
+impl<'a> WasmFunctionExecutorImpl<'a> {
+    fn func(&self) -> &FuncDecl {
+        &self.module.get_funcs()[self.func_index as usize]
+    }
+
+    /// The number of frames below the innermost one.
+    fn call_depth(&self) -> usize {
+        self.callers.len()
+    }
+
+    /// Make the frame of `func_index` with `locals` the innermost frame,
+    /// returns the frame it replaces.
+    fn enter_frame(&mut self, func_index: u32, locals: Vec<WasmValue>) -> SavedFrame {
+        self.started = false;
+        self.code = self.module.get_funcs()[func_index as usize].get_code();
+        SavedFrame {
+            func_index: std::mem::replace(&mut self.func_index, func_index),
+            pc: std::mem::replace(&mut self.pc, 0),
+            locals: std::mem::replace(&mut self.locals, locals),
+            operand_stack: std::mem::replace(
+                &mut self.operand_stack,
+                Vec::with_capacity(OPERAND_STACK_CAPACITY),
+            ),
+            control_flow_frames: std::mem::take(&mut self.control_flow_frames),
+        }
+    }
+
+    /// Make `frame` the innermost frame again.
+    fn restore_frame(&mut self, frame: SavedFrame) {
+        self.func_index = frame.func_index;
+        self.code = self.func().get_code();
+        self.pc = frame.pc;
+        self.locals = frame.locals;
+        self.operand_stack = frame.operand_stack;
+        // a function interrupted on entry sets up its frame again
+        self.started = !frame.control_flow_frames.is_empty();
+        self.control_flow_frames = frame.control_flow_frames;
+    }
+
+    fn push_function_frame(&mut self) {
+        self.control_flow_frames
+            .push(BlockControlFlowFrame::Function);
+    }
+
+    /// Log the instruction at pc and hand it to the debugger, both see the
+    /// decoded instruction rather than its op.
+    fn observe_instruction(&self) -> Result<()> {
+        let module = &self.module;
+        let inst = self.func().get_inst(self.pc);
+        if self.trace {
+            trace_instruction(
+                self.func_index,
+                module.get_func_name(self.func_index),
+                self.pc,
+                module
+                    .get_source_location(self.func_index, self.pc)
+                    .as_ref(),
+                inst,
+                &self.operand_stack,
+            );
+        }
+        if let Some(debugger) = &self.debugger {
+            debugger.borrow_mut().on_instruction(self, inst)?;
+        }
+        Ok(())
+    }
+
+    fn consume_fuel(&mut self) -> Result<()> {
+        if let Some(fuel) = self.fuel.get() {
+            if fuel == 0 {
+                return Err(TrapKind::FuelExhausted.into());
+            }
+            self.fuel.set(Some(fuel - 1));
+        }
+        Ok(())
+    }
+
+    fn check_epoch(&self) -> Result<()> {
+        match &self.epoch_deadline {
+            Some(epoch_deadline) if epoch_deadline.reached() => Err(TrapKind::Interrupted.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    pub(crate) fn pc(&self) -> Pc {
+        self.pc
+    }
+
+    pub(crate) fn locals(&self) -> &[WasmValue] {
+        &self.locals
+    }
+
+    /// The operand stack, topmost value last.
+    pub(crate) fn operand_stack(&self) -> &[WasmValue] {
+        &self.operand_stack
+    }
+
+    /// Copy `len` bytes of memory `mem` from `addr`, `None` if they are out
+    /// of bounds.
+    pub(crate) fn read_memory(&self, mem: u32, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let mem = self.store.mems.get(mem as usize)?.borrow();
+        let end = addr.checked_add(len)?;
+        mem.data.get(addr..end).map(|bytes| bytes.to_vec())
+    }
+
+    /// The function exported as `name`, or else named `name` in the name
+    /// section.
+    pub(crate) fn func_index_by_name(&self, name: &str) -> Option<u32> {
+        let module = &self.module;
+        module
+            .get_export_func_index(name)
+            .or_else(|| module.get_func_index_by_name(name))
+    }
+
+    pub(crate) fn func_name(&self) -> Option<String> {
+        let module = &self.module;
+        module.get_func_name(self.func_index).map(str::to_string)
+    }
+
+    /// The wasm call stack at the current instruction, the innermost frame
+    /// first.
+    pub(crate) fn call_stack(&self) -> Vec<WasmFrame> {
+        std::iter::once((self.func_index, self.pc))
+            .chain(
+                self.callers
+                    .iter()
+                    .rev()
+                    .map(|caller| (caller.func_index, caller.pc)),
+            )
+            .map(|(func_index, pc)| self.wasm_frame(func_index, pc))
+            .collect()
+    }
+
+    fn wasm_frame(&self, func_index: u32, pc: Pc) -> WasmFrame {
+        let module = &self.module;
+        WasmFrame {
+            func_index,
+            func_name: module.get_func_name(func_index).map(str::to_string),
+            pc: Some(pc),
+            location: module.get_source_location(func_index, pc),
+        }
+    }
+
+    /// Add the innermost frame to the backtrace of a trap that unwinds
+    /// through it, the backtrace is created by the frame that trapped.
+    fn record_trap_frame(&self, mut err: anyhow::Error, pc: Pc) -> anyhow::Error {
+        if err.downcast_ref::<TrapKind>().is_none() {
+            return err;
+        }
+        let frame = self.wasm_frame(self.func_index, pc);
+        let state = self.record_frame_states.then(|| SavedFrame {
+            func_index: self.func_index,
+            pc,
+            locals: self.locals.clone(),
+            operand_stack: self.operand_stack.clone(),
+            control_flow_frames: self.control_flow_frames.clone(),
+        });
+        match err.downcast_mut::<WasmBacktrace>() {
+            Some(backtrace) => {
+                backtrace.push_caller(frame, state);
+                err
+            }
+            None => {
+                let mut backtrace = WasmBacktrace::default();
+                backtrace.push_caller(frame, state);
+                err.context(backtrace)
+            }
+        }
+    }
+
+    /// The source line of the instruction at `pc` in the DWARF debug info.
+    pub(crate) fn source_location(&self, pc: Pc) -> Option<SourceLocation> {
+        self.module.get_source_location(self.func_index, pc)
+    }
+
+    pub(crate) fn local_name(&self, local: u32) -> Option<String> {
+        let module = &self.module;
+        module
+            .get_local_name(self.func_index, local)
+            .map(str::to_string)
+    }
+
     pub fn inc_pc(&mut self) {
         self.pc += 1;
     }
@@ -305,188 +1306,322 @@ impl WasmFunctionExecutorImpl<'_> {
     }
 
     pub fn push_operand_stack(&mut self, value: WasmValue) {
-        self.operand_stack.push_front(value);
+        self.operand_stack.push(value);
     }
 
     pub fn pop_operand_stack(&mut self) -> WasmValue {
-        self.operand_stack
-            .pop_front()
-            .expect("operand stack underflow")
+        self.operand_stack.pop().expect("operand stack underflow")
     }
 
-    pub fn mem_size_in_pages(&self) -> usize {
-        self.mem.borrow().size() / WASM_DEFAULT_PAGE_SIZE_BYTE
+    /// Pop the `n` topmost values, the topmost one last.
+    fn pop_operand_values(&mut self, n: usize) -> Vec<WasmValue> {
+        let height = self
+            .operand_stack
+            .len()
+            .checked_sub(n)
+            .expect("operand stack underflow");
+        self.operand_stack.split_off(height)
     }
 
-    pub fn mem_size_in_bytes(&self) -> usize {
-        self.mem.borrow().size()
+    pub fn mem_size_in_pages(&self, mem: u32) -> usize {
+        self.mem_size_in_bytes(mem) / WASM_DEFAULT_PAGE_SIZE_BYTE
     }
 
-    pub fn grow_mem(&mut self, additional_pages: u32) {
-        self.mem.borrow_mut().grow(additional_pages);
+    pub fn mem_size_in_bytes(&self, mem: u32) -> usize {
+        self.store.mems[mem as usize].borrow().size()
     }
 
-    pub fn call_func(&mut self, func: FuncDecl) -> Option<WasmValue> {
-        // prepare the argument locals
-        let mut args = VecDeque::new();
-        for param in func.get_sig().params().iter().rev() {
-            let v = self.pop_operand_stack();
-            match param {
-                ValType::I32 => {
-                    if !matches!(v, WasmValue::I32(_)) {
-                        panic!("call_func: invalid argument type");
-                    }
-                }
-                ValType::F64 => {
-                    if !matches!(v, WasmValue::F64(_)) {
-                        panic!("call_func: invalid argument type");
-                    }
-                }
-                _ => panic!("unsupported param type"),
-            }
-            args.push_front(v);
+    /// Grow memory `mem` by `additional_pages`, returns false if they cannot
+    /// be mapped.
+    pub fn grow_mem(&self, mem: u32, additional_pages: u64) -> bool {
+        self.store.mems[mem as usize]
+            .borrow_mut()
+            .grow(additional_pages)
+    }
+
+    /// Call the function at `func_index` defined in the module, with the
+    /// arguments on the operand stack. Returns whether the frame of the
+    /// callee was entered, a hot callee runs as jit code right away and its
+    /// results are pushed instead.
+    pub fn call_func(&mut self, func_index: u32) -> Result<bool> {
+        if self.call_depth() + 1 >= self.max_call_depth {
+            return Err(TrapKind::StackExhausted.into());
         }
 
-        let mut executor = WasmFunctionExecutorImpl::new(
-            func,
-            Rc::clone(&self.module),
-            Rc::clone(&self.mem),
-            Some(args.into()),
-        );
+        let module = Rc::clone(&self.module);
+        let func = &module.get_funcs()[func_index as usize];
+        let args = self.pop_call_args(func)?;
+        if let Some(results) = self.call_tiered(func_index, &args) {
+            self.operand_stack.extend(results?);
+            return Ok(false);
+        }
+        let caller = self.enter_frame(func_index, Self::setup_locals(Some(args), func));
+        self.callers.push(caller);
+        Ok(true)
+    }
 
-        executor.execute().unwrap()
+    /// Run the callee as jit code if it is hot.
+    fn call_tiered(&self, func_index: u32, args: &[WasmValue]) -> Option<Result<Vec<WasmValue>>> {
+        self.tiering()?.call(func_index, args, self.call_depth())
+    }
+
+    /// Run the rest of the function as jit code from the loop at pc if the
+    /// loop is hot, the jit code takes over the operand stack.
+    fn enter_loop_tiered(&mut self) -> Option<Result<Vec<WasmValue>>> {
+        let results = self.tiering()?.enter_loop(
+            self.func_index,
+            self.pc,
+            &self.locals,
+            &self.operand_stack,
+            self.call_depth(),
+        )?;
+        // the jit code ran the rest of the function on it
+        self.operand_stack.clear();
+        Some(results)
+    }
+
+    /// The tiering of the instance, unless the execution is metered,
+    /// interruptible or observed, which the jit code would not honor.
+    fn tiering(&self) -> Option<&Tiering<'a>> {
+        if self.fuel.get().is_some()
+            || self.epoch_deadline.is_some()
+            || self.trace
+            || self.debugger.is_some()
+            || self.record_frame_states
+        {
+            return None;
+        }
+        self.tiering.as_deref()
+    }
+
+    /// Pop the arguments of `func` from the operand stack, the first argument
+    /// comes first.
+    fn pop_call_args(&mut self, func: &FuncDecl) -> Result<Vec<WasmValue>> {
+        let params = func.get_sig().params();
+        let args = self.pop_operand_values(params.len());
+        if !args.iter().zip(params).all(|(v, param)| v.has_type(param)) {
+            return Err(anyhow!("call_func: invalid argument type"));
+        }
+
+        Ok(args)
     }
 }
 
 /// Instruction execution
 impl WasmFunctionExecutorImpl<'_> {
-    fn run_call(&mut self, func_idx: u32) -> Result<()> {
+    /// Returns whether the frame of the callee was entered, host functions
+    /// run to completion and push their results, see `call_func`.
+    fn run_call(&mut self, func_idx: u32) -> Result<bool> {
         // first try to run host function
         let runned = self.try_run_host_func(func_idx)?;
         if runned {
-            return Ok(());
+            return Ok(false);
         }
 
-        let module = self.module.borrow();
-        let func = module.get_func(func_idx).unwrap().clone();
-        drop(module);
+        if self.module.get_func(func_idx).is_none() {
+            return Err(anyhow!("function {} not found", func_idx));
+        }
 
-        let v = self.call_func(func);
-        if let Some(v) = v {
-            self.push_operand_stack(v);
+        self.call_func(func_idx)
+    }
+
+    fn run_call_indirect(&mut self, type_index: u32, table_index: u32) -> Result<bool> {
+        let callee_index = self.indirect_callee(type_index, table_index)?;
+        self.run_call(callee_index)
+    }
+
+    /// Replace the current frame with the callee instead of entering a new
+    /// one, returns whether the current function is done, which is only the
+    /// case for host functions.
+    fn run_return_call(&mut self, func_idx: u32) -> Result<bool> {
+        let runned = self.try_run_host_func(func_idx)?;
+        if runned {
+            return Ok(true);
+        }
+
+        let module = Rc::clone(&self.module);
+        let func = module
+            .get_func(func_idx)
+            .ok_or_else(|| anyhow!("function {} not found", func_idx))?;
+
+        let args = self.pop_call_args(func)?;
+        self.locals = Self::setup_locals(Some(args), func);
+        self.func_index = func_idx;
+        self.code = func.get_code();
+        self.pc = 0;
+        self.operand_stack.clear();
+        self.control_flow_frames.clear();
+        self.push_function_frame();
+
+        Ok(false)
+    }
+
+    /// Pop the element index and return the function it refers to, after
+    /// checking its signature. The last callee that passed the check at this
+    /// call site is not checked again.
+    fn indirect_callee(&mut self, type_index: u32, table_index: u32) -> Result<u32> {
+        let callee_index_in_table = self.pop_operand_stack().as_i32() as u32;
+
+        // get the callee which we want to call
+        let callee_index = self
+            .table_ref(table_index, callee_index_in_table)
+            .map_err(|_| TrapKind::UndefinedElement)?
+            .as_ref()
+            .ok_or(TrapKind::UninitializedElement)?;
+        if self.store.cached_indirect_callee(self.func_index, self.pc) == Some(callee_index) {
+            return Ok(callee_index);
         }
+
+        // check callee signature, make sure it matches the expected signature
+        let module_ref = &self.module;
+        let expected_sig = module_ref
+            .get_sig(type_index)
+            .ok_or_else(|| anyhow!("call_indirect: unknown type {}", type_index))?;
+        let actual_sig = module_ref
+            .get_func(callee_index)
+            .ok_or_else(|| anyhow!("call_indirect: function {} not found", callee_index))?
+            .get_sig();
+
+        if expected_sig != actual_sig {
+            return Err(TrapKind::CallIndirectMismatch.into());
+        }
+        self.store
+            .cache_indirect_callee(self.func_index, self.pc, callee_index);
+
+        Ok(callee_index)
+    }
+
+    fn table_ref(&self, table_index: u32, elem_index: u32) -> Result<WasmValue> {
+        let tables = self.store.tables.borrow();
+        let table = tables
+            .get(table_index as usize)
+            .ok_or_else(|| anyhow!("invalid table index"))?;
+        table
+            .elems
+            .get(elem_index as usize)
+            .copied()
+            .ok_or_else(|| TrapKind::OutOfBoundsTable.into())
+    }
+
+    fn run_table_get(&mut self, table_index: u32) -> Result<()> {
+        let elem_index = self.pop_operand_stack().as_i32() as u32;
+        let value = self.table_ref(table_index, elem_index)?;
+        self.push_operand_stack(value);
         Ok(())
     }
 
-    fn run_call_indirect(&mut self, type_index: u32, table_index: u32) -> Result<()> {
-        let callee_index_in_table = self.pop_operand_stack().as_i32();
+    fn run_table_set(&mut self, table_index: u32) -> Result<()> {
+        let value = self.pop_operand_stack();
+        let elem_index = self.pop_operand_stack().as_i32() as u32;
+
+        let mut tables = self.store.tables.borrow_mut();
+        let table = tables
+            .get_mut(table_index as usize)
+            .ok_or_else(|| anyhow!("table.set: invalid table index"))?;
+        let slot = table
+            .elems
+            .get_mut(elem_index as usize)
+            .ok_or(TrapKind::OutOfBoundsTable)?;
+        *slot = value;
 
-        let module_ref = self.module.borrow();
+        Ok(())
+    }
 
-        // get the corresponding element segment for the funcref table
-        let elem = module_ref
-            .get_elems()
-            .iter()
-            .find(|e| match &e.kind {
-                wasmparser::ElementKind::Passive => {
-                    panic!("passive element segment not implemented")
-                }
-                wasmparser::ElementKind::Active {
-                    table_index: i,
-                    offset_expr,
-                } => {
-                    if let Some(idx) = i {
-                        *idx == table_index
-                    } else {
-                        // parse the offset expression
-                        let mut reader = offset_expr.get_binary_reader();
-                        let op = reader.read_u8().expect(
-                            "invalid offset expression when parsing opcode, should be i32.const",
-                        );
-                        if op as u32 != WASM_OP_I32_CONST {
-                            panic!("invalid offset expression when parsing opcode, should be i32.const, op: {}", op);
-                        }
-                        reader
-                            .read_var_i32()
-                            .expect("invalid offset expression when parsing value of i32.const") as u32 == table_index
-                    }
-                }
-                wasmparser::ElementKind::Declared => {
-                    panic!("declared element segment not implemented")
-                }
-            })
-            .ok_or_else(|| anyhow!("element segment not found"))?;
+    fn run_table_init(&mut self, elem_index: u32, table_index: u32) -> Result<()> {
+        let n = self.pop_operand_stack().as_i32() as u32 as usize;
+        let src = self.pop_operand_stack().as_i32() as u32 as usize;
+        let dst = self.pop_operand_stack().as_i32() as u32 as usize;
+
+        let elem_segments = self.store.elem_segments.borrow();
+        let segment = elem_segments
+            .get(elem_index as usize)
+            .ok_or_else(|| anyhow!("table.init: invalid element index"))?;
+        let mut tables = self.store.tables.borrow_mut();
+        let table = tables
+            .get_mut(table_index as usize)
+            .ok_or_else(|| anyhow!("table.init: invalid table index"))?;
+
+        if src + n > segment.len() || dst + n > table.size() {
+            return Err(TrapKind::OutOfBoundsTable.into());
+        }
+
+        table.elems[dst..dst + n].copy_from_slice(&segment[src..src + n]);
+
+        Ok(())
+    }
 
-        // get the callee which we want to call
-        let func_indices = match &elem.items {
-            wasmparser::ElementItems::Functions(r) => r
-                .clone()
-                .into_iter()
-                .map(|i| i.expect("invalid function index"))
-                .collect::<Vec<_>>(),
-            _ => {
-                panic!("Should be function elements in the segment");
-            }
+    fn run_elem_drop(&mut self, elem_index: u32) -> Result<()> {
+        let mut elem_segments = self.store.elem_segments.borrow_mut();
+        let segment = elem_segments
+            .get_mut(elem_index as usize)
+            .ok_or_else(|| anyhow!("elem.drop: invalid element index"))?;
+        segment.clear();
+
+        Ok(())
+    }
+
+    fn run_table_grow(&mut self, table_index: u32) -> Result<()> {
+        let n = self.pop_operand_stack().as_i32() as u32;
+        let init = self.pop_operand_stack();
+
+        let mut tables = self.store.tables.borrow_mut();
+        let table = tables
+            .get_mut(table_index as usize)
+            .ok_or_else(|| anyhow!("table.grow: invalid table index"))?;
+        let allowed = table.grown_size(n).is_some_and(|new_size| {
+            new_size as u64 <= self.max_table_elements
+                && self.limiter.as_ref().is_none_or(|limiter| {
+                    limiter.table_growing(table.size() as u64, new_size as u64, table.max)
+                })
+        });
+        // -1 if the table cannot grow
+        let old_size = if allowed {
+            table.grow(n, init).map_or(-1, |size| size as i32)
+        } else {
+            -1
         };
-        let callee_index = func_indices
-            .get(callee_index_in_table as usize)
-            .ok_or_else(|| anyhow!("callee index not found"))?;
+        drop(tables);
 
-        // check callee signature, make sure it matches the expected signature
-        let expected_sig = module_ref
-            .get_sig(type_index)
-            .expect("callee signature not found");
-        let actual_sig = module_ref
-            .get_func(*callee_index)
-            .expect("callee not found")
-            .get_sig();
+        self.push_operand_stack(WasmValue::I32(old_size));
+        Ok(())
+    }
 
-        if expected_sig != actual_sig {
-            return Err(anyhow!("call_indirect: callee signature mismatch"));
-        }
-        drop(module_ref);
+    fn run_table_size(&mut self, table_index: u32) -> Result<()> {
+        let size = self
+            .store
+            .tables
+            .borrow()
+            .get(table_index as usize)
+            .ok_or_else(|| anyhow!("table.size: invalid table index"))?
+            .size();
+        self.push_operand_stack(WasmValue::I32(size as i32));
+        Ok(())
+    }
 
-        // call it and push the result to the operand stack
-        self.run_call(*callee_index)?;
+    fn run_table_fill(&mut self, table_index: u32) -> Result<()> {
+        let n = self.pop_operand_stack().as_i32() as u32 as usize;
+        let value = self.pop_operand_stack();
+        let dst = self.pop_operand_stack().as_i32() as u32 as usize;
+
+        let mut tables = self.store.tables.borrow_mut();
+        let table = tables
+            .get_mut(table_index as usize)
+            .ok_or_else(|| anyhow!("table.fill: invalid table index"))?;
+        if dst + n > table.size() {
+            return Err(TrapKind::OutOfBoundsTable.into());
+        }
+        table.elems[dst..dst + n].fill(value);
 
         Ok(())
     }
 
     fn run_global_get(&mut self, global_index: u32) -> Result<()> {
-        let module = self.module.borrow();
-        let global = module
-            .get_globals()
+        let value = self
+            .store
+            .globals
             .get(global_index as usize)
-            .expect("global not found");
-
-        let value = match global.get_ty().content_type {
-            ValType::I32 => {
-                let init_expr = global.get_init_expr();
-                let mut reader = BinaryReader::new(init_expr, 0, WasmFeatures::all());
-                let op = reader.read_var_u32()?;
-                if op != WASM_OP_I32_CONST {
-                    return Err(anyhow!(
-                        "global.get: invalid init expr, should start with i32.const"
-                    ));
-                }
-                WasmValue::I32(reader.read_var_i32()?)
-            }
-            ValType::F64 => {
-                let init_expr = global.get_init_expr();
-                let mut reader = BinaryReader::new(init_expr, 0, WasmFeatures::all());
-                let op = reader.read_var_u32()?;
-                if op != WASM_OP_F64_CONST {
-                    return Err(anyhow!(
-                        "global.get: invalid init expr, should start with f64.const"
-                    ));
-                }
-                WasmValue::F64(f64::from(reader.read_f64()?))
-            }
-            _ => panic!("unsupported global type"),
-        };
-
-        drop(module);
-
+            .ok_or_else(|| anyhow!("global.get: global {} not found", global_index))?
+            .get();
         self.push_operand_stack(value);
 
         Ok(())
@@ -495,184 +1630,300 @@ impl WasmFunctionExecutorImpl<'_> {
     fn run_global_set(&mut self, global_index: u32) -> Result<()> {
         let value = self.pop_operand_stack();
 
-        let mut module = self.module.borrow_mut();
-        let global = module
-            .get_globals_mut()
-            .get_mut(global_index as usize)
-            .expect("global not found");
-
+        let global = self
+            .module
+            .get_globals()
+            .get(global_index as usize)
+            .ok_or_else(|| anyhow!("global.set: global {} not found", global_index))?;
         if !global.get_ty().mutable {
             return Err(anyhow!("global.set: global is not mutable"));
         }
-
-        match global.get_ty().content_type {
-            ValType::I32 => {
-                if !matches!(value, WasmValue::I32(_)) {
-                    return Err(anyhow!("global.set: invalid value type"));
-                }
-            }
-            ValType::F64 => {
-                if !matches!(value, WasmValue::F64(_)) {
-                    return Err(anyhow!("global.set: invalid value type"));
-                }
-            }
-            _ => panic!("unsupported global type"),
-        }
-
-        let mut init_expr = vec![];
-        match value {
-            WasmValue::I32(v) => {
-                init_expr.push(WASM_OP_I32_CONST as u8);
-                init_expr.extend(encode_i32leb(v));
-                init_expr.push(WASM_OP_END as u8);
-            }
-            WasmValue::F64(v) => {
-                init_expr.push(WASM_OP_F64_CONST as u8);
-                init_expr.extend(encode_f64(v));
-                init_expr.push(WASM_OP_END as u8);
-            }
+        if !value.has_type(&global.get_ty().content_type) {
+            return Err(anyhow!("global.set: invalid value type"));
         }
 
-        global.set_init_expr(init_expr);
+        self.store.globals[global_index as usize].set(value);
 
         Ok(())
     }
 
     fn run_memory_size(&mut self, mem: u32) -> Result<()> {
-        if mem != 0 {
-            return Err(anyhow!("memory.size: invalid memory index"));
-        }
-
-        let npages = self.mem_size_in_pages();
-        self.push_operand_stack(WasmValue::I32(i32::try_from(npages).unwrap()));
+        let memory64 = self.is_memory64(mem)?;
+        let npages = self.mem_size_in_pages(mem);
+        self.push_address(memory64, npages as i64);
 
         Ok(())
     }
 
     fn run_memory_grow(&mut self, mem: u32) -> Result<()> {
-        if mem != 0 {
-            return Err(anyhow!("memory.grow: invalid memory index"));
-        }
+        let memory64 = self.is_memory64(mem)?;
 
         // memory size limit
-        let module = self.module.borrow();
-        let mem_limit = module.get_memory().unwrap().maximum.unwrap();
-        drop(module);
+        let max = self.store.mems[mem as usize].borrow().max;
+        let mem_limit = max
+            .unwrap_or(self.default_memory_maximum)
+            .min(self.max_memory_pages);
+
+        let additional_pages = self.pop_address(memory64);
+        let old_pages = self.mem_size_in_pages(mem) as u64;
+        let allowed = |new_pages| {
+            new_pages <= mem_limit
+                && self
+                    .limiter
+                    .as_ref()
+                    .is_none_or(|limiter| limiter.memory_growing(old_pages, new_pages, max))
+        };
+        match old_pages.checked_add(additional_pages) {
+            // -1 as well if the host runs out of memory
+            Some(new_pages) if allowed(new_pages) && self.grow_mem(mem, additional_pages) => {
+                self.push_address(memory64, old_pages as i64);
+            }
+            _ => self.push_address(memory64, -1),
+        }
 
-        let additional_pages = self.pop_operand_stack().as_i32();
-        if self.mem_size_in_pages() + additional_pages as usize > mem_limit as usize
-            || additional_pages < 0
+        Ok(())
+    }
+
+    fn run_memory_copy(&mut self, dst_mem: u32, src_mem: u32) -> Result<()> {
+        let dst64 = self.is_memory64(dst_mem)?;
+        let src64 = self.is_memory64(src_mem)?;
+
+        // the length is an i64 only if both memories are memory64
+        let n = self.pop_address(dst64 && src64);
+        let src = self.pop_address(src64);
+        let dst = self.pop_address(dst64);
+
+        if !in_bounds(src, n, self.mem_size_in_bytes(src_mem) as u64)
+            || !in_bounds(dst, n, self.mem_size_in_bytes(dst_mem) as u64)
         {
-            self.push_operand_stack(WasmValue::I32(-1));
-        } else {
-            self.push_operand_stack(WasmValue::I32(
-                i32::try_from(self.mem_size_in_pages()).unwrap(),
-            ));
+            return Err(TrapKind::OutOfBoundsMemory.into());
+        }
+        let (src, dst, n) = (src as usize, dst as usize, n as usize);
 
-            self.grow_mem(u32::try_from(additional_pages)?);
+        if dst_mem == src_mem {
+            // the regions may overlap, copy_within has memmove semantics
+            let mut mem = self.store.mems[dst_mem as usize].borrow_mut();
+            mem.data.copy_within(src..src + n, dst);
+        } else {
+            // the two memories may still be the same imported memory
+            let bytes = self.store.mems[src_mem as usize].borrow().data[src..src + n].to_vec();
+            self.store.mems[dst_mem as usize].borrow_mut().data[dst..dst + n]
+                .copy_from_slice(&bytes);
         }
 
         Ok(())
     }
 
-    fn run_i32_load(&mut self, memarg: &MemArg, width: u32) -> Result<WasmValue> {
-        let base = u32::try_from(self.pop_operand_stack().as_i32())?;
-        let effective_addr = base + memarg.offset;
+    fn run_memory_fill(&mut self, mem: u32) -> Result<()> {
+        let memory64 = self.is_memory64(mem)?;
 
-        let mem_size = self.mem_size_in_bytes();
-        if effective_addr + width > mem_size as u32 {
-            return Err(anyhow!(
-                "out of bounds memory access, effective_addr: {}, width: {}, mem_size: {}",
-                effective_addr,
-                width,
-                mem_size
-            ));
+        let n = self.pop_address(memory64);
+        let value = self.pop_operand_stack().as_i32() as u8;
+        let dst = self.pop_address(memory64);
+
+        if !in_bounds(dst, n, self.mem_size_in_bytes(mem) as u64) {
+            return Err(TrapKind::OutOfBoundsMemory.into());
         }
+        let (dst, n) = (dst as usize, n as usize);
+
+        self.store.mems[mem as usize].borrow_mut().data[dst..dst + n].fill(value);
+
+        Ok(())
+    }
+
+    fn run_memory_init(&mut self, data_index: u32, mem: u32) -> Result<()> {
+        let memory64 = self.is_memory64(mem)?;
+
+        let n = self.pop_operand_stack().as_i32() as u32 as usize;
+        let src = self.pop_operand_stack().as_i32() as u32 as usize;
+        let dst = self.pop_address(memory64);
+
+        let dropped = *self
+            .store
+            .dropped_datas
+            .borrow()
+            .get(data_index as usize)
+            .ok_or_else(|| anyhow!("memory.init: invalid data index"))?;
+
+        let module = &self.module;
+        // a dropped segment behaves like an empty one
+        let data = if dropped {
+            &[]
+        } else {
+            module.get_datas()[data_index as usize].data
+        };
 
-        // little endian read
-        let mem = self.mem.borrow();
-        let mut value = 0u32;
-        for i in 0..width {
-            value |= (mem.0[(effective_addr + i) as usize] as u32) << (i * 8);
+        if src + n > data.len() || !in_bounds(dst, n as u64, self.mem_size_in_bytes(mem) as u64) {
+            return Err(TrapKind::OutOfBoundsMemory.into());
         }
-        drop(mem);
+        let dst = dst as usize;
+
+        self.store.mems[mem as usize].borrow_mut().data[dst..dst + n]
+            .copy_from_slice(&data[src..src + n]);
 
-        let i32_value = i32::from_le_bytes(value.to_le_bytes());
-        Ok(WasmValue::I32(i32_value))
+        Ok(())
     }
 
-    fn run_i32_store(&mut self, memarg: &MemArg, width: u32) -> Result<()> {
-        let value = self.pop_operand_stack().as_i32();
-        let base = u32::try_from(self.pop_operand_stack().as_i32())?;
-        let effective_addr = base + memarg.offset;
+    fn run_data_drop(&mut self, data_index: u32) -> Result<()> {
+        let mut dropped_datas = self.store.dropped_datas.borrow_mut();
+        let dropped = dropped_datas
+            .get_mut(data_index as usize)
+            .ok_or_else(|| anyhow!("data.drop: invalid data index"))?;
+        *dropped = true;
+
+        Ok(())
+    }
 
-        let mut mem = self.mem.borrow_mut();
-        let mem_size = mem.size();
+    /// Whether memory `mem` is indexed with i64 addresses, fails if there is
+    /// no such memory.
+    fn is_memory64(&self, mem: u32) -> Result<bool> {
+        self.store
+            .mems
+            .get(mem as usize)
+            .map(|m| m.borrow().memory64)
+            .ok_or_else(|| anyhow!("invalid memory index: {}", mem))
+    }
 
-        if effective_addr + width > mem_size as u32 {
-            return Err(anyhow!(
-                "out of bounds memory access, effective_addr: {}, width: {}, mem_size: {}",
-                effective_addr,
-                width,
-                mem_size
-            ));
+    /// Pop an address operand, memory64 memories are indexed with i64.
+    fn pop_address(&mut self, memory64: bool) -> u64 {
+        if memory64 {
+            self.pop_operand_stack().as_i64() as u64
+        } else {
+            self.pop_operand_stack().as_i32() as u32 as u64
         }
+    }
 
-        for i in 0..width {
-            mem.0[(effective_addr + i) as usize] = ((value >> (i * 8)) & 0xFF) as u8;
+    fn push_address(&mut self, memory64: bool, addr: i64) {
+        if memory64 {
+            self.push_operand_stack(WasmValue::I64(addr));
+        } else {
+            self.push_operand_stack(WasmValue::I32(addr as i32));
         }
+    }
 
-        Ok(())
+    /// Pop the address of a load and read the `N` bytes at `address +
+    /// offset`.
+    fn load<const N: usize>(&mut self, mem: u32, offset: u64) -> Result<[u8; N]> {
+        let memory64 = self.is_memory64(mem)?;
+        let addr = self.pop_address(memory64);
+        self.store.mems[mem as usize]
+            .borrow()
+            .load(addr, offset)
+            .ok_or_else(|| TrapKind::OutOfBoundsMemory.into())
     }
 
-    fn run_f64_load(&mut self, memarg: &MemArg) -> Result<WasmValue> {
-        let base = u32::try_from(self.pop_operand_stack().as_i32())?;
-        let effective_addr = base + memarg.offset;
+    /// Pop the address of a store and write `bytes` at `address + offset`.
+    fn store<const N: usize>(&mut self, mem: u32, offset: u64, bytes: [u8; N]) -> Result<()> {
+        let memory64 = self.is_memory64(mem)?;
+        let addr = self.pop_address(memory64);
+        self.store.mems[mem as usize]
+            .borrow_mut()
+            .store(addr, offset, bytes)
+            .ok_or_else(|| TrapKind::OutOfBoundsMemory.into())
+    }
 
-        let mem = self.mem.borrow();
-        let mem_size = mem.size();
+    /// Narrower loads are zero extended.
+    fn run_i32_load(&mut self, mem: u32, offset: u64, width: u32) -> Result<WasmValue> {
+        let value = match width {
+            1 => u8::from_le_bytes(self.load(mem, offset)?) as u32,
+            2 => u16::from_le_bytes(self.load(mem, offset)?) as u32,
+            _ => u32::from_le_bytes(self.load(mem, offset)?),
+        };
+        Ok(WasmValue::I32(value as i32))
+    }
 
-        if effective_addr + 8 > mem_size as u32 {
-            return Err(anyhow!(
-                "out of bounds memory access, effective_addr: {}, width: {}, mem_size: {}",
-                effective_addr,
-                8,
-                mem_size
-            ));
+    /// Narrower stores keep the low bytes of the value.
+    fn run_i32_store(&mut self, mem: u32, offset: u64, width: u32) -> Result<()> {
+        let bytes = self.pop_operand_stack().as_i32().to_le_bytes();
+        match width {
+            1 => self.store(mem, offset, [bytes[0]]),
+            2 => self.store(mem, offset, [bytes[0], bytes[1]]),
+            _ => self.store(mem, offset, bytes),
         }
+    }
+
+    /// Narrower loads are zero extended.
+    fn run_i64_load(&mut self, mem: u32, offset: u64, width: u32) -> Result<WasmValue> {
+        let value = match width {
+            1 => u8::from_le_bytes(self.load(mem, offset)?) as u64,
+            2 => u16::from_le_bytes(self.load(mem, offset)?) as u64,
+            4 => u32::from_le_bytes(self.load(mem, offset)?) as u64,
+            _ => u64::from_le_bytes(self.load(mem, offset)?),
+        };
+        Ok(WasmValue::I64(value as i64))
+    }
 
-        let mut value = 0u64;
-        for i in 0..8 {
-            value |= (mem.0[(effective_addr + i) as usize] as u64) << (i * 8);
+    /// Narrower stores keep the low bytes of the value.
+    fn run_i64_store(&mut self, mem: u32, offset: u64, width: u32) -> Result<()> {
+        let bytes = self.pop_operand_stack().as_i64().to_le_bytes();
+        match width {
+            1 => self.store(mem, offset, [bytes[0]]),
+            2 => self.store(mem, offset, [bytes[0], bytes[1]]),
+            4 => self.store(mem, offset, [bytes[0], bytes[1], bytes[2], bytes[3]]),
+            _ => self.store(mem, offset, bytes),
         }
-        drop(mem);
+    }
+
+    fn run_f32_load(&mut self, mem: u32, offset: u64) -> Result<WasmValue> {
+        Ok(WasmValue::F32(f32::from_le_bytes(self.load(mem, offset)?)))
+    }
 
-        let f64_value = f64::from_le_bytes(value.to_le_bytes());
-        Ok(WasmValue::F64(f64_value))
+    fn run_f32_store(&mut self, mem: u32, offset: u64) -> Result<()> {
+        let value = self.pop_operand_stack().as_f32();
+        self.store(mem, offset, value.to_le_bytes())
     }
 
-    fn run_f64_store(&mut self, memarg: &MemArg) -> Result<()> {
+    fn run_f64_load(&mut self, mem: u32, offset: u64) -> Result<WasmValue> {
+        Ok(WasmValue::F64(f64::from_le_bytes(self.load(mem, offset)?)))
+    }
+
+    fn run_f64_store(&mut self, mem: u32, offset: u64) -> Result<()> {
         let value = self.pop_operand_stack().as_f64();
-        let base = u32::try_from(self.pop_operand_stack().as_i32())?;
-        let effective_addr = base + memarg.offset;
-
-        let mut mem = self.mem.borrow_mut();
-        let mem_size = mem.size();
-
-        if effective_addr + 8 > mem_size as u32 {
-            return Err(anyhow!(
-                "out of bounds memory access, effective_addr: {}, width: {}, mem_size: {}",
-                effective_addr,
-                8,
-                mem_size
-            ));
-        }
+        self.store(mem, offset, value.to_le_bytes())
+    }
 
-        let value = value.to_le_bytes();
-        for i in 0..8 {
-            mem.0[(effective_addr + i) as usize] = value[i as usize];
+    fn run_v128_load(&mut self, mem: u32, offset: u64) -> Result<WasmValue> {
+        Ok(WasmValue::V128(u128::from_le_bytes(
+            self.load(mem, offset)?,
+        )))
+    }
+
+    fn run_v128_store(&mut self, mem: u32, offset: u64) -> Result<()> {
+        let value = self.pop_operand_stack().as_v128();
+        self.store(mem, offset, value.to_le_bytes())
+    }
+
+    /// Load a lane of `shape`, zero extended.
+    fn load_v128_lane(&mut self, mem: u32, offset: u64, shape: V128Shape) -> Result<u64> {
+        Ok(match shape.lane_bits() {
+            8 => u8::from_le_bytes(self.load(mem, offset)?) as u64,
+            16 => u16::from_le_bytes(self.load(mem, offset)?) as u64,
+            32 => u32::from_le_bytes(self.load(mem, offset)?) as u64,
+            _ => u64::from_le_bytes(self.load(mem, offset)?),
+        })
+    }
+
+    /// Store the low bytes of `x`, as many as a lane of `shape` has.
+    fn store_v128_lane(&mut self, mem: u32, offset: u64, shape: V128Shape, x: u64) -> Result<()> {
+        let bytes = x.to_le_bytes();
+        match shape.lane_bits() {
+            8 => self.store(mem, offset, [bytes[0]]),
+            16 => self.store(mem, offset, [bytes[0], bytes[1]]),
+            32 => self.store(mem, offset, [bytes[0], bytes[1], bytes[2], bytes[3]]),
+            _ => self.store(mem, offset, bytes),
         }
+    }
+
+    fn run_v128_splat(&mut self, shape: V128Shape) -> Result<()> {
+        let value = self.pop_operand_stack();
+        self.push_operand_stack(WasmValue::V128(v128_splat(shape, value)));
+        Ok(())
+    }
 
+    fn run_v128_replace_lane(&mut self, shape: V128Shape, lane: u8) -> Result<()> {
+        let value = self.pop_operand_stack();
+        let v = self.pop_operand_stack().as_v128();
+        self.push_operand_stack(WasmValue::V128(v128_replace_lane(shape, v, lane, value)));
         Ok(())
     }
 
@@ -687,6 +1938,11 @@ impl WasmFunctionExecutorImpl<'_> {
             I32Unop::Extend16S => Ok(WasmValue::I32(a as i16 as i32)),
             I32Unop::F64ConvertI32S => Ok(WasmValue::F64(f64::from(a))),
             I32Unop::F64ConvertI32U => Ok(WasmValue::F64(f64::from(a as u32))),
+            I32Unop::F32ConvertI32S => Ok(WasmValue::F32(a as f32)),
+            I32Unop::F32ConvertI32U => Ok(WasmValue::F32(a as u32 as f32)),
+            I32Unop::I64ExtendI32S => Ok(WasmValue::I64(i64::from(a))),
+            I32Unop::I64ExtendI32U => Ok(WasmValue::I64(i64::from(a as u32))),
+            I32Unop::F32ReinterpretI32 => Ok(WasmValue::F32(f32::from_bits(a as u32))),
         }?;
 
         self.push_operand_stack(result);
@@ -698,26 +1954,27 @@ impl WasmFunctionExecutorImpl<'_> {
         let b = self.pop_operand_stack().as_i32();
         let a = self.pop_operand_stack().as_i32();
         let result = match i32_binop {
-            I32Binop::Eq => Ok(WasmValue::I32((a == b) as i32)),
-            I32Binop::Ne => Ok(WasmValue::I32((a != b) as i32)),
-            I32Binop::LtS => Ok(WasmValue::I32((a < b) as i32)),
-            I32Binop::LtU => Ok(WasmValue::I32(((a as u32) < (b as u32)) as i32)),
-            I32Binop::GtS => Ok(WasmValue::I32((a > b) as i32)),
-            I32Binop::GtU => Ok(WasmValue::I32(((a as u32) > (b as u32)) as i32)),
-            I32Binop::LeS => Ok(WasmValue::I32((a <= b) as i32)),
-            I32Binop::LeU => Ok(WasmValue::I32(((a as u32) <= (b as u32)) as i32)),
-            I32Binop::GeS => Ok(WasmValue::I32((a >= b) as i32)),
-            I32Binop::GeU => Ok(WasmValue::I32(((a as u32) >= (b as u32)) as i32)),
+            I32Binop::Eq
+            | I32Binop::Ne
+            | I32Binop::LtS
+            | I32Binop::LtU
+            | I32Binop::GtS
+            | I32Binop::GtU
+            | I32Binop::LeS
+            | I32Binop::LeU
+            | I32Binop::GeS
+            | I32Binop::GeU => Ok(WasmValue::I32(i32_compare(i32_binop, a, b) as i32)),
             I32Binop::Add => Ok(WasmValue::I32(a.wrapping_add(b))),
             I32Binop::Sub => Ok(WasmValue::I32(a.wrapping_sub(b))),
             I32Binop::Mul => Ok(WasmValue::I32(a.wrapping_mul(b))),
             I32Binop::DivS => match a.checked_div(b) {
                 Some(v) => Ok(WasmValue::I32(v)),
-                None => Err(anyhow!("division overflow")),
+                None if b == 0 => Err(TrapKind::DivisionByZero),
+                None => Err(TrapKind::IntegerOverflow),
             },
             I32Binop::DivU => {
                 if b == 0 {
-                    Err(anyhow!("division by zero"))
+                    Err(TrapKind::DivisionByZero)
                 } else {
                     Ok(WasmValue::I32(i32::try_from(
                         (a as u32).wrapping_div(b as u32),
@@ -726,14 +1983,14 @@ impl WasmFunctionExecutorImpl<'_> {
             }
             I32Binop::RemS => {
                 if b == 0 {
-                    Err(anyhow!("division by zero"))
+                    Err(TrapKind::DivisionByZero)
                 } else {
                     Ok(WasmValue::I32(a.wrapping_rem(b)))
                 }
             }
             I32Binop::RemU => {
                 if b == 0 {
-                    Err(anyhow!("division by zero"))
+                    Err(TrapKind::DivisionByZero)
                 } else {
                     Ok(WasmValue::I32((a as u32).wrapping_rem(b as u32) as i32))
                 }
@@ -755,34 +2012,194 @@ impl WasmFunctionExecutorImpl<'_> {
         Ok(())
     }
 
-    fn run_f64_unop(&mut self, f64_unop: &F64Unop) -> Result<()> {
-        let a = self.pop_operand_stack().as_f64();
-        let result = match f64_unop {
-            F64Unop::Neg => Ok(WasmValue::F64(-a)),
-            F64Unop::Abs => Ok(WasmValue::F64(a.abs())),
-            F64Unop::Ceil => Ok(WasmValue::F64(a.ceil())),
-            F64Unop::Floor => Ok(WasmValue::F64(a.floor())),
-            F64Unop::Trunc => Ok(WasmValue::F64(a.trunc())),
-            F64Unop::Nearest => Ok(WasmValue::F64(a.round())),
-            F64Unop::Sqrt => Ok(WasmValue::F64(a.sqrt())),
-            F64Unop::I32TruncF64S => {
-                let f = a.trunc();
-                if f.is_nan() || f < (i32::MIN as f64) || f > (i32::MAX as f64) || f.is_infinite() {
-                    Err(anyhow!("f64.trunc_s: value out of range"))
+    fn run_i64_unop(&mut self, i64_unop: &I64Unop) -> Result<()> {
+        let a = self.pop_operand_stack().as_i64();
+        let result = match i64_unop {
+            I64Unop::Eqz => WasmValue::I32((a == 0) as i32),
+            I64Unop::Clz => WasmValue::I64(a.leading_zeros() as i64),
+            I64Unop::Ctz => WasmValue::I64(a.trailing_zeros() as i64),
+            I64Unop::Popcnt => WasmValue::I64(a.count_ones() as i64),
+            I64Unop::Extend8S => WasmValue::I64(a as i8 as i64),
+            I64Unop::Extend16S => WasmValue::I64(a as i16 as i64),
+            I64Unop::Extend32S => WasmValue::I64(a as i32 as i64),
+            I64Unop::I32WrapI64 => WasmValue::I32(a as i32),
+            I64Unop::F32ConvertI64S => WasmValue::F32(a as f32),
+            I64Unop::F32ConvertI64U => WasmValue::F32(a as u64 as f32),
+            I64Unop::F64ConvertI64S => WasmValue::F64(a as f64),
+            I64Unop::F64ConvertI64U => WasmValue::F64(a as u64 as f64),
+            I64Unop::F64ReinterpretI64 => WasmValue::F64(f64::from_bits(a as u64)),
+        };
+
+        self.push_operand_stack(result);
+
+        Ok(())
+    }
+
+    fn run_i64_binop(&mut self, i64_binop: &I64Binop) -> Result<()> {
+        let b = self.pop_operand_stack().as_i64();
+        let a = self.pop_operand_stack().as_i64();
+        let result = match i64_binop {
+            I64Binop::Eq => Ok(WasmValue::I32((a == b) as i32)),
+            I64Binop::Ne => Ok(WasmValue::I32((a != b) as i32)),
+            I64Binop::LtS => Ok(WasmValue::I32((a < b) as i32)),
+            I64Binop::LtU => Ok(WasmValue::I32(((a as u64) < (b as u64)) as i32)),
+            I64Binop::GtS => Ok(WasmValue::I32((a > b) as i32)),
+            I64Binop::GtU => Ok(WasmValue::I32(((a as u64) > (b as u64)) as i32)),
+            I64Binop::LeS => Ok(WasmValue::I32((a <= b) as i32)),
+            I64Binop::LeU => Ok(WasmValue::I32(((a as u64) <= (b as u64)) as i32)),
+            I64Binop::GeS => Ok(WasmValue::I32((a >= b) as i32)),
+            I64Binop::GeU => Ok(WasmValue::I32(((a as u64) >= (b as u64)) as i32)),
+            I64Binop::Add => Ok(WasmValue::I64(a.wrapping_add(b))),
+            I64Binop::Sub => Ok(WasmValue::I64(a.wrapping_sub(b))),
+            I64Binop::Mul => Ok(WasmValue::I64(a.wrapping_mul(b))),
+            I64Binop::DivS => match a.checked_div(b) {
+                Some(v) => Ok(WasmValue::I64(v)),
+                None if b == 0 => Err(TrapKind::DivisionByZero),
+                None => Err(TrapKind::IntegerOverflow),
+            },
+            I64Binop::DivU => {
+                if b == 0 {
+                    Err(TrapKind::DivisionByZero)
                 } else {
-                    Ok(WasmValue::I32(f as i32))
+                    Ok(WasmValue::I64((a as u64).wrapping_div(b as u64) as i64))
                 }
             }
-            F64Unop::I32TruncF64U => {
-                let f = a.trunc();
-                if f.is_nan() || f < 0.0 || f > (u32::MAX as f64) || f.is_infinite() {
-                    Err(anyhow!("f64.trunc_u: value out of range"))
+            I64Binop::RemS => {
+                if b == 0 {
+                    Err(TrapKind::DivisionByZero)
+                } else {
+                    Ok(WasmValue::I64(a.wrapping_rem(b)))
+                }
+            }
+            I64Binop::RemU => {
+                if b == 0 {
+                    Err(TrapKind::DivisionByZero)
                 } else {
-                    Ok(WasmValue::I32((f as u32) as i32))
+                    Ok(WasmValue::I64((a as u64).wrapping_rem(b as u64) as i64))
                 }
             }
+            I64Binop::And => Ok(WasmValue::I64(a & b)),
+            I64Binop::Or => Ok(WasmValue::I64(a | b)),
+            I64Binop::Xor => Ok(WasmValue::I64(a ^ b)),
+            I64Binop::Shl => Ok(WasmValue::I64(a.wrapping_shl((b & 0x3f) as u32))),
+            I64Binop::ShrS => Ok(WasmValue::I64(a.wrapping_shr((b & 0x3f) as u32))),
+            I64Binop::ShrU => Ok(WasmValue::I64(
+                (a as u64).wrapping_shr((b & 0x3f) as u32) as i64
+            )),
+            I64Binop::Rotl => Ok(WasmValue::I64(a.rotate_left((b & 0x3f) as u32))),
+            I64Binop::Rotr => Ok(WasmValue::I64(a.rotate_right((b & 0x3f) as u32))),
         }?;
 
+        self.push_operand_stack(result);
+
+        Ok(())
+    }
+
+    /// The result of a float instruction, with its NaN canonicalized if
+    /// requested, unless the instruction only changes the sign bit, which
+    /// the spec defines for every NaN.
+    fn float_result(&self, result: WasmValue, sign_only: bool) -> WasmValue {
+        if self.canonicalize_nans && !sign_only {
+            result.canonicalize_nan()
+        } else {
+            result
+        }
+    }
+
+    fn run_f32_unop(&mut self, f32_unop: &F32Unop) -> Result<()> {
+        let a = self.pop_operand_stack().as_f32();
+        let result = match f32_unop {
+            F32Unop::Abs => WasmValue::F32(a.abs()),
+            F32Unop::Neg => WasmValue::F32(-a),
+            F32Unop::Ceil => WasmValue::F32(a.ceil()),
+            F32Unop::Floor => WasmValue::F32(a.floor()),
+            F32Unop::Trunc => WasmValue::F32(a.trunc()),
+            F32Unop::Nearest => WasmValue::F32(a.round_ties_even()),
+            F32Unop::Sqrt => WasmValue::F32(a.sqrt()),
+            // every f32 is exactly representable as f64, so the range checks
+            // are done in double precision
+            F32Unop::I32TruncF32S => {
+                WasmValue::I32(trunc_in_range(f64::from(a), I32_MIN, I32_MAX_EXCL)? as i32)
+            }
+            F32Unop::I32TruncF32U => {
+                WasmValue::I32(trunc_in_range(f64::from(a), 0.0, U32_MAX_EXCL)? as u32 as i32)
+            }
+            F32Unop::I64TruncF32S => {
+                WasmValue::I64(trunc_in_range(f64::from(a), I64_MIN, I64_MAX_EXCL)? as i64)
+            }
+            F32Unop::I64TruncF32U => {
+                WasmValue::I64(trunc_in_range(f64::from(a), 0.0, U64_MAX_EXCL)? as u64 as i64)
+            }
+            F32Unop::F64PromoteF32 => WasmValue::F64(f64::from(a)),
+            F32Unop::I32ReinterpretF32 => WasmValue::I32(a.to_bits() as i32),
+            // rust float to int casts saturate and map NaN to 0
+            F32Unop::I32TruncSatF32S => WasmValue::I32(a as i32),
+            F32Unop::I32TruncSatF32U => WasmValue::I32(a as u32 as i32),
+            F32Unop::I64TruncSatF32S => WasmValue::I64(a as i64),
+            F32Unop::I64TruncSatF32U => WasmValue::I64(a as u64 as i64),
+        };
+
+        let result = self.float_result(result, matches!(f32_unop, F32Unop::Abs | F32Unop::Neg));
+        self.push_operand_stack(result);
+        Ok(())
+    }
+
+    fn run_f32_binop(&mut self, f32_binop: &F32Binop) -> Result<()> {
+        let b = self.pop_operand_stack().as_f32();
+        let a = self.pop_operand_stack().as_f32();
+        let result = match f32_binop {
+            F32Binop::Eq => WasmValue::I32((a == b) as i32),
+            F32Binop::Ne => WasmValue::I32((a != b) as i32),
+            F32Binop::Lt => WasmValue::I32((a < b) as i32),
+            F32Binop::Gt => WasmValue::I32((a > b) as i32),
+            F32Binop::Le => WasmValue::I32((a <= b) as i32),
+            F32Binop::Ge => WasmValue::I32((a >= b) as i32),
+            F32Binop::Add => WasmValue::F32(a + b),
+            F32Binop::Sub => WasmValue::F32(a - b),
+            F32Binop::Mul => WasmValue::F32(a * b),
+            F32Binop::Div => WasmValue::F32(a / b),
+            F32Binop::Min => WasmValue::F32(f32_min(a, b)),
+            F32Binop::Max => WasmValue::F32(f32_max(a, b)),
+            F32Binop::Copysign => WasmValue::F32(a.copysign(b)),
+        };
+
+        let result = self.float_result(result, matches!(f32_binop, F32Binop::Copysign));
+        self.push_operand_stack(result);
+
+        Ok(())
+    }
+
+    fn run_f64_unop(&mut self, f64_unop: &F64Unop) -> Result<()> {
+        let a = self.pop_operand_stack().as_f64();
+        let result = match f64_unop {
+            F64Unop::Neg => WasmValue::F64(-a),
+            F64Unop::Abs => WasmValue::F64(a.abs()),
+            F64Unop::Ceil => WasmValue::F64(a.ceil()),
+            F64Unop::Floor => WasmValue::F64(a.floor()),
+            F64Unop::Trunc => WasmValue::F64(a.trunc()),
+            F64Unop::Nearest => WasmValue::F64(a.round()),
+            F64Unop::Sqrt => WasmValue::F64(a.sqrt()),
+            F64Unop::I32TruncF64S => {
+                WasmValue::I32(trunc_in_range(a, I32_MIN, I32_MAX_EXCL)? as i32)
+            }
+            F64Unop::I32TruncF64U => {
+                WasmValue::I32(trunc_in_range(a, 0.0, U32_MAX_EXCL)? as u32 as i32)
+            }
+            F64Unop::I64TruncF64S => {
+                WasmValue::I64(trunc_in_range(a, I64_MIN, I64_MAX_EXCL)? as i64)
+            }
+            F64Unop::I64TruncF64U => {
+                WasmValue::I64(trunc_in_range(a, 0.0, U64_MAX_EXCL)? as u64 as i64)
+            }
+            F64Unop::F32DemoteF64 => WasmValue::F32(a as f32),
+            F64Unop::I64ReinterpretF64 => WasmValue::I64(a.to_bits() as i64),
+            F64Unop::I32TruncSatF64S => WasmValue::I32(a as i32),
+            F64Unop::I32TruncSatF64U => WasmValue::I32(a as u32 as i32),
+            F64Unop::I64TruncSatF64S => WasmValue::I64(a as i64),
+            F64Unop::I64TruncSatF64U => WasmValue::I64(a as u64 as i64),
+        };
+
+        let result = self.float_result(result, matches!(f64_unop, F64Unop::Abs | F64Unop::Neg));
         self.push_operand_stack(result);
         Ok(())
     }
@@ -821,297 +2238,195 @@ impl WasmFunctionExecutorImpl<'_> {
                     a.max(b)
                 }
             })),
+            F64Binop::Copysign => Ok(WasmValue::F64(a.copysign(b))),
         }?;
 
+        let result = self.float_result(result, matches!(f64_binop, F64Binop::Copysign));
         self.push_operand_stack(result);
 
         Ok(())
     }
 
     // control flow functions
-    fn run_block(&mut self, insts: &[Instruction], block_type: BlockType) -> Result<()> {
-        let mut expected_stack_height = self.operand_stack.len();
-        expected_stack_height += stack_height_delta(self.module.clone(), block_type);
-
-        let frame = BlockControlFlowFrame {
-            control_type: BlockControlFlowType::Block,
-            expected_stack_height,
-            num_results: block_type_num_results(self.module.clone(), block_type),
-            start_pc: self.pc,
-            end_pc: Self::find_matching_end(insts, self.pc)?,
-        };
-
-        self.control_flow_frames.push_back(frame);
+    fn run_throw(&mut self, tag_index: u32) -> Result<()> {
+        let nvalues = self
+            .module
+            .get_tag_sig(tag_index)
+            .ok_or_else(|| anyhow!("throw: invalid tag index"))?
+            .params()
+            .len();
 
-        Ok(())
+        let values = self.pop_operand_values(nvalues);
+        self.throw(WasmException { tag_index, values })
     }
 
-    fn run_loop(&mut self, insts: &[Instruction], block_type: BlockType) -> Result<()> {
-        let mut expected_stack_height = self.operand_stack.len();
-        expected_stack_height += stack_height_delta(self.module.clone(), block_type);
-
-        let frame = BlockControlFlowFrame {
-            control_type: BlockControlFlowType::Loop,
-            expected_stack_height,
-            num_results: block_type_num_results(self.module.clone(), block_type),
-            start_pc: self.pc,
-            end_pc: Self::find_matching_end(insts, self.pc)?,
+    /// Rethrow the exception of the catch clause `depth` try blocks up.
+    fn run_rethrow(&mut self, depth: u32) -> Result<()> {
+        let frame = self
+            .control_flow_frames
+            .len()
+            .checked_sub(depth as usize + 1)
+            .map(|i| &self.control_flow_frames[i]);
+        let Some(BlockControlFlowFrame::Catch { exception }) = frame else {
+            return Err(anyhow!("rethrow: target is not a catch clause"));
         };
 
-        self.control_flow_frames.push_back(frame);
-
-        Ok(())
+        self.throw(exception.clone())
     }
 
-    /// Run the if instruction, return true if the condition is met, false otherwise
-    fn run_if(&mut self, insts: &[Instruction], block_type: BlockType) -> Result<()> {
-        let mut expected_stack_height = self.operand_stack.len();
-        expected_stack_height += stack_height_delta(self.module.clone(), block_type);
-
-        let cond = self.pop_operand_stack().as_i32();
-        let else_pc = Self::find_closest_else(insts, self.pc);
-        let frame = BlockControlFlowFrame {
-            control_type: BlockControlFlowType::If {
-                else_pc,
-                condition_met: cond != 0,
-            },
-            expected_stack_height,
-            num_results: block_type_num_results(self.module.clone(), block_type),
-            start_pc: self.pc,
-            end_pc: Self::find_matching_end(insts, self.pc)?,
-        };
-
-        self.control_flow_frames.push_back(frame);
-
-        Ok(())
+    /// Hand an error coming out of a call to the enclosing try blocks if it
+    /// is a Wasm exception, other errors are traps and go up unchanged.
+    fn catch_exception(&mut self, err: anyhow::Error) -> Result<()> {
+        match err.downcast::<WasmException>() {
+            Ok(exception) => self.throw(exception),
+            Err(err) => Err(err),
+        }
     }
 
-    fn run_br(&mut self, rel_depth: u32) -> Result<()> {
-        let target_depth = rel_depth as usize;
-        let stack_depth = self.control_flow_frames.len();
-
-        if target_depth >= stack_depth {
-            return Err(anyhow!("br: invalid depth"));
-        }
+    /// Unwind to the innermost try block that catches `exception` and jump to
+    /// its catch clause, the exception leaves the function if nothing does.
+    fn throw(&mut self, exception: WasmException) -> Result<()> {
+        let code = Rc::clone(&self.code);
+        let mut i = self.control_flow_frames.len();
+        while i > 0 {
+            i -= 1;
 
-        let target_frame = self.control_flow_frames[stack_depth - 1 - target_depth].clone();
-        let expected_stack_height = target_frame.expected_stack_height;
-        let num_results = target_frame.num_results;
+            let BlockControlFlowFrame::Try { handler } = self.control_flow_frames[i] else {
+                continue;
+            };
+            let handler = &code.handlers[handler as usize];
 
-        self.unwind_stack(expected_stack_height, num_results);
+            // a delegating try forwards the exception to an enclosing label,
+            // past the try blocks in between
+            if let Some(skipped) = handler.delegate {
+                i = i.saturating_sub(skipped as usize);
+                continue;
+            }
 
-        match target_frame.control_type {
-            BlockControlFlowType::Block | BlockControlFlowType::If { .. } => {
-                self.set_pc(target_frame.end_pc);
+            let Some(&(tag, catch_pc)) = handler
+                .catches
+                .iter()
+                .find(|(tag, _)| tag.is_none_or(|tag| tag == exception.tag_index))
+            else {
+                continue;
+            };
 
-                // truncate the control flow frames **excluding** the target frame, the
-                // current frame will be pop on the *end* of the control flow
-                self.control_flow_frames
-                    .truncate(stack_depth - target_depth);
+            // leave the stack as it was when the try block started
+            self.operand_stack.truncate(handler.height as usize);
+            if tag.is_some() {
+                for v in &exception.values {
+                    self.push_operand_stack(*v);
+                }
             }
-            BlockControlFlowType::Loop => {
-                self.set_pc(target_frame.start_pc);
 
-                // truncate the control flow frames **incluing** the target frame, the
-                // this is because we will add the control flow frame again when the loop start
-                self.control_flow_frames
-                    .truncate(stack_depth - target_depth - 1);
-            }
+            self.control_flow_frames.truncate(i + 1);
+            self.control_flow_frames[i] = BlockControlFlowFrame::Catch { exception };
+            self.set_pc(catch_pc as Pc + 1);
+            return Ok(());
         }
 
-        Ok(())
-    }
-
-    /// Run the br_if instruction, return true if the condition is met, false otherwise
-    fn run_br_if(&mut self, rel_depth: u32) -> Result<bool> {
-        let cond = self.pop_operand_stack().as_i32();
-        if cond == 0 {
-            Ok(false)
-        } else {
-            self.run_br(rel_depth)?;
-            Ok(true)
-        }
+        Err(exception.into())
     }
 
-    fn run_br_table(&mut self, table: &BrTable) -> Result<()> {
-        let index = self.pop_operand_stack().as_i32();
-        if index < 0 || index >= table.targets.len() as i32 {
-            self.run_br(table.default_target)?;
-        } else {
-            self.run_br(table.targets[index as usize])?;
-        }
-
-        Ok(())
+    /// Take the branch at `branch` in the code, the blocks it leaves keep
+    /// only the values it carries.
+    fn run_br(&mut self, branch: u32) {
+        let branch = self.code.branches[branch as usize];
+        self.unwind_stack(branch.height as usize, branch.arity as usize);
+        let frames = self.control_flow_frames.len() - branch.try_blocks as usize;
+        self.control_flow_frames.truncate(frames);
+        self.set_pc(branch.pc as Pc);
     }
 
-    /// Unwind the stack to the expected stack height, but we have to keep the result
-    /// in the stack.
-    fn unwind_stack(&mut self, expected_stack_height: usize, num_results: usize) {
-        let mut result_buf = VecDeque::new();
-        for _ in 0..num_results {
-            result_buf.push_back(self.pop_operand_stack());
-        }
-
-        while self.operand_stack.len() > expected_stack_height.saturating_sub(num_results) {
-            self.pop_operand_stack();
-        }
-
-        for _ in 0..num_results {
-            self.push_operand_stack(result_buf.pop_back().unwrap());
-        }
+    /// Unwind the stack to `height`, but keep the `arity` topmost values on
+    /// top of it.
+    fn unwind_stack(&mut self, height: usize, arity: usize) {
+        let kept = self.operand_stack.len() - arity;
+        self.operand_stack.drain(height..kept);
     }
 }
 
 impl WasmFunctionExecutorImpl<'_> {
-    fn try_run_host_func(&mut self, func_ind: u32) -> Result<bool> {
-        let host_func_import = self
-            .module
-            .borrow()
-            .get_imports()
-            .imports
-            .iter()
-            .find(|i| match i.ty {
-                TypeRef::Func(ind) => ind == func_ind,
-                _ => false,
-            })
-            .map(|i| i.name.to_string());
-
-        if let Some(host_func_name) = host_func_import {
-            self.run_host_func(&host_func_name)?;
-            Ok(true)
-        } else {
-            Ok(false)
-        }
-    }
-
-    const HOST_FUNC_PUTI: &'static str = "puti";
-    const HOST_FUNC_PUTD: &'static str = "putd";
-    const HOST_FUNC_PUTS: &'static str = "puts";
-
-    fn run_host_func(&mut self, func_name: &str) -> Result<()> {
-        match func_name {
-            Self::HOST_FUNC_PUTI => {
-                let a = self.pop_operand_stack().as_i32();
-                print!("{}", a);
-            }
-            Self::HOST_FUNC_PUTD => {
-                let a = self.pop_operand_stack().as_f64();
-                print!("{:.6}", a);
-            }
-            Self::HOST_FUNC_PUTS => {
-                let len = self.pop_operand_stack().as_i32();
-                let addr = self.pop_operand_stack().as_i32();
-                let mem = self.mem.borrow();
+    fn try_run_host_func(&mut self, func_idx: u32) -> Result<bool> {
+        let import = HostFuncImport::resolve(&self.module, func_idx);
+        let Some(import) = import else {
+            return Ok(false);
+        };
 
-                if (addr + len) as usize > self.mem_size_in_bytes() {
-                    return Err(anyhow!("out of bounds memory access"));
-                }
+        let args = self.pop_operand_values(import.sig.params().len());
+
+        // host functions only see the first memory, a function of another
+        // instance may use the same memory
+        let uses_caller = self.store.host.borrow().uses_caller(&import);
+        let mut mem = self
+            .store
+            .mems
+            .first()
+            .filter(|_| uses_caller)
+            .map(|m| m.borrow_mut());
+        let data = mem.as_mut().map_or(&mut [][..], |m| &mut m.data[..]);
+        let results = self.store.host.borrow_mut().call(&import, &args, data)?;
+        drop(mem);
 
-                let bytes = mem.0.get(addr as usize..(addr + len) as usize).unwrap();
-                let s = String::from_utf8(bytes.to_vec())?;
-                print!("{}", s);
-            }
-            _ => panic!("host function {} not supported", func_name),
+        for v in results {
+            self.push_operand_stack(v);
         }
-        Ok(())
+        Ok(true)
     }
 }
 
-impl WasmFunctionExecutorImpl<'_> {
-    fn find_closest_else(insts: &[Instruction], start: Pc) -> Option<Pc> {
-        let end_pc = Self::find_matching_end(insts, start).expect("no matching end for if block");
-        let mut pc = start;
-        while pc < insts.len() {
-            let inst = &insts[pc];
-            if inst == &Instruction::Else {
-                if pc < end_pc {
-                    return Some(pc);
-                } else {
-                    return None;
-                }
-            }
-            pc += 1;
-        }
-
-        None
-    }
-
-    fn find_matching_end(insts: &[Instruction], start: Pc) -> Result<Pc> {
-        let mut pc = start;
-        let mut depth = 0;
-        while pc < insts.len() {
-            let inst = &insts[pc];
-            if Instruction::is_control_block_start(inst) {
-                depth += 1;
-            } else if Instruction::is_control_block_end(inst) {
-                depth -= 1;
-            }
-
-            if depth == 0 {
-                return Ok(pc);
-            }
-
-            pc += 1;
-        }
-
-        Err(anyhow!("no matching end for block"))
+fn i32_compare(cmp: &I32Binop, a: i32, b: i32) -> bool {
+    match cmp {
+        I32Binop::Eq => a == b,
+        I32Binop::Ne => a != b,
+        I32Binop::LtS => a < b,
+        I32Binop::LtU => (a as u32) < (b as u32),
+        I32Binop::GtS => a > b,
+        I32Binop::GtU => (a as u32) > (b as u32),
+        I32Binop::LeS => a <= b,
+        I32Binop::LeU => (a as u32) <= (b as u32),
+        I32Binop::GeS => a >= b,
+        I32Binop::GeU => (a as u32) >= (b as u32),
+        _ => unreachable!("{:?} is not a comparison", cmp),
     }
+}
 
-    fn should_skip(&self, pc: Pc) -> bool {
-        let frame = self.control_flow_frames.back().unwrap();
-        match frame.control_type {
-            BlockControlFlowType::Block => false,
-            BlockControlFlowType::Loop => false,
-            BlockControlFlowType::If {
-                else_pc,
-                condition_met,
-            } => {
-                if let Some(else_pc) = else_pc {
-                    if pc >= else_pc {
-                        condition_met
-                    } else {
-                        !condition_met
-                    }
-                } else {
-                    !condition_met
-                }
-            }
-        }
-    }
+/// Whether the `n` bytes at `start` are within `size` bytes.
+fn in_bounds(start: u64, n: u64, size: u64) -> bool {
+    start.checked_add(n).is_some_and(|end| end <= size)
 }
 
-pub(crate) fn block_type_num_results(
-    module: Rc<RefCell<WasmModule>>,
-    block_type: BlockType,
-) -> usize {
-    match block_type {
-        BlockType::Empty => 0,
-        BlockType::Type(_) => 1,
-        BlockType::FuncType(f) => module
-            .borrow()
-            .get_func(f)
-            .expect("function not found")
-            .get_sig()
-            .results()
-            .len(),
+/// Truncate `a` towards zero, failing if the result is NaN or does not fit in
+/// `[min, max_excl)`.
+fn trunc_in_range(a: f64, min: f64, max_excl: f64) -> Result<f64, TrapKind> {
+    let f = a.trunc();
+    if f.is_nan() {
+        Err(TrapKind::InvalidConversion)
+    } else if f >= min && f < max_excl {
+        Ok(f)
+    } else {
+        Err(TrapKind::IntegerOverflow)
     }
 }
 
-pub(crate) fn stack_height_delta(module: Rc<RefCell<WasmModule>>, block_type: BlockType) -> usize {
-    match block_type {
-        BlockType::Empty => 0,
-        BlockType::Type(_) => 1,
-        BlockType::FuncType(f) => {
-            let module = module.borrow();
-            let func = module.get_func(f).expect("function not found");
-            let nparams = func.get_sig().params().len();
-            let nresults = func.get_sig().results().len();
-            nresults - nparams
+pub(super) fn encode_i32leb(v: i32) -> Vec<u8> {
+    let mut buf = vec![];
+
+    let mut val = v;
+    let mut b: u8 = 0xFF;
+    while b & 0x80 != 0 {
+        b = (val & 0x7F) as u8;
+        val >>= 7;
+        if !(((val == 0) && (b & 0x40 == 0)) || ((val == -1) && (b & 0x40 != 0))) {
+            b |= 0x80;
         }
+        buf.push(b);
     }
+
+    buf
 }
 
-fn encode_i32leb(v: i32) -> Vec<u8> {
+pub(super) fn encode_i64leb(v: i64) -> Vec<u8> {
     let mut buf = vec![];
 
     let mut val = v;
@@ -1127,8 +2442,3 @@ fn encode_i32leb(v: i32) -> Vec<u8> {
 
     buf
 }
-
-fn encode_f64(v: f64) -> Vec<u8> {
-    let u64 = u64::from_le_bytes(v.to_le_bytes());
-    u64.to_le_bytes().to_vec()
-}
@@ -0,0 +1,142 @@
+//! Host function dispatch shared by the interpreter and the JIT.
+//!
+//! Both engines resolve an imported function to a `HostFuncImport` and hand
+//! the decoded arguments over to `HostFuncDispatcher::call`, so anything that
+//! observes host calls (e.g. import tracing) only lives here. Imports are
+//! resolved by module and field name when the module is instantiated, in the
+//! embedder's `Linker` first, then in the built-in host functions, which
+//! include WASI, see `vm::wasi`.
+
+use anyhow::{anyhow, bail, Result};
+use wasmparser::FuncType;
+
+use super::{
+    linker::{Caller, HostFunc, Linker},
+    trace::ImportTracer,
+    trap::TrapKind,
+    wasi::{define_wasi, WasiCtx},
+};
+use crate::module::{dump::format_sig, value_type::WasmValue, wasm_module::WasmModule};
+
+/// An imported function, identified by its index in the function index space.
+#[derive(Debug, Clone)]
+pub(crate) struct HostFuncImport {
+    pub(crate) index: u32,
+    pub(crate) module: String,
+    pub(crate) name: String,
+    pub(crate) sig: FuncType,
+}
+
+impl HostFuncImport {
+    /// Resolve the import for `func_idx`, returns None if the function is
+    /// defined in the module itself.
+    pub(crate) fn resolve(module: &WasmModule, func_idx: u32) -> Option<Self> {
+        let import = module.get_imports().get_func_import(func_idx)?;
+        let sig = module.get_func(func_idx)?.get_sig().clone();
+        Some(Self {
+            index: func_idx,
+            module: import.module.to_string(),
+            name: import.name.to_string(),
+            sig,
+        })
+    }
+
+    /// Every imported function of `module`, in order.
+    pub(crate) fn resolve_all(module: &WasmModule) -> Result<Vec<Self>> {
+        (0..module.get_num_func_imports() as u32)
+            .map(|i| {
+                Self::resolve(module, i).ok_or_else(|| anyhow!("function import {} not found", i))
+            })
+            .collect()
+    }
+}
+
+pub(crate) struct HostFuncDispatcher<'a> {
+    /// the host function of every imported function, by function index
+    funcs: Vec<HostFunc<'a>>,
+    tracer: Option<ImportTracer>,
+}
+
+impl<'a> HostFuncDispatcher<'a> {
+    /// Modules the built-in host functions can be imported from, `weewasm`
+    /// is the namespace of the course test programs.
+    const HOST_MODULES: [&'static str; 2] = ["env", "weewasm"];
+
+    /// Resolve every imported function of `module`, in `linker` first and
+    /// then in the built-in host functions. Fails on the first import that
+    /// is not defined or has another signature.
+    pub(crate) fn new(module: &WasmModule, linker: &Linker<'a>) -> Result<Self> {
+        let builtins = Self::builtins();
+        let funcs = HostFuncImport::resolve_all(module)?
+            .into_iter()
+            .map(|import| {
+                let func = linker
+                    .get(&import.module, &import.name)
+                    .or_else(|| builtins.get(&import.module, &import.name))
+                    .ok_or_else(|| anyhow!("unknown import: {}.{}", import.module, import.name))?;
+                if func.sig != import.sig {
+                    bail!(
+                        "incompatible import type: {}.{} is {}, imported as {}",
+                        import.module,
+                        import.name,
+                        format_sig(&func.sig),
+                        format_sig(&import.sig)
+                    );
+                }
+                Ok(func.clone())
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            funcs,
+            tracer: None,
+        })
+    }
+
+    /// Whether the host function of `import` sees the caller's memory, the
+    /// engines only pass the memory to those.
+    pub(crate) fn uses_caller(&self, import: &HostFuncImport) -> bool {
+        self.funcs[import.index as usize].uses_caller()
+    }
+
+    pub(crate) fn set_tracer(&mut self, tracer: ImportTracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Call the host function with the already decoded arguments, `mem` is
+    /// the caller's linear memory.
+    pub(crate) fn call(
+        &mut self,
+        import: &HostFuncImport,
+        args: &[WasmValue],
+        mem: &mut [u8],
+    ) -> Result<Vec<WasmValue>> {
+        let result = self.funcs[import.index as usize].call(&mut Caller::new(mem), args);
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record(import, args, &result);
+        }
+        result
+    }
+
+    fn builtins() -> Linker<'a> {
+        let mut linker = Linker::new();
+        for module in Self::HOST_MODULES {
+            linker
+                .func_wrap(module, "puti", |v: i32| print!("{}", v))
+                .and_then(|l| l.func_wrap(module, "putd", |v: f64| print!("{:.6}", v)))
+                .and_then(|l| l.func_wrap(module, "puts", Self::puts))
+                .unwrap();
+        }
+        define_wasi(&mut linker, WasiCtx::new()).unwrap();
+        linker
+    }
+
+    fn puts(caller: &mut Caller, addr: i32, len: i32) -> Result<()> {
+        let start = addr as u32 as usize;
+        let bytes = start
+            .checked_add(len as u32 as usize)
+            .and_then(|end| caller.memory().get(start..end))
+            .ok_or(TrapKind::OutOfBoundsMemory)?;
+        print!("{}", String::from_utf8(bytes.to_vec())?);
+        Ok(())
+    }
+}
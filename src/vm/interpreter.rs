@@ -1,166 +1,1023 @@
 use anyhow::{anyhow, Result};
 use debug_cell::RefCell;
+use wasmparser::ExternalKind;
 
-use std::rc::Rc;
+use std::{
+    cell::{Cell, OnceCell},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
 
+#[cfg(feature = "cranelift")]
+use crate::jit::CraneliftJitCompiler;
 use crate::{
-    jit::{register_trap_handler, ReturnFunc, WasmJitCompiler, X86JitCompiler},
+    jit::{register_trap_handler, JitSettings, Rv64JitCompiler, WasmJitCompiler, X86JitCompiler},
     module::{
-        components::FuncDecl, value_type::WasmValue, wasm_module::WasmModule,
-        wasmops::WASM_OP_I32_CONST,
+        components::FuncDecl,
+        insts::Instruction,
+        value_type::{FloatFormat, WasmValue},
+        wasm_module::WasmModule,
     },
-    vm::WASM_DEFAULT_PAGE_SIZE_BYTE,
+    vm::{DEFAULT_MAX_CALL_DEPTH, DEFAULT_MEMORY_MAXIMUM},
 };
 
-use super::{func_exec::WasmFunctionExecutorImpl, WasmFunctionExecutor, WasmVm};
+use super::{
+    checkpoint::SavedFrame,
+    coredump::write_coredump,
+    debugger::Debugger,
+    func_exec::{BlockControlFlowFrame, WasmFunctionExecutorImpl},
+    jit_instance::JitInstance,
+    linker::{Caller, HostFunc},
+    store::{Global, Store},
+    tiering::Tiering,
+    trace::ImportTracer,
+    Checkpoint, Epoch, EpochDeadline, ImportTraceSink, JitBackend, Linker, Memory, RuntimeError,
+    SharedLimiter, Snapshot, TrapKind, WasmBacktrace, WasmFunctionExecutor, WasmInterpreterConfig,
+    WasmVm, Watchdog,
+};
 
-pub(crate) struct LinearMemory(pub(crate) Vec<u8>);
+pub struct WasmInterpreter<'a> {
+    module: Rc<WasmModule<'a>>,
+    store: Rc<Store<'a>>,
+    jit_mode: bool,
+    jit_backend: JitBackend,
+    max_call_depth: usize,
+    /// the pages a memory declared without a maximum can grow to
+    default_memory_maximum: u64,
+    /// the pages no memory can grow past, whatever its maximum
+    max_memory_pages: u64,
+    /// the elements no table can grow past, whatever its maximum
+    max_table_elements: u64,
+    /// approves the growth of the memories and tables
+    limiter: Option<SharedLimiter>,
+    /// the fuel left, `None` if the execution is not metered
+    fuel: Rc<Cell<Option<u64>>>,
+    epoch: Option<Epoch>,
+    epoch_deadline: Cell<u64>,
+    timeout: Option<Duration>,
+    /// log every executed instruction, or basic block in jit mode
+    trace: bool,
+    debugger: Option<Rc<RefCell<Debugger>>>,
+    /// register jit code with debuggers
+    jit_debug_info: bool,
+    /// stop at a breakpoint when the jit code is entered
+    debug_break: bool,
+    /// list jit code in the perf map of the process
+    perf_map: bool,
+    /// jit compile every function on its first call
+    lazy_jit: bool,
+    /// where the jit code is cached across runs
+    jit_cache_dir: Option<PathBuf>,
+    /// print the wasm frames when jit code traps
+    jit_backtrace: bool,
+    /// where to write the core dump of a trap
+    coredump: Option<PathBuf>,
+    checkpoint_on_interrupt: bool,
+    /// replace the NaNs produced by float arithmetic by the canonical NaN
+    canonicalize_nans: bool,
+    /// the export `run` calls instead of `main`
+    entry: Option<String>,
+    /// how `run` prints float results
+    float_format: FloatFormat,
+    /// the state of the last call if it was interrupted
+    checkpoint: RefCell<Option<Checkpoint>>,
+    /// the interpreted calls after which a function is jit compiled
+    tier_up: Option<u64>,
+    /// set up on the first call once tiering is enabled
+    tiering: OnceCell<Rc<Tiering<'a>>>,
+    /// the x86 jit code, compiled on the first call in jit mode
+    x86_jit: RefCell<Option<Rc<JitInstance<'a>>>>,
+    /// the Cranelift code, compiled on the first call with the Cranelift
+    /// backend
+    #[cfg(feature = "cranelift")]
+    cranelift_jit: RefCell<Option<CraneliftJitCompiler<'a>>>,
+    /// the times the x86 jit code was loaded from `jit_cache_dir` and
+    /// compiled there
+    jit_cache_stats: Cell<(u64, u64)>,
+}
 
-impl LinearMemory {
-    pub fn size(&self) -> usize {
-        self.0.len()
-    }
+impl WasmVm for WasmInterpreter<'_> {
+    fn run(&self, main_params: Vec<WasmValue>) -> Result<String, RuntimeError> {
+        let main_index = self
+            .module
+            .get_entry_index(self.entry.as_deref())
+            .ok_or_else(|| match &self.entry {
+                Some(name) => anyhow!("entry function {} not found", name),
+                None => anyhow!("main function not found"),
+            })?;
 
-    pub fn grow(&mut self, additional_pages: u32) {
-        let new_size = self.0.len() + (additional_pages as usize * WASM_DEFAULT_PAGE_SIZE_BYTE);
-        self.0.resize(new_size, 0);
+        let results = self.invoke_func(main_index, main_params)?;
+        Ok(self.format_results(&results))
     }
 }
 
-pub struct WasmInterpreter<'a> {
-    module: Rc<RefCell<WasmModule<'a>>>,
-    mem: Rc<RefCell<LinearMemory>>,
-    jit_mode: bool,
-}
+impl<'a> WasmInterpreter<'a> {
+    /// Call the function exported as `name`, returns its results in order.
+    pub fn invoke(
+        &self,
+        name: &str,
+        params: Vec<WasmValue>,
+    ) -> Result<Vec<WasmValue>, RuntimeError> {
+        let func_index = self
+            .module
+            .get_export_func_index(name)
+            .ok_or_else(|| anyhow!("exported function {} not found", name))?;
+        Ok(self.invoke_func(func_index, params)?)
+    }
 
-impl WasmVm for WasmInterpreter<'_> {
-    fn run(&self, main_params: Vec<WasmValue>) -> anyhow::Result<String> {
-        // find main from export to run
-        let main_func = {
-            let module_ref = self.module.borrow();
-            let main_index = module_ref
-                .get_main_index()
-                .expect("main function not found");
-            module_ref
-                .get_func(main_index)
-                .ok_or_else(|| anyhow!("main function not found"))?
-                .clone()
-        };
+    /// Prepare a call to the function exported as `name` that is driven one
+    /// instruction at a time with `WasmFunctionExecutor::step`. The call
+    /// always runs on the interpreter, and unlike `invoke` it is not bounded
+    /// by the timeout.
+    pub fn invoke_stepwise(
+        &self,
+        name: &str,
+        params: Vec<WasmValue>,
+    ) -> Result<impl WasmFunctionExecutor + 'a> {
+        let func_index = self
+            .module
+            .get_export_func_index(name)
+            .ok_or_else(|| anyhow!("exported function {} not found", name))?;
+        self.get_func_checked(func_index, &params)?;
+        Ok(self.new_executor(func_index, params, self.epoch_deadline()))
+    }
 
-        let result = if self.jit_mode {
-            log::debug!("Running in JIT mode");
-            self.run_jit(main_func, main_params)?
-        } else {
-            log::debug!("Running in interpreter mode");
-            self.run_interpreter(main_func, main_params)?
-        };
+    /// The memory exported as `name`, a handle to the memory of the
+    /// instance, e.g. to read what a call wrote into it.
+    pub fn get_memory(&self, name: &str) -> Result<Memory> {
+        let index = self
+            .module
+            .get_export_index(name, ExternalKind::Memory)
+            .ok_or_else(|| anyhow!("exported memory {} not found", name))?;
+        self.store
+            .mems
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| anyhow!("exported memory {}: invalid memory index", name))
+    }
 
-        Ok(result)
+    /// The current value of the global exported as `name`.
+    pub fn get_global(&self, name: &str) -> Result<WasmValue> {
+        let index = self
+            .module
+            .get_export_index(name, ExternalKind::Global)
+            .ok_or_else(|| anyhow!("exported global {} not found", name))?;
+        self.store
+            .globals
+            .get(index as usize)
+            .map(Global::get)
+            .ok_or_else(|| anyhow!("exported global {}: invalid global index", name))
     }
-}
 
-impl WasmInterpreter<'_> {
-    fn run_jit(&self, main_func: FuncDecl, main_params: Vec<WasmValue>) -> Result<String> {
-        // register trap handler for SIGSEGV, which is used when wasm code has
-        // error. There, we print "!trap" and exit.
-        register_trap_handler();
+    fn invoke_func(&self, func_index: u32, params: Vec<WasmValue>) -> Result<Vec<WasmValue>> {
+        let func = self.get_func_checked(func_index, &params)?;
 
-        // jit compile all functions
-        // vm_entry is an opaque entry point to the typed main function
-        let mut compiler = X86JitCompiler::new(Rc::clone(&self.module));
-        let vm_entry = compiler.compile(main_params)?;
+        if self.jit_mode && self.debugger.is_some() {
+            return Err(anyhow!("the debugger is not supported by the jit"));
+        }
+        if self.jit_mode && self.coredump.is_some() {
+            return Err(anyhow!("core dumps are not supported by the jit"));
+        }
+        if self.jit_mode && self.checkpoint_on_interrupt {
+            return Err(anyhow!("checkpoints are not supported by the jit"));
+        }
 
-        // invoke main
-        let result = match main_func.get_sig().results()[0] {
-            wasmparser::ValType::I32 => {
-                let f: ReturnFunc = unsafe { std::mem::transmute(vm_entry) };
-                WasmValue::I32(f() as i32).to_string()
+        self.supervise(|epoch_deadline| {
+            if self.jit_mode {
+                log::debug!("Running in JIT mode");
+                self.run_jit(func_index, func, params, epoch_deadline)
+            } else {
+                log::debug!("Running in interpreter mode");
+                self.run_interpreter(func_index, params, epoch_deadline)
             }
-            wasmparser::ValType::F64 => {
-                let f: ReturnFunc = unsafe { std::mem::transmute(vm_entry) };
-                let fval = f64::from_bits(f());
-
-                // i think this is compiler optimization problem, if we do not
-                // do this, the result precision is ignored
-                let _ = format!("{:.6}", fval);
-                format!("{:.6}", fval)
+        })
+    }
+
+    /// Run a call under the timeout, then write the core dump of a trap or
+    /// keep the checkpoint of an interruption.
+    fn supervise(
+        &self,
+        run: impl FnOnce(Option<EpochDeadline>) -> Result<Vec<WasmValue>>,
+    ) -> Result<Vec<WasmValue>> {
+        // the watchdog interrupts the call at the next increment of the epoch
+        let mut epoch_deadline = self.epoch_deadline();
+        let watchdog = match (self.timeout, &mut epoch_deadline) {
+            (Some(timeout), Some(epoch_deadline)) => {
+                epoch_deadline.deadline = epoch_deadline
+                    .deadline
+                    .min(epoch_deadline.epoch.current() + 1);
+                Some(Watchdog::arm(epoch_deadline.epoch.clone(), timeout))
+            }
+            _ => None,
+        };
+
+        self.checkpoint.borrow_mut().take();
+        let result = match run(epoch_deadline) {
+            Err(e)
+                if e.downcast_ref::<TrapKind>() == Some(&TrapKind::Interrupted)
+                    && watchdog.is_some_and(|watchdog| watchdog.fired()) =>
+            {
+                let backtrace = e.downcast_ref::<WasmBacktrace>().cloned();
+                let err = anyhow::Error::new(TrapKind::Timeout);
+                Err(match backtrace {
+                    Some(backtrace) => err.context(backtrace),
+                    None => err,
+                })
             }
-            _ => unimplemented!(),
+            result => result,
         };
 
-        Ok(result)
+        if let Err(e) = &result {
+            if let Some(trap) = e.downcast_ref::<TrapKind>() {
+                if let Some(path) = &self.coredump {
+                    self.write_coredump(path, e);
+                }
+                if self.checkpoint_on_interrupt && trap.is_resumable() {
+                    self.keep_checkpoint(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Keep the state of the call that `err` interrupted for `take_checkpoint`.
+    fn keep_checkpoint(&self, err: &anyhow::Error) {
+        let frames = err
+            .downcast_ref::<WasmBacktrace>()
+            .map_or(&[][..], WasmBacktrace::frame_states);
+        *self.checkpoint.borrow_mut() = Some(Checkpoint {
+            frames: frames.to_vec(),
+            instance: self.snapshot(),
+        });
+    }
+
+    /// Write the core dump of the trap `err`, the trap is reported whether or
+    /// not this succeeds.
+    fn write_coredump(&self, path: &Path, err: &anyhow::Error) {
+        let frames = err
+            .downcast_ref::<WasmBacktrace>()
+            .map_or(&[][..], WasmBacktrace::frame_states);
+        let mems = self.store.copy_memories();
+        let globals = self.store.global_values();
+        if let Err(e) = write_coredump(path, &self.module, &mems, &globals, frames) {
+            log::warn!("failed to write the core dump to {}: {}", path.display(), e);
+        }
+    }
+
+    /// The function at `func_index`, after checking that it takes `params`.
+    fn get_func_checked(&self, func_index: u32, params: &[WasmValue]) -> Result<&FuncDecl> {
+        let func = self
+            .module
+            .get_func(func_index)
+            .ok_or_else(|| anyhow!("function {} not found", func_index))?;
+
+        let sig = func.get_sig();
+        if params.len() != sig.params().len()
+            || !params
+                .iter()
+                .zip(sig.params())
+                .all(|(v, ty)| v.has_type(ty))
+        {
+            return Err(anyhow!(
+                "function {} expects parameters {:?}, got {:?}",
+                func_index,
+                sig.params(),
+                params
+            ));
+        }
+
+        Ok(func)
+    }
+
+    fn run_jit(
+        &self,
+        func_index: u32,
+        main_func: &FuncDecl,
+        main_params: Vec<WasmValue>,
+        epoch_deadline: Option<EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        if self.jit_backend == JitBackend::Cranelift {
+            return self.run_cranelift_jit(func_index, main_func, main_params, epoch_deadline);
+        }
+        if cfg!(target_arch = "riscv64") {
+            return self.run_rv64_jit(func_index, main_func, main_params, epoch_deadline);
+        }
+
+        if let Some(reason) = X86JitCompiler::unsupported(&self.module) {
+            log::info!("the jit does not support {}, interpreting", reason);
+            return self.run_interpreter(func_index, main_params, epoch_deadline);
+        }
+        self.x86_jit()?
+            .call(func_index, &main_params, epoch_deadline.as_ref())
+    }
+
+    /// What every jit backend compiles the code of the instance with.
+    fn jit_settings(&self) -> JitSettings {
+        JitSettings {
+            max_call_depth: self.max_call_depth,
+            fuel: Rc::clone(&self.fuel),
+            epoch: self.epoch.clone(),
+            limiter: self.limiter.clone(),
+            trace: self.trace,
+            debug_info: self.jit_debug_info,
+            debug_break: self.debug_break,
+            perf_map: self.perf_map,
+            backtrace: self.jit_backtrace,
+            canonicalize_nans: self.canonicalize_nans,
+            default_memory_maximum: self.default_memory_maximum,
+            max_memory_pages: self.max_memory_pages,
+            max_table_elements: self.max_table_elements,
+            lazy: self.lazy_jit,
+            cache_dir: self.jit_cache_dir.clone(),
+        }
     }
 
-    fn run_interpreter(&self, main_func: FuncDecl, main_params: Vec<WasmValue>) -> Result<String> {
-        let mut executor = WasmFunctionExecutorImpl::new(
-            main_func,
+    /// The x86 jit code of the instance, compiled again if the settings it
+    /// was compiled with changed since.
+    fn x86_jit(&self) -> Result<Rc<JitInstance<'a>>> {
+        let settings = self.jit_settings();
+        if let Some(jit) = &*self.x86_jit.borrow() {
+            if jit.compiled_with(&settings) {
+                return Ok(Rc::clone(jit));
+            }
+        }
+
+        // register trap handler for SIGSEGV and SIGFPE, which are raised when
+        // wasm code has error. The trap comes back here as an error.
+        register_trap_handler();
+
+        let jit = Rc::new(JitInstance::new(
             Rc::clone(&self.module),
-            Rc::clone(&self.mem),
-            Some(main_params),
-        );
-
-        let result = executor.execute()?;
-        let result = match result {
-            Some(v) => v.to_string(),
-            None => String::new(),
+            Rc::clone(&self.store),
+            settings,
+        )?);
+        let (hits, compilations) = self.jit_cache_stats.get();
+        match jit.cache_hit() {
+            Some(true) => self.jit_cache_stats.set((hits + 1, compilations)),
+            Some(false) => self.jit_cache_stats.set((hits, compilations + 1)),
+            None => {}
+        }
+        *self.x86_jit.borrow_mut() = Some(Rc::clone(&jit));
+        Ok(jit)
+    }
+
+    /// The jit on riscv64, which runs on the store in place like the
+    /// interpreter. Calls using what it does not support run in the
+    /// interpreter instead.
+    fn run_rv64_jit(
+        &self,
+        func_index: u32,
+        main_func: &FuncDecl,
+        main_params: Vec<WasmValue>,
+        epoch_deadline: Option<EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        let unsupported = self
+            .unsupported_in_place(epoch_deadline.as_ref())
+            .or_else(|| Rv64JitCompiler::unsupported(&self.module));
+        if let Some(reason) = unsupported {
+            log::info!("the riscv64 jit does not support {}, interpreting", reason);
+            return self.run_interpreter(func_index, main_params, epoch_deadline);
+        }
+
+        let mut compiler = Rv64JitCompiler::new(
+            Rc::clone(&self.module),
+            Rc::clone(&self.store),
+            &self.jit_settings(),
+        )?;
+        let vm_entry = compiler.compile(func_index, main_params)?;
+        let results = compiler.run(vm_entry)?;
+
+        Ok(main_func
+            .get_sig()
+            .results()
+            .iter()
+            .zip(results)
+            .map(|(ty, raw)| WasmValue::from_raw(ty, raw))
+            .collect())
+    }
+
+    /// The jit with Cranelift, which runs on the store in place like the
+    /// riscv64 jit and falls back to the interpreter the same way.
+    #[cfg(feature = "cranelift")]
+    fn run_cranelift_jit(
+        &self,
+        func_index: u32,
+        main_func: &FuncDecl,
+        main_params: Vec<WasmValue>,
+        epoch_deadline: Option<EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        let unsupported = self
+            .unsupported_in_place(epoch_deadline.as_ref())
+            .or_else(|| CraneliftJitCompiler::unsupported(&self.module));
+        if let Some(reason) = unsupported {
+            log::info!(
+                "the cranelift jit does not support {}, interpreting",
+                reason
+            );
+            return self.run_interpreter(func_index, main_params, epoch_deadline);
+        }
+
+        // the code compiled by an earlier call, compiled again if the
+        // settings changed since. It is taken for the call, a host function
+        // calling into the instance compiles its own
+        let settings = self.jit_settings();
+        let cached = self.cranelift_jit.borrow_mut().take();
+        let mut compiler = match cached {
+            Some(compiler) if compiler.compiled_with(&settings) => compiler,
+            _ => CraneliftJitCompiler::new(
+                Rc::clone(&self.module),
+                Rc::clone(&self.store),
+                &settings,
+            )?,
         };
+        let results = compiler
+            .compile(func_index, main_params)
+            .and_then(|vm_entry| compiler.run(vm_entry));
+        *self.cranelift_jit.borrow_mut() = Some(compiler);
+        let results = results?;
 
-        Ok(result)
+        Ok(main_func
+            .get_sig()
+            .results()
+            .iter()
+            .zip(results)
+            .map(|(ty, raw)| WasmValue::from_raw(ty, raw))
+            .collect())
     }
-}
 
-impl<'a> WasmInterpreter<'a> {
-    pub fn from_module(module: WasmModule<'a>, jit_mode: bool) -> Self {
-        let mut mem = LinearMemory(if let Some(mem) = module.get_memory() {
-            vec![0; mem.initial as usize * WASM_DEFAULT_PAGE_SIZE_BYTE]
+    #[cfg(not(feature = "cranelift"))]
+    fn run_cranelift_jit(
+        &self,
+        _func_index: u32,
+        _main_func: &FuncDecl,
+        _main_params: Vec<WasmValue>,
+        _epoch_deadline: Option<EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        Err(anyhow!(
+            "the cranelift backend is not built in, build with `--features cranelift`"
+        ))
+    }
+
+    /// Why the jit backends running on the store in place, the riscv64 and
+    /// the Cranelift ones, cannot run a call, if they cannot whatever the
+    /// module. Their code has no fuel counter, no epoch check on function
+    /// entries and loop iterations and no trace of the blocks it enters, so a
+    /// metered, interruptible or traced call runs in the interpreter instead,
+    /// with the same results and only slower. A timeout interrupts through
+    /// the epoch too.
+    fn unsupported_in_place(&self, epoch_deadline: Option<&EpochDeadline>) -> Option<String> {
+        if self.fuel.get().is_some() {
+            Some("fuel".to_string())
+        } else if epoch_deadline.is_some() {
+            Some("epoch interruption".to_string())
+        } else if self.trace {
+            Some("tracing".to_string())
         } else {
-            vec![]
-        });
+            None
+        }
+    }
 
-        Self::setup_data_section(&module, &mut mem).expect("failed to setup data section");
+    fn run_interpreter(
+        &self,
+        func_index: u32,
+        main_params: Vec<WasmValue>,
+        epoch_deadline: Option<EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        self.new_executor(func_index, main_params, epoch_deadline)
+            .execute()
+    }
 
-        WasmInterpreter {
-            module: Rc::new(RefCell::new(module)),
-            mem: Rc::new(RefCell::new(mem)),
-            jit_mode,
+    /// Run the frames of a checkpoint, the innermost one first, every caller
+    /// takes the results of its callee.
+    fn run_saved_frames(
+        &self,
+        frames: &[SavedFrame],
+        epoch_deadline: Option<EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        let outermost = frames
+            .last()
+            .ok_or_else(|| anyhow!("resume: the checkpoint has no frames"))?;
+        if let Some(saved) = frames
+            .iter()
+            .find(|saved| self.module.get_func(saved.func_index).is_none())
+        {
+            return Err(anyhow!("function {} not found", saved.func_index));
+        }
+        self.new_executor(outermost.func_index, vec![], epoch_deadline)
+            .with_saved_frames(frames)
+            .execute()
+    }
+
+    /// Check that the frames of a checkpoint fit the module, the callers must
+    /// be at a call.
+    fn check_saved_frames(&self, frames: &[SavedFrame]) -> Result<()> {
+        let module = &self.module;
+        for (i, saved) in frames.iter().enumerate() {
+            let func = module
+                .get_func(saved.func_index)
+                .ok_or_else(|| anyhow!("resume: function {} not found", saved.func_index))?;
+            let at_call = matches!(
+                func.get_insts().get(saved.pc),
+                Some(Instruction::Call { .. } | Instruction::CallIndirect { .. })
+            );
+            // the try blocks refer to the handlers of the lowered body
+            let num_handlers = func.get_code().handlers.len();
+            let handlers_fit = saved.control_flow_frames.iter().all(|frame| match frame {
+                BlockControlFlowFrame::Try { handler } => (*handler as usize) < num_handlers,
+                _ => true,
+            });
+            if saved.pc >= func.get_insts().len()
+                || !handlers_fit
+                || (i > 0 && !(at_call && !saved.control_flow_frames.is_empty()))
+            {
+                return Err(anyhow!(
+                    "resume: the checkpoint does not fit function {}",
+                    saved.func_index
+                ));
+            }
         }
+        Ok(())
+    }
+
+    fn new_executor(
+        &self,
+        func_index: u32,
+        params: Vec<WasmValue>,
+        epoch_deadline: Option<EpochDeadline>,
+    ) -> WasmFunctionExecutorImpl<'a> {
+        WasmFunctionExecutorImpl::new(
+            func_index,
+            Rc::clone(&self.module),
+            Rc::clone(&self.store),
+            Some(params),
+        )
+        .with_max_call_depth(self.max_call_depth)
+        .with_default_memory_maximum(self.default_memory_maximum)
+        .with_max_memory_pages(self.max_memory_pages)
+        .with_max_table_elements(self.max_table_elements)
+        .with_limiter(self.limiter.clone())
+        .with_fuel(Rc::clone(&self.fuel))
+        .with_epoch_deadline(epoch_deadline)
+        .with_trace(self.trace)
+        .with_debugger(self.debugger.clone())
+        .with_frame_states(self.coredump.is_some() || self.checkpoint_on_interrupt)
+        .with_canonicalize_nans(self.canonicalize_nans)
+        .with_tiering(self.tiering())
+    }
+
+    /// The call counts and jit code of the hot functions, shared by every
+    /// call of the instance.
+    pub(super) fn tiering(&self) -> Option<Rc<Tiering<'a>>> {
+        // tiering compiles with the x86 jit
+        if cfg!(not(target_arch = "x86_64")) {
+            return None;
+        }
+        let threshold = self.tier_up.filter(|_| !self.jit_mode)?;
+        let tiering = self.tiering.get_or_init(|| {
+            Rc::new(Tiering::new(
+                Rc::clone(&self.module),
+                Rc::clone(&self.store),
+                threshold,
+                self.jit_settings(),
+            ))
+        });
+        Some(Rc::clone(tiering))
     }
 }
 
 impl<'a> WasmInterpreter<'a> {
-    /// setup data section with the given data section in the module
-    /// e.g. (data (i32.const 10) "foo") will be loaded to linear memory at address 10
-    fn setup_data_section(module: &WasmModule<'a>, mem: &mut LinearMemory) -> Result<()> {
-        let datas = module.get_datas();
-        for data in datas {
-            match &data.kind {
-                wasmparser::DataKind::Passive => panic!("passive data segment not implemented"),
-                wasmparser::DataKind::Active {
-                    memory_index,
-                    offset_expr,
-                } => {
-                    if *memory_index != 0 {
-                        return Err(anyhow!("memory.init: invalid memory index"));
-                    }
+    /// Instantiate the module and run its start function, fails if one of
+    /// its imports is not a built-in host function, if its data or element
+    /// segments do not fit in their memory or table or if the start function
+    /// traps.
+    pub fn from_module(module: WasmModule<'a>, jit_mode: bool) -> Result<Self> {
+        Self::instantiate(module, jit_mode, JitBackend::default(), Linker::default())
+    }
 
-                    // read offset_index
-                    let mut reader = offset_expr.get_binary_reader();
-                    let op = reader.read_u8()?; // skip WASM_OP_I32_CONST
-                    if op as u32 != WASM_OP_I32_CONST {
-                        panic!("data segment offset: invalid opcode, should be i32.const");
-                    }
+    /// Imported functions are resolved in `linker` before the built-in host
+    /// functions, the start function can already call them. Imported
+    /// globals, memories and tables are only resolved in `linker`.
+    fn instantiate(
+        module: WasmModule<'a>,
+        jit_mode: bool,
+        jit_backend: JitBackend,
+        linker: Linker<'a>,
+    ) -> Result<Self> {
+        let store = Store::new(&module, &linker)?;
+        let vm = WasmInterpreter {
+            module: Rc::new(module),
+            store: Rc::new(store),
+            jit_mode,
+            jit_backend,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            default_memory_maximum: DEFAULT_MEMORY_MAXIMUM,
+            max_memory_pages: u64::MAX,
+            max_table_elements: u64::MAX,
+            limiter: None,
+            fuel: Rc::new(Cell::new(None)),
+            epoch: None,
+            epoch_deadline: Cell::new(0),
+            timeout: None,
+            trace: false,
+            debugger: None,
+            jit_debug_info: false,
+            debug_break: false,
+            perf_map: false,
+            lazy_jit: false,
+            jit_cache_dir: None,
+            jit_backtrace: false,
+            coredump: None,
+            checkpoint_on_interrupt: false,
+            canonicalize_nans: false,
+            entry: None,
+            float_format: FloatFormat::default(),
+            checkpoint: RefCell::new(None),
+            tier_up: None,
+            tiering: OnceCell::new(),
+            x86_jit: RefCell::new(None),
+            #[cfg(feature = "cranelift")]
+            cranelift_jit: RefCell::new(None),
+            jit_cache_stats: Cell::new((0, 0)),
+        };
+
+        if let Some(start) = vm.module.get_start_func() {
+            vm.invoke_func(start, vec![])?;
+        }
+        Ok(vm)
+    }
+
+    pub fn with_config(module: WasmModule<'a>, config: &WasmInterpreterConfig<'a>) -> Result<Self> {
+        let max_memory_pages = config.max_memory_pages.unwrap_or(u64::MAX);
+        let max_table_elements = config.max_table_elements.unwrap_or(u64::MAX);
+        module.check_features(&config.features)?;
+        check_initial_sizes(&module, max_memory_pages, max_table_elements)?;
+        let mut vm = Self::instantiate(
+            module,
+            config.jit_mode,
+            config.jit_backend,
+            config.linker.clone(),
+        )?;
+        vm.max_memory_pages = max_memory_pages;
+        vm.max_table_elements = max_table_elements;
+        if let Some(depth) = config.max_call_depth {
+            vm.max_call_depth = depth;
+        }
+        if let Some(pages) = config.default_memory_maximum {
+            vm.default_memory_maximum = pages;
+        }
+        vm.limiter = config.limiter.clone();
+        if let Some(fuel) = config.fuel {
+            vm.set_fuel(fuel);
+        }
+        if let Some(epoch) = &config.epoch {
+            vm.epoch_interruption(epoch.clone());
+        }
+        if let Some(timeout) = config.timeout {
+            vm.set_timeout(timeout);
+        }
+        if let Some(sink) = &config.trace_imports {
+            vm.trace_imports(sink)?;
+        }
+        vm.trace_instructions(config.trace_instructions);
+        if config.debugger {
+            vm.enable_debugger();
+        }
+        vm.jit_debug_info(config.jit_debug_info);
+        vm.debug_break(config.debug_break);
+        vm.perf_map(config.perf_map);
+        vm.lazy_jit(config.lazy_jit);
+        if let Some(dir) = &config.jit_cache_dir {
+            vm.jit_cache_dir(dir.clone());
+        }
+        vm.jit_backtrace(config.jit_backtrace);
+        if let Some(path) = &config.coredump {
+            vm.coredump_on_trap(path.clone());
+        }
+        vm.checkpoint_on_interrupt(config.checkpoint_on_interrupt);
+        vm.canonicalize_nans(config.canonicalize_nans);
+        if let Some(name) = &config.entry {
+            vm.set_entry(name.clone());
+        }
+        vm.float_format(config.float_format);
+        if let Some(calls) = config.tier_up {
+            vm.tier_up(calls);
+        }
+        Ok(vm)
+    }
+
+    /// Meter the execution with `fuel` units, one per executed instruction.
+    /// The fuel is kept across calls, running out of it traps with
+    /// `FuelExhausted`.
+    pub fn set_fuel(&self, fuel: u64) {
+        self.fuel.set(Some(fuel));
+    }
+
+    /// The fuel left, `None` if the execution is not metered.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel.get()
+    }
+
+    /// Check `epoch` on function entries and loop iterations and stop with
+    /// `TrapKind::Interrupted` once it is incremented past the deadline, which
+    /// is the next increment until `set_epoch_deadline` is called.
+    pub fn epoch_interruption(&mut self, epoch: Epoch) {
+        self.epoch_deadline.set(epoch.current() + 1);
+        self.epoch = Some(epoch);
+    }
+
+    /// Interrupt the execution `ticks` increments of the epoch from now.
+    pub fn set_epoch_deadline(&self, ticks: u64) {
+        if let Some(epoch) = &self.epoch {
+            self.epoch_deadline.set(epoch.current() + ticks);
+        }
+    }
+
+    /// Stop every call that runs longer than `timeout` with
+    /// `TrapKind::Timeout`. A watchdog thread increments the epoch of the
+    /// instance when the time is up, a private one unless epoch interruption
+    /// is enabled.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        if self.epoch.is_none() {
+            self.epoch = Some(Epoch::new());
+            self.epoch_deadline.set(u64::MAX);
+        }
+        self.timeout = Some(timeout);
+    }
+
+    fn epoch_deadline(&self) -> Option<EpochDeadline> {
+        self.epoch.as_ref().map(|epoch| EpochDeadline {
+            epoch: epoch.clone(),
+            deadline: self.epoch_deadline.get(),
+        })
+    }
+
+    /// Log every call to an imported host function, with its decoded
+    /// arguments and results, to the given sink.
+    pub fn trace_imports(&mut self, sink: &ImportTraceSink) -> Result<()> {
+        let tracer = ImportTracer::new(sink)?;
+        self.store.host.borrow_mut().set_tracer(tracer);
+        Ok(())
+    }
+
+    /// Log every executed instruction to stderr, with the function index, the
+    /// pc and the top of the operand stack. The jit only logs the function
+    /// index and pc of every basic block it enters.
+    pub fn trace_instructions(&mut self, enabled: bool) {
+        self.trace = enabled;
+    }
+
+    /// Register the jit code of every call with gdb and lldb through the GDB
+    /// JIT interface, so their backtraces name the wasm functions.
+    pub fn jit_debug_info(&mut self, enabled: bool) {
+        self.jit_debug_info = enabled;
+    }
+
+    /// Stop at an `int3` when a call in jit mode enters the jit code, so a
+    /// debugger attached to the process breaks before the start function
+    /// and the called function run. Without a debugger the process dies of
+    /// SIGTRAP.
+    pub fn debug_break(&mut self, enabled: bool) {
+        self.debug_break = enabled;
+    }
+
+    /// Append the address range of every jit compiled function to
+    /// /tmp/perf-<pid>.map, so `perf report` attributes samples in jit code
+    /// to wasm functions.
+    pub fn perf_map(&mut self, enabled: bool) {
+        self.perf_map = enabled;
+    }
 
-                    let offset = reader.read_var_i32()?;
-                    let byte_slice = data.data;
+    /// Only compile a function when the jit code first calls it, instead of
+    /// every function before the call starts, which cuts the startup of
+    /// large modules of which a call only reaches a few functions. A
+    /// function that fails to compile then traps when it is called, instead
+    /// of failing the whole call up front.
+    pub fn lazy_jit(&mut self, enabled: bool) {
+        self.lazy_jit = enabled;
+    }
+
+    /// Save the code the jit compiles up front in `dir` and load it from
+    /// there on the next run of the same module with the same settings,
+    /// instead of compiling it again. A file that does not match is
+    /// replaced, and the code runs all the same if it cannot be written.
+    /// Only the x86 jit without `lazy_jit` caches its code.
+    pub fn jit_cache_dir(&mut self, dir: PathBuf) {
+        self.jit_cache_dir = Some(dir);
+    }
+
+    /// How many times the jit code was loaded from `jit_cache_dir`, and how
+    /// many times it was compiled and saved there instead.
+    pub fn jit_cache_stats(&self) -> (u64, u64) {
+        self.jit_cache_stats.get()
+    }
+
+    /// Record the wasm frames when jit code traps, the trap then carries
+    /// them in `RuntimeError::backtrace` like a trap of the interpreter.
+    pub fn jit_backtrace(&mut self, enabled: bool) {
+        self.jit_backtrace = enabled;
+    }
+
+    /// Write a core dump of the instance to `path` when a call traps, in the
+    /// wasm coredump format of the tool conventions: the memories, the
+    /// globals and the locals and operand stack of every frame. The dump is
+    /// a wasm module, so `wasm-tools` and debuggers can inspect it offline.
+    /// Only the interpreter writes core dumps.
+    pub fn coredump_on_trap(&mut self, path: PathBuf) {
+        self.coredump = Some(path);
+    }
+
+    /// Capture the linear memories, globals and tables of the instance, e.g.
+    /// right after initialization, to reset it with `restore` between calls.
+    pub fn snapshot(&self) -> Snapshot {
+        self.store.snapshot()
+    }
+
+    /// Reinstate the state captured by `snapshot`, which must have been taken
+    /// of this instance.
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        self.store.restore(snapshot)
+    }
+
+    /// Replace every NaN produced by float arithmetic, f32 and f64, by the
+    /// canonical NaN, so results are bit for bit the same on the interpreter
+    /// and the jit whatever NaN payloads the host propagates. `abs`, `neg`
+    /// and `copysign` only change the sign bit and keep their payload, as
+    /// do loads and reinterpretations.
+    pub fn canonicalize_nans(&mut self, enabled: bool) {
+        self.canonicalize_nans = enabled;
+        // the hot functions are compiled again with the new setting
+        self.tiering = OnceCell::new();
+    }
+
+    /// Make `run` call the function exported as `name`. Without an entry it
+    /// calls `main`, `_start` or the first exported function, whichever the
+    /// module exports first in that order.
+    pub fn set_entry(&mut self, name: impl Into<String>) {
+        self.entry = Some(name.into());
+    }
+
+    /// Print the float results of `run` in `format` instead of with six
+    /// decimal places.
+    pub fn float_format(&mut self, format: FloatFormat) {
+        self.float_format = format;
+    }
+
+    /// Start every call in the interpreter and jit compile a function once
+    /// it was called `calls` times, its later calls then run the jit code.
+    /// The function is compiled on the thread of the call that made it hot.
+    /// Functions using simd or exceptions, which the jit does not support,
+    /// and their callers stay in the interpreter, so do calls that are
+    /// metered, interruptible, traced, debugged or may be dumped. A trap in
+    /// jit code is returned like a trap of the interpreter. Ignored in jit
+    /// mode and off x86-64.
+    pub fn tier_up(&mut self, calls: u64) {
+        self.tier_up = Some(calls);
+        self.tiering = OnceCell::new();
+    }
+
+    /// The results separated by spaces, as returned by `run`.
+    pub fn format_results(&self, results: &[WasmValue]) -> String {
+        results
+            .iter()
+            .map(|v| v.format_with(self.float_format))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 
-                    let offset = usize::try_from(offset)?;
-                    for (i, b) in byte_slice.iter().enumerate() {
-                        mem.0[offset + i] = *b;
+    /// Keep the execution state of a call that runs out of fuel, is
+    /// interrupted or times out, for `take_checkpoint`. Only the interpreter
+    /// takes checkpoints.
+    pub fn checkpoint_on_interrupt(&mut self, enabled: bool) {
+        self.checkpoint_on_interrupt = enabled;
+    }
+
+    /// The checkpoint of the last call, if it stopped with a trap for which
+    /// `TrapKind::is_resumable` holds. It holds the frames of the call and
+    /// the state of the instance, and can be saved to disk.
+    pub fn take_checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpoint.borrow_mut().take()
+    }
+
+    /// Continue the call of `checkpoint` from where it stopped, on an
+    /// instance of the same module whose state is replaced by the one of
+    /// the checkpoint. The fuel, epoch deadline and timeout of this instance
+    /// apply, and the call can be checkpointed again.
+    pub fn resume(&self, checkpoint: &Checkpoint) -> Result<Vec<WasmValue>, RuntimeError> {
+        if self.jit_mode {
+            return Err(anyhow!("checkpoints are not supported by the jit").into());
+        }
+        self.check_saved_frames(&checkpoint.frames)?;
+        self.restore(&checkpoint.instance)?;
+        Ok(self.supervise(|epoch_deadline| {
+            self.run_saved_frames(&checkpoint.frames, epoch_deadline)
+        })?)
+    }
+
+    /// Run the interactive debugger on stdin, it stops before the first
+    /// instruction and at breakpoints set by function and pc, see `help` at
+    /// its prompt. Only the interpreter can be debugged.
+    pub fn enable_debugger(&mut self) {
+        self.debugger = Some(Rc::new(RefCell::new(Debugger::new())));
+    }
+}
+
+impl<'a> WasmInterpreter<'a> {
+    /// Define the exports of the instance in `linker` under the module name
+    /// `name`, see `Linker::instance`.
+    pub(crate) fn define_exports(
+        self: &Rc<Self>,
+        name: &str,
+        linker: &mut Linker<'a>,
+    ) -> Result<()> {
+        let module = &self.module;
+        for export in module.get_exports() {
+            let index = export.index as usize;
+            match export.kind {
+                ExternalKind::Func => {
+                    let sig = module
+                        .get_func(export.index)
+                        .ok_or_else(|| anyhow!("export {}: unknown function", export.name))?
+                        .get_sig()
+                        .clone();
+                    let instance = Rc::clone(self);
+                    let func_index = export.index;
+                    let func = HostFunc::new(
+                        sig,
+                        move |_caller: &mut Caller, args: &[WasmValue]| {
+                            instance.invoke_func(func_index, args.to_vec())
+                        },
+                        false,
+                    );
+                    linker.define(name, export.name, func)?;
+                }
+                ExternalKind::Memory => {
+                    let mem = self
+                        .store
+                        .mems
+                        .get(index)
+                        .ok_or_else(|| anyhow!("export {}: unknown memory", export.name))?;
+                    linker.memory(name, export.name, mem.clone())?;
+                }
+                ExternalKind::Global => {
+                    let global = self
+                        .store
+                        .globals
+                        .get(index)
+                        .ok_or_else(|| anyhow!("export {}: unknown global", export.name))?;
+                    // only numeric globals can be imported
+                    if !global.get().is_ref() {
+                        linker.define_global(name, export.name, global.clone())?;
+                    }
+                }
+                ExternalKind::Table => {
+                    let element_type = module
+                        .get_tables()
+                        .get(index)
+                        .ok_or_else(|| anyhow!("export {}: unknown table", export.name))?
+                        .ty
+                        .element_type;
+                    let table = self.store.tables.borrow()[index].clone();
+                    if !element_type.is_extern_ref()
+                        && table.elems.iter().any(|elem| elem.as_ref().is_some())
+                    {
+                        continue;
                     }
+                    linker.table(name, export.name, element_type, table.elems, table.max)?;
                 }
+                ExternalKind::Tag => {}
             }
         }
-
         Ok(())
     }
 }
+
+/// Fail if a memory or a table of `module` starts larger than the limits of
+/// `WasmInterpreterConfig::max_memory_pages` and `max_table_elements`.
+fn check_initial_sizes(
+    module: &WasmModule,
+    max_memory_pages: u64,
+    max_table_elements: u64,
+) -> Result<()> {
+    for (i, ty) in module.get_memories().iter().enumerate() {
+        if ty.initial > max_memory_pages {
+            return Err(anyhow!(
+                "memory {} starts at {} pages, more than the limit of {}",
+                i,
+                ty.initial,
+                max_memory_pages
+            ));
+        }
+    }
+    for (i, table) in module.get_tables().iter().enumerate() {
+        if table.ty.initial > max_table_elements {
+            return Err(anyhow!(
+                "table {} starts at {} elements, more than the limit of {}",
+                i,
+                table.ty.initial,
+                max_table_elements
+            ));
+        }
+    }
+    Ok(())
+}
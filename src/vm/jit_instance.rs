@@ -0,0 +1,112 @@
+//! The x86 jit code of an instance, see `WasmInterpreter::run_jit`. The
+//! module is compiled once, or function by function with the lazy jit, and
+//! every call enters the code of its function through an entry emitted on
+//! its first call, see `jit::setup::tier`.
+//!
+//! The jit code runs on the store of the instance like the interpreter, see
+//! `jit::setup::state`: a call starts from the state the previous one left,
+//! the start function included, and the embedder sees what it changed.
+//!
+//! The code is compiled again when the settings it was compiled with
+//! change, e.g. when fuel is set on an instance that was not metered.
+
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use debug_cell::RefCell;
+
+use crate::{
+    jit::{call_guarded, JitSettings, TierEntry, X86JitCompiler},
+    module::{value_type::WasmValue, wasm_module::WasmModule},
+};
+
+use super::{EpochDeadline, Store};
+
+pub(crate) struct JitInstance<'a> {
+    settings: JitSettings,
+    /// whether the code is metered, see `JitSettings::metered`
+    metered: bool,
+    module: Rc<WasmModule<'a>>,
+    /// boxed since its stubs hold its address
+    compiler: RefCell<Box<X86JitCompiler<'a>>>,
+    entries: RefCell<HashMap<u32, TierEntry>>,
+}
+
+impl<'a> JitInstance<'a> {
+    /// Compile the code of the instance running on `store` with
+    /// `settings`.
+    pub(crate) fn new(
+        module: Rc<WasmModule<'a>>,
+        store: Rc<Store<'a>>,
+        settings: JitSettings,
+    ) -> Result<Self> {
+        // jit compile all functions, or only stubs compiling them on their
+        // first call
+        let mut compiler = Box::new(X86JitCompiler::new(module, store, &settings)?);
+        compiler.setup()?;
+        Ok(Self {
+            metered: settings.metered(),
+            settings,
+            module: Rc::clone(&compiler.module),
+            compiler: RefCell::new(compiler),
+            entries: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Whether the code was compiled with `settings`.
+    pub(crate) fn compiled_with(&self, settings: &JitSettings) -> bool {
+        self.settings == *settings && self.metered == settings.metered()
+    }
+
+    /// Whether the code was loaded from the jit cache, `None` if it is not
+    /// cached, see `X86JitCompiler::setup_cached`.
+    pub(crate) fn cache_hit(&self) -> Option<bool> {
+        self.compiler.borrow().cache_hit
+    }
+
+    /// Call `func_index` with `args`, interrupted at `epoch_deadline`.
+    pub(crate) fn call(
+        &self,
+        func_index: u32,
+        args: &[WasmValue],
+        epoch_deadline: Option<&EpochDeadline>,
+    ) -> Result<Vec<WasmValue>> {
+        if args.iter().any(|arg| matches!(arg, WasmValue::V128(_))) {
+            return Err(anyhow!("v128 values are not supported by the jit compiler"));
+        }
+        let entry = self.entry(func_index)?;
+        let raw_args = args.iter().map(WasmValue::to_raw).collect::<Vec<_>>();
+
+        let (landing, saved) = {
+            let mut compiler = self.compiler.borrow_mut();
+            (compiler.trap_landing(), compiler.enter(epoch_deadline))
+        };
+        // the jit code only borrows the compiler to compile the functions it
+        // reaches on their first call and around host calls, which may call
+        // back into the instance
+        let first = call_guarded(landing, |frame| entry(raw_args.as_ptr(), frame));
+
+        let mut compiler = self.compiler.borrow_mut();
+        let results = first.map(|first| compiler.entry_results(func_index, first));
+        compiler.leave(saved);
+        results
+    }
+
+    /// The entry of the jit code of `func_index`, emitted on the first call.
+    fn entry(&self, func_index: u32) -> Result<TierEntry> {
+        if let Some(entry) = self.entries.borrow().get(&func_index) {
+            return Ok(*entry);
+        }
+
+        let nargs = self.module.get_funcs()[func_index as usize]
+            .get_sig()
+            .params()
+            .len();
+        let entry = self
+            .compiler
+            .borrow_mut()
+            .emit_tier_entry(func_index, nargs)?;
+        self.entries.borrow_mut().insert(func_index, entry);
+        Ok(entry)
+    }
+}
@@ -0,0 +1,147 @@
+//! Quotas the embedder puts on an instance on top of the limits its module
+//! declares. A `ResourceLimiter` is asked before every `memory.grow` and
+//! `table.grow` that the declared maximum allows, in both engines, and a
+//! denied growth fails like one past the maximum, with -1.
+
+use std::{fmt, rc::Rc};
+
+use debug_cell::RefCell;
+
+/// Approves or denies the growth of the memories and tables of an instance,
+/// see `WasmInterpreterConfig::limiter`.
+///
+/// ```ignore
+/// struct Quota(u64);
+///
+/// impl ResourceLimiter for Quota {
+///     fn memory_growing(&mut self, _current: u64, desired: u64, _maximum: Option<u64>) -> bool {
+///         desired <= self.0
+///     }
+///
+///     fn table_growing(&mut self, _current: u64, _desired: u64, _maximum: Option<u64>) -> bool {
+///         true
+///     }
+/// }
+/// ```
+pub trait ResourceLimiter {
+    /// Whether a memory of `current` pages may grow to `desired` pages.
+    /// `maximum` is the maximum the memory declares, `desired` is within it.
+    fn memory_growing(&mut self, current: u64, desired: u64, maximum: Option<u64>) -> bool;
+
+    /// Whether a table of `current` elements may grow to `desired` elements.
+    /// `maximum` is the maximum the table declares, `desired` is within it.
+    fn table_growing(&mut self, current: u64, desired: u64, maximum: Option<u64>) -> bool;
+}
+
+/// The limiter of an instance, shared by its config, its executors and its
+/// jit code.
+#[derive(Clone)]
+pub(crate) struct SharedLimiter(Rc<RefCell<dyn ResourceLimiter>>);
+
+impl SharedLimiter {
+    pub(crate) fn new(limiter: impl ResourceLimiter + 'static) -> Self {
+        Self(Rc::new(RefCell::new(limiter)))
+    }
+
+    pub(crate) fn memory_growing(&self, current: u64, desired: u64, maximum: Option<u64>) -> bool {
+        self.0
+            .borrow_mut()
+            .memory_growing(current, desired, maximum)
+    }
+
+    pub(crate) fn table_growing(&self, current: u64, desired: u64, maximum: Option<u64>) -> bool {
+        self.0.borrow_mut().table_growing(current, desired, maximum)
+    }
+}
+
+impl fmt::Debug for SharedLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResourceLimiter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::ResourceLimiter;
+    use crate::{
+        module::{value_type::WasmValue, wasm_module::WasmModule},
+        vm::{WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    const WAT: &str = r#"
+        (module
+          (memory 1 10)
+          (table 1 10 funcref)
+          (func (export "grow_memory") (param i32) (result i32)
+            (memory.grow (local.get 0)))
+          (func (export "grow_table") (param i32) (result i32)
+            (table.grow (ref.null func) (local.get 0))))
+    "#;
+
+    /// Allows 3 pages and 2 elements, and records what it was asked.
+    struct Quota(Rc<RefCell<Vec<(u64, u64, Option<u64>)>>>);
+
+    impl ResourceLimiter for Quota {
+        fn memory_growing(&mut self, current: u64, desired: u64, maximum: Option<u64>) -> bool {
+            self.0.borrow_mut().push((current, desired, maximum));
+            desired <= 3
+        }
+
+        fn table_growing(&mut self, current: u64, desired: u64, maximum: Option<u64>) -> bool {
+            self.0.borrow_mut().push((current, desired, maximum));
+            desired <= 2
+        }
+    }
+
+    fn grow(jit: bool, func: &str) -> (Vec<i32>, Vec<(u64, u64, Option<u64>)>) {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let asked = Rc::new(RefCell::new(Vec::new()));
+        let config = WasmInterpreterConfig::new()
+            .jit(jit)
+            .limiter(Quota(asked.clone()));
+        let vm = WasmInterpreter::with_config(module, &config).unwrap();
+        // the last growth is past the declared maximum, the limiter is not
+        // asked about it
+        let results = [1, 2, 1, 20]
+            .into_iter()
+            .map(
+                |n| match vm.invoke(func, vec![WasmValue::I32(n)]).unwrap()[..] {
+                    [WasmValue::I32(old)] => old,
+                    _ => panic!("{} returns an i32", func),
+                },
+            )
+            .collect();
+        let asked = asked.borrow().clone();
+        (results, asked)
+    }
+
+    fn check(jit: bool) {
+        let (memory, asked) = grow(jit, "grow_memory");
+        assert_eq!(memory, [1, -1, 2, -1]);
+        assert_eq!(
+            asked,
+            [(1, 2, Some(10)), (2, 4, Some(10)), (2, 3, Some(10))]
+        );
+
+        let (table, asked) = grow(jit, "grow_table");
+        assert_eq!(table, [1, -1, -1, -1]);
+        assert_eq!(
+            asked,
+            [(1, 2, Some(10)), (2, 4, Some(10)), (2, 3, Some(10))]
+        );
+    }
+
+    #[test]
+    fn the_limiter_denies_growth_in_the_interpreter() {
+        check(false);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn the_limiter_denies_growth_in_the_jit_code() {
+        check(true);
+    }
+}
@@ -0,0 +1,582 @@
+//! Host functions, globals, memories and tables provided by the embedder. A
+//! `Linker` maps the module and field name of an import to a Rust closure, a
+//! value, a `Memory` or the elements of a table, imported calls of both
+//! engines reach it through `HostFuncDispatcher`.
+//!
+//! ```ignore
+//! let mut linker = Linker::new();
+//! linker.func_wrap("env", "add", |a: i32, b: i32| a + b)?;
+//! linker.func_wrap("env", "peek", |caller: &mut Caller, addr: i32| {
+//!     caller.memory()[addr as usize] as i32
+//! })?;
+//! linker.global("env", "base", WasmValue::I32(1024))?;
+//! linker.memory("env", "memory", Memory::new(1, None))?;
+//! linker.table("env", "table", RefType::FUNCREF, vec![WasmValue::FuncRef(Some(0))], None)?;
+//! linker.instance("lib", &Rc::new(lib))?;
+//! let config = WasmInterpreterConfig::new().linker(linker);
+//! let vm = WasmInterpreter::with_config(module, &config)?;
+//! ```
+
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use wasmparser::{FuncType, RefType, ValType};
+
+use super::{
+    spectest::define_spectest,
+    store::{Global, Table},
+    wasi::{define_wasi, WasiCtx},
+    Memory, WasmInterpreter,
+};
+use crate::module::{dump::format_sig, value_type::WasmValue};
+
+/// What a host function sees of the instance that called it.
+pub struct Caller<'a> {
+    memory: &'a mut [u8],
+}
+
+impl<'a> Caller<'a> {
+    pub(crate) fn new(memory: &'a mut [u8]) -> Self {
+        Self { memory }
+    }
+
+    /// The first linear memory of the caller, empty if it has none.
+    pub fn memory(&self) -> &[u8] {
+        self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.memory
+    }
+}
+
+type HostFn<'a> = dyn Fn(&mut Caller, &[WasmValue]) -> Result<Vec<WasmValue>> + 'a;
+
+/// A host function with the signature its closure was registered with.
+#[derive(Clone)]
+pub struct HostFunc<'a> {
+    pub(crate) sig: FuncType,
+    func: Rc<HostFn<'a>>,
+    /// whether the closure takes a `Caller`, the caller's memory is only
+    /// borrowed for those
+    uses_caller: bool,
+}
+
+impl<'a> HostFunc<'a> {
+    pub(crate) fn new(
+        sig: FuncType,
+        func: impl Fn(&mut Caller, &[WasmValue]) -> Result<Vec<WasmValue>> + 'a,
+        uses_caller: bool,
+    ) -> Self {
+        Self {
+            sig,
+            func: Rc::new(func),
+            uses_caller,
+        }
+    }
+
+    pub(crate) fn uses_caller(&self) -> bool {
+        self.uses_caller
+    }
+
+    /// Call the function, `args` have the types of its parameters. An error
+    /// traps the calling wasm code.
+    pub(crate) fn call(&self, caller: &mut Caller, args: &[WasmValue]) -> Result<Vec<WasmValue>> {
+        let results = (self.func)(caller, args)?;
+        if results.len() != self.sig.results().len()
+            || !results
+                .iter()
+                .zip(self.sig.results())
+                .all(|(v, ty)| v.has_type(ty))
+        {
+            return Err(anyhow!(
+                "host function returned {:?}, expected {:?}",
+                results,
+                self.sig.results()
+            ));
+        }
+        Ok(results)
+    }
+}
+
+/// The host functions, global values, memories and tables that imports are
+/// resolved to, by module and field name.
+#[derive(Clone, Default)]
+pub struct Linker<'a> {
+    funcs: HashMap<(String, String), HostFunc<'a>>,
+    globals: HashMap<(String, String), Global>,
+    memories: HashMap<(String, String), Memory>,
+    tables: HashMap<(String, String), (RefType, Table)>,
+}
+
+impl<'a> Linker<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define `module.name` as a closure over wasm values, its parameters
+    /// and results are `i32`, `i64`, `f32` or `f64`, and it may take a
+    /// `&mut Caller` first and return a `Result`:
+    /// `|caller: &mut Caller, addr: i32| -> Result<i64> { ... }`.
+    pub fn func_wrap<Params, Results>(
+        &mut self,
+        module: &str,
+        name: &str,
+        func: impl IntoHostFunc<'a, Params, Results>,
+    ) -> Result<&mut Self> {
+        self.define(module, name, func.into_host_func())
+    }
+
+    /// Define `module.name` with the signature `ty`, the closure gets the
+    /// arguments as `WasmValue`s and returns the results the same way.
+    pub fn func_new(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl Fn(&mut Caller, &[WasmValue]) -> Result<Vec<WasmValue>> + 'a,
+    ) -> Result<&mut Self> {
+        self.define(module, name, HostFunc::new(ty, func, true))
+    }
+
+    /// Define the imported global `module.name` with the initial value
+    /// `value`, which must be an `i32`, `i64`, `f32` or `f64`. The instances
+    /// importing it share it, `global.set` on a mutable import is seen by
+    /// all of them.
+    pub fn global(&mut self, module: &str, name: &str, value: WasmValue) -> Result<&mut Self> {
+        if !matches!(
+            value,
+            WasmValue::I32(_) | WasmValue::I64(_) | WasmValue::F32(_) | WasmValue::F64(_)
+        ) {
+            return Err(anyhow!("{}.{}: unsupported global value", module, name));
+        }
+        self.define_global(module, name, Global::new(value))
+    }
+
+    pub(crate) fn define_global(
+        &mut self,
+        module: &str,
+        name: &str,
+        global: Global,
+    ) -> Result<&mut Self> {
+        let key = self.key(module, name)?;
+        self.globals.insert(key, global);
+        Ok(self)
+    }
+
+    /// Define the imported memory `module.name`. The instances importing it
+    /// and the embedder all see the same memory.
+    pub fn memory(&mut self, module: &str, name: &str, memory: Memory) -> Result<&mut Self> {
+        let key = self.key(module, name)?;
+        self.memories.insert(key, memory);
+        Ok(self)
+    }
+
+    /// Define the imported table `module.name` of `element_type` references,
+    /// starting with `elems` and growing up to `maximum` elements. Function
+    /// references are indices into the function index space of the importing
+    /// module. Unlike globals, every instance gets its own copy of the table.
+    pub fn table(
+        &mut self,
+        module: &str,
+        name: &str,
+        element_type: RefType,
+        elems: Vec<WasmValue>,
+        maximum: Option<u64>,
+    ) -> Result<&mut Self> {
+        if let Some(elem) = elems
+            .iter()
+            .find(|elem| !elem.has_type(&ValType::Ref(element_type)))
+        {
+            return Err(anyhow!(
+                "{}.{}: {:?} is not a {} reference",
+                module,
+                name,
+                elem,
+                element_type
+            ));
+        }
+        let key = self.key(module, name)?;
+        self.tables.insert(
+            key,
+            (
+                element_type,
+                Table {
+                    elems,
+                    max: maximum,
+                },
+            ),
+        );
+        Ok(self)
+    }
+
+    /// Define the WASI functions with the arguments and environment of
+    /// `ctx`, instead of the built-in ones that have neither.
+    pub fn wasi(&mut self, ctx: WasiCtx) -> Result<&mut Self> {
+        define_wasi(self, ctx)?;
+        Ok(self)
+    }
+
+    /// Define the `spectest` module of the spec testsuite, see
+    /// `vm::spectest`.
+    pub fn spectest(&mut self) -> Result<&mut Self> {
+        define_spectest(self)?;
+        Ok(self)
+    }
+
+    /// Define every export of `instance` under the module name `name`, so the
+    /// modules instantiated with this linker can import them. Functions are
+    /// called on `instance` and memories and globals are shared with it,
+    /// tables are copies of their current value. Tables holding function
+    /// references are not defined, the references are indices into the
+    /// functions of `instance`.
+    pub fn instance(
+        &mut self,
+        name: &str,
+        instance: &Rc<WasmInterpreter<'a>>,
+    ) -> Result<&mut Self> {
+        instance.define_exports(name, self)?;
+        Ok(self)
+    }
+
+    pub(crate) fn define(
+        &mut self,
+        module: &str,
+        name: &str,
+        func: HostFunc<'a>,
+    ) -> Result<&mut Self> {
+        let key = self.key(module, name)?;
+        self.funcs.insert(key, func);
+        Ok(self)
+    }
+
+    /// The key of a new definition, a name is defined once for all kinds.
+    fn key(&self, module: &str, name: &str) -> Result<(String, String)> {
+        let key = (module.to_string(), name.to_string());
+        if self.funcs.contains_key(&key)
+            || self.globals.contains_key(&key)
+            || self.memories.contains_key(&key)
+            || self.tables.contains_key(&key)
+        {
+            return Err(anyhow!("{}.{} is already defined", module, name));
+        }
+        Ok(key)
+    }
+
+    pub(crate) fn get(&self, module: &str, name: &str) -> Option<&HostFunc<'a>> {
+        self.funcs.get(&(module.to_string(), name.to_string()))
+    }
+
+    pub(crate) fn get_global(&self, module: &str, name: &str) -> Option<Global> {
+        self.globals
+            .get(&(module.to_string(), name.to_string()))
+            .cloned()
+    }
+
+    pub(crate) fn get_memory(&self, module: &str, name: &str) -> Option<Memory> {
+        self.memories
+            .get(&(module.to_string(), name.to_string()))
+            .cloned()
+    }
+
+    pub(crate) fn get_table(&self, module: &str, name: &str) -> Option<&(RefType, Table)> {
+        self.tables.get(&(module.to_string(), name.to_string()))
+    }
+}
+
+impl fmt::Debug for Linker<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self
+            .funcs
+            .iter()
+            .map(|((module, name), func)| format!("{}.{}: {}", module, name, format_sig(&func.sig)))
+            .collect::<Vec<_>>();
+        names.sort();
+        let mut globals = self
+            .globals
+            .iter()
+            .map(|((module, name), global)| format!("{}.{}: {:?}", module, name, global.get()))
+            .collect::<Vec<_>>();
+        globals.sort();
+        let mut memories = self
+            .memories
+            .iter()
+            .map(|((module, name), mem)| {
+                format!("{}.{}: {} pages", module, name, mem.size_in_pages())
+            })
+            .collect::<Vec<_>>();
+        memories.sort();
+        let mut tables = self
+            .tables
+            .iter()
+            .map(|((module, name), (ty, table))| {
+                format!("{}.{}: {} x {}", module, name, ty, table.size())
+            })
+            .collect::<Vec<_>>();
+        tables.sort();
+        f.debug_struct("Linker")
+            .field("funcs", &names)
+            .field("globals", &globals)
+            .field("memories", &memories)
+            .field("tables", &tables)
+            .finish()
+    }
+}
+
+/// A Rust type that is passed to and from host functions as a wasm value.
+pub trait WasmTy: Sized {
+    fn val_type() -> ValType;
+    fn from_value(value: WasmValue) -> Self;
+    fn into_value(self) -> WasmValue;
+}
+
+macro_rules! impl_wasm_ty {
+    ($ty:ty, $val_type:ident, $as:ident) => {
+        impl WasmTy for $ty {
+            fn val_type() -> ValType {
+                ValType::$val_type
+            }
+
+            fn from_value(value: WasmValue) -> Self {
+                value.$as()
+            }
+
+            fn into_value(self) -> WasmValue {
+                WasmValue::$val_type(self)
+            }
+        }
+    };
+}
+
+impl_wasm_ty!(i32, I32, as_i32);
+impl_wasm_ty!(i64, I64, as_i64);
+impl_wasm_ty!(f32, F32, as_f32);
+impl_wasm_ty!(f64, F64, as_f64);
+
+/// What a closure given to `Linker::func_wrap` may return: nothing, a wasm
+/// value, or either of them in a `Result` whose error traps.
+pub trait HostResults {
+    fn val_types() -> Vec<ValType>;
+    fn into_values(self) -> Result<Vec<WasmValue>>;
+}
+
+impl HostResults for () {
+    fn val_types() -> Vec<ValType> {
+        vec![]
+    }
+
+    fn into_values(self) -> Result<Vec<WasmValue>> {
+        Ok(vec![])
+    }
+}
+
+impl<T: WasmTy> HostResults for T {
+    fn val_types() -> Vec<ValType> {
+        vec![T::val_type()]
+    }
+
+    fn into_values(self) -> Result<Vec<WasmValue>> {
+        Ok(vec![self.into_value()])
+    }
+}
+
+impl<R: HostResults> HostResults for Result<R> {
+    fn val_types() -> Vec<ValType> {
+        R::val_types()
+    }
+
+    fn into_values(self) -> Result<Vec<WasmValue>> {
+        self?.into_values()
+    }
+}
+
+/// A closure that `Linker::func_wrap` accepts, `Params` tells the closures
+/// with a `Caller` apart from those without.
+pub trait IntoHostFunc<'a, Params, Results> {
+    #[doc(hidden)]
+    fn into_host_func(self) -> HostFunc<'a>;
+}
+
+macro_rules! impl_into_host_func {
+    ($($param:ident),*) => {
+        #[allow(non_snake_case, unused_mut, unused_variables)]
+        impl<'a, F, R, $($param),*> IntoHostFunc<'a, ($($param,)*), R> for F
+        where
+            F: Fn($($param),*) -> R + 'a,
+            R: HostResults,
+            $($param: WasmTy,)*
+        {
+            fn into_host_func(self) -> HostFunc<'a> {
+                let sig = FuncType::new([$($param::val_type()),*], R::val_types());
+                HostFunc::new(
+                    sig,
+                    move |_caller: &mut Caller, args: &[WasmValue]| {
+                        let mut args = args.iter().copied();
+                        $(let $param = $param::from_value(args.next().unwrap());)*
+                        self($($param),*).into_values()
+                    },
+                    false,
+                )
+            }
+        }
+
+        #[allow(non_snake_case, unused_mut, unused_variables)]
+        impl<'a, F, R, $($param),*> IntoHostFunc<'a, (Caller<'static>, $($param,)*), R> for F
+        where
+            F: Fn(&mut Caller, $($param),*) -> R + 'a,
+            R: HostResults,
+            $($param: WasmTy,)*
+        {
+            fn into_host_func(self) -> HostFunc<'a> {
+                let sig = FuncType::new([$($param::val_type()),*], R::val_types());
+                HostFunc::new(
+                    sig,
+                    move |caller: &mut Caller, args: &[WasmValue]| {
+                        let mut args = args.iter().copied();
+                        $(let $param = $param::from_value(args.next().unwrap());)*
+                        self(caller, $($param),*).into_values()
+                    },
+                    true,
+                )
+            }
+        }
+    };
+}
+
+impl_into_host_func!();
+impl_into_host_func!(A1);
+impl_into_host_func!(A1, A2);
+impl_into_host_func!(A1, A2, A3);
+impl_into_host_func!(A1, A2, A3, A4);
+impl_into_host_func!(A1, A2, A3, A4, A5);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6, A7);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6, A7, A8);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6, A7, A8, A9);
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{Caller, Linker};
+    use crate::{
+        module::{value_type::WasmValue, wasm_module::WasmModule},
+        vm::{Memory, WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    fn instantiate<'a>(
+        binary: &'a [u8],
+        linker: Linker<'a>,
+        jit: bool,
+    ) -> anyhow::Result<WasmInterpreter<'a>> {
+        let module = WasmModule::from_bytecode(binary)?;
+        let config = WasmInterpreterConfig::new().jit(jit).linker(linker);
+        WasmInterpreter::with_config(module, &config)
+    }
+
+    fn invoke_i32(vm: &WasmInterpreter, name: &str, params: Vec<WasmValue>) -> i32 {
+        match vm.invoke(name, params).unwrap()[..] {
+            [WasmValue::I32(result)] => result,
+            ref results => panic!("{} returned {:?}", name, results),
+        }
+    }
+
+    /// The module stores 40 in the memory of the host, the host function
+    /// adds its second argument to the word at its first one.
+    fn host_function_and_memory(jit: bool) {
+        let binary = wat::parse_str(
+            r#"
+            (module
+              (import "env" "memory" (memory 1))
+              (import "env" "add_at" (func $add_at (param i32 i32) (result i32)))
+              (func (export "run") (result i32)
+                (i32.store (i32.const 8) (i32.const 40))
+                (i32.store (i32.const 16) (call $add_at (i32.const 8) (i32.const 2)))
+                (i32.load (i32.const 16))))
+            "#,
+        )
+        .unwrap();
+        let memory = Memory::new(1, None);
+        let mut linker = Linker::new();
+        linker.memory("env", "memory", memory.clone()).unwrap();
+        linker
+            .func_wrap("env", "add_at", |caller: &mut Caller, addr: i32, n: i32| {
+                let word = &caller.memory()[addr as usize..addr as usize + 4];
+                i32::from_le_bytes(word.try_into().unwrap()) + n
+            })
+            .unwrap();
+        let vm = instantiate(&binary, linker, jit).unwrap();
+
+        assert_eq!(invoke_i32(&vm, "run", vec![]), 42);
+        let mut word = [0; 4];
+        memory.read(16, &mut word).unwrap();
+        assert_eq!(i32::from_le_bytes(word), 42);
+    }
+
+    #[test]
+    fn the_interpreter_calls_host_functions_on_a_shared_memory() {
+        host_function_and_memory(false);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn the_jit_code_calls_host_functions_on_a_shared_memory() {
+        host_function_and_memory(true);
+    }
+
+    #[test]
+    fn a_module_imports_the_exports_of_another() {
+        let lib_binary = wat::parse_str(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 0) "\15")
+              (func (export "double") (param i32) (result i32)
+                (i32.mul (local.get 0) (i32.const 2)))
+              (func (export "peek") (result i32)
+                (i32.load8_u (i32.const 1))))
+            "#,
+        )
+        .unwrap();
+        let app_binary = wat::parse_str(
+            r#"
+            (module
+              (import "lib" "memory" (memory 1))
+              (import "lib" "double" (func $double (param i32) (result i32)))
+              (func (export "run") (result i32)
+                (i32.store8 (i32.const 1) (i32.const 7))
+                (call $double (i32.load8_u (i32.const 0)))))
+            "#,
+        )
+        .unwrap();
+        let lib = Rc::new(instantiate(&lib_binary, Linker::new(), false).unwrap());
+        let mut linker = Linker::new();
+        linker.instance("lib", &lib).unwrap();
+        let app = instantiate(&app_binary, linker, false).unwrap();
+
+        assert_eq!(invoke_i32(&app, "run", vec![]), 42);
+        // the store of the app is seen by the lib
+        assert_eq!(invoke_i32(&lib, "peek", vec![]), 7);
+    }
+
+    #[test]
+    fn a_missing_import_fails_to_instantiate() {
+        let binary = wat::parse_str(r#"(module (import "env" "missing" (func)))"#).unwrap();
+        let err = instantiate(&binary, Linker::new(), false).err().unwrap();
+        assert_eq!(err.to_string(), "unknown import: env.missing");
+    }
+
+    #[test]
+    fn an_import_of_another_signature_fails_to_instantiate() {
+        let binary =
+            wat::parse_str(r#"(module (import "env" "inc" (func (param i64) (result i64))))"#)
+                .unwrap();
+        let mut linker = Linker::new();
+        linker.func_wrap("env", "inc", |n: i32| n + 1).unwrap();
+        let err = instantiate(&binary, linker, false).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "incompatible import type: env.inc is [i32] -> [i32], imported as [i64] -> [i64]"
+        );
+    }
+}
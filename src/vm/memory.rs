@@ -0,0 +1,92 @@
+//! Linear memories that outlive a single instance. Every memory of an
+//! instance is a `Memory`, a memory defined with `Linker::memory` is the same
+//! memory in every instance importing it and in the embedder.
+
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use debug_cell::{Ref, RefCell, RefMut};
+
+use super::{store::LinearMemory, trap::TrapKind, WASM_DEFAULT_PAGE_SIZE_BYTE};
+
+/// A handle to a linear memory, clones refer to the same memory.
+#[derive(Clone)]
+pub struct Memory {
+    mem: Rc<RefCell<LinearMemory>>,
+}
+
+impl Memory {
+    /// A zeroed memory of `initial` pages that can grow up to `maximum`
+    /// pages.
+    pub fn new(initial: u64, maximum: Option<u64>) -> Self {
+        Self::from_linear(LinearMemory::from_bytes(
+            &[],
+            initial as usize * WASM_DEFAULT_PAGE_SIZE_BYTE,
+            false,
+            maximum,
+        ))
+    }
+
+    /// A memory holding `data`, e.g. a buffer the host already filled, whose
+    /// length must be a multiple of the page size.
+    pub fn from_bytes(data: Vec<u8>, maximum: Option<u64>) -> Result<Self> {
+        if !data.len().is_multiple_of(WASM_DEFAULT_PAGE_SIZE_BYTE) {
+            return Err(anyhow!(
+                "memory of {} bytes is not a multiple of the page size",
+                data.len()
+            ));
+        }
+        Ok(Self::from_linear(LinearMemory::from_bytes(
+            &data, 0, false, maximum,
+        )))
+    }
+
+    pub(crate) fn from_linear(memory: LinearMemory) -> Self {
+        Self {
+            mem: Rc::new(RefCell::new(memory)),
+        }
+    }
+
+    pub fn size_in_pages(&self) -> u64 {
+        (self.mem.borrow().size() / WASM_DEFAULT_PAGE_SIZE_BYTE) as u64
+    }
+
+    pub fn maximum(&self) -> Option<u64> {
+        self.mem.borrow().max
+    }
+
+    /// Copy `buf.len()` bytes from `offset` into `buf`.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<()> {
+        let mem = self.mem.borrow();
+        let bytes = offset
+            .checked_add(buf.len())
+            .and_then(|end| mem.data.get(offset..end))
+            .ok_or(TrapKind::OutOfBoundsMemory)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Copy `data` to the memory at `offset`.
+    pub fn write(&self, offset: usize, data: &[u8]) -> Result<()> {
+        let mut mem = self.mem.borrow_mut();
+        let bytes = offset
+            .checked_add(data.len())
+            .and_then(|end| mem.data.get_mut(offset..end))
+            .ok_or(TrapKind::OutOfBoundsMemory)?;
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// A copy of the whole memory.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.mem.borrow().data.to_vec()
+    }
+
+    pub(crate) fn borrow(&self) -> Ref<'_, LinearMemory> {
+        self.mem.borrow()
+    }
+
+    pub(crate) fn borrow_mut(&self) -> RefMut<'_, LinearMemory> {
+        self.mem.borrow_mut()
+    }
+}
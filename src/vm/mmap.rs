@@ -0,0 +1,169 @@
+//! The bytes of the interpreter's linear memories. A memory lives in a
+//! region of address space reserved for all it can grow to, so growing it
+//! only makes more pages of the region accessible and never moves it. The
+//! rest of the region stays `PROT_NONE`, an access past the end faults
+//! instead of reaching whatever the host mapped after the memory.
+
+use std::{
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use super::WASM_DEFAULT_PAGE_SIZE_BYTE;
+
+/// What a memory32 memory reserves: an i32 address plus a 32-bit offset
+/// stays within it.
+const MEMORY32_RESERVATION_BYTE: usize = 8 * 1024 * 1024 * 1024;
+
+/// Memory64 memories reserve their maximum up to this much, they move to a
+/// larger region if they grow past it.
+const MEMORY64_RESERVATION_BYTE: usize = 32 * 1024 * 1024 * 1024;
+
+/// The inaccessible bytes a region keeps past the most its memory uses.
+const GUARD_BYTE: usize = WASM_DEFAULT_PAGE_SIZE_BYTE;
+
+/// A region of `reserved` bytes of address space, the first `len` of which
+/// are readable and writable.
+pub(crate) struct MmapRegion {
+    base: NonNull<u8>,
+    len: usize,
+    reserved: usize,
+}
+
+impl MmapRegion {
+    /// A zeroed region of `len` bytes, with room for a memory that can grow
+    /// up to `max_len` bytes.
+    pub(crate) fn new(len: usize, max_len: usize, memory64: bool) -> Self {
+        let reserved = if memory64 {
+            max_len.clamp(len, MEMORY64_RESERVATION_BYTE.max(len)) + GUARD_BYTE
+        } else {
+            MEMORY32_RESERVATION_BYTE.max(len + GUARD_BYTE)
+        };
+        Self::map(reserved, len)
+    }
+
+    /// `reserved` bytes of address space, the first `len` of which are
+    /// accessible, for a new memory or a copy. Unlike `grow`, it panics if
+    /// the bytes cannot be mapped.
+    fn map(reserved: usize, len: usize) -> Self {
+        // fall back to the bytes in use if the address space is limited,
+        // growing then moves the memory
+        let mut region = Self::reserve(reserved).unwrap_or_else(|| Self::reserve_exact(len));
+        if !region.protect(len) {
+            panic!("failed to map a linear memory of {len} bytes");
+        }
+        region
+    }
+
+    /// Make the region `new_len` bytes long, the new bytes are zero. The
+    /// region only moves if it has no room for them. Returns false, with the
+    /// region left as it was, if the new bytes cannot be mapped.
+    #[must_use]
+    pub(crate) fn grow(&mut self, new_len: usize) -> bool {
+        let Some(min_reserved) = new_len.checked_add(GUARD_BYTE) else {
+            return false;
+        };
+        if min_reserved <= self.reserved {
+            return self.protect(new_len);
+        }
+        let reserved = new_len.saturating_mul(2).max(min_reserved);
+        let Some(mut grown) = Self::reserve(reserved).or_else(|| Self::reserve(min_reserved))
+        else {
+            return false;
+        };
+        if !grown.protect(new_len) {
+            return false;
+        }
+        grown[..self.len].copy_from_slice(self);
+        *self = grown;
+        true
+    }
+
+    /// Whether an access of a memory32 memory, an i32 address plus a
+    /// 32-bit offset, stays within the region, so it faults past the memory
+    /// instead of reaching another mapping. The x86 jit relies on it.
+    pub(crate) fn guards_memory32(&self) -> bool {
+        self.reserved >= MEMORY32_RESERVATION_BYTE
+    }
+
+    fn reserve_exact(len: usize) -> Self {
+        Self::reserve(len + GUARD_BYTE)
+            .unwrap_or_else(|| panic!("failed to map a linear memory of {len} bytes"))
+    }
+
+    /// Map `reserved` bytes of inaccessible address space.
+    fn reserve(reserved: usize) -> Option<Self> {
+        // SAFETY: a new private anonymous mapping does not alias anything
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                reserved,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return None;
+        }
+        Some(Self {
+            base: NonNull::new(base.cast())?,
+            len: 0,
+            reserved,
+        })
+    }
+
+    /// Make the first `new_len` bytes accessible, `new_len` is at least the
+    /// current length. Returns false if the pages cannot be mapped.
+    fn protect(&mut self, new_len: usize) -> bool {
+        assert!(new_len >= self.len && new_len + GUARD_BYTE <= self.reserved);
+        if new_len > 0 {
+            // SAFETY: the range is within the mapping, the page size divides
+            // the wasm page size
+            let ret = unsafe {
+                libc::mprotect(
+                    self.base.as_ptr().cast(),
+                    new_len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                )
+            };
+            if ret != 0 {
+                return false;
+            }
+        }
+        self.len = new_len;
+        true
+    }
+}
+
+impl Deref for MmapRegion {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the first `len` bytes are mapped readable and writable
+        unsafe { std::slice::from_raw_parts(self.base.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for MmapRegion {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and the region is borrowed mutably
+        unsafe { std::slice::from_raw_parts_mut(self.base.as_ptr(), self.len) }
+    }
+}
+
+impl Clone for MmapRegion {
+    fn clone(&self) -> Self {
+        let mut region = Self::map(self.reserved, self.len);
+        region.copy_from_slice(self);
+        region
+    }
+}
+
+impl Drop for MmapRegion {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is owned by the region and no longer borrowed
+        unsafe { libc::munmap(self.base.as_ptr().cast(), self.reserved) };
+    }
+}
@@ -1,20 +1,127 @@
-use anyhow::Result;
+use crate::module::{insts::Instruction, value_type::WasmValue};
 
-use crate::module::value_type::WasmValue;
+mod config;
+pub use config::{JitBackend, WasmInterpreterConfig};
+
+mod engine;
+pub use engine::{Engine, Instance};
 
 mod interpreter;
 pub use interpreter::WasmInterpreter;
 
 mod func_exec;
-pub(crate) use func_exec::{block_type_num_results, stack_height_delta};
+
+mod epoch;
+pub use epoch::Epoch;
+pub(crate) use epoch::{EpochDeadline, Watchdog};
+
+mod backtrace;
+pub use backtrace::{WasmBacktrace, WasmFrame};
+
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+
+mod coredump;
+mod debugger;
+mod exception;
+mod host;
+mod simd;
+pub(crate) use exception::WasmException;
+pub(crate) use host::HostFuncImport;
+
+mod linker;
+pub use linker::{Caller, HostResults, IntoHostFunc, Linker, WasmTy};
+
+mod limiter;
+pub use limiter::ResourceLimiter;
+pub(crate) use limiter::SharedLimiter;
+
+mod memory;
+pub use memory::Memory;
+
+mod mmap;
+
+mod snapshot;
+pub use snapshot::Snapshot;
+
+mod store;
+pub(crate) use store::{Store, Table};
+
+mod jit_instance;
+mod tiering;
+mod trace;
+pub use trace::ImportTraceSink;
+pub(crate) use trace::{json_string, trace_block};
+
+mod trap;
+pub use trap::{RuntimeError, TrapKind, TRAP_EXIT_STATUS};
+
+mod spectest;
+pub use spectest::SPECTEST_MODULE;
+
+mod wasi;
+pub use wasi::{WasiCtx, WASI_MODULE};
 
 pub const WASM_DEFAULT_PAGE_SIZE_BYTE: usize = 65536;
 
+/// How deep wasm calls may nest before they trap with
+/// `TrapKind::StackExhausted`, see `WasmInterpreterConfig::max_call_depth`.
+/// The interpreter keeps its frames on the heap, but the jit code nests its
+/// calls on the host stack and shares the limit.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 512;
+
+/// The pages a memory declared without a maximum can grow to, the most a
+/// memory32 memory can address, see
+/// `WasmInterpreterConfig::default_memory_maximum`.
+pub const DEFAULT_MEMORY_MAXIMUM: u64 = 65536;
+
+/// The elements a table declared without a maximum can grow to, well short
+/// of the `u32::MAX` the index allows, whose elements could not be
+/// allocated.
+pub const DEFAULT_TABLE_MAXIMUM: u64 = 10_000_000;
+
 pub trait WasmVm {
-    /// Run the interpreter,the final result will be returned as a string.
-    fn run(&self, main_params: Vec<WasmValue>) -> Result<String>;
+    /// Run the entry function, see `WasmInterpreter::set_entry`, the final
+    /// result will be returned as a string.
+    /// Multiple results are separated by spaces.
+    /// A trap is reported as `RuntimeError::Trap`.
+    fn run(&self, main_params: Vec<WasmValue>) -> Result<String, RuntimeError>;
 }
 
 pub trait WasmFunctionExecutor {
-    fn execute(&mut self) -> Result<Option<WasmValue>>;
+    /// Run the function, returns its results in order.
+    fn execute(&mut self) -> anyhow::Result<Vec<WasmValue>>;
+
+    /// Execute a single instruction of the function. A call runs the callee
+    /// to completion in one step, and the instructions of an arm of an `if`
+    /// that is not taken are not steps.
+    fn step(&mut self) -> anyhow::Result<StepOutcome>;
+
+    /// The state of the function between steps.
+    fn state(&self) -> FrameState;
+}
+
+/// The result of `WasmFunctionExecutor::step`.
+#[derive(Debug, Clone)]
+pub enum StepOutcome {
+    /// The function has more instructions to run.
+    Running,
+    /// The function returned with these results, it cannot be stepped any
+    /// further.
+    Returned(Vec<WasmValue>),
+}
+
+/// A snapshot of a function being executed.
+#[derive(Debug, Clone)]
+pub struct FrameState {
+    /// The index of the function in the module.
+    pub func_index: u32,
+    /// The index of the next instruction in the function body.
+    pub pc: usize,
+    /// The next instruction, `None` once the function returned.
+    pub next_instruction: Option<Instruction>,
+    /// The parameters followed by the declared locals.
+    pub locals: Vec<WasmValue>,
+    /// The operand stack, the topmost value last.
+    pub operand_stack: Vec<WasmValue>,
 }
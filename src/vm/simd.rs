@@ -0,0 +1,446 @@
+//! Lane arithmetic of the v128 instructions. A v128 is kept in a `u128`,
+//! lane 0 lives in the lowest bits, the same layout as the little endian
+//! bytes in the linear memory.
+
+use crate::module::{
+    insts::{V128Binop, V128Shape, V128Shift, V128Test, V128Unop},
+    value_type::{f32_max, f32_min, f64_max, f64_min, WasmValue},
+};
+
+fn lane_mask(bits: u32) -> u64 {
+    if bits == 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+fn get_lane(v: u128, bits: u32, i: u32) -> u64 {
+    (v >> (i * bits)) as u64 & lane_mask(bits)
+}
+
+fn set_lane(v: u128, bits: u32, i: u32, x: u64) -> u128 {
+    let mask = (lane_mask(bits) as u128) << (i * bits);
+    (v & !mask) | (((x & lane_mask(bits)) as u128) << (i * bits))
+}
+
+/// sign extend a lane to 64 bits
+fn sext(x: u64, bits: u32) -> i64 {
+    ((x << (64 - bits)) as i64) >> (64 - bits)
+}
+
+/// all bits set for true, as returned by the comparisons
+fn bool_lane(b: bool) -> u64 {
+    if b {
+        u64::MAX
+    } else {
+        0
+    }
+}
+
+fn map_lanes(shape: V128Shape, a: u128, f: impl Fn(u64) -> u64) -> u128 {
+    let bits = shape.lane_bits();
+    (0..shape.num_lanes()).fold(0, |v, i| set_lane(v, bits, i, f(get_lane(a, bits, i))))
+}
+
+fn zip_lanes(shape: V128Shape, a: u128, b: u128, f: impl Fn(u64, u64) -> u64) -> u128 {
+    let bits = shape.lane_bits();
+    (0..shape.num_lanes()).fold(0, |v, i| {
+        set_lane(v, bits, i, f(get_lane(a, bits, i), get_lane(b, bits, i)))
+    })
+}
+
+/// apply a float operation lane wise, f32 lanes are computed in f32
+fn map_float_lanes(
+    shape: V128Shape,
+    a: u128,
+    f32_op: fn(f32) -> f32,
+    f64_op: fn(f64) -> f64,
+) -> u128 {
+    match shape {
+        V128Shape::F32x4 => map_lanes(shape, a, |x| {
+            f32_op(f32::from_bits(x as u32)).to_bits() as u64
+        }),
+        V128Shape::F64x2 => map_lanes(shape, a, |x| f64_op(f64::from_bits(x)).to_bits()),
+        _ => unreachable!("not a float shape: {:?}", shape),
+    }
+}
+
+fn zip_float_lanes(
+    shape: V128Shape,
+    a: u128,
+    b: u128,
+    f32_op: fn(f32, f32) -> f32,
+    f64_op: fn(f64, f64) -> f64,
+) -> u128 {
+    match shape {
+        V128Shape::F32x4 => zip_lanes(shape, a, b, |x, y| {
+            f32_op(f32::from_bits(x as u32), f32::from_bits(y as u32)).to_bits() as u64
+        }),
+        V128Shape::F64x2 => zip_lanes(shape, a, b, |x, y| {
+            f64_op(f64::from_bits(x), f64::from_bits(y)).to_bits()
+        }),
+        _ => unreachable!("not a float shape: {:?}", shape),
+    }
+}
+
+/// the lanes `first..` of the lanes half as wide as the ones of `shape`,
+/// widened to `shape`
+fn widen(shape: V128Shape, a: u128, first: u32, signed: bool) -> u128 {
+    let bits = shape.lane_bits() / 2;
+    (0..shape.num_lanes()).fold(0, |v, i| {
+        let x = get_lane(a, bits, first + i);
+        let x = if signed { sext(x, bits) as u64 } else { x };
+        set_lane(v, shape.lane_bits(), i, x)
+    })
+}
+
+/// adjacent lanes half as wide as the ones of `shape`, widened, multiplied
+/// by `b` if there is one, and added
+fn pairwise(shape: V128Shape, a: u128, b: Option<u128>, signed: bool) -> u128 {
+    let half = shape.lane_bits() / 2;
+    let ext = |x| if signed { sext(x, half) as u64 } else { x };
+    let get = |i| {
+        let x = ext(get_lane(a, half, i));
+        b.map_or(x, |b| x.wrapping_mul(ext(get_lane(b, half, i))))
+    };
+    let bits = shape.lane_bits();
+    (0..shape.num_lanes()).fold(0, |v, i| {
+        set_lane(v, bits, i, get(2 * i).wrapping_add(get(2 * i + 1)))
+    })
+}
+
+/// the lanes `first..` of `a` and `b` widened to `shape` and multiplied
+fn extmul(shape: V128Shape, a: u128, b: u128, first: u32, signed: bool) -> u128 {
+    let a = widen(shape, a, first, signed);
+    let b = widen(shape, b, first, signed);
+    zip_lanes(shape, a, b, u64::wrapping_mul)
+}
+
+/// the lanes of `a` then `b` saturated to the lanes of `shape`, half as wide
+fn narrow(shape: V128Shape, a: u128, b: u128, signed: bool) -> u128 {
+    let bits = shape.lane_bits();
+    let (min, max) = if signed {
+        (-(1i64 << (bits - 1)), (1i64 << (bits - 1)) - 1)
+    } else {
+        (0, (1i64 << bits) - 1)
+    };
+    let half = shape.num_lanes() / 2;
+    (0..shape.num_lanes()).fold(0, |v, i| {
+        let x = if i < half {
+            get_lane(a, bits * 2, i)
+        } else {
+            get_lane(b, bits * 2, i - half)
+        };
+        set_lane(v, bits, i, sext(x, bits * 2).clamp(min, max) as u64)
+    })
+}
+
+/// the two f64 lanes converted to the low lanes of 32 bits, the others are 0
+fn from_f64x2(a: u128, f: impl Fn(f64) -> u32) -> u128 {
+    (0..2).fold(0, |v, i| {
+        set_lane(v, 32, i, f(f64::from_bits(get_lane(a, 64, i))) as u64)
+    })
+}
+
+/// the two low lanes of 32 bits converted to f64 lanes
+fn to_f64x2(a: u128, f: impl Fn(u32) -> f64) -> u128 {
+    (0..2).fold(0, |v, i| {
+        set_lane(v, 64, i, f(get_lane(a, 32, i) as u32).to_bits())
+    })
+}
+
+fn cmp_float_lanes(
+    shape: V128Shape,
+    a: u128,
+    b: u128,
+    f32_op: fn(f32, f32) -> bool,
+    f64_op: fn(f64, f64) -> bool,
+) -> u128 {
+    match shape {
+        V128Shape::F32x4 => zip_lanes(shape, a, b, |x, y| {
+            bool_lane(f32_op(f32::from_bits(x as u32), f32::from_bits(y as u32)))
+        }),
+        V128Shape::F64x2 => zip_lanes(shape, a, b, |x, y| {
+            bool_lane(f64_op(f64::from_bits(x), f64::from_bits(y)))
+        }),
+        _ => unreachable!("not a float shape: {:?}", shape),
+    }
+}
+
+pub(super) fn v128_splat(shape: V128Shape, value: WasmValue) -> u128 {
+    let x = match value {
+        WasmValue::I32(v) => v as u32 as u64,
+        WasmValue::I64(v) => v as u64,
+        WasmValue::F32(v) => v.to_bits() as u64,
+        WasmValue::F64(v) => v.to_bits(),
+        _ => panic!("splat: invalid operand {:?}", value),
+    };
+    v128_splat_lane(shape, x)
+}
+
+/// every lane set to the low bits of `x`
+pub(super) fn v128_splat_lane(shape: V128Shape, x: u64) -> u128 {
+    map_lanes(shape, 0, |_| x)
+}
+
+/// the bits of a lane, zero extended
+pub(super) fn v128_lane(shape: V128Shape, v: u128, lane: u8) -> u64 {
+    get_lane(v, shape.lane_bits(), lane as u32)
+}
+
+pub(super) fn v128_with_lane(shape: V128Shape, v: u128, lane: u8, x: u64) -> u128 {
+    set_lane(v, shape.lane_bits(), lane as u32, x)
+}
+
+/// the 8 bytes of `bytes`, little endian, widened to the lanes of `shape`
+pub(super) fn v128_load_extend(shape: V128Shape, bytes: u64, signed: bool) -> u128 {
+    widen(shape, bytes as u128, 0, signed)
+}
+
+pub(super) fn v128_extract_lane(shape: V128Shape, v: u128, lane: u8, signed: bool) -> WasmValue {
+    let bits = shape.lane_bits();
+    let x = get_lane(v, bits, lane as u32);
+    match shape {
+        V128Shape::I8x16 | V128Shape::I16x8 if signed => WasmValue::I32(sext(x, bits) as i32),
+        V128Shape::I8x16 | V128Shape::I16x8 | V128Shape::I32x4 => WasmValue::I32(x as i32),
+        V128Shape::I64x2 => WasmValue::I64(x as i64),
+        V128Shape::F32x4 => WasmValue::F32(f32::from_bits(x as u32)),
+        V128Shape::F64x2 => WasmValue::F64(f64::from_bits(x)),
+    }
+}
+
+pub(super) fn v128_replace_lane(shape: V128Shape, v: u128, lane: u8, value: WasmValue) -> u128 {
+    let x = get_lane(v128_splat(shape, value), shape.lane_bits(), 0);
+    set_lane(v, shape.lane_bits(), lane as u32, x)
+}
+
+/// pick bytes from the concatenation of `a` and `b`
+pub(super) fn i8x16_shuffle(a: u128, b: u128, lanes: &[u8; 16]) -> u128 {
+    lanes.iter().enumerate().fold(0, |v, (i, &lane)| {
+        let x = if lane < 16 {
+            get_lane(a, 8, lane as u32)
+        } else {
+            get_lane(b, 8, lane as u32 - 16)
+        };
+        set_lane(v, 8, i as u32, x)
+    })
+}
+
+/// take the bits of `a` where `mask` is set and the bits of `b` elsewhere
+pub(super) fn v128_bitselect(a: u128, b: u128, mask: u128) -> u128 {
+    (a & mask) | (b & !mask)
+}
+
+pub(super) fn v128_unop(op: &V128Unop, a: u128) -> u128 {
+    match op {
+        V128Unop::Not => !a,
+        V128Unop::Abs(shape) if shape.is_float() => {
+            let sign = 1 << (shape.lane_bits() - 1);
+            map_lanes(*shape, a, |x| x & !sign)
+        }
+        V128Unop::Abs(shape) => {
+            let bits = shape.lane_bits();
+            map_lanes(*shape, a, |x| sext(x, bits).wrapping_abs() as u64)
+        }
+        V128Unop::Neg(shape) if shape.is_float() => {
+            let sign = 1 << (shape.lane_bits() - 1);
+            map_lanes(*shape, a, |x| x ^ sign)
+        }
+        V128Unop::Neg(shape) => map_lanes(*shape, a, |x| x.wrapping_neg()),
+        V128Unop::Sqrt(shape) => map_float_lanes(*shape, a, f32::sqrt, f64::sqrt),
+        V128Unop::Ceil(shape) => map_float_lanes(*shape, a, f32::ceil, f64::ceil),
+        V128Unop::Floor(shape) => map_float_lanes(*shape, a, f32::floor, f64::floor),
+        V128Unop::Trunc(shape) => map_float_lanes(*shape, a, f32::trunc, f64::trunc),
+        V128Unop::Nearest(shape) => {
+            map_float_lanes(*shape, a, f32::round_ties_even, f64::round_ties_even)
+        }
+        V128Unop::I8x16Popcnt => map_lanes(V128Shape::I8x16, a, |x| x.count_ones() as u64),
+        // `as` saturates and maps NaN to 0
+        V128Unop::I32x4TruncSatF32x4S => map_lanes(V128Shape::I32x4, a, |x| {
+            f32::from_bits(x as u32) as i32 as u32 as u64
+        }),
+        V128Unop::I32x4TruncSatF32x4U => map_lanes(V128Shape::I32x4, a, |x| {
+            f32::from_bits(x as u32) as u32 as u64
+        }),
+        V128Unop::F32x4ConvertI32x4S => map_lanes(V128Shape::F32x4, a, |x| {
+            (x as u32 as i32 as f32).to_bits() as u64
+        }),
+        V128Unop::F32x4ConvertI32x4U => {
+            map_lanes(V128Shape::F32x4, a, |x| (x as u32 as f32).to_bits() as u64)
+        }
+        V128Unop::I32x4TruncSatF64x2SZero => from_f64x2(a, |x| x as i32 as u32),
+        V128Unop::I32x4TruncSatF64x2UZero => from_f64x2(a, |x| x as u32),
+        V128Unop::F64x2ConvertLowI32x4S => to_f64x2(a, |x| x as i32 as f64),
+        V128Unop::F64x2ConvertLowI32x4U => to_f64x2(a, |x| x as f64),
+        V128Unop::F32x4DemoteF64x2Zero => from_f64x2(a, |x| (x as f32).to_bits()),
+        V128Unop::F64x2PromoteLowF32x4 => to_f64x2(a, |x| f32::from_bits(x) as f64),
+        V128Unop::ExtendLowS(shape) => widen(*shape, a, 0, true),
+        V128Unop::ExtendLowU(shape) => widen(*shape, a, 0, false),
+        V128Unop::ExtendHighS(shape) => widen(*shape, a, shape.num_lanes(), true),
+        V128Unop::ExtendHighU(shape) => widen(*shape, a, shape.num_lanes(), false),
+        V128Unop::ExtaddPairwiseS(shape) => pairwise(*shape, a, None, true),
+        V128Unop::ExtaddPairwiseU(shape) => pairwise(*shape, a, None, false),
+    }
+}
+
+pub(super) fn v128_binop(op: &V128Binop, a: u128, b: u128) -> u128 {
+    match op {
+        V128Binop::And => a & b,
+        V128Binop::AndNot => a & !b,
+        V128Binop::Or => a | b,
+        V128Binop::Xor => a ^ b,
+        // out of range indices select 0
+        V128Binop::I8x16Swizzle => zip_lanes(V128Shape::I8x16, a, b, |_, idx| {
+            if idx < 16 {
+                get_lane(a, 8, idx as u32)
+            } else {
+                0
+            }
+        }),
+        V128Binop::Add(shape) if shape.is_float() => {
+            zip_float_lanes(*shape, a, b, |x, y| x + y, |x, y| x + y)
+        }
+        V128Binop::Add(shape) => zip_lanes(*shape, a, b, u64::wrapping_add),
+        V128Binop::Sub(shape) if shape.is_float() => {
+            zip_float_lanes(*shape, a, b, |x, y| x - y, |x, y| x - y)
+        }
+        V128Binop::Sub(shape) => zip_lanes(*shape, a, b, u64::wrapping_sub),
+        V128Binop::Mul(shape) if shape.is_float() => {
+            zip_float_lanes(*shape, a, b, |x, y| x * y, |x, y| x * y)
+        }
+        V128Binop::Mul(shape) => zip_lanes(*shape, a, b, u64::wrapping_mul),
+        V128Binop::Div(shape) => zip_float_lanes(*shape, a, b, |x, y| x / y, |x, y| x / y),
+        V128Binop::Min(shape) => zip_float_lanes(*shape, a, b, f32_min, f64_min),
+        V128Binop::Max(shape) => zip_float_lanes(*shape, a, b, f32_max, f64_max),
+        V128Binop::MinS(shape) => {
+            let bits = shape.lane_bits();
+            zip_lanes(*shape, a, b, |x, y| sext(x, bits).min(sext(y, bits)) as u64)
+        }
+        V128Binop::MinU(shape) => zip_lanes(*shape, a, b, u64::min),
+        V128Binop::MaxS(shape) => {
+            let bits = shape.lane_bits();
+            zip_lanes(*shape, a, b, |x, y| sext(x, bits).max(sext(y, bits)) as u64)
+        }
+        V128Binop::MaxU(shape) => zip_lanes(*shape, a, b, u64::max),
+        V128Binop::AddSatS(shape) | V128Binop::SubSatS(shape) => {
+            let bits = shape.lane_bits();
+            let (min, max) = (-(1i64 << (bits - 1)), (1i64 << (bits - 1)) - 1);
+            let sub = matches!(op, V128Binop::SubSatS(_));
+            zip_lanes(*shape, a, b, |x, y| {
+                let (x, y) = (sext(x, bits), sext(y, bits));
+                let r = if sub { x - y } else { x + y };
+                r.clamp(min, max) as u64
+            })
+        }
+        V128Binop::AddSatU(shape) => {
+            let max = lane_mask(shape.lane_bits());
+            zip_lanes(*shape, a, b, |x, y| (x + y).min(max))
+        }
+        V128Binop::SubSatU(shape) => zip_lanes(*shape, a, b, u64::saturating_sub),
+        V128Binop::AvgrU(shape) => zip_lanes(*shape, a, b, |x, y| (x + y).div_ceil(2)),
+        V128Binop::Eq(shape) if shape.is_float() => {
+            cmp_float_lanes(*shape, a, b, |x, y| x == y, |x, y| x == y)
+        }
+        V128Binop::Eq(shape) => zip_lanes(*shape, a, b, |x, y| bool_lane(x == y)),
+        V128Binop::Ne(shape) if shape.is_float() => {
+            cmp_float_lanes(*shape, a, b, |x, y| x != y, |x, y| x != y)
+        }
+        V128Binop::Ne(shape) => zip_lanes(*shape, a, b, |x, y| bool_lane(x != y)),
+        V128Binop::LtS(shape) => {
+            let bits = shape.lane_bits();
+            zip_lanes(*shape, a, b, |x, y| {
+                bool_lane(sext(x, bits) < sext(y, bits))
+            })
+        }
+        V128Binop::LtU(shape) => zip_lanes(*shape, a, b, |x, y| bool_lane(x < y)),
+        V128Binop::GtS(shape) => {
+            let bits = shape.lane_bits();
+            zip_lanes(*shape, a, b, |x, y| {
+                bool_lane(sext(x, bits) > sext(y, bits))
+            })
+        }
+        V128Binop::GtU(shape) => zip_lanes(*shape, a, b, |x, y| bool_lane(x > y)),
+        V128Binop::LeS(shape) => {
+            let bits = shape.lane_bits();
+            zip_lanes(*shape, a, b, |x, y| {
+                bool_lane(sext(x, bits) <= sext(y, bits))
+            })
+        }
+        V128Binop::LeU(shape) => zip_lanes(*shape, a, b, |x, y| bool_lane(x <= y)),
+        V128Binop::GeS(shape) => {
+            let bits = shape.lane_bits();
+            zip_lanes(*shape, a, b, |x, y| {
+                bool_lane(sext(x, bits) >= sext(y, bits))
+            })
+        }
+        V128Binop::GeU(shape) => zip_lanes(*shape, a, b, |x, y| bool_lane(x >= y)),
+        V128Binop::Lt(shape) => cmp_float_lanes(*shape, a, b, |x, y| x < y, |x, y| x < y),
+        V128Binop::Gt(shape) => cmp_float_lanes(*shape, a, b, |x, y| x > y, |x, y| x > y),
+        V128Binop::Le(shape) => cmp_float_lanes(*shape, a, b, |x, y| x <= y, |x, y| x <= y),
+        V128Binop::Ge(shape) => cmp_float_lanes(*shape, a, b, |x, y| x >= y, |x, y| x >= y),
+        // pseudo min and max, `b < a ? b : a` and `a < b ? b : a`
+        V128Binop::Pmin(shape) => zip_float_lanes(
+            *shape,
+            a,
+            b,
+            |x, y| if y < x { y } else { x },
+            |x, y| if y < x { y } else { x },
+        ),
+        V128Binop::Pmax(shape) => zip_float_lanes(
+            *shape,
+            a,
+            b,
+            |x, y| if x < y { y } else { x },
+            |x, y| if x < y { y } else { x },
+        ),
+        V128Binop::NarrowS(shape) => narrow(*shape, a, b, true),
+        V128Binop::NarrowU(shape) => narrow(*shape, a, b, false),
+        V128Binop::ExtmulLowS(shape) => extmul(*shape, a, b, 0, true),
+        V128Binop::ExtmulLowU(shape) => extmul(*shape, a, b, 0, false),
+        V128Binop::ExtmulHighS(shape) => extmul(*shape, a, b, shape.num_lanes(), true),
+        V128Binop::ExtmulHighU(shape) => extmul(*shape, a, b, shape.num_lanes(), false),
+        // the product of two q15 fixed point numbers, rounded
+        V128Binop::I16x8Q15MulrSatS => zip_lanes(V128Shape::I16x8, a, b, |x, y| {
+            let r = (sext(x, 16) * sext(y, 16) + 0x4000) >> 15;
+            r.clamp(i16::MIN as i64, i16::MAX as i64) as u64
+        }),
+        V128Binop::I32x4DotI16x8S => pairwise(V128Shape::I32x4, a, Some(b), true),
+    }
+}
+
+/// the shift count is taken modulo the lane width
+pub(super) fn v128_shift(op: &V128Shift, a: u128, count: u32) -> u128 {
+    match op {
+        V128Shift::Shl(shape) => {
+            let count = count % shape.lane_bits();
+            map_lanes(*shape, a, |x| x << count)
+        }
+        V128Shift::ShrS(shape) => {
+            let bits = shape.lane_bits();
+            let count = count % bits;
+            map_lanes(*shape, a, |x| (sext(x, bits) >> count) as u64)
+        }
+        V128Shift::ShrU(shape) => {
+            let count = count % shape.lane_bits();
+            map_lanes(*shape, a, |x| x >> count)
+        }
+    }
+}
+
+pub(super) fn v128_test(op: &V128Test, a: u128) -> i32 {
+    match op {
+        V128Test::AnyTrue => (a != 0) as i32,
+        V128Test::AllTrue(shape) => {
+            let bits = shape.lane_bits();
+            (0..shape.num_lanes()).all(|i| get_lane(a, bits, i) != 0) as i32
+        }
+        // the sign bit of each lane
+        V128Test::Bitmask(shape) => {
+            let bits = shape.lane_bits();
+            (0..shape.num_lanes()).fold(0, |mask, i| {
+                mask | (((get_lane(a, bits, i) >> (bits - 1)) as i32) << i)
+            })
+        }
+    }
+}
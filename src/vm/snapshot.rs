@@ -0,0 +1,96 @@
+//! Snapshots of the state of an instance, see `WasmInterpreter::snapshot`.
+
+use super::store::{LinearMemory, Table};
+use crate::module::value_type::WasmValue;
+
+/// The linear memories, globals and tables of an instance at some point,
+/// which `WasmInterpreter::restore` reinstates. A snapshot only fits the
+/// instance it was taken of.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) mems: Vec<LinearMemory>,
+    pub(crate) globals: Vec<WasmValue>,
+    pub(crate) tables: Vec<Table>,
+    /// the element segments and the data segments dropped by then, so
+    /// `table.init` and `memory.init` behave as they did
+    pub(crate) elem_segments: Vec<Vec<WasmValue>>,
+    pub(crate) dropped_datas: Vec<bool>,
+}
+
+impl Snapshot {
+    /// The total size of the linear memories in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.mems.iter().map(LinearMemory::size).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        module::{value_type::WasmValue, wasm_module::WasmModule},
+        vm::{WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    const WAT: &str = r#"
+        (module
+          (memory 1)
+          (global $count (mut i32) (i32.const 0))
+          (table $funcs 1 funcref)
+          (func (export "bump")
+            (i32.store (i32.const 0) (i32.add (i32.load (i32.const 0)) (i32.const 10)))
+            (global.set $count (i32.add (global.get $count) (i32.const 1)))
+            (drop (table.grow $funcs (ref.null func) (i32.const 1))))
+          (func (export "state") (result i32 i32 i32)
+            (i32.load (i32.const 0))
+            (global.get $count)
+            (table.size $funcs)))
+    "#;
+
+    /// The memory, the global and the table size of the instance.
+    fn state(vm: &WasmInterpreter) -> (i32, i32, i32) {
+        match vm.invoke("state", Vec::new()).unwrap()[..] {
+            [WasmValue::I32(mem), WasmValue::I32(global), WasmValue::I32(table)] => {
+                (mem, global, table)
+            }
+            ref results => panic!("state returned {:?}", results),
+        }
+    }
+
+    fn bump(vm: &WasmInterpreter, times: usize) {
+        for _ in 0..times {
+            vm.invoke("bump", Vec::new()).unwrap();
+        }
+    }
+
+    #[test]
+    fn restore_reinstates_memory_globals_and_tables() {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let vm = WasmInterpreter::with_config(module, &WasmInterpreterConfig::new()).unwrap();
+        bump(&vm, 2);
+        let snapshot = vm.snapshot();
+        assert_eq!(snapshot.memory_size(), 65536);
+        bump(&vm, 3);
+        assert_eq!(state(&vm), (50, 5, 6));
+
+        vm.restore(&snapshot).unwrap();
+        assert_eq!(state(&vm), (20, 2, 3));
+        bump(&vm, 1);
+        assert_eq!(state(&vm), (30, 3, 4));
+        // the snapshot is left as it was
+        vm.restore(&snapshot).unwrap();
+        assert_eq!(state(&vm), (20, 2, 3));
+    }
+
+    #[test]
+    fn a_snapshot_of_another_module_is_rejected() {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let vm = WasmInterpreter::with_config(module, &WasmInterpreterConfig::new()).unwrap();
+        let mut other_binary = Vec::new();
+        let other =
+            WasmModule::from_wat("(module (func (export \"f\")))", &mut other_binary).unwrap();
+        let other = WasmInterpreter::with_config(other, &WasmInterpreterConfig::new()).unwrap();
+        assert!(other.restore(&vm.snapshot()).is_err());
+    }
+}
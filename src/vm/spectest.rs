@@ -0,0 +1,57 @@
+//! The `spectest` module the scripts of the spec testsuite import from: its
+//! print functions, four globals, a memory and a table. It is not one of the
+//! built-in host modules, the wast runner and the `--spectest` flag define it
+//! with `Linker::spectest`.
+//!
+//! The print functions write their arguments like the reference interpreter,
+//! one `value : type` line each.
+
+use anyhow::Result;
+use wasmparser::RefType;
+
+use super::{linker::Linker, Memory};
+use crate::module::value_type::WasmValue;
+
+/// The module the spec testsuite imports from.
+pub const SPECTEST_MODULE: &str = "spectest";
+
+/// Define the `spectest` module in `linker`. Every instance gets its own
+/// copy of the table, but the globals and the memory are shared by the
+/// instances of the linker like any imported global and memory.
+pub(crate) fn define_spectest(linker: &mut Linker<'_>) -> Result<()> {
+    linker
+        .func_wrap(SPECTEST_MODULE, "print", || {})?
+        .func_wrap(SPECTEST_MODULE, "print_i32", |v: i32| {
+            println!("{} : i32", v)
+        })?
+        .func_wrap(SPECTEST_MODULE, "print_i64", |v: i64| {
+            println!("{} : i64", v)
+        })?
+        .func_wrap(SPECTEST_MODULE, "print_f32", |v: f32| {
+            println!("{} : f32", v)
+        })?
+        .func_wrap(SPECTEST_MODULE, "print_f64", |v: f64| {
+            println!("{} : f64", v)
+        })?
+        .func_wrap(SPECTEST_MODULE, "print_i32_f32", |a: i32, b: f32| {
+            println!("{} : i32", a);
+            println!("{} : f32", b);
+        })?
+        .func_wrap(SPECTEST_MODULE, "print_f64_f64", |a: f64, b: f64| {
+            println!("{} : f64", a);
+            println!("{} : f64", b);
+        })?
+        .global(SPECTEST_MODULE, "global_i32", WasmValue::I32(666))?
+        .global(SPECTEST_MODULE, "global_i64", WasmValue::I64(666))?
+        .global(SPECTEST_MODULE, "global_f32", WasmValue::F32(666.6))?
+        .global(SPECTEST_MODULE, "global_f64", WasmValue::F64(666.6))?
+        .memory(SPECTEST_MODULE, "memory", Memory::new(1, Some(2)))?
+        .table(
+            SPECTEST_MODULE,
+            "table",
+            RefType::FUNCREF,
+            vec![WasmValue::null_ref(&RefType::FUNCREF); 10],
+            Some(20),
+        )?;
+    Ok(())
+}
@@ -0,0 +1,498 @@
+//! The state of an instance, its memories, tables, globals and host
+//! functions, apart from the module it instantiates. The interpreter shares
+//! the store of an instance with every executor and the jit code runs on
+//! it.
+
+use std::{cell::Cell, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use debug_cell::RefCell;
+use wasmparser::{BinaryReader, MemoryType, TypeRef, WasmFeatures};
+
+use crate::module::{
+    const_expr::{
+        elem_segment_refs, eval_const_expr, eval_data_offset_expr, eval_offset_expr, eval_ref_expr,
+    },
+    insts::Instruction,
+    value_type::WasmValue,
+    wasm_module::WasmModule,
+};
+
+use super::{
+    host::HostFuncDispatcher, mmap::MmapRegion, Linker, Memory, Snapshot, TrapKind,
+    DEFAULT_TABLE_MAXIMUM, WASM_DEFAULT_PAGE_SIZE_BYTE,
+};
+
+/// A linear memory, memory64 memories are indexed with i64 addresses. It
+/// can grow up to `max` pages, in place.
+#[derive(Clone)]
+pub(crate) struct LinearMemory {
+    pub(crate) data: MmapRegion,
+    pub(crate) memory64: bool,
+    pub(crate) max: Option<u64>,
+}
+
+impl LinearMemory {
+    pub(crate) fn new(ty: &MemoryType) -> Self {
+        Self::from_bytes(
+            &[],
+            ty.initial as usize * WASM_DEFAULT_PAGE_SIZE_BYTE,
+            ty.memory64,
+            ty.maximum,
+        )
+    }
+
+    /// A memory holding `bytes` followed by `zeroed` zero bytes.
+    pub(crate) fn from_bytes(
+        bytes: &[u8],
+        zeroed: usize,
+        memory64: bool,
+        max: Option<u64>,
+    ) -> Self {
+        let max_len = max.map_or(usize::MAX, |max| {
+            (max as usize).saturating_mul(WASM_DEFAULT_PAGE_SIZE_BYTE)
+        });
+        let mut data = MmapRegion::new(bytes.len() + zeroed, max_len, memory64);
+        data[..bytes.len()].copy_from_slice(bytes);
+        Self {
+            data,
+            memory64,
+            max,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Grow by `additional_pages`, returns false if they cannot be mapped.
+    #[must_use]
+    pub fn grow(&mut self, additional_pages: u64) -> bool {
+        usize::try_from(additional_pages)
+            .ok()
+            .and_then(|pages| pages.checked_mul(WASM_DEFAULT_PAGE_SIZE_BYTE))
+            .and_then(|bytes| bytes.checked_add(self.data.len()))
+            .is_some_and(|new_size| self.data.grow(new_size))
+    }
+
+    /// The `N` bytes at `addr + offset`, `None` if they are out of bounds.
+    pub(crate) fn load<const N: usize>(&self, addr: u64, offset: u64) -> Option<[u8; N]> {
+        let start = self.effective_addr(addr, offset, N)?;
+        // SAFETY: the bytes were checked to be within the memory
+        Some(unsafe {
+            self.data
+                .as_ptr()
+                .add(start)
+                .cast::<[u8; N]>()
+                .read_unaligned()
+        })
+    }
+
+    /// Write `bytes` at `addr + offset`, `None` if they are out of bounds.
+    pub(crate) fn store<const N: usize>(
+        &mut self,
+        addr: u64,
+        offset: u64,
+        bytes: [u8; N],
+    ) -> Option<()> {
+        let start = self.effective_addr(addr, offset, N)?;
+        // SAFETY: as above
+        unsafe {
+            self.data
+                .as_mut_ptr()
+                .add(start)
+                .cast::<[u8; N]>()
+                .write_unaligned(bytes)
+        };
+        Some(())
+    }
+
+    /// The start of the `width` bytes at `addr + offset`, if they are within
+    /// the memory. This is the only bounds check of a load or a store.
+    fn effective_addr(&self, addr: u64, offset: u64, width: usize) -> Option<usize> {
+        let end = addr.checked_add(offset)?.checked_add(width as u64)?;
+        (end <= self.data.len() as u64).then(|| (end - width as u64) as usize)
+    }
+}
+
+/// A table holds references, indexed by `call_indirect`, `table.get` and
+/// `table.set`, it can grow up to its maximum size.
+#[derive(Clone)]
+pub(crate) struct Table {
+    pub(crate) elems: Vec<WasmValue>,
+    pub(crate) max: Option<u64>,
+}
+
+impl Table {
+    pub fn size(&self) -> usize {
+        self.elems.len()
+    }
+
+    /// The size after growing by `n` elements, `None` if it would exceed the
+    /// maximum size, `DEFAULT_TABLE_MAXIMUM` without one.
+    pub fn grown_size(&self, n: u32) -> Option<u32> {
+        let new_size = (self.size() as u32).checked_add(n)?;
+        (new_size as u64 <= self.max.unwrap_or(DEFAULT_TABLE_MAXIMUM)).then_some(new_size)
+    }
+
+    /// Grow the table by `n` elements set to `init`, returns the old size or
+    /// `None` if the table would exceed its maximum size or the elements
+    /// cannot be allocated.
+    pub fn grow(&mut self, n: u32, init: WasmValue) -> Option<u32> {
+        let old_size = self.size() as u32;
+        let new_size = self.grown_size(n)?;
+        self.elems.try_reserve_exact(n as usize).ok()?;
+        self.elems.resize(new_size as usize, init);
+        Some(old_size)
+    }
+}
+
+/// A handle to a global, clones refer to the same global. An imported
+/// global is the global of the linker or of the instance exporting it.
+#[derive(Clone, Debug)]
+pub(crate) struct Global {
+    value: Rc<Cell<WasmValue>>,
+}
+
+impl Global {
+    pub(crate) fn new(value: WasmValue) -> Self {
+        Self {
+            value: Rc::new(Cell::new(value)),
+        }
+    }
+
+    pub(crate) fn get(&self) -> WasmValue {
+        self.value.get()
+    }
+
+    pub(crate) fn set(&self, value: WasmValue) {
+        self.value.set(value);
+    }
+}
+
+/// The mutable state of an instance, every index space starts with the
+/// imports.
+pub(crate) struct Store<'a> {
+    pub(crate) mems: Vec<Memory>,
+    pub(crate) tables: RefCell<Vec<Table>>,
+    /// every global, `global.set` on an imported one is seen by every
+    /// instance importing it
+    pub(crate) globals: Vec<Global>,
+    /// the references of each element segment used by table.init, active and
+    /// declared segments are dropped once the module is instantiated
+    pub(crate) elem_segments: RefCell<Vec<Vec<WasmValue>>>,
+    /// whether each data segment is dropped
+    pub(crate) dropped_datas: RefCell<Vec<bool>>,
+    pub(crate) host: RefCell<HostFuncDispatcher<'a>>,
+    /// the last callee of each indirect call of the interpreter that had
+    /// the signature the call expects, by function index and pc, empty for
+    /// a function without `call_indirect`
+    indirect_callees: Vec<Box<[Cell<Option<u32>>]>>,
+}
+
+impl<'a> Store<'a> {
+    /// Resolve the imports of `module` in `linker` and initialize its
+    /// globals, memories and tables, fails if an import is missing or of
+    /// another type, or if a data or element segment does not fit.
+    pub(crate) fn new(module: &WasmModule, linker: &Linker<'a>) -> Result<Self> {
+        let host = HostFuncDispatcher::new(module, linker)?;
+        let globals = Self::setup_globals(module, linker)?;
+        let values = globals.iter().map(Global::get).collect::<Vec<_>>();
+        let mems = Self::setup_memories(module, linker)?;
+        let dropped_datas = Self::setup_data_section(module, &mems, &values)?;
+        let tables = Self::setup_tables(module, linker, &values)?;
+        let elem_segments = Self::setup_elem_segments(module)?;
+        let indirect_callees = Self::setup_indirect_callees(module);
+        Ok(Self {
+            mems,
+            tables: RefCell::new(tables),
+            globals,
+            elem_segments: RefCell::new(elem_segments),
+            dropped_datas: RefCell::new(dropped_datas),
+            host: RefCell::new(host),
+            indirect_callees,
+        })
+    }
+
+    /// The last callee of the indirect call at `pc` of `func_index` that had
+    /// the signature the call expects. A callee's signature never changes,
+    /// so a call that finds the same function in the table can skip the
+    /// check.
+    pub(crate) fn cached_indirect_callee(&self, func_index: u32, pc: usize) -> Option<u32> {
+        self.indirect_callees[func_index as usize]
+            .get(pc)
+            .and_then(Cell::get)
+    }
+
+    pub(crate) fn cache_indirect_callee(&self, func_index: u32, pc: usize, callee: u32) {
+        if let Some(cached) = self.indirect_callees[func_index as usize].get(pc) {
+            cached.set(Some(callee));
+        }
+    }
+
+    /// Capture the memories, globals and tables, see
+    /// `WasmInterpreter::snapshot`.
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mems: self.copy_memories(),
+            globals: self.global_values(),
+            tables: self.tables.borrow().clone(),
+            elem_segments: self.elem_segments.borrow().clone(),
+            dropped_datas: self.dropped_datas.borrow().clone(),
+        }
+    }
+
+    /// The current value of every global.
+    pub(crate) fn global_values(&self) -> Vec<WasmValue> {
+        self.globals.iter().map(Global::get).collect()
+    }
+
+    pub(crate) fn copy_memories(&self) -> Vec<LinearMemory> {
+        self.mems.iter().map(|mem| mem.borrow().clone()).collect()
+    }
+
+    /// Reinstate the state captured by `snapshot`, which must have been taken
+    /// of this store.
+    pub(crate) fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.mems.len() != self.mems.len()
+            || snapshot.globals.len() != self.globals.len()
+            || snapshot.tables.len() != self.tables.borrow().len()
+            || snapshot.elem_segments.len() != self.elem_segments.borrow().len()
+            || snapshot.dropped_datas.len() != self.dropped_datas.borrow().len()
+        {
+            return Err(anyhow!("restore: the snapshot is of another module"));
+        }
+
+        // an imported global or memory is restored for every instance
+        // sharing it
+        for (global, saved) in self.globals.iter().zip(&snapshot.globals) {
+            global.set(*saved);
+        }
+        for (mem, saved) in self.mems.iter().zip(&snapshot.mems) {
+            mem.borrow_mut().clone_from(saved);
+        }
+        self.tables.borrow_mut().clone_from(&snapshot.tables);
+        self.elem_segments
+            .borrow_mut()
+            .clone_from(&snapshot.elem_segments);
+        self.dropped_datas
+            .borrow_mut()
+            .clone_from(&snapshot.dropped_datas);
+        Ok(())
+    }
+
+    /// The imported globals, defined in `linker`, followed by the globals of
+    /// the module evaluated from their init expression.
+    fn setup_globals(module: &WasmModule, linker: &Linker<'_>) -> Result<Vec<Global>> {
+        let mut globals = vec![];
+        for import in &module.get_imports().imports {
+            let TypeRef::Global(ty) = import.ty else {
+                continue;
+            };
+            let global = linker
+                .get_global(import.module, import.name)
+                .ok_or_else(|| anyhow!("unknown import: {}.{}", import.module, import.name))?;
+            let value = global.get();
+            if !value.has_type(&ty.content_type) {
+                return Err(anyhow!(
+                    "incompatible import type: {}.{} is {:?}, imported as {}",
+                    import.module,
+                    import.name,
+                    value,
+                    ty.content_type
+                ));
+            }
+            globals.push(global);
+        }
+
+        let nimports = globals.len();
+        let mut values = globals.iter().map(Global::get).collect::<Vec<_>>();
+        for global in &module.get_globals()[nimports..] {
+            let reader = BinaryReader::new(global.get_init_expr(), 0, WasmFeatures::all());
+            let value = eval_const_expr(reader, &values)?;
+            values.push(value);
+            globals.push(Global::new(value));
+        }
+        Ok(globals)
+    }
+
+    /// The imported memories, defined in `linker`, followed by the memories
+    /// of the module.
+    fn setup_memories(module: &WasmModule, linker: &Linker<'_>) -> Result<Vec<Memory>> {
+        let mut mems = vec![];
+        for import in &module.get_imports().imports {
+            let TypeRef::Memory(ty) = import.ty else {
+                continue;
+            };
+            let mem = linker
+                .get_memory(import.module, import.name)
+                .ok_or_else(|| anyhow!("unknown import: {}.{}", import.module, import.name))?;
+            // the memory may have grown past the minimum of the import, but
+            // not past its maximum
+            let compatible = !ty.memory64
+                && mem.size_in_pages() >= ty.initial
+                && match (ty.maximum, mem.maximum()) {
+                    (Some(import_max), Some(max)) => max <= import_max,
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                };
+            if !compatible {
+                return Err(anyhow!(
+                    "incompatible import type: {}.{}",
+                    import.module,
+                    import.name
+                ));
+            }
+            mems.push(mem);
+        }
+        let nimports = mems.len();
+        mems.extend(
+            module.get_memories()[nimports..]
+                .iter()
+                .map(|ty| Memory::from_linear(LinearMemory::new(ty))),
+        );
+        Ok(mems)
+    }
+
+    /// The imported tables, copied from `linker`, followed by the tables of
+    /// the module with their initial size, then fill them with the active
+    /// element segments.
+    fn setup_tables(
+        module: &WasmModule,
+        linker: &Linker<'_>,
+        globals: &[WasmValue],
+    ) -> Result<Vec<Table>> {
+        let mut tables = vec![];
+        for import in &module.get_imports().imports {
+            let TypeRef::Table(ty) = import.ty else {
+                continue;
+            };
+            let (element_type, table) = linker
+                .get_table(import.module, import.name)
+                .ok_or_else(|| anyhow!("unknown import: {}.{}", import.module, import.name))?;
+            let compatible = *element_type == ty.element_type
+                && table.size() as u64 >= ty.initial
+                && match (ty.maximum, table.max) {
+                    (Some(import_max), Some(max)) => max <= import_max,
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                };
+            if !compatible {
+                return Err(anyhow!(
+                    "incompatible import type: {}.{}",
+                    import.module,
+                    import.name
+                ));
+            }
+            tables.push(table.clone());
+        }
+        let nimports = tables.len();
+        for table in &module.get_tables()[nimports..] {
+            let init = match &table.init {
+                wasmparser::TableInit::RefNull => WasmValue::null_ref(&table.ty.element_type),
+                wasmparser::TableInit::Expr(expr) => eval_ref_expr(expr, &table.ty.element_type)?,
+            };
+            tables.push(Table {
+                elems: vec![init; table.ty.initial as usize],
+                max: table.ty.maximum,
+            });
+        }
+
+        for elem in module.get_elems() {
+            match &elem.kind {
+                wasmparser::ElementKind::Active {
+                    table_index,
+                    offset_expr,
+                } => {
+                    let table = tables
+                        .get_mut(table_index.unwrap_or(0) as usize)
+                        .ok_or_else(|| anyhow!("element segment: invalid table index"))?;
+                    let offset = eval_offset_expr(offset_expr, globals)? as usize;
+                    let refs = elem_segment_refs(&elem.items)?;
+                    if offset + refs.len() > table.size() {
+                        return Err(TrapKind::OutOfBoundsTable.into());
+                    }
+                    table.elems[offset..offset + refs.len()].copy_from_slice(&refs);
+                }
+                // declared segments only forward declare the functions used
+                // by ref.func, passive ones are copied by table.init
+                wasmparser::ElementKind::Declared | wasmparser::ElementKind::Passive => {}
+            }
+        }
+
+        Ok(tables)
+    }
+
+    fn setup_elem_segments(module: &WasmModule) -> Result<Vec<Vec<WasmValue>>> {
+        let mut segments = vec![];
+        for elem in module.get_elems() {
+            match &elem.kind {
+                wasmparser::ElementKind::Passive => segments.push(elem_segment_refs(&elem.items)?),
+                _ => segments.push(vec![]),
+            }
+        }
+
+        Ok(segments)
+    }
+
+    fn setup_indirect_callees(module: &WasmModule) -> Vec<Box<[Cell<Option<u32>>]>> {
+        let indirect_call = |inst: &Instruction| {
+            matches!(
+                inst,
+                Instruction::CallIndirect { .. } | Instruction::ReturnCallIndirect { .. }
+            )
+        };
+        module
+            .get_funcs()
+            .iter()
+            .map(|func| {
+                let insts = func.get_insts();
+                if insts.iter().any(indirect_call) {
+                    vec![Cell::new(None); insts.len()].into()
+                } else {
+                    Box::default()
+                }
+            })
+            .collect()
+    }
+
+    /// setup data section with the given data section in the module
+    /// e.g. (data (i32.const 10) "foo") will be loaded to linear memory at address 10
+    /// copy the active data segments into their linear memory, returns
+    /// whether each segment is dropped. Active segments are dropped once they
+    /// are copied, passive ones stay alive until `data.drop`.
+    fn setup_data_section(
+        module: &WasmModule,
+        mems: &[Memory],
+        globals: &[WasmValue],
+    ) -> Result<Vec<bool>> {
+        let datas = module.get_datas();
+        let mut dropped = vec![];
+        for data in datas {
+            match &data.kind {
+                wasmparser::DataKind::Passive => dropped.push(false),
+                wasmparser::DataKind::Active {
+                    memory_index,
+                    offset_expr,
+                } => {
+                    let mut mem = mems
+                        .get(*memory_index as usize)
+                        .ok_or_else(|| anyhow!("data segment: invalid memory index"))?
+                        .borrow_mut();
+
+                    let offset = usize::try_from(eval_data_offset_expr(offset_expr, globals)?)?;
+                    let byte_slice = data.data;
+
+                    let dst = offset
+                        .checked_add(byte_slice.len())
+                        .and_then(|end| mem.data.get_mut(offset..end))
+                        .ok_or(TrapKind::OutOfBoundsMemory)?;
+                    dst.copy_from_slice(byte_slice);
+                    dropped.push(true);
+                }
+            }
+        }
+
+        Ok(dropped)
+    }
+}
@@ -0,0 +1,410 @@
+//! Tiered execution, see `WasmInterpreter::tier_up`. Every call starts in
+//! the interpreter, which counts the calls of each function. Once a function
+//! was called `threshold` times, it is jit compiled and later calls run its
+//! jit code instead, along with the functions it calls.
+//!
+//! A loop of an interpreted call that iterated `threshold` times is entered
+//! on the stack: the function is compiled again to start at the loop with
+//! the locals of the interpreted frame, and the jit code runs the rest of
+//! the call, see `jit::setup::tier`.
+//!
+//! Hot functions and loops are compiled on the interpreter thread, by the
+//! call or the iteration that finds them hot, which then runs the jit code.
+//! The compiler shares the `Rc`s of the module and the store with the
+//! interpreter, so it never leaves the thread.
+//!
+//! The jit code runs on the store of the instance like the interpreter, see
+//! `jit::setup::state`, so every function is tiered up unless it uses what
+//! the jit does not support, simd, or calls a function that does. A trap of
+//! the jit code is returned to the interpreter like its own traps, see
+//! `jit::setup::trap`, and so is an exception it does not catch, which the
+//! try blocks of the interpreted callers may catch.
+
+use std::{
+    cell::{Cell, OnceCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use anyhow::Result;
+use debug_cell::RefCell;
+
+use crate::{
+    jit::{
+        call_guarded, register_trap_handler, JitSettings, OsrEntry, TierEntry, TrapFrame,
+        X86JitCompiler,
+    },
+    module::{insts::Instruction, value_type::WasmValue, wasm_module::WasmModule},
+};
+
+use super::Store;
+
+pub(crate) struct Tiering<'a> {
+    module: Rc<WasmModule<'a>>,
+    store: Rc<Store<'a>>,
+    /// the number of interpreted calls before a function is compiled
+    threshold: u64,
+    settings: JitSettings,
+    /// the interpreted calls of every function
+    calls: Vec<Cell<u64>>,
+    /// whether every function may run as jit code, imports never do
+    eligible: Vec<Cell<bool>>,
+    /// shared by all the tiered up functions, boxed since its stubs hold its
+    /// address
+    compiler: OnceCell<RefCell<Box<X86JitCompiler<'a>>>>,
+    entries: RefCell<HashMap<u32, TierEntry>>,
+    /// the loops of every function, by function index and pc
+    loops: RefCell<HashMap<(u32, usize), HotLoop>>,
+    /// the calls into the jit code on the stack, which may return to the
+    /// jit code through a host call
+    running: Cell<usize>,
+}
+
+enum HotLoop {
+    /// the iterations so far
+    Counting(u64),
+    Compiled(OsrEntry),
+    /// stays in the interpreter
+    Failed,
+}
+
+/// What is tiered up.
+#[derive(Clone, Copy)]
+enum Target {
+    Func(u32),
+    /// a function at the loop at pc
+    Loop(u32, usize),
+}
+
+impl<'a> Tiering<'a> {
+    /// The hot code is compiled with `settings`, apart from what the calls
+    /// staying in the interpreter need, see `compiler`.
+    pub(crate) fn new(
+        module: Rc<WasmModule<'a>>,
+        store: Rc<Store<'a>>,
+        threshold: u64,
+        settings: JitSettings,
+    ) -> Self {
+        let nfuncs = module.get_funcs().len();
+        let eligible = supported_funcs(&module);
+        Self {
+            module,
+            store,
+            threshold,
+            settings,
+            calls: vec![Cell::new(0); nfuncs],
+            eligible: eligible.into_iter().map(Cell::new).collect(),
+            compiler: OnceCell::new(),
+            entries: RefCell::new(HashMap::new()),
+            loops: RefCell::new(HashMap::new()),
+            running: Cell::new(0),
+        }
+    }
+
+    /// Count a call of `func_index` and run it as jit code if it is hot and
+    /// compiled, returns `None` if the call is left to the interpreter.
+    /// `call_depth` is the depth of the caller.
+    pub(crate) fn call(
+        &self,
+        func_index: u32,
+        args: &[WasmValue],
+        call_depth: usize,
+    ) -> Option<Result<Vec<WasmValue>>> {
+        let index = func_index as usize;
+        if !self.eligible[index].get() {
+            return None;
+        }
+        let calls = self.calls[index].get() + 1;
+        self.calls[index].set(calls);
+        if calls <= self.threshold {
+            return None;
+        }
+
+        let entry = self.entries.borrow().get(&func_index).copied();
+        let entry = match entry {
+            Some(entry) => entry,
+            None => self.compile_func(func_index)?,
+        };
+
+        let raw_args = args.iter().map(WasmValue::to_raw).collect::<Vec<_>>();
+        Some(self.run(func_index, call_depth as u64, |frame| {
+            entry(raw_args.as_ptr(), frame)
+        }))
+    }
+
+    /// Count an iteration of the loop at `pc` and run the rest of the call
+    /// as jit code if it is hot and compiled, returns the results of the
+    /// function or `None` if the loop is left to the interpreter. The jit
+    /// code starts with the `locals` and operand `stack` of the frame, whose
+    /// depth is `call_depth`.
+    pub(crate) fn enter_loop(
+        &self,
+        func_index: u32,
+        pc: usize,
+        locals: &[WasmValue],
+        stack: &[WasmValue],
+        call_depth: usize,
+    ) -> Option<Result<Vec<WasmValue>>> {
+        if !self.eligible[func_index as usize].get() {
+            return None;
+        }
+
+        let mut loops = self.loops.borrow_mut();
+        let hot_loop = loops
+            .entry((func_index, pc))
+            .or_insert(HotLoop::Counting(0));
+        if let HotLoop::Counting(iterations) = hot_loop {
+            *iterations += 1;
+            if *iterations <= self.threshold {
+                return None;
+            }
+            drop(loops);
+            self.compile_loop(func_index, pc);
+            loops = self.loops.borrow_mut();
+        }
+        let Some(HotLoop::Compiled(osr_entry)) = loops.get_mut(&(func_index, pc)) else {
+            return None;
+        };
+
+        if osr_entry.values.len() != locals.len() + stack.len() {
+            return None;
+        }
+        for (raw, value) in osr_entry.values.iter_mut().zip(locals.iter().chain(stack)) {
+            *raw = value.to_raw();
+        }
+        let entry = osr_entry.entry;
+        drop(loops);
+        // the prologue of the copy counts the frame it replaces again
+        let call_depth = (call_depth as u64).wrapping_sub(1);
+        Some(self.run(func_index, call_depth, |frame| {
+            entry(std::ptr::null(), frame)
+        }))
+    }
+
+    /// Call into the jit code and decode the results of `func_index`, with
+    /// the call depth of the jit code starting at `call_depth`. The jit code
+    /// runs on the state of the store and writes it back once it returns.
+    fn run(
+        &self,
+        func_index: u32,
+        call_depth: u64,
+        call: impl FnOnce(*mut TrapFrame) -> u64,
+    ) -> Result<Vec<WasmValue>> {
+        let compiler = self.compiler.get().unwrap();
+        let (landing, saved) = {
+            let mut compiler = compiler.borrow_mut();
+            let saved = compiler.enter(None);
+            *compiler.call_depth = call_depth;
+            (compiler.trap_landing(), saved)
+        };
+        // the jit code only borrows the compiler to compile the functions it
+        // reaches on their first call and around host calls, which may call
+        // back into the instance
+        self.running.set(self.running.get() + 1);
+        let first = call_guarded(landing, call);
+        self.running.set(self.running.get() - 1);
+
+        let mut compiler = compiler.borrow_mut();
+        let results = first.map(|first| compiler.entry_results(func_index, first));
+        compiler.leave(saved);
+        results
+    }
+
+    /// Compile `func_index` and record its entry, returns `None` if it is
+    /// left to the interpreter. Nothing is compiled while jit code is on the
+    /// stack, a host call returns to it.
+    fn compile_func(&self, func_index: u32) -> Option<TierEntry> {
+        if self.running.get() > 0 {
+            return None;
+        }
+        let nargs = self.module.get_funcs()[func_index as usize]
+            .get_sig()
+            .params()
+            .len();
+        let compiled = self
+            .compiler()
+            .and_then(|compiler| compiler.borrow_mut().emit_tier_entry(func_index, nargs));
+        match compiled {
+            Ok(entry) => {
+                self.entries.borrow_mut().insert(func_index, entry);
+                Some(entry)
+            }
+            Err(e) => {
+                self.fail(Target::Func(func_index), e);
+                None
+            }
+        }
+    }
+
+    /// Compile `func_index` to start at the loop at `pc` and record its
+    /// entry, unless jit code is on the stack.
+    fn compile_loop(&self, func_index: u32, pc: usize) {
+        if self.running.get() > 0 {
+            return;
+        }
+        let compiled = self
+            .compiler()
+            .and_then(|compiler| compiler.borrow_mut().emit_osr_entry(func_index, pc));
+        match compiled {
+            Ok(osr_entry) => {
+                self.loops
+                    .borrow_mut()
+                    .insert((func_index, pc), HotLoop::Compiled(osr_entry));
+            }
+            Err(e) => self.fail(Target::Loop(func_index, pc), e),
+        }
+    }
+
+    /// Leave `target` to the interpreter from now on.
+    fn fail(&self, target: Target, e: anyhow::Error) {
+        match target {
+            Target::Func(func_index) => {
+                log::debug!("function {} is not tiered up: {}", func_index, e);
+                self.eligible[func_index as usize].set(false);
+            }
+            Target::Loop(func_index, pc) => {
+                log::debug!(
+                    "loop {} of function {} is not tiered up: {}",
+                    pc,
+                    func_index,
+                    e
+                );
+                self.loops
+                    .borrow_mut()
+                    .insert((func_index, pc), HotLoop::Failed);
+            }
+        }
+    }
+
+    /// The compiler shared by the jit code, set up on the first tier up.
+    fn compiler(&self) -> Result<&RefCell<Box<X86JitCompiler<'a>>>> {
+        match self.compiler.get() {
+            Some(compiler) => Ok(compiler),
+            None => {
+                register_trap_handler();
+                // the calls that are metered, interruptible, traced or
+                // debugged stay in the interpreter, see
+                // `WasmFunctionExecutorImpl::tiering`
+                let settings = JitSettings {
+                    fuel: Rc::new(Cell::new(None)),
+                    epoch: None,
+                    trace: false,
+                    debug_break: false,
+                    ..self.settings.clone()
+                };
+                let mut compiler = Box::new(X86JitCompiler::new(
+                    Rc::clone(&self.module),
+                    Rc::clone(&self.store),
+                    &settings,
+                )?);
+                compiler.setup_tiering()?;
+                Ok(self.compiler.get_or_init(|| RefCell::new(compiler)))
+            }
+        }
+    }
+}
+
+/// Whether each function may run as jit code: it only uses what the jit
+/// supports and only calls such functions. A function reached through a
+/// table is compiled on its first call and traps if it cannot be, so
+/// `call_indirect` is only tiered up when every function is supported.
+fn supported_funcs(module: &WasmModule) -> Vec<bool> {
+    let nimports = module.get_num_func_imports();
+    let funcs = module.get_funcs();
+    let supported = funcs
+        .iter()
+        .map(|func| X86JitCompiler::supports(module, func))
+        .collect::<Vec<_>>();
+    let all_supported = supported.iter().skip(nimports).all(|supported| *supported);
+    let mut eligible = supported
+        .iter()
+        .enumerate()
+        .map(|(i, supported)| i >= nimports && *supported)
+        .collect::<Vec<_>>();
+
+    // drop the callers of functions that are not eligible until none is left
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, func) in funcs.iter().enumerate() {
+            if !eligible[i] {
+                continue;
+            }
+            let calls_ineligible = func.get_insts().iter().any(|inst| match inst {
+                Instruction::Call { func_idx } | Instruction::ReturnCall { func_idx } => {
+                    let callee = *func_idx as usize;
+                    callee >= nimports && !eligible[callee]
+                }
+                Instruction::CallIndirect { .. } | Instruction::ReturnCallIndirect { .. } => {
+                    !all_supported
+                }
+                _ => false,
+            });
+            if calls_ineligible {
+                eligible[i] = false;
+                changed = true;
+            }
+        }
+    }
+    eligible
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::HotLoop;
+    use crate::{
+        module::{value_type::WasmValue, wasm_module::WasmModule},
+        vm::{WasmInterpreter, WasmInterpreterConfig},
+    };
+
+    const WAT: &str = r#"
+        (module
+          (func $fib (export "fib") (param $n i32) (result i32)
+            (if (result i32) (i32.lt_s (local.get $n) (i32.const 2))
+              (then (i32.const 1))
+              (else
+                (i32.add
+                  (call $fib (i32.sub (local.get $n) (i32.const 2)))
+                  (call $fib (i32.sub (local.get $n) (i32.const 1)))))))
+          (func (export "sum") (param $n i32) (result i64) (local $i i32) (local $sum i64)
+            ;; on the operand stack while the loop runs
+            (i64.const 5)
+            (block $done
+              (loop $next
+                (br_if $done (i32.ge_s (local.get $i) (local.get $n)))
+                (local.set $sum
+                  (i64.add (local.get $sum) (i64.extend_i32_s (local.get $i))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next)))
+            (i64.add (local.get $sum))))
+    "#;
+
+    #[test]
+    fn hot_functions_run_as_jit_code() {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let config = WasmInterpreterConfig::new().tier_up(10);
+        let vm = WasmInterpreter::with_config(module, &config).unwrap();
+        let results = vm.invoke("fib", vec![WasmValue::I32(20)]).unwrap();
+        assert!(matches!(results[..], [WasmValue::I32(10946)]));
+        assert!(vm.tiering().unwrap().entries.borrow().contains_key(&0));
+        // the later calls start in the jit code right away
+        let results = vm.invoke("fib", vec![WasmValue::I32(10)]).unwrap();
+        assert!(matches!(results[..], [WasmValue::I32(89)]));
+    }
+
+    #[test]
+    fn hot_loops_are_entered_on_the_stack() {
+        let mut binary = Vec::new();
+        let module = WasmModule::from_wat(WAT, &mut binary).unwrap();
+        let config = WasmInterpreterConfig::new().tier_up(10);
+        let vm = WasmInterpreter::with_config(module, &config).unwrap();
+        let results = vm.invoke("sum", vec![WasmValue::I32(1000)]).unwrap();
+        assert!(matches!(results[..], [WasmValue::I64(499505)]));
+        let tiering = vm.tiering().unwrap();
+        let loops = tiering.loops.borrow();
+        assert!(loops
+            .values()
+            .any(|hot_loop| matches!(hot_loop, HotLoop::Compiled(_))));
+    }
+}
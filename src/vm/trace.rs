@@ -0,0 +1,247 @@
+//! Tracing of host import calls. Every call that goes through the host
+//! dispatcher is recorded with its decoded arguments and results, either as a
+//! human readable line on stderr or as one JSON object per line in a file.
+//!
+//! The interpreter can also trace every instruction it executes, and the jit
+//! every basic block it enters, see `trace_instruction` and `trace_block`.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+
+use super::host::HostFuncImport;
+use crate::module::{dwarf::SourceLocation, insts::Instruction, value_type::WasmValue};
+
+/// The number of operand stack values shown in an instruction trace.
+const TRACE_STACK_DEPTH: usize = 4;
+
+/// Where import call traces are written to.
+#[derive(Debug, Clone)]
+pub enum ImportTraceSink {
+    Stderr,
+    JsonFile(PathBuf),
+}
+
+pub(crate) struct ImportTracer {
+    out: Box<dyn Write>,
+    json: bool,
+}
+
+impl ImportTracer {
+    pub(crate) fn new(sink: &ImportTraceSink) -> Result<Self> {
+        let tracer = match sink {
+            ImportTraceSink::Stderr => Self {
+                out: Box::new(std::io::stderr()),
+                json: false,
+            },
+            ImportTraceSink::JsonFile(path) => Self {
+                out: Box::new(BufWriter::new(File::create(path)?)),
+                json: true,
+            },
+        };
+        Ok(tracer)
+    }
+
+    /// Record a single host call. Failing to write the trace must not change
+    /// the outcome of the program, so write errors are ignored.
+    pub(crate) fn record(
+        &mut self,
+        import: &HostFuncImport,
+        args: &[WasmValue],
+        result: &Result<Vec<WasmValue>>,
+    ) {
+        let line = if self.json {
+            Self::format_json(import, args, result)
+        } else {
+            Self::format_text(import, args, result)
+        };
+        let _ = writeln!(self.out, "{}", line);
+        let _ = self.out.flush();
+    }
+
+    fn format_text(
+        import: &HostFuncImport,
+        args: &[WasmValue],
+        result: &Result<Vec<WasmValue>>,
+    ) -> String {
+        let args = args
+            .iter()
+            .map(|v| format!("{}:{}", type_name(v), v))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = match result {
+            Ok(results) if results.len() == 1 => {
+                format!("{}:{}", type_name(&results[0]), results[0])
+            }
+            Ok(results) => format!(
+                "({})",
+                results
+                    .iter()
+                    .map(|v| format!("{}:{}", type_name(v), v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Err(e) => format!("trap({})", e),
+        };
+        format!(
+            "[import] {}.{}({}) -> {}",
+            import.module, import.name, args, result
+        )
+    }
+
+    fn format_json(
+        import: &HostFuncImport,
+        args: &[WasmValue],
+        result: &Result<Vec<WasmValue>>,
+    ) -> String {
+        let args = args.iter().map(json_value).collect::<Vec<_>>().join(",");
+        let outcome = match result {
+            Ok(results) => format!(
+                "\"results\":[{}]",
+                results.iter().map(json_value).collect::<Vec<_>>().join(",")
+            ),
+            Err(e) => format!("\"trap\":{}", json_string(&e.to_string())),
+        };
+        format!(
+            "{{\"module\":{},\"field\":{},\"args\":[{}],{}}}",
+            json_string(&import.module),
+            json_string(&import.name),
+            args,
+            outcome
+        )
+    }
+}
+
+/// Log an instruction the interpreter is about to execute to stderr, with
+/// the top of the operand stack, topmost value first.
+pub(crate) fn trace_instruction(
+    func_index: u32,
+    func_name: Option<&str>,
+    pc: usize,
+    location: Option<&SourceLocation>,
+    inst: &Instruction,
+    stack: &[WasmValue],
+) {
+    let mut top = stack
+        .iter()
+        .rev()
+        .take(TRACE_STACK_DEPTH)
+        .map(|v| format!("{}:{}", type_name(v), v))
+        .collect::<Vec<_>>();
+    if stack.len() > TRACE_STACK_DEPTH {
+        top.push("..".to_string());
+    }
+    let func = match func_name {
+        Some(name) => format!("{} ${}", func_index, name),
+        None => func_index.to_string(),
+    };
+    let location = match location {
+        Some(location) => format!(" ({})", location),
+        None => String::new(),
+    };
+    eprintln!(
+        "[trace] func {} pc {}{}: {:?} [{}]",
+        func,
+        pc,
+        location,
+        inst,
+        top.join(", ")
+    );
+}
+
+/// Called from jit code at the start of every basic block, the operand stack
+/// lives in registers there so only the position is logged.
+pub(crate) extern "C" fn trace_block(func_index: u64, pc: u64) -> u64 {
+    eprintln!("[trace] func {} pc {}: block", func_index, pc);
+    0
+}
+
+fn type_name(v: &WasmValue) -> &'static str {
+    match v {
+        WasmValue::I32(_) => "i32",
+        WasmValue::I64(_) => "i64",
+        WasmValue::F32(_) => "f32",
+        WasmValue::F64(_) => "f64",
+        WasmValue::FuncRef(_) => "funcref",
+        WasmValue::ExternRef(_) => "externref",
+        WasmValue::V128(_) => "v128",
+    }
+}
+
+fn json_value(v: &WasmValue) -> String {
+    let value = match v {
+        WasmValue::I32(val) => val.to_string(),
+        WasmValue::I64(val) => val.to_string(),
+        // NaN and infinities are not valid json numbers
+        WasmValue::F32(val) if val.is_finite() => format!("{:?}", val),
+        WasmValue::F32(val) => json_string(&val.to_string()),
+        WasmValue::F64(val) if val.is_finite() => format!("{:?}", val),
+        WasmValue::F64(val) => json_string(&val.to_string()),
+        WasmValue::FuncRef(val) | WasmValue::ExternRef(val) => {
+            val.map_or("null".to_string(), |idx| idx.to_string())
+        }
+        // too wide for a json number
+        WasmValue::V128(_) => json_string(&v.to_string()),
+    };
+    format!("{{\"type\":\"{}\",\"value\":{}}}", type_name(v), value)
+}
+
+/// `s` as a JSON string literal.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmparser::{FuncType, ValType};
+
+    use super::ImportTracer;
+    use crate::{
+        module::value_type::WasmValue,
+        vm::{host::HostFuncImport, TrapKind},
+    };
+
+    fn import() -> HostFuncImport {
+        HostFuncImport {
+            index: 0,
+            module: "env".to_string(),
+            name: "f".to_string(),
+            sig: FuncType::new([ValType::I32, ValType::F64], [ValType::I64]),
+        }
+    }
+
+    #[test]
+    fn calls_are_json_objects() {
+        let args = [WasmValue::I32(-1), WasmValue::F64(2.5)];
+        assert_eq!(
+            ImportTracer::format_json(&import(), &args, &Ok(vec![WasmValue::I64(7)])),
+            r#"{"module":"env","field":"f","args":[{"type":"i32","value":-1},{"type":"f64","value":2.5}],"results":[{"type":"i64","value":7}]}"#
+        );
+    }
+
+    #[test]
+    fn traps_and_nans_are_json_strings() {
+        let args = [WasmValue::I32(0), WasmValue::F64(f64::NAN)];
+        let trap = Err(TrapKind::OutOfBoundsMemory.into());
+        assert_eq!(
+            ImportTracer::format_json(&import(), &args, &trap),
+            r#"{"module":"env","field":"f","args":[{"type":"i32","value":0},{"type":"f64","value":"NaN"}],"trap":"out of bounds memory access"}"#
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use super::{backtrace::WasmBacktrace, exception::WasmException};
+
+/// Why the execution of wasm code was aborted. A trap travels up the host
+/// stack as an error, like `WasmException`, and is recovered with a
+/// downcast once it leaves the vm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    Unreachable,
+    OutOfBoundsMemory,
+    OutOfBoundsTable,
+    DivisionByZero,
+    IntegerOverflow,
+    /// a float to integer truncation of NaN
+    InvalidConversion,
+    /// `call_indirect` with an index past the end of the table
+    UndefinedElement,
+    /// `call_indirect` through a null table slot
+    UninitializedElement,
+    CallIndirectMismatch,
+    StackExhausted,
+    /// the fuel given to the instance ran out
+    FuelExhausted,
+    /// the epoch reached the deadline of the instance
+    Interrupted,
+    /// the execution ran longer than the timeout of the instance
+    Timeout,
+    /// an exception that no `try` block caught
+    UncaughtException,
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the messages of the spec testsuite
+        let msg = match self {
+            TrapKind::Unreachable => "unreachable",
+            TrapKind::OutOfBoundsMemory => "out of bounds memory access",
+            TrapKind::OutOfBoundsTable => "out of bounds table access",
+            TrapKind::DivisionByZero => "integer divide by zero",
+            TrapKind::IntegerOverflow => "integer overflow",
+            TrapKind::InvalidConversion => "invalid conversion to integer",
+            TrapKind::UndefinedElement => "undefined element",
+            TrapKind::UninitializedElement => "uninitialized element",
+            TrapKind::CallIndirectMismatch => "indirect call type mismatch",
+            TrapKind::StackExhausted => "call stack exhausted",
+            TrapKind::FuelExhausted => "all fuel consumed",
+            TrapKind::Interrupted => "interrupted",
+            TrapKind::Timeout => "execution timed out",
+            TrapKind::UncaughtException => "uncaught exception",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl TrapKind {
+    /// Whether the execution stopped cleanly before an instruction, so that
+    /// its checkpoint can be resumed, see `WasmInterpreter::resume`.
+    pub fn is_resumable(&self) -> bool {
+        matches!(
+            self,
+            TrapKind::FuelExhausted | TrapKind::Interrupted | TrapKind::Timeout
+        )
+    }
+}
+
+impl std::error::Error for TrapKind {}
+
+/// The status the module called `proc_exit` with, it unwinds the host
+/// stack like a trap but ends the run with `RuntimeError::Exit`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WasmExit(pub(crate) i32);
+
+impl fmt::Display for WasmExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit with status {}", self.0)
+    }
+}
+
+impl std::error::Error for WasmExit {}
+
+/// The process exit status of a trap when the command line enables exit
+/// codes. It is the status of a process killed by
+/// `SIGABRT`, so it does not collide with small `proc_exit` statuses.
+pub const TRAP_EXIT_STATUS: i32 = 134;
+
+/// The error returned when running a module.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The wasm code trapped in the frames of the backtrace.
+    Trap(TrapKind, WasmBacktrace),
+    /// The module called `proc_exit` with this status.
+    Exit(i32),
+    /// Anything else, e.g. a missing export or an instruction the backend
+    /// does not support.
+    Other(anyhow::Error),
+}
+
+impl RuntimeError {
+    pub fn trap_kind(&self) -> Option<TrapKind> {
+        match self {
+            RuntimeError::Trap(kind, _) => Some(*kind),
+            RuntimeError::Exit(_) | RuntimeError::Other(_) => None,
+        }
+    }
+
+    /// The status the module called `proc_exit` with.
+    pub fn exit_status(&self) -> Option<i32> {
+        match self {
+            RuntimeError::Exit(status) => Some(*status),
+            RuntimeError::Trap(..) | RuntimeError::Other(_) => None,
+        }
+    }
+
+    pub fn backtrace(&self) -> Option<&WasmBacktrace> {
+        match self {
+            RuntimeError::Trap(_, backtrace) => Some(backtrace),
+            RuntimeError::Exit(_) | RuntimeError::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Trap(kind, backtrace) if backtrace.is_empty() => {
+                write!(f, "trap: {}", kind)
+            }
+            RuntimeError::Trap(kind, backtrace) => write!(f, "trap: {}\n{}", kind, backtrace),
+            RuntimeError::Exit(status) => write!(f, "exit with status {}", status),
+            RuntimeError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<anyhow::Error> for RuntimeError {
+    fn from(err: anyhow::Error) -> Self {
+        // the interpreter attaches the backtrace to traps as context
+        let backtrace = err
+            .downcast_ref::<WasmBacktrace>()
+            .cloned()
+            .unwrap_or_default();
+        if let Some(kind) = err.downcast_ref::<TrapKind>() {
+            RuntimeError::Trap(*kind, backtrace)
+        } else if err.is::<WasmException>() {
+            RuntimeError::Trap(TrapKind::UncaughtException, backtrace)
+        } else if let Some(WasmExit(status)) = err.downcast_ref::<WasmExit>() {
+            RuntimeError::Exit(*status)
+        } else {
+            RuntimeError::Other(err)
+        }
+    }
+}
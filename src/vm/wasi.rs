@@ -0,0 +1,718 @@
+//! The built-in `wasi_snapshot_preview1` host functions, enough of WASI for
+//! command modules compiled from Rust or C to read their arguments, print,
+//! work on files and exit. They are resolved like the other built-in host
+//! functions, after the `Linker`, with no arguments, an empty environment and
+//! no directories. An embedder passes them with `Linker::wasi`:
+//!
+//! ```ignore
+//! let mut linker = Linker::new();
+//! linker.wasi(
+//!     WasiCtx::new()
+//!         .args(["prog.wasm", "-v"])
+//!         .env("HOME", "/")
+//!         .preopen_dir("./data", "/data"),
+//! )?;
+//! ```
+//!
+//! Stdin is the one of the host, or a file with `WasiCtx::stdin`. The files
+//! a module opens are all under the directories preopened for it, the file
+//! descriptors after stdio: a path that leads out of its directory, through
+//! `..` or a symlink, fails with `NOTCAPABLE`.
+//!
+//! The clocks are the ones of the host, or all read the same fixed time with
+//! `WasiCtx::fixed_clock`, so that a run can be replayed. The same goes for
+//! `random_get`, which reads the random source of the host unless
+//! `WasiCtx::random_seed` seeds a PRNG.
+//!
+//! Like libc, the functions return an errno instead of trapping: a pointer
+//! out of the caller's memory fails with `FAULT` and an unknown file
+//! descriptor with `BADF`. Only `proc_exit` ends the run, with its status.
+
+use std::{
+    cell::{Cell, RefCell},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    linker::{Caller, Linker},
+    trap::WasmExit,
+};
+
+/// The module WASI functions are imported from.
+pub const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// What the WASI functions of an instance see of the host.
+#[derive(Debug, Clone, Default)]
+pub struct WasiCtx {
+    args: Vec<String>,
+    /// `KEY=VALUE`
+    env: Vec<String>,
+    /// the time in nanoseconds every clock reads, the clocks of the host if
+    /// not set
+    fixed_clock: Option<u64>,
+    /// the seed of the PRNG of `random_get`, the random source of the host
+    /// if not set
+    random_seed: Option<u64>,
+    /// the file read as stdin, the stdin of the host if not set
+    stdin: Option<PathBuf>,
+    /// the host directories and the names they are seen as
+    preopens: Vec<(PathBuf, String)>,
+}
+
+impl WasiCtx {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The arguments `args_get` returns, the program name first.
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add `key` to the environment `environ_get` returns.
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push(format!("{}={}", key, value));
+        self
+    }
+
+    /// Make every clock read `nanos`, with a resolution of a nanosecond.
+    pub fn fixed_clock(mut self, nanos: u64) -> Self {
+        self.fixed_clock = Some(nanos);
+        self
+    }
+
+    /// Make `random_get` return the bytes of a PRNG seeded with `seed`, the
+    /// same for every run, instead of secure random bytes.
+    pub fn random_seed(mut self, seed: u64) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+
+    /// Read stdin from the file at `path`, it is opened by `Linker::wasi`.
+    pub fn stdin(mut self, path: impl Into<PathBuf>) -> Self {
+        self.stdin = Some(path.into());
+        self
+    }
+
+    /// Let the module open the files under the host directory `host`, which
+    /// it sees as `guest`.
+    pub fn preopen_dir(mut self, host: impl Into<PathBuf>, guest: &str) -> Self {
+        self.preopens.push((host.into(), guest.to_string()));
+        self
+    }
+}
+
+/// What a file descriptor refers to.
+enum Fd {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+    /// `path` is canonical, `guest` is the name of a preopened directory
+    Dir {
+        path: PathBuf,
+        guest: Option<String>,
+    },
+}
+
+/// The state of the WASI functions of a linker, shared by the instances
+/// linked with it.
+struct WasiState {
+    ctx: WasiCtx,
+    /// the state of the PRNG, if seeded
+    rng: Option<Cell<u64>>,
+    /// by file descriptor, `None` once closed
+    fds: RefCell<Vec<Option<Fd>>>,
+}
+
+// the flags of path_open
+const OFLAGS_CREAT: i32 = 1;
+const OFLAGS_DIRECTORY: i32 = 2;
+const OFLAGS_EXCL: i32 = 4;
+const OFLAGS_TRUNC: i32 = 8;
+const FDFLAGS_APPEND: i32 = 1;
+const RIGHTS_FD_READ: i64 = 1 << 1;
+const RIGHTS_FD_WRITE: i64 = 1 << 6;
+
+impl WasiState {
+    fn new(ctx: WasiCtx) -> Result<Self> {
+        let stdin = match &ctx.stdin {
+            Some(path) => Fd::File(
+                File::open(path)
+                    .map_err(|e| anyhow!("failed to open {} as stdin: {}", path.display(), e))?,
+            ),
+            None => Fd::Stdin,
+        };
+        let mut fds = vec![Some(stdin), Some(Fd::Stdout), Some(Fd::Stderr)];
+        for (host, guest) in &ctx.preopens {
+            let path = host
+                .canonicalize()
+                .ok()
+                .filter(|path| path.is_dir())
+                .ok_or_else(|| anyhow!("{} is not a directory", host.display()))?;
+            fds.push(Some(Fd::Dir {
+                path,
+                guest: Some(guest.clone()),
+            }));
+        }
+        Ok(Self {
+            rng: ctx.random_seed.map(Cell::new),
+            fds: RefCell::new(fds),
+            ctx,
+        })
+    }
+
+    /// Fill `buf` with random bytes.
+    fn fill_random(&self, buf: &mut [u8]) -> Errno {
+        let Some(rng) = &self.rng else {
+            let mut filled = 0;
+            while filled < buf.len() {
+                let rest = &mut buf[filled..];
+                let n = unsafe { libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0) };
+                if n < 0 {
+                    if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Errno::Io;
+                }
+                filled += n as usize;
+            }
+            return Errno::Success;
+        };
+        // splitmix64
+        for chunk in buf.chunks_mut(8) {
+            let state = rng.get().wrapping_add(0x9e3779b97f4a7c15);
+            rng.set(state);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+        }
+        Errno::Success
+    }
+
+    /// Run `f` on the open file descriptor `fd`.
+    fn with_fd<T>(&self, fd: i32, f: impl FnOnce(&mut Fd) -> Result<T, Errno>) -> Result<T, Errno> {
+        let mut fds = self.fds.borrow_mut();
+        match fds.get_mut(fd as u32 as usize) {
+            Some(Some(entry)) => f(entry),
+            _ => Err(Errno::Badf),
+        }
+    }
+
+    /// Read from `fd` into `buf`, returns the number of bytes read.
+    fn read(&self, fd: i32, buf: &mut [u8]) -> Result<usize, Errno> {
+        self.with_fd(fd, |entry| match entry {
+            Fd::Stdin => std::io::stdin().read(buf).map_err(io_errno),
+            Fd::File(file) => file.read(buf).map_err(io_errno),
+            Fd::Dir { .. } => Err(Errno::Isdir),
+            Fd::Stdout | Fd::Stderr => Err(Errno::Badf),
+        })
+    }
+
+    fn write(&self, fd: i32, buf: &[u8]) -> Result<(), Errno> {
+        self.with_fd(fd, |entry| match entry {
+            Fd::Stdout => std::io::stdout().write_all(buf).map_err(io_errno),
+            Fd::Stderr => std::io::stderr().write_all(buf).map_err(io_errno),
+            Fd::File(file) => file.write_all(buf).map_err(io_errno),
+            Fd::Dir { .. } => Err(Errno::Isdir),
+            Fd::Stdin => Err(Errno::Badf),
+        })
+    }
+
+    /// Move the offset of `fd` from `whence`, the start, the current offset
+    /// or the end, returns the new offset.
+    fn seek(&self, fd: i32, offset: i64, whence: i32) -> Result<u64, Errno> {
+        let pos = match whence {
+            0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(Errno::Inval),
+        };
+        self.with_fd(fd, |entry| match entry {
+            Fd::File(file) => file.seek(pos).map_err(io_errno),
+            _ => Err(Errno::Spipe),
+        })
+    }
+
+    fn close(&self, fd: i32) -> Result<(), Errno> {
+        let mut fds = self.fds.borrow_mut();
+        match fds.get_mut(fd as u32 as usize) {
+            Some(entry @ Some(_)) => {
+                *entry = None;
+                Ok(())
+            }
+            _ => Err(Errno::Badf),
+        }
+    }
+
+    /// The name of the preopened directory `fd`.
+    fn prestat_name(&self, fd: i32) -> Result<String, Errno> {
+        self.with_fd(fd, |entry| match entry {
+            Fd::Dir {
+                guest: Some(guest), ..
+            } => Ok(guest.clone()),
+            _ => Err(Errno::Badf),
+        })
+    }
+
+    /// Open `path` under the directory `dir_fd`, returns the new file
+    /// descriptor, the lowest one free.
+    fn open(
+        &self,
+        dir_fd: i32,
+        path: &str,
+        oflags: i32,
+        rights: i64,
+        fdflags: i32,
+    ) -> Result<i32, Errno> {
+        let dir = self.with_fd(dir_fd, |entry| match entry {
+            Fd::Dir { path, .. } => Ok(path.clone()),
+            _ => Err(Errno::Notdir),
+        })?;
+        let path = resolve(&dir, path)?;
+
+        let write = rights & RIGHTS_FD_WRITE != 0;
+        let entry = if path.is_dir() {
+            if write {
+                return Err(Errno::Isdir);
+            }
+            Fd::Dir { path, guest: None }
+        } else if oflags & OFLAGS_DIRECTORY != 0 {
+            return Err(if path.exists() {
+                Errno::Notdir
+            } else {
+                Errno::Noent
+            });
+        } else {
+            let file = OpenOptions::new()
+                .read(rights & RIGHTS_FD_READ != 0 || !write)
+                .write(write)
+                .append(fdflags & FDFLAGS_APPEND != 0)
+                .create(oflags & OFLAGS_CREAT != 0)
+                .create_new(oflags & OFLAGS_CREAT != 0 && oflags & OFLAGS_EXCL != 0)
+                .truncate(oflags & OFLAGS_TRUNC != 0)
+                // a symlink swapped in since `resolve` is not followed
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&path)
+                .map_err(io_errno)?;
+            Fd::File(file)
+        };
+
+        let mut fds = self.fds.borrow_mut();
+        let fd = match fds.iter().position(Option::is_none) {
+            Some(fd) => fd,
+            None => {
+                fds.push(None);
+                fds.len() - 1
+            }
+        };
+        fds[fd] = Some(entry);
+        Ok(fd as i32)
+    }
+}
+
+/// The host path of the guest `path` relative to the canonical directory
+/// `dir`, which it must not lead out of.
+fn resolve(dir: &Path, path: &str) -> Result<PathBuf, Errno> {
+    let mut resolved = dir.to_path_buf();
+    let mut depth = 0;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => {
+                resolved.push(name);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => {
+                resolved.pop();
+                depth -= 1;
+            }
+            _ => return Err(Errno::Notcapable),
+        }
+    }
+
+    // nor through a symlink: the path returned has none left, a file that
+    // does not exist yet is checked through its directory, and a dangling
+    // symlink is refused as creating the file would follow it
+    let real = match resolved.canonicalize() {
+        Ok(real) => real,
+        Err(_) if resolved.symlink_metadata().is_ok() => return Err(Errno::Notcapable),
+        Err(_) => {
+            let parent = resolved
+                .parent()
+                .map_or(Ok(dir.to_path_buf()), Path::canonicalize)
+                .map_err(io_errno)?;
+            match resolved.file_name() {
+                Some(name) => parent.join(name),
+                None => parent,
+            }
+        }
+    };
+    if real.starts_with(dir) {
+        Ok(real)
+    } else {
+        Err(Errno::Notcapable)
+    }
+}
+
+/// The errnos of WASI preview1 the host functions return.
+#[derive(Debug, Clone, Copy)]
+#[repr(i32)]
+enum Errno {
+    Success = 0,
+    Acces = 2,
+    Badf = 8,
+    Exist = 20,
+    Fault = 21,
+    Inval = 28,
+    Io = 29,
+    Isdir = 31,
+    Noent = 44,
+    Notdir = 54,
+    Spipe = 70,
+    Notcapable = 76,
+}
+
+fn io_errno(e: std::io::Error) -> Errno {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => Errno::Noent,
+        std::io::ErrorKind::PermissionDenied => Errno::Acces,
+        std::io::ErrorKind::AlreadyExists => Errno::Exist,
+        std::io::ErrorKind::InvalidInput => Errno::Inval,
+        std::io::ErrorKind::IsADirectory => Errno::Isdir,
+        std::io::ErrorKind::NotADirectory => Errno::Notdir,
+        // O_NOFOLLOW met a symlink
+        _ if e.raw_os_error() == Some(libc::ELOOP) => Errno::Notcapable,
+        _ => Errno::Io,
+    }
+}
+
+/// The errno of a call, a pointer out of the memory faults.
+fn result_errno(result: Result<Option<()>, Errno>) -> i32 {
+    match result {
+        Ok(written) => errno(written),
+        Err(e) => e as i32,
+    }
+}
+
+/// Define the WASI functions in `linker`, on the host seen through `ctx`.
+pub(crate) fn define_wasi(linker: &mut Linker<'_>, ctx: WasiCtx) -> Result<()> {
+    let state = Rc::new(WasiState::new(ctx)?);
+    let (args, args_sizes) = (state.clone(), state.clone());
+    let (env, env_sizes) = (state.clone(), state.clone());
+    let (time, res, random) = (state.clone(), state.clone(), state.clone());
+    let (read, write, seek, close) = (state.clone(), state.clone(), state.clone(), state.clone());
+    let (prestat, prestat_name, open) = (state.clone(), state.clone(), state);
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "args_get",
+            move |caller: &mut Caller, argv: i32, buf: i32| {
+                strings_get(caller, &args.ctx.args, argv as u32, buf as u32)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "args_sizes_get",
+            move |caller: &mut Caller, argc: i32, buf_size: i32| {
+                strings_sizes_get(caller, &args_sizes.ctx.args, argc as u32, buf_size as u32)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "environ_get",
+            move |caller: &mut Caller, environ: i32, buf: i32| {
+                strings_get(caller, &env.ctx.env, environ as u32, buf as u32)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "environ_sizes_get",
+            move |caller: &mut Caller, count: i32, buf_size: i32| {
+                strings_sizes_get(caller, &env_sizes.ctx.env, count as u32, buf_size as u32)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "clock_time_get",
+            move |caller: &mut Caller, id: i32, _precision: i64, time_ptr: i32| {
+                clock_get(caller, &time.ctx, id, time_ptr as u32, false)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "clock_res_get",
+            move |caller: &mut Caller, id: i32, res_ptr: i32| {
+                clock_get(caller, &res.ctx, id, res_ptr as u32, true)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "random_get",
+            move |caller: &mut Caller, buf: i32, len: i32| {
+                let start = buf as u32 as usize;
+                let Some(buf) = start
+                    .checked_add(len as u32 as usize)
+                    .and_then(|end| caller.memory_mut().get_mut(start..end))
+                else {
+                    return Errno::Fault as i32;
+                };
+                random.fill_random(buf) as i32
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "fd_read",
+            move |caller: &mut Caller, fd: i32, iovs: i32, iovs_len: i32, nread: i32| {
+                fd_read(
+                    caller,
+                    &read,
+                    fd,
+                    iovs as u32,
+                    iovs_len as u32,
+                    nread as u32,
+                )
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "fd_write",
+            move |caller: &mut Caller, fd: i32, iovs: i32, iovs_len: i32, nwritten: i32| {
+                let Some(bytes) = gather_iovs(caller, iovs as u32, iovs_len as u32) else {
+                    return Errno::Fault as i32;
+                };
+                let written = write
+                    .write(fd, &bytes)
+                    .map(|()| write_u32(caller, nwritten as u32, bytes.len() as u32));
+                result_errno(written)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "fd_seek",
+            move |caller: &mut Caller, fd: i32, offset: i64, whence: i32, new_offset: i32| {
+                let moved = seek
+                    .seek(fd, offset, whence)
+                    .map(|pos| write_u64(caller, new_offset as u32, pos));
+                result_errno(moved)
+            },
+        )?
+        .func_wrap(WASI_MODULE, "fd_close", move |fd: i32| {
+            result_errno(close.close(fd).map(Some))
+        })?
+        .func_wrap(
+            WASI_MODULE,
+            "fd_prestat_get",
+            move |caller: &mut Caller, fd: i32, buf: i32| {
+                // the tag of a directory is 0, then the length of its name
+                let written = prestat.prestat_name(fd).map(|name| {
+                    write_u32(caller, buf as u32, 0)?;
+                    write_u32(caller, (buf as u32).checked_add(4)?, name.len() as u32)
+                });
+                result_errno(written)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "fd_prestat_dir_name",
+            move |caller: &mut Caller, fd: i32, path: i32, path_len: i32| {
+                let written = prestat_name.prestat_name(fd).and_then(|name| {
+                    if (path_len as u32 as usize) < name.len() {
+                        return Err(Errno::Inval);
+                    }
+                    Ok(write_bytes(caller, path as u32, name.as_bytes()))
+                });
+                result_errno(written)
+            },
+        )?
+        .func_wrap(
+            WASI_MODULE,
+            "path_open",
+            move |caller: &mut Caller,
+                  dir_fd: i32,
+                  _dirflags: i32,
+                  path: i32,
+                  path_len: i32,
+                  oflags: i32,
+                  rights: i64,
+                  _rights_inheriting: i64,
+                  fdflags: i32,
+                  fd_ptr: i32| {
+                let start = path as u32 as usize;
+                let Some(path) = start
+                    .checked_add(path_len as u32 as usize)
+                    .and_then(|end| caller.memory().get(start..end))
+                    .map(|path| String::from_utf8_lossy(path).into_owned())
+                else {
+                    return Errno::Fault as i32;
+                };
+                let opened = open
+                    .open(dir_fd, &path, oflags, rights, fdflags)
+                    .map(|fd| write_u32(caller, fd_ptr as u32, fd as u32));
+                result_errno(opened)
+            },
+        )?
+        .func_wrap(WASI_MODULE, "proc_exit", |status: i32| -> Result<()> {
+            Err(WasmExit(status).into())
+        })?;
+    Ok(())
+}
+
+/// Read the little endian `u32` at `addr` of the caller's memory.
+fn read_u32(caller: &Caller, addr: u32) -> Option<u32> {
+    let start = addr as usize;
+    let bytes = caller.memory().get(start..start.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn write_u32(caller: &mut Caller, addr: u32, value: u32) -> Option<()> {
+    let start = addr as usize;
+    caller
+        .memory_mut()
+        .get_mut(start..start.checked_add(4)?)?
+        .copy_from_slice(&value.to_le_bytes());
+    Some(())
+}
+
+fn write_u64(caller: &mut Caller, addr: u32, value: u64) -> Option<()> {
+    write_bytes(caller, addr, &value.to_le_bytes())
+}
+
+fn write_bytes(caller: &mut Caller, addr: u32, bytes: &[u8]) -> Option<()> {
+    let start = addr as usize;
+    caller
+        .memory_mut()
+        .get_mut(start..start.checked_add(bytes.len())?)?
+        .copy_from_slice(bytes);
+    Some(())
+}
+
+/// Map a result to the errno, a pointer out of the memory faults.
+fn errno(result: Option<()>) -> i32 {
+    match result {
+        Some(()) => Errno::Success as i32,
+        None => Errno::Fault as i32,
+    }
+}
+
+/// `args_get` and `environ_get`: write a pointer to every string at `ptrs`,
+/// and the strings at `buf`, each ending with a nul.
+fn strings_get(caller: &mut Caller, strings: &[String], ptrs: u32, buf: u32) -> i32 {
+    let mut write = || {
+        let mut addr = buf;
+        for (i, s) in strings.iter().enumerate() {
+            write_u32(caller, ptrs.checked_add(i as u32 * 4)?, addr)?;
+            write_bytes(caller, addr, s.as_bytes())?;
+            addr = addr.checked_add(s.len() as u32)?;
+            write_bytes(caller, addr, &[0])?;
+            addr = addr.checked_add(1)?;
+        }
+        Some(())
+    };
+    errno(write())
+}
+
+/// `args_sizes_get` and `environ_sizes_get`: write the number of strings at
+/// `count` and the size of the buffer they take at `buf_size`.
+fn strings_sizes_get(caller: &mut Caller, strings: &[String], count: u32, buf_size: u32) -> i32 {
+    let size = strings.iter().map(|s| s.len() + 1).sum::<usize>();
+    errno(
+        write_u32(caller, count, strings.len() as u32)
+            .and_then(|()| write_u32(caller, buf_size, size as u32)),
+    )
+}
+
+/// `clock_time_get` and `clock_res_get`: write the time of the clock `id`
+/// at `addr`, or its resolution if `resolution` is set.
+fn clock_get(caller: &mut Caller, ctx: &WasiCtx, id: i32, addr: u32, resolution: bool) -> i32 {
+    // realtime, monotonic, process and thread cputime
+    let clock = match id {
+        0 => libc::CLOCK_REALTIME,
+        1 => libc::CLOCK_MONOTONIC,
+        2 => libc::CLOCK_PROCESS_CPUTIME_ID,
+        3 => libc::CLOCK_THREAD_CPUTIME_ID,
+        _ => return Errno::Inval as i32,
+    };
+    let nanos = match ctx.fixed_clock {
+        Some(_) if resolution => 1,
+        Some(nanos) => nanos,
+        None => {
+            let mut ts = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let read = if resolution {
+                libc::clock_getres
+            } else {
+                libc::clock_gettime
+            };
+            if unsafe { read(clock, &mut ts) } != 0 {
+                return Errno::Inval as i32;
+            }
+            ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+        }
+    };
+    errno(write_u64(caller, addr, nanos))
+}
+
+/// The buffers of the iovec array at `iovs`, each a pointer and a length,
+/// `None` if any of them is out of the memory.
+fn iovs(caller: &Caller, iovs_addr: u32, iovs_len: u32) -> Option<Vec<(usize, usize)>> {
+    let mut bufs = Vec::new();
+    for i in 0..iovs_len {
+        let iov = iovs_addr.checked_add(i.checked_mul(8)?)?;
+        let buf = read_u32(caller, iov)? as usize;
+        let len = read_u32(caller, iov.checked_add(4)?)? as usize;
+        caller.memory().get(buf..buf.checked_add(len)?)?;
+        bufs.push((buf, len));
+    }
+    Some(bufs)
+}
+
+/// Gather the buffers of the iovec array at `iovs`.
+fn gather_iovs(caller: &Caller, iovs_addr: u32, iovs_len: u32) -> Option<Vec<u8>> {
+    let bufs = iovs(caller, iovs_addr, iovs_len)?;
+    Some(
+        bufs.into_iter()
+            .flat_map(|(buf, len)| &caller.memory()[buf..buf + len])
+            .copied()
+            .collect(),
+    )
+}
+
+/// `fd_read(fd, iovs, iovs_len, nread) -> errno`, the buffers are filled in
+/// order until a read comes short.
+fn fd_read(
+    caller: &mut Caller,
+    state: &WasiState,
+    fd: i32,
+    iovs_addr: u32,
+    iovs_len: u32,
+    nread: u32,
+) -> i32 {
+    let Some(bufs) = iovs(caller, iovs_addr, iovs_len) else {
+        return Errno::Fault as i32;
+    };
+    let mut total = 0;
+    for (buf, len) in bufs {
+        match state.read(fd, &mut caller.memory_mut()[buf..buf + len]) {
+            Ok(n) => {
+                total += n;
+                if n < len {
+                    break;
+                }
+            }
+            Err(e) => return e as i32,
+        }
+    }
+    errno(write_u32(caller, nread, total as u32))
+}
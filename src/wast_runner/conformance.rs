@@ -0,0 +1,208 @@
+//! The conformance matrix of a directory of spec testsuite scripts, see
+//! `run_conformance`. The scripts are grouped by proposal the way the spec
+//! testsuite lays them out: the scripts right in the directory test the core
+//! spec, the ones in a subdirectory test the proposal it is named after.
+//!
+//! ```ignore
+//! let report = wasm_interpreter_rs::wast_runner::run_conformance("tests/wast")?;
+//! print!("{}", report.markdown());
+//! ```
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use super::{run_wast_file, Summary};
+use crate::vm::json_string;
+
+/// The proposal of the scripts right in the directory.
+const CORE: &str = "core";
+
+/// The outcome of a script on each backend.
+#[derive(Debug, Clone)]
+pub struct FileConformance {
+    /// relative to the directory of the suite
+    pub path: PathBuf,
+    pub interp: Summary,
+    pub jit: Summary,
+    /// why the script could not be read or parsed, nothing of it ran
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProposalConformance {
+    pub name: String,
+    pub files: Vec<FileConformance>,
+}
+
+impl ProposalConformance {
+    pub fn interp_summary(&self) -> Summary {
+        Summary::total(self.files.iter().map(|file| &file.interp))
+    }
+
+    pub fn jit_summary(&self) -> Summary {
+        Summary::total(self.files.iter().map(|file| &file.jit))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConformanceReport {
+    /// core first, then the proposals by name
+    pub proposals: Vec<ProposalConformance>,
+}
+
+impl ConformanceReport {
+    /// One table per proposal, with a row per script and their total.
+    pub fn markdown(&self) -> String {
+        let mut out = String::from("# Conformance\n");
+        for proposal in &self.proposals {
+            let _ = write!(
+                out,
+                "\n## {}\n\n\
+                 | script | interp passed | interp failed | interp skipped \
+                 | jit passed | jit failed | jit skipped |\n\
+                 |---|---:|---:|---:|---:|---:|---:|\n",
+                proposal.name
+            );
+            for file in &proposal.files {
+                let script = match &file.error {
+                    Some(error) => format!("{} (error: {})", file.path.display(), error),
+                    None => file.path.display().to_string(),
+                };
+                markdown_row(&mut out, &script, &file.interp, &file.jit);
+            }
+            markdown_row(
+                &mut out,
+                "**total**",
+                &proposal.interp_summary(),
+                &proposal.jit_summary(),
+            );
+        }
+        out
+    }
+
+    /// The same as `markdown`, as a single JSON object.
+    pub fn json(&self) -> String {
+        let proposals = self
+            .proposals
+            .iter()
+            .map(|proposal| {
+                let files = proposal
+                    .files
+                    .iter()
+                    .map(|file| {
+                        let error = file
+                            .error
+                            .as_ref()
+                            .map(|error| format!(",\"error\":{}", json_string(error)))
+                            .unwrap_or_default();
+                        format!(
+                            "{{\"script\":{},\"interp\":{},\"jit\":{}{}}}",
+                            json_string(&file.path.display().to_string()),
+                            json_summary(&file.interp),
+                            json_summary(&file.jit),
+                            error
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!(
+                    "{{\"proposal\":{},\"interp\":{},\"jit\":{},\"scripts\":[{}]}}",
+                    json_string(&proposal.name),
+                    json_summary(&proposal.interp_summary()),
+                    json_summary(&proposal.jit_summary()),
+                    files
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{{\"proposals\":[{}]}}", proposals)
+    }
+}
+
+fn markdown_row(out: &mut String, script: &str, interp: &Summary, jit: &Summary) {
+    let _ = writeln!(
+        out,
+        "| {} | {} | {} | {} | {} | {} | {} |",
+        script.replace('|', "\\|"),
+        interp.passed,
+        interp.failed,
+        interp.skipped,
+        jit.passed,
+        jit.failed,
+        jit.skipped
+    );
+}
+
+fn json_summary(summary: &Summary) -> String {
+    format!(
+        "{{\"passed\":{},\"failed\":{},\"skipped\":{}}}",
+        summary.passed, summary.failed, summary.skipped
+    )
+}
+
+/// Run every `.wast` script in `dir` and in its subdirectories, one level
+/// deep, on both backends. A script that cannot be read or parsed is
+/// reported as such, the others still run.
+pub fn run_conformance(dir: impl AsRef<Path>) -> Result<ConformanceReport> {
+    let dir = dir.as_ref();
+    let mut proposals = vec![(CORE.to_string(), dir.to_path_buf())];
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            subdirs.push(path);
+        }
+    }
+    subdirs.sort();
+    proposals.extend(subdirs.into_iter().filter_map(|path| {
+        let name = path.file_name()?.to_str()?.to_string();
+        Some((name, path))
+    }));
+
+    let mut report = ConformanceReport::default();
+    for (name, proposal_dir) in proposals {
+        let files = scripts(&proposal_dir)?
+            .into_iter()
+            .map(|script| run_script(dir, &script))
+            .collect::<Vec<_>>();
+        if !files.is_empty() {
+            report.proposals.push(ProposalConformance { name, files });
+        }
+    }
+    Ok(report)
+}
+
+/// The `.wast` scripts right in `dir`, by name.
+fn scripts(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut scripts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "wast") {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+fn run_script(dir: &Path, script: &Path) -> FileConformance {
+    let path = script.strip_prefix(dir).unwrap_or(script).to_path_buf();
+    match run_wast_file(script) {
+        Ok(report) => FileConformance {
+            path,
+            interp: report.interp_summary(),
+            jit: report.jit_summary(),
+            error: None,
+        },
+        Err(e) => FileConformance {
+            path,
+            interp: Summary::default(),
+            jit: Summary::default(),
+            error: Some(e.to_string()),
+        },
+    }
+}
@@ -0,0 +1,330 @@
+//! Running jit code for the wast runner. A trap of the jit code comes back as
+//! an error, but a fault the trap handler does not own still terminates the
+//! process, so every module instantiated with the jit lives in a forked
+//! child. The runner sends it the invocations and the global reads of the
+//! script through a pipe and gets their results back through another, and
+//! the child keeps the state of its instance from one request to the next.
+//!
+//! The module is parsed and linked before the fork, so the child starts
+//! right away with instantiating it. A child that dies, whether killed or
+//! exiting, fails the directive it was running.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use super::{spectest_config, InvokeOutcome};
+use crate::{
+    module::{value_type::WasmValue, wasm_module::WasmModule},
+    vm::{Linker, RuntimeError, WasmInterpreter, WasmInterpreterConfig},
+};
+
+/// a runaway request is killed after this many seconds
+const JIT_CHILD_TIMEOUT_SECS: u32 = 5;
+
+const TAG_RETURNED: u8 = b'R';
+const TAG_TRAP: u8 = b'T';
+const TAG_ERROR: u8 = b'E';
+const TAG_PANIC: u8 = b'P';
+
+const REQUEST_INVOKE: u8 = b'I';
+const REQUEST_GET: u8 = b'G';
+
+/// A module instantiated with the jit in a child process. Its imports from
+/// `registered` instances run on the child's copy of those instances, as
+/// they were when the child was forked.
+pub(crate) struct JitChild {
+    pid: libc::pid_t,
+    requests: i32,
+    replies: i32,
+}
+
+impl JitChild {
+    /// Fork a child instantiating `binary` with the jit, returns it with
+    /// the outcome of the instantiation, the child is gone if that failed.
+    pub(crate) fn spawn<'a>(
+        binary: &'a [u8],
+        registered: &Linker<'a>,
+    ) -> (Option<Self>, InvokeOutcome) {
+        let prepared = catch_unwind(AssertUnwindSafe(|| {
+            let module = WasmModule::from_bytecode(binary)?;
+            Ok::<_, anyhow::Error>((module, spectest_config(true, registered)?))
+        }));
+        let (module, config) = match prepared {
+            Ok(Ok(prepared)) => prepared,
+            Ok(Err(e)) => return (None, InvokeOutcome::Crashed(e.to_string())),
+            Err(_) => return (None, crashed("panicked")),
+        };
+
+        let mut requests = [0; 2];
+        let mut replies = [0; 2];
+        unsafe {
+            // the runner's later children must not hold this one's pipes
+            if libc::pipe2(requests.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+                return (None, crashed("failed to create a pipe"));
+            }
+            if libc::pipe2(replies.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+                close_all(&requests);
+                return (None, crashed("failed to create a pipe"));
+            }
+            let pid = libc::fork();
+            if pid < 0 {
+                close_all(&requests);
+                close_all(&replies);
+                return (None, crashed("failed to fork"));
+            }
+            if pid == 0 {
+                libc::close(requests[1]);
+                libc::close(replies[0]);
+                // keep whatever the module prints out of the report
+                let devnull = libc::open(c"/dev/null".as_ptr(), libc::O_WRONLY);
+                libc::dup2(devnull, libc::STDOUT_FILENO);
+                serve(module, &config, requests[0], replies[1]);
+            }
+
+            libc::close(requests[0]);
+            libc::close(replies[1]);
+            let mut child = Self {
+                pid,
+                requests: requests[1],
+                replies: replies[0],
+            };
+            match child.reply() {
+                InvokeOutcome::Returned(_) => (Some(child), InvokeOutcome::Returned(vec![])),
+                outcome => (None, outcome),
+            }
+        }
+    }
+
+    /// Call the export `field` of the child's instance.
+    pub(crate) fn invoke(&mut self, field: &str, args: &[WasmValue]) -> InvokeOutcome {
+        let mut request = vec![REQUEST_INVOKE];
+        encode_str(field, &mut request);
+        for arg in args {
+            encode_value(arg, &mut request);
+        }
+        self.request(&request)
+    }
+
+    /// Read the exported global `name` of the child's instance.
+    pub(crate) fn get(&mut self, name: &str) -> InvokeOutcome {
+        let mut request = vec![REQUEST_GET];
+        encode_str(name, &mut request);
+        self.request(&request)
+    }
+
+    fn request(&mut self, request: &[u8]) -> InvokeOutcome {
+        // a child that died is left for `reply` to report
+        unsafe { write_message(self.requests, request) };
+        self.reply()
+    }
+
+    /// Read the reply to the last request, or why the child died before it
+    /// sent one.
+    fn reply(&mut self) -> InvokeOutcome {
+        if let Some(message) = unsafe { read_message(self.replies) } {
+            return decode(&message);
+        }
+        let mut status = 0;
+        unsafe { libc::waitpid(self.pid, &mut status, 0) };
+        self.pid = 0;
+        if libc::WIFSIGNALED(status) {
+            return match libc::WTERMSIG(status) {
+                libc::SIGALRM => crashed("timed out"),
+                sig => crashed(&format!("killed by signal {}", sig)),
+            };
+        }
+        crashed(&format!("exited with status {}", libc::WEXITSTATUS(status)))
+    }
+
+    /// Whether the child died, the next request needs a new one.
+    pub(crate) fn is_dead(&self) -> bool {
+        self.pid == 0
+    }
+}
+
+impl Drop for JitChild {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.requests);
+            libc::close(self.replies);
+            if self.pid != 0 {
+                libc::kill(self.pid, libc::SIGKILL);
+                libc::waitpid(self.pid, std::ptr::null_mut(), 0);
+            }
+        }
+    }
+}
+
+/// The loop of the child, runs the requests until the runner goes away.
+fn serve(
+    module: WasmModule<'_>,
+    config: &WasmInterpreterConfig<'_>,
+    requests: i32,
+    replies: i32,
+) -> ! {
+    unsafe { libc::alarm(JIT_CHILD_TIMEOUT_SECS) };
+    let instance = catch_unwind(AssertUnwindSafe(|| {
+        WasmInterpreter::with_config(module, config).map_err(RuntimeError::from)
+    }));
+    unsafe { libc::alarm(0) };
+    let vm = match instance {
+        Ok(Ok(vm)) => {
+            unsafe { write_message(replies, &[TAG_RETURNED]) };
+            vm
+        }
+        Ok(Err(e)) => unsafe {
+            write_message(replies, &encode_result(Ok(Err(e))));
+            libc::_exit(0)
+        },
+        Err(_) => unsafe {
+            write_message(replies, &[TAG_PANIC]);
+            libc::_exit(0)
+        },
+    };
+
+    while let Some(request) = unsafe { read_message(requests) } {
+        unsafe { libc::alarm(JIT_CHILD_TIMEOUT_SECS) };
+        let result = catch_unwind(AssertUnwindSafe(|| run_request(&vm, &request)));
+        unsafe {
+            libc::alarm(0);
+            write_message(replies, &encode_result(result));
+        }
+    }
+    unsafe { libc::_exit(0) }
+}
+
+fn run_request(vm: &WasmInterpreter, request: &[u8]) -> Result<Vec<WasmValue>, RuntimeError> {
+    let (name, rest) = decode_str(&request[1..]);
+    match request[0] {
+        REQUEST_INVOKE => vm.invoke(&name, decode_values(rest)),
+        _ => Ok(vec![vm.get_global(&name)?]),
+    }
+}
+
+fn encode_result(result: std::thread::Result<Result<Vec<WasmValue>, RuntimeError>>) -> Vec<u8> {
+    match result {
+        Ok(Ok(values)) => {
+            let mut message = vec![TAG_RETURNED];
+            for value in values {
+                encode_value(&value, &mut message);
+            }
+            message
+        }
+        Ok(Err(RuntimeError::Trap(kind, _))) => {
+            let mut message = vec![TAG_TRAP];
+            message.extend_from_slice(kind.to_string().as_bytes());
+            message
+        }
+        Ok(Err(e)) => {
+            let mut message = vec![TAG_ERROR];
+            message.extend_from_slice(e.to_string().as_bytes());
+            message
+        }
+        Err(_) => vec![TAG_PANIC],
+    }
+}
+
+fn crashed(reason: &str) -> InvokeOutcome {
+    InvokeOutcome::Crashed(reason.to_string())
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn decode_str(bytes: &[u8]) -> (String, &[u8]) {
+    let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+    let s = String::from_utf8_lossy(&bytes[4..4 + len]).to_string();
+    (s, &bytes[4 + len..])
+}
+
+/// a value is sent as its type tag followed by 16 bytes of payload
+fn encode_value(value: &WasmValue, out: &mut Vec<u8>) {
+    let (tag, payload) = match value {
+        WasmValue::V128(v) => (6, *v),
+        WasmValue::I32(_) => (0, value.to_raw() as u128),
+        WasmValue::I64(_) => (1, value.to_raw() as u128),
+        WasmValue::F32(_) => (2, value.to_raw() as u128),
+        WasmValue::F64(_) => (3, value.to_raw() as u128),
+        WasmValue::FuncRef(_) => (4, value.to_raw() as u128),
+        WasmValue::ExternRef(_) => (5, value.to_raw() as u128),
+    };
+    out.push(tag);
+    out.extend_from_slice(&payload.to_le_bytes());
+}
+
+fn decode_values(bytes: &[u8]) -> Vec<WasmValue> {
+    bytes
+        .chunks(17)
+        .map(|chunk| {
+            let payload = u128::from_le_bytes(chunk[1..].try_into().unwrap());
+            let raw = payload as u64;
+            match chunk[0] {
+                0 => WasmValue::I32(raw as i32),
+                1 => WasmValue::I64(raw as i64),
+                2 => WasmValue::F32(f32::from_bits(raw as u32)),
+                3 => WasmValue::F64(f64::from_bits(raw)),
+                4 => WasmValue::FuncRef((raw as u32).checked_sub(1)),
+                5 => WasmValue::ExternRef((raw as u32).checked_sub(1)),
+                _ => WasmValue::V128(payload),
+            }
+        })
+        .collect()
+}
+
+fn decode(message: &[u8]) -> InvokeOutcome {
+    match message.split_first() {
+        Some((&TAG_TRAP, rest)) => {
+            InvokeOutcome::Trapped(String::from_utf8_lossy(rest).to_string())
+        }
+        Some((&TAG_ERROR, rest)) => {
+            InvokeOutcome::Crashed(String::from_utf8_lossy(rest).to_string())
+        }
+        Some((&TAG_RETURNED, rest)) => InvokeOutcome::Returned(decode_values(rest)),
+        Some((&TAG_PANIC, _)) => crashed("panicked"),
+        _ => crashed("malformed reply from the jit child"),
+    }
+}
+
+unsafe fn close_all(fds: &[i32]) {
+    for fd in fds {
+        libc::close(*fd);
+    }
+}
+
+/// A message is its length followed by its bytes, returns false if the
+/// other end is gone.
+unsafe fn write_message(fd: i32, message: &[u8]) -> bool {
+    write_all(fd, &(message.len() as u32).to_le_bytes()) && write_all(fd, message)
+}
+
+/// Returns `None` if the other end went away before a whole message came.
+unsafe fn read_message(fd: i32) -> Option<Vec<u8>> {
+    let mut len = [0u8; 4];
+    read_exact(fd, &mut len)?;
+    let mut message = vec![0u8; u32::from_le_bytes(len) as usize];
+    read_exact(fd, &mut message)?;
+    Some(message)
+}
+
+unsafe fn write_all(fd: i32, mut buf: &[u8]) -> bool {
+    while !buf.is_empty() {
+        let n = libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len());
+        if n <= 0 {
+            return false;
+        }
+        buf = &buf[n as usize..];
+    }
+    true
+}
+
+unsafe fn read_exact(fd: i32, mut buf: &mut [u8]) -> Option<()> {
+    while !buf.is_empty() {
+        let n = libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+        if n <= 0 {
+            return None;
+        }
+        buf = &mut buf[n as usize..];
+    }
+    Some(())
+}
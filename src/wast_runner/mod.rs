@@ -0,0 +1,806 @@
+//! Runner for the spec testsuite `.wast` scripts. Every directive of a script
+//! is executed against the interpreter and the jit compiler and reported as
+//! passed, failed or skipped for each of them.
+//!
+//! ```ignore
+//! let report = wasm_interpreter_rs::wast_runner::run_wast_file("i32.wast")?;
+//! for directive in &report.directives {
+//!     println!("{}", directive);
+//! }
+//! ```
+//!
+//! Modules are instantiated once for each of them, so the state left by one
+//! invocation is seen by the next. The jit instance runs in a child process,
+//! see `jit_child`, which is reaped once a later module replaces its module
+//! as the current one, unless the script can still name it.
+//!
+//! The conformance matrix of a whole suite, per proposal, is built from the
+//! reports of its scripts, see `conformance`.
+
+mod conformance;
+mod jit_child;
+mod value;
+
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+    rc::Rc,
+    sync::Once,
+};
+
+use anyhow::{anyhow, Result};
+use wast::{
+    parser::{self, ParseBuffer},
+    QuoteWat, Wast, WastDirective, WastExecute, WastInvoke, Wat,
+};
+
+pub use self::conformance::{
+    run_conformance, ConformanceReport, FileConformance, ProposalConformance,
+};
+use self::{
+    jit_child::JitChild,
+    value::{lower_arg, lower_ret, ExpectedValue},
+};
+use crate::{
+    module::{value_type::WasmValue, wasm_module::WasmModule},
+    vm::{Linker, RuntimeError, TrapKind, WasmInterpreter, WasmInterpreterConfig},
+};
+
+/// The result of a directive on one backend.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectiveReport {
+    /// 1-based line of the directive in the script
+    pub line: usize,
+    pub kind: &'static str,
+    pub interp: Outcome,
+    pub jit: Outcome,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WastReport {
+    pub directives: Vec<DirectiveReport>,
+}
+
+impl WastReport {
+    pub fn interp_summary(&self) -> Summary {
+        Self::summarize(self.directives.iter().map(|d| &d.interp))
+    }
+
+    pub fn jit_summary(&self) -> Summary {
+        Self::summarize(self.directives.iter().map(|d| &d.jit))
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.interp_summary().failed > 0 || self.jit_summary().failed > 0
+    }
+
+    fn summarize<'a>(outcomes: impl Iterator<Item = &'a Outcome>) -> Summary {
+        let mut summary = Summary::default();
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Pass => summary.passed += 1,
+                Outcome::Fail(_) => summary.failed += 1,
+                Outcome::Skip(_) => summary.skipped += 1,
+            }
+        }
+        summary
+    }
+}
+
+impl Summary {
+    /// The sum of `summaries`.
+    fn total<'a>(summaries: impl Iterator<Item = &'a Summary>) -> Summary {
+        let mut total = Summary::default();
+        for summary in summaries {
+            total.passed += summary.passed;
+            total.failed += summary.failed;
+            total.skipped += summary.skipped;
+        }
+        total
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Fail(reason) => write!(f, "fail ({})", reason),
+            Outcome::Skip(reason) => write!(f, "skip ({})", reason),
+        }
+    }
+}
+
+impl std::fmt::Display for DirectiveReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} interp: {}, jit: {}",
+            self.line, self.kind, self.interp, self.jit
+        )
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} passed, {} failed, {} skipped",
+            self.passed, self.failed, self.skipped
+        )
+    }
+}
+
+/// Parse and run the `.wast` script at `path`.
+pub fn run_wast_file(path: impl AsRef<Path>) -> Result<WastReport> {
+    let text = std::fs::read_to_string(path)?;
+    run_wast(&text)
+}
+
+/// Parse and run a `.wast` script.
+pub fn run_wast(text: &str) -> Result<WastReport> {
+    let commands = lower_script(text)?;
+
+    // traps that are reported as panics would flood stderr otherwise
+    let _quiet = QuietPanics::new();
+    let report = WastRunner::default().run(&commands);
+
+    Ok(report)
+}
+
+thread_local! {
+    static QUIET_PANICS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps the panics of the current thread, which the runner catches and
+/// reports, off stderr until it is dropped. The panic hook is only wrapped
+/// once, the other threads still report their panics through it.
+struct QuietPanics {
+    was_quiet: bool,
+}
+
+impl QuietPanics {
+    fn new() -> Self {
+        static WRAP_HOOK: Once = Once::new();
+        WRAP_HOOK.call_once(|| {
+            let hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                if !QUIET_PANICS.with(Cell::get) {
+                    hook(info);
+                }
+            }));
+        });
+        Self {
+            was_quiet: QUIET_PANICS.with(|quiet| quiet.replace(true)),
+        }
+    }
+}
+
+impl Drop for QuietPanics {
+    fn drop(&mut self) {
+        QUIET_PANICS.with(|quiet| quiet.set(self.was_quiet));
+    }
+}
+
+/// A directive with everything borrowed from the script text resolved, so
+/// that instances can borrow the module binaries while the script runs.
+enum Command {
+    Module {
+        line: usize,
+        name: Option<String>,
+        binary: Result<Vec<u8>, String>,
+    },
+    Register {
+        line: usize,
+        as_name: String,
+        module: Option<String>,
+    },
+    Action {
+        line: usize,
+        kind: &'static str,
+        invoke: Invoke,
+        expect: Expectation,
+    },
+    /// `(get "g")`, read an exported global
+    Get {
+        line: usize,
+        module: Option<String>,
+        global: String,
+        expect: Expectation,
+    },
+    AssertRejected {
+        line: usize,
+        kind: &'static str,
+        binary: Result<Vec<u8>, String>,
+    },
+    /// `(assert_trap (module ...))`, the instantiation traps
+    AssertInstantiationTrap {
+        line: usize,
+        binary: Result<Vec<u8>, String>,
+        message: String,
+    },
+    AssertUnlinkable {
+        line: usize,
+        binary: Result<Vec<u8>, String>,
+        message: String,
+    },
+    Unsupported {
+        line: usize,
+        kind: &'static str,
+        reason: String,
+    },
+}
+
+struct Invoke {
+    module: Option<String>,
+    field: String,
+    args: Vec<WasmValue>,
+}
+
+enum InvokeOutcome {
+    Returned(Vec<WasmValue>),
+    /// the trap message
+    Trapped(String),
+    /// the invocation failed without trapping, panicked, or the jit child
+    /// died
+    Crashed(String),
+}
+
+enum Expectation {
+    /// a plain `invoke`, anything but a trap passes
+    Complete,
+    Return(Vec<ExpectedValue>),
+    Trap(String),
+    /// an exception is thrown out of the invocation
+    Exception,
+}
+
+fn lower_script(text: &str) -> Result<Vec<Command>> {
+    let buf = ParseBuffer::new(text).map_err(|e| anyhow!("{}", e))?;
+    let wast = parser::parse::<Wast>(&buf).map_err(|e| anyhow!("{}", e))?;
+
+    let commands = wast
+        .directives
+        .into_iter()
+        .map(|directive| {
+            let line = directive.span().linecol_in(text).0 + 1;
+            lower_directive(directive, line)
+        })
+        .collect();
+    Ok(commands)
+}
+
+fn lower_directive(directive: WastDirective, line: usize) -> Command {
+    let unsupported = |kind, reason: &str| Command::Unsupported {
+        line,
+        kind,
+        reason: reason.to_string(),
+    };
+
+    match directive {
+        WastDirective::Module(mut module) => {
+            if is_component(&module) {
+                return unsupported("module", "components");
+            }
+            Command::Module {
+                line,
+                name: module_name(&module),
+                binary: module.encode().map_err(|e| e.to_string()),
+            }
+        }
+        WastDirective::Register { name, module, .. } => Command::Register {
+            line,
+            as_name: name.to_string(),
+            module: module.map(|id| id.name().to_string()),
+        },
+        WastDirective::Invoke(invoke) => {
+            lower_action(line, "invoke", invoke, Ok(Expectation::Complete))
+        }
+        WastDirective::AssertReturn { exec, results, .. } => {
+            let expect = results
+                .iter()
+                .map(lower_ret)
+                .collect::<Result<Vec<_>, _>>()
+                .map(Expectation::Return);
+            match exec {
+                WastExecute::Invoke(invoke) => lower_action(line, "assert_return", invoke, expect),
+                WastExecute::Get { module, global, .. } => match expect {
+                    Ok(expect) => Command::Get {
+                        line,
+                        module: module.map(|id| id.name().to_string()),
+                        global: global.to_string(),
+                        expect,
+                    },
+                    Err(reason) => Command::Unsupported {
+                        line,
+                        kind: "assert_return",
+                        reason,
+                    },
+                },
+                WastExecute::Wat(_) => {
+                    unsupported("assert_return", "only invoke and get are supported")
+                }
+            }
+        }
+        WastDirective::AssertTrap { exec, message, .. } => match exec {
+            WastExecute::Invoke(invoke) => lower_action(
+                line,
+                "assert_trap",
+                invoke,
+                Ok(Expectation::Trap(message.to_string())),
+            ),
+            WastExecute::Wat(mut module) => Command::AssertInstantiationTrap {
+                line,
+                binary: module.encode().map_err(|e| e.to_string()),
+                message: message.to_string(),
+            },
+            WastExecute::Get { .. } => unsupported("assert_trap", "reading a global cannot trap"),
+        },
+        WastDirective::AssertInvalid { mut module, .. } => Command::AssertRejected {
+            line,
+            kind: "assert_invalid",
+            binary: module.encode().map_err(|e| e.to_string()),
+        },
+        WastDirective::AssertMalformed { mut module, .. } => Command::AssertRejected {
+            line,
+            kind: "assert_malformed",
+            binary: module.encode().map_err(|e| e.to_string()),
+        },
+        WastDirective::ModuleDefinition(_) => unsupported("module definition", "not supported"),
+        WastDirective::ModuleInstance { .. } => unsupported("module instance", "not supported"),
+        // running out of stack is a trap like any other
+        WastDirective::AssertExhaustion { call, message, .. } => lower_action(
+            line,
+            "assert_exhaustion",
+            call,
+            Ok(Expectation::Trap(message.to_string())),
+        ),
+        WastDirective::AssertUnlinkable {
+            mut module,
+            message,
+            ..
+        } => Command::AssertUnlinkable {
+            line,
+            binary: module.encode().map_err(|e| e.to_string()),
+            message: message.to_string(),
+        },
+        WastDirective::AssertException { exec, .. } => match exec {
+            WastExecute::Invoke(invoke) => {
+                lower_action(line, "assert_exception", invoke, Ok(Expectation::Exception))
+            }
+            _ => unsupported("assert_exception", "only invoke is supported"),
+        },
+        WastDirective::AssertSuspension { .. } => unsupported("assert_suspension", "not supported"),
+        WastDirective::Thread(_) => unsupported("thread", "not supported"),
+        WastDirective::Wait { .. } => unsupported("wait", "not supported"),
+    }
+}
+
+fn lower_action(
+    line: usize,
+    kind: &'static str,
+    invoke: WastInvoke,
+    expect: Result<Expectation, String>,
+) -> Command {
+    let args = invoke
+        .args
+        .iter()
+        .map(lower_arg)
+        .collect::<Result<Vec<_>, _>>();
+    match (args, expect) {
+        (Ok(args), Ok(expect)) => Command::Action {
+            line,
+            kind,
+            invoke: Invoke {
+                module: invoke.module.map(|id| id.name().to_string()),
+                field: invoke.name.to_string(),
+                args,
+            },
+            expect,
+        },
+        (Err(reason), _) | (_, Err(reason)) => Command::Unsupported { line, kind, reason },
+    }
+}
+
+fn is_component(module: &QuoteWat) -> bool {
+    matches!(
+        module,
+        QuoteWat::Wat(Wat::Component(_)) | QuoteWat::QuoteComponent(..)
+    )
+}
+
+fn module_name(module: &QuoteWat) -> Option<String> {
+    match module {
+        QuoteWat::Wat(Wat::Module(m)) => m.id.map(|id| id.name().to_string()),
+        _ => None,
+    }
+}
+
+/// A module instantiated by the script, the jit instantiates its own copy
+/// from `binary` in a child. The binary is borrowed from the script's
+/// commands, which outlive every instance.
+struct Instance<'c> {
+    binary: &'c [u8],
+    interp: Rc<WasmInterpreter<'c>>,
+    /// `None` once the child died or if the jit failed to instantiate
+    jit: Option<JitChild>,
+}
+
+#[derive(Default)]
+struct WastRunner<'c> {
+    instances: Vec<Instance<'c>>,
+    current: Option<usize>,
+    named: HashMap<String, usize>,
+    /// the exports of the instances named by `register`, the modules
+    /// instantiated later can import them
+    registered: Linker<'c>,
+}
+
+impl<'c> WastRunner<'c> {
+    fn run(mut self, commands: &'c [Command]) -> WastReport {
+        let directives = commands.iter().map(|c| self.run_command(c)).collect();
+        WastReport { directives }
+    }
+
+    fn run_command(&mut self, command: &'c Command) -> DirectiveReport {
+        match command {
+            Command::Module { line, name, binary } => {
+                let (interp, jit) = self.instantiate(name, binary);
+                report(*line, "module", interp, jit)
+            }
+            Command::Register {
+                line,
+                as_name,
+                module,
+            } => {
+                let outcome = match self.lookup(module).and_then(|index| {
+                    self.registered
+                        .instance(as_name, &self.instances[index].interp)
+                        .map_err(|e| e.to_string())
+                }) {
+                    Ok(_) => Outcome::Pass,
+                    Err(reason) => Outcome::Fail(reason),
+                };
+                report(*line, "register", outcome.clone(), outcome)
+            }
+            Command::Action {
+                line,
+                kind,
+                invoke,
+                expect,
+            } => match self.lookup(&invoke.module) {
+                Ok(index) => {
+                    let interp =
+                        check(expect, interp_invoke(&self.instances[index].interp, invoke));
+                    let jit =
+                        self.jit_request(index, |child| child.invoke(&invoke.field, &invoke.args));
+                    report(*line, kind, interp, check(expect, jit))
+                }
+                Err(reason) => report(
+                    *line,
+                    kind,
+                    Outcome::Fail(reason.clone()),
+                    Outcome::Fail(reason),
+                ),
+            },
+            Command::Get {
+                line,
+                module,
+                global,
+                expect,
+            } => match self.lookup(module) {
+                Ok(index) => {
+                    let outcome = match self.instances[index].interp.get_global(global) {
+                        Ok(value) => InvokeOutcome::Returned(vec![value]),
+                        Err(e) => InvokeOutcome::Crashed(e.to_string()),
+                    };
+                    let jit = self.jit_request(index, |child| child.get(global));
+                    report(
+                        *line,
+                        "assert_return",
+                        check(expect, outcome),
+                        check(expect, jit),
+                    )
+                }
+                Err(reason) => report(
+                    *line,
+                    "assert_return",
+                    Outcome::Fail(reason.clone()),
+                    Outcome::Fail(reason),
+                ),
+            },
+            Command::AssertRejected { line, kind, binary } => {
+                let outcome = match binary {
+                    // the text could not even be encoded
+                    Err(_) => Outcome::Pass,
+                    Ok(binary) => {
+                        match catch_unwind(|| WasmModule::from_bytecode(binary).is_err()) {
+                            Ok(true) => Outcome::Pass,
+                            Ok(false) => Outcome::Fail("module was accepted".to_string()),
+                            Err(_) => Outcome::Fail("panicked while parsing".to_string()),
+                        }
+                    }
+                };
+                report(*line, kind, outcome.clone(), outcome)
+            }
+            Command::AssertUnlinkable {
+                line,
+                binary,
+                message,
+            } => match binary {
+                Err(e) => {
+                    let outcome = Outcome::Fail(format!("failed to encode module: {}", e));
+                    report(*line, "assert_unlinkable", outcome.clone(), outcome)
+                }
+                // linking runs no code, the jit is checked in the runner
+                Ok(binary) => report(
+                    *line,
+                    "assert_unlinkable",
+                    unlinkable(binary, message, false, &self.registered),
+                    unlinkable(binary, message, true, &self.registered),
+                ),
+            },
+            Command::AssertInstantiationTrap {
+                line,
+                binary,
+                message,
+            } => match binary {
+                Err(e) => {
+                    let outcome = Outcome::Fail(format!("failed to encode module: {}", e));
+                    report(*line, "assert_trap", outcome.clone(), outcome)
+                }
+                Ok(binary) => {
+                    let expect = Expectation::Trap(message.clone());
+                    let interp = check(&expect, interp_instantiate(binary, &self.registered));
+                    let jit = check(&expect, JitChild::spawn(binary, &self.registered).1);
+                    report(*line, "assert_trap", interp, jit)
+                }
+            },
+            Command::Unsupported { line, kind, reason } => report(
+                *line,
+                kind,
+                Outcome::Skip(reason.clone()),
+                Outcome::Skip(reason.clone()),
+            ),
+        }
+    }
+
+    /// Instantiate the module with the interpreter and with the jit, returns
+    /// the outcome of both.
+    fn instantiate(
+        &mut self,
+        name: &Option<String>,
+        binary: &'c Result<Vec<u8>, String>,
+    ) -> (Outcome, Outcome) {
+        let binary = match binary {
+            Ok(binary) => binary.as_slice(),
+            Err(e) => {
+                let outcome = Outcome::Fail(format!("failed to encode module: {}", e));
+                return (outcome.clone(), outcome);
+            }
+        };
+
+        let interp = catch_unwind(AssertUnwindSafe(|| -> Result<WasmInterpreter<'c>> {
+            let module = WasmModule::from_bytecode(binary)?;
+            spectest_instance(module, false, &self.registered)
+        }));
+        let interp = match interp {
+            Ok(Ok(interp)) => interp,
+            Ok(Err(e)) => {
+                let outcome = Outcome::Fail(format!("failed to instantiate module: {}", e));
+                return (outcome.clone(), outcome);
+            }
+            Err(_) => {
+                let outcome = Outcome::Fail("panicked while instantiating module".to_string());
+                return (outcome.clone(), outcome);
+            }
+        };
+
+        let (jit, jit_outcome) = JitChild::spawn(binary, &self.registered);
+        let jit_outcome = match jit_outcome {
+            InvokeOutcome::Returned(_) => Outcome::Pass,
+            InvokeOutcome::Trapped(reason) | InvokeOutcome::Crashed(reason) => {
+                Outcome::Fail(format!("failed to instantiate module: {}", reason))
+            }
+        };
+
+        // the script cannot name the module it replaces any more, unless it
+        // has a name
+        if let Some(current) = self.current {
+            if !self.named.values().any(|&index| index == current) {
+                self.instances[current].jit = None;
+            }
+        }
+        let index = self.instances.len();
+        self.instances.push(Instance {
+            binary,
+            interp: Rc::new(interp),
+            jit,
+        });
+        self.current = Some(index);
+        if let Some(name) = name {
+            self.named.insert(name.clone(), index);
+        }
+        (Outcome::Pass, jit_outcome)
+    }
+
+    /// Send a request to the jit instance of `index`. A child that died,
+    /// e.g. of a fault the trap handler does not own, is replaced by a
+    /// freshly instantiated one, which starts over from the module.
+    fn jit_request(
+        &mut self,
+        index: usize,
+        request: impl FnOnce(&mut JitChild) -> InvokeOutcome,
+    ) -> InvokeOutcome {
+        let instance = &mut self.instances[index];
+        if instance.jit.as_ref().is_none_or(JitChild::is_dead) {
+            let (jit, outcome) = JitChild::spawn(instance.binary, &self.registered);
+            instance.jit = jit;
+            if instance.jit.is_none() {
+                return outcome;
+            }
+        }
+        request(instance.jit.as_mut().unwrap())
+    }
+
+    fn lookup(&self, module: &Option<String>) -> Result<usize, String> {
+        match module {
+            Some(name) => self
+                .named
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unknown module ${}", name)),
+            None => self
+                .current
+                .ok_or_else(|| "no module instantiated".to_string()),
+        }
+    }
+}
+
+/// Instantiate `module` with the exports of the `registered` instances and
+/// the imports of the `spectest` module of the testsuite, its globals, memory
+/// and table are provided. Every instance gets a fresh spectest memory and
+/// table.
+fn spectest_instance<'a>(
+    module: WasmModule<'a>,
+    jit_mode: bool,
+    registered: &Linker<'a>,
+) -> Result<WasmInterpreter<'a>> {
+    let config = spectest_config(jit_mode, registered)?;
+    WasmInterpreter::with_config(module, &config)
+}
+
+/// The configuration `spectest_instance` instantiates with.
+fn spectest_config<'a>(
+    jit_mode: bool,
+    registered: &Linker<'a>,
+) -> Result<WasmInterpreterConfig<'a>> {
+    let mut linker = registered.clone();
+    linker.spectest()?;
+    Ok(WasmInterpreterConfig::new().jit(jit_mode).linker(linker))
+}
+
+/// Imports are resolved when the module is instantiated, before any code
+/// runs.
+fn unlinkable(binary: &[u8], message: &str, jit_mode: bool, registered: &Linker<'_>) -> Outcome {
+    let result = catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+        let module = WasmModule::from_bytecode(binary)?;
+        spectest_instance(module, jit_mode, registered)?;
+        Ok(())
+    }));
+    match result {
+        Ok(Ok(())) => Outcome::Fail("module was linked".to_string()),
+        Ok(Err(e)) if e.to_string().contains(message) => Outcome::Pass,
+        Ok(Err(e)) => Outcome::Fail(format!("expected \"{}\", got \"{}\"", message, e)),
+        Err(_) => Outcome::Fail("panicked while instantiating module".to_string()),
+    }
+}
+
+fn report(line: usize, kind: &'static str, interp: Outcome, jit: Outcome) -> DirectiveReport {
+    DirectiveReport {
+        line,
+        kind,
+        interp,
+        jit,
+    }
+}
+
+fn interp_invoke(interp: &WasmInterpreter, invoke: &Invoke) -> InvokeOutcome {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        interp.invoke(&invoke.field, invoke.args.clone())
+    }));
+    match result {
+        Ok(Ok(values)) => InvokeOutcome::Returned(values),
+        Ok(Err(RuntimeError::Trap(kind, _))) => InvokeOutcome::Trapped(kind.to_string()),
+        Ok(Err(e)) => InvokeOutcome::Crashed(e.to_string()),
+        Err(_) => InvokeOutcome::Crashed("panicked".to_string()),
+    }
+}
+
+/// Instantiate `binary` with the interpreter, the instance is dropped.
+fn interp_instantiate(binary: &[u8], registered: &Linker<'_>) -> InvokeOutcome {
+    let result = catch_unwind(AssertUnwindSafe(|| -> Result<(), RuntimeError> {
+        let module = WasmModule::from_bytecode(binary).map_err(RuntimeError::Other)?;
+        spectest_instance(module, false, registered)?;
+        Ok(())
+    }));
+    match result {
+        Ok(Ok(())) => InvokeOutcome::Returned(vec![]),
+        Ok(Err(RuntimeError::Trap(kind, _))) => InvokeOutcome::Trapped(kind.to_string()),
+        Ok(Err(e)) => InvokeOutcome::Crashed(e.to_string()),
+        Err(_) => InvokeOutcome::Crashed("panicked".to_string()),
+    }
+}
+
+fn check(expect: &Expectation, outcome: InvokeOutcome) -> Outcome {
+    match (expect, outcome) {
+        (_, InvokeOutcome::Crashed(reason)) => Outcome::Fail(reason),
+        (Expectation::Complete, InvokeOutcome::Returned(_)) => Outcome::Pass,
+        (Expectation::Complete, InvokeOutcome::Trapped(e)) => {
+            Outcome::Fail(format!("trapped: {}", e))
+        }
+        (Expectation::Return(expected), InvokeOutcome::Returned(values)) => {
+            let matches = expected.len() == values.len()
+                && expected.iter().zip(&values).all(|(e, v)| e.matches(v));
+            if matches {
+                Outcome::Pass
+            } else {
+                Outcome::Fail(format!(
+                    "expected [{}], got [{}]",
+                    join(expected),
+                    join(&values)
+                ))
+            }
+        }
+        (Expectation::Return(_), InvokeOutcome::Trapped(e)) => {
+            Outcome::Fail(format!("trapped: {}", e))
+        }
+        // the testsuite sometimes appends details, e.g. "uninitialized element 2"
+        (Expectation::Trap(message), InvokeOutcome::Trapped(e)) => {
+            if message.starts_with(&e) || e.starts_with(message.as_str()) {
+                Outcome::Pass
+            } else {
+                Outcome::Fail(format!("expected trap \"{}\", got \"{}\"", message, e))
+            }
+        }
+        (Expectation::Trap(message), InvokeOutcome::Returned(values)) => Outcome::Fail(format!(
+            "expected trap \"{}\", got [{}]",
+            message,
+            join(&values)
+        )),
+        (Expectation::Exception, InvokeOutcome::Trapped(e))
+            if e == TrapKind::UncaughtException.to_string() =>
+        {
+            Outcome::Pass
+        }
+        (Expectation::Exception, InvokeOutcome::Trapped(e)) => {
+            Outcome::Fail(format!("expected an exception, trapped: {}", e))
+        }
+        (Expectation::Exception, InvokeOutcome::Returned(values)) => {
+            Outcome::Fail(format!("expected an exception, got [{}]", join(&values)))
+        }
+    }
+}
+
+fn join<T: std::fmt::Display>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,243 @@
+//! Conversion of `.wast` arguments into `WasmValue`s and matching of results
+//! against the expected values of `assert_return`.
+
+use wast::{
+    core::{AbstractHeapType, HeapType, NanPattern, V128Pattern, WastArgCore, WastRetCore},
+    WastArg, WastRet,
+};
+
+use crate::module::value_type::WasmValue;
+
+/// A float expectation, either an exact bit pattern or a class of NaNs.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FloatPattern<T> {
+    CanonicalNan,
+    ArithmeticNan,
+    Bits(T),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum ExpectedValue {
+    I32(i32),
+    I64(i64),
+    F32(FloatPattern<u32>),
+    F64(FloatPattern<u64>),
+    V128(u128),
+    F32x4([FloatPattern<u32>; 4]),
+    F64x2([FloatPattern<u64>; 2]),
+    /// A null reference, `None` accepts a null of any reference type
+    RefNull(Option<RefKind>),
+    /// Any non null function reference
+    RefFunc,
+    /// A non null host reference, `None` accepts any of them
+    RefExtern(Option<u32>),
+    Either(Vec<ExpectedValue>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RefKind {
+    Func,
+    Extern,
+}
+
+pub(crate) fn lower_arg(arg: &WastArg) -> Result<WasmValue, String> {
+    let arg = match arg {
+        WastArg::Core(arg) => arg,
+        _ => return Err("component model arguments".to_string()),
+    };
+    let value = match arg {
+        WastArgCore::I32(v) => WasmValue::I32(*v),
+        WastArgCore::I64(v) => WasmValue::I64(*v),
+        WastArgCore::F32(v) => WasmValue::F32(f32::from_bits(v.bits)),
+        WastArgCore::F64(v) => WasmValue::F64(f64::from_bits(v.bits)),
+        WastArgCore::V128(v) => WasmValue::V128(u128::from_le_bytes(v.to_le_bytes())),
+        WastArgCore::RefNull(heap_type) => match ref_kind(heap_type)? {
+            RefKind::Func => WasmValue::FuncRef(None),
+            RefKind::Extern => WasmValue::ExternRef(None),
+        },
+        WastArgCore::RefExtern(v) => WasmValue::ExternRef(Some(*v)),
+        WastArgCore::RefHost(_) => return Err("ref.host arguments".to_string()),
+    };
+    Ok(value)
+}
+
+pub(crate) fn lower_ret(ret: &WastRet) -> Result<ExpectedValue, String> {
+    match ret {
+        WastRet::Core(ret) => lower_ret_core(ret),
+        _ => Err("component model results".to_string()),
+    }
+}
+
+fn lower_ret_core(ret: &WastRetCore) -> Result<ExpectedValue, String> {
+    let expected = match ret {
+        WastRetCore::I32(v) => ExpectedValue::I32(*v),
+        WastRetCore::I64(v) => ExpectedValue::I64(*v),
+        WastRetCore::F32(p) => ExpectedValue::F32(float_pattern(p, |f| f.bits)),
+        WastRetCore::F64(p) => ExpectedValue::F64(float_pattern(p, |f| f.bits)),
+        WastRetCore::V128(p) => lower_v128(p),
+        WastRetCore::RefNull(None) => ExpectedValue::RefNull(None),
+        WastRetCore::RefNull(Some(heap_type)) => ExpectedValue::RefNull(Some(ref_kind(heap_type)?)),
+        WastRetCore::RefFunc(_) => ExpectedValue::RefFunc,
+        WastRetCore::RefExtern(v) => ExpectedValue::RefExtern(*v),
+        WastRetCore::Either(rets) => {
+            ExpectedValue::Either(rets.iter().map(lower_ret_core).collect::<Result<_, _>>()?)
+        }
+        _ => return Err("gc reference results".to_string()),
+    };
+    Ok(expected)
+}
+
+fn ref_kind(heap_type: &HeapType) -> Result<RefKind, String> {
+    match heap_type {
+        HeapType::Abstract {
+            shared: false,
+            ty: AbstractHeapType::Func | AbstractHeapType::NoFunc,
+        } => Ok(RefKind::Func),
+        HeapType::Abstract {
+            shared: false,
+            ty: AbstractHeapType::Extern | AbstractHeapType::NoExtern,
+        } => Ok(RefKind::Extern),
+        _ => Err("gc heap types".to_string()),
+    }
+}
+
+fn float_pattern<F, T>(pattern: &NanPattern<F>, bits: impl Fn(&F) -> T) -> FloatPattern<T> {
+    match pattern {
+        NanPattern::CanonicalNan => FloatPattern::CanonicalNan,
+        NanPattern::ArithmeticNan => FloatPattern::ArithmeticNan,
+        NanPattern::Value(v) => FloatPattern::Bits(bits(v)),
+    }
+}
+
+/// integer lanes are folded into the bits of the whole vector, float lanes
+/// are kept apart since they may be NaN patterns
+fn lower_v128(pattern: &V128Pattern) -> ExpectedValue {
+    let mut bytes = [0u8; 16];
+    match pattern {
+        V128Pattern::I8x16(lanes) => {
+            for (i, lane) in lanes.iter().enumerate() {
+                bytes[i] = *lane as u8;
+            }
+        }
+        V128Pattern::I16x8(lanes) => {
+            for (i, lane) in lanes.iter().enumerate() {
+                bytes[i * 2..i * 2 + 2].copy_from_slice(&lane.to_le_bytes());
+            }
+        }
+        V128Pattern::I32x4(lanes) => {
+            for (i, lane) in lanes.iter().enumerate() {
+                bytes[i * 4..i * 4 + 4].copy_from_slice(&lane.to_le_bytes());
+            }
+        }
+        V128Pattern::I64x2(lanes) => {
+            for (i, lane) in lanes.iter().enumerate() {
+                bytes[i * 8..i * 8 + 8].copy_from_slice(&lane.to_le_bytes());
+            }
+        }
+        V128Pattern::F32x4(lanes) => {
+            return ExpectedValue::F32x4(lanes.each_ref().map(|p| float_pattern(p, |f| f.bits)))
+        }
+        V128Pattern::F64x2(lanes) => {
+            return ExpectedValue::F64x2(lanes.each_ref().map(|p| float_pattern(p, |f| f.bits)))
+        }
+    }
+    ExpectedValue::V128(u128::from_le_bytes(bytes))
+}
+
+impl ExpectedValue {
+    pub(crate) fn matches(&self, value: &WasmValue) -> bool {
+        match (self, value) {
+            (ExpectedValue::I32(e), WasmValue::I32(v)) => e == v,
+            (ExpectedValue::I64(e), WasmValue::I64(v)) => e == v,
+            (ExpectedValue::F32(e), WasmValue::F32(v)) => f32_matches(e, v.to_bits()),
+            (ExpectedValue::F64(e), WasmValue::F64(v)) => f64_matches(e, v.to_bits()),
+            (ExpectedValue::V128(e), WasmValue::V128(v)) => e == v,
+            (ExpectedValue::F32x4(lanes), WasmValue::V128(v)) => {
+                lanes.iter().enumerate().all(|(i, lane)| {
+                    let bits = (v >> (i * 32)) as u32;
+                    f32_matches(lane, bits)
+                })
+            }
+            (ExpectedValue::F64x2(lanes), WasmValue::V128(v)) => {
+                lanes.iter().enumerate().all(|(i, lane)| {
+                    let bits = (v >> (i * 64)) as u64;
+                    f64_matches(lane, bits)
+                })
+            }
+            (ExpectedValue::RefNull(kind), value) => matches!(
+                (kind, value),
+                (None, WasmValue::FuncRef(None) | WasmValue::ExternRef(None))
+                    | (Some(RefKind::Func), WasmValue::FuncRef(None))
+                    | (Some(RefKind::Extern), WasmValue::ExternRef(None))
+            ),
+            (ExpectedValue::RefFunc, WasmValue::FuncRef(v)) => v.is_some(),
+            (ExpectedValue::RefExtern(e), WasmValue::ExternRef(Some(v))) => {
+                e.is_none_or(|e| e == *v)
+            }
+            (ExpectedValue::Either(options), value) => options.iter().any(|e| e.matches(value)),
+            _ => false,
+        }
+    }
+}
+
+fn f32_matches(pattern: &FloatPattern<u32>, bits: u32) -> bool {
+    match pattern {
+        FloatPattern::CanonicalNan => bits & 0x7fff_ffff == 0x7fc0_0000,
+        FloatPattern::ArithmeticNan => bits & 0x7fc0_0000 == 0x7fc0_0000,
+        FloatPattern::Bits(e) => *e == bits,
+    }
+}
+
+fn f64_matches(pattern: &FloatPattern<u64>, bits: u64) -> bool {
+    match pattern {
+        FloatPattern::CanonicalNan => bits & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000,
+        FloatPattern::ArithmeticNan => bits & 0x7ff8_0000_0000_0000 == 0x7ff8_0000_0000_0000,
+        FloatPattern::Bits(e) => *e == bits,
+    }
+}
+
+impl std::fmt::Display for ExpectedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpectedValue::I32(v) => write!(f, "{}", v),
+            ExpectedValue::I64(v) => write!(f, "{}", v),
+            ExpectedValue::F32(p) => write!(f, "{}", FloatDisplay(p, |b| f32::from_bits(b) as f64)),
+            ExpectedValue::F64(p) => write!(f, "{}", FloatDisplay(p, f64::from_bits)),
+            ExpectedValue::V128(v) => write!(f, "0x{:032x}", v),
+            ExpectedValue::F32x4(lanes) => {
+                let lanes = lanes
+                    .iter()
+                    .map(|p| FloatDisplay(p, |b| f32::from_bits(b) as f64).to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "f32x4({})", lanes.join(" "))
+            }
+            ExpectedValue::F64x2(lanes) => {
+                let lanes = lanes
+                    .iter()
+                    .map(|p| FloatDisplay(p, f64::from_bits).to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "f64x2({})", lanes.join(" "))
+            }
+            ExpectedValue::RefNull(_) => write!(f, "null"),
+            ExpectedValue::RefFunc => write!(f, "ref.func"),
+            ExpectedValue::RefExtern(Some(v)) => write!(f, "ref.extern {}", v),
+            ExpectedValue::RefExtern(None) => write!(f, "ref.extern"),
+            ExpectedValue::Either(options) => {
+                let options = options.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "either({})", options.join(" | "))
+            }
+        }
+    }
+}
+
+struct FloatDisplay<'a, T, F>(&'a FloatPattern<T>, F);
+
+impl<T: Copy, F: Fn(T) -> f64> std::fmt::Display for FloatDisplay<'_, T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            FloatPattern::CanonicalNan => write!(f, "nan:canonical"),
+            FloatPattern::ArithmeticNan => write!(f, "nan:arithmetic"),
+            FloatPattern::Bits(bits) => write!(f, "{:.6}", (self.1)(*bits)),
+        }
+    }
+}
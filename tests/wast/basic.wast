@@ -0,0 +1,47 @@
+(module $arith
+  (func (export "add") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+  (func (export "div_s") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.div_s)
+  (func (export "mul64") (param i64 i64) (result i64)
+    local.get 0
+    local.get 1
+    i64.mul)
+  (func (export "sqrt") (param f64) (result f64)
+    local.get 0
+    f64.sqrt)
+  (func (export "swap") (param i32 i32) (result i32 i32)
+    local.get 1
+    local.get 0)
+)
+
+(assert_return (invoke "add" (i32.const 1) (i32.const 2)) (i32.const 3))
+(assert_return (invoke "add" (i32.const -1) (i32.const 1)) (i32.const 0))
+(assert_return (invoke "div_s" (i32.const 7) (i32.const -2)) (i32.const -3))
+(assert_trap (invoke "div_s" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_trap (invoke "div_s" (i32.const 0x80000000) (i32.const -1)) "integer overflow")
+(assert_return (invoke "mul64" (i64.const 0x100000000) (i64.const 3)) (i64.const 0x300000000))
+(assert_return (invoke "sqrt" (f64.const 2.25)) (f64.const 1.5))
+(assert_return (invoke "sqrt" (f64.const -1)) (f64.const nan:canonical))
+(assert_return (invoke "swap" (i32.const 1) (i32.const 2)) (i32.const 2) (i32.const 1))
+(invoke "add" (i32.const 0) (i32.const 0))
+
+(register "arith" $arith)
+
+(module $mem
+  (memory 1)
+  (data (i32.const 0) "\2a\00\00\00")
+  (func (export "load") (param i32) (result i32)
+    local.get 0
+    i32.load)
+)
+
+(assert_return (invoke $mem "load" (i32.const 0)) (i32.const 42))
+(assert_trap (invoke $mem "load" (i32.const 65536)) "out of bounds memory access")
+(assert_return (invoke $arith "add" (i32.const 2) (i32.const 2)) (i32.const 4))
+
+(assert_malformed (module quote "(func (i32.nop))") "unknown operator")
@@ -0,0 +1,170 @@
+;; branches leave the values they carry at the height of their target, and
+;; the try blocks they leave no longer catch
+
+(module
+  ;; the 1 and 2 below the blocks are not carried
+  (func (export "br-nested") (param i32) (result i32)
+    (i32.const 100)
+    (block (result i32)
+      (i32.const 1)
+      (block (result i32)
+        (i32.const 2)
+        (local.get 0)
+        (br 1))
+      (drop))
+    (i32.add))
+
+  ;; a loop with parameters gets them back on each iteration
+  (func (export "sum") (param i32) (result i32)
+    (i32.const 0)
+    (local.get 0)
+    (loop (param i32 i32) (result i32)
+      (local.set 0)
+      (local.get 0)
+      (i32.add)
+      (local.get 0)
+      (i32.const 1)
+      (i32.sub)
+      (local.tee 0)
+      (br_if 0 (i32.ne (local.get 0) (i32.const 0)))
+      (drop)))
+
+  (func (export "switch") (param i32) (result i32)
+    (block (block (block (block
+      (br_table 0 1 2 3 (local.get 0)))
+      (return (i32.const 10)))
+      (return (i32.const 11)))
+      (return (i32.const 12)))
+    (i32.const 13))
+
+  (func (export "if") (param i32) (result i32)
+    (local i32)
+    (if (local.get 0) (then (local.set 1 (i32.const 5))))
+    (if (result i32) (local.get 0)
+      (then (i32.const 1))
+      (else (i32.const 2)))
+    (local.get 1)
+    (i32.add))
+
+  ;; a branch to the function returns its results
+  (func (export "br-function") (param i32) (result i32)
+    (drop (br_if 0 (i32.const 1) (local.get 0)))
+    (i32.const 2))
+)
+
+(assert_return (invoke "br-nested" (i32.const 5)) (i32.const 105))
+(assert_return (invoke "sum" (i32.const 4)) (i32.const 10))
+(assert_return (invoke "switch" (i32.const 0)) (i32.const 10))
+(assert_return (invoke "switch" (i32.const 2)) (i32.const 12))
+(assert_return (invoke "switch" (i32.const 3)) (i32.const 13))
+(assert_return (invoke "switch" (i32.const -1)) (i32.const 13))
+(assert_return (invoke "if" (i32.const 0)) (i32.const 2))
+(assert_return (invoke "if" (i32.const 1)) (i32.const 6))
+(assert_return (invoke "br-function" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "br-function" (i32.const 0)) (i32.const 2))
+
+;; the jit does not support exception handling
+
+(module
+  (tag $e (param i32))
+
+  ;; the try left by the branch does not catch the throw after it
+  (func (export "br-out-of-try") (result i32)
+    try (result i32)
+      block
+        try
+          br 1
+        catch $e
+          drop
+        end
+      end
+      i32.const 7
+      throw $e
+    catch $e
+      i32.const 100
+      i32.add
+    end)
+
+  (func (export "br-out-of-catch") (result i32)
+    try (result i32)
+      block
+        try
+          i32.const 1
+          throw $e
+        catch $e
+          drop
+          br 1
+        end
+      end
+      i32.const 8
+      throw $e
+    catch $e
+      i32.const 200
+      i32.add
+    end)
+
+  ;; the delegate skips the catch of the try in between
+  (func (export "delegate") (result i32)
+    try (result i32)
+      try (result i32)
+        block (result i32)
+          try (result i32)
+            i32.const 3
+            throw $e
+          delegate 2
+        end
+      catch $e
+        i32.const 1000
+        i32.add
+      end
+    catch $e
+      i32.const 2000
+      i32.add
+    end)
+
+  ;; the label of the rethrow is two blocks up, but one try block
+  (func (export "rethrow") (result i32)
+    try (result i32)
+      try
+        i32.const 4
+        throw $e
+      catch $e
+        drop
+        block
+          try
+            i32.const 5
+            throw $e
+          catch_all
+            rethrow 2
+          end
+        end
+      end
+      i32.const 0
+    catch $e
+      i32.const 3000
+      i32.add
+    end)
+
+  (func $thrower (param i32) (result i32)
+    (throw $e (local.get 0)))
+
+  ;; the values below the try are left, those in it are not
+  (func (export "catch-call") (result i32)
+    i32.const 40
+    try (result i32)
+      i32.const 1
+      i32.const 2
+      i32.const 9
+      call $thrower
+      drop
+      drop
+    catch $e
+    end
+    i32.add)
+)
+
+(assert_return (invoke "br-out-of-try") (i32.const 107))
+(assert_return (invoke "br-out-of-catch") (i32.const 208))
+(assert_return (invoke "delegate") (i32.const 2003))
+(assert_return (invoke "rethrow") (i32.const 3004))
+(assert_return (invoke "catch-call") (i32.const 49))
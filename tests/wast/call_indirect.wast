@@ -0,0 +1,28 @@
+;; a call site checks the signature again once the table holds another
+;; function
+
+(module
+  (type $i2i (func (param i32) (result i32)))
+  (table 2 funcref)
+  (elem (i32.const 0) $inc)
+  (elem declare func $dec $nullary)
+  (func $inc (param i32) (result i32) (i32.add (local.get 0) (i32.const 1)))
+  (func $dec (param i32) (result i32) (i32.sub (local.get 0) (i32.const 1)))
+  (func $nullary (result i32) (i32.const 0))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect (type $i2i) (local.get 0) (i32.const 0)))
+  (func (export "set_dec") (table.set (i32.const 0) (ref.func $dec)))
+  (func (export "set_nullary") (table.set (i32.const 0) (ref.func $nullary)))
+  (func (export "set_null") (table.set (i32.const 0) (ref.null func)))
+)
+
+(assert_return (invoke "call" (i32.const 1)) (i32.const 2))
+(assert_return (invoke "call" (i32.const 1)) (i32.const 2))
+(invoke "set_dec")
+(assert_return (invoke "call" (i32.const 1)) (i32.const 0))
+(invoke "set_nullary")
+(assert_trap (invoke "call" (i32.const 1)) "indirect call type mismatch")
+(invoke "set_dec")
+(assert_return (invoke "call" (i32.const 5)) (i32.const 4))
+(invoke "set_null")
+(assert_trap (invoke "call" (i32.const 1)) "uninitialized element")
@@ -0,0 +1,15 @@
+;; an exception thrown out of the invocation, and one caught inside it
+
+(module
+  (tag $e (param i32))
+  (func (export "throw") (param i32)
+    (throw $e (local.get 0)))
+  (func (export "catch") (param i32) (result i32)
+    try (result i32)
+      local.get 0
+      throw $e
+    catch $e
+    end))
+
+(assert_exception (invoke "throw" (i32.const 1)))
+(assert_return (invoke "catch" (i32.const 7)) (i32.const 7))
@@ -0,0 +1,21 @@
+(module
+  (func $countdown (export "countdown") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 0))
+      (else
+        (i32.add (i32.const 1)
+          (call $countdown (i32.sub (local.get 0) (i32.const 1)))))))
+  (func $runaway (export "runaway") (call $runaway))
+  (func $mutual1 (export "mutual") (call $mutual2))
+  (func $mutual2 (call $mutual1))
+  (func $loop (export "tail") (param i32) (result i32)
+    (if (result i32) (i32.eqz (local.get 0))
+      (then (i32.const 42))
+      (else (return_call $loop (i32.sub (local.get 0) (i32.const 1))))))
+)
+
+(assert_return (invoke "countdown" (i32.const 200)) (i32.const 200))
+(assert_exhaustion (invoke "runaway") "call stack exhausted")
+(assert_exhaustion (invoke "mutual") "call stack exhausted")
+(assert_return (invoke "tail" (i32.const 100000)) (i32.const 42))
+(assert_return (invoke "countdown" (i32.const 10)) (i32.const 10))
@@ -0,0 +1,21 @@
+;; exported globals are read with (get), also after the calls that set them
+
+(module
+  (global $base (import "spectest" "global_i64") i64)
+  (global $count (export "count") (mut i32) (i32.const 0))
+  (global (export "pi") f64 (f64.const 3.25))
+  (export "base" (global $base))
+  (func (export "bump") (result i32)
+    (global.set $count (i32.add (global.get $count) (i32.const 1)))
+    (global.get $count)))
+
+(assert_return (get "count") (i32.const 0))
+(assert_return (get "pi") (f64.const 3.25))
+(assert_return (get "base") (i64.const 666))
+(assert_return (invoke "bump") (i32.const 1))
+(assert_return (get "count") (i32.const 1))
+
+(module $other
+  (global (export "count") i32 (i32.const 40)))
+
+(assert_return (get $other "count") (i32.const 40))
@@ -0,0 +1,97 @@
+;; the constants the jit folds before compiling give the same results as
+;; their instructions
+
+(module
+  (memory 1)
+  (data (i32.const 0) "\01\02\03\04\05\06\07\08")
+
+  (func (export "mul-pow2") (param i32) (result i32)
+    local.get 0
+    i32.const 0x80000000
+    i32.mul)
+
+  (func (export "mul-pow2-64") (param i64) (result i64)
+    local.get 0
+    i64.const 8
+    i64.mul)
+
+  ;; the constant of the local reaches the add
+  (func (export "local") (result i32)
+    (local i32)
+    i32.const 40
+    local.set 0
+    local.get 0
+    i32.const 2
+    i32.add)
+
+  (func (export "tee") (result i32)
+    (local i32)
+    i32.const 1
+    i32.const 2
+    i32.add
+    local.tee 0
+    i32.const 4
+    i32.add
+    local.get 0
+    i32.add)
+
+  ;; the loop sets the local again, its start does not see the constant
+  (func (export "loop") (result i32)
+    (local i32)
+    i32.const 0
+    local.set 0
+    loop
+      local.get 0
+      i32.const 1
+      i32.add
+      local.tee 0
+      i32.const 10
+      i32.lt_u
+      br_if 0
+    end
+    local.get 0)
+
+  ;; the else does not see the constant set in the then
+  (func (export "if") (param i32) (result i32)
+    (local i32)
+    i32.const 7
+    local.set 1
+    local.get 0
+    if
+      i32.const 3
+      local.set 1
+    else
+      local.get 1
+      i32.const 1
+      i32.add
+      local.set 1
+    end
+    local.get 1)
+
+  (func (export "load-const") (result i32)
+    i32.const 4
+    i32.load offset=2)
+
+  (func (export "store-const") (result i64)
+    i32.const 8
+    i64.const 0x1122334455667788
+    i64.store offset=8
+    i32.const 12
+    i64.load offset=4)
+
+  ;; the address and the offset are more than 4GiB together
+  (func (export "load-wrap") (result i32)
+    i32.const -1
+    i32.load offset=0xffffffff))
+
+(assert_return (invoke "mul-pow2" (i32.const 3)) (i32.const 0x80000000))
+(assert_return (invoke "mul-pow2" (i32.const 2)) (i32.const 0))
+(assert_return (invoke "mul-pow2-64" (i64.const -3)) (i64.const -24))
+(assert_return (invoke "local") (i32.const 42))
+(assert_return (invoke "tee") (i32.const 10))
+(assert_return (invoke "loop") (i32.const 10))
+(assert_return (invoke "if" (i32.const 1)) (i32.const 3))
+(assert_return (invoke "if" (i32.const 0)) (i32.const 8))
+(assert_return (invoke "load-const") (i32.const 0x0807))
+(assert_return (invoke "store-const") (i64.const 0x1122334455667788))
+(assert_trap (invoke "load-wrap") "out of bounds memory access")
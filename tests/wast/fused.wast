@@ -0,0 +1,48 @@
+;; the sequences the interpreter runs as one op give the same results as
+;; their instructions
+
+(module
+  (func (export "add-locals") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+
+  (func (export "add-const") (param i32) (result i32)
+    local.get 0
+    i32.const 0x7fffffff
+    i32.add)
+
+  ;; the branch carries the value below the compared operands
+  (func (export "lt-u") (param i32 i32) (result i32)
+    block (result i32)
+      i32.const 1
+      local.get 0
+      local.get 1
+      i32.lt_u
+      br_if 0
+      drop
+      i32.const 0
+    end)
+
+  (func (export "count") (param i32) (result i32)
+    (local i32)
+    loop
+      local.get 1
+      i32.const 1
+      i32.add
+      local.set 1
+      local.get 1
+      local.get 0
+      i32.lt_s
+      br_if 0
+    end
+    local.get 1)
+)
+
+(assert_return (invoke "add-locals" (i32.const 2) (i32.const 3)) (i32.const 5))
+(assert_return (invoke "add-locals" (i32.const -1) (i32.const 1)) (i32.const 0))
+(assert_return (invoke "add-const" (i32.const 1)) (i32.const 0x80000000))
+(assert_return (invoke "lt-u" (i32.const 1) (i32.const 2)) (i32.const 1))
+(assert_return (invoke "lt-u" (i32.const -1) (i32.const 2)) (i32.const 0))
+(assert_return (invoke "count" (i32.const 10)) (i32.const 10))
+(assert_return (invoke "count" (i32.const -5)) (i32.const 1))
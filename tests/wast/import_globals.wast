@@ -0,0 +1,53 @@
+;; imported globals come first in the global index space, the runner
+;; provides the globals of the spectest module
+
+(module
+  (global $i32 (import "spectest" "global_i32") i32)
+  (global $i64 (import "spectest" "global_i64") i64)
+  (global $f32 (import "spectest" "global_f32") f32)
+  (global $f64 (import "spectest" "global_f64") f64)
+  (global $own (mut i32) (i32.const 7))
+  (global $copy i32 (global.get $i32))
+  (func (export "i32") (result i32) (global.get $i32))
+  (func (export "i64") (result i64) (global.get $i64))
+  (func (export "f32") (result f32) (global.get $f32))
+  (func (export "f64") (result f64) (global.get $f64))
+  (func (export "own") (result i32) (global.get 4))
+  (func (export "copy") (result i32) (global.get $copy))
+  (func (export "set-own") (result i32)
+    (global.set $own (i32.add (global.get $own) (global.get $i32)))
+    (global.get $own)))
+
+(assert_return (invoke "i32") (i32.const 666))
+(assert_return (invoke "i64") (i64.const 666))
+(assert_return (invoke "f32") (f32.const 666.6))
+(assert_return (invoke "f64") (f64.const 666.6))
+(assert_return (invoke "own") (i32.const 7))
+(assert_return (invoke "copy") (i32.const 666))
+(assert_return (invoke "set-own") (i32.const 673))
+
+;; a mutable import gets its own copy of the value
+(module
+  (global $g (import "spectest" "global_i32") (mut i32))
+  (func (export "inc") (result i32)
+    (global.set $g (i32.add (global.get $g) (i32.const 1)))
+    (global.get $g)))
+
+(assert_return (invoke "inc") (i32.const 667))
+
+;; segment offsets can be imported globals
+(module
+  (global $base (import "spectest" "global_i32") i32)
+  (memory 1)
+  (data (global.get $base) "\2a")
+  (func (export "load") (result i32) (i32.load8_u (i32.const 666))))
+
+(assert_return (invoke "load") (i32.const 42))
+
+(assert_unlinkable
+  (module (global (import "spectest" "unknown") i32))
+  "unknown import")
+
+(assert_unlinkable
+  (module (global (import "spectest" "global_i32") i64))
+  "incompatible import type")
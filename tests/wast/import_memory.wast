@@ -0,0 +1,38 @@
+;; an imported memory comes first in the memory index space, the runner
+;; provides the memory of the spectest module, 1 page growing up to 2
+
+(module
+  (import "spectest" "memory" (memory 1))
+  (data (i32.const 16) "\2a\00\00\00")
+  (func (export "load") (param i32) (result i32) (i32.load (local.get 0)))
+  (func (export "store") (param i32 i32)
+    (i32.store (local.get 0) (local.get 1)))
+  (func (export "size") (result i32) (memory.size))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0))))
+
+(assert_return (invoke "load" (i32.const 16)) (i32.const 42))
+(assert_return (invoke "load" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "size") (i32.const 1))
+(assert_trap (invoke "load" (i32.const 65536)) "out of bounds memory access")
+
+;; the maximum of the imported memory bounds memory.grow
+(module
+  (import "spectest" "memory" (memory 1))
+  (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+  (func (export "size") (result i32) (memory.size)))
+
+(assert_return (invoke "grow" (i32.const 2)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "size") (i32.const 2))
+
+;; the import must be at least as large as declared, and no larger than its
+;; declared maximum
+(assert_unlinkable
+  (module (import "spectest" "memory" (memory 2)))
+  "incompatible import type")
+(assert_unlinkable
+  (module (import "spectest" "memory" (memory 1 1)))
+  "incompatible import type")
+(assert_unlinkable
+  (module (import "spectest" "no_memory" (memory 1)))
+  "unknown import")
@@ -0,0 +1,48 @@
+;; an imported table comes first in the table index space, the runner
+;; provides the table of the spectest module, 10 null funcrefs growing up to
+;; 20
+
+(module
+  (import "spectest" "table" (table $imported 10 funcref))
+  (table $own 2 funcref)
+  (type $ret (func (result i32)))
+  (func $one (result i32) (i32.const 1))
+  (func $two (result i32) (i32.const 2))
+  (elem (table $imported) (i32.const 3) func $one $two)
+  (elem (table $own) (i32.const 0) func $two)
+  (func (export "call-imported") (param i32) (result i32)
+    (call_indirect $imported (type $ret) (local.get 0)))
+  (func (export "call-own") (param i32) (result i32)
+    (call_indirect $own (type $ret) (local.get 0)))
+  (func (export "size") (result i32) (table.size $imported))
+  (func (export "grow") (param i32) (result i32)
+    (table.grow $imported (ref.null func) (local.get 0)))
+  (func (export "is-null") (param i32) (result i32)
+    (ref.is_null (table.get $imported (local.get 0)))))
+
+(assert_return (invoke "call-imported" (i32.const 3)) (i32.const 1))
+(assert_return (invoke "call-imported" (i32.const 4)) (i32.const 2))
+(assert_return (invoke "call-own" (i32.const 0)) (i32.const 2))
+(assert_trap (invoke "call-imported" (i32.const 0)) "uninitialized element")
+(assert_trap (invoke "call-imported" (i32.const 10)) "undefined element")
+(assert_return (invoke "is-null" (i32.const 5)) (i32.const 1))
+(assert_return (invoke "size") (i32.const 10))
+
+;; the maximum of the imported table bounds table.grow
+(assert_return (invoke "grow" (i32.const 11)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 10)) (i32.const 10))
+(assert_return (invoke "size") (i32.const 20))
+
+;; the element type, size and maximum must match the import
+(assert_unlinkable
+  (module (import "spectest" "table" (table 11 funcref)))
+  "incompatible import type")
+(assert_unlinkable
+  (module (import "spectest" "table" (table 10 15 funcref)))
+  "incompatible import type")
+(assert_unlinkable
+  (module (import "spectest" "table" (table 10 externref)))
+  "incompatible import type")
+(assert_unlinkable
+  (module (import "spectest" "no_table" (table 1 funcref)))
+  "unknown import")
@@ -0,0 +1,38 @@
+;; imported functions are resolved by module and field name when the module
+;; is instantiated
+
+(module
+  (import "env" "puti" (func (param i32)))
+  (import "weewasm" "putd" (func (param f64)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+  (func (export "f") (result i32) (i32.const 1)))
+
+(assert_return (invoke "f") (i32.const 1))
+
+(assert_unlinkable
+  (module (import "spectest" "puti" (func (param i32))))
+  "unknown import")
+
+(assert_unlinkable
+  (module (import "env" "proc_exit" (func (param i32))))
+  "unknown import")
+
+(assert_unlinkable
+  (module (import "env" "missing" (func)))
+  "unknown import")
+
+(assert_unlinkable
+  (module (import "env" "puti" (func (param i64))))
+  "incompatible import type")
+
+(assert_unlinkable
+  (module (import "env" "putd" (func (param f64) (result f64))))
+  "incompatible import type")
+
+;; unused imports are resolved as well
+(assert_unlinkable
+  (module
+    (import "env" "puti" (func (param i32)))
+    (import "env" "unused" (func))
+    (func (export "f")))
+  "unknown import")
@@ -0,0 +1,28 @@
+;; a module whose instantiation traps, through a segment that does not fit
+;; or through its start function
+
+(assert_trap
+  (module
+    (memory 1)
+    (data (i32.const 65535) "ab"))
+  "out of bounds memory access")
+
+(assert_trap
+  (module
+    (table 1 funcref)
+    (func $f)
+    (elem (i32.const 1) $f))
+  "out of bounds table access")
+
+(assert_trap
+  (module
+    (func $start unreachable)
+    (start $start))
+  "unreachable")
+
+(module
+  (memory (export "memory") 1)
+  (func (export "load") (result i32)
+    (i32.load8_u (i32.const 0))))
+
+(assert_return (invoke "load") (i32.const 0))
@@ -0,0 +1,63 @@
+;; the exports of a registered module can be imported by the modules
+;; instantiated after it
+
+(module $lib
+  (memory (export "mem") 1)
+  (global (export "base") i32 (i32.const 100))
+  (table (export "tab") 4 funcref)
+  (func (export "add") (param i32 i32) (result i32)
+    (i32.add (local.get 0) (local.get 1)))
+  (func (export "peek") (param i32) (result i32) (i32.load (local.get 0)))
+  (func (export "poke") (param i32 i32) (i32.store (local.get 0) (local.get 1))))
+(register "lib" $lib)
+
+(module $user
+  (import "lib" "add" (func $add (param i32 i32) (result i32)))
+  (import "lib" "peek" (func $peek (param i32) (result i32)))
+  (import "lib" "poke" (func $poke (param i32 i32)))
+  (import "lib" "mem" (memory 1))
+  (import "lib" "base" (global $base i32))
+  (import "lib" "tab" (table $tab 4 funcref))
+  (type $ret (func (result i32)))
+  (func $seven (result i32) (i32.const 7))
+  (elem (table $tab) (i32.const 1) func $seven)
+  (func (export "add-base") (param i32) (result i32)
+    (call $add (local.get 0) (global.get $base)))
+  ;; the functions of $lib use the memory $user imports
+  (func (export "poke-peek") (param i32) (result i32)
+    (call $poke (i32.const 8) (local.get 0))
+    (call $peek (i32.const 8)))
+  (func (export "call-tab") (param i32) (result i32)
+    (call_indirect $tab (type $ret) (local.get 0))))
+
+(assert_return (invoke $user "add-base" (i32.const 5)) (i32.const 105))
+(assert_return (invoke $user "poke-peek" (i32.const 42)) (i32.const 42))
+(assert_return (invoke $user "call-tab" (i32.const 1)) (i32.const 7))
+
+;; stores through $lib are seen by $user's memory
+(invoke $lib "poke" (i32.const 16) (i32.const 9))
+(module
+  (import "lib" "mem" (memory 1))
+  (func (export "load") (param i32) (result i32) (i32.load (local.get 0))))
+(assert_return (invoke "load" (i32.const 16)) (i32.const 9))
+
+(assert_unlinkable
+  (module (import "lib" "add" (func (param i32) (result i32))))
+  "incompatible import type")
+(assert_unlinkable
+  (module (import "lib" "missing" (func)))
+  "unknown import")
+
+;; an imported mutable global is the global of the exporting instance
+(module $counter
+  (global (export "count") (mut i32) (i32.const 0))
+  (func (export "get-count") (result i32) (global.get 0)))
+(register "counter" $counter)
+(module
+  (import "counter" "count" (global $count (mut i32)))
+  (import "counter" "get-count" (func $get-count (result i32)))
+  (func (export "bump") (result i32)
+    (global.set $count (i32.add (global.get $count) (i32.const 1)))
+    (call $get-count)))
+(assert_return (invoke "bump") (i32.const 1))
+(assert_return (invoke "bump") (i32.const 2))
@@ -0,0 +1,62 @@
+;; a memory keeps its contents when it grows, the new pages are zeroed and
+;; the bytes past its end stay out of bounds
+
+(module
+  (memory 1 4)
+  (func (export "grow") (param i32) (result i32)
+    (memory.grow (local.get 0)))
+  (func (export "store") (param i32 i32)
+    (i32.store (local.get 0) (local.get 1)))
+  (func (export "load") (param i32) (result i32)
+    (i32.load (local.get 0)))
+  (func (export "load8") (param i32) (result i32)
+    (i32.load8_u (local.get 0)))
+  ;; the offset takes the access past the end of a 32-bit address
+  (func (export "load-offset") (param i32) (result i32)
+    (i32.load offset=0xffffffff (local.get 0)))
+)
+
+(assert_return (invoke "store" (i32.const 65532) (i32.const 0x01020304)))
+(assert_trap (invoke "load" (i32.const 65533)) "out of bounds memory access")
+(assert_return (invoke "load8" (i32.const 65535)) (i32.const 1))
+(assert_trap (invoke "load8" (i32.const 65536)) "out of bounds memory access")
+(assert_return (invoke "grow" (i32.const 2)) (i32.const 1))
+(assert_return (invoke "load" (i32.const 65532)) (i32.const 0x01020304))
+(assert_return (invoke "load" (i32.const 65533)) (i32.const 0x010203))
+(assert_return (invoke "load" (i32.const 196604)) (i32.const 0))
+(assert_trap (invoke "load" (i32.const 196605)) "out of bounds memory access")
+(assert_return (invoke "grow" (i32.const 2)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 3))
+(assert_return (invoke "load8" (i32.const 262143)) (i32.const 0))
+(assert_trap (invoke "load-offset" (i32.const 1)) "out of bounds memory access")
+(assert_trap (invoke "load-offset" (i32.const -1)) "out of bounds memory access")
+
+(module
+  (memory i64 1 2)
+  (func (export "grow") (param i64) (result i64)
+    (memory.grow (local.get 0)))
+  (func (export "load") (param i64) (result i64)
+    (i64.load (local.get 0)))
+  (func (export "store") (param i64 i64)
+    (i64.store (local.get 0) (local.get 1)))
+)
+
+(assert_return (invoke "store" (i64.const 65528) (i64.const -2)))
+(assert_return (invoke "grow" (i64.const 1)) (i64.const 1))
+(assert_return (invoke "load" (i64.const 65528)) (i64.const -2))
+(assert_return (invoke "load" (i64.const 131064)) (i64.const 0))
+(assert_trap (invoke "load" (i64.const 131065)) "out of bounds memory access")
+(assert_trap (invoke "load" (i64.const 0x100000000)) "out of bounds memory access")
+(assert_trap (invoke "load" (i64.const -1)) "out of bounds memory access")
+
+;; a memory without a maximum grows up to 65536 pages
+(module
+  (memory 1)
+  (func (export "grow") (param i32) (result i32)
+    (memory.grow (local.get 0)))
+)
+
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "grow" (i32.const 65535)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 2)) (i32.const 2))
+(assert_return (invoke "grow" (i32.const 0)) (i32.const 4))
@@ -0,0 +1,262 @@
+;; the loads that widen, splat or set a lane, the stores of a lane
+
+(module
+  (memory 1)
+  (data (i32.const 0) "\01\ff\80\7f\00\02\fe\10\80\00\00\80\ff\ff\ff\ff")
+
+  (func (export "load8x8_s") (result v128) (v128.load8x8_s (i32.const 0)))
+  (func (export "load8x8_u") (result v128) (v128.load8x8_u (i32.const 0)))
+  (func (export "load16x4_s") (result v128) (v128.load16x4_s (i32.const 0)))
+  (func (export "load16x4_u") (result v128) (v128.load16x4_u (i32.const 0)))
+  (func (export "load32x2_s") (result v128) (v128.load32x2_s (i32.const 8)))
+  (func (export "load32x2_u") (result v128) (v128.load32x2_u (i32.const 8)))
+
+  (func (export "load8_splat") (result v128) (v128.load8_splat (i32.const 1)))
+  (func (export "load16_splat") (result v128) (v128.load16_splat (i32.const 2)))
+  (func (export "load32_splat") (result v128) (v128.load32_splat (i32.const 8)))
+  (func (export "load64_splat") (result v128) (v128.load64_splat (i32.const 8)))
+
+  (func (export "load32_zero") (result v128) (v128.load32_zero (i32.const 8)))
+  (func (export "load64_zero") (result v128) (v128.load64_zero (i32.const 0)))
+
+  (func (export "load8_lane") (result v128)
+    (v128.load8_lane 3 (i32.const 1) (v128.const i8x16 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0)))
+  (func (export "load32_lane") (result v128)
+    (v128.load32_lane 1 (i32.const 8) (v128.const i32x4 1 2 3 4)))
+  (func (export "load64_lane") (result v128)
+    (v128.load64_lane 1 (i32.const 0) (v128.const i64x2 5 6)))
+
+  (func (export "store16_lane") (param i32) (result i32)
+    (v128.store16_lane 1 (local.get 0) (v128.const i16x8 0 0x1234 0 0 0 0 0 0))
+    (i32.load16_u (local.get 0)))
+  (func (export "store64_lane") (param i32)
+    (v128.store64_lane 0 (local.get 0) (v128.const i64x2 -1 -1))))
+
+(assert_return (invoke "load8x8_s") (v128.const i16x8 1 -1 -128 127 0 2 -2 16))
+(assert_return (invoke "load8x8_u") (v128.const i16x8 1 255 128 127 0 2 254 16))
+(assert_return (invoke "load16x4_s") (v128.const i32x4 -255 32640 512 4350))
+(assert_return (invoke "load16x4_u") (v128.const i32x4 65281 32640 512 4350))
+(assert_return (invoke "load32x2_s") (v128.const i64x2 -2147483520 -1))
+(assert_return (invoke "load32x2_u") (v128.const i64x2 2147483776 4294967295))
+
+(assert_return (invoke "load8_splat") (v128.const i8x16 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1))
+(assert_return (invoke "load16_splat") (v128.const i16x8 32640 32640 32640 32640 32640 32640 32640 32640))
+(assert_return (invoke "load32_splat") (v128.const i32x4 0x80000080 0x80000080 0x80000080 0x80000080))
+(assert_return (invoke "load64_splat") (v128.const i64x2 0xffffffff80000080 0xffffffff80000080))
+
+(assert_return (invoke "load32_zero") (v128.const i32x4 0x80000080 0 0 0))
+(assert_return (invoke "load64_zero") (v128.const i64x2 0x10fe02007f80ff01 0))
+
+(assert_return (invoke "load8_lane") (v128.const i8x16 0 0 0 0xff 0 0 0 0 0 0 0 0 0 0 0 0))
+(assert_return (invoke "load32_lane") (v128.const i32x4 1 0x80000080 3 4))
+(assert_return (invoke "load64_lane") (v128.const i64x2 5 0x10fe02007f80ff01))
+
+(assert_return (invoke "store16_lane" (i32.const 32)) (i32.const 0x1234))
+(assert_trap (invoke "store64_lane" (i32.const 65529)) "out of bounds memory access")
+
+(assert_invalid
+  (module (memory 1)
+    (func (drop (v128.load16_splat align=4 (i32.const 0)))))
+  "alignment must not be larger than natural")
+(assert_invalid
+  (module (memory 1)
+    (func (v128.store8_lane 16 (i32.const 0) (v128.const i64x2 0 0))))
+  "invalid lane index")
+
+;; the lane operations that widen, narrow and combine lanes
+
+(module
+  (func (export "i8x16.narrow_i16x8_s") (param v128 v128) (result v128)
+    (i8x16.narrow_i16x8_s (local.get 0) (local.get 1)))
+  (func (export "i8x16.narrow_i16x8_u") (param v128 v128) (result v128)
+    (i8x16.narrow_i16x8_u (local.get 0) (local.get 1)))
+  (func (export "i16x8.narrow_i32x4_s") (param v128 v128) (result v128)
+    (i16x8.narrow_i32x4_s (local.get 0) (local.get 1)))
+  (func (export "i16x8.narrow_i32x4_u") (param v128 v128) (result v128)
+    (i16x8.narrow_i32x4_u (local.get 0) (local.get 1)))
+
+  (func (export "i16x8.extend_low_i8x16_s") (param v128) (result v128)
+    (i16x8.extend_low_i8x16_s (local.get 0)))
+  (func (export "i16x8.extend_high_i8x16_u") (param v128) (result v128)
+    (i16x8.extend_high_i8x16_u (local.get 0)))
+  (func (export "i32x4.extend_high_i16x8_s") (param v128) (result v128)
+    (i32x4.extend_high_i16x8_s (local.get 0)))
+  (func (export "i64x2.extend_low_i32x4_u") (param v128) (result v128)
+    (i64x2.extend_low_i32x4_u (local.get 0)))
+
+  (func (export "i16x8.extmul_high_i8x16_u") (param v128 v128) (result v128)
+    (i16x8.extmul_high_i8x16_u (local.get 0) (local.get 1)))
+  (func (export "i32x4.extmul_low_i16x8_s") (param v128 v128) (result v128)
+    (i32x4.extmul_low_i16x8_s (local.get 0) (local.get 1)))
+  (func (export "i64x2.extmul_high_i32x4_u") (param v128 v128) (result v128)
+    (i64x2.extmul_high_i32x4_u (local.get 0) (local.get 1)))
+  (func (export "i64x2.extmul_low_i32x4_s") (param v128 v128) (result v128)
+    (i64x2.extmul_low_i32x4_s (local.get 0) (local.get 1)))
+
+  (func (export "i32x4.dot_i16x8_s") (param v128 v128) (result v128)
+    (i32x4.dot_i16x8_s (local.get 0) (local.get 1)))
+  (func (export "i16x8.q15mulr_sat_s") (param v128 v128) (result v128)
+    (i16x8.q15mulr_sat_s (local.get 0) (local.get 1)))
+
+  (func (export "i16x8.extadd_pairwise_i8x16_s") (param v128) (result v128)
+    (i16x8.extadd_pairwise_i8x16_s (local.get 0)))
+  (func (export "i16x8.extadd_pairwise_i8x16_u") (param v128) (result v128)
+    (i16x8.extadd_pairwise_i8x16_u (local.get 0)))
+  (func (export "i32x4.extadd_pairwise_i16x8_s") (param v128) (result v128)
+    (i32x4.extadd_pairwise_i16x8_s (local.get 0)))
+  (func (export "i32x4.extadd_pairwise_i16x8_u") (param v128) (result v128)
+    (i32x4.extadd_pairwise_i16x8_u (local.get 0)))
+
+  (func (export "i8x16.avgr_u") (param v128 v128) (result v128)
+    (i8x16.avgr_u (local.get 0) (local.get 1))))
+
+(assert_return
+  (invoke "i8x16.narrow_i16x8_s"
+    (v128.const i16x8 0 1 -1 127 128 -128 -129 300)
+    (v128.const i16x8 -300 2 3 4 5 6 7 8))
+  (v128.const i8x16 0 1 -1 127 127 -128 -128 127 -128 2 3 4 5 6 7 8))
+(assert_return
+  (invoke "i8x16.narrow_i16x8_u"
+    (v128.const i16x8 0 1 -1 127 128 -128 -129 300)
+    (v128.const i16x8 -300 2 3 4 5 6 7 255))
+  (v128.const i8x16 0 1 0 127 128 0 0 255 0 2 3 4 5 6 7 255))
+(assert_return
+  (invoke "i16x8.narrow_i32x4_s"
+    (v128.const i32x4 70000 -70000 5 -5)
+    (v128.const i32x4 32768 -32769 0 1))
+  (v128.const i16x8 32767 -32768 5 -5 32767 -32768 0 1))
+(assert_return
+  (invoke "i16x8.narrow_i32x4_u"
+    (v128.const i32x4 70000 -70000 5 -5)
+    (v128.const i32x4 32768 -32769 0 1))
+  (v128.const i16x8 65535 0 5 0 32768 0 0 1))
+
+(assert_return
+  (invoke "i16x8.extend_low_i8x16_s"
+    (v128.const i8x16 -1 2 -3 4 -5 6 -7 8 9 10 11 12 13 14 15 -16))
+  (v128.const i16x8 -1 2 -3 4 -5 6 -7 8))
+(assert_return
+  (invoke "i16x8.extend_high_i8x16_u"
+    (v128.const i8x16 -1 2 -3 4 -5 6 -7 8 9 10 11 12 13 14 15 -16))
+  (v128.const i16x8 9 10 11 12 13 14 15 240))
+(assert_return
+  (invoke "i32x4.extend_high_i16x8_s" (v128.const i16x8 0 0 0 0 -1 2 -3 4))
+  (v128.const i32x4 -1 2 -3 4))
+(assert_return
+  (invoke "i64x2.extend_low_i32x4_u" (v128.const i32x4 -1 2 3 4))
+  (v128.const i64x2 4294967295 2))
+
+(assert_return
+  (invoke "i16x8.extmul_high_i8x16_u"
+    (v128.const i8x16 0 0 0 0 0 0 0 0 255 255 255 255 255 255 255 2)
+    (v128.const i8x16 0 0 0 0 0 0 0 0 255 255 255 255 255 255 255 3))
+  (v128.const i16x8 65025 65025 65025 65025 65025 65025 65025 6))
+(assert_return
+  (invoke "i32x4.extmul_low_i16x8_s"
+    (v128.const i16x8 -32768 -2 3 4 0 0 0 0)
+    (v128.const i16x8 -32768 5 -6 32767 0 0 0 0))
+  (v128.const i32x4 1073741824 -10 -18 131068))
+(assert_return
+  (invoke "i64x2.extmul_high_i32x4_u"
+    (v128.const i32x4 0 0 -1 2)
+    (v128.const i32x4 0 0 -1 3))
+  (v128.const i64x2 0xfffffffe00000001 6))
+(assert_return
+  (invoke "i64x2.extmul_low_i32x4_s"
+    (v128.const i32x4 -1 0x80000000 0 0)
+    (v128.const i32x4 5 0x80000000 0 0))
+  (v128.const i64x2 -5 0x4000000000000000))
+
+(assert_return
+  (invoke "i32x4.dot_i16x8_s"
+    (v128.const i16x8 1 2 3 4 -32768 -32768 5 6)
+    (v128.const i16x8 7 8 9 10 -32768 -32768 -1 1))
+  (v128.const i32x4 23 67 -2147483648 1))
+(assert_return
+  (invoke "i16x8.q15mulr_sat_s"
+    (v128.const i16x8 -32768 16384 -16384 1 0 0 0 0)
+    (v128.const i16x8 -32768 16384 16384 16384 0 0 0 0))
+  (v128.const i16x8 32767 8192 -8192 1 0 0 0 0))
+
+(assert_return
+  (invoke "i16x8.extadd_pairwise_i8x16_s"
+    (v128.const i8x16 -1 -1 -128 -128 127 127 1 -2 0 0 0 0 0 0 0 0))
+  (v128.const i16x8 -2 -256 254 -1 0 0 0 0))
+(assert_return
+  (invoke "i16x8.extadd_pairwise_i8x16_u"
+    (v128.const i8x16 255 255 128 128 127 127 1 254 0 0 0 0 0 0 0 0))
+  (v128.const i16x8 510 256 254 255 0 0 0 0))
+(assert_return
+  (invoke "i32x4.extadd_pairwise_i16x8_s" (v128.const i16x8 -1 -1 -32768 -32768 1 2 3 -4))
+  (v128.const i32x4 -2 -65536 3 -1))
+(assert_return
+  (invoke "i32x4.extadd_pairwise_i16x8_u" (v128.const i16x8 -1 -1 -32768 -32768 1 2 3 -4))
+  (v128.const i32x4 131070 65536 3 65535))
+
+(assert_return
+  (invoke "i8x16.avgr_u"
+    (v128.const i8x16 255 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14)
+    (v128.const i8x16 254 0 2 2 4 4 6 6 8 8 10 10 12 12 14 14))
+  (v128.const i8x16 255 0 2 2 4 4 6 6 8 8 10 10 12 12 14 14))
+
+;; the float lane operations: pseudo min and max, conversions between the
+;; f64x2 and the 32-bit lanes
+
+(module
+  (func (export "f32x4.pmin") (param v128 v128) (result v128)
+    (f32x4.pmin (local.get 0) (local.get 1)))
+  (func (export "f32x4.pmax") (param v128 v128) (result v128)
+    (f32x4.pmax (local.get 0) (local.get 1)))
+  (func (export "f64x2.pmin") (param v128 v128) (result v128)
+    (f64x2.pmin (local.get 0) (local.get 1)))
+  (func (export "f64x2.pmax") (param v128 v128) (result v128)
+    (f64x2.pmax (local.get 0) (local.get 1)))
+
+  (func (export "i32x4.trunc_sat_f64x2_s_zero") (param v128) (result v128)
+    (i32x4.trunc_sat_f64x2_s_zero (local.get 0)))
+  (func (export "i32x4.trunc_sat_f64x2_u_zero") (param v128) (result v128)
+    (i32x4.trunc_sat_f64x2_u_zero (local.get 0)))
+  (func (export "f64x2.convert_low_i32x4_s") (param v128) (result v128)
+    (f64x2.convert_low_i32x4_s (local.get 0)))
+  (func (export "f64x2.convert_low_i32x4_u") (param v128) (result v128)
+    (f64x2.convert_low_i32x4_u (local.get 0)))
+  (func (export "f32x4.demote_f64x2_zero") (param v128) (result v128)
+    (f32x4.demote_f64x2_zero (local.get 0)))
+  (func (export "f64x2.promote_low_f32x4") (param v128) (result v128)
+    (f64x2.promote_low_f32x4 (local.get 0))))
+
+;; a NaN or equal operands give the first one
+(assert_return
+  (invoke "f32x4.pmin" (v128.const f32x4 1 -0 nan 2) (v128.const f32x4 2 0 1 nan))
+  (v128.const f32x4 1 -0 nan:canonical 2))
+(assert_return
+  (invoke "f32x4.pmax" (v128.const f32x4 1 -0 nan 2) (v128.const f32x4 2 0 1 nan))
+  (v128.const f32x4 2 -0 nan:canonical 2))
+(assert_return
+  (invoke "f64x2.pmin" (v128.const f64x2 -0 5) (v128.const f64x2 0 3))
+  (v128.const f64x2 -0 3))
+(assert_return
+  (invoke "f64x2.pmax" (v128.const f64x2 -0 5) (v128.const f64x2 0 3))
+  (v128.const f64x2 -0 5))
+
+(assert_return
+  (invoke "i32x4.trunc_sat_f64x2_s_zero" (v128.const f64x2 -1.5 1e10))
+  (v128.const i32x4 -1 2147483647 0 0))
+(assert_return
+  (invoke "i32x4.trunc_sat_f64x2_u_zero" (v128.const f64x2 -1.5 nan))
+  (v128.const i32x4 0 0 0 0))
+(assert_return
+  (invoke "i32x4.trunc_sat_f64x2_u_zero" (v128.const f64x2 4294967296 42.9))
+  (v128.const i32x4 4294967295 42 0 0))
+(assert_return
+  (invoke "f64x2.convert_low_i32x4_s" (v128.const i32x4 -1 2 3 4))
+  (v128.const f64x2 -1 2))
+(assert_return
+  (invoke "f64x2.convert_low_i32x4_u" (v128.const i32x4 -1 2 3 4))
+  (v128.const f64x2 4294967295 2))
+(assert_return
+  (invoke "f32x4.demote_f64x2_zero" (v128.const f64x2 1.5 1e300))
+  (v128.const f32x4 1.5 inf 0 0))
+(assert_return
+  (invoke "f64x2.promote_low_f32x4" (v128.const f32x4 1.5 -2 3 4))
+  (v128.const f64x2 1.5 -2))
@@ -0,0 +1,45 @@
+;; the spectest module of the testsuite
+
+(module
+  (import "spectest" "print" (func $print))
+  (import "spectest" "print_i32" (func $print_i32 (param i32)))
+  (import "spectest" "print_i64" (func $print_i64 (param i64)))
+  (import "spectest" "print_f32" (func $print_f32 (param f32)))
+  (import "spectest" "print_f64" (func $print_f64 (param f64)))
+  (import "spectest" "print_i32_f32" (func $print_i32_f32 (param i32 f32)))
+  (import "spectest" "print_f64_f64" (func $print_f64_f64 (param f64 f64)))
+  (import "spectest" "global_i32" (global $g i32))
+  (import "spectest" "global_f64" (global $gf f64))
+  (import "spectest" "memory" (memory 1 2))
+  (import "spectest" "table" (table 10 20 funcref))
+  (func (export "print") (result i32)
+    call $print
+    global.get $g
+    call $print_i32
+    i64.const -1
+    call $print_i64
+    f32.const 1.5
+    call $print_f32
+    global.get $gf
+    call $print_f64
+    i32.const 1
+    f32.const 2
+    call $print_i32_f32
+    f64.const 3
+    f64.const 4
+    call $print_f64_f64
+    global.get $g)
+  (func (export "sizes") (result i32 i32)
+    memory.size
+    table.size))
+
+(assert_return (invoke "print") (i32.const 666))
+(assert_return (invoke "sizes") (i32.const 1) (i32.const 10))
+
+(assert_unlinkable
+  (module (import "spectest" "print_i32" (func (param i64))))
+  "incompatible import type")
+
+(assert_unlinkable
+  (module (import "spectest" "global_i64" (global i32)))
+  "incompatible import type")
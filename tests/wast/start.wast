@@ -0,0 +1,24 @@
+;; the start function runs when the module is instantiated, before any export
+(module
+  (global $g (mut i32) (i32.const 0))
+  (memory 1)
+  (func $start
+    (global.set $g (i32.const 42))
+    (i32.store8 (i32.const 0) (i32.const 7)))
+  (start $start)
+  (func (export "get") (result i32) (global.get $g))
+  (func (export "load") (result i32) (i32.load8_u (i32.const 0))))
+
+(assert_return (invoke "get") (i32.const 42))
+(assert_return (invoke "load") (i32.const 7))
+
+;; the data segments are written before the start function runs
+(module
+  (memory 1)
+  (func $start (i32.store8 (i32.const 1) (i32.const 9)))
+  (start $start)
+  (data (i32.const 0) "\01\02")
+  (func (export "load") (param i32) (result i32) (i32.load8_u (local.get 0))))
+
+(assert_return (invoke "load" (i32.const 0)) (i32.const 1))
+(assert_return (invoke "load" (i32.const 1)) (i32.const 9))
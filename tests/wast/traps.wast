@@ -0,0 +1,39 @@
+(module
+  (type $i2i (func (param i32) (result i32)))
+  (table 3 funcref)
+  (elem (i32.const 0) $id $nullary)
+  (memory 1)
+  (func $id (param i32) (result i32) (local.get 0))
+  (func $nullary (result i32) (i32.const 0))
+  (func (export "unreachable") (unreachable))
+  (func (export "rem_u") (param i32 i32) (result i32)
+    (i32.rem_u (local.get 0) (local.get 1)))
+  (func (export "div_s64") (param i64 i64) (result i64)
+    (i64.div_s (local.get 0) (local.get 1)))
+  (func (export "trunc_s") (param f64) (result i32)
+    (i32.trunc_f64_s (local.get 0)))
+  (func (export "trunc_u") (param f32) (result i64)
+    (i64.trunc_f32_u (local.get 0)))
+  (func (export "store") (param i32)
+    (i32.store (local.get 0) (i32.const 1)))
+  (func (export "table_get") (param i32) (result funcref)
+    (table.get (local.get 0)))
+  (func (export "call") (param i32) (result i32)
+    (call_indirect (type $i2i) (i32.const 7) (local.get 0)))
+)
+
+(assert_trap (invoke "unreachable") "unreachable")
+(assert_trap (invoke "rem_u" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_trap (invoke "div_s64" (i64.const 1) (i64.const 0)) "integer divide by zero")
+(assert_trap (invoke "div_s64" (i64.const 0x8000000000000000) (i64.const -1)) "integer overflow")
+(assert_return (invoke "trunc_s" (f64.const -2147483648.9)) (i32.const -2147483648))
+(assert_trap (invoke "trunc_s" (f64.const 2147483648)) "integer overflow")
+(assert_trap (invoke "trunc_s" (f64.const nan)) "invalid conversion to integer")
+(assert_trap (invoke "trunc_u" (f32.const -1)) "integer overflow")
+(assert_trap (invoke "trunc_u" (f32.const -nan)) "invalid conversion to integer")
+(assert_trap (invoke "store" (i32.const 65533)) "out of bounds memory access")
+(assert_trap (invoke "table_get" (i32.const 3)) "out of bounds table access")
+(assert_return (invoke "call" (i32.const 0)) (i32.const 7))
+(assert_trap (invoke "call" (i32.const 1)) "indirect call type mismatch")
+(assert_trap (invoke "call" (i32.const 2)) "uninitialized element")
+(assert_trap (invoke "call" (i32.const 3)) "undefined element")
@@ -0,0 +1,70 @@
+(assert_invalid
+  (module (func (result i32) (i64.const 0)))
+  "type mismatch")
+(assert_invalid
+  (module (func (result i32)))
+  "type mismatch")
+(assert_invalid
+  (module (func (i32.const 1)))
+  "type mismatch")
+(assert_invalid
+  (module (func (param i32) (result f32) (local.get 0)))
+  "type mismatch")
+(assert_invalid
+  (module (func (result i32) (i32.add (i32.const 1))))
+  "type mismatch")
+(assert_invalid
+  (module (func (if (i32.const 1) (then (i32.const 1)))))
+  "type mismatch")
+(assert_invalid
+  (module (func (result i32) (block (result i32) (br 0 (f32.const 1)))))
+  "type mismatch")
+(assert_invalid
+  (module (func (block (result i32) (block (result i64) (br_table 0 1 (i32.const 0) (i32.const 0)))) (drop)))
+  "type mismatch")
+(assert_invalid
+  (module (func (local.get 1) (drop)))
+  "unknown local")
+(assert_invalid
+  (module (func (global.get 0) (drop)))
+  "unknown global")
+(assert_invalid
+  (module (global i32 (i32.const 0)) (func (global.set 0 (i32.const 1))))
+  "global is immutable")
+(assert_invalid
+  (module (func (br 1)))
+  "unknown label")
+(assert_invalid
+  (module (func (call 1)))
+  "unknown function")
+(assert_invalid
+  (module (func (drop (i32.load (i32.const 0)))))
+  "unknown memory")
+(assert_invalid
+  (module (memory 1) (func (drop (i32.load align=8 (i32.const 0)))))
+  "alignment must not be larger than natural")
+(assert_invalid
+  (module (func (select (i32.const 0) (i64.const 0) (i32.const 1)) (drop)))
+  "type mismatch")
+
+(module
+  (memory 1)
+  (func (export "fac") (param i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else (i64.mul (local.get 0) (call 0 (i64.sub (local.get 0) (i64.const 1)))))))
+  (func (export "unreachable-add") (result i32)
+    (unreachable)
+    (i32.add))
+  (func (export "br-table") (param i32) (result i32)
+    (block (result i32)
+      (block (result i32)
+        (br_table 0 1 (i32.const 7) (local.get 0)))
+      (drop)
+      (i32.const 8)))
+)
+
+(assert_return (invoke "fac" (i64.const 5)) (i64.const 120))
+(assert_trap (invoke "unreachable-add") "unreachable")
+(assert_return (invoke "br-table" (i32.const 0)) (i32.const 8))
+(assert_return (invoke "br-table" (i32.const 1)) (i32.const 7))
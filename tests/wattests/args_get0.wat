@@ -0,0 +1,57 @@
+(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func (param i32 i32 i32 i32) (result i32)))
+  (type (;2;) (func (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get" (func (;0;) (type 0)))
+  (import "wasi_snapshot_preview1" "args_get" (func (;1;) (type 0)))
+  (import "wasi_snapshot_preview1" "fd_write" (func (;2;) (type 1)))
+  (memory (;0;) 1)
+  (func (;3;) (type 2) (result i32)
+    (local i32 i32)
+    ;; argc at 16, the size of the strings at 20
+    i32.const 16
+    i32.const 20
+    call 0
+    drop
+    ;; the pointers at 512, the strings at 1024
+    i32.const 512
+    i32.const 1024
+    call 1
+    drop
+    i32.const 16
+    i32.load
+    local.set 0
+    ;; print the last argument unless there is only the program name
+    local.get 0
+    i32.const 1
+    i32.gt_u
+    if  ;; label = @1
+      local.get 0
+      i32.const 4
+      i32.mul
+      i32.const 508
+      i32.add
+      i32.load
+      local.set 1
+      i32.const 0
+      local.get 1
+      i32.store
+      i32.const 4
+      i32.const 1024
+      i32.const 20
+      i32.load
+      i32.add
+      local.get 1
+      i32.sub
+      i32.const 1
+      i32.sub
+      i32.store
+      i32.const 1
+      i32.const 0
+      i32.const 1
+      i32.const 24
+      call 2
+      drop
+    end
+    local.get 0)
+  (export "main" (func 3)))
@@ -0,0 +1,12 @@
+(module
+  (type (;0;) (func (result i32)))
+  (type (;1;) (func (param i32 i32) (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 10
+    i32.const 3
+    block (type 1)
+      i32.sub
+    end
+    i32.const 2
+    i32.mul)
+  (export "main" (func 0)))
@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 1
+    call_indirect (type 0))
+  (table (;0;) 2 funcref)
+  (export "main" (func 0))
+  (elem (;0;) (i32.const 0) func 0))
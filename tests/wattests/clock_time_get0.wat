@@ -0,0 +1,39 @@
+(module
+  (type (;0;) (func (param i32 i64 i32) (result i32)))
+  (type (;1;) (func (param i32 i32) (result i32)))
+  (type (;2;) (func (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func (;0;) (type 0)))
+  (import "wasi_snapshot_preview1" "clock_res_get" (func (;1;) (type 1)))
+  (memory (;0;) 1)
+  (func (;2;) (type 2) (param i32) (result i32)
+    (local i32)
+    ;; the time at 16, the resolution at 24, 11 if both are set
+    local.get 0
+    i64.const 1
+    i32.const 16
+    call 0
+    local.tee 1
+    if  ;; label = @1
+      local.get 1
+      return
+    end
+    local.get 0
+    i32.const 24
+    call 1
+    local.tee 1
+    if  ;; label = @1
+      local.get 1
+      return
+    end
+    i32.const 16
+    i64.load
+    i64.const 0
+    i64.gt_u
+    i32.const 24
+    i64.load
+    i64.const 0
+    i64.gt_u
+    i32.const 10
+    i32.mul
+    i32.add)
+  (export "main" (func 2)))
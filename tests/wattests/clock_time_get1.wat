@@ -0,0 +1,18 @@
+(module
+  (type (;0;) (func (param i32 i64 i32) (result i32)))
+  (type (;1;) (func (param i32) (result i64)))
+  (import "wasi_snapshot_preview1" "clock_time_get" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (param i32) (result i64)
+    ;; the time of the clock in the param, -1 if clock_time_get fails
+    local.get 0
+    i64.const 1
+    i32.const 16
+    call 0
+    if  ;; label = @1
+      i64.const -1
+      return
+    end
+    i32.const 16
+    i64.load)
+  (export "main" (func 1)))
@@ -0,0 +1,20 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (param i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    try (result i32)
+      try (result i32)
+        try (result i32)
+          local.get 0
+          throw 0
+        delegate 1
+      catch 0
+        i32.const 1000
+        i32.add
+      end
+    catch 0
+      i32.const 2000
+      i32.add
+    end)
+  (tag (;0;) (type 1))
+  (export "main" (func 0)))
@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 1)
+  (func (;1;) (type 0) (result i32)
+    i32.const 2)
+  (export "helper" (func 0))
+  (export "_start" (func 1)))
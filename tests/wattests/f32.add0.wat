@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result f32)))
+  (func (;0;) (type 0) (result f32)
+    f32.const 1.5
+    f32.const 2.25
+    f32.add)
+  (export "main" (func 0)))
@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result f32)))
+  (func (;0;) (type 0) (result f32)
+    f32.const -1.25
+    f32.const 3
+    f32.copysign)
+  (export "main" (func 0)))
@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result f32)))
+  (func (;0;) (type 0) (result f32)
+    f64.const 0.1
+    f32.demote_f64
+    f64.promote_f32
+    f32.demote_f64)
+  (export "main" (func 0)))
@@ -0,0 +1,12 @@
+(module
+  (type (;0;) (func (result f32)))
+  (func (;0;) (type 0) (result f32)
+    i32.const 8
+    f32.const 0.125
+    f32.store
+    i32.const 8
+    f32.load
+    f32.const 4
+    f32.mul)
+  (memory (;0;) 1)
+  (export "main" (func 0)))
@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    f32.const -0.5
+    f32.const 0.25
+    f32.lt)
+  (export "main" (func 0)))
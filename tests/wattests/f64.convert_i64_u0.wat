@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result f64)))
+  (func (;0;) (type 0) (result f64)
+    i64.const -1
+    f64.convert_i64_u)
+  (export "main" (func 0)))
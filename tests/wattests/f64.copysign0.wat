@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result f64)))
+  (func (;0;) (type 0) (result f64)
+    f64.const 2.5
+    f64.const -0
+    f64.copysign)
+  (export "main" (func 0)))
@@ -0,0 +1,40 @@
+(module
+  (type (;0;) (func (param i32 i32 i32 i32) (result i32)))
+  (type (;1;) (func (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func (;0;) (type 0)))
+  (import "wasi_snapshot_preview1" "fd_write" (func (;1;) (type 0)))
+  (memory (;0;) 1)
+  (func (;2;) (type 1) (result i32)
+    (local i32)
+    ;; read stdin into 64 bytes at 32 through the iovec at 0, the number of
+    ;; bytes read at 16, then write them back to stdout and return their
+    ;; number, or the errno of the read
+    i32.const 0
+    i32.const 32
+    i32.store
+    i32.const 4
+    i32.const 64
+    i32.store
+    i32.const 0
+    i32.const 0
+    i32.const 1
+    i32.const 16
+    call 0
+    local.tee 0
+    if  ;; label = @1
+      local.get 0
+      return
+    end
+    i32.const 4
+    i32.const 16
+    i32.load
+    i32.store
+    i32.const 1
+    i32.const 0
+    i32.const 1
+    i32.const 20
+    call 1
+    drop
+    i32.const 16
+    i32.load)
+  (export "main" (func 2)))
@@ -0,0 +1,29 @@
+(module
+  (type (;0;) (func (param i32 i32 i32 i32) (result i32)))
+  (type (;1;) (func (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (result i32)
+    ;; two iovecs at 0, the number of bytes written at 16
+    i32.const 0
+    i32.const 32
+    i32.store
+    i32.const 4
+    i32.const 6
+    i32.store
+    i32.const 8
+    i32.const 38
+    i32.store
+    i32.const 12
+    i32.const 7
+    i32.store
+    i32.const 1
+    i32.const 0
+    i32.const 2
+    i32.const 16
+    call 0
+    drop
+    i32.const 16
+    i32.load)
+  (export "main" (func 1))
+  (data (;0;) (i32.const 32) "hello world!\0a"))
@@ -0,0 +1,21 @@
+(module
+  (type (;0;) (func (param i32 i32 i32 i32) (result i32)))
+  (type (;1;) (func (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (param i32 i32) (result i32)
+    ;; write "hi" to the fd in the first param, the second param is the
+    ;; number of iovecs, the ones after the first are empty
+    i32.const 0
+    i32.const 32
+    i32.store
+    i32.const 4
+    i32.const 2
+    i32.store
+    local.get 0
+    i32.const 0
+    local.get 1
+    i32.const 16
+    call 0)
+  (export "main" (func 1))
+  (data (;0;) (i32.const 32) "hi"))
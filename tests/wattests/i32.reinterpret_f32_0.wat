@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    f32.const -0
+    i32.reinterpret_f32)
+  (export "main" (func 0)))
@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    f32.const nan
+    i32.trunc_sat_f32_u)
+  (export "main" (func 0)))
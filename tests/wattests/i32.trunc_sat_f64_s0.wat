@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    f64.const 1e10
+    i32.trunc_sat_f64_s)
+  (export "main" (func 0)))
@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i64.const 4294967301
+    i32.wrap_i64)
+  (export "main" (func 0)))
@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    i64.const 9223372036854775807
+    i64.const 1
+    i64.add)
+  (export "main" (func 0)))
@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    i32.const -1
+    i64.extend_i32_s)
+  (export "main" (func 0)))
@@ -0,0 +1,11 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    i32.const 0
+    i64.load32_s
+    i32.const 0
+    i64.load32_u
+    i64.add)
+  (memory (;0;) 1)
+  (data (;0;) (i32.const 0) "\ff\ff\ff\ff")
+  (export "main" (func 0)))
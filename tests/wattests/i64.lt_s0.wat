@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i64.const -9223372036854775808
+    i64.const 1
+    i64.lt_s)
+  (export "main" (func 0)))
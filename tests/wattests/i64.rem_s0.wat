@@ -0,0 +1,11 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    i64.const -9223372036854775808
+    i64.const -1
+    i64.rem_s
+    i64.const -77777777777
+    i64.const 10000000000
+    i64.rem_s
+    i64.add)
+  (export "main" (func 0)))
@@ -0,0 +1,7 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    i64.const 0x8000000000000001
+    i64.const 65
+    i64.rotl)
+  (export "main" (func 0)))
@@ -0,0 +1,19 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    (local i64)
+    i64.const 0x0102030405060708
+    local.set 0
+    i32.const 8
+    local.get 0
+    i64.store
+    i32.const 16
+    local.get 0
+    i64.store16
+    i32.const 8
+    i64.load
+    i32.const 16
+    i64.load
+    i64.sub)
+  (memory (;0;) 1)
+  (export "main" (func 0)))
@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    f32.const 3e9
+    i64.trunc_f32_u)
+  (export "main" (func 0)))
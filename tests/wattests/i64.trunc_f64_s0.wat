@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    f64.const 1e19
+    i64.trunc_f64_s)
+  (export "main" (func 0)))
@@ -0,0 +1,6 @@
+(module
+  (type (;0;) (func (result i64)))
+  (func (;0;) (type 0) (result i64)
+    f64.const -5.5
+    i64.trunc_sat_f64_u)
+  (export "main" (func 0)))
@@ -0,0 +1,26 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    if (result i32)
+      local.get 0
+      i32.const 1
+      i32.gt_s
+      if (result i32)
+        i32.const 10
+      else
+        i32.const 20
+      end
+    else
+      i32.const 30
+    end
+    local.get 0
+    i32.const 2
+    i32.eq
+    if (param i32) (result i32)
+      i32.const 100
+      i32.add
+    end
+    i32.const 1
+    i32.add)
+  (export "main" (func 0)))
@@ -0,0 +1,59 @@
+(module
+  (type $unop (func (param i32) (result i32)))
+  (memory 1)
+  (global $calls (mut i32) (i32.const 0))
+  (table 3 funcref)
+  (elem (i32.const 0) $double $square $negate)
+  (func $double (type $unop) (param i32) (result i32)
+    local.get 0
+    local.get 0
+    i32.add)
+  (func $square (type $unop) (param i32) (result i32)
+    local.get 0
+    local.get 0
+    i32.mul)
+  (func $negate (type $unop) (param i32) (result i32)
+    i32.const 0
+    local.get 0
+    i32.sub)
+  (func $bias (param i32) (result i32)
+    block
+      block
+        block
+          block
+            local.get 0
+            i32.const 3
+            i32.and
+            br_table 0 1 2 3
+          end
+          i32.const 10
+          return
+        end
+        i32.const 20
+        return
+      end
+      i32.const 30
+      return
+    end
+    i32.const 40)
+  ;; every run but the first loads the code the first one cached
+  (func $main (param i32) (result i32)
+    i32.const 16
+    local.get 0
+    local.get 0
+    i32.const 3
+    i32.rem_u
+    call_indirect (type $unop)
+    local.get 0
+    call $bias
+    i32.add
+    i32.store
+    global.get $calls
+    i32.const 1
+    i32.add
+    global.set $calls
+    i32.const 16
+    i32.load
+    global.get $calls
+    i32.add)
+  (export "main" (func $main)))
@@ -0,0 +1,22 @@
+(module
+  (type (;0;) (func (result i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (func (;0;) (type 1) (param i32) (result i32)
+    local.get 0
+    call_indirect (type 0)
+    i32.const 100
+    i32.mul
+    local.get 0
+    call_indirect (type 0)
+    i32.add)
+  (func (;1;) (type 0) (result i32)
+    i32.const 7)
+  (func (;2;) (type 0) (result i32)
+    i32.const 8)
+  (func (;3;) (type 0) (result i32)
+    i32.const 3
+    call 1
+    i32.add)
+  (table (;0;) 3 3 funcref)
+  (export "main" (func 0))
+  (elem (;0;) (i32.const 0) func 1 2 3))
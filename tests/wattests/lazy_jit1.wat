@@ -0,0 +1,79 @@
+(module
+  (type (;0;) (func (param i32) (result i64)))
+  (type (;1;) (func (param i32) (result i32)))
+  (type (;2;) (func (param i32 i64 i32 i64 i32 i64 i32 i64) (result i64)))
+  (func (;0;) (type 0) (param i32) (result i64)
+    local.get 0
+    call 1
+    i64.extend_i32_u
+    i64.const 1000000000
+    i64.mul
+    local.get 0
+    i64.const 0
+    i32.const 1
+    i64.const 2
+    i32.const 3
+    i64.const 4
+    i32.const 5
+    i64.const 6
+    call 3
+    i64.add)
+  (func (;1;) (type 1) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)  ;; label = @1
+      i32.const 1
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      call 2
+    end)
+  (func (;2;) (type 1) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)  ;; label = @1
+      i32.const 0
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      call 1
+    end)
+  (func (;3;) (type 2) (param i32 i64 i32 i64 i32 i64 i32 i64) (result i64)
+    local.get 0
+    i32.eqz
+    if (result i64)  ;; label = @1
+      local.get 1
+      local.get 2
+      i64.extend_i32_s
+      i64.add
+      local.get 3
+      i64.add
+      local.get 4
+      i64.extend_i32_s
+      i64.add
+      local.get 5
+      i64.add
+      local.get 6
+      i64.extend_i32_s
+      i64.add
+      local.get 7
+      i64.add
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      local.get 1
+      local.get 0
+      i64.extend_i32_u
+      i64.add
+      local.get 2
+      local.get 3
+      local.get 4
+      local.get 5
+      local.get 6
+      local.get 7
+      call 3
+    end)
+  (export "main" (func 0)))
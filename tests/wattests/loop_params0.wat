@@ -0,0 +1,22 @@
+(module
+  (type (;0;) (func (result i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (result i32)
+    (local i32 i32)
+    i32.const 0
+    local.set 0
+    i32.const 5
+    loop (type 1)
+      local.get 0
+      i32.const 1
+      i32.add
+      local.set 0
+      i32.const 1
+      i32.sub
+      local.tee 1
+      local.get 1
+      br_if 0
+    end
+    local.get 0
+    i32.add)
+  (export "main" (func 0)))
@@ -0,0 +1,21 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    (local i64)
+    local.get 0
+    i64.extend_i32_s
+    memory.grow
+    local.set 1
+    i64.const 0
+    i64.const 7
+    i64.store offset=65536
+    local.get 1
+    i32.wrap_i64
+    memory.size
+    i32.wrap_i64
+    i32.const 100
+    i32.mul
+    i32.add)
+  (memory (;0;) i64 1 3)
+  (export "main" (func 0))
+)
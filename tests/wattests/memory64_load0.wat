@@ -0,0 +1,13 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    i64.extend_i32_u
+    i32.load offset=4
+    i64.const 65534
+    i32.load16_u
+    i32.add)
+  (memory (;0;) i64 1)
+  (export "main" (func 0))
+  (data (;0;) (i64.const 4) "\0a\00\00\00\14\00\00\00")
+  (data (;1;) (i64.const 65534) "\01\00"))
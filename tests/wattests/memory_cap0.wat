@@ -0,0 +1,11 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    memory.grow
+    i32.const 100
+    i32.mul
+    memory.size
+    i32.add)
+  (memory (;0;) 1 10)
+  (export "main" (func 0)))
@@ -0,0 +1,12 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 1
+    i32.const 0
+    i32.const 4
+    memory.copy
+    i32.const 0
+    i32.load)
+  (memory (;0;) 1)
+  (export "main" (func 0))
+  (data (;0;) (i32.const 0) "\01\02\03\04\05"))
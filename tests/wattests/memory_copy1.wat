@@ -0,0 +1,10 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 0
+    i32.const 65534
+    i32.const 4
+    memory.copy
+    i32.const 0)
+  (memory (;0;) 1)
+  (export "main" (func 0)))
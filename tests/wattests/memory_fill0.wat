@@ -0,0 +1,12 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    i32.const 2
+    i32.const 255
+    local.get 0
+    memory.fill
+    i32.const 0
+    i32.load)
+  (memory (;0;) 1)
+  (export "main" (func 0))
+  (data (;0;) (i32.const 0) "\01\01\01\01"))
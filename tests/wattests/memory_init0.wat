@@ -0,0 +1,13 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    i32.const 0
+    local.get 0
+    i32.const 4
+    memory.init 1
+    i32.const 0
+    i32.load)
+  (memory (;0;) 1)
+  (export "main" (func 0))
+  (data (;0;) (i32.const 8) "\ff\ff\ff\ff")
+  (data (;1;) "\01\02\03\04\05\06"))
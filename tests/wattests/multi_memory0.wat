@@ -0,0 +1,19 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    i32.const 0
+    local.get 0
+    i32.store 1 offset=8
+    i32.const 8
+    i32.load 1
+    i32.const 8
+    i32.load
+    i32.add
+    i32.const 0
+    i32.load 1
+    i32.add)
+  (memory (;0;) 1)
+  (memory (;1;) 1)
+  (export "main" (func 0))
+  (data (;0;) (i32.const 8) "\64\00\00\00")
+  (data (;1;) (memory 1) (i32.const 0) "\e8\03\00\00"))
@@ -0,0 +1,24 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    memory.grow 1
+    drop
+    i32.const 16
+    i32.const 0
+    i32.const 4
+    memory.copy 1 0
+    i32.const 16
+    i32.load 1
+    memory.size 1
+    i32.const 1000
+    i32.mul
+    i32.add
+    memory.size
+    i32.const 100000
+    i32.mul
+    i32.add)
+  (memory (;0;) 2)
+  (memory (;1;) 1 3)
+  (export "main" (func 0))
+  (data (;0;) (i32.const 0) "\2a\00\00\00"))
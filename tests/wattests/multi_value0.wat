@@ -0,0 +1,14 @@
+(module
+  (type (;0;) (func (param i32) (result i32 i64)))
+  (type (;1;) (func (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32 i64)
+    local.get 0
+    i32.const 1
+    i32.add
+    i64.const 9)
+  (func (;1;) (type 1) (result i32)
+    i32.const 6
+    call 0
+    i32.wrap_i64
+    i32.sub)
+  (export "main" (func 1)))
@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result i32 f64 i64)))
+  (func (;0;) (type 0) (result i32 f64 i64)
+    i32.const 3
+    f64.const 2.5
+    i64.const -1
+    return)
+  (export "main" (func 0)))
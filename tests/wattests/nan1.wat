@@ -0,0 +1,50 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    block  ;; label = @1
+      block  ;; label = @2
+        block  ;; label = @3
+          block  ;; label = @4
+            local.get 0
+            br_table 0 (;@4;) 1 (;@3;) 2 (;@2;) 3 (;@1;)
+          end
+          ;; f32 0/0
+          global.get 2
+          global.get 2
+          f32.div
+          i32.reinterpret_f32
+          return
+        end
+        ;; a NaN with a payload, divided by 1
+        global.get 0
+        f32.reinterpret_i32
+        global.get 3
+        f32.div
+        i32.reinterpret_f32
+        return
+      end
+      ;; the low half of an f64 NaN with a payload, divided by 1
+      global.get 1
+      f64.reinterpret_i64
+      global.get 3
+      f64.promote_f32
+      f64.div
+      i64.reinterpret_f64
+      i32.wrap_i64
+      return
+    end
+    ;; the high half of f64 0/0
+    global.get 2
+    f64.promote_f32
+    global.get 2
+    f64.promote_f32
+    f64.div
+    i64.reinterpret_f64
+    i64.const 32
+    i64.shr_u
+    i32.wrap_i64)
+  (global (;0;) (mut i32) (i32.const 2143363909))
+  (global (;1;) (mut i64) (i64.const 9221120237346510456))
+  (global (;2;) (mut f32) (f32.const 0x0p+0 (;=0;)))
+  (global (;3;) (mut f32) (f32.const 0x1p+0 (;=1;)))
+  (export "main" (func 0)))
@@ -0,0 +1,63 @@
+(module
+  (type (;0;) (func (param i32) (result f64)))
+  (func (;0;) (type 0) (param i32) (result f64)
+    (local i32 i64 f32 f64)
+    ;; stays on the operand stack while the loop runs
+    i64.const 1000
+    block  ;; label = @1
+      loop  ;; label = @2
+        local.get 1
+        local.get 0
+        i32.ge_s
+        br_if 1 (;@1;)
+        block  ;; label = @3
+          block  ;; label = @4
+            block  ;; label = @5
+              block  ;; label = @6
+                local.get 1
+                i32.const 3
+                i32.rem_u
+                br_table 0 (;@6;) 1 (;@5;) 2 (;@4;)
+              end
+              local.get 2
+              local.get 1
+              i64.extend_i32_u
+              i64.const 3
+              i64.mul
+              i64.add
+              local.set 2
+              br 2 (;@3;)
+            end
+            local.get 3
+            f32.const 0x1p-1 (;=0.5;)
+            f32.add
+            local.set 3
+            br 1 (;@3;)
+          end
+          local.get 4
+          local.get 1
+          f64.convert_i32_s
+          f64.const 0x1p-2 (;=0.25;)
+          f64.mul
+          f64.add
+          local.set 4
+        end
+        local.get 1
+        i32.const 1
+        i32.add
+        local.set 1
+        br 0 (;@2;)
+      end
+    end
+    local.get 2
+    i64.add
+    f64.convert_i64_s
+    local.get 3
+    f64.promote_f32
+    f64.add
+    local.get 4
+    f64.add
+    local.get 1
+    f64.convert_i32_s
+    f64.add)
+  (export "main" (func 0)))
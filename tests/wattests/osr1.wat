@@ -0,0 +1,52 @@
+(module
+  (type (;0;) (func (param i32 f32) (result f64)))
+  (type (;1;) (func (param i32) (result f64)))
+  (func (;0;) (type 0) (param i32 f32) (result f64)
+    (local i32 i32 f64)
+    ;; stay on the operand stack while the loops run
+    local.get 1
+    f64.promote_f32
+    i32.const 7
+    loop  ;; label = @1
+      i32.const 0
+      local.set 3
+      loop  ;; label = @2
+        local.get 3
+        local.get 2
+        i32.lt_s
+        if  ;; label = @3
+          local.get 4
+          local.get 3
+          f64.convert_i32_s
+          local.get 1
+          f64.promote_f32
+          f64.mul
+          f64.add
+          local.set 4
+          local.get 3
+          i32.const 1
+          i32.add
+          local.set 3
+          br 1 (;@2;)
+        end
+      end
+      local.get 2
+      i32.const 1
+      i32.add
+      local.tee 2
+      local.get 0
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+    f64.convert_i32_s
+    local.get 4
+    f64.add
+    f64.mul)
+  (func (;1;) (type 1) (param i32) (result f64)
+    local.get 0
+    f32.const 0x1.8p+0 (;=1.5;)
+    call 0
+    local.get 0
+    f64.convert_i32_s
+    f64.sub)
+  (export "main" (func 1)))
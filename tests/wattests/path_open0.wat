@@ -0,0 +1,36 @@
+(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (type (;2;) (func (param i32 i64 i32 i32) (result i32)))
+  (type (;3;) (func (param i32) (result i32)))
+  (type (;4;) (func (param i32) (result i32 i32 i32 i32)))
+  (import "wasi_snapshot_preview1" "fd_prestat_get" (func (;0;) (type 0)))
+  (import "wasi_snapshot_preview1" "path_open" (func (;1;) (type 1)))
+  (import "wasi_snapshot_preview1" "fd_seek" (func (;2;) (type 2)))
+  (import "wasi_snapshot_preview1" "fd_close" (func (;3;) (type 3)))
+  (memory (;0;) 1)
+  (func (;4;) (type 4) (param i32) (result i32 i32 i32 i32)
+    ;; without preopened directories, every file descriptor is stdio or
+    ;; not open
+    local.get 0
+    i32.const 16
+    call 0
+    local.get 0
+    i32.const 0
+    i32.const 32
+    i32.const 4
+    i32.const 0
+    i64.const 2
+    i64.const 0
+    i32.const 0
+    i32.const 24
+    call 1
+    local.get 0
+    i64.const 0
+    i32.const 0
+    i32.const 24
+    call 2
+    local.get 0
+    call 3)
+  (export "main" (func 4))
+  (data (;0;) (i32.const 32) "file"))
@@ -0,0 +1,35 @@
+(module
+  (type (;0;) (func (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "path_open" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (param i32) (result i32)
+    ;; open the path at index param of the table at 0 under the preopened
+    ;; tests/fixtures/sandbox, which holds `file`, `link` to it and the
+    ;; dangling `escape` to ../outside: from 2 on, create it for writing
+    i32.const 3
+    i32.const 0
+    local.get 0
+    i32.const 8
+    i32.mul
+    i32.load
+    local.get 0
+    i32.const 8
+    i32.mul
+    i32.load offset=4
+    local.get 0
+    i32.const 2
+    i32.ge_u
+    i64.const 64
+    i64.const 2
+    local.get 0
+    i32.const 2
+    i32.ge_u
+    select
+    i64.const 0
+    i32.const 0
+    i32.const 48
+    call 0)
+  (export "main" (func 1))
+  (data (;0;) (i32.const 0) "@\00\00\00\04\00\00\00H\00\00\00\04\00\00\00P\00\00\00\06\00\00\00X\00\00\00\0a\00\00\00")
+  (data (;1;) (i32.const 64) "file\00\00\00\00link\00\00\00\00escape\00\00../outside"))
@@ -0,0 +1,17 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func (;0;) (type 0)))
+  (import "env" "puti" (func (;1;) (type 0)))
+  (func (;2;) (type 1) (param i32) (result i32)
+    i32.const 7
+    call 1
+    local.get 0
+    i32.eqz
+    if  ;; label = @1
+      unreachable
+    end
+    local.get 0
+    call 0
+    i32.const 99)
+  (export "main" (func 2)))
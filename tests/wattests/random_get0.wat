@@ -0,0 +1,11 @@
+(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (param i32) (result i32)
+    ;; fill the number of bytes in the param at 16
+    i32.const 16
+    local.get 0
+    call 0)
+  (export "main" (func 1)))
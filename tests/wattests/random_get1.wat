@@ -0,0 +1,18 @@
+(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func (param i32) (result i64)))
+  (import "wasi_snapshot_preview1" "random_get" (func (;0;) (type 0)))
+  (memory (;0;) 1)
+  (func (;1;) (type 1) (param i32) (result i64)
+    ;; fill the number of bytes in the param at 16 and return the 8 bytes
+    ;; there, -1 if random_get fails
+    i32.const 16
+    local.get 0
+    call 0
+    if  ;; label = @1
+      i64.const -1
+      return
+    end
+    i32.const 16
+    i64.load)
+  (export "main" (func 1)))
@@ -0,0 +1,10 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    (local externref)
+    local.get 0
+    ref.is_null
+    ref.null func
+    ref.is_null
+    i32.add)
+  (export "main" (func 0)))
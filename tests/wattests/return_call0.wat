@@ -0,0 +1,22 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (param i32 i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.const 0
+    return_call 1)
+  (func (;1;) (type 1) (param i32 i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)
+      local.get 1
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      local.get 1
+      local.get 0
+      i32.add
+      return_call 1
+    end)
+  (export "main" (func 0)))
@@ -0,0 +1,25 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)
+      i32.const 1
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      return_call 1
+    end)
+  (func (;1;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)
+      i32.const 0
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      return_call 0
+    end)
+  (export "main" (func 0)))
@@ -0,0 +1,37 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    local.get 0
+    i32.const 1
+    i32.and
+    return_call_indirect (type 0))
+  (func (;1;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)
+      i32.const 1
+    else
+      local.get 0
+      i32.const 1
+      i32.sub
+      local.get 0
+      i32.const 1
+      i32.sub
+      i32.const 1
+      i32.and
+      return_call_indirect (type 0)
+    end)
+  (func (;2;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.const 1
+    i32.sub
+    local.get 0
+    i32.const 1
+    i32.sub
+    i32.const 1
+    i32.and
+    return_call_indirect (type 0))
+  (table (;0;) 2 2 funcref)
+  (elem (;0;) (i32.const 0) func 1 2)
+  (export "main" (func 0)))
@@ -0,0 +1,18 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    i64.const 7
+    i64.const 9
+    local.get 0
+    select (result i64)
+    i32.wrap_i64
+    i32.const 10
+    i32.mul
+    ref.func 0
+    ref.null func
+    local.get 0
+    select (result funcref)
+    ref.is_null
+    i32.add)
+  (export "main" (func 0))
+  (elem (;0;) declare func 0))
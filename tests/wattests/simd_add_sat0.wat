@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    v128.const i8x16 100 -100 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+    local.get 0
+    i8x16.splat
+    i8x16.add_sat_s
+    i8x16.extract_lane_s 0)
+  (export "main" (func 0)))
@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    v128.const i64x2 0 0
+    local.get 0
+    i64.extend_i32_u
+    i64x2.replace_lane 1
+    v128.any_true)
+  (export "main" (func 0)))
@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    v128.const i32x4 1 5 -2 9
+    local.get 0
+    i32x4.splat
+    i32x4.lt_s
+    i32x4.bitmask)
+  (export "main" (func 0)))
@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    i16x8.splat
+    v128.const i16x8 1 2 3 4 5 6 7 8
+    i32x4.dot_i16x8_s
+    i32x4.extract_lane 3)
+  (export "main" (func 0)))
@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (result f32)))
+  (func (;0;) (type 0) (result f32)
+    v128.const f32x4 1.5 2.0 -3.0 4.0
+    f32.const 2.5
+    f32x4.splat
+    f32x4.mul
+    f32x4.extract_lane 2)
+  (export "main" (func 0)))
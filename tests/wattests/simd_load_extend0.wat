@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    v128.load8x8_s
+    i16x8.extract_lane_s 0)
+  (memory (;0;) 1)
+  (export "main" (func 0))
+  (data (;0;) (i32.const 0) "\ff\01\80\7f"))
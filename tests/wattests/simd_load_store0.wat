@@ -0,0 +1,14 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    i32.const 16
+    v128.const i16x8 -1 2 -3 4 -5 6 -7 -8
+    v128.store
+    i32.const 16
+    v128.load
+    local.get 0
+    i16x8.splat
+    i16x8.add
+    i16x8.extract_lane_s 7)
+  (memory (;0;) 1)
+  (export "main" (func 0)))
@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 65530
+    v128.load
+    i32x4.extract_lane 0)
+  (memory (;0;) 1)
+  (export "main" (func 0)))
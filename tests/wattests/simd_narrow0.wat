@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32x4.splat
+    v128.const i32x4 0 0 0 0
+    i16x8.narrow_i32x4_s
+    i16x8.extract_lane_s 1)
+  (export "main" (func 0)))
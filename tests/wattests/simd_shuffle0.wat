@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    v128.const i8x16 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+    v128.const i8x16 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    i8x16.shuffle 31 0 30 1 29 2 28 3 27 4 26 5 25 6 24 7
+    i32x4.extract_lane 1)
+  (export "main" (func 0)))
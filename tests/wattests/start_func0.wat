@@ -0,0 +1,13 @@
+(module
+  (type (;0;) (func))
+  (type (;1;) (func (result i32)))
+  (func (;0;) (type 0)
+    global.get 0
+    i32.const 10
+    i32.mul
+    global.set 0)
+  (func (;1;) (type 1) (result i32)
+    global.get 0)
+  (global (;0;) (mut i32) (i32.const 4))
+  (export "main" (func 1))
+  (start 0))
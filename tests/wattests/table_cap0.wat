@@ -0,0 +1,12 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    ref.null func
+    local.get 0
+    table.grow 0
+    i32.const 100
+    i32.mul
+    table.size 0
+    i32.add)
+  (table (;0;) 1 10 funcref)
+  (export "main" (func 0)))
@@ -0,0 +1,15 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    ref.func 1
+    i32.const 1
+    table.fill 0
+    i32.const 2
+    call_indirect (type 1))
+  (func (;1;) (type 1) (result i32)
+    i32.const 5)
+  (table (;0;) 3 funcref)
+  (export "main" (func 0))
+  (elem (;0;) declare func 1))
@@ -0,0 +1,9 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    table.get 0
+    ref.is_null)
+  (table (;0;) 3 funcref)
+  (export "main" (func 0))
+  (elem (;0;) (i32.const 1) func 0))
@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    ref.null func
+    local.get 0
+    table.grow 0)
+  (table (;0;) 1 3 funcref)
+  (export "main" (func 0)))
@@ -0,0 +1,14 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    ref.func 1
+    i32.const 1
+    table.grow 0
+    drop
+    i32.const 0
+    call_indirect (type 0))
+  (func (;1;) (type 0) (result i32)
+    i32.const 9)
+  (table (;0;) 0 funcref)
+  (export "main" (func 0))
+  (elem (;0;) declare func 1))
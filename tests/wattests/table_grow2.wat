@@ -0,0 +1,10 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (table (;0;) 0 funcref)
+  (func (;0;) (type 0) (param i32) (result i32)
+    ;; grow the table without a maximum by the param, too many elements
+    ;; fail instead of being allocated
+    ref.null func
+    local.get 0
+    table.grow 0)
+  (export "main" (func 0)))
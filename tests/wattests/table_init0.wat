@@ -0,0 +1,17 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    i32.const 0
+    local.get 0
+    i32.const 1
+    table.init 0
+    i32.const 0
+    call_indirect (type 1))
+  (func (;1;) (type 1) (result i32)
+    i32.const 10)
+  (func (;2;) (type 1) (result i32)
+    i32.const 20)
+  (table (;0;) 2 funcref)
+  (export "main" (func 0))
+  (elem (;0;) func 1 2))
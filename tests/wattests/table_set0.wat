@@ -0,0 +1,13 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    i32.const 0
+    ref.func 1
+    table.set 0
+    i32.const 0
+    call_indirect (type 0))
+  (func (;1;) (type 0) (result i32)
+    i32.const 42)
+  (table (;0;) 2 funcref)
+  (export "main" (func 0))
+  (elem (;0;) declare func 1))
@@ -0,0 +1,10 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    ref.null func
+    i32.const 2
+    table.grow 0
+    drop
+    table.size 0)
+  (table (;0;) 1 funcref)
+  (export "main" (func 0)))
@@ -0,0 +1,22 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (param i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    try (result i32)
+      local.get 0
+      call 1
+      i32.const 100
+    catch 0
+      i32.const 1
+      i32.add
+    end)
+  (func (;1;) (type 1) (param i32)
+    local.get 0
+    i32.const 10
+    i32.gt_s
+    if
+      local.get 0
+      throw 0
+    end)
+  (tag (;0;) (type 1))
+  (export "main" (func 0)))
@@ -0,0 +1,21 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (param i32)))
+  (type (;2;) (func))
+  (func (;0;) (type 0) (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if
+      i32.const 7
+      throw 0
+    end
+    local.get 0
+    i32.const 1
+    i32.eq
+    if
+      throw 1
+    end
+    i32.const 0)
+  (tag (;0;) (type 1))
+  (tag (;1;) (type 2))
+  (export "main" (func 0)))
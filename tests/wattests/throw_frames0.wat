@@ -0,0 +1,41 @@
+(module
+  (type (;0;) (func (param i32) (result f64)))
+  (type (;1;) (func (param f64 i32)))
+  (type (;2;) (func (param f64 i32) (result f64)))
+  (func (;0;) (type 0) (param i32) (result f64)
+    f64.const 0.5
+    try (result f64)
+      f64.const 2.5
+      local.get 0
+      call 1
+    catch 0
+      f64.convert_i32_s
+      f64.add
+    end
+    f64.add)
+  (func (;1;) (type 2) (param f64 i32) (result f64)
+    local.get 1
+    i32.const 0
+    i32.lt_s
+    if
+      local.get 0
+      return
+    end
+    local.get 1
+    i32.eqz
+    if
+      local.get 0
+      i32.const 100
+      throw 0
+    end
+    local.get 0
+    f64.const 1
+    f64.add
+    local.get 1
+    i32.const 1
+    i32.sub
+    call 1
+    f64.const 1000
+    f64.add)
+  (tag (;0;) (type 1))
+  (export "main" (func 0)))
@@ -0,0 +1,79 @@
+(module
+  (type (;0;) (func (param i32 i64 f64 f32) (result f64)))
+  (type (;1;) (func (param i32) (result f64)))
+  (func (;0;) (type 0) (param i32 i64 f64 f32) (result f64)
+    local.get 0
+    f64.convert_i32_s
+    local.get 2
+    f64.mul
+    local.get 1
+    f64.convert_i64_s
+    f64.add
+    local.get 3
+    f64.promote_f32
+    f64.add)
+  (func (;1;) (type 1) (param i32) (result f64)
+    local.get 0
+    i32.const 0
+    i32.add
+    i64.const 0
+    f64.const 0.0
+    f32.const 0.0
+    call 0
+    local.get 0
+    i32.const 1
+    i32.add
+    i64.const 1000000000007
+    f64.const 0.5
+    f32.const 0.25
+    call 0
+    f64.add
+    local.get 0
+    i32.const 2
+    i32.add
+    i64.const 2000000000014
+    f64.const 1.0
+    f32.const 0.5
+    call 0
+    f64.add
+    local.get 0
+    i32.const 3
+    i32.add
+    i64.const 3000000000021
+    f64.const 1.5
+    f32.const 0.75
+    call 0
+    f64.add
+    local.get 0
+    i32.const 4
+    i32.add
+    i64.const 4000000000028
+    f64.const 2.0
+    f32.const 1.0
+    call 0
+    f64.add
+    local.get 0
+    i32.const 5
+    i32.add
+    i64.const 5000000000035
+    f64.const 2.5
+    f32.const 1.25
+    call 0
+    f64.add
+    local.get 0
+    i32.const 6
+    i32.add
+    i64.const 6000000000042
+    f64.const 3.0
+    f32.const 1.5
+    call 0
+    f64.add
+    local.get 0
+    i32.const 7
+    i32.add
+    i64.const 7000000000049
+    f64.const 3.5
+    f32.const 1.75
+    call 0
+    f64.add)
+  (export "main" (func 1)))
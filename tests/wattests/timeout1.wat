@@ -0,0 +1,8 @@
+(module
+  (type (;0;) (func (result i32)))
+  (func (;0;) (type 0) (result i32)
+    loop  ;; label = @1
+      br 0 (;@1;)
+    end
+    i32.const 0)
+  (export "main" (func 0)))
@@ -0,0 +1,23 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func (param f64)))
+  (type (;2;) (func (param i32 i32)))
+  (type (;3;) (func (result i32)))
+  (import "env" "puti" (func (;0;) (type 0)))
+  (import "env" "putd" (func (;1;) (type 1)))
+  (import "env" "puts" (func (;2;) (type 2)))
+  (func (;3;) (type 3) (result i32)
+    i32.const 5
+    call 0
+    f64.const 0x1.8p+0 (;=1.5;)
+    call 1
+    i32.const 0
+    i32.const 2
+    call 2
+    i32.const 65530
+    i32.const 100
+    call 2
+    i32.const 0)
+  (memory (;0;) 1)
+  (export "main" (func 3))
+  (data (;0;) (i32.const 0) "hi"))
@@ -0,0 +1,30 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (param i32)))
+  (type (;2;) (func))
+  (func (;0;) (type 0) (param i32) (result i32)
+    try (result i32)
+      local.get 0
+      call 1
+      i32.const 0
+    catch 0
+    catch_all
+      i32.const -1
+    end)
+  (func (;1;) (type 1) (param i32)
+    local.get 0
+    i32.const 1
+    i32.eq
+    if
+      i32.const 5
+      throw 0
+    end
+    local.get 0
+    i32.const 2
+    i32.eq
+    if
+      throw 1
+    end)
+  (tag (;0;) (type 1))
+  (tag (;1;) (type 2))
+  (export "main" (func 0)))